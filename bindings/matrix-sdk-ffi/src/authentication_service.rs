@@ -70,7 +70,7 @@ pub enum AuthenticationError {
     WellKnownLookupFailed(RumaApiError),
     #[error(transparent)]
     WellKnownDeserializationError(DeserializationError),
-    #[error("The homeserver doesn't provide a trusted sliding sync proxy in its well-known configuration.")]
+    #[error("The homeserver doesn't support sliding sync natively, nor provide a trusted sliding sync proxy in its well-known configuration.")]
     SlidingSyncNotAvailable,
 
     #[error("Login was successful but is missing a valid Session to configure the file store.")]
@@ -259,9 +259,10 @@ impl AuthenticationService {
         let client = builder.build_inner()?;
         let details = RUNTIME.block_on(self.details_from_client(&client))?;
 
-        // Make sure there's a sliding sync proxy available.
+        // Make sure sliding sync is available, either natively or with a proxy.
         if self.custom_sliding_sync_proxy.read().unwrap().is_none()
             && client.discovered_sliding_sync_proxy().is_none()
+            && !RUNTIME.block_on(client.inner.supports_native_sliding_sync()).unwrap_or(false)
         {
             return Err(AuthenticationError::SlidingSyncNotAvailable);
         }
@@ -1,9 +1,9 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use matrix_sdk_ui::notification_client::{
     NotificationClient as MatrixNotificationClient,
     NotificationClientBuilder as MatrixNotificationClientBuilder,
-    NotificationItem as MatrixNotificationItem, NotificationProcessSetup,
+    NotificationItem as MatrixNotificationItem, NotificationProcessSetup, PushPayload,
 };
 use ruma::{EventId, RoomId};
 
@@ -46,6 +46,10 @@ pub struct NotificationItem {
     /// information to create a push context.
     pub is_noisy: Option<bool>,
     pub has_mention: Option<bool>,
+
+    /// The number of unread notifications of the user, as sent by the push
+    /// gateway. Only set by `NotificationClient::get_notification_from_push`.
+    pub unread_count: Option<u64>,
 }
 
 impl NotificationItem {
@@ -76,6 +80,7 @@ impl NotificationItem {
             },
             is_noisy: item.is_noisy,
             has_mention: item.has_mention,
+            unread_count: item.unread_count,
         }
     }
 }
@@ -108,6 +113,23 @@ impl NotificationClientBuilder {
         Arc::new(Self { builder, client: this.client })
     }
 
+    /// Set the maximum time `NotificationClient::get_notification_from_push`
+    /// may take, in milliseconds.
+    pub fn push_time_budget(self: Arc<Self>, push_time_budget_ms: u64) -> Arc<Self> {
+        let this = unwrap_or_clone_arc(self);
+        let builder = this.builder.push_time_budget(Duration::from_millis(push_time_budget_ms));
+        Arc::new(Self { builder, client: this.client })
+    }
+
+    /// Set the maximum size of an event fetched by
+    /// `NotificationClient::get_notification_from_push`, in bytes.
+    pub fn max_push_event_size(self: Arc<Self>, max_push_event_size: u64) -> Arc<Self> {
+        let this = unwrap_or_clone_arc(self);
+        let max_push_event_size = max_push_event_size.try_into().unwrap_or(usize::MAX);
+        let builder = this.builder.max_push_event_size(max_push_event_size);
+        Arc::new(Self { builder, client: this.client })
+    }
+
     pub fn finish(self: Arc<Self>) -> Arc<NotificationClient> {
         let this = unwrap_or_clone_arc(self);
         Arc::new(NotificationClient { inner: this.builder.build(), _client: this.client })
@@ -150,4 +172,20 @@ impl NotificationClient {
             }
         })
     }
+
+    /// See also documentation of
+    /// `MatrixNotificationClient::get_notification_from_push`.
+    ///
+    /// The `payload` is the JSON payload of the push notification.
+    pub fn get_notification_from_push(
+        &self,
+        payload: String,
+    ) -> Result<Option<NotificationItem>, ClientError> {
+        let payload = PushPayload::from_json(&payload)?;
+        RUNTIME.block_on(async move {
+            let item =
+                self.inner.get_notification_from_push(&payload).await.map_err(ClientError::from)?;
+            Ok(item.map(NotificationItem::from_inner))
+        })
+    }
 }
@@ -16,10 +16,14 @@ use matrix_sdk_ui::{
     room_list_service::{
         filters::{
             new_filter_all, new_filter_any, new_filter_category, new_filter_favourite,
-            new_filter_fuzzy_match_room_name, new_filter_non_left, new_filter_none,
-            new_filter_normalized_match_room_name, new_filter_unread, RoomCategory,
+            new_filter_fuzzy_match_room_name, new_filter_knocked, new_filter_non_left,
+            new_filter_none, new_filter_normalized_match_room_name, new_filter_unread,
+            RoomCategory,
         },
-        BoxedFilterFn,
+        sorters::{
+            new_sorter_lexicographic, new_sorter_name, new_sorter_recency, new_sorter_unread,
+        },
+        BoxedFilterFn, BoxedSorterFn,
     },
     timeline::default_event_filter,
 };
@@ -405,6 +409,11 @@ impl RoomListDynamicEntriesController {
         self.inner.set_filter(filter)
     }
 
+    fn set_sorter(&self, kind: RoomListEntriesDynamicSorterKind) -> bool {
+        let SorterWrapper(sorter) = SorterWrapper::from(&self.client, kind);
+        self.inner.set_sorter(sorter)
+    }
+
     fn add_one_page(&self) {
         self.inner.add_one_page();
     }
@@ -419,6 +428,7 @@ pub enum RoomListEntriesDynamicFilterKind {
     All { filters: Vec<RoomListEntriesDynamicFilterKind> },
     Any { filters: Vec<RoomListEntriesDynamicFilterKind> },
     NonLeft,
+    Knocked,
     Unread,
     Favourite,
     Category { expect: RoomListFilterCategory },
@@ -458,6 +468,7 @@ impl FilterWrapper {
                 filters.into_iter().map(|filter| FilterWrapper::from(client, filter).0).collect(),
             ))),
             Kind::NonLeft => Self(Box::new(new_filter_non_left(client))),
+            Kind::Knocked => Self(Box::new(new_filter_knocked(client))),
             Kind::Unread => Self(Box::new(new_filter_unread(client))),
             Kind::Favourite => Self(Box::new(new_filter_favourite(client))),
             Kind::Category { expect } => Self(Box::new(new_filter_category(client, expect.into()))),
@@ -472,6 +483,33 @@ impl FilterWrapper {
     }
 }
 
+#[derive(uniffi::Enum)]
+pub enum RoomListEntriesDynamicSorterKind {
+    Lexicographic { sorters: Vec<RoomListEntriesDynamicSorterKind> },
+    Recency,
+    Name,
+    Unread,
+}
+
+/// Custom internal type to transform a `RoomListEntriesDynamicSorterKind` into
+/// a `BoxedSorterFn`.
+struct SorterWrapper(BoxedSorterFn);
+
+impl SorterWrapper {
+    fn from(client: &matrix_sdk::Client, value: RoomListEntriesDynamicSorterKind) -> Self {
+        use RoomListEntriesDynamicSorterKind as Kind;
+
+        match value {
+            Kind::Lexicographic { sorters } => Self(Box::new(new_sorter_lexicographic(
+                sorters.into_iter().map(|sorter| SorterWrapper::from(client, sorter).0).collect(),
+            ))),
+            Kind::Recency => Self(Box::new(new_sorter_recency(client))),
+            Kind::Name => Self(Box::new(new_sorter_name(client))),
+            Kind::Unread => Self(Box::new(new_sorter_unread(client))),
+        }
+    }
+}
+
 #[derive(uniffi::Object)]
 pub struct RoomListItem {
     inner: Arc<matrix_sdk_ui::room_list_service::Room>,
@@ -3,7 +3,7 @@ use std::sync::{Arc, Mutex};
 use language_tags::LanguageTag;
 use matrix_sdk::{
     async_trait,
-    widget::{MessageLikeEventFilter, StateEventFilter},
+    widget::{MessageLikeEventFilter, StateEventFilter, ToDeviceEventFilter},
 };
 use tracing::error;
 
@@ -273,6 +273,9 @@ pub fn get_element_call_required_permissions() -> WidgetCapabilities {
             WidgetEventFilter::MessageLikeWithType {
                 event_type: "io.element.call.encryption_keys".to_owned(),
             },
+            WidgetEventFilter::ToDeviceWithType {
+                event_type: "io.element.call.encryption_keys".to_owned(),
+            },
         ],
         send: vec![
             WidgetEventFilter::StateWithType { event_type: StateEventType::CallMember.to_string() },
@@ -282,8 +285,17 @@ pub fn get_element_call_required_permissions() -> WidgetCapabilities {
             WidgetEventFilter::StateWithType {
                 event_type: "io.element.call.encryption_keys".to_owned(),
             },
+            WidgetEventFilter::ToDeviceWithType {
+                event_type: "io.element.call.encryption_keys".to_owned(),
+            },
         ],
         requires_client: true,
+        send_delayed_event: true,
+        update_delayed_event: true,
+        navigate: false,
+        turn_servers: true,
+        upload_file: false,
+        download_file: false,
     }
 }
 
@@ -345,6 +357,21 @@ pub struct WidgetCapabilities {
     /// This means clients should not offer to open the widget in a separate
     /// browser/tab/webview that is not connected to the postmessage widget-api.
     pub requires_client: bool,
+    /// This allows the widget to send delayed events, that the homeserver
+    /// sends on its behalf after a timeout.
+    pub send_delayed_event: bool,
+    /// This allows the widget to cancel, restart or send right away the
+    /// delayed events it scheduled.
+    pub update_delayed_event: bool,
+    /// This allows the widget to ask the client to navigate to a `matrix.to`
+    /// permalink.
+    pub navigate: bool,
+    /// This allows the widget to receive the TURN servers of the homeserver.
+    pub turn_servers: bool,
+    /// This allows the widget to upload files to the media repository.
+    pub upload_file: bool,
+    /// This allows the widget to download files from the media repository.
+    pub download_file: bool,
 }
 
 impl From<WidgetCapabilities> for matrix_sdk::widget::Capabilities {
@@ -353,6 +380,12 @@ impl From<WidgetCapabilities> for matrix_sdk::widget::Capabilities {
             read: value.read.into_iter().map(Into::into).collect(),
             send: value.send.into_iter().map(Into::into).collect(),
             requires_client: value.requires_client,
+            send_delayed_event: value.send_delayed_event,
+            update_delayed_event: value.update_delayed_event,
+            navigate: value.navigate,
+            turn_servers: value.turn_servers,
+            upload_file: value.upload_file,
+            download_file: value.download_file,
         }
     }
 }
@@ -363,6 +396,12 @@ impl From<matrix_sdk::widget::Capabilities> for WidgetCapabilities {
             read: value.read.into_iter().map(Into::into).collect(),
             send: value.send.into_iter().map(Into::into).collect(),
             requires_client: value.requires_client,
+            send_delayed_event: value.send_delayed_event,
+            update_delayed_event: value.update_delayed_event,
+            navigate: value.navigate,
+            turn_servers: value.turn_servers,
+            upload_file: value.upload_file,
+            download_file: value.download_file,
         }
     }
 }
@@ -378,6 +417,8 @@ pub enum WidgetEventFilter {
     StateWithType { event_type: String },
     /// Matches state events with the given `type` and `state_key`.
     StateWithTypeAndStateKey { event_type: String, state_key: String },
+    /// Matches to-device events with the given `type`.
+    ToDeviceWithType { event_type: String },
 }
 
 impl From<WidgetEventFilter> for matrix_sdk::widget::EventFilter {
@@ -395,6 +436,9 @@ impl From<WidgetEventFilter> for matrix_sdk::widget::EventFilter {
             WidgetEventFilter::StateWithTypeAndStateKey { event_type, state_key } => {
                 Self::State(StateEventFilter::WithTypeAndStateKey(event_type.into(), state_key))
             }
+            WidgetEventFilter::ToDeviceWithType { event_type } => {
+                Self::ToDevice(ToDeviceEventFilter::new(event_type.into()))
+            }
         }
     }
 }
@@ -416,6 +460,9 @@ impl From<matrix_sdk::widget::EventFilter> for WidgetEventFilter {
             F::State(StateEventFilter::WithTypeAndStateKey(event_type, state_key)) => {
                 Self::StateWithTypeAndStateKey { event_type: event_type.to_string(), state_key }
             }
+            F::ToDevice(ToDeviceEventFilter { event_type }) => {
+                Self::ToDeviceWithType { event_type: event_type.to_string() }
+            }
         }
     }
 }
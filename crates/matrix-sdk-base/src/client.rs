@@ -13,12 +13,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[cfg(feature = "e2e-encryption")]
+use std::ops::Deref;
 use std::{
     collections::{BTreeMap, BTreeSet},
     fmt, iter,
+    sync::Arc,
 };
-#[cfg(feature = "e2e-encryption")]
-use std::{ops::Deref, sync::Arc};
 
 use eyeball::{SharedObservable, Subscriber};
 use matrix_sdk_common::instant::Instant;
@@ -63,6 +64,8 @@ use crate::{
         AmbiguityChanges, MembersResponse, RawAnySyncOrStrippedTimelineEvent, SyncTimelineEvent,
     },
     error::Result,
+    event_cache_store::DynEventCacheStore,
+    media_cache_store::DynMediaCacheStore,
     rooms::{normal::RoomInfoUpdate, Room, RoomInfo, RoomState},
     store::{
         ambiguity_map::AmbiguityCache, DynStateStore, MemoryStore, Result as StoreResult,
@@ -82,6 +85,10 @@ use crate::{error::Error, RoomMemberships};
 pub struct BaseClient {
     /// Database
     pub(crate) store: Store,
+    /// The store used by the event cache.
+    event_cache_store: Arc<DynEventCacheStore>,
+    /// The store used by the media cache.
+    media_cache_store: Arc<DynMediaCacheStore>,
     /// The store used for encryption.
     ///
     /// This field is only meant to be used for `OlmMachine` initialization.
@@ -130,6 +137,8 @@ impl BaseClient {
 
         BaseClient {
             store: Store::new(config.state_store),
+            event_cache_store: config.event_cache_store,
+            media_cache_store: config.media_cache_store,
             #[cfg(feature = "e2e-encryption")]
             crypto_store: config.crypto_store,
             #[cfg(feature = "e2e-encryption")]
@@ -187,6 +196,16 @@ impl BaseClient {
         &*self.store
     }
 
+    /// Get a reference to the event cache store.
+    pub fn event_cache_store(&self) -> &Arc<DynEventCacheStore> {
+        &self.event_cache_store
+    }
+
+    /// Get a reference to the media cache store.
+    pub fn media_cache_store(&self) -> &Arc<DynMediaCacheStore> {
+        &self.media_cache_store
+    }
+
     /// Is the client logged in.
     pub fn logged_in(&self) -> bool {
         self.store.session_meta().is_some()
@@ -758,6 +777,32 @@ impl BaseClient {
         Ok(room)
     }
 
+    /// User has knocked on a room.
+    ///
+    /// Update the internal and cached state accordingly. Return the final Room.
+    pub async fn room_knocked(&self, room_id: &RoomId) -> Result<Room> {
+        let room = self.store.get_or_create_room(
+            room_id,
+            RoomState::Knocked,
+            self.roominfo_update_sender.clone(),
+        );
+        if room.state() != RoomState::Knocked {
+            let _sync_lock = self.sync_lock().lock().await;
+
+            let mut room_info = room.clone_info();
+            room_info.mark_as_knocked();
+            room_info.mark_state_partially_synced();
+            room_info.mark_members_missing(); // the own member event changed
+            let mut changes = StateChanges::default();
+            changes.add_room(room_info.clone());
+            self.store.save_changes(&changes).await?; // Update the store
+                                                      // Update the cached room handle
+            room.set_room_info(room_info, false);
+        }
+
+        Ok(room)
+    }
+
     /// User has left a room.
     ///
     /// Update the internal and cached state accordingly.
@@ -1031,6 +1076,32 @@ impl BaseClient {
             new_rooms.invite.insert(room_id, new_info);
         }
 
+        for (room_id, new_info) in response.rooms.knock {
+            let room = self.store.get_or_create_room(
+                &room_id,
+                RoomState::Knocked,
+                self.roominfo_update_sender.clone(),
+            );
+            let mut room_info = room.clone_info();
+            room_info.mark_as_knocked();
+            room_info.mark_state_fully_synced();
+
+            // The knock state has the same shape as the invite state.
+            self.handle_invited_state(
+                &room,
+                &new_info.knock_state.events,
+                &push_rules,
+                &mut room_info,
+                &mut changes,
+                &mut notifications,
+            )
+            .await?;
+
+            changes.add_room(room_info);
+
+            new_rooms.knock.insert(room_id, new_info);
+        }
+
         // TODO remove this, we're processing account data events here again
         // because we want to have the push rules in place before we process
         // rooms and their events, but we want to create the rooms before we
@@ -1518,6 +1589,62 @@ mod tests {
         assert_eq!(client.get_room(room_id).unwrap().state(), RoomState::Invited);
     }
 
+    #[async_test]
+    async fn test_knocked_room() {
+        let user_id = user_id!("@alice:example.org");
+        let room_id = room_id!("!knock:example.org");
+
+        let client = logged_in_client(user_id).await;
+
+        let response = api::sync::sync_events::v3::Response::try_from_http_response(
+            response_from_file(&json!({
+                "next_batch": "asdkl;fjasdkl;fj;asdkl;f",
+                "rooms": {
+                    "knock": {
+                        "!knock:example.org": {
+                            "knock_state": {
+                                "events": [
+                                    {
+                                        "content": {
+                                            "name": "Knock knock",
+                                        },
+                                        "sender": "@test:example.org",
+                                        "state_key": "",
+                                        "type": "m.room.name",
+                                    },
+                                    {
+                                        "content": {
+                                            "join_rule": "knock",
+                                        },
+                                        "sender": "@test:example.org",
+                                        "state_key": "",
+                                        "type": "m.room.join_rules",
+                                    },
+                                    {
+                                        "content": {
+                                            "membership": "knock",
+                                        },
+                                        "sender": user_id,
+                                        "state_key": user_id,
+                                        "type": "m.room.member",
+                                    },
+                                ],
+                            },
+                        },
+                    },
+                },
+            })),
+        )
+        .expect("static json doesn't fail to parse");
+
+        let sync = client.receive_sync_response(response).await.unwrap();
+        assert!(sync.rooms.knock.contains_key(room_id));
+
+        let room = client.get_room(room_id).unwrap();
+        assert_eq!(room.state(), RoomState::Knocked);
+        assert_eq!(room.name().as_deref(), Some("Knock knock"));
+    }
+
     #[async_test]
     async fn test_invite_displayname() {
         let user_id = user_id!("@alice:example.org");
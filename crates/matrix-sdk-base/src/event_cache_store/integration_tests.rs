@@ -0,0 +1,364 @@
+//! Trait and macro of integration tests for EventCacheStore implementations.
+
+use assert_matches2::assert_let;
+use async_trait::async_trait;
+use ruma::{
+    event_id, room_id, serde::Raw, user_id, EventId, MilliSecondsSinceUnixEpoch, OwnedEventId,
+    RoomId, UserId,
+};
+use serde_json::json;
+
+use matrix_sdk_common::linked_chunk::{ChunkContent, LinkedChunk};
+
+use super::{DynEventCacheStore, Gap, Result, SearchIndexEntry, SearchIndexQuery};
+use crate::deserialized_responses::SyncTimelineEvent;
+
+/// `EventCacheStore` integration tests.
+///
+/// This trait is not meant to be used directly, but will be used with the
+/// [`event_cache_store_integration_tests!`] macro.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait EventCacheStoreIntegrationTests {
+    /// Test saving and reloading the linked chunk of a room.
+    async fn test_linked_chunk(&self) -> Result<()>;
+    /// Test applying incremental updates to the linked chunk of a room.
+    async fn test_linked_chunk_incremental_updates(&self) -> Result<()>;
+    /// Test clearing the linked chunk of a room.
+    async fn test_clear_linked_chunk(&self) -> Result<()>;
+    /// Test querying the local search index.
+    async fn test_search_index(&self) -> Result<()>;
+    /// Test replacing, removing and clearing events of the local search index.
+    async fn test_search_index_updates(&self) -> Result<()>;
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl EventCacheStoreIntegrationTests for DynEventCacheStore {
+    async fn test_linked_chunk(&self) -> Result<()> {
+        let room_id = room_id!("!r0:matrix.org");
+        let other_room_id = room_id!("!r1:matrix.org");
+
+        assert!(self.reload_linked_chunk(room_id).await?.is_empty());
+
+        let mut linked_chunk = LinkedChunk::new();
+        linked_chunk.push_gap_back(gap("t0"));
+        linked_chunk.push_items_back([
+            text_event(event_id!("$ev0"), "hello"),
+            text_event(event_id!("$ev1"), "world"),
+        ]);
+        linked_chunk.push_gap_back(gap("t1"));
+        linked_chunk.push_items_back([text_event(event_id!("$ev2"), "!")]);
+        self.handle_linked_chunk_updates(room_id, linked_chunk.take_updates()).await?;
+
+        let mut other_linked_chunk = LinkedChunk::new();
+        other_linked_chunk.push_items_back([text_event(event_id!("$ev3"), "elsewhere")]);
+        self.handle_linked_chunk_updates(other_room_id, other_linked_chunk.take_updates()).await?;
+
+        let chunks = reload(self, room_id).await?;
+        assert_eq!(chunks.len(), 4);
+        assert_let!(ChunkContent::Gap(gap) = &chunks[0]);
+        assert_eq!(gap.prev_token, "t0");
+        assert_let!(ChunkContent::Items(events) = &chunks[1]);
+        assert_eq!(event_ids(events), [event_id!("$ev0"), event_id!("$ev1")]);
+        assert_let!(ChunkContent::Gap(gap) = &chunks[2]);
+        assert_eq!(gap.prev_token, "t1");
+        assert_let!(ChunkContent::Items(events) = &chunks[3]);
+        assert_eq!(event_ids(events), [event_id!("$ev2")]);
+
+        let chunks = reload(self, other_room_id).await?;
+        assert_eq!(chunks.len(), 1);
+        assert_let!(ChunkContent::Items(events) = &chunks[0]);
+        assert_eq!(event_ids(events), [event_id!("$ev3")]);
+
+        Ok(())
+    }
+
+    async fn test_linked_chunk_incremental_updates(&self) -> Result<()> {
+        let room_id = room_id!("!r0:matrix.org");
+
+        let mut linked_chunk = LinkedChunk::new();
+        linked_chunk.push_items_back([text_event(event_id!("$ev0"), "hello")]);
+        let gap_identifier = linked_chunk.push_gap_back(gap("t0"));
+        linked_chunk.push_items_back([
+            text_event(event_id!("$ev3"), "world"),
+            text_event(event_id!("$ev4"), "!"),
+        ]);
+        self.handle_linked_chunk_updates(room_id, linked_chunk.take_updates()).await?;
+
+        // Reload the linked chunk, and keep modifying it, like after a restart.
+        let mut linked_chunk =
+            LinkedChunk::from_raw_chunks(self.reload_linked_chunk(room_id).await?)
+                .expect("the reloaded chunks must be linked together");
+
+        linked_chunk.insert_gap_before(gap("t1"), gap_identifier).unwrap();
+        linked_chunk
+            .replace_gap_at(
+                vec![text_event(event_id!("$ev1"), "hi"), text_event(event_id!("$ev2"), "there")],
+                gap_identifier,
+            )
+            .unwrap();
+        let position = linked_chunk
+            .item_position(|event| event.event_id().as_deref() == Some(event_id!("$ev3")))
+            .unwrap();
+        linked_chunk.remove_item_at(position).unwrap();
+        linked_chunk.push_items_back([text_event(event_id!("$ev5"), "bye")]);
+        self.handle_linked_chunk_updates(room_id, linked_chunk.take_updates()).await?;
+
+        let chunks = reload(self, room_id).await?;
+        assert_eq!(chunks.len(), 4);
+        assert_let!(ChunkContent::Items(events) = &chunks[0]);
+        assert_eq!(event_ids(events), [event_id!("$ev0")]);
+        assert_let!(ChunkContent::Gap(gap) = &chunks[1]);
+        assert_eq!(gap.prev_token, "t1");
+        assert_let!(ChunkContent::Items(events) = &chunks[2]);
+        assert_eq!(event_ids(events), [event_id!("$ev1"), event_id!("$ev2")]);
+        assert_let!(ChunkContent::Items(events) = &chunks[3]);
+        assert_eq!(event_ids(events), [event_id!("$ev4"), event_id!("$ev5")]);
+
+        Ok(())
+    }
+
+    async fn test_clear_linked_chunk(&self) -> Result<()> {
+        let room_id = room_id!("!r0:matrix.org");
+        let other_room_id = room_id!("!r1:matrix.org");
+
+        let mut linked_chunk = LinkedChunk::new();
+        linked_chunk.push_gap_back(gap("t0"));
+        linked_chunk.push_items_back([text_event(event_id!("$ev0"), "hello")]);
+        self.handle_linked_chunk_updates(room_id, linked_chunk.take_updates()).await?;
+
+        let mut other_linked_chunk = LinkedChunk::new();
+        other_linked_chunk.push_items_back([text_event(event_id!("$ev1"), "world")]);
+        self.handle_linked_chunk_updates(other_room_id, other_linked_chunk.take_updates()).await?;
+
+        linked_chunk.clear();
+        linked_chunk.push_items_back([text_event(event_id!("$ev2"), "again")]);
+        self.handle_linked_chunk_updates(room_id, linked_chunk.take_updates()).await?;
+
+        let chunks = reload(self, room_id).await?;
+        assert_eq!(chunks.len(), 1);
+        assert_let!(ChunkContent::Items(events) = &chunks[0]);
+        assert_eq!(event_ids(events), [event_id!("$ev2")]);
+        assert_eq!(reload(self, other_room_id).await?.len(), 1);
+
+        Ok(())
+    }
+
+    async fn test_search_index(&self) -> Result<()> {
+        let room_id = room_id!("!r0:matrix.org");
+        let other_room_id = room_id!("!r1:matrix.org");
+        let alice = user_id!("@alice:matrix.org");
+        let bob = user_id!("@bob:matrix.org");
+
+        assert!(self.search_index(&SearchIndexQuery::new("hello")).await?.is_empty());
+
+        self.index_events(vec![
+            search_entry(room_id, event_id!("$ev0"), alice, 1, "Hello, world!"),
+            search_entry(room_id, event_id!("$ev1"), bob, 2, "hello there"),
+            search_entry(other_room_id, event_id!("$ev2"), alice, 3, "HELLO WORLD"),
+            search_entry(room_id, event_id!("$ev3"), alice, 4, "goodbye world"),
+        ])
+        .await?;
+
+        // Words match regardless of case and punctuation, most recent first.
+        let results = self.search_index(&SearchIndexQuery::new("hello")).await?;
+        assert_eq!(search_event_ids(&results), ["$ev2", "$ev1", "$ev0"]);
+        assert_eq!(results[0].body, "HELLO WORLD");
+
+        // All the words must match.
+        let results = self.search_index(&SearchIndexQuery::new("world hello")).await?;
+        assert_eq!(search_event_ids(&results), ["$ev2", "$ev0"]);
+
+        // Words match exactly.
+        assert!(self.search_index(&SearchIndexQuery::new("hell")).await?.is_empty());
+
+        let mut query = SearchIndexQuery::new("world");
+        query.room_id = Some(room_id.to_owned());
+        let results = self.search_index(&query).await?;
+        assert_eq!(search_event_ids(&results), ["$ev3", "$ev0"]);
+
+        let mut query = SearchIndexQuery::new("hello");
+        query.sender = Some(alice.to_owned());
+        let results = self.search_index(&query).await?;
+        assert_eq!(search_event_ids(&results), ["$ev2", "$ev0"]);
+
+        let mut query = SearchIndexQuery::new("");
+        query.since = Some(ts(2));
+        query.until = Some(ts(3));
+        let results = self.search_index(&query).await?;
+        assert_eq!(search_event_ids(&results), ["$ev2", "$ev1"]);
+
+        let mut query = SearchIndexQuery::new("world");
+        query.limit = 1;
+        let results = self.search_index(&query).await?;
+        assert_eq!(search_event_ids(&results), ["$ev3"]);
+
+        Ok(())
+    }
+
+    async fn test_search_index_updates(&self) -> Result<()> {
+        let room_id = room_id!("!r0:matrix.org");
+        let alice = user_id!("@alice:matrix.org");
+
+        self.index_events(vec![
+            search_entry(room_id, event_id!("$ev0"), alice, 1, "hello world"),
+            search_entry(room_id, event_id!("$ev1"), alice, 2, "hello there"),
+        ])
+        .await?;
+
+        // Replace the text of an event.
+        self.index_events(vec![search_entry(room_id, event_id!("$ev0"), alice, 1, "goodbye")])
+            .await?;
+        let results = self.search_index(&SearchIndexQuery::new("hello")).await?;
+        assert_eq!(search_event_ids(&results), ["$ev1"]);
+        let results = self.search_index(&SearchIndexQuery::new("goodbye")).await?;
+        assert_eq!(search_event_ids(&results), ["$ev0"]);
+
+        let entry = self.get_indexed_event(room_id, event_id!("$ev0")).await?.unwrap();
+        assert_eq!(entry.sender, alice);
+        assert_eq!(entry.origin_server_ts, ts(1));
+        assert_eq!(entry.body, "goodbye");
+
+        self.remove_indexed_event(room_id, event_id!("$ev1")).await?;
+        assert!(self.search_index(&SearchIndexQuery::new("hello")).await?.is_empty());
+        assert!(self.get_indexed_event(room_id, event_id!("$ev1")).await?.is_none());
+
+        self.clear_search_index().await?;
+        assert!(self.search_index(&SearchIndexQuery::new("")).await?.is_empty());
+
+        Ok(())
+    }
+}
+
+/// Reload the linked chunk of the given room, and return the content of its
+/// chunks, in order.
+async fn reload(
+    store: &DynEventCacheStore,
+    room_id: &RoomId,
+) -> Result<Vec<ChunkContent<SyncTimelineEvent, Gap>>> {
+    let linked_chunk = LinkedChunk::from_raw_chunks(store.reload_linked_chunk(room_id).await?)
+        .expect("the reloaded chunks must be linked together");
+
+    Ok(linked_chunk.chunks().map(|chunk| chunk.content().clone()).collect())
+}
+
+fn gap(prev_token: &str) -> Gap {
+    Gap { prev_token: prev_token.to_owned() }
+}
+
+fn event_ids(events: &[SyncTimelineEvent]) -> Vec<OwnedEventId> {
+    events.iter().map(|ev| ev.event_id().unwrap()).collect()
+}
+
+fn text_event(event_id: &EventId, body: &str) -> SyncTimelineEvent {
+    let ev_json = json!({
+        "content": {
+            "body": body,
+            "msgtype": "m.text",
+        },
+        "event_id": event_id,
+        "origin_server_ts": 151393755,
+        "sender": user_id!("@example:localhost"),
+        "type": "m.room.message",
+    });
+
+    SyncTimelineEvent::new(Raw::new(&ev_json).unwrap().cast())
+}
+
+fn search_entry(
+    room_id: &RoomId,
+    event_id: &EventId,
+    sender: &UserId,
+    origin_server_ts: u32,
+    body: &str,
+) -> SearchIndexEntry {
+    SearchIndexEntry {
+        room_id: room_id.to_owned(),
+        event_id: event_id.to_owned(),
+        sender: sender.to_owned(),
+        origin_server_ts: ts(origin_server_ts),
+        body: body.to_owned(),
+    }
+}
+
+fn ts(ts: u32) -> MilliSecondsSinceUnixEpoch {
+    MilliSecondsSinceUnixEpoch(ts.into())
+}
+
+fn search_event_ids(results: &[SearchIndexEntry]) -> Vec<&str> {
+    results.iter().map(|entry| entry.event_id.as_str()).collect()
+}
+
+/// Macro building to allow your EventCacheStore implementation to run the
+/// entire tests suite locally.
+///
+/// You need to provide a `async fn get_event_cache_store() ->
+/// EventCacheStoreResult<impl EventCacheStore>` providing a fresh event cache
+/// store on the same level you invoke the macro.
+///
+/// ## Usage Example:
+/// ```no_run
+/// # use matrix_sdk_base::event_cache_store::{
+/// #    EventCacheStore,
+/// #    MemoryStore as MyStore,
+/// #    Result as EventCacheStoreResult,
+/// # };
+///
+/// #[cfg(test)]
+/// mod tests {
+///     use super::{EventCacheStore, EventCacheStoreResult, MyStore};
+///
+///     async fn get_event_cache_store(
+///     ) -> EventCacheStoreResult<impl EventCacheStore> {
+///         Ok(MyStore::new())
+///     }
+///
+///     event_cache_store_integration_tests!();
+/// }
+/// ```
+#[allow(unused_macros, unused_extern_crates)]
+#[macro_export]
+macro_rules! event_cache_store_integration_tests {
+    () => {
+        mod event_cache_store_integration_tests {
+            use matrix_sdk_test::async_test;
+            use $crate::event_cache_store::{
+                EventCacheStoreIntegrationTests, IntoEventCacheStore,
+                Result as EventCacheStoreResult,
+            };
+
+            use super::get_event_cache_store;
+
+            #[async_test]
+            async fn test_linked_chunk() -> EventCacheStoreResult<()> {
+                let store = get_event_cache_store().await?.into_event_cache_store();
+                store.test_linked_chunk().await
+            }
+
+            #[async_test]
+            async fn test_linked_chunk_incremental_updates() -> EventCacheStoreResult<()> {
+                let store = get_event_cache_store().await?.into_event_cache_store();
+                store.test_linked_chunk_incremental_updates().await
+            }
+
+            #[async_test]
+            async fn test_clear_linked_chunk() -> EventCacheStoreResult<()> {
+                let store = get_event_cache_store().await?.into_event_cache_store();
+                store.test_clear_linked_chunk().await
+            }
+
+            #[async_test]
+            async fn test_search_index() -> EventCacheStoreResult<()> {
+                let store = get_event_cache_store().await?.into_event_cache_store();
+                store.test_search_index().await
+            }
+
+            #[async_test]
+            async fn test_search_index_updates() -> EventCacheStoreResult<()> {
+                let store = get_event_cache_store().await?.into_event_cache_store();
+                store.test_search_index_updates().await
+            }
+        }
+    };
+}
@@ -0,0 +1,200 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::BTreeMap, sync::RwLock as StdRwLock};
+
+use async_trait::async_trait;
+use matrix_sdk_common::linked_chunk::{ChunkContent, ChunkIdentifier, RawChunk, Update};
+use ruma::{EventId, OwnedEventId, OwnedRoomId, RoomId};
+
+use super::{
+    search_index_tokens, EventCacheStore, EventCacheStoreError, Gap, Result, RoomEventsRawChunk,
+    RoomEventsUpdate, SearchIndexEntry, SearchIndexQuery,
+};
+use crate::deserialized_responses::SyncTimelineEvent;
+
+/// In-memory, non-persistent implementation of the `EventCacheStore`.
+///
+/// Default if no other is configured at startup.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    /// The chunks of the history of each room, by identifier.
+    chunks: StdRwLock<BTreeMap<OwnedRoomId, BTreeMap<ChunkIdentifier, RoomEventsRawChunk>>>,
+
+    /// The local search index, by room and event ID.
+    search_index: StdRwLock<BTreeMap<(OwnedRoomId, OwnedEventId), SearchIndexEntry>>,
+}
+
+impl MemoryStore {
+    /// Create a new empty [`MemoryStore`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl EventCacheStore for MemoryStore {
+    type Error = EventCacheStoreError;
+
+    async fn handle_linked_chunk_updates(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<RoomEventsUpdate>,
+    ) -> Result<()> {
+        let mut chunks = self.chunks.write().unwrap();
+        let room_chunks = chunks.entry(room_id.to_owned()).or_default();
+
+        for update in updates {
+            match update {
+                Update::NewItemsChunk { previous, new, next } => {
+                    insert_chunk(room_chunks, previous, new, next, ChunkContent::Items(Vec::new()));
+                }
+                Update::NewGapChunk { previous, new, next, gap } => {
+                    insert_chunk(room_chunks, previous, new, next, ChunkContent::Gap(gap));
+                }
+                Update::RemoveChunk(identifier) => {
+                    let Some(chunk) = room_chunks.remove(&identifier) else {
+                        continue;
+                    };
+
+                    if let Some(previous) = chunk.previous.and_then(|id| room_chunks.get_mut(&id)) {
+                        previous.next = chunk.next;
+                    }
+                    if let Some(next) = chunk.next.and_then(|id| room_chunks.get_mut(&id)) {
+                        next.previous = chunk.previous;
+                    }
+                }
+                Update::PushItems { at, items } => {
+                    if let Some(RawChunk { content: ChunkContent::Items(chunk_items), .. }) =
+                        room_chunks.get_mut(&at.chunk_identifier())
+                    {
+                        let index = at.index().min(chunk_items.len());
+                        chunk_items.splice(index..index, items);
+                    }
+                }
+                Update::RemoveItem { at } => {
+                    if let Some(RawChunk { content: ChunkContent::Items(chunk_items), .. }) =
+                        room_chunks.get_mut(&at.chunk_identifier())
+                    {
+                        if at.index() < chunk_items.len() {
+                            chunk_items.remove(at.index());
+                        }
+                    }
+                }
+                Update::Clear => room_chunks.clear(),
+            }
+        }
+
+        if room_chunks.is_empty() {
+            chunks.remove(room_id);
+        }
+
+        Ok(())
+    }
+
+    async fn reload_linked_chunk(&self, room_id: &RoomId) -> Result<Vec<RoomEventsRawChunk>> {
+        Ok(self
+            .chunks
+            .read()
+            .unwrap()
+            .get(room_id)
+            .map(|room_chunks| room_chunks.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn index_events(&self, entries: Vec<SearchIndexEntry>) -> Result<()> {
+        let mut search_index = self.search_index.write().unwrap();
+        for entry in entries {
+            search_index.insert((entry.room_id.clone(), entry.event_id.clone()), entry);
+        }
+        Ok(())
+    }
+
+    async fn get_indexed_event(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Result<Option<SearchIndexEntry>> {
+        Ok(self
+            .search_index
+            .read()
+            .unwrap()
+            .get(&(room_id.to_owned(), event_id.to_owned()))
+            .cloned())
+    }
+
+    async fn remove_indexed_event(&self, room_id: &RoomId, event_id: &EventId) -> Result<()> {
+        self.search_index.write().unwrap().remove(&(room_id.to_owned(), event_id.to_owned()));
+        Ok(())
+    }
+
+    async fn search_index(&self, query: &SearchIndexQuery) -> Result<Vec<SearchIndexEntry>> {
+        let tokens = search_index_tokens(&query.text);
+
+        let mut results = self
+            .search_index
+            .read()
+            .unwrap()
+            .values()
+            .filter(|entry| {
+                query.room_id.as_ref().map_or(true, |room_id| entry.room_id == *room_id)
+                    && query.sender.as_ref().map_or(true, |sender| entry.sender == *sender)
+                    && query.since.map_or(true, |since| entry.origin_server_ts >= since)
+                    && query.until.map_or(true, |until| entry.origin_server_ts <= until)
+                    && search_index_tokens(&entry.body).is_superset(&tokens)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        results.sort_by(|a, b| b.origin_server_ts.cmp(&a.origin_server_ts));
+        results.truncate(query.limit);
+
+        Ok(results)
+    }
+
+    async fn clear_search_index(&self) -> Result<()> {
+        self.search_index.write().unwrap().clear();
+        Ok(())
+    }
+}
+
+/// Insert a new chunk between the given chunks, linking them to it.
+fn insert_chunk(
+    room_chunks: &mut BTreeMap<ChunkIdentifier, RoomEventsRawChunk>,
+    previous: Option<ChunkIdentifier>,
+    new: ChunkIdentifier,
+    next: Option<ChunkIdentifier>,
+    content: ChunkContent<SyncTimelineEvent, Gap>,
+) {
+    if let Some(previous) = previous.and_then(|id| room_chunks.get_mut(&id)) {
+        previous.next = Some(new);
+    }
+    if let Some(next) = next.and_then(|id| room_chunks.get_mut(&id)) {
+        next.previous = Some(new);
+    }
+
+    room_chunks.insert(new, RawChunk { identifier: new, previous, next, content });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventCacheStore, MemoryStore, Result};
+
+    async fn get_event_cache_store() -> Result<impl EventCacheStore> {
+        Ok(MemoryStore::new())
+    }
+
+    event_cache_store_integration_tests!();
+}
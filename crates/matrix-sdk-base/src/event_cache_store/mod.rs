@@ -0,0 +1,163 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The event cache store holds the events the event cache has seen for each
+//! room, so they can be reloaded after a restart without hitting the network.
+//!
+//! It also holds the local search index, that allows searching the messages of
+//! encrypted rooms, which the homeserver can't search.
+//!
+//! Implementing the `EventCacheStore` trait, you can plug any storage backend
+//! into the event cache. By default this brings an in-memory store.
+
+use std::collections::BTreeSet;
+
+use matrix_sdk_common::linked_chunk::{RawChunk, Update};
+use matrix_sdk_store_encryption::Error as StoreEncryptionError;
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedUserId};
+use serde::{Deserialize, Serialize};
+
+use crate::deserialized_responses::SyncTimelineEvent;
+
+#[cfg(any(test, feature = "testing"))]
+#[macro_use]
+pub mod integration_tests;
+mod memory_store;
+mod traits;
+
+#[cfg(any(test, feature = "testing"))]
+pub use self::integration_tests::EventCacheStoreIntegrationTests;
+pub use self::{
+    memory_store::MemoryStore,
+    traits::{DynEventCacheStore, EventCacheStore, IntoEventCacheStore},
+};
+
+/// A hole in the history of a room, as persisted in an [`EventCacheStore`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Gap {
+    /// The token to use to back-paginate into this gap.
+    pub prev_token: String,
+}
+
+/// A modification of the history of a room, to apply with
+/// [`EventCacheStore::handle_linked_chunk_updates`].
+pub type RoomEventsUpdate = Update<SyncTimelineEvent, Gap>;
+
+/// A chunk of the history of a room, as returned by
+/// [`EventCacheStore::reload_linked_chunk`].
+pub type RoomEventsRawChunk = RawChunk<SyncTimelineEvent, Gap>;
+
+/// An event in the local search index of an [`EventCacheStore`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchIndexEntry {
+    /// The ID of the room of the event.
+    pub room_id: OwnedRoomId,
+
+    /// The ID of the event.
+    pub event_id: OwnedEventId,
+
+    /// The sender of the event.
+    pub sender: OwnedUserId,
+
+    /// The time at which the event was sent.
+    pub origin_server_ts: MilliSecondsSinceUnixEpoch,
+
+    /// The text of the event, which is split into words with
+    /// [`search_index_tokens`] to be indexed.
+    pub body: String,
+}
+
+/// A query on the local search index of an [`EventCacheStore`].
+#[derive(Clone, Debug)]
+pub struct SearchIndexQuery {
+    /// The words to search for.
+    ///
+    /// Only the events containing all the words of this text match. Words
+    /// match exactly, ignoring case. If this doesn't contain any word, all the
+    /// events match.
+    pub text: String,
+
+    /// Only match the events of this room.
+    pub room_id: Option<OwnedRoomId>,
+
+    /// Only match the events of this sender.
+    pub sender: Option<OwnedUserId>,
+
+    /// Only match the events sent at or after this time.
+    pub since: Option<MilliSecondsSinceUnixEpoch>,
+
+    /// Only match the events sent at or before this time.
+    pub until: Option<MilliSecondsSinceUnixEpoch>,
+
+    /// The maximum number of events to return.
+    pub limit: usize,
+}
+
+impl SearchIndexQuery {
+    /// The default value of [`SearchIndexQuery::limit`].
+    pub const DEFAULT_LIMIT: usize = 50;
+
+    /// Create a query for the given words, in all the rooms.
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            room_id: None,
+            sender: None,
+            since: None,
+            until: None,
+            limit: Self::DEFAULT_LIMIT,
+        }
+    }
+}
+
+/// Split the given text into the lowercase words that are stored in the local
+/// search index.
+pub fn search_index_tokens(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// Event cache store specific error type.
+#[derive(Debug, thiserror::Error)]
+pub enum EventCacheStoreError {
+    /// An error happened in the underlying database backend.
+    #[error(transparent)]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+
+    /// An error happened while serializing or deserializing some data.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// The store failed to encrypt or decrypt some data.
+    #[error("Error encrypting or decrypting data from the event cache store: {0}")]
+    Encryption(#[from] StoreEncryptionError),
+}
+
+impl EventCacheStoreError {
+    /// Create a new [`Backend`][Self::Backend] error.
+    ///
+    /// Shorthand for `EventCacheStoreError::Backend(Box::new(error))`.
+    #[inline]
+    pub fn backend<E>(error: E) -> Self
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Self::Backend(Box::new(error))
+    }
+}
+
+/// An `EventCacheStore` specific result type.
+pub type Result<T, E = EventCacheStoreError> = std::result::Result<T, E>;
@@ -0,0 +1,183 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fmt, sync::Arc};
+
+use async_trait::async_trait;
+use matrix_sdk_common::AsyncTraitDeps;
+use ruma::{EventId, RoomId};
+
+use super::{
+    EventCacheStoreError, RoomEventsRawChunk, RoomEventsUpdate, SearchIndexEntry, SearchIndexQuery,
+};
+
+/// An abstract trait that can be used to implement different stores for the
+/// event cache.
+///
+/// It really acts as a cache, in the sense that clearing the backing data
+/// should not have any irremediable effect, other than providing a lesser user
+/// experience.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait EventCacheStore: AsyncTraitDeps {
+    /// The error type used by this event cache store.
+    type Error: fmt::Debug + Into<EventCacheStoreError>;
+
+    /// Apply the given modifications, in order, to the history of the given
+    /// room.
+    ///
+    /// The history of a room is a linked chunk of events and gaps, that is
+    /// persisted incrementally with these updates.
+    async fn handle_linked_chunk_updates(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<RoomEventsUpdate>,
+    ) -> Result<(), Self::Error>;
+
+    /// Returns the chunks of the history of the given room, in no particular
+    /// order, to rebuild its linked chunk.
+    async fn reload_linked_chunk(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<RoomEventsRawChunk>, Self::Error>;
+
+    /// Add the given events to the local search index.
+    ///
+    /// An event that is already indexed is replaced.
+    async fn index_events(&self, entries: Vec<SearchIndexEntry>) -> Result<(), Self::Error>;
+
+    /// Get the given event from the local search index, if it's there.
+    async fn get_indexed_event(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Result<Option<SearchIndexEntry>, Self::Error>;
+
+    /// Remove the given event from the local search index, if it's there.
+    async fn remove_indexed_event(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Result<(), Self::Error>;
+
+    /// Search the local search index.
+    ///
+    /// Returns the matching events, from the most recent to the oldest one.
+    async fn search_index(
+        &self,
+        query: &SearchIndexQuery,
+    ) -> Result<Vec<SearchIndexEntry>, Self::Error>;
+
+    /// Remove all the events from the local search index.
+    async fn clear_search_index(&self) -> Result<(), Self::Error>;
+}
+
+#[repr(transparent)]
+struct EraseEventCacheStoreError<T>(T);
+
+#[cfg(not(tarpaulin_include))]
+impl<T: fmt::Debug> fmt::Debug for EraseEventCacheStoreError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<T: EventCacheStore> EventCacheStore for EraseEventCacheStoreError<T> {
+    type Error = EventCacheStoreError;
+
+    async fn handle_linked_chunk_updates(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<RoomEventsUpdate>,
+    ) -> Result<(), Self::Error> {
+        self.0.handle_linked_chunk_updates(room_id, updates).await.map_err(Into::into)
+    }
+
+    async fn reload_linked_chunk(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<RoomEventsRawChunk>, Self::Error> {
+        self.0.reload_linked_chunk(room_id).await.map_err(Into::into)
+    }
+
+    async fn index_events(&self, entries: Vec<SearchIndexEntry>) -> Result<(), Self::Error> {
+        self.0.index_events(entries).await.map_err(Into::into)
+    }
+
+    async fn get_indexed_event(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Result<Option<SearchIndexEntry>, Self::Error> {
+        self.0.get_indexed_event(room_id, event_id).await.map_err(Into::into)
+    }
+
+    async fn remove_indexed_event(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Result<(), Self::Error> {
+        self.0.remove_indexed_event(room_id, event_id).await.map_err(Into::into)
+    }
+
+    async fn search_index(
+        &self,
+        query: &SearchIndexQuery,
+    ) -> Result<Vec<SearchIndexEntry>, Self::Error> {
+        self.0.search_index(query).await.map_err(Into::into)
+    }
+
+    async fn clear_search_index(&self) -> Result<(), Self::Error> {
+        self.0.clear_search_index().await.map_err(Into::into)
+    }
+}
+
+/// A type-erased [`EventCacheStore`].
+pub type DynEventCacheStore = dyn EventCacheStore<Error = EventCacheStoreError>;
+
+/// A type that can be type-erased into `Arc<dyn EventCacheStore>`.
+///
+/// This trait is not meant to be implemented directly outside
+/// `matrix-sdk-base`, but it is automatically implemented for everything that
+/// implements `EventCacheStore`.
+pub trait IntoEventCacheStore {
+    #[doc(hidden)]
+    fn into_event_cache_store(self) -> Arc<DynEventCacheStore>;
+}
+
+impl<T> IntoEventCacheStore for T
+where
+    T: EventCacheStore + Sized + 'static,
+{
+    fn into_event_cache_store(self) -> Arc<DynEventCacheStore> {
+        Arc::new(EraseEventCacheStoreError(self))
+    }
+}
+
+// Turns a given `Arc<T>` into `Arc<DynEventCacheStore>` by attaching the
+// EventCacheStore impl vtable of `EraseEventCacheStoreError<T>`.
+impl<T> IntoEventCacheStore for Arc<T>
+where
+    T: EventCacheStore + 'static,
+{
+    fn into_event_cache_store(self) -> Arc<DynEventCacheStore> {
+        let ptr: *const T = Arc::into_raw(self);
+        let ptr_erased = ptr as *const EraseEventCacheStoreError<T>;
+        // SAFETY: EraseEventCacheStoreError is repr(transparent) so T and
+        //         EraseEventCacheStoreError<T> have the same layout and ABI
+        unsafe { Arc::from_raw(ptr_erased) }
+    }
+}
@@ -27,8 +27,10 @@ mod client;
 pub mod debug;
 pub mod deserialized_responses;
 mod error;
+pub mod event_cache_store;
 pub mod latest_event;
 pub mod media;
+pub mod media_cache_store;
 mod rooms;
 
 pub mod read_receipts;
@@ -50,8 +52,8 @@ pub use http;
 pub use matrix_sdk_crypto as crypto;
 pub use once_cell;
 pub use rooms::{
-    DisplayName, Room, RoomCreateWithCreatorEventContent, RoomInfo, RoomInfoUpdate, RoomMember,
-    RoomMemberships, RoomState, RoomStateFilter,
+    DisplayName, PredecessorRoom, Room, RoomCreateWithCreatorEventContent, RoomInfo,
+    RoomInfoUpdate, RoomMember, RoomMemberships, RoomState, RoomStateFilter, SuccessorRoom,
 };
 pub use store::{StateChanges, StateStore, StateStoreDataKey, StateStoreDataValue, StoreError};
 pub use utils::{
@@ -0,0 +1,469 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use ruma::{MilliSecondsSinceUnixEpoch, MxcUri};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{fs, sync::RwLock};
+
+use super::{MediaCacheEntry, MediaCacheStore, MediaCacheStoreError, Result};
+use crate::media::{MediaRequest, UniqueKey};
+
+/// The name of the file holding the metadata of the media files.
+const INDEX_FILE_NAME: &str = "index.json";
+/// The name of the file holding the metadata of the URL previews.
+const URL_PREVIEWS_INDEX_FILE_NAME: &str = "url_previews.json";
+/// The name of the directory holding the data of the URL previews.
+const URL_PREVIEWS_DIR_NAME: &str = "url_previews";
+/// The minimum interval between two writes of the index that only update the
+/// times the media files were last accessed.
+const ACCESS_TIMES_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The metadata of a URL preview, as it is stored in the index of the URL
+/// previews.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct UrlPreviewEntry {
+    url: String,
+    ts: Option<MilliSecondsSinceUnixEpoch>,
+    added_at: MilliSecondsSinceUnixEpoch,
+    size: usize,
+}
+
+/// The metadata of the media files of a [`FileSystemStore`].
+#[derive(Debug)]
+struct Index {
+    /// The metadata of the media files, by key.
+    entries: BTreeMap<String, MediaCacheEntry>,
+
+    /// When the index was last written to its file.
+    saved_at: Instant,
+}
+
+/// A `MediaCacheStore` that keeps the media files in a directory.
+///
+/// Each media file is stored in its own file, named after the hash of its key,
+/// and the metadata of all the files is stored in an `index.json` file in the
+/// same directory. Reading a file only updates the time it was last accessed
+/// in memory, the index file is updated at most once a minute for reads, or
+/// with the next write. URL previews are stored the same way, in a
+/// `url_previews` subdirectory with their metadata in a `url_previews.json`
+/// file.
+///
+/// The content of the files is **not** encrypted, so the directory should be
+/// protected by other means, for example by using the cache directory of the
+/// application, that is only accessible to it.
+#[derive(Debug)]
+pub struct FileSystemStore {
+    /// The directory containing the files.
+    path: PathBuf,
+
+    /// The metadata of the media files.
+    index: RwLock<Index>,
+
+    /// The metadata of the URL previews.
+    url_previews: RwLock<Vec<UrlPreviewEntry>>,
+}
+
+impl FileSystemStore {
+    /// Open the store in the given directory.
+    ///
+    /// The directory is created if it doesn't exist.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        fs::create_dir_all(path.join(URL_PREVIEWS_DIR_NAME))
+            .await
+            .map_err(MediaCacheStoreError::backend)?;
+
+        let entries = read_json_file::<Vec<MediaCacheEntry>>(&path.join(INDEX_FILE_NAME))
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| (entry.key.clone(), entry))
+            .collect();
+        let index = Index { entries, saved_at: Instant::now() };
+        let url_previews =
+            read_json_file(&path.join(URL_PREVIEWS_INDEX_FILE_NAME)).await?.unwrap_or_default();
+
+        Ok(Self { path, index: RwLock::new(index), url_previews: RwLock::new(url_previews) })
+    }
+
+    /// The path of the file containing the content of the media with the
+    /// given key.
+    fn content_path(&self, key: &str) -> PathBuf {
+        self.path.join(format!("{:x}", Sha256::digest(key.as_bytes())))
+    }
+
+    /// The path of the file containing the data of the URL preview with the
+    /// given URL and timestamp.
+    fn url_preview_path(
+        &self,
+        url: &str,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+    ) -> Result<PathBuf> {
+        // Serialize both parts, so the name can't be ambiguous whatever the URL.
+        let key = serde_json::to_vec(&(url, ts))?;
+        Ok(self.path.join(URL_PREVIEWS_DIR_NAME).join(format!("{:x}", Sha256::digest(key))))
+    }
+
+    /// Persist the index.
+    async fn save_index(&self, index: &mut Index) -> Result<()> {
+        self.write_json_file(INDEX_FILE_NAME, &index.entries.values().collect::<Vec<_>>()).await?;
+        index.saved_at = Instant::now();
+        Ok(())
+    }
+
+    /// Persist the metadata of the URL previews.
+    async fn save_url_previews(&self, url_previews: &[UrlPreviewEntry]) -> Result<()> {
+        self.write_json_file(URL_PREVIEWS_INDEX_FILE_NAME, &url_previews).await
+    }
+
+    /// Serialize the given value to the file with the given name.
+    async fn write_json_file(&self, name: &str, value: &impl Serialize) -> Result<()> {
+        let bytes = serde_json::to_vec(value)?;
+
+        // Write to a temporary file first, so the file is never left
+        // half-written.
+        let tmp_path = self.path.join(format!("{name}.tmp"));
+        fs::write(&tmp_path, bytes).await.map_err(MediaCacheStoreError::backend)?;
+        fs::rename(&tmp_path, self.path.join(name)).await.map_err(MediaCacheStoreError::backend)
+    }
+
+    /// Remove the content of the media with the given key, if it exists.
+    async fn remove_content(&self, key: &str) -> Result<()> {
+        remove_file(&self.content_path(key)).await
+    }
+
+    /// Remove the data of the given URL preview, if it exists.
+    async fn remove_url_preview_data(&self, preview: &UrlPreviewEntry) -> Result<()> {
+        remove_file(&self.url_preview_path(&preview.url, preview.ts)?).await
+    }
+}
+
+#[async_trait]
+impl MediaCacheStore for FileSystemStore {
+    type Error = MediaCacheStoreError;
+
+    async fn add_media_content(
+        &self,
+        request: &MediaRequest,
+        content: Vec<u8>,
+        pinned: bool,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<()> {
+        let key = request.unique_key();
+        let mut index = self.index.write().await;
+        let was_pinned = index.entries.get(&key).is_some_and(|entry| entry.pinned);
+
+        let entry = MediaCacheEntry {
+            key: key.clone(),
+            uri: request.uri().to_owned(),
+            size: content.len(),
+            added_at: now,
+            last_accessed_at: now,
+            pinned: pinned || was_pinned,
+        };
+
+        fs::write(self.content_path(&key), content).await.map_err(MediaCacheStoreError::backend)?;
+        index.entries.insert(key, entry);
+
+        self.save_index(&mut index).await
+    }
+
+    async fn get_media_content(
+        &self,
+        request: &MediaRequest,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<Option<Vec<u8>>> {
+        let key = request.unique_key();
+        let mut index = self.index.write().await;
+        if !index.entries.contains_key(&key) {
+            return Ok(None);
+        }
+
+        let content = match fs::read(self.content_path(&key)).await {
+            Ok(content) => content,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                // The file was removed behind our back, forget about it.
+                index.entries.remove(&key);
+                self.save_index(&mut index).await?;
+                return Ok(None);
+            }
+            Err(error) => return Err(MediaCacheStoreError::backend(error)),
+        };
+
+        if let Some(entry) = index.entries.get_mut(&key) {
+            entry.last_accessed_at = now;
+        }
+
+        // Don't rewrite the whole index for every read, the access times are
+        // only used to choose the files to evict so they can lag a bit.
+        if index.saved_at.elapsed() >= ACCESS_TIMES_SAVE_INTERVAL {
+            self.save_index(&mut index).await?;
+        }
+
+        Ok(Some(content))
+    }
+
+    async fn set_media_pinned(&self, request: &MediaRequest, pinned: bool) -> Result<()> {
+        let mut index = self.index.write().await;
+        let Some(entry) = index.entries.get_mut(&request.unique_key()) else { return Ok(()) };
+
+        if entry.pinned == pinned {
+            return Ok(());
+        }
+        entry.pinned = pinned;
+        self.save_index(&mut index).await
+    }
+
+    async fn remove_media_content(&self, request: &MediaRequest) -> Result<()> {
+        self.remove_media_entries(&[request.unique_key()]).await
+    }
+
+    async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<()> {
+        let keys = self
+            .index
+            .read()
+            .await
+            .entries
+            .values()
+            .filter(|entry| *entry.uri == *uri)
+            .map(|entry| entry.key.clone())
+            .collect::<Vec<_>>();
+
+        self.remove_media_entries(&keys).await
+    }
+
+    async fn media_entries(&self) -> Result<Vec<MediaCacheEntry>> {
+        Ok(self.index.read().await.entries.values().cloned().collect())
+    }
+
+    async fn remove_media_entries(&self, keys: &[String]) -> Result<()> {
+        let mut index = self.index.write().await;
+
+        let mut removed = false;
+
+        for key in keys {
+            if index.entries.remove(key).is_some() {
+                self.remove_content(key).await?;
+                removed = true;
+            }
+        }
+
+        if !removed {
+            return Ok(());
+        }
+        self.save_index(&mut index).await
+    }
+
+    async fn clear_media_cache(&self) -> Result<()> {
+        let mut index = self.index.write().await;
+
+        for key in index.entries.keys() {
+            self.remove_content(key).await?;
+        }
+        index.entries.clear();
+        self.save_index(&mut index).await?;
+
+        let mut url_previews = self.url_previews.write().await;
+        for preview in url_previews.iter() {
+            self.remove_url_preview_data(preview).await?;
+        }
+        url_previews.clear();
+        self.save_url_previews(&url_previews).await
+    }
+
+    async fn add_url_preview(
+        &self,
+        url: &str,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+        data: Vec<u8>,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<()> {
+        let mut url_previews = self.url_previews.write().await;
+        let entry = UrlPreviewEntry { url: url.to_owned(), ts, added_at: now, size: data.len() };
+
+        fs::write(self.url_preview_path(url, ts)?, data)
+            .await
+            .map_err(MediaCacheStoreError::backend)?;
+        url_previews.retain(|preview| preview.url != url || preview.ts != ts);
+        url_previews.push(entry);
+
+        self.save_url_previews(&url_previews).await
+    }
+
+    async fn get_url_preview(
+        &self,
+        url: &str,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+    ) -> Result<Option<Vec<u8>>> {
+        let mut url_previews = self.url_previews.write().await;
+        if !url_previews.iter().any(|preview| preview.url == url && preview.ts == ts) {
+            return Ok(None);
+        }
+
+        match fs::read(self.url_preview_path(url, ts)?).await {
+            Ok(data) => Ok(Some(data)),
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                // The file was removed behind our back, forget about it.
+                url_previews.retain(|preview| preview.url != url || preview.ts != ts);
+                self.save_url_previews(&url_previews).await?;
+                Ok(None)
+            }
+            Err(error) => Err(MediaCacheStoreError::backend(error)),
+        }
+    }
+
+    async fn remove_url_previews_added_before(
+        &self,
+        before: MilliSecondsSinceUnixEpoch,
+    ) -> Result<()> {
+        let mut url_previews = self.url_previews.write().await;
+        let (removed, kept): (Vec<_>, Vec<_>) =
+            url_previews.drain(..).partition(|preview| preview.added_at < before);
+        *url_previews = kept;
+
+        if removed.is_empty() {
+            return Ok(());
+        }
+        for preview in &removed {
+            self.remove_url_preview_data(preview).await?;
+        }
+        self.save_url_previews(&url_previews).await
+    }
+
+    async fn url_previews_size(&self) -> Result<usize> {
+        Ok(self.url_previews.read().await.iter().map(|preview| preview.size).sum())
+    }
+}
+
+/// Deserialize the content of the file at the given path, if it exists.
+async fn read_json_file<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    match fs::read(path).await {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(MediaCacheStoreError::backend(error)),
+    }
+}
+
+/// Remove the file at the given path, if it exists.
+async fn remove_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(MediaCacheStoreError::backend(error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_test::async_test;
+    use once_cell::sync::Lazy;
+    use ruma::{events::room::MediaSource, mxc_uri, uint, MilliSecondsSinceUnixEpoch};
+    use tempfile::{tempdir, TempDir};
+
+    use super::{
+        FileSystemStore, MediaCacheStore, Result, INDEX_FILE_NAME, URL_PREVIEWS_DIR_NAME,
+        URL_PREVIEWS_INDEX_FILE_NAME,
+    };
+    use crate::media::{MediaFormat, MediaRequest};
+
+    static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    async fn get_media_cache_store() -> Result<impl MediaCacheStore> {
+        let name = NUM.fetch_add(1, SeqCst).to_string();
+        FileSystemStore::open(TMP_DIR.path().join(name)).await
+    }
+
+    media_cache_store_integration_tests!();
+
+    #[async_test]
+    async fn test_reopen_store() {
+        let path = TMP_DIR.path().join("reopen");
+        let request = MediaRequest {
+            source: MediaSource::Plain(mxc_uri!("mxc://localhost/media").to_owned()),
+            format: MediaFormat::File,
+        };
+        let now = MilliSecondsSinceUnixEpoch::now();
+
+        let store = FileSystemStore::open(&path).await.unwrap();
+        store.add_media_content(&request, b"hello".to_vec(), true, now).await.unwrap();
+        drop(store);
+
+        let store = FileSystemStore::open(&path).await.unwrap();
+        assert_eq!(store.get_media_content(&request, now).await.unwrap().unwrap(), b"hello");
+
+        let entries = store.media_entries().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].pinned);
+    }
+
+    #[async_test]
+    async fn test_read_does_not_rewrite_index() {
+        let path = TMP_DIR.path().join("read");
+        let request = MediaRequest {
+            source: MediaSource::Plain(mxc_uri!("mxc://localhost/media").to_owned()),
+            format: MediaFormat::File,
+        };
+        let now = MilliSecondsSinceUnixEpoch(uint!(1000));
+
+        let store = FileSystemStore::open(&path).await.unwrap();
+        store.add_media_content(&request, b"hello".to_vec(), false, now).await.unwrap();
+        let index = std::fs::read(path.join(INDEX_FILE_NAME)).unwrap();
+
+        let later = MilliSecondsSinceUnixEpoch(uint!(2000));
+        assert_eq!(store.get_media_content(&request, later).await.unwrap().unwrap(), b"hello");
+
+        // The access time is updated in memory, but the index file is untouched.
+        assert_eq!(store.media_entries().await.unwrap()[0].last_accessed_at, later);
+        assert_eq!(std::fs::read(path.join(INDEX_FILE_NAME)).unwrap(), index);
+    }
+
+    #[async_test]
+    async fn test_url_previews_are_stored_in_their_own_files() {
+        let path = TMP_DIR.path().join("url_previews");
+        let now = MilliSecondsSinceUnixEpoch(uint!(1000));
+
+        let store = FileSystemStore::open(&path).await.unwrap();
+        store.add_url_preview("https://matrix.org", None, b"hello".to_vec(), now).await.unwrap();
+        store
+            .add_url_preview("https://matrix.org/blog", None, b"world".to_vec(), now)
+            .await
+            .unwrap();
+
+        // Each preview has its own file, the index only contains the metadata.
+        assert_eq!(std::fs::read_dir(path.join(URL_PREVIEWS_DIR_NAME)).unwrap().count(), 2);
+        let index = std::fs::read_to_string(path.join(URL_PREVIEWS_INDEX_FILE_NAME)).unwrap();
+        assert!(!index.contains("data"));
+        assert_eq!(store.url_previews_size().await.unwrap(), 10);
+        drop(store);
+
+        let store = FileSystemStore::open(&path).await.unwrap();
+        assert_eq!(
+            store.get_url_preview("https://matrix.org", None).await.unwrap().unwrap(),
+            b"hello"
+        );
+        assert_eq!(store.url_previews_size().await.unwrap(), 10);
+    }
+}
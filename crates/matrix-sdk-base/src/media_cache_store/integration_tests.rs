@@ -0,0 +1,315 @@
+//! Trait and macro of integration tests for MediaCacheStore implementations.
+
+use async_trait::async_trait;
+use ruma::{
+    api::client::media::get_content_thumbnail::v3::Method, events::room::MediaSource, mxc_uri,
+    uint, MilliSecondsSinceUnixEpoch, MxcUri,
+};
+
+use super::{DynMediaCacheStore, MediaCacheEntry, Result};
+use crate::media::{MediaFormat, MediaRequest, MediaThumbnailSize, UniqueKey};
+
+/// `MediaCacheStore` integration tests.
+///
+/// This trait is not meant to be used directly, but will be used with the
+/// [`media_cache_store_integration_tests!`] macro.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait MediaCacheStoreIntegrationTests {
+    /// Test adding, reading and removing media content.
+    async fn test_media_content(&self) -> Result<()>;
+    /// Test the metadata of the media entries.
+    async fn test_media_entries(&self) -> Result<()>;
+    /// Test pinning media content.
+    async fn test_media_pinned(&self) -> Result<()>;
+    /// Test removing several media entries and clearing the media cache.
+    async fn test_remove_media_entries(&self) -> Result<()>;
+    /// Test adding, reading and removing URL previews.
+    async fn test_url_previews(&self) -> Result<()>;
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl MediaCacheStoreIntegrationTests for DynMediaCacheStore {
+    async fn test_media_content(&self) -> Result<()> {
+        let uri = mxc_uri!("mxc://localhost/media");
+        let request_file = file_request(uri);
+        let request_thumbnail = thumbnail_request(uri);
+        let request_other_file = file_request(mxc_uri!("mxc://localhost/media-other"));
+        let now = ts(0);
+
+        let content: Vec<u8> = "hello".into();
+        let thumbnail_content: Vec<u8> = "world".into();
+        let other_content: Vec<u8> = "foo".into();
+
+        // Media isn't present in the cache.
+        assert!(self.get_media_content(&request_file, now).await?.is_none());
+        assert!(self.get_media_content(&request_thumbnail, now).await?.is_none());
+
+        // Let's add the media.
+        self.add_media_content(&request_file, content.clone(), false, now).await?;
+        assert_eq!(self.get_media_content(&request_file, now).await?, Some(content.clone()));
+
+        // Let's remove the media.
+        self.remove_media_content(&request_file).await?;
+        assert!(self.get_media_content(&request_file, now).await?.is_none());
+
+        // Let's add all the media.
+        self.add_media_content(&request_file, content.clone(), false, now).await?;
+        self.add_media_content(&request_thumbnail, thumbnail_content.clone(), false, now).await?;
+        self.add_media_content(&request_other_file, other_content.clone(), false, now).await?;
+
+        assert_eq!(self.get_media_content(&request_file, now).await?, Some(content));
+        assert_eq!(self.get_media_content(&request_thumbnail, now).await?, Some(thumbnail_content));
+        assert_eq!(
+            self.get_media_content(&request_other_file, now).await?,
+            Some(other_content.clone())
+        );
+
+        // Replacing the content works.
+        let new_content: Vec<u8> = "bar".into();
+        self.add_media_content(&request_other_file, new_content.clone(), false, now).await?;
+        assert_eq!(self.get_media_content(&request_other_file, now).await?, Some(new_content));
+
+        // Let's remove the media of the first URI.
+        self.remove_media_content_for_uri(uri).await?;
+        assert!(self.get_media_content(&request_file, now).await?.is_none());
+        assert!(self.get_media_content(&request_thumbnail, now).await?.is_none());
+        assert!(self.get_media_content(&request_other_file, now).await?.is_some());
+
+        Ok(())
+    }
+
+    async fn test_media_entries(&self) -> Result<()> {
+        let uri = mxc_uri!("mxc://localhost/media");
+        let request_file = file_request(uri);
+        let request_thumbnail = thumbnail_request(uri);
+
+        assert!(self.media_entries().await?.is_empty());
+
+        self.add_media_content(&request_file, "hello".into(), false, ts(10)).await?;
+        self.add_media_content(&request_thumbnail, "hi".into(), true, ts(20)).await?;
+
+        let mut entries = self.media_entries().await?;
+        entries.sort_by_key(|entry| entry.added_at);
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(entries[0].key, request_file.unique_key());
+        assert_eq!(*entries[0].uri, *uri);
+        assert_eq!(entries[0].size, 5);
+        assert_eq!(entries[0].added_at, ts(10));
+        assert_eq!(entries[0].last_accessed_at, ts(10));
+        assert!(!entries[0].pinned);
+
+        assert_eq!(entries[1].key, request_thumbnail.unique_key());
+        assert_eq!(entries[1].size, 2);
+        assert!(entries[1].pinned);
+
+        // Reading the content updates the last access time.
+        self.get_media_content(&request_file, ts(30)).await?;
+
+        let entry = self
+            .media_entries()
+            .await?
+            .into_iter()
+            .find(|entry| entry.key == request_file.unique_key())
+            .unwrap();
+        assert_eq!(entry.added_at, ts(10));
+        assert_eq!(entry.last_accessed_at, ts(30));
+
+        Ok(())
+    }
+
+    async fn test_media_pinned(&self) -> Result<()> {
+        let request = file_request(mxc_uri!("mxc://localhost/media"));
+        let is_pinned = |entries: Vec<MediaCacheEntry>| {
+            entries.into_iter().find(|entry| entry.key == request.unique_key()).unwrap().pinned
+        };
+
+        // Pinning a media that isn't in the cache does nothing.
+        self.set_media_pinned(&request, true).await?;
+        assert!(self.media_entries().await?.is_empty());
+
+        self.add_media_content(&request, "hello".into(), false, ts(0)).await?;
+        assert!(!is_pinned(self.media_entries().await?));
+
+        self.set_media_pinned(&request, true).await?;
+        assert!(is_pinned(self.media_entries().await?));
+
+        // Replacing the content keeps the media pinned.
+        self.add_media_content(&request, "world".into(), false, ts(0)).await?;
+        assert!(is_pinned(self.media_entries().await?));
+
+        self.set_media_pinned(&request, false).await?;
+        assert!(!is_pinned(self.media_entries().await?));
+
+        Ok(())
+    }
+
+    async fn test_remove_media_entries(&self) -> Result<()> {
+        let request_file = file_request(mxc_uri!("mxc://localhost/media"));
+        let request_other_file = file_request(mxc_uri!("mxc://localhost/media-other"));
+        let request_pinned_file = file_request(mxc_uri!("mxc://localhost/media-pinned"));
+
+        self.add_media_content(&request_file, "hello".into(), false, ts(0)).await?;
+        self.add_media_content(&request_other_file, "world".into(), false, ts(0)).await?;
+        self.add_media_content(&request_pinned_file, "foo".into(), true, ts(0)).await?;
+
+        self.remove_media_entries(&[request_file.unique_key(), "unknown".to_owned()]).await?;
+
+        assert!(self.get_media_content(&request_file, ts(0)).await?.is_none());
+        assert!(self.get_media_content(&request_other_file, ts(0)).await?.is_some());
+        assert_eq!(self.media_entries().await?.len(), 2);
+
+        // Clearing the cache also removes pinned media.
+        self.clear_media_cache().await?;
+
+        assert!(self.get_media_content(&request_other_file, ts(0)).await?.is_none());
+        assert!(self.get_media_content(&request_pinned_file, ts(0)).await?.is_none());
+        assert!(self.media_entries().await?.is_empty());
+
+        Ok(())
+    }
+
+    async fn test_url_previews(&self) -> Result<()> {
+        let url = "https://matrix.org";
+        let other_url = "https://matrix.org/blog";
+
+        // The preview isn't present in the cache.
+        assert!(self.get_url_preview(url, None).await?.is_none());
+
+        // Let's add the previews, they are different for each timestamp.
+        self.add_url_preview(url, None, "hello".into(), ts(0)).await?;
+        self.add_url_preview(url, Some(ts(5)), "world".into(), ts(10)).await?;
+        self.add_url_preview(other_url, None, "foo".into(), ts(20)).await?;
+
+        assert_eq!(self.get_url_preview(url, None).await?, Some("hello".into()));
+        assert_eq!(self.get_url_preview(url, Some(ts(5))).await?, Some("world".into()));
+        assert!(self.get_url_preview(url, Some(ts(6))).await?.is_none());
+        assert_eq!(self.get_url_preview(other_url, None).await?, Some("foo".into()));
+
+        assert_eq!(self.url_previews_size().await?, 13);
+
+        // Replacing the data works.
+        self.add_url_preview(url, None, "bar".into(), ts(0)).await?;
+        assert_eq!(self.get_url_preview(url, None).await?, Some("bar".into()));
+        assert_eq!(self.url_previews_size().await?, 11);
+
+        // A URL containing the timestamp doesn't clash with the URL and the
+        // timestamp.
+        let url_with_ts = "https://matrix.org 5";
+        self.add_url_preview(url_with_ts, None, "baz".into(), ts(20)).await?;
+        assert_eq!(self.get_url_preview(url_with_ts, None).await?, Some("baz".into()));
+        assert_eq!(self.get_url_preview(url, Some(ts(5))).await?, Some("world".into()));
+
+        // Previews are removed by age.
+        self.remove_url_previews_added_before(ts(10)).await?;
+        assert!(self.get_url_preview(url, None).await?.is_none());
+        assert!(self.get_url_preview(url, Some(ts(5))).await?.is_some());
+        assert!(self.get_url_preview(other_url, None).await?.is_some());
+        assert_eq!(self.url_previews_size().await?, 11);
+
+        // They don't count as media entries.
+        assert!(self.media_entries().await?.is_empty());
+
+        // Clearing the cache removes all of them.
+        self.clear_media_cache().await?;
+        assert!(self.get_url_preview(url, Some(ts(5))).await?.is_none());
+        assert!(self.get_url_preview(other_url, None).await?.is_none());
+        assert_eq!(self.url_previews_size().await?, 0);
+
+        Ok(())
+    }
+}
+
+fn ts(millis: u32) -> MilliSecondsSinceUnixEpoch {
+    MilliSecondsSinceUnixEpoch(millis.into())
+}
+
+fn file_request(uri: &MxcUri) -> MediaRequest {
+    MediaRequest { source: MediaSource::Plain(uri.to_owned()), format: MediaFormat::File }
+}
+
+fn thumbnail_request(uri: &MxcUri) -> MediaRequest {
+    MediaRequest {
+        source: MediaSource::Plain(uri.to_owned()),
+        format: MediaFormat::Thumbnail(MediaThumbnailSize {
+            method: Method::Crop,
+            width: uint!(100),
+            height: uint!(100),
+        }),
+    }
+}
+
+/// Macro building to allow your `MediaCacheStore` implementation to run the
+/// entire tests suite locally.
+///
+/// You need to provide a `async fn get_media_cache_store() ->
+/// MediaCacheStoreResult<impl MediaCacheStore>` providing a fresh media cache
+/// store on the same level you invoke the macro.
+///
+/// ## Usage Example:
+/// ```no_run
+/// # use matrix_sdk_base::media_cache_store::{
+/// #    MediaCacheStore,
+/// #    MemoryStore as MyStore,
+/// #    Result as MediaCacheStoreResult,
+/// # };
+///
+/// #[cfg(test)]
+/// mod tests {
+///     use super::{MediaCacheStore, MediaCacheStoreResult, MyStore};
+///
+///     async fn get_media_cache_store(
+///     ) -> MediaCacheStoreResult<impl MediaCacheStore> {
+///         Ok(MyStore::new())
+///     }
+///
+///     media_cache_store_integration_tests!();
+/// }
+/// ```
+#[allow(unused_macros, unused_extern_crates)]
+#[macro_export]
+macro_rules! media_cache_store_integration_tests {
+    () => {
+        mod media_cache_store_integration_tests {
+            use matrix_sdk_test::async_test;
+            use $crate::media_cache_store::{
+                IntoMediaCacheStore, MediaCacheStoreIntegrationTests,
+                Result as MediaCacheStoreResult,
+            };
+
+            use super::get_media_cache_store;
+
+            #[async_test]
+            async fn test_media_content() -> MediaCacheStoreResult<()> {
+                let store = get_media_cache_store().await?.into_media_cache_store();
+                store.test_media_content().await
+            }
+
+            #[async_test]
+            async fn test_media_entries() -> MediaCacheStoreResult<()> {
+                let store = get_media_cache_store().await?.into_media_cache_store();
+                store.test_media_entries().await
+            }
+
+            #[async_test]
+            async fn test_media_pinned() -> MediaCacheStoreResult<()> {
+                let store = get_media_cache_store().await?.into_media_cache_store();
+                store.test_media_pinned().await
+            }
+
+            #[async_test]
+            async fn test_remove_media_entries() -> MediaCacheStoreResult<()> {
+                let store = get_media_cache_store().await?.into_media_cache_store();
+                store.test_remove_media_entries().await
+            }
+
+            #[async_test]
+            async fn test_url_previews() -> MediaCacheStoreResult<()> {
+                let store = get_media_cache_store().await?.into_media_cache_store();
+                store.test_url_previews().await
+            }
+        }
+    };
+}
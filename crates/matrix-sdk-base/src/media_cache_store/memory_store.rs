@@ -0,0 +1,164 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::BTreeMap, sync::RwLock as StdRwLock};
+
+use async_trait::async_trait;
+use ruma::{MilliSecondsSinceUnixEpoch, MxcUri};
+
+use super::{MediaCacheEntry, MediaCacheStore, MediaCacheStoreError, Result};
+use crate::media::{MediaRequest, UniqueKey};
+
+/// In-memory, non-persistent implementation of the `MediaCacheStore`.
+///
+/// Default if no other is configured at startup.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    /// The media files and their metadata, by key.
+    media: StdRwLock<BTreeMap<String, (MediaCacheEntry, Vec<u8>)>>,
+
+    /// The URL previews and the time they were added, by URL and timestamp.
+    url_previews: StdRwLock<UrlPreviewMap>,
+}
+
+type UrlPreviewMap =
+    BTreeMap<(String, Option<MilliSecondsSinceUnixEpoch>), (MilliSecondsSinceUnixEpoch, Vec<u8>)>;
+
+impl MemoryStore {
+    /// Create a new empty [`MemoryStore`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl MediaCacheStore for MemoryStore {
+    type Error = MediaCacheStoreError;
+
+    async fn add_media_content(
+        &self,
+        request: &MediaRequest,
+        content: Vec<u8>,
+        pinned: bool,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<()> {
+        let key = request.unique_key();
+        let mut media = self.media.write().unwrap();
+        let was_pinned = media.get(&key).is_some_and(|(entry, _)| entry.pinned);
+
+        let entry = MediaCacheEntry {
+            key: key.clone(),
+            uri: request.uri().to_owned(),
+            size: content.len(),
+            added_at: now,
+            last_accessed_at: now,
+            pinned: pinned || was_pinned,
+        };
+        media.insert(key, (entry, content));
+
+        Ok(())
+    }
+
+    async fn get_media_content(
+        &self,
+        request: &MediaRequest,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<Option<Vec<u8>>> {
+        let mut media = self.media.write().unwrap();
+
+        Ok(media.get_mut(&request.unique_key()).map(|(entry, content)| {
+            entry.last_accessed_at = now;
+            content.clone()
+        }))
+    }
+
+    async fn set_media_pinned(&self, request: &MediaRequest, pinned: bool) -> Result<()> {
+        if let Some((entry, _)) = self.media.write().unwrap().get_mut(&request.unique_key()) {
+            entry.pinned = pinned;
+        }
+        Ok(())
+    }
+
+    async fn remove_media_content(&self, request: &MediaRequest) -> Result<()> {
+        self.media.write().unwrap().remove(&request.unique_key());
+        Ok(())
+    }
+
+    async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<()> {
+        self.media.write().unwrap().retain(|_, (entry, _)| *entry.uri != *uri);
+        Ok(())
+    }
+
+    async fn media_entries(&self) -> Result<Vec<MediaCacheEntry>> {
+        Ok(self.media.read().unwrap().values().map(|(entry, _)| entry.clone()).collect())
+    }
+
+    async fn remove_media_entries(&self, keys: &[String]) -> Result<()> {
+        let mut media = self.media.write().unwrap();
+        for key in keys {
+            media.remove(key);
+        }
+        Ok(())
+    }
+
+    async fn clear_media_cache(&self) -> Result<()> {
+        self.media.write().unwrap().clear();
+        self.url_previews.write().unwrap().clear();
+        Ok(())
+    }
+
+    async fn add_url_preview(
+        &self,
+        url: &str,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+        data: Vec<u8>,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<()> {
+        self.url_previews.write().unwrap().insert((url.to_owned(), ts), (now, data));
+        Ok(())
+    }
+
+    async fn get_url_preview(
+        &self,
+        url: &str,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+    ) -> Result<Option<Vec<u8>>> {
+        let url_previews = self.url_previews.read().unwrap();
+        Ok(url_previews.get(&(url.to_owned(), ts)).map(|(_, data)| data.clone()))
+    }
+
+    async fn remove_url_previews_added_before(
+        &self,
+        before: MilliSecondsSinceUnixEpoch,
+    ) -> Result<()> {
+        self.url_previews.write().unwrap().retain(|_, (added_at, _)| *added_at >= before);
+        Ok(())
+    }
+
+    async fn url_previews_size(&self) -> Result<usize> {
+        Ok(self.url_previews.read().unwrap().values().map(|(_, data)| data.len()).sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MediaCacheStore, MemoryStore, Result};
+
+    async fn get_media_cache_store() -> Result<impl MediaCacheStore> {
+        Ok(MemoryStore::new())
+    }
+
+    media_cache_store_integration_tests!();
+}
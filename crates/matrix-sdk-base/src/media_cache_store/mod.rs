@@ -0,0 +1,406 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The media cache store holds the media files downloaded by the client, so
+//! they don't need to be downloaded again.
+//!
+//! The size of the cache is kept in check by a [`MediaCachePolicy`], which
+//! limits the size of the cache and of the files in it, and expires the files
+//! that haven't been accessed for some time. Pinned files, like avatars, are
+//! only evicted if they are larger than the maximum file size.
+//!
+//! Implementing the `MediaCacheStore` trait, you can plug any storage backend
+//! into the media cache. By default this brings an in-memory store, and a
+//! store that keeps the files in a directory is available on non-wasm targets.
+
+use std::time::Duration;
+
+use matrix_sdk_store_encryption::Error as StoreEncryptionError;
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedMxcUri};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod filesystem_store;
+#[cfg(any(test, feature = "testing"))]
+#[macro_use]
+pub mod integration_tests;
+mod memory_store;
+mod traits;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::filesystem_store::FileSystemStore;
+#[cfg(any(test, feature = "testing"))]
+pub use self::integration_tests::MediaCacheStoreIntegrationTests;
+pub use self::{
+    memory_store::MemoryStore,
+    traits::{DynMediaCacheStore, IntoMediaCacheStore, MediaCacheStore},
+};
+
+/// The metadata of a media file in a [`MediaCacheStore`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaCacheEntry {
+    /// The unique key of the [`MediaRequest`] of the file.
+    ///
+    /// [`MediaRequest`]: crate::media::MediaRequest
+    pub key: String,
+
+    /// The URI of the file.
+    pub uri: OwnedMxcUri,
+
+    /// The size of the file, in bytes.
+    pub size: usize,
+
+    /// When the file was added to the cache.
+    pub added_at: MilliSecondsSinceUnixEpoch,
+
+    /// When the file was last read from the cache.
+    pub last_accessed_at: MilliSecondsSinceUnixEpoch,
+
+    /// Whether the file is pinned, i.e. is not evicted from the cache when it
+    /// expires or when the cache is full.
+    pub pinned: bool,
+}
+
+/// The order in which the media files are evicted when the cache is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MediaEvictionPolicy {
+    /// Evict the files that were accessed the longest time ago first.
+    #[default]
+    LeastRecentlyUsed,
+
+    /// Evict the files that were added the longest time ago first.
+    OldestFirst,
+
+    /// Evict the largest files first.
+    LargestFirst,
+}
+
+/// The rules used to decide which media files are kept in a
+/// [`MediaCacheStore`].
+///
+/// Pinned files never expire and are not evicted when the cache is full, but
+/// they count towards the size of the cache. They are still subject to
+/// [`MediaCachePolicy::max_file_size`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaCachePolicy {
+    /// The maximum size of the cache, in bytes.
+    ///
+    /// If this is `None`, the size of the cache is unlimited.
+    pub max_cache_size: Option<usize>,
+
+    /// The maximum size of a file in the cache, in bytes. Larger files are not
+    /// cached.
+    ///
+    /// If this is `None`, the size of the files is unlimited.
+    pub max_file_size: Option<usize>,
+
+    /// The time after which a file that hasn't been accessed is evicted.
+    ///
+    /// If this is `None`, the files never expire.
+    pub expiry: Option<Duration>,
+
+    /// The order in which the files are evicted when the cache is full.
+    pub eviction: MediaEvictionPolicy,
+}
+
+impl MediaCachePolicy {
+    /// The default value of [`MediaCachePolicy::max_cache_size`], 400 MiB.
+    pub const DEFAULT_MAX_CACHE_SIZE: usize = 400 * 1024 * 1024;
+
+    /// The default value of [`MediaCachePolicy::max_file_size`], 20 MiB.
+    pub const DEFAULT_MAX_FILE_SIZE: usize = 20 * 1024 * 1024;
+
+    /// The default value of [`MediaCachePolicy::expiry`], 60 days.
+    pub const DEFAULT_EXPIRY: Duration = Duration::from_secs(60 * 24 * 60 * 60);
+
+    /// Create a `MediaCachePolicy` with the default limits.
+    pub fn new() -> Self {
+        Self {
+            max_cache_size: Some(Self::DEFAULT_MAX_CACHE_SIZE),
+            max_file_size: Some(Self::DEFAULT_MAX_FILE_SIZE),
+            expiry: Some(Self::DEFAULT_EXPIRY),
+            eviction: MediaEvictionPolicy::default(),
+        }
+    }
+
+    /// Create a `MediaCachePolicy` without any limit.
+    pub fn unlimited() -> Self {
+        Self {
+            max_cache_size: None,
+            max_file_size: None,
+            expiry: None,
+            eviction: MediaEvictionPolicy::default(),
+        }
+    }
+
+    /// Whether a file of the given size can be added to the cache.
+    pub fn is_cacheable(&self, size: usize) -> bool {
+        self.max_file_size.map_or(true, |max| size <= max)
+            && self.max_cache_size.map_or(true, |max| size <= max)
+    }
+
+    /// Whether the given entry has expired at the given time.
+    ///
+    /// Pinned entries never expire.
+    pub fn is_expired(&self, entry: &MediaCacheEntry, now: MilliSecondsSinceUnixEpoch) -> bool {
+        let Some(expiry) = self.expiry else { return false };
+
+        let elapsed = u64::from(now.0).saturating_sub(entry.last_accessed_at.0.into());
+        !entry.pinned && u128::from(elapsed) > expiry.as_millis()
+    }
+
+    /// Select the entries that must be evicted from a cache containing the
+    /// given entries at the given time.
+    ///
+    /// `other_size` is the size of the rest of the content of the cache, like
+    /// the URL previews, that counts towards
+    /// [`MediaCachePolicy::max_cache_size`] but is not evicted with the
+    /// entries.
+    ///
+    /// Returns the keys of the entries to evict: first the expired entries and
+    /// the ones that are too large, then as many entries as needed to fit in
+    /// [`MediaCachePolicy::max_cache_size`], in the order of
+    /// [`MediaCachePolicy::eviction`]. Pinned entries are only evicted if they
+    /// are too large.
+    pub fn entries_to_evict(
+        &self,
+        entries: &[MediaCacheEntry],
+        other_size: usize,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Vec<String> {
+        let (evicted, mut kept): (Vec<_>, Vec<_>) = entries
+            .iter()
+            .partition(|entry| self.is_expired(entry, now) || !self.is_cacheable(entry.size));
+        let mut evicted = evicted.into_iter().map(|entry| entry.key.clone()).collect::<Vec<_>>();
+
+        let Some(max_cache_size) = self.max_cache_size else { return evicted };
+        let mut cache_size =
+            kept.iter().map(|entry| entry.size).fold(other_size, usize::saturating_add);
+        if cache_size <= max_cache_size {
+            return evicted;
+        }
+
+        kept.retain(|entry| !entry.pinned);
+        match self.eviction {
+            MediaEvictionPolicy::LeastRecentlyUsed => kept.sort_by_key(|e| e.last_accessed_at),
+            MediaEvictionPolicy::OldestFirst => kept.sort_by_key(|e| e.added_at),
+            MediaEvictionPolicy::LargestFirst => {
+                kept.sort_by(|a, b| b.size.cmp(&a.size));
+            }
+        }
+
+        for entry in kept {
+            if cache_size <= max_cache_size {
+                break;
+            }
+
+            cache_size -= entry.size;
+            evicted.push(entry.key.clone());
+        }
+
+        evicted
+    }
+}
+
+impl Default for MediaCachePolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Statistics about the content of a [`MediaCacheStore`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MediaCacheStats {
+    /// The number of files in the cache.
+    pub entries: usize,
+
+    /// The total size of the files in the cache, in bytes.
+    pub size: usize,
+
+    /// The number of pinned files in the cache.
+    pub pinned_entries: usize,
+
+    /// The total size of the pinned files in the cache, in bytes.
+    pub pinned_size: usize,
+}
+
+impl MediaCacheStats {
+    /// Compute the statistics of a cache containing the given entries.
+    pub fn from_entries(entries: &[MediaCacheEntry]) -> Self {
+        entries.iter().fold(Self::default(), |mut stats, entry| {
+            stats.entries += 1;
+            stats.size += entry.size;
+
+            if entry.pinned {
+                stats.pinned_entries += 1;
+                stats.pinned_size += entry.size;
+            }
+
+            stats
+        })
+    }
+}
+
+/// Media cache store specific error type.
+#[derive(Debug, thiserror::Error)]
+pub enum MediaCacheStoreError {
+    /// An error happened in the underlying storage backend.
+    #[error(transparent)]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+
+    /// An error happened while serializing or deserializing some data.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// The store failed to encrypt or decrypt some data.
+    #[error("Error encrypting or decrypting data from the media cache store: {0}")]
+    Encryption(#[from] StoreEncryptionError),
+}
+
+impl MediaCacheStoreError {
+    /// Create a new [`Backend`][Self::Backend] error.
+    ///
+    /// Shorthand for `MediaCacheStoreError::Backend(Box::new(error))`.
+    #[inline]
+    pub fn backend<E>(error: E) -> Self
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Self::Backend(Box::new(error))
+    }
+}
+
+/// A `MediaCacheStore` specific result type.
+pub type Result<T, E = MediaCacheStoreError> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ruma::{mxc_uri, uint, MilliSecondsSinceUnixEpoch};
+
+    use super::{MediaCacheEntry, MediaCachePolicy, MediaCacheStats, MediaEvictionPolicy};
+
+    fn entry(key: &str, size: usize, added_at: u32, last_accessed_at: u32) -> MediaCacheEntry {
+        MediaCacheEntry {
+            key: key.to_owned(),
+            uri: mxc_uri!("mxc://localhost/media").to_owned(),
+            size,
+            added_at: MilliSecondsSinceUnixEpoch(added_at.into()),
+            last_accessed_at: MilliSecondsSinceUnixEpoch(last_accessed_at.into()),
+            pinned: false,
+        }
+    }
+
+    fn pinned(entry: MediaCacheEntry) -> MediaCacheEntry {
+        MediaCacheEntry { pinned: true, ..entry }
+    }
+
+    #[test]
+    fn test_is_cacheable() {
+        let mut policy = MediaCachePolicy::unlimited();
+        assert!(policy.is_cacheable(usize::MAX));
+
+        policy.max_file_size = Some(10);
+        assert!(policy.is_cacheable(10));
+        assert!(!policy.is_cacheable(11));
+
+        policy.max_file_size = None;
+        policy.max_cache_size = Some(5);
+        assert!(policy.is_cacheable(5));
+        assert!(!policy.is_cacheable(6));
+    }
+
+    #[test]
+    fn test_expired_entries_are_evicted() {
+        let mut policy = MediaCachePolicy::unlimited();
+        let entries =
+            [entry("old", 1, 0, 0), entry("recent", 1, 0, 900), pinned(entry("p", 1, 0, 0))];
+        let now = MilliSecondsSinceUnixEpoch(uint!(1000));
+
+        // Without expiry, nothing is evicted.
+        assert!(policy.entries_to_evict(&entries, 0, now).is_empty());
+
+        policy.expiry = Some(Duration::from_millis(500));
+        assert_eq!(policy.entries_to_evict(&entries, 0, now), ["old"]);
+    }
+
+    #[test]
+    fn test_eviction_policies() {
+        let entries = [
+            entry("a", 4, 10, 30),
+            entry("b", 2, 20, 10),
+            entry("c", 3, 30, 20),
+            pinned(entry("p", 5, 0, 0)),
+        ];
+        let now = MilliSecondsSinceUnixEpoch(uint!(100));
+
+        let mut policy = MediaCachePolicy::unlimited();
+        policy.max_cache_size = Some(14);
+        assert!(policy.entries_to_evict(&entries, 0, now).is_empty());
+
+        policy.max_cache_size = Some(10);
+        policy.eviction = MediaEvictionPolicy::LeastRecentlyUsed;
+        assert_eq!(policy.entries_to_evict(&entries, 0, now), ["b", "c"]);
+
+        policy.eviction = MediaEvictionPolicy::OldestFirst;
+        assert_eq!(policy.entries_to_evict(&entries, 0, now), ["a"]);
+
+        policy.eviction = MediaEvictionPolicy::LargestFirst;
+        assert_eq!(policy.entries_to_evict(&entries, 0, now), ["a"]);
+
+        // Pinned entries are kept even if the cache is still too large.
+        policy.max_cache_size = Some(1);
+        assert_eq!(policy.entries_to_evict(&entries, 0, now), ["a", "c", "b"]);
+    }
+
+    #[test]
+    fn test_other_content_counts_towards_cache_size() {
+        let entries = [entry("a", 4, 10, 30), entry("b", 2, 20, 10), entry("c", 3, 30, 20)];
+        let now = MilliSecondsSinceUnixEpoch(uint!(100));
+
+        let mut policy = MediaCachePolicy::unlimited();
+        policy.max_cache_size = Some(10);
+        assert!(policy.entries_to_evict(&entries, 0, now).is_empty());
+
+        // The other content is not evicted, so more entries need to go.
+        assert_eq!(policy.entries_to_evict(&entries, 2, now), ["b"]);
+        assert_eq!(policy.entries_to_evict(&entries, 8, now), ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn test_too_large_entries_are_evicted() {
+        let entries = [
+            entry("small", 1, 0, 0),
+            entry("large", 10, 0, 0),
+            pinned(entry("pinned_small", 1, 0, 0)),
+            pinned(entry("pinned_large", 10, 0, 0)),
+        ];
+        let now = MilliSecondsSinceUnixEpoch(uint!(0));
+
+        let mut policy = MediaCachePolicy::unlimited();
+        policy.max_file_size = Some(5);
+        assert_eq!(policy.entries_to_evict(&entries, 0, now), ["large", "pinned_large"]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let entries = [entry("a", 4, 0, 0), entry("b", 2, 0, 0), pinned(entry("p", 5, 0, 0))];
+
+        assert_eq!(
+            MediaCacheStats::from_entries(&entries),
+            MediaCacheStats { entries: 3, size: 11, pinned_entries: 1, pinned_size: 5 }
+        );
+    }
+}
@@ -0,0 +1,274 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fmt, sync::Arc};
+
+use async_trait::async_trait;
+use matrix_sdk_common::AsyncTraitDeps;
+use ruma::{MilliSecondsSinceUnixEpoch, MxcUri};
+
+use super::{MediaCacheEntry, MediaCacheStoreError};
+use crate::media::MediaRequest;
+
+/// An abstract trait that can be used to implement different stores for the
+/// media cache.
+///
+/// The store doesn't enforce any [`MediaCachePolicy`] by itself, it only
+/// provides the metadata of its entries so the policy can be applied by the
+/// caller.
+///
+/// [`MediaCachePolicy`]: super::MediaCachePolicy
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait MediaCacheStore: AsyncTraitDeps {
+    /// The error type used by this media cache store.
+    type Error: fmt::Debug + Into<MediaCacheStoreError>;
+
+    /// Add a media file's content in the media cache.
+    ///
+    /// If the file is already in the cache, its content is replaced. It stays
+    /// pinned if it was pinned before.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `MediaRequest` of the file.
+    ///
+    /// * `content` - The content of the file.
+    ///
+    /// * `pinned` - Whether the file should be kept when the cache is full or
+    ///   when it expires.
+    ///
+    /// * `now` - The current time.
+    async fn add_media_content(
+        &self,
+        request: &MediaRequest,
+        content: Vec<u8>,
+        pinned: bool,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<(), Self::Error>;
+
+    /// Get a media file's content out of the media cache, and update the time
+    /// it was last accessed.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `MediaRequest` of the file.
+    ///
+    /// * `now` - The current time.
+    async fn get_media_content(
+        &self,
+        request: &MediaRequest,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<Option<Vec<u8>>, Self::Error>;
+
+    /// Set whether a media file in the media cache is pinned.
+    ///
+    /// Does nothing if the file is not in the cache.
+    async fn set_media_pinned(
+        &self,
+        request: &MediaRequest,
+        pinned: bool,
+    ) -> Result<(), Self::Error>;
+
+    /// Remove a media file's content from the media cache.
+    async fn remove_media_content(&self, request: &MediaRequest) -> Result<(), Self::Error>;
+
+    /// Remove all the media files' content associated to an `MxcUri` from the
+    /// media cache.
+    async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<(), Self::Error>;
+
+    /// Get the metadata of all the media files in the media cache.
+    async fn media_entries(&self) -> Result<Vec<MediaCacheEntry>, Self::Error>;
+
+    /// Remove the media files with the given keys from the media cache.
+    ///
+    /// The keys are the ones of the [`MediaCacheEntry`]s.
+    async fn remove_media_entries(&self, keys: &[String]) -> Result<(), Self::Error>;
+
+    /// Remove all the media files from the media cache, including the pinned
+    /// ones, and all the URL previews.
+    async fn clear_media_cache(&self) -> Result<(), Self::Error>;
+
+    /// Add the data of a URL preview in the media cache.
+    ///
+    /// If the preview is already in the cache, its data is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL that was previewed.
+    ///
+    /// * `ts` - The timestamp at which the preview was requested, if any.
+    ///
+    /// * `data` - The data of the preview.
+    ///
+    /// * `now` - The current time.
+    async fn add_url_preview(
+        &self,
+        url: &str,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+        data: Vec<u8>,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<(), Self::Error>;
+
+    /// Get the data of a URL preview out of the media cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL that was previewed.
+    ///
+    /// * `ts` - The timestamp at which the preview was requested, if any.
+    async fn get_url_preview(
+        &self,
+        url: &str,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+    ) -> Result<Option<Vec<u8>>, Self::Error>;
+
+    /// Remove the URL previews that were added to the media cache before the
+    /// given time.
+    async fn remove_url_previews_added_before(
+        &self,
+        before: MilliSecondsSinceUnixEpoch,
+    ) -> Result<(), Self::Error>;
+
+    /// Get the total size of the URL previews in the media cache, in bytes.
+    async fn url_previews_size(&self) -> Result<usize, Self::Error>;
+}
+
+#[repr(transparent)]
+struct EraseMediaCacheStoreError<T>(T);
+
+#[cfg(not(tarpaulin_include))]
+impl<T: fmt::Debug> fmt::Debug for EraseMediaCacheStoreError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<T: MediaCacheStore> MediaCacheStore for EraseMediaCacheStoreError<T> {
+    type Error = MediaCacheStoreError;
+
+    async fn add_media_content(
+        &self,
+        request: &MediaRequest,
+        content: Vec<u8>,
+        pinned: bool,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<(), Self::Error> {
+        self.0.add_media_content(request, content, pinned, now).await.map_err(Into::into)
+    }
+
+    async fn get_media_content(
+        &self,
+        request: &MediaRequest,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.0.get_media_content(request, now).await.map_err(Into::into)
+    }
+
+    async fn set_media_pinned(
+        &self,
+        request: &MediaRequest,
+        pinned: bool,
+    ) -> Result<(), Self::Error> {
+        self.0.set_media_pinned(request, pinned).await.map_err(Into::into)
+    }
+
+    async fn remove_media_content(&self, request: &MediaRequest) -> Result<(), Self::Error> {
+        self.0.remove_media_content(request).await.map_err(Into::into)
+    }
+
+    async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<(), Self::Error> {
+        self.0.remove_media_content_for_uri(uri).await.map_err(Into::into)
+    }
+
+    async fn media_entries(&self) -> Result<Vec<MediaCacheEntry>, Self::Error> {
+        self.0.media_entries().await.map_err(Into::into)
+    }
+
+    async fn remove_media_entries(&self, keys: &[String]) -> Result<(), Self::Error> {
+        self.0.remove_media_entries(keys).await.map_err(Into::into)
+    }
+
+    async fn clear_media_cache(&self) -> Result<(), Self::Error> {
+        self.0.clear_media_cache().await.map_err(Into::into)
+    }
+
+    async fn add_url_preview(
+        &self,
+        url: &str,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+        data: Vec<u8>,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<(), Self::Error> {
+        self.0.add_url_preview(url, ts, data, now).await.map_err(Into::into)
+    }
+
+    async fn get_url_preview(
+        &self,
+        url: &str,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+    ) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.0.get_url_preview(url, ts).await.map_err(Into::into)
+    }
+
+    async fn remove_url_previews_added_before(
+        &self,
+        before: MilliSecondsSinceUnixEpoch,
+    ) -> Result<(), Self::Error> {
+        self.0.remove_url_previews_added_before(before).await.map_err(Into::into)
+    }
+
+    async fn url_previews_size(&self) -> Result<usize, Self::Error> {
+        self.0.url_previews_size().await.map_err(Into::into)
+    }
+}
+
+/// A type-erased [`MediaCacheStore`].
+pub type DynMediaCacheStore = dyn MediaCacheStore<Error = MediaCacheStoreError>;
+
+/// A type that can be type-erased into `Arc<dyn MediaCacheStore>`.
+///
+/// This trait is not meant to be implemented directly outside
+/// `matrix-sdk-base`, but it is automatically implemented for everything that
+/// implements `MediaCacheStore`.
+pub trait IntoMediaCacheStore {
+    #[doc(hidden)]
+    fn into_media_cache_store(self) -> Arc<DynMediaCacheStore>;
+}
+
+impl<T> IntoMediaCacheStore for T
+where
+    T: MediaCacheStore + Sized + 'static,
+{
+    fn into_media_cache_store(self) -> Arc<DynMediaCacheStore> {
+        Arc::new(EraseMediaCacheStoreError(self))
+    }
+}
+
+// Turns a given `Arc<T>` into `Arc<DynMediaCacheStore>` by attaching the
+// MediaCacheStore impl vtable of `EraseMediaCacheStoreError<T>`.
+impl<T> IntoMediaCacheStore for Arc<T>
+where
+    T: MediaCacheStore + 'static,
+{
+    fn into_media_cache_store(self) -> Arc<DynMediaCacheStore> {
+        let ptr: *const T = Arc::into_raw(self);
+        let ptr_erased = ptr as *const EraseMediaCacheStoreError<T>;
+        // SAFETY: EraseMediaCacheStoreError is repr(transparent) so T and
+        //         EraseMediaCacheStoreError<T> have the same layout and ABI
+        unsafe { Arc::from_raw(ptr_erased) }
+    }
+}
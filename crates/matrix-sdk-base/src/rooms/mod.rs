@@ -11,7 +11,9 @@ use std::{
 
 use bitflags::bitflags;
 pub use members::RoomMember;
-pub use normal::{Room, RoomInfo, RoomInfoUpdate, RoomState, RoomStateFilter};
+pub use normal::{
+    PredecessorRoom, Room, RoomInfo, RoomInfoUpdate, RoomState, RoomStateFilter, SuccessorRoom,
+};
 use ruma::{
     assign,
     events::{
@@ -85,6 +85,24 @@ pub struct RoomInfoUpdate {
     pub trigger_room_list_update: bool,
 }
 
+/// The room that a room replaces, as advertised in its `m.room.create` event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PredecessorRoom {
+    /// The ID of the replaced room.
+    pub room_id: OwnedRoomId,
+}
+
+/// The room that replaces a room, as advertised in its `m.room.tombstone`
+/// event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuccessorRoom {
+    /// The ID of the replacement room.
+    pub room_id: OwnedRoomId,
+
+    /// The reason why the room was replaced, if any.
+    pub reason: Option<String>,
+}
+
 /// The underlying room data structure collecting state for joined, left and
 /// invited rooms.
 #[derive(Debug, Clone)]
@@ -122,7 +140,7 @@ pub struct RoomSummary {
 }
 
 /// Enum keeping track in which state the room is, e.g. if our own user is
-/// joined, invited, or has left the room.
+/// joined, invited, has knocked on, or has left the room.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum RoomState {
     /// The room is in a joined state.
@@ -131,17 +149,19 @@ pub enum RoomState {
     Left,
     /// The room is in a invited state.
     Invited,
+    /// The room is in a knocked state.
+    Knocked,
 }
 
 impl From<&MembershipState> for RoomState {
     fn from(membership_state: &MembershipState) -> Self {
-        // We consider Ban, Knock and Leave to be Left, because they all mean we are not
-        // in the room.
+        // We consider Ban and Leave to be Left, because they both mean we are not in
+        // the room.
         match membership_state {
             MembershipState::Ban => Self::Left,
             MembershipState::Invite => Self::Invited,
             MembershipState::Join => Self::Joined,
-            MembershipState::Knock => Self::Left,
+            MembershipState::Knock => Self::Knocked,
             MembershipState::Leave => Self::Left,
             _ => panic!("Unexpected MembershipState: {}", membership_state),
         }
@@ -325,7 +345,7 @@ impl Room {
     #[instrument(skip_all, fields(room_id = ?self.room_id))]
     pub async fn is_direct(&self) -> StoreResult<bool> {
         match self.state() {
-            RoomState::Joined | RoomState::Left => {
+            RoomState::Joined | RoomState::Left | RoomState::Knocked => {
                 Ok(!self.inner.read().base_info.dm_targets.is_empty())
             }
             RoomState::Invited => {
@@ -424,6 +444,20 @@ impl Room {
         self.inner.read().tombstone().cloned()
     }
 
+    /// Get the room that this room replaces, if it was created by upgrading
+    /// another room.
+    pub fn predecessor_room(&self) -> Option<PredecessorRoom> {
+        let predecessor = self.create_content()?.predecessor?;
+        Some(PredecessorRoom { room_id: predecessor.room_id })
+    }
+
+    /// Get the room that replaces this room, if it has been tombstoned.
+    pub fn successor_room(&self) -> Option<SuccessorRoom> {
+        let tombstone = self.tombstone()?;
+        let reason = Some(tombstone.body).filter(|body| !body.is_empty());
+        Some(SuccessorRoom { room_id: tombstone.replacement_room, reason })
+    }
+
     /// Get the topic of the room.
     pub fn topic(&self) -> Option<String> {
         self.inner.read().topic().map(ToOwned::to_owned)
@@ -901,6 +935,11 @@ impl RoomInfo {
         self.room_state = RoomState::Invited;
     }
 
+    /// Mark this Room as knocked.
+    pub fn mark_as_knocked(&mut self) {
+        self.room_state = RoomState::Knocked;
+    }
+
     /// Set the membership RoomState of this Room
     pub fn set_state(&mut self, room_state: RoomState) {
         self.room_state = room_state;
@@ -1261,6 +1300,8 @@ bitflags! {
         const INVITED  = 0b00000010;
         /// The room is in a left state.
         const LEFT     = 0b00000100;
+        /// The room is in a knocked state.
+        const KNOCKED  = 0b00001000;
     }
 }
 
@@ -1275,6 +1316,7 @@ impl RoomStateFilter {
             RoomState::Joined => Self::JOINED,
             RoomState::Left => Self::LEFT,
             RoomState::Invited => Self::INVITED,
+            RoomState::Knocked => Self::KNOCKED,
         };
 
         self.contains(bit_state)
@@ -1293,6 +1335,9 @@ impl RoomStateFilter {
         if self.contains(Self::INVITED) {
             states.push(RoomState::Invited);
         }
+        if self.contains(Self::KNOCKED) {
+            states.push(RoomState::Knocked);
+        }
 
         states
     }
@@ -237,7 +237,7 @@ impl BaseClient {
                         .or_insert_with(LeftRoomUpdate::default)
                         .account_data
                         .append(&mut raw.to_vec()),
-                    RoomState::Invited => {}
+                    RoomState::Invited | RoomState::Knocked => {}
                 }
             }
         }
@@ -444,6 +444,10 @@ impl BaseClient {
             )),
 
             RoomState::Invited => Ok((room_info, None, None, invited_room)),
+
+            // Sliding sync doesn't return the knock state, the room is only known from the
+            // membership of the current user.
+            RoomState::Knocked => Ok((room_info, None, None, None)),
         }
     }
 
@@ -7,7 +7,6 @@ use assert_matches2::assert_let;
 use async_trait::async_trait;
 use matrix_sdk_test::test_json;
 use ruma::{
-    api::client::media::get_content_thumbnail::v3::Method,
     event_id,
     events::{
         presence::PresenceEvent,
@@ -19,13 +18,12 @@ use ruma::{
             },
             power_levels::RoomPowerLevelsEventContent,
             topic::RoomTopicEventContent,
-            MediaSource,
         },
         AnyEphemeralRoomEventContent, AnyGlobalAccountDataEvent, AnyRoomAccountDataEvent,
         AnyStrippedStateEvent, AnySyncEphemeralRoomEvent, AnySyncStateEvent,
         GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType, SyncStateEvent,
     },
-    mxc_uri, room_id,
+    room_id,
     serde::Raw,
     uint, user_id, EventId, OwnedEventId, OwnedUserId, RoomId, UserId,
 };
@@ -34,8 +32,7 @@ use serde_json::{json, value::Value as JsonValue};
 use super::DynStateStore;
 use crate::{
     deserialized_responses::MemberEvent,
-    media::{MediaFormat, MediaRequest, MediaThumbnailSize},
-    store::{Result, StateStoreExt},
+    store::{QueuedRequest, QueuedRequestKind, Result, StateStoreExt},
     RoomInfo, RoomMemberships, RoomState, StateChanges, StateStoreDataKey, StateStoreDataValue,
 };
 
@@ -48,8 +45,6 @@ use crate::{
 pub trait StateStoreIntegrationTests {
     /// Populate the given `StateStore`.
     async fn populate(&self) -> Result<()>;
-    /// Test media content storage.
-    async fn test_media_content(&self);
     /// Test room topic redaction.
     async fn test_topic_redaction(&self) -> Result<()>;
     /// Test populating the store.
@@ -72,12 +67,16 @@ pub trait StateStoreIntegrationTests {
     async fn test_persist_invited_room(&self) -> Result<()>;
     /// Test stripped and non-stripped room member saving.
     async fn test_stripped_non_stripped(&self) -> Result<()>;
+    /// Test that the stripped state of a knocked room is kept.
+    async fn test_persist_knocked_room(&self) -> Result<()>;
     /// Test room removal.
     async fn test_room_removal(&self) -> Result<()>;
     /// Test presence saving.
     async fn test_presence_saving(&self);
     /// Test display names saving.
     async fn test_display_names_saving(&self);
+    /// Test send queue requests saving.
+    async fn test_send_queue(&self) -> Result<()>;
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
@@ -198,110 +197,6 @@ impl StateStoreIntegrationTests for DynStateStore {
         Ok(())
     }
 
-    async fn test_media_content(&self) {
-        let uri = mxc_uri!("mxc://localhost/media");
-        let request_file =
-            MediaRequest { source: MediaSource::Plain(uri.to_owned()), format: MediaFormat::File };
-        let request_thumbnail = MediaRequest {
-            source: MediaSource::Plain(uri.to_owned()),
-            format: MediaFormat::Thumbnail(MediaThumbnailSize {
-                method: Method::Crop,
-                width: uint!(100),
-                height: uint!(100),
-            }),
-        };
-
-        let other_uri = mxc_uri!("mxc://localhost/media-other");
-        let request_other_file = MediaRequest {
-            source: MediaSource::Plain(other_uri.to_owned()),
-            format: MediaFormat::File,
-        };
-
-        let content: Vec<u8> = "hello".into();
-        let thumbnail_content: Vec<u8> = "world".into();
-        let other_content: Vec<u8> = "foo".into();
-
-        // Media isn't present in the cache.
-        assert!(
-            self.get_media_content(&request_file).await.unwrap().is_none(),
-            "unexpected media found"
-        );
-        assert!(
-            self.get_media_content(&request_thumbnail).await.unwrap().is_none(),
-            "media not found"
-        );
-
-        // Let's add the media.
-        self.add_media_content(&request_file, content.clone()).await.expect("adding media failed");
-
-        // Media is present in the cache.
-        assert_eq!(
-            self.get_media_content(&request_file).await.unwrap().as_ref(),
-            Some(&content),
-            "media not found though added"
-        );
-
-        // Let's remove the media.
-        self.remove_media_content(&request_file).await.expect("removing media failed");
-
-        // Media isn't present in the cache.
-        assert!(
-            self.get_media_content(&request_file).await.unwrap().is_none(),
-            "media still there after removing"
-        );
-
-        // Let's add the media again.
-        self.add_media_content(&request_file, content.clone())
-            .await
-            .expect("adding media again failed");
-
-        assert_eq!(
-            self.get_media_content(&request_file).await.unwrap().as_ref(),
-            Some(&content),
-            "media not found after adding again"
-        );
-
-        // Let's add the thumbnail media.
-        self.add_media_content(&request_thumbnail, thumbnail_content.clone())
-            .await
-            .expect("adding thumbnail failed");
-
-        // Media's thumbnail is present.
-        assert_eq!(
-            self.get_media_content(&request_thumbnail).await.unwrap().as_ref(),
-            Some(&thumbnail_content),
-            "thumbnail not found"
-        );
-
-        // Let's add another media with a different URI.
-        self.add_media_content(&request_other_file, other_content.clone())
-            .await
-            .expect("adding other media failed");
-
-        // Other file is present.
-        assert_eq!(
-            self.get_media_content(&request_other_file).await.unwrap().as_ref(),
-            Some(&other_content),
-            "other file not found"
-        );
-
-        // Let's remove media based on URI.
-        self.remove_media_content_for_uri(uri).await.expect("removing all media for uri failed");
-
-        assert!(
-            self.get_media_content(&request_file).await.unwrap().is_none(),
-            "media wasn't removed"
-        );
-        assert!(
-            self.get_media_content(&request_thumbnail).await.unwrap().is_none(),
-            "thumbnail wasn't removed"
-        );
-        assert!(
-            self.get_media_content(&request_other_file).await.unwrap().is_some(),
-            "other media was removed"
-        );
-    }
-
     async fn test_topic_redaction(&self) -> Result<()> {
         let room_id = room_id();
         self.populate().await?;
@@ -987,6 +882,32 @@ impl StateStoreIntegrationTests for DynStateStore {
         Ok(())
     }
 
+    async fn test_persist_knocked_room(&self) -> Result<()> {
+        let room_id = room_id!("!test_persist_knocked_room:localhost");
+        let user_id = user_id();
+
+        let mut changes = StateChanges::default();
+        changes.add_stripped_member(room_id, user_id, custom_stripped_membership_event(user_id));
+        changes.add_room(RoomInfo::new(room_id, RoomState::Knocked));
+        self.save_changes(&changes).await?;
+
+        // Saving the room info again, without any state, doesn't remove the stripped
+        // state.
+        let mut changes = StateChanges::default();
+        changes.add_room(RoomInfo::new(room_id, RoomState::Knocked));
+        self.save_changes(&changes).await?;
+
+        let member_event = self.get_member_event(room_id, user_id).await?.unwrap().deserialize()?;
+        assert!(matches!(member_event, MemberEvent::Stripped(_)));
+
+        #[allow(deprecated)]
+        let stripped_rooms = self.get_stripped_room_infos().await?;
+        assert_eq!(stripped_rooms.len(), 1);
+        assert_eq!(stripped_rooms[0].room_id(), room_id);
+
+        Ok(())
+    }
+
     async fn test_room_removal(&self) -> Result<()> {
         let room_id = room_id();
         let user_id = user_id();
@@ -1163,6 +1084,74 @@ impl StateStoreIntegrationTests for DynStateStore {
         let names = self.get_users_with_display_names(room_id, &[]).await;
         assert!(names.unwrap().is_empty());
     }
+
+    async fn test_send_queue(&self) -> Result<()> {
+        let room_id = room_id!("!test_send_queue:localhost");
+        let other_room_id = room_id!("!test_send_queue_other:localhost");
+
+        let request = |transaction_id: &str, body: &str| QueuedRequest {
+            transaction_id: transaction_id.into(),
+            kind: QueuedRequestKind::Event {
+                event_type: "m.room.message".to_owned(),
+                content: Raw::new(&json!({ "msgtype": "m.text", "body": body })).unwrap().cast(),
+            },
+            is_wedged: false,
+        };
+        let transaction_ids = |requests: Vec<QueuedRequest>| {
+            requests.into_iter().map(|req| req.transaction_id.to_string()).collect::<Vec<_>>()
+        };
+
+        // Empty queue.
+        assert!(self.load_send_queue_requests(room_id).await?.is_empty());
+        assert!(self.load_rooms_with_unsent_requests().await?.is_empty());
+
+        // Requests are loaded in the order they were saved.
+        self.save_send_queue_request(room_id, request("txn1", "first")).await?;
+        self.save_send_queue_request(room_id, request("txn2", "second")).await?;
+        self.save_send_queue_request(room_id, request("txn3", "third")).await?;
+        self.save_send_queue_request(other_room_id, request("txn4", "other")).await?;
+
+        assert_eq!(
+            transaction_ids(self.load_send_queue_requests(room_id).await?),
+            ["txn1", "txn2", "txn3"]
+        );
+        assert_eq!(transaction_ids(self.load_send_queue_requests(other_room_id).await?), ["txn4"]);
+
+        let mut rooms = self.load_rooms_with_unsent_requests().await?;
+        rooms.sort();
+        assert_eq!(rooms, [room_id.to_owned(), other_room_id.to_owned()]);
+
+        // Saving an existing request replaces it in place.
+        let mut wedged = request("txn2", "second");
+        wedged.is_wedged = true;
+        self.save_send_queue_request(room_id, wedged).await?;
+
+        let requests = self.load_send_queue_requests(room_id).await?;
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[1].transaction_id, "txn2");
+        assert!(requests[1].is_wedged);
+        assert!(!requests[0].is_wedged);
+        assert!(!requests[2].is_wedged);
+
+        // Removing and saving again moves the request to the end of the queue.
+        assert!(self.remove_send_queue_request(room_id, "txn1".into()).await?);
+        self.save_send_queue_request(room_id, request("txn1", "first")).await?;
+        assert_eq!(
+            transaction_ids(self.load_send_queue_requests(room_id).await?),
+            ["txn2", "txn3", "txn1"]
+        );
+
+        // Removing an unknown request does nothing.
+        assert!(!self.remove_send_queue_request(room_id, "unknown".into()).await?);
+        assert!(!self.remove_send_queue_request(other_room_id, "txn1".into()).await?);
+
+        // A room without requests isn't listed anymore.
+        assert!(self.remove_send_queue_request(other_room_id, "txn4".into()).await?);
+        assert!(self.load_send_queue_requests(other_room_id).await?.is_empty());
+        assert_eq!(self.load_rooms_with_unsent_requests().await?, [room_id.to_owned()]);
+
+        Ok(())
+    }
 }
 
 /// Macro building to allow your StateStore implementation to run the entire
@@ -1193,17 +1182,6 @@ impl StateStoreIntegrationTests for DynStateStore {
 #[allow(unused_macros, unused_extern_crates)]
 #[macro_export]
 macro_rules! statestore_integration_tests {
-    (with_media_tests) => {
-        mod statestore_integration_tests {
-            $crate::statestore_integration_tests!(@inner);
-
-            #[async_test]
-            async fn test_media_content() {
-                let store = get_store().await.unwrap().into_state_store();
-                store.test_media_content().await;
-            }
-        }
-    };
     () => {
         mod statestore_integration_tests {
             $crate::statestore_integration_tests!(@inner);
@@ -1282,6 +1260,12 @@ macro_rules! statestore_integration_tests {
             store.test_stripped_non_stripped().await
         }
 
+        #[async_test]
+        async fn test_persist_knocked_room() -> StoreResult<()> {
+            let store = get_store().await?.into_state_store();
+            store.test_persist_knocked_room().await
+        }
+
         #[async_test]
         async fn test_room_removal() -> StoreResult<()> {
             let store = get_store().await?.into_state_store();
@@ -1299,6 +1283,12 @@ macro_rules! statestore_integration_tests {
             let store = get_store().await.expect("creating store failed").into_state_store();
             store.test_display_names_saving().await;
         }
+
+        #[async_test]
+        async fn test_send_queue() -> StoreResult<()> {
+            let store = get_store().await?.into_state_store();
+            store.test_send_queue().await
+        }
     };
 }
 
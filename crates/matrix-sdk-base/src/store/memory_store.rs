@@ -14,12 +14,11 @@
 
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
-    num::NonZeroUsize,
     sync::RwLock as StdRwLock,
 };
 
 use async_trait::async_trait;
-use matrix_sdk_common::{instant::Instant, ring_buffer::RingBuffer};
+use matrix_sdk_common::instant::Instant;
 use ruma::{
     canonical_json::{redact, RedactedBecause},
     events::{
@@ -30,23 +29,22 @@ use ruma::{
         AnySyncStateEvent, GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType,
     },
     serde::Raw,
-    CanonicalJsonObject, EventId, MxcUri, OwnedEventId, OwnedMxcUri, OwnedRoomId, OwnedUserId,
-    RoomId, RoomVersionId, UserId,
+    CanonicalJsonObject, EventId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId,
+    TransactionId, UserId,
 };
 use tracing::{debug, warn};
 
-use super::{Result, RoomInfo, StateChanges, StateStore, StoreError};
+use super::{QueuedRequest, Result, RoomInfo, StateChanges, StateStore, StoreError};
 use crate::{
-    deserialized_responses::RawAnySyncOrStrippedState,
-    media::{MediaRequest, UniqueKey as _},
-    MinimalRoomMemberEvent, RoomMemberships, RoomState, StateStoreDataKey, StateStoreDataValue,
+    deserialized_responses::RawAnySyncOrStrippedState, MinimalRoomMemberEvent, RoomMemberships,
+    RoomState, StateStoreDataKey, StateStoreDataValue,
 };
 
 /// In-Memory, non-persistent implementation of the `StateStore`
 ///
 /// Default if no other is configured at startup.
 #[allow(clippy::type_complexity)]
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct MemoryStore {
     user_avatar_url: StdRwLock<HashMap<String, String>>,
     sync_token: StdRwLock<Option<String>>,
@@ -79,35 +77,8 @@ pub struct MemoryStore {
             HashMap<(String, Option<String>), HashMap<OwnedEventId, HashMap<OwnedUserId, Receipt>>>,
         >,
     >,
-    media: StdRwLock<RingBuffer<(OwnedMxcUri, String /* unique key */, Vec<u8>)>>,
     custom: StdRwLock<HashMap<Vec<u8>, Vec<u8>>>,
-}
-
-// SAFETY: `new_unchecked` is safe because 20 is not zero.
-const NUMBER_OF_MEDIAS: NonZeroUsize = unsafe { NonZeroUsize::new_unchecked(20) };
-
-impl Default for MemoryStore {
-    fn default() -> Self {
-        Self {
-            user_avatar_url: Default::default(),
-            sync_token: Default::default(),
-            filters: Default::default(),
-            account_data: Default::default(),
-            profiles: Default::default(),
-            display_names: Default::default(),
-            members: Default::default(),
-            room_info: Default::default(),
-            room_state: Default::default(),
-            room_account_data: Default::default(),
-            stripped_room_state: Default::default(),
-            stripped_members: Default::default(),
-            presence: Default::default(),
-            room_user_receipts: Default::default(),
-            room_event_receipts: Default::default(),
-            media: StdRwLock::new(RingBuffer::new(NUMBER_OF_MEDIAS)),
-            custom: Default::default(),
-        }
-    }
+    send_queue_requests: StdRwLock<BTreeMap<OwnedRoomId, Vec<QueuedRequest>>>,
 }
 
 impl MemoryStore {
@@ -636,7 +607,7 @@ impl StateStore for MemoryStore {
             .read()
             .unwrap()
             .values()
-            .filter(|r| matches!(r.state(), RoomState::Invited))
+            .filter(|r| matches!(r.state(), RoomState::Invited | RoomState::Knocked))
             .cloned()
             .collect())
     }
@@ -730,71 +701,69 @@ impl StateStore for MemoryStore {
         Ok(self.custom.write().unwrap().remove(key))
     }
 
-    async fn add_media_content(&self, request: &MediaRequest, data: Vec<u8>) -> Result<()> {
-        // Avoid duplication. Let's try to remove it first.
-        self.remove_media_content(request).await?;
-        // Now, let's add it.
-        self.media.write().unwrap().push((request.uri().to_owned(), request.unique_key(), data));
+    async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
+        self.profiles.write().unwrap().remove(room_id);
+        self.display_names.write().unwrap().remove(room_id);
+        self.members.write().unwrap().remove(room_id);
+        self.room_info.write().unwrap().remove(room_id);
+        self.room_state.write().unwrap().remove(room_id);
+        self.room_account_data.write().unwrap().remove(room_id);
+        self.stripped_room_state.write().unwrap().remove(room_id);
+        self.stripped_members.write().unwrap().remove(room_id);
+        self.room_user_receipts.write().unwrap().remove(room_id);
+        self.room_event_receipts.write().unwrap().remove(room_id);
 
         Ok(())
     }
 
-    async fn get_media_content(&self, request: &MediaRequest) -> Result<Option<Vec<u8>>> {
-        let media = self.media.read().unwrap();
-        let expected_key = request.unique_key();
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<()> {
+        let mut send_queue_requests = self.send_queue_requests.write().unwrap();
+        let requests = send_queue_requests.entry(room_id.to_owned()).or_default();
 
-        Ok(media.iter().find_map(|(_media_uri, media_key, media_content)| {
-            (media_key == &expected_key).then(|| media_content.to_owned())
-        }))
+        if let Some(existing) =
+            requests.iter_mut().find(|req| req.transaction_id == request.transaction_id)
+        {
+            *existing = request;
+        } else {
+            requests.push(request);
+        }
+
+        Ok(())
     }
 
-    async fn remove_media_content(&self, request: &MediaRequest) -> Result<()> {
-        let mut media = self.media.write().unwrap();
-        let expected_key = request.unique_key();
-        let Some(index) = media
-            .iter()
-            .position(|(_media_uri, media_key, _media_content)| media_key == &expected_key)
-        else {
-            return Ok(());
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool> {
+        let mut send_queue_requests = self.send_queue_requests.write().unwrap();
+        let Some(requests) = send_queue_requests.get_mut(room_id) else {
+            return Ok(false);
         };
 
-        media.remove(index);
-
-        Ok(())
-    }
+        let Some(index) = requests.iter().position(|req| req.transaction_id == transaction_id)
+        else {
+            return Ok(false);
+        };
+        requests.remove(index);
 
-    async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<()> {
-        let mut media = self.media.write().unwrap();
-        let expected_key = uri.to_owned();
-        let positions = media
-            .iter()
-            .enumerate()
-            .filter_map(|(position, (media_uri, _media_key, _media_content))| {
-                (media_uri == &expected_key).then_some(position)
-            })
-            .collect::<Vec<_>>();
-
-        // Iterate in reverse-order so that positions stay valid after first removals.
-        for position in positions.into_iter().rev() {
-            media.remove(position);
+        if requests.is_empty() {
+            send_queue_requests.remove(room_id);
         }
 
-        Ok(())
+        Ok(true)
     }
 
-    async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
-        self.profiles.write().unwrap().remove(room_id);
-        self.display_names.write().unwrap().remove(room_id);
-        self.members.write().unwrap().remove(room_id);
-        self.room_info.write().unwrap().remove(room_id);
-        self.room_state.write().unwrap().remove(room_id);
-        self.room_account_data.write().unwrap().remove(room_id);
-        self.stripped_room_state.write().unwrap().remove(room_id);
-        self.stripped_members.write().unwrap().remove(room_id);
-        self.room_user_receipts.write().unwrap().remove(room_id);
-        self.room_event_receipts.write().unwrap().remove(room_id);
+    async fn load_send_queue_requests(&self, room_id: &RoomId) -> Result<Vec<QueuedRequest>> {
+        Ok(self.send_queue_requests.read().unwrap().get(room_id).cloned().unwrap_or_default())
+    }
 
-        Ok(())
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>> {
+        Ok(self.send_queue_requests.read().unwrap().keys().cloned().collect())
     }
 }
 
@@ -806,5 +775,5 @@ mod tests {
         Ok(MemoryStore::new())
     }
 
-    statestore_integration_tests!(with_media_tests);
+    statestore_integration_tests!();
 }
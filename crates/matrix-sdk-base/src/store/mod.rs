@@ -57,6 +57,8 @@ use tokio::sync::{broadcast, Mutex, RwLock};
 pub type BoxStream<T> = Pin<Box<dyn futures_util::Stream<Item = T> + Send>>;
 
 use crate::{
+    event_cache_store::{self, DynEventCacheStore, IntoEventCacheStore},
+    media_cache_store::{self, DynMediaCacheStore, IntoMediaCacheStore},
     rooms::{normal::RoomInfoUpdate, RoomInfo, RoomState},
     MinimalRoomMemberEvent, Room, RoomStateFilter, SessionMeta,
 };
@@ -64,11 +66,13 @@ use crate::{
 pub(crate) mod ambiguity_map;
 mod memory_store;
 pub mod migration_helpers;
+mod send_queue;
 
 #[cfg(any(test, feature = "testing"))]
 pub use self::integration_tests::StateStoreIntegrationTests;
 pub use self::{
     memory_store::MemoryStore,
+    send_queue::{QueuedRequest, QueuedRequestKind},
     traits::{
         DynStateStore, IntoStateStore, StateStore, StateStoreDataKey, StateStoreDataValue,
         StateStoreExt,
@@ -411,6 +415,8 @@ pub struct StoreConfig {
     #[cfg(feature = "e2e-encryption")]
     pub(crate) crypto_store: Arc<DynCryptoStore>,
     pub(crate) state_store: Arc<DynStateStore>,
+    pub(crate) event_cache_store: Arc<DynEventCacheStore>,
+    pub(crate) media_cache_store: Arc<DynMediaCacheStore>,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -428,6 +434,8 @@ impl StoreConfig {
             #[cfg(feature = "e2e-encryption")]
             crypto_store: matrix_sdk_crypto::store::MemoryStore::new().into_crypto_store(),
             state_store: Arc::new(MemoryStore::new()),
+            event_cache_store: event_cache_store::MemoryStore::new().into_event_cache_store(),
+            media_cache_store: media_cache_store::MemoryStore::new().into_media_cache_store(),
         }
     }
 
@@ -445,6 +453,18 @@ impl StoreConfig {
         self.state_store = store.into_state_store();
         self
     }
+
+    /// Set a custom implementation of an `EventCacheStore`.
+    pub fn event_cache_store(mut self, store: impl IntoEventCacheStore) -> Self {
+        self.event_cache_store = store.into_event_cache_store();
+        self
+    }
+
+    /// Set a custom implementation of a `MediaCacheStore`.
+    pub fn media_cache_store(mut self, store: impl IntoMediaCacheStore) -> Self {
+        self.media_cache_store = store.into_media_cache_store();
+        self
+    }
 }
 
 impl Default for StoreConfig {
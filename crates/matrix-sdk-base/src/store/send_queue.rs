@@ -0,0 +1,63 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The types of the requests persisted by the send queue.
+
+use ruma::{
+    events::{room::message::RoomMessageEventContent, AnyMessageLikeEventContent},
+    serde::Raw,
+    OwnedTransactionId,
+};
+use serde::{Deserialize, Serialize};
+
+/// What is to be sent by a send queue request.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum QueuedRequestKind {
+    /// A message-like event.
+    Event {
+        /// The type of the event.
+        event_type: String,
+        /// The content of the event.
+        content: Raw<AnyMessageLikeEventContent>,
+    },
+
+    /// A media file, that is uploaded first and then sent as a message.
+    ///
+    /// The content of the file and of its thumbnail isn't part of the
+    /// request, it is kept in the media cache store until the request is
+    /// sent.
+    Attachment {
+        /// The content of the message, with local placeholder sources for the
+        /// file and its thumbnail, that are replaced once they are uploaded.
+        content: Raw<RoomMessageEventContent>,
+        /// The MIME type of the file.
+        content_type: String,
+        /// The MIME type of the thumbnail, if there is one.
+        thumbnail_content_type: Option<String>,
+    },
+}
+
+/// A request waiting in a send queue, as it's saved in the state store.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueuedRequest {
+    /// The transaction ID the request will be sent with.
+    pub transaction_id: OwnedTransactionId,
+
+    /// What is to be sent.
+    pub kind: QueuedRequestKind,
+
+    /// Whether sending the request failed with an error that won't go away by
+    /// itself, so it won't be sent until it's retried.
+    pub is_wedged: bool,
+}
@@ -32,13 +32,12 @@ use ruma::{
         RoomAccountDataEventType, StateEventType, StaticEventContent, StaticStateEventContent,
     },
     serde::Raw,
-    EventId, MxcUri, OwnedEventId, OwnedUserId, RoomId, UserId,
+    EventId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, TransactionId, UserId,
 };
 
-use super::{StateChanges, StoreError};
+use super::{QueuedRequest, StateChanges, StoreError};
 use crate::{
     deserialized_responses::{RawAnySyncOrStrippedState, RawMemberEvent, RawSyncOrStrippedState},
-    media::MediaRequest,
     MinimalRoomMemberEvent, RoomInfo, RoomMemberships,
 };
 
@@ -322,50 +321,58 @@ pub trait StateStore: AsyncTraitDeps {
     /// * `key` - The key to remove data from
     async fn remove_custom_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error>;
 
-    /// Add a media file's content in the media store.
+    /// Removes a room and all elements associated from the state store.
     ///
     /// # Arguments
     ///
-    /// * `request` - The `MediaRequest` of the file.
-    ///
-    /// * `content` - The content of the file.
-    async fn add_media_content(
-        &self,
-        request: &MediaRequest,
-        content: Vec<u8>,
-    ) -> Result<(), Self::Error>;
+    /// * `room_id` - The `RoomId` of the room to delete.
+    async fn remove_room(&self, room_id: &RoomId) -> Result<(), Self::Error>;
 
-    /// Get a media file's content out of the media store.
+    /// Save a request in the send queue of a room.
+    ///
+    /// If a request with the same transaction ID is already in the queue, it
+    /// is replaced in place. Otherwise, the request is added at the end of the
+    /// queue.
     ///
     /// # Arguments
     ///
-    /// * `request` - The `MediaRequest` of the file.
-    async fn get_media_content(
+    /// * `room_id` - The `RoomId` of the send queue.
+    ///
+    /// * `request` - The request to save.
+    async fn save_send_queue_request(
         &self,
-        request: &MediaRequest,
-    ) -> Result<Option<Vec<u8>>, Self::Error>;
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<(), Self::Error>;
 
-    /// Removes a media file's content from the media store.
+    /// Remove a request from the send queue of a room.
     ///
-    /// # Arguments
-    ///
-    /// * `request` - The `MediaRequest` of the file.
-    async fn remove_media_content(&self, request: &MediaRequest) -> Result<(), Self::Error>;
-
-    /// Removes all the media files' content associated to an `MxcUri` from the
-    /// media store.
+    /// Returns whether the request was in the queue.
     ///
     /// # Arguments
     ///
-    /// * `uri` - The `MxcUri` of the media files.
-    async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<(), Self::Error>;
+    /// * `room_id` - The `RoomId` of the send queue.
+    ///
+    /// * `transaction_id` - The transaction ID of the request to remove.
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool, Self::Error>;
 
-    /// Removes a room and all elements associated from the state store.
+    /// Load the requests in the send queue of a room, in the order they were
+    /// added.
     ///
     /// # Arguments
     ///
-    /// * `room_id` - The `RoomId` of the room to delete.
-    async fn remove_room(&self, room_id: &RoomId) -> Result<(), Self::Error>;
+    /// * `room_id` - The `RoomId` of the send queue.
+    async fn load_send_queue_requests(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<QueuedRequest>, Self::Error>;
+
+    /// Load the IDs of the rooms with requests in their send queue.
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>, Self::Error>;
 }
 
 #[repr(transparent)]
@@ -563,31 +570,35 @@ impl<T: StateStore> StateStore for EraseStateStoreError<T> {
         self.0.remove_custom_value(key).await.map_err(Into::into)
     }
 
-    async fn add_media_content(
-        &self,
-        request: &MediaRequest,
-        content: Vec<u8>,
-    ) -> Result<(), Self::Error> {
-        self.0.add_media_content(request, content).await.map_err(Into::into)
+    async fn remove_room(&self, room_id: &RoomId) -> Result<(), Self::Error> {
+        self.0.remove_room(room_id).await.map_err(Into::into)
     }
 
-    async fn get_media_content(
+    async fn save_send_queue_request(
         &self,
-        request: &MediaRequest,
-    ) -> Result<Option<Vec<u8>>, Self::Error> {
-        self.0.get_media_content(request).await.map_err(Into::into)
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<(), Self::Error> {
+        self.0.save_send_queue_request(room_id, request).await.map_err(Into::into)
     }
 
-    async fn remove_media_content(&self, request: &MediaRequest) -> Result<(), Self::Error> {
-        self.0.remove_media_content(request).await.map_err(Into::into)
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool, Self::Error> {
+        self.0.remove_send_queue_request(room_id, transaction_id).await.map_err(Into::into)
     }
 
-    async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<(), Self::Error> {
-        self.0.remove_media_content_for_uri(uri).await.map_err(Into::into)
+    async fn load_send_queue_requests(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<QueuedRequest>, Self::Error> {
+        self.0.load_send_queue_requests(room_id).await.map_err(Into::into)
     }
 
-    async fn remove_room(&self, room_id: &RoomId) -> Result<(), Self::Error> {
-        self.0.remove_room(room_id).await.map_err(Into::into)
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>, Self::Error> {
+        self.0.load_rooms_with_unsent_requests().await.map_err(Into::into)
     }
 }
 
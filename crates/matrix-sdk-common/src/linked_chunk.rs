@@ -0,0 +1,887 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A linked chunk is an ordered list of chunks, where each chunk is either a
+//! sequence of items, or a gap representing a hole between two sequences of
+//! items.
+//!
+//! Chunks are linked to their previous and next chunks, and addressed by a
+//! [`ChunkIdentifier`], which stays valid as long as the chunk exists,
+//! independently of the insertions or removals happening around it.
+//!
+//! Every modification of a [`LinkedChunk`] is recorded as a list of
+//! [`Update`]s, that can be retrieved with [`LinkedChunk::take_updates`]. This
+//! allows to persist a linked chunk incrementally, instead of rewriting it
+//! entirely after each modification. A persisted linked chunk can be rebuilt
+//! with [`LinkedChunk::from_raw_chunks`].
+
+use std::{collections::HashMap, fmt};
+
+/// The identifier of a chunk, unique within a [`LinkedChunk`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChunkIdentifier(u64);
+
+impl ChunkIdentifier {
+    /// Create a chunk identifier from its persisted value.
+    pub fn new(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// The value of this identifier, to persist it.
+    pub fn index(&self) -> u64 {
+        self.0
+    }
+}
+
+/// The position of an item in a [`LinkedChunk`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position(ChunkIdentifier, usize);
+
+impl Position {
+    /// Create the position of the item at the given index in the given chunk.
+    pub fn new(chunk_identifier: ChunkIdentifier, index: usize) -> Self {
+        Self(chunk_identifier, index)
+    }
+
+    /// The identifier of the chunk containing the item.
+    pub fn chunk_identifier(&self) -> ChunkIdentifier {
+        self.0
+    }
+
+    /// The index of the item within its chunk.
+    pub fn index(&self) -> usize {
+        self.1
+    }
+}
+
+/// The content of a chunk.
+#[derive(Clone, Debug)]
+pub enum ChunkContent<Item, Gap> {
+    /// The chunk represents a gap.
+    Gap(Gap),
+
+    /// The chunk contains items, from the oldest to the most recent one.
+    Items(Vec<Item>),
+}
+
+/// A chunk of a [`LinkedChunk`].
+pub struct Chunk<Item, Gap> {
+    previous: Option<ChunkIdentifier>,
+    next: Option<ChunkIdentifier>,
+    identifier: ChunkIdentifier,
+    content: ChunkContent<Item, Gap>,
+}
+
+impl<Item, Gap> Chunk<Item, Gap> {
+    /// The identifier of this chunk.
+    pub fn identifier(&self) -> ChunkIdentifier {
+        self.identifier
+    }
+
+    /// The identifier of the previous chunk, if any.
+    pub fn previous(&self) -> Option<ChunkIdentifier> {
+        self.previous
+    }
+
+    /// The identifier of the next chunk, if any.
+    pub fn next(&self) -> Option<ChunkIdentifier> {
+        self.next
+    }
+
+    /// The content of this chunk.
+    pub fn content(&self) -> &ChunkContent<Item, Gap> {
+        &self.content
+    }
+
+    /// Whether this chunk represents a gap.
+    pub fn is_gap(&self) -> bool {
+        matches!(self.content, ChunkContent::Gap(_))
+    }
+}
+
+impl<Item: fmt::Debug, Gap: fmt::Debug> fmt::Debug for Chunk<Item, Gap> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chunk")
+            .field("identifier", &self.identifier)
+            .field("previous", &self.previous)
+            .field("next", &self.next)
+            .field("content", &self.content)
+            .finish()
+    }
+}
+
+/// A chunk as persisted in a store, to rebuild a [`LinkedChunk`] with
+/// [`LinkedChunk::from_raw_chunks`].
+#[derive(Clone, Debug)]
+pub struct RawChunk<Item, Gap> {
+    /// The identifier of the chunk.
+    pub identifier: ChunkIdentifier,
+
+    /// The identifier of the previous chunk, if any.
+    pub previous: Option<ChunkIdentifier>,
+
+    /// The identifier of the next chunk, if any.
+    pub next: Option<ChunkIdentifier>,
+
+    /// The content of the chunk.
+    pub content: ChunkContent<Item, Gap>,
+}
+
+/// A modification of a [`LinkedChunk`].
+///
+/// Applying the updates in order to a persisted copy of a linked chunk keeps
+/// it in sync with the linked chunk.
+#[derive(Clone, Debug)]
+pub enum Update<Item, Gap> {
+    /// A new, empty, chunk of items has been inserted between `previous` and
+    /// `next`.
+    NewItemsChunk {
+        /// The chunk before the new one, if any.
+        previous: Option<ChunkIdentifier>,
+        /// The identifier of the new chunk.
+        new: ChunkIdentifier,
+        /// The chunk after the new one, if any.
+        next: Option<ChunkIdentifier>,
+    },
+
+    /// A new gap has been inserted between `previous` and `next`.
+    NewGapChunk {
+        /// The chunk before the new one, if any.
+        previous: Option<ChunkIdentifier>,
+        /// The identifier of the new chunk.
+        new: ChunkIdentifier,
+        /// The chunk after the new one, if any.
+        next: Option<ChunkIdentifier>,
+        /// The content of the gap.
+        gap: Gap,
+    },
+
+    /// A chunk has been removed, its previous and next chunks are now linked
+    /// to each other.
+    RemoveChunk(ChunkIdentifier),
+
+    /// Items have been inserted at the given position, shifting the following
+    /// items of the chunk.
+    PushItems {
+        /// The position of the first inserted item.
+        at: Position,
+        /// The inserted items.
+        items: Vec<Item>,
+    },
+
+    /// The item at the given position has been removed, shifting the
+    /// following items of the chunk.
+    RemoveItem {
+        /// The position of the removed item.
+        at: Position,
+    },
+
+    /// All the chunks have been removed.
+    Clear,
+}
+
+/// An error observed when manipulating a [`LinkedChunk`].
+#[derive(thiserror::Error, Debug)]
+pub enum LinkedChunkError {
+    /// No chunk with this identifier exists.
+    #[error("The chunk identifier is invalid: `{identifier:?}`")]
+    InvalidChunkIdentifier {
+        /// The invalid identifier.
+        identifier: ChunkIdentifier,
+    },
+
+    /// The chunk was expected to be a gap, but it contains items.
+    #[error("The chunk is not a gap: `{identifier:?}`")]
+    ChunkIsNotAGap {
+        /// The identifier of the chunk.
+        identifier: ChunkIdentifier,
+    },
+
+    /// The chunk was expected to contain items, but it is a gap.
+    #[error("The chunk is a gap: `{identifier:?}`")]
+    ChunkIsAGap {
+        /// The identifier of the chunk.
+        identifier: ChunkIdentifier,
+    },
+
+    /// No item exists at this index in the chunk.
+    #[error("The item index is invalid: `{index}`")]
+    InvalidItemIndex {
+        /// The invalid index.
+        index: usize,
+    },
+
+    /// The raw chunks don't form a single list of linked chunks.
+    #[error("The raw chunks are malformed: {reason}")]
+    MalformedRawChunks {
+        /// Why the chunks are malformed.
+        reason: &'static str,
+    },
+}
+
+/// An ordered list of chunks of items and gaps.
+pub struct LinkedChunk<Item, Gap> {
+    /// All the chunks, by identifier.
+    chunks: HashMap<ChunkIdentifier, Chunk<Item, Gap>>,
+
+    /// The oldest chunk.
+    first: Option<ChunkIdentifier>,
+
+    /// The most recent chunk.
+    last: Option<ChunkIdentifier>,
+
+    /// The identifier to give to the next created chunk.
+    next_identifier: u64,
+
+    /// The modifications that haven't been taken with
+    /// [`LinkedChunk::take_updates`] yet.
+    updates: Vec<Update<Item, Gap>>,
+}
+
+impl<Item, Gap> LinkedChunk<Item, Gap> {
+    /// Create a new empty [`LinkedChunk`].
+    pub fn new() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            first: None,
+            last: None,
+            next_identifier: 0,
+            updates: Vec::new(),
+        }
+    }
+
+    /// Whether there is no chunk at all.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Take the modifications made since the last call, in order.
+    pub fn take_updates(&mut self) -> Vec<Update<Item, Gap>> {
+        std::mem::take(&mut self.updates)
+    }
+
+    /// Iterate over the chunks, from the oldest to the most recent one.
+    pub fn chunks(&self) -> Iter<'_, Item, Gap> {
+        Iter {
+            linked_chunk: self,
+            front: self.first,
+            back: self.last,
+            remaining: self.chunks.len(),
+        }
+    }
+
+    /// Iterate over the items along with their position, from the oldest to
+    /// the most recent one.
+    pub fn items(&self) -> impl DoubleEndedIterator<Item = (Position, &Item)> {
+        self.chunks().flat_map(|chunk| {
+            let items = match &chunk.content {
+                ChunkContent::Items(items) => items.as_slice(),
+                ChunkContent::Gap(_) => &[],
+            };
+
+            items
+                .iter()
+                .enumerate()
+                .map(move |(index, item)| (Position(chunk.identifier, index), item))
+        })
+    }
+
+    /// Find the identifier of the most recent chunk matching the given
+    /// predicate.
+    pub fn chunk_identifier<P>(&self, mut predicate: P) -> Option<ChunkIdentifier>
+    where
+        P: FnMut(&Chunk<Item, Gap>) -> bool,
+    {
+        self.chunks().rev().find(|chunk| predicate(chunk)).map(|chunk| chunk.identifier)
+    }
+
+    /// Find the position of the most recent item matching the given predicate.
+    pub fn item_position<P>(&self, mut predicate: P) -> Option<Position>
+    where
+        P: FnMut(&Item) -> bool,
+    {
+        self.items().rev().find(|(_, item)| predicate(item)).map(|(position, _)| position)
+    }
+
+    fn chunk_mut(
+        &mut self,
+        identifier: ChunkIdentifier,
+    ) -> Result<&mut Chunk<Item, Gap>, LinkedChunkError> {
+        self.chunks
+            .get_mut(&identifier)
+            .ok_or(LinkedChunkError::InvalidChunkIdentifier { identifier })
+    }
+}
+
+impl<Item: Clone, Gap: Clone> LinkedChunk<Item, Gap> {
+    /// Rebuild a [`LinkedChunk`] from the chunks persisted in a store, in any
+    /// order.
+    ///
+    /// The chunks keep their identifiers, so the updates of the rebuilt linked
+    /// chunk apply to the persisted chunks.
+    pub fn from_raw_chunks(raw_chunks: Vec<RawChunk<Item, Gap>>) -> Result<Self, LinkedChunkError> {
+        let mut this = Self::new();
+
+        for raw in raw_chunks {
+            this.next_identifier = this.next_identifier.max(raw.identifier.0 + 1);
+            this.chunks.insert(
+                raw.identifier,
+                Chunk {
+                    previous: raw.previous,
+                    next: raw.next,
+                    identifier: raw.identifier,
+                    content: raw.content,
+                },
+            );
+        }
+
+        if this.chunks.is_empty() {
+            return Ok(this);
+        }
+
+        let mut firsts = this.chunks.values().filter(|chunk| chunk.previous.is_none());
+        let (Some(first), None) = (firsts.next(), firsts.next()) else {
+            return Err(LinkedChunkError::MalformedRawChunks {
+                reason: "there must be exactly one first chunk",
+            });
+        };
+
+        // Walk the chunks from the first one, to check that they are all linked
+        // together.
+        let mut current = first;
+        let mut count = 1;
+
+        while let Some(next) = current.next {
+            let next = this.chunks.get(&next).ok_or(LinkedChunkError::MalformedRawChunks {
+                reason: "a chunk links to an unknown chunk",
+            })?;
+
+            if next.previous != Some(current.identifier) || count == this.chunks.len() {
+                return Err(LinkedChunkError::MalformedRawChunks {
+                    reason: "the links between chunks are inconsistent",
+                });
+            }
+
+            current = next;
+            count += 1;
+        }
+
+        if count != this.chunks.len() {
+            return Err(LinkedChunkError::MalformedRawChunks {
+                reason: "some chunks are not linked to the others",
+            });
+        }
+
+        this.first = Some(first.identifier);
+        this.last = Some(current.identifier);
+
+        Ok(this)
+    }
+
+    /// Remove all the chunks.
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.first = None;
+        self.last = None;
+        self.updates.push(Update::Clear);
+    }
+
+    /// Push items at the end, extending the last chunk if it contains items,
+    /// creating a new chunk otherwise.
+    pub fn push_items_back<I>(&mut self, items: I)
+    where
+        I: IntoIterator<Item = Item>,
+    {
+        let mut items = items.into_iter().peekable();
+
+        if items.peek().is_none() {
+            return;
+        }
+
+        let last = self.last.and_then(|last| self.chunks.get_mut(&last));
+
+        if let Some(Chunk { identifier, content: ChunkContent::Items(last_items), .. }) = last {
+            let at = Position(*identifier, last_items.len());
+            let items = items.collect::<Vec<_>>();
+            last_items.extend(items.iter().cloned());
+            self.updates.push(Update::PushItems { at, items });
+        } else {
+            self.insert_chunk(self.last, None, ChunkContent::Items(items.collect()));
+        }
+    }
+
+    /// Push a gap at the end.
+    pub fn push_gap_back(&mut self, gap: Gap) -> ChunkIdentifier {
+        self.insert_chunk(self.last, None, ChunkContent::Gap(gap))
+    }
+
+    /// Push a gap at the beginning.
+    pub fn push_gap_front(&mut self, gap: Gap) -> ChunkIdentifier {
+        self.insert_chunk(None, self.first, ChunkContent::Gap(gap))
+    }
+
+    /// Insert a gap right before the chunk with the given identifier.
+    pub fn insert_gap_before(
+        &mut self,
+        gap: Gap,
+        identifier: ChunkIdentifier,
+    ) -> Result<ChunkIdentifier, LinkedChunkError> {
+        let previous = self.chunk_mut(identifier)?.previous;
+
+        Ok(self.insert_chunk(previous, Some(identifier), ChunkContent::Gap(gap)))
+    }
+
+    /// Replace the gap with the given identifier by the given items.
+    ///
+    /// If `items` is empty, the gap is simply removed and `None` is returned.
+    /// Otherwise, the identifier of the chunk containing the items is
+    /// returned.
+    pub fn replace_gap_at(
+        &mut self,
+        items: Vec<Item>,
+        identifier: ChunkIdentifier,
+    ) -> Result<Option<ChunkIdentifier>, LinkedChunkError> {
+        if !self.chunk_mut(identifier)?.is_gap() {
+            return Err(LinkedChunkError::ChunkIsNotAGap { identifier });
+        }
+
+        let gap = self.remove_chunk(identifier);
+
+        if items.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(self.insert_chunk(gap.previous, gap.next, ChunkContent::Items(items))))
+    }
+
+    /// Remove the item at the given position.
+    ///
+    /// The chunk containing the item is removed if it becomes empty.
+    pub fn remove_item_at(&mut self, position: Position) -> Result<Item, LinkedChunkError> {
+        let identifier = position.chunk_identifier();
+
+        let ChunkContent::Items(items) = &mut self.chunk_mut(identifier)?.content else {
+            return Err(LinkedChunkError::ChunkIsAGap { identifier });
+        };
+
+        if position.index() >= items.len() {
+            return Err(LinkedChunkError::InvalidItemIndex { index: position.index() });
+        }
+
+        let item = items.remove(position.index());
+        let is_empty = items.is_empty();
+
+        self.updates.push(Update::RemoveItem { at: position });
+
+        if is_empty {
+            self.remove_chunk(identifier);
+        }
+
+        Ok(item)
+    }
+
+    /// Insert a new chunk between the given chunks, which must be linked to
+    /// each other.
+    fn insert_chunk(
+        &mut self,
+        previous: Option<ChunkIdentifier>,
+        next: Option<ChunkIdentifier>,
+        content: ChunkContent<Item, Gap>,
+    ) -> ChunkIdentifier {
+        let new = ChunkIdentifier(self.next_identifier);
+        self.next_identifier += 1;
+
+        match previous.and_then(|previous| self.chunks.get_mut(&previous)) {
+            Some(previous) => previous.next = Some(new),
+            None => self.first = Some(new),
+        }
+
+        match next.and_then(|next| self.chunks.get_mut(&next)) {
+            Some(next) => next.previous = Some(new),
+            None => self.last = Some(new),
+        }
+
+        match &content {
+            ChunkContent::Gap(gap) => {
+                self.updates.push(Update::NewGapChunk { previous, new, next, gap: gap.clone() });
+            }
+            ChunkContent::Items(items) => {
+                self.updates.push(Update::NewItemsChunk { previous, new, next });
+
+                if !items.is_empty() {
+                    self.updates
+                        .push(Update::PushItems { at: Position(new, 0), items: items.clone() });
+                }
+            }
+        }
+
+        self.chunks.insert(new, Chunk { previous, next, identifier: new, content });
+
+        new
+    }
+
+    /// Remove the chunk with the given identifier, which must exist, linking
+    /// its previous and next chunks to each other.
+    fn remove_chunk(&mut self, identifier: ChunkIdentifier) -> Chunk<Item, Gap> {
+        let chunk = self.chunks.remove(&identifier).expect("the chunk must exist");
+
+        match chunk.previous.and_then(|previous| self.chunks.get_mut(&previous)) {
+            Some(previous) => previous.next = chunk.next,
+            None => self.first = chunk.next,
+        }
+
+        match chunk.next.and_then(|next| self.chunks.get_mut(&next)) {
+            Some(next) => next.previous = chunk.previous,
+            None => self.last = chunk.previous,
+        }
+
+        self.updates.push(Update::RemoveChunk(identifier));
+
+        chunk
+    }
+}
+
+impl<Item, Gap> Default for LinkedChunk<Item, Gap> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Item: fmt::Debug, Gap: fmt::Debug> fmt::Debug for LinkedChunk<Item, Gap> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.chunks()).finish()
+    }
+}
+
+/// An iterator over the chunks of a [`LinkedChunk`], following their links.
+///
+/// Get one with [`LinkedChunk::chunks`].
+pub struct Iter<'a, Item, Gap> {
+    linked_chunk: &'a LinkedChunk<Item, Gap>,
+    front: Option<ChunkIdentifier>,
+    back: Option<ChunkIdentifier>,
+    remaining: usize,
+}
+
+impl<'a, Item, Gap> Iterator for Iter<'a, Item, Gap> {
+    type Item = &'a Chunk<Item, Gap>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let chunk = self.linked_chunk.chunks.get(&self.front?)?;
+        self.front = chunk.next;
+        self.remaining -= 1;
+
+        Some(chunk)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, Item, Gap> DoubleEndedIterator for Iter<'a, Item, Gap> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let chunk = self.linked_chunk.chunks.get(&self.back?)?;
+        self.back = chunk.previous;
+        self.remaining -= 1;
+
+        Some(chunk)
+    }
+}
+
+impl<'a, Item, Gap> ExactSizeIterator for Iter<'a, Item, Gap> {}
+
+impl<'a, Item, Gap> fmt::Debug for Iter<'a, Item, Gap> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Iter")
+            .field("front", &self.front)
+            .field("back", &self.back)
+            .field("remaining", &self.remaining)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::{ChunkContent, ChunkIdentifier, LinkedChunk, LinkedChunkError, RawChunk, Update};
+
+    fn items(linked_chunk: &LinkedChunk<char, ()>) -> Vec<char> {
+        linked_chunk.items().map(|(_, item)| *item).collect()
+    }
+
+    /// Apply the updates to a list of raw chunks, like a store would.
+    fn apply_updates(raw_chunks: &mut Vec<RawChunk<char, ()>>, updates: Vec<Update<char, ()>>) {
+        fn relink(
+            raw_chunks: &mut [RawChunk<char, ()>],
+            previous: Option<ChunkIdentifier>,
+            next: Option<ChunkIdentifier>,
+            new: Option<ChunkIdentifier>,
+        ) {
+            for raw in raw_chunks.iter_mut() {
+                if Some(raw.identifier) == previous {
+                    raw.next = new.or(next);
+                }
+                if Some(raw.identifier) == next {
+                    raw.previous = new.or(previous);
+                }
+            }
+        }
+
+        for update in updates {
+            match update {
+                Update::NewItemsChunk { previous, new, next } => {
+                    relink(raw_chunks, previous, next, Some(new));
+                    raw_chunks.push(RawChunk {
+                        identifier: new,
+                        previous,
+                        next,
+                        content: ChunkContent::Items(Vec::new()),
+                    });
+                }
+                Update::NewGapChunk { previous, new, next, gap } => {
+                    relink(raw_chunks, previous, next, Some(new));
+                    raw_chunks.push(RawChunk {
+                        identifier: new,
+                        previous,
+                        next,
+                        content: ChunkContent::Gap(gap),
+                    });
+                }
+                Update::RemoveChunk(identifier) => {
+                    let index =
+                        raw_chunks.iter().position(|raw| raw.identifier == identifier).unwrap();
+                    let raw = raw_chunks.remove(index);
+                    relink(raw_chunks, raw.previous, raw.next, None);
+                }
+                Update::PushItems { at, items } => {
+                    let raw = raw_chunks
+                        .iter_mut()
+                        .find(|raw| raw.identifier == at.chunk_identifier())
+                        .unwrap();
+                    assert_matches!(&mut raw.content, ChunkContent::Items(raw_items) => {
+                        raw_items.splice(at.index()..at.index(), items);
+                    });
+                }
+                Update::RemoveItem { at } => {
+                    let raw = raw_chunks
+                        .iter_mut()
+                        .find(|raw| raw.identifier == at.chunk_identifier())
+                        .unwrap();
+                    assert_matches!(&mut raw.content, ChunkContent::Items(raw_items) => {
+                        raw_items.remove(at.index());
+                    });
+                }
+                Update::Clear => raw_chunks.clear(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_push_items_back_extends_last_chunk() {
+        let mut linked_chunk = LinkedChunk::<char, ()>::new();
+        assert!(linked_chunk.is_empty());
+
+        linked_chunk.push_items_back(['a', 'b']);
+        linked_chunk.push_items_back(['c']);
+        linked_chunk.push_items_back([]);
+
+        assert_eq!(linked_chunk.chunks().count(), 1);
+        assert_eq!(items(&linked_chunk), ['a', 'b', 'c']);
+
+        let updates = linked_chunk.take_updates();
+        assert_eq!(updates.len(), 3);
+        assert_matches!(&updates[0], Update::NewItemsChunk { previous: None, next: None, .. });
+        assert_matches!(&updates[1], Update::PushItems { at, items } => {
+            assert_eq!(at.index(), 0);
+            assert_eq!(items, &['a', 'b']);
+        });
+        assert_matches!(&updates[2], Update::PushItems { at, items } => {
+            assert_eq!(at.index(), 2);
+            assert_eq!(items, &['c']);
+        });
+        assert!(linked_chunk.take_updates().is_empty());
+    }
+
+    #[test]
+    fn test_gaps_split_chunks() {
+        let mut linked_chunk = LinkedChunk::<char, ()>::new();
+
+        linked_chunk.push_items_back(['a']);
+        linked_chunk.push_gap_back(());
+        linked_chunk.push_items_back(['b', 'c']);
+
+        let chunks = linked_chunk.chunks().collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 3);
+        assert_matches!(chunks[0].content(), ChunkContent::Items(items) => assert_eq!(items, &['a']));
+        assert!(chunks[1].is_gap());
+        assert_matches!(chunks[2].content(), ChunkContent::Items(items) => assert_eq!(items, &['b', 'c']));
+
+        assert_eq!(chunks[0].next(), Some(chunks[1].identifier()));
+        assert_eq!(chunks[2].previous(), Some(chunks[1].identifier()));
+
+        let reversed = linked_chunk.chunks().rev().map(|chunk| chunk.identifier());
+        assert!(reversed.eq(chunks.iter().rev().map(|chunk| chunk.identifier())));
+    }
+
+    #[test]
+    fn test_replace_gap_at() {
+        let mut linked_chunk = LinkedChunk::<char, ()>::new();
+
+        linked_chunk.push_items_back(['a']);
+        let gap = linked_chunk.push_gap_back(());
+        linked_chunk.push_items_back(['d']);
+
+        let new_gap = linked_chunk.insert_gap_before((), gap).unwrap();
+        let replaced = linked_chunk.replace_gap_at(vec!['b', 'c'], gap).unwrap();
+        assert!(replaced.is_some());
+        assert_eq!(items(&linked_chunk), ['a', 'b', 'c', 'd']);
+
+        // Replacing a gap with nothing removes it.
+        assert!(linked_chunk.replace_gap_at(Vec::new(), new_gap).unwrap().is_none());
+        assert!(linked_chunk.chunks().all(|chunk| !chunk.is_gap()));
+
+        // The gap doesn't exist anymore.
+        assert_matches!(
+            linked_chunk.replace_gap_at(vec!['z'], gap),
+            Err(LinkedChunkError::InvalidChunkIdentifier { .. })
+        );
+
+        // Only gaps can be replaced.
+        let items_chunk = replaced.unwrap();
+        assert_matches!(
+            linked_chunk.replace_gap_at(vec!['z'], items_chunk),
+            Err(LinkedChunkError::ChunkIsNotAGap { .. })
+        );
+    }
+
+    #[test]
+    fn test_remove_item_at() {
+        let mut linked_chunk = LinkedChunk::<char, ()>::new();
+
+        linked_chunk.push_items_back(['a']);
+        linked_chunk.push_gap_back(());
+        linked_chunk.push_items_back(['b', 'c']);
+
+        let position = linked_chunk.item_position(|item| *item == 'b').unwrap();
+        assert_eq!(linked_chunk.remove_item_at(position).unwrap(), 'b');
+        assert_eq!(items(&linked_chunk), ['a', 'c']);
+
+        // Removing the last item of a chunk removes the chunk.
+        let position = linked_chunk.item_position(|item| *item == 'a').unwrap();
+        assert_eq!(linked_chunk.remove_item_at(position).unwrap(), 'a');
+        assert_eq!(linked_chunk.chunks().count(), 2);
+        assert!(linked_chunk.chunks().next().unwrap().is_gap());
+
+        assert_matches!(
+            linked_chunk.remove_item_at(position),
+            Err(LinkedChunkError::InvalidChunkIdentifier { .. })
+        );
+    }
+
+    #[test]
+    fn test_chunk_identifier() {
+        let mut linked_chunk = LinkedChunk::<char, u8>::new();
+
+        let first_gap = linked_chunk.push_gap_back(1);
+        linked_chunk.push_items_back(['a']);
+        let second_gap = linked_chunk.push_gap_back(2);
+
+        let find_gap = |value: u8| {
+            linked_chunk.chunk_identifier(
+                |chunk| matches!(chunk.content(), ChunkContent::Gap(gap) if *gap == value),
+            )
+        };
+
+        assert_eq!(find_gap(1), Some(first_gap));
+        assert_eq!(find_gap(2), Some(second_gap));
+        assert_eq!(find_gap(3), None);
+
+        let front_gap = linked_chunk.push_gap_front(3);
+        assert_eq!(linked_chunk.chunks().next().unwrap().identifier(), front_gap);
+    }
+
+    #[test]
+    fn test_updates_rebuild_the_linked_chunk() {
+        let mut linked_chunk = LinkedChunk::<char, ()>::new();
+        let mut raw_chunks = Vec::new();
+
+        linked_chunk.push_items_back(['a', 'b']);
+        let gap = linked_chunk.push_gap_back(());
+        linked_chunk.push_items_back(['e']);
+        linked_chunk.push_gap_front(());
+        apply_updates(&mut raw_chunks, linked_chunk.take_updates());
+
+        linked_chunk.insert_gap_before((), gap).unwrap();
+        linked_chunk.replace_gap_at(vec!['c', 'd'], gap).unwrap();
+        let position = linked_chunk.item_position(|item| *item == 'a').unwrap();
+        linked_chunk.remove_item_at(position).unwrap();
+        linked_chunk.push_items_back(['f']);
+        apply_updates(&mut raw_chunks, linked_chunk.take_updates());
+
+        let mut rebuilt = LinkedChunk::from_raw_chunks(raw_chunks.clone()).unwrap();
+        assert_eq!(items(&rebuilt), ['b', 'c', 'd', 'e', 'f']);
+        assert_eq!(
+            rebuilt.chunks().map(|chunk| chunk.identifier()).collect::<Vec<_>>(),
+            linked_chunk.chunks().map(|chunk| chunk.identifier()).collect::<Vec<_>>()
+        );
+
+        // The rebuilt linked chunk doesn't reuse existing identifiers.
+        rebuilt.push_gap_back(());
+        rebuilt.push_items_back(['g']);
+        apply_updates(&mut raw_chunks, rebuilt.take_updates());
+        let rebuilt = LinkedChunk::from_raw_chunks(raw_chunks.clone()).unwrap();
+        assert_eq!(items(&rebuilt), ['b', 'c', 'd', 'e', 'f', 'g']);
+
+        linked_chunk.clear();
+        apply_updates(&mut raw_chunks, linked_chunk.take_updates());
+        assert!(LinkedChunk::from_raw_chunks(raw_chunks).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_from_malformed_raw_chunks() {
+        let mut linked_chunk = LinkedChunk::<char, ()>::new();
+        let mut raw_chunks = Vec::new();
+
+        linked_chunk.push_items_back(['a']);
+        linked_chunk.push_gap_back(());
+        linked_chunk.push_items_back(['b']);
+        apply_updates(&mut raw_chunks, linked_chunk.take_updates());
+
+        // A chunk is missing.
+        let mut missing = raw_chunks.clone();
+        missing.remove(1);
+        assert_matches!(
+            LinkedChunk::from_raw_chunks(missing),
+            Err(LinkedChunkError::MalformedRawChunks { .. })
+        );
+
+        // A loop.
+        let mut looping = raw_chunks.clone();
+        looping[2].next = Some(looping[0].identifier);
+        assert_matches!(
+            LinkedChunk::from_raw_chunks(looping),
+            Err(LinkedChunkError::MalformedRawChunks { .. })
+        );
+    }
+}
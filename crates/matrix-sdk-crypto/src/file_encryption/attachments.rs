@@ -94,6 +94,69 @@ pub enum DecryptorError {
     /// attachment encryption spec.
     #[error("Unknown version for the encrypted attachment.")]
     UnknownVersion,
+    /// The hash of the decrypted data doesn't match the expected hash.
+    #[error("Hash mismatch while decrypting")]
+    HashMismatch,
+}
+
+/// Get the expected hash and the cipher to decrypt an attachment with the
+/// given encryption info.
+fn decryption_state(info: MediaEncryptionInfo) -> Result<(Vec<u8>, Aes256Ctr), DecryptorError> {
+    if info.version != VERSION {
+        return Err(DecryptorError::UnknownVersion);
+    }
+
+    let hash = info.hashes.get("sha256").ok_or(DecryptorError::MissingHash)?.as_bytes().to_owned();
+    let mut key = info.key.k.into_inner();
+    let iv = info.iv.into_inner();
+
+    if key.len() != KEY_SIZE {
+        return Err(DecryptorError::KeyNonceLength);
+    }
+
+    let key_array = GenericArray::from_slice(&key);
+    let iv = GenericArray::from_exact_iter(iv).ok_or(DecryptorError::KeyNonceLength)?;
+
+    let aes = Aes256Ctr::new(key_array, &iv);
+    key.zeroize();
+
+    Ok((hash, aes))
+}
+
+/// Generate a fresh key and initialization vector to encrypt an attachment.
+///
+/// # Panics
+///
+/// Panics if we can't generate enough random data to create a fresh
+/// encryption key.
+fn encryption_state() -> (JsonWebKey, Base64, Aes256Ctr) {
+    let mut key = [0u8; KEY_SIZE];
+    let mut iv = [0u8; IV_SIZE];
+
+    let mut rng = thread_rng();
+
+    rng.fill_bytes(&mut key);
+    // Only populate the first 8 bytes with randomness, the rest is 0
+    // initialized for the counter.
+    rng.fill_bytes(&mut iv[0..8]);
+
+    let web_key = JsonWebKey::from(JsonWebKeyInit {
+        kty: "oct".to_owned(),
+        key_ops: vec!["encrypt".to_owned(), "decrypt".to_owned()],
+        alg: "A256CTR".to_owned(),
+        #[allow(clippy::unnecessary_to_owned)]
+        k: Base64::new(key.to_vec()),
+        ext: true,
+    });
+    #[allow(clippy::unnecessary_to_owned)]
+    let encoded_iv = Base64::new(iv.to_vec());
+
+    let key_array = &key.into();
+
+    let aes = Aes256Ctr::new(key_array, &iv.into());
+    key.zeroize();
+
+    (web_key, encoded_iv, aes)
 }
 
 impl<'a, R: Read + 'a> AttachmentDecryptor<'a, R> {
@@ -130,27 +193,9 @@ impl<'a, R: Read + 'a> AttachmentDecryptor<'a, R> {
         input: &'a mut R,
         info: MediaEncryptionInfo,
     ) -> Result<AttachmentDecryptor<'a, R>, DecryptorError> {
-        if info.version != VERSION {
-            return Err(DecryptorError::UnknownVersion);
-        }
-
-        let hash =
-            info.hashes.get("sha256").ok_or(DecryptorError::MissingHash)?.as_bytes().to_owned();
-        let mut key = info.key.k.into_inner();
-        let iv = info.iv.into_inner();
-
-        if key.len() != KEY_SIZE {
-            return Err(DecryptorError::KeyNonceLength);
-        }
-
-        let key_array = GenericArray::from_slice(&key);
-        let iv = GenericArray::from_exact_iter(iv).ok_or(DecryptorError::KeyNonceLength)?;
-
+        let (hash, aes) = decryption_state(info)?;
         let sha = Sha256::default();
 
-        let aes = Aes256Ctr::new(key_array, &iv);
-        key.zeroize();
-
         Ok(AttachmentDecryptor { inner: input, expected_hash: hash, sha, aes })
     }
 }
@@ -225,31 +270,7 @@ impl<'a, R: Read + ?Sized + 'a> AttachmentEncryptor<'a, R> {
     /// let key = encryptor.finish();
     /// ```
     pub fn new(reader: &'a mut R) -> Self {
-        let mut key = [0u8; KEY_SIZE];
-        let mut iv = [0u8; IV_SIZE];
-
-        let mut rng = thread_rng();
-
-        rng.fill_bytes(&mut key);
-        // Only populate the first 8 bytes with randomness, the rest is 0
-        // initialized for the counter.
-        rng.fill_bytes(&mut iv[0..8]);
-
-        let web_key = JsonWebKey::from(JsonWebKeyInit {
-            kty: "oct".to_owned(),
-            key_ops: vec!["encrypt".to_owned(), "decrypt".to_owned()],
-            alg: "A256CTR".to_owned(),
-            #[allow(clippy::unnecessary_to_owned)]
-            k: Base64::new(key.to_vec()),
-            ext: true,
-        });
-        #[allow(clippy::unnecessary_to_owned)]
-        let encoded_iv = Base64::new(iv.to_vec());
-
-        let key_array = &key.into();
-
-        let aes = Aes256Ctr::new(key_array, &iv.into());
-        key.zeroize();
+        let (web_key, encoded_iv, aes) = encryption_state();
 
         AttachmentEncryptor {
             finished: false,
@@ -278,6 +299,137 @@ impl<'a, R: Read + ?Sized + 'a> AttachmentEncryptor<'a, R> {
     }
 }
 
+/// An encryptor for Matrix attachments that encrypts the data chunk by chunk.
+///
+/// Unlike [`AttachmentEncryptor`], this doesn't need a `Read` implementation,
+/// so it can be used to encrypt a stream of data, for example while it is
+/// being uploaded.
+///
+/// # Examples
+/// ```
+/// # use matrix_sdk_crypto::{AttachmentChunkDecryptor, AttachmentChunkEncryptor};
+/// let mut chunks = vec![b"Hello ".to_vec(), b"world".to_vec()];
+///
+/// let mut encryptor = AttachmentChunkEncryptor::new();
+/// for chunk in &mut chunks {
+///     encryptor.encrypt_chunk(chunk);
+/// }
+/// let info = encryptor.finish();
+///
+/// let mut decryptor = AttachmentChunkDecryptor::new(info).unwrap();
+/// for chunk in &mut chunks {
+///     decryptor.decrypt_chunk(chunk);
+/// }
+/// decryptor.finish().unwrap();
+///
+/// assert_eq!(chunks.concat(), b"Hello world");
+/// ```
+pub struct AttachmentChunkEncryptor {
+    web_key: JsonWebKey,
+    iv: Base64,
+    aes: Aes256Ctr,
+    sha: Sha256,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl std::fmt::Debug for AttachmentChunkEncryptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AttachmentChunkEncryptor").finish_non_exhaustive()
+    }
+}
+
+impl AttachmentChunkEncryptor {
+    /// Create an encryptor with a fresh encryption key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if we can't generate enough random data to create a fresh
+    /// encryption key.
+    pub fn new() -> Self {
+        let (web_key, iv, aes) = encryption_state();
+        Self { web_key, iv, aes, sha: Sha256::default() }
+    }
+
+    /// Encrypt the next chunk of the attachment in place.
+    pub fn encrypt_chunk(&mut self, chunk: &mut [u8]) {
+        self.aes.apply_keystream(chunk);
+        self.sha.update(&*chunk);
+    }
+
+    /// Consume the encryptor and get the information needed to decrypt the
+    /// attachment.
+    ///
+    /// This must be called after all the chunks were encrypted.
+    pub fn finish(self) -> MediaEncryptionInfo {
+        let hash = self.sha.finalize();
+
+        MediaEncryptionInfo {
+            version: VERSION.to_owned(),
+            hashes: BTreeMap::from([(
+                "sha256".to_owned(),
+                Base64::new(hash.as_slice().to_owned()),
+            )]),
+            iv: self.iv,
+            key: self.web_key,
+        }
+    }
+}
+
+impl Default for AttachmentChunkEncryptor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A decryptor for Matrix attachments that decrypts the data chunk by chunk.
+///
+/// Unlike [`AttachmentDecryptor`], this doesn't need a `Read` implementation,
+/// so it can be used to decrypt a stream of data, for example while it is
+/// being downloaded.
+///
+/// The integrity of the attachment can only be checked once all the chunks
+/// were decrypted, with [`AttachmentChunkDecryptor::finish()`].
+pub struct AttachmentChunkDecryptor {
+    expected_hash: Vec<u8>,
+    sha: Sha256,
+    aes: Aes256Ctr,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl std::fmt::Debug for AttachmentChunkDecryptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AttachmentChunkDecryptor")
+            .field("expected_hash", &self.expected_hash)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AttachmentChunkDecryptor {
+    /// Create a decryptor for the attachment with the given encryption info.
+    pub fn new(info: MediaEncryptionInfo) -> Result<Self, DecryptorError> {
+        let (expected_hash, aes) = decryption_state(info)?;
+        Ok(Self { expected_hash, sha: Sha256::default(), aes })
+    }
+
+    /// Decrypt the next chunk of the attachment in place.
+    pub fn decrypt_chunk(&mut self, chunk: &mut [u8]) {
+        self.sha.update(&*chunk);
+        self.aes.apply_keystream(chunk);
+    }
+
+    /// Consume the decryptor and check the integrity of the attachment.
+    ///
+    /// This must be called after all the chunks were decrypted. The decrypted
+    /// data must not be trusted if this returns an error.
+    pub fn finish(self) -> Result<(), DecryptorError> {
+        if self.sha.finalize().as_slice() == self.expected_hash.as_slice() {
+            Ok(())
+        } else {
+            Err(DecryptorError::HashMismatch)
+        }
+    }
+}
+
 /// Struct holding all the information that is needed to decrypt an encrypted
 /// file.
 #[derive(Debug, Serialize, Deserialize)]
@@ -305,7 +457,10 @@ mod tests {
 
     use serde_json::json;
 
-    use super::{AttachmentDecryptor, AttachmentEncryptor, MediaEncryptionInfo};
+    use super::{
+        AttachmentChunkDecryptor, AttachmentChunkEncryptor, AttachmentDecryptor,
+        AttachmentEncryptor, DecryptorError, MediaEncryptionInfo,
+    };
 
     const EXAMPLE_DATA: &[u8] = &[
         179, 154, 118, 127, 186, 127, 110, 33, 203, 33, 33, 134, 67, 100, 173, 46, 235, 27, 215,
@@ -369,6 +524,63 @@ mod tests {
         assert_eq!("It's a secret to everybody", decrypted);
     }
 
+    #[test]
+    fn chunk_encrypt_decrypt_cycle() {
+        let data = b"Hello world, this is a longer message".to_vec();
+
+        let mut encryptor = AttachmentChunkEncryptor::new();
+        let mut encrypted = data.clone();
+        for chunk in encrypted.chunks_mut(5) {
+            encryptor.encrypt_chunk(chunk);
+        }
+        let key = encryptor.finish();
+        assert_ne!(encrypted, data);
+
+        // The chunk encryptor is compatible with the reader-based decryptor.
+        let mut cursor = Cursor::new(encrypted.clone());
+        let mut decryptor = AttachmentDecryptor::new(&mut cursor, key).unwrap();
+        let mut decrypted_data = Vec::new();
+        decryptor.read_to_end(&mut decrypted_data).unwrap();
+        assert_eq!(decrypted_data, data);
+
+        // And the chunk decryptor with the reader-based encryptor.
+        let mut cursor = Cursor::new(data.clone());
+        let mut encryptor = AttachmentEncryptor::new(&mut cursor);
+        let mut encrypted = Vec::new();
+        encryptor.read_to_end(&mut encrypted).unwrap();
+        let key = encryptor.finish();
+
+        let mut decryptor = AttachmentChunkDecryptor::new(key).unwrap();
+        for chunk in encrypted.chunks_mut(7) {
+            decryptor.decrypt_chunk(chunk);
+        }
+        decryptor.finish().unwrap();
+        assert_eq!(encrypted, data);
+    }
+
+    #[test]
+    fn chunk_real_decrypt() {
+        let mut data = EXAMPLE_DATA.to_vec();
+
+        let mut decryptor = AttachmentChunkDecryptor::new(example_key()).unwrap();
+        for chunk in data.chunks_mut(3) {
+            decryptor.decrypt_chunk(chunk);
+        }
+        decryptor.finish().unwrap();
+
+        assert_eq!(String::from_utf8(data).unwrap(), "It's a secret to everybody");
+    }
+
+    #[test]
+    fn chunk_decrypt_invalid_hash() {
+        let mut data = b"fake message".to_vec();
+
+        let mut decryptor = AttachmentChunkDecryptor::new(example_key()).unwrap();
+        decryptor.decrypt_chunk(&mut data);
+
+        assert!(matches!(decryptor.finish(), Err(DecryptorError::HashMismatch)));
+    }
+
     #[test]
     fn decrypt_invalid_hash() {
         let mut cursor = Cursor::new("fake message");
@@ -46,7 +46,7 @@ use super::{
 };
 use crate::IndexeddbStateStoreError;
 
-const CURRENT_DB_VERSION: u32 = 8;
+const CURRENT_DB_VERSION: u32 = 10;
 const CURRENT_META_DB_VERSION: u32 = 2;
 
 /// Sometimes Migrations can't proceed without having to drop existing
@@ -77,6 +77,7 @@ mod old_keys {
     pub const STRIPPED_JOINED_USER_IDS: &str = "stripped_joined_user_ids";
     pub const STRIPPED_INVITED_USER_IDS: &str = "stripped_invited_user_ids";
     pub const STRIPPED_ROOM_INFOS: &str = "stripped_room_infos";
+    pub const MEDIA: &str = "media";
 }
 
 pub async fn upgrade_meta_db(
@@ -225,6 +226,12 @@ pub async fn upgrade_inner_db(
             if old_version < 8 {
                 db = migrate_to_v8(db, store_cipher).await?;
             }
+            if old_version < 9 {
+                db = migrate_to_v9(db).await?;
+            }
+            if old_version < 10 {
+                db = migrate_to_v10(db).await?;
+            }
         }
 
         db.close();
@@ -310,7 +317,7 @@ pub const V1_STORES: &[&str] = &[
     old_keys::STRIPPED_INVITED_USER_IDS,
     keys::ROOM_USER_RECEIPTS,
     keys::ROOM_EVENT_RECEIPTS,
-    keys::MEDIA,
+    old_keys::MEDIA,
     keys::CUSTOM,
     old_keys::SYNC_TOKEN,
 ];
@@ -731,6 +738,24 @@ async fn migrate_to_v8(db: IdbDatabase, store_cipher: Option<&StoreCipher>) -> R
     Ok(IdbDatabase::open_u32(&name, 8)?.await?)
 }
 
+/// Add the store of the send queue requests.
+async fn migrate_to_v9(db: IdbDatabase) -> Result<IdbDatabase> {
+    let migration = OngoingMigration {
+        create_stores: [keys::SEND_QUEUE].into_iter().collect(),
+        ..Default::default()
+    };
+    apply_migration(db, 9, migration).await
+}
+
+/// Drop the media store, the media are cached in the media cache store now.
+async fn migrate_to_v10(db: IdbDatabase) -> Result<IdbDatabase> {
+    let migration = OngoingMigration {
+        drop_stores: [old_keys::MEDIA].into_iter().collect(),
+        ..Default::default()
+    };
+    apply_migration(db, 10, migration).await
+}
+
 #[cfg(all(test, target_arch = "wasm32"))]
 mod tests {
     wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
@@ -788,7 +813,7 @@ mod tests {
                     keys::STRIPPED_ROOM_STATE,
                     keys::ROOM_USER_RECEIPTS,
                     keys::ROOM_EVENT_RECEIPTS,
-                    keys::MEDIA,
+                    old_keys::MEDIA,
                     keys::CUSTOM,
                 ];
 
@@ -23,8 +23,7 @@ use gloo_utils::format::JsValueSerdeExt;
 use indexed_db_futures::prelude::*;
 use matrix_sdk_base::{
     deserialized_responses::RawAnySyncOrStrippedState,
-    media::{MediaRequest, UniqueKey},
-    store::{StateChanges, StateStore, StoreError},
+    store::{QueuedRequest, StateChanges, StateStore, StoreError},
     MinimalRoomMemberEvent, RoomInfo, RoomMemberships, RoomState, StateStoreDataKey,
     StateStoreDataValue,
 };
@@ -41,7 +40,8 @@ use ruma::{
         GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType, SyncStateEvent,
     },
     serde::Raw,
-    CanonicalJsonObject, EventId, MxcUri, OwnedEventId, OwnedUserId, RoomId, RoomVersionId, UserId,
+    CanonicalJsonObject, EventId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId,
+    TransactionId, UserId,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tracing::{debug, warn};
@@ -110,11 +110,11 @@ mod keys {
     pub const ROOM_USER_RECEIPTS: &str = "room_user_receipts";
     pub const ROOM_EVENT_RECEIPTS: &str = "room_event_receipts";
 
-    pub const MEDIA: &str = "media";
-
     pub const CUSTOM: &str = "custom";
     pub const KV: &str = "kv";
 
+    pub const SEND_QUEUE: &str = "send_queue";
+
     /// All names of the current state stores for convenience.
     pub const ALL_STORES: &[&str] = &[
         ACCOUNT_DATA,
@@ -129,9 +129,9 @@ mod keys {
         STRIPPED_USER_IDS,
         ROOM_USER_RECEIPTS,
         ROOM_EVENT_RECEIPTS,
-        MEDIA,
         CUSTOM,
         KV,
+        SEND_QUEUE,
     ];
 
     // static keys
@@ -1004,7 +1004,7 @@ impl_state_store!({
                 let value = cursor.value();
                 let info = self.deserialize_event::<RoomInfo>(&value)?;
 
-                if info.state() == RoomState::Invited {
+                if matches!(info.state(), RoomState::Invited | RoomState::Knocked) {
                     infos.push(info);
                 }
 
@@ -1138,29 +1138,6 @@ impl_state_store!({
             .collect::<Vec<_>>())
     }
 
-    async fn add_media_content(&self, request: &MediaRequest, data: Vec<u8>) -> Result<()> {
-        let key = self
-            .encode_key(keys::MEDIA, (request.source.unique_key(), request.format.unique_key()));
-        let tx =
-            self.inner.transaction_on_one_with_mode(keys::MEDIA, IdbTransactionMode::Readwrite)?;
-
-        tx.object_store(keys::MEDIA)?.put_key_val(&key, &self.serialize_event(&data)?)?;
-
-        tx.await.into_result().map_err(|e| e.into())
-    }
-
-    async fn get_media_content(&self, request: &MediaRequest) -> Result<Option<Vec<u8>>> {
-        let key = self
-            .encode_key(keys::MEDIA, (request.source.unique_key(), request.format.unique_key()));
-        self.inner
-            .transaction_on_one_with_mode(keys::MEDIA, IdbTransactionMode::Readonly)?
-            .object_store(keys::MEDIA)?
-            .get(&key)?
-            .await?
-            .map(|f| self.deserialize_event(&f))
-            .transpose()
-    }
-
     async fn get_custom_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         let jskey = &JsValue::from_str(core::str::from_utf8(key).map_err(StoreError::Codec)?);
         self.get_custom_value_for_js(jskey).await
@@ -1194,30 +1171,6 @@ impl_state_store!({
         Ok(prev)
     }
 
-    async fn remove_media_content(&self, request: &MediaRequest) -> Result<()> {
-        let key = self
-            .encode_key(keys::MEDIA, (request.source.unique_key(), request.format.unique_key()));
-        let tx =
-            self.inner.transaction_on_one_with_mode(keys::MEDIA, IdbTransactionMode::Readwrite)?;
-
-        tx.object_store(keys::MEDIA)?.delete(&key)?;
-
-        tx.await.into_result().map_err(|e| e.into())
-    }
-
-    async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<()> {
-        let range = self.encode_to_range(keys::MEDIA, uri)?;
-        let tx =
-            self.inner.transaction_on_one_with_mode(keys::MEDIA, IdbTransactionMode::Readwrite)?;
-        let store = tx.object_store(keys::MEDIA)?;
-
-        for k in store.get_all_keys_with_key(&range)?.await?.iter() {
-            store.delete(&k)?;
-        }
-
-        tx.await.into_result().map_err(|e| e.into())
-    }
-
     async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
         let direct_stores = [keys::ROOM_INFOS];
 
@@ -1258,6 +1211,94 @@ impl_state_store!({
         tx.await.into_result().map_err(|e| e.into())
     }
 
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<()> {
+        let key = self.encode_key(keys::SEND_QUEUE, (room_id, &*request.transaction_id));
+        let range = self.encode_to_range(keys::SEND_QUEUE, room_id)?;
+
+        let tx = self
+            .inner
+            .transaction_on_one_with_mode(keys::SEND_QUEUE, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::SEND_QUEUE)?;
+
+        // Keep the position of the request if it's already queued, otherwise add it
+        // at the end of the queue.
+        let order = if let Some(existing) = store.get(&key)?.await? {
+            self.deserialize_event::<PersistedQueuedRequest>(&existing)?.order
+        } else {
+            let mut next_order = 0;
+            for value in store.get_all_with_key(&range)?.await?.iter() {
+                let persisted = self.deserialize_event::<PersistedQueuedRequest>(&value)?;
+                next_order = next_order.max(persisted.order + 1);
+            }
+            next_order
+        };
+
+        let persisted = PersistedQueuedRequest { room_id: room_id.to_owned(), order, request };
+        store.put_key_val(&key, &self.serialize_event(&persisted)?)?;
+
+        tx.await.into_result().map_err(|e| e.into())
+    }
+
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool> {
+        let key = self.encode_key(keys::SEND_QUEUE, (room_id, transaction_id));
+
+        let tx = self
+            .inner
+            .transaction_on_one_with_mode(keys::SEND_QUEUE, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::SEND_QUEUE)?;
+
+        let exists = store.get(&key)?.await?.is_some();
+        if exists {
+            store.delete(&key)?;
+        }
+
+        tx.await.into_result().map_err(IndexeddbStateStoreError::from)?;
+        Ok(exists)
+    }
+
+    async fn load_send_queue_requests(&self, room_id: &RoomId) -> Result<Vec<QueuedRequest>> {
+        let range = self.encode_to_range(keys::SEND_QUEUE, room_id)?;
+
+        let mut requests = self
+            .inner
+            .transaction_on_one_with_mode(keys::SEND_QUEUE, IdbTransactionMode::Readonly)?
+            .object_store(keys::SEND_QUEUE)?
+            .get_all_with_key(&range)?
+            .await?
+            .iter()
+            .map(|value| self.deserialize_event::<PersistedQueuedRequest>(&value))
+            .collect::<Result<Vec<_>>>()?;
+
+        requests.sort_by_key(|persisted| persisted.order);
+
+        Ok(requests.into_iter().map(|persisted| persisted.request).collect())
+    }
+
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>> {
+        let room_ids = self
+            .inner
+            .transaction_on_one_with_mode(keys::SEND_QUEUE, IdbTransactionMode::Readonly)?
+            .object_store(keys::SEND_QUEUE)?
+            .get_all()?
+            .await?
+            .iter()
+            .map(|value| {
+                self.deserialize_event::<PersistedQueuedRequest>(&value)
+                    .map(|persisted| persisted.room_id)
+            })
+            .collect::<Result<BTreeSet<_>>>()?;
+
+        Ok(room_ids.into_iter().collect())
+    }
+
     async fn get_user_ids(
         &self,
         room_id: &RoomId,
@@ -1286,6 +1327,17 @@ struct RoomMember {
     membership: MembershipState,
 }
 
+/// A request of a send queue, as it's saved in the store.
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedQueuedRequest {
+    /// The room of the send queue.
+    room_id: OwnedRoomId,
+    /// The position of the request in the queue.
+    order: u64,
+    /// The request.
+    request: QueuedRequest,
+}
+
 impl From<&SyncStateEvent<RoomMemberEventContent>> for RoomMember {
     fn from(event: &SyncStateEvent<RoomMemberEventContent>) -> Self {
         Self { user_id: event.state_key().clone(), membership: event.membership().clone() }
@@ -1313,7 +1365,7 @@ mod tests {
         Ok(IndexeddbStateStore::builder().name(db_name).build().await?)
     }
 
-    statestore_integration_tests!(with_media_tests);
+    statestore_integration_tests!();
 }
 
 #[cfg(all(test, target_arch = "wasm32"))]
@@ -1332,5 +1384,5 @@ mod encrypted_tests {
         Ok(IndexeddbStateStore::builder().name(db_name).passphrase(passphrase).build().await?)
     }
 
-    statestore_integration_tests!(with_media_tests);
+    statestore_integration_tests!();
 }
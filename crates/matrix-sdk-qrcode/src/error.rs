@@ -38,6 +38,9 @@ pub enum DecodingError {
     /// The QR code data doesn't contain valid ed25519 keys.
     #[error("the QR code contains invalid ed25519 keys: {0}")]
     Keys(#[from] vodozemac::KeyError),
+    /// The QR code data contains an invalid rendezvous URL.
+    #[error("the QR code contains an invalid rendezvous URL: {0}")]
+    Url(#[from] url::ParseError),
 }
 
 /// Error type describing errors that happen while QR data is being encoded.
@@ -50,4 +53,7 @@ pub enum EncodingError {
     /// Error encoding the given flow id, the flow id is too large.
     #[error("The verification flow id length can't be converted into a u16: {0}")]
     FlowId(#[from] std::num::TryFromIntError),
+    /// Error encoding a variable-length field, the field is too large.
+    #[error("A field of {0} bytes is too large to be encoded")]
+    Length(usize),
 }
@@ -17,10 +17,12 @@
 #![warn(missing_debug_implementations, missing_docs)]
 
 mod error;
+mod login;
 mod types;
 mod utils;
 
 pub use error::{DecodingError, EncodingError};
+pub use login::{QrCodeData, QrCodeModeData};
 pub use qrcode;
 pub use types::{
     QrVerificationData, SelfVerificationData, SelfVerificationNoMasterKey, VerificationData,
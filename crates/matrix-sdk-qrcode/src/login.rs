@@ -0,0 +1,261 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{Cursor, Read};
+
+use byteorder::{BigEndian, ReadBytesExt};
+use qrcode::{bits::Bits, EcLevel, QrCode, Version};
+use url::Url;
+use vodozemac::Curve25519PublicKey;
+
+use crate::{
+    error::{DecodingError, EncodingError},
+    utils::{HEADER, VERSION},
+};
+
+/// The mode of a QR code used to log in a new device, as defined in
+/// [MSC4108].
+///
+/// [MSC4108]: https://github.com/matrix-org/matrix-spec-proposals/pull/4108
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QrCodeModeData {
+    /// The QR code is displayed by the new device, which wants to log in.
+    Login,
+    /// The QR code is displayed by an existing device, which wants to log in
+    /// a new device.
+    Reciprocate {
+        /// The server name of the homeserver the new device should log in to.
+        server_name: String,
+    },
+}
+
+impl QrCodeModeData {
+    const LOGIN_MODE: u8 = 0x03;
+    const RECIPROCATE_MODE: u8 = 0x04;
+
+    fn mode(&self) -> u8 {
+        match self {
+            QrCodeModeData::Login => Self::LOGIN_MODE,
+            QrCodeModeData::Reciprocate { .. } => Self::RECIPROCATE_MODE,
+        }
+    }
+}
+
+/// The data of a QR code used to log in a new device, as defined in
+/// [MSC4108].
+///
+/// The QR code contains everything the scanning device needs to establish a
+/// secure channel with the displaying device, through a rendezvous server.
+///
+/// [MSC4108]: https://github.com/matrix-org/matrix-spec-proposals/pull/4108
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QrCodeData {
+    /// The ephemeral Curve25519 public key of the displaying device.
+    pub public_key: Curve25519PublicKey,
+    /// The URL of the rendezvous session used to exchange messages between
+    /// the two devices.
+    pub rendezvous_url: Url,
+    /// The mode of the QR code.
+    pub mode_data: QrCodeModeData,
+}
+
+impl QrCodeData {
+    /// The QR code version, the data format doesn't fit into the version used
+    /// for verification QR codes.
+    const QR_VERSION: Version = Version::Normal(12);
+
+    /// Parse the decoded payload of a QR code in byte slice form as a
+    /// `QrCodeData`.
+    ///
+    /// The byte slice consists of the following parts:
+    ///
+    /// * the ASCII string MATRIX
+    /// * one byte indicating the QR code version (must be 0x02)
+    /// * one byte indicating the QR code mode, one of the following values:
+    ///     * 0x03 the QR code is displayed by the new device
+    ///     * 0x04 the QR code is displayed by an existing device
+    /// * the ephemeral Curve25519 public key, as 32 bytes
+    /// * the rendezvous URL, encoded as:
+    ///     * two bytes in network byte order (big-endian) indicating the length
+    ///       in bytes of the URL as a UTF-8 string
+    ///     * the URL as a UTF-8 string
+    /// * if the mode is 0x04, the server name of the homeserver, encoded like
+    ///   the rendezvous URL
+    pub fn from_bytes(bytes: impl AsRef<[u8]>) -> Result<Self, DecodingError> {
+        let mut decoded = Cursor::new(bytes);
+
+        let mut header = [0u8; 6];
+        let mut public_key = [0u8; 32];
+
+        decoded.read_exact(&mut header)?;
+        let version = decoded.read_u8()?;
+        let mode = decoded.read_u8()?;
+
+        if header != HEADER {
+            return Err(DecodingError::Header);
+        } else if version != VERSION {
+            return Err(DecodingError::Version(version));
+        } else if mode != QrCodeModeData::LOGIN_MODE && mode != QrCodeModeData::RECIPROCATE_MODE {
+            return Err(DecodingError::Mode(mode));
+        }
+
+        decoded.read_exact(&mut public_key)?;
+        let public_key = Curve25519PublicKey::from_bytes(public_key);
+
+        let rendezvous_url = Url::parse(&read_string(&mut decoded)?)?;
+
+        let mode_data = if mode == QrCodeModeData::RECIPROCATE_MODE {
+            QrCodeModeData::Reciprocate { server_name: read_string(&mut decoded)? }
+        } else {
+            QrCodeModeData::Login
+        };
+
+        Ok(Self { public_key, rendezvous_url, mode_data })
+    }
+
+    /// Encode the `QrCodeData` into a vector of bytes that can be encoded as a
+    /// QR code.
+    ///
+    /// The encoding can fail if the rendezvous URL or the server name are too
+    /// long.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EncodingError> {
+        let mut data =
+            [HEADER, &[VERSION], &[self.mode_data.mode()], self.public_key.as_bytes().as_slice()]
+                .concat();
+
+        write_string(&mut data, self.rendezvous_url.as_str())?;
+
+        if let QrCodeModeData::Reciprocate { server_name } = &self.mode_data {
+            write_string(&mut data, server_name)?;
+        }
+
+        Ok(data)
+    }
+
+    /// Encode the `QrCodeData` into a `QrCode`.
+    ///
+    /// The encoding can fail if the data doesn't fit into a QR code.
+    pub fn to_qr_code(&self) -> Result<QrCode, EncodingError> {
+        let data = self.to_bytes()?;
+
+        // Like for the verification QR codes, we push the bytes without an ECI
+        // bit so decoders treat the whole payload as raw bytes.
+        let mut bits = Bits::new(Self::QR_VERSION);
+        bits.push_byte_data(&data)?;
+        bits.push_terminator(EcLevel::L)?;
+
+        Ok(QrCode::with_bits(bits, EcLevel::L)?)
+    }
+}
+
+impl TryFrom<&[u8]> for QrCodeData {
+    type Error = DecodingError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Self::from_bytes(value)
+    }
+}
+
+/// Read a UTF-8 string prefixed with its length as a big-endian `u16`.
+fn read_string(decoded: &mut Cursor<impl AsRef<[u8]>>) -> Result<String, DecodingError> {
+    let len = decoded.read_u16::<BigEndian>()?;
+    let mut bytes = vec![0; len.into()];
+    decoded.read_exact(&mut bytes)?;
+
+    Ok(String::from_utf8(bytes)?)
+}
+
+/// Write a UTF-8 string prefixed with its length as a big-endian `u16`.
+fn write_string(data: &mut Vec<u8>, string: &str) -> Result<(), EncodingError> {
+    let len: u16 = string.len().try_into().map_err(|_| EncodingError::Length(string.len()))?;
+
+    data.extend_from_slice(&len.to_be_bytes());
+    data.extend_from_slice(string.as_bytes());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+    use vodozemac::Curve25519PublicKey;
+
+    use super::{QrCodeData, QrCodeModeData};
+    use crate::DecodingError;
+
+    fn public_key() -> Curve25519PublicKey {
+        Curve25519PublicKey::from_bytes([7u8; 32])
+    }
+
+    #[test]
+    fn login_roundtrip() {
+        let data = QrCodeData {
+            public_key: public_key(),
+            rendezvous_url: Url::parse("https://rendezvous.lab.element.dev/abcdef").unwrap(),
+            mode_data: QrCodeModeData::Login,
+        };
+
+        let bytes = data.to_bytes().unwrap();
+        assert_eq!(&bytes[..8], b"MATRIX\x02\x03");
+
+        let decoded = QrCodeData::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, data);
+
+        data.to_qr_code().unwrap();
+    }
+
+    #[test]
+    fn reciprocate_roundtrip() {
+        let data = QrCodeData {
+            public_key: public_key(),
+            rendezvous_url: Url::parse("https://rendezvous.lab.element.dev/abcdef").unwrap(),
+            mode_data: QrCodeModeData::Reciprocate { server_name: "matrix.org".to_owned() },
+        };
+
+        let bytes = data.to_bytes().unwrap();
+        assert_eq!(&bytes[..8], b"MATRIX\x02\x04");
+        assert!(bytes.ends_with(b"\x00\x0amatrix.org"));
+
+        let decoded = QrCodeData::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, data);
+
+        data.to_qr_code().unwrap();
+    }
+
+    #[test]
+    fn decode_login_invalid_mode() {
+        let result = QrCodeData::from_bytes(b"MATRIX\x02\x02");
+        assert!(matches!(result, Err(DecodingError::Mode(2))));
+    }
+
+    #[test]
+    fn decode_login_missing_server_name() {
+        let mut bytes = b"MATRIX\x02\x04".to_vec();
+        bytes.extend_from_slice(&[7u8; 32]);
+        bytes.extend_from_slice(b"\x00\x13https://example.org");
+
+        let result = QrCodeData::from_bytes(bytes);
+        assert!(matches!(result, Err(DecodingError::Read(_))));
+    }
+
+    #[test]
+    fn decode_login_invalid_url() {
+        let mut bytes = b"MATRIX\x02\x03".to_vec();
+        bytes.extend_from_slice(&[7u8; 32]);
+        bytes.extend_from_slice(b"\x00\x03foo");
+
+        let result = QrCodeData::from_bytes(bytes);
+        assert!(matches!(result, Err(DecodingError::Url(_))));
+    }
+}
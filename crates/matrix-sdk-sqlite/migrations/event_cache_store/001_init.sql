@@ -0,0 +1,26 @@
+-- basic kv data like the database version and store cipher
+CREATE TABLE "kv" (
+    "key" TEXT PRIMARY KEY NOT NULL,
+    "value" BLOB NOT NULL
+);
+
+-- the chunks of the history of each room, linked to their previous and next
+-- chunks; a chunk is either a gap, with the encrypted token to back-paginate
+-- into it, or a sequence of events, stored in the "event_chunk" table
+CREATE TABLE "linked_chunk" (
+    "id" INTEGER NOT NULL,
+    "room_id" BLOB NOT NULL,
+    "previous" INTEGER,
+    "next" INTEGER,
+    "gap" BLOB,
+    PRIMARY KEY ("room_id", "id")
+);
+
+-- the events of the chunks of events, by position within their chunk
+CREATE TABLE "event_chunk" (
+    "room_id" BLOB NOT NULL,
+    "chunk_id" INTEGER NOT NULL,
+    "position" INTEGER NOT NULL,
+    "data" BLOB NOT NULL
+);
+CREATE INDEX "event_chunk_position" ON "event_chunk" ("room_id", "chunk_id", "position");
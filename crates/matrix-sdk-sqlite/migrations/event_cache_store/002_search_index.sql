@@ -0,0 +1,21 @@
+-- the events of the local search index; the room IDs, event IDs and senders
+-- are hashed, and the data is encrypted with the store cipher, but the
+-- timestamps are kept in clear to query date ranges
+CREATE TABLE "search_event" (
+    "room_id" BLOB NOT NULL,
+    "event_id" BLOB NOT NULL,
+    "sender" BLOB NOT NULL,
+    "origin_server_ts" INTEGER NOT NULL,
+    "data" BLOB NOT NULL,
+    PRIMARY KEY ("room_id", "event_id")
+);
+CREATE INDEX "search_event_origin_server_ts" ON "search_event" ("origin_server_ts");
+
+-- the hashed words of each event of the local search index
+CREATE TABLE "search_token" (
+    "token" BLOB NOT NULL,
+    "room_id" BLOB NOT NULL,
+    "event_id" BLOB NOT NULL,
+    PRIMARY KEY ("token", "room_id", "event_id")
+);
+CREATE INDEX "search_token_event" ON "search_token" ("room_id", "event_id");
@@ -0,0 +1,19 @@
+-- basic kv data like the database version and store cipher
+CREATE TABLE "kv" (
+    "key" TEXT PRIMARY KEY NOT NULL,
+    "value" BLOB NOT NULL
+);
+
+-- the media files and the metadata used to decide which ones to evict; the
+-- key and URI are kept in clear in the encrypted metadata
+CREATE TABLE "media" (
+    "key" BLOB PRIMARY KEY NOT NULL,
+    "uri" BLOB NOT NULL,
+    "size" INTEGER NOT NULL,
+    "added_at" INTEGER NOT NULL,
+    "last_accessed_at" INTEGER NOT NULL,
+    "pinned" BOOLEAN NOT NULL,
+    "metadata" BLOB NOT NULL,
+    "data" BLOB NOT NULL
+);
+CREATE INDEX "media_uri" ON "media" ("uri");
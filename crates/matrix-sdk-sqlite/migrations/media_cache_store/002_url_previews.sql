@@ -0,0 +1,7 @@
+-- the URL previews, by URL and timestamp
+CREATE TABLE "url_preview" (
+    "key" BLOB PRIMARY KEY NOT NULL,
+    "size" INTEGER NOT NULL,
+    "added_at" INTEGER NOT NULL,
+    "data" BLOB NOT NULL
+);
@@ -0,0 +1,13 @@
+-- the requests of the send queues, one row per request
+--
+-- the autoincremented id keeps the order in which the requests were queued
+CREATE TABLE "send_queue_request" (
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "room_id" BLOB NOT NULL,
+    -- the encrypted room ID, to list the rooms with unsent requests
+    "room_id_val" BLOB NOT NULL,
+    "transaction_id" BLOB NOT NULL,
+    "data" BLOB NOT NULL
+);
+CREATE UNIQUE INDEX "send_queue_request_room_id_transaction_id"
+    ON "send_queue_request" ("room_id", "transaction_id");
@@ -0,0 +1,3 @@
+-- the media are now cached in the media cache store, so the media table of
+-- the state store isn't used anymore
+DROP TABLE "media";
@@ -13,6 +13,10 @@
 // limitations under the License.
 
 use deadpool_sqlite::{CreatePoolError, PoolError};
+#[cfg(feature = "event-cache-store")]
+use matrix_sdk_base::event_cache_store::EventCacheStoreError;
+#[cfg(feature = "media-cache-store")]
+use matrix_sdk_base::media_cache_store::MediaCacheStoreError;
 #[cfg(feature = "state-store")]
 use matrix_sdk_base::store::StoreError as StateStoreError;
 #[cfg(feature = "crypto-store")]
@@ -136,4 +140,26 @@ impl From<Error> for StateStoreError {
     }
 }
 
+#[cfg(feature = "event-cache-store")]
+impl From<Error> for EventCacheStoreError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Json(e) => EventCacheStoreError::Json(e),
+            Error::Encryption(e) => EventCacheStoreError::Encryption(e),
+            e => EventCacheStoreError::backend(e),
+        }
+    }
+}
+
+#[cfg(feature = "media-cache-store")]
+impl From<Error> for MediaCacheStoreError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Json(e) => MediaCacheStoreError::Json(e),
+            Error::Encryption(e) => MediaCacheStoreError::Encryption(e),
+            e => MediaCacheStoreError::backend(e),
+        }
+    }
+}
+
 pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
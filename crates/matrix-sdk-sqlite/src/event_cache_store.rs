@@ -0,0 +1,632 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use deadpool_sqlite::{Object as SqliteConn, Pool as SqlitePool, Runtime};
+use matrix_sdk_base::{
+    event_cache_store::{
+        search_index_tokens, EventCacheStore, Gap, RoomEventsRawChunk, RoomEventsUpdate,
+        SearchIndexEntry, SearchIndexQuery,
+    },
+    linked_chunk::{ChunkContent, ChunkIdentifier, RawChunk, Update},
+};
+use matrix_sdk_store_encryption::StoreCipher;
+use ruma::{EventId, RoomId};
+use rusqlite::{types::Value, OptionalExtension, Transaction};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::fs;
+use tracing::debug;
+
+use crate::{
+    error::{Error, Result},
+    get_or_create_store_cipher,
+    utils::{load_db_version, repeat_vars, Key, SqliteObjectExt},
+    OpenStoreError, SqliteObjectStoreExt,
+};
+
+mod keys {
+    // Tables
+    pub const LINKED_CHUNK: &str = "linked_chunk";
+    pub const SEARCH_EVENT: &str = "search_event";
+    pub const SEARCH_TOKEN: &str = "search_token";
+}
+
+const DATABASE_VERSION: u8 = 2;
+
+/// A sqlite based event cache store.
+#[derive(Clone)]
+pub struct SqliteEventCacheStore {
+    store_cipher: Option<Arc<StoreCipher>>,
+    path: Option<PathBuf>,
+    pool: SqlitePool,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for SqliteEventCacheStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            f.debug_struct("SqliteEventCacheStore").field("path", &path).finish()
+        } else {
+            f.debug_struct("SqliteEventCacheStore").field("path", &"memory store").finish()
+        }
+    }
+}
+
+impl SqliteEventCacheStore {
+    /// Open the sqlite-based event cache store at the given path using the
+    /// given passphrase to encrypt private data.
+    pub async fn open(
+        path: impl AsRef<Path>,
+        passphrase: Option<&str>,
+    ) -> Result<Self, OpenStoreError> {
+        let path = path.as_ref();
+        let pool = create_pool(path).await?;
+        let mut this = Self::open_with_pool(pool, passphrase).await?;
+        this.path = Some(path.to_owned());
+
+        Ok(this)
+    }
+
+    /// Create a sqlite-based event cache store using the given sqlite database
+    /// pool. The given passphrase will be used to encrypt private data.
+    pub async fn open_with_pool(
+        pool: SqlitePool,
+        passphrase: Option<&str>,
+    ) -> Result<Self, OpenStoreError> {
+        let conn = pool.get().await?;
+        let version = load_db_version(&conn).await?;
+        run_migrations(&conn, version).await?;
+
+        let store_cipher = match passphrase {
+            Some(p) => Some(Arc::new(get_or_create_store_cipher(p, &conn).await?)),
+            None => None,
+        };
+
+        Ok(Self { store_cipher, path: None, pool })
+    }
+
+    fn encode_value(&self, value: Vec<u8>) -> Result<Vec<u8>> {
+        if let Some(key) = &self.store_cipher {
+            let encrypted = key.encrypt_value_data(value)?;
+            Ok(rmp_serde::to_vec_named(&encrypted)?)
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn serialize_json(&self, value: &impl Serialize) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(value)?;
+        self.encode_value(serialized)
+    }
+
+    fn decode_value<'a>(&self, value: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        if let Some(key) = &self.store_cipher {
+            let encrypted = rmp_serde::from_slice(value)?;
+            let decrypted = key.decrypt_value_data(encrypted)?;
+            Ok(Cow::Owned(decrypted))
+        } else {
+            Ok(Cow::Borrowed(value))
+        }
+    }
+
+    fn deserialize_json<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        let decoded = self.decode_value(data)?;
+        Ok(serde_json::from_slice(&decoded)?)
+    }
+
+    fn encode_key(&self, table_name: &str, key: impl AsRef<[u8]>) -> Key {
+        let bytes = key.as_ref();
+        if let Some(store_cipher) = &self.store_cipher {
+            Key::Hashed(store_cipher.hash_key(table_name, bytes))
+        } else {
+            Key::Plain(bytes.to_owned())
+        }
+    }
+
+    async fn acquire(&self) -> Result<SqliteConn> {
+        Ok(self.pool.get().await?)
+    }
+}
+
+async fn create_pool(path: &Path) -> Result<SqlitePool, OpenStoreError> {
+    fs::create_dir_all(path).await.map_err(OpenStoreError::CreateDir)?;
+    let cfg = deadpool_sqlite::Config::new(path.join("matrix-sdk-event-cache.sqlite3"));
+    Ok(cfg.create_pool(Runtime::Tokio1)?)
+}
+
+/// Run migrations for the given version of the database.
+async fn run_migrations(conn: &SqliteConn, version: u8) -> Result<()> {
+    if version == 0 {
+        debug!("Creating database");
+    } else if version < DATABASE_VERSION {
+        debug!(version, new_version = DATABASE_VERSION, "Upgrading database");
+    } else {
+        return Ok(());
+    }
+
+    if version < 1 {
+        // First turn on WAL mode, this can't be done in the transaction, it fails with
+        // the error message: "cannot change into wal mode from within a transaction".
+        conn.execute_batch("PRAGMA journal_mode = wal;").await?;
+        conn.with_transaction(|txn| {
+            txn.execute_batch(include_str!("../migrations/event_cache_store/001_init.sql"))
+        })
+        .await?;
+    }
+
+    if version < 2 {
+        conn.with_transaction(|txn| {
+            txn.execute_batch(include_str!("../migrations/event_cache_store/002_search_index.sql"))
+        })
+        .await?;
+    }
+
+    conn.set_kv("version", vec![DATABASE_VERSION]).await?;
+
+    Ok(())
+}
+
+/// An [`Update`] of a linked chunk, with its data already encrypted.
+enum SqlUpdate {
+    NewChunk {
+        previous: Option<ChunkIdentifier>,
+        new: ChunkIdentifier,
+        next: Option<ChunkIdentifier>,
+        gap: Option<Vec<u8>>,
+    },
+    RemoveChunk(ChunkIdentifier),
+    PushItems {
+        chunk: ChunkIdentifier,
+        index: usize,
+        items: Vec<Vec<u8>>,
+    },
+    RemoveItem {
+        chunk: ChunkIdentifier,
+        index: usize,
+    },
+    Clear,
+}
+
+impl SqlUpdate {
+    fn apply(self, txn: &Transaction<'_>, room_id: &Key) -> rusqlite::Result<()> {
+        match self {
+            Self::NewChunk { previous, new, next, gap } => {
+                let (previous, new, next) =
+                    (previous.map(|id| id.index()), new.index(), next.map(|id| id.index()));
+
+                txn.execute(
+                    "INSERT INTO linked_chunk (id, room_id, previous, next, gap) \
+                     VALUES (?, ?, ?, ?, ?)",
+                    (new, room_id, previous, next, gap),
+                )?;
+                link_chunks(txn, room_id, previous, Some(new))?;
+                link_chunks(txn, room_id, Some(new), next)?;
+            }
+
+            Self::RemoveChunk(identifier) => {
+                let identifier = identifier.index();
+
+                let (previous, next): (Option<u64>, Option<u64>) = txn.query_row(
+                    "SELECT previous, next FROM linked_chunk WHERE room_id = ? AND id = ?",
+                    (room_id, identifier),
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )?;
+                link_chunks(txn, room_id, previous, next)?;
+
+                txn.execute(
+                    "DELETE FROM event_chunk WHERE room_id = ? AND chunk_id = ?",
+                    (room_id, identifier),
+                )?;
+                txn.execute(
+                    "DELETE FROM linked_chunk WHERE room_id = ? AND id = ?",
+                    (room_id, identifier),
+                )?;
+            }
+
+            Self::PushItems { chunk, index, items } => {
+                let chunk = chunk.index();
+
+                // Make room for the new items, if they are not pushed at the end of the
+                // chunk.
+                txn.execute(
+                    "UPDATE event_chunk SET position = position + ? \
+                     WHERE room_id = ? AND chunk_id = ? AND position >= ?",
+                    (items.len(), room_id, chunk, index),
+                )?;
+
+                let mut stmt = txn.prepare_cached(
+                    "INSERT INTO event_chunk (room_id, chunk_id, position, data) \
+                     VALUES (?, ?, ?, ?)",
+                )?;
+                for (offset, data) in items.into_iter().enumerate() {
+                    stmt.execute((room_id, chunk, index + offset, data))?;
+                }
+            }
+
+            Self::RemoveItem { chunk, index } => {
+                let chunk = chunk.index();
+
+                txn.execute(
+                    "DELETE FROM event_chunk WHERE room_id = ? AND chunk_id = ? AND position = ?",
+                    (room_id, chunk, index),
+                )?;
+                txn.execute(
+                    "UPDATE event_chunk SET position = position - 1 \
+                     WHERE room_id = ? AND chunk_id = ? AND position > ?",
+                    (room_id, chunk, index),
+                )?;
+            }
+
+            Self::Clear => {
+                txn.execute("DELETE FROM event_chunk WHERE room_id = ?", (room_id,))?;
+                txn.execute("DELETE FROM linked_chunk WHERE room_id = ?", (room_id,))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Link the given chunks of a room, so `next` follows `previous`.
+fn link_chunks(
+    txn: &Transaction<'_>,
+    room_id: &Key,
+    previous: Option<u64>,
+    next: Option<u64>,
+) -> rusqlite::Result<()> {
+    if let Some(previous) = previous {
+        txn.execute(
+            "UPDATE linked_chunk SET next = ? WHERE room_id = ? AND id = ?",
+            (next, room_id, previous),
+        )?;
+    }
+
+    if let Some(next) = next {
+        txn.execute(
+            "UPDATE linked_chunk SET previous = ? WHERE room_id = ? AND id = ?",
+            (previous, room_id, next),
+        )?;
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl EventCacheStore for SqliteEventCacheStore {
+    type Error = Error;
+
+    async fn handle_linked_chunk_updates(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<RoomEventsUpdate>,
+    ) -> Result<()> {
+        let room_id = self.encode_key(keys::LINKED_CHUNK, room_id);
+
+        // Encrypt the data before starting the transaction.
+        let updates = updates
+            .into_iter()
+            .map(|update| {
+                Ok(match update {
+                    Update::NewItemsChunk { previous, new, next } => {
+                        SqlUpdate::NewChunk { previous, new, next, gap: None }
+                    }
+                    Update::NewGapChunk { previous, new, next, gap } => {
+                        let gap = Some(self.serialize_json(&gap)?);
+                        SqlUpdate::NewChunk { previous, new, next, gap }
+                    }
+                    Update::RemoveChunk(identifier) => SqlUpdate::RemoveChunk(identifier),
+                    Update::PushItems { at, items } => SqlUpdate::PushItems {
+                        chunk: at.chunk_identifier(),
+                        index: at.index(),
+                        items: items
+                            .iter()
+                            .map(|event| self.serialize_json(event))
+                            .collect::<Result<_>>()?,
+                    },
+                    Update::RemoveItem { at } => {
+                        SqlUpdate::RemoveItem { chunk: at.chunk_identifier(), index: at.index() }
+                    }
+                    Update::Clear => SqlUpdate::Clear,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.acquire()
+            .await?
+            .with_transaction(move |txn| {
+                for update in updates {
+                    update.apply(txn, &room_id)?;
+                }
+
+                Result::<_, Error>::Ok(())
+            })
+            .await
+    }
+
+    async fn reload_linked_chunk(&self, room_id: &RoomId) -> Result<Vec<RoomEventsRawChunk>> {
+        let room_id = self.encode_key(keys::LINKED_CHUNK, room_id);
+
+        let (chunks, events) = self
+            .acquire()
+            .await?
+            .with_transaction(move |txn| {
+                let chunks = txn
+                    .prepare("SELECT id, previous, next, gap FROM linked_chunk WHERE room_id = ?")?
+                    .query_map((&room_id,), |row| {
+                        Ok((
+                            row.get::<_, u64>(0)?,
+                            row.get::<_, Option<u64>>(1)?,
+                            row.get::<_, Option<u64>>(2)?,
+                            row.get::<_, Option<Vec<u8>>>(3)?,
+                        ))
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+                let events = txn
+                    .prepare(
+                        "SELECT chunk_id, data FROM event_chunk WHERE room_id = ? \
+                         ORDER BY chunk_id, position",
+                    )?
+                    .query_map((&room_id,), |row| {
+                        Ok((row.get::<_, u64>(0)?, row.get::<_, Vec<u8>>(1)?))
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+                Result::<_, Error>::Ok((chunks, events))
+            })
+            .await?;
+
+        let mut events_by_chunk = BTreeMap::<u64, Vec<_>>::new();
+        for (chunk_id, data) in events {
+            events_by_chunk.entry(chunk_id).or_default().push(self.deserialize_json(&data)?);
+        }
+
+        chunks
+            .into_iter()
+            .map(|(id, previous, next, gap)| {
+                let content = match gap {
+                    Some(gap) => ChunkContent::Gap(self.deserialize_json::<Gap>(&gap)?),
+                    None => ChunkContent::Items(events_by_chunk.remove(&id).unwrap_or_default()),
+                };
+
+                Ok(RawChunk {
+                    identifier: ChunkIdentifier::new(id),
+                    previous: previous.map(ChunkIdentifier::new),
+                    next: next.map(ChunkIdentifier::new),
+                    content,
+                })
+            })
+            .collect()
+    }
+
+    async fn index_events(&self, entries: Vec<SearchIndexEntry>) -> Result<()> {
+        let entries = entries
+            .iter()
+            .map(|entry| {
+                let tokens = search_index_tokens(&entry.body)
+                    .into_iter()
+                    .map(|token| self.encode_key(keys::SEARCH_TOKEN, token))
+                    .collect::<Vec<_>>();
+
+                Ok((
+                    self.encode_key(keys::SEARCH_EVENT, &entry.room_id),
+                    self.encode_key(keys::SEARCH_EVENT, &entry.event_id),
+                    self.encode_key(keys::SEARCH_EVENT, &entry.sender),
+                    i64::from(entry.origin_server_ts.0),
+                    self.serialize_json(entry)?,
+                    tokens,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.acquire()
+            .await?
+            .with_transaction(move |txn| {
+                let mut delete_tokens = txn.prepare_cached(
+                    "DELETE FROM search_token WHERE room_id = ? AND event_id = ?",
+                )?;
+                let mut insert_event = txn.prepare_cached(
+                    "INSERT OR REPLACE INTO search_event \
+                     (room_id, event_id, sender, origin_server_ts, data) VALUES (?, ?, ?, ?, ?)",
+                )?;
+                let mut insert_token = txn.prepare_cached(
+                    "INSERT INTO search_token (token, room_id, event_id) VALUES (?, ?, ?)",
+                )?;
+
+                for (room_id, event_id, sender, origin_server_ts, data, tokens) in entries {
+                    delete_tokens.execute((&room_id, &event_id))?;
+                    insert_event.execute((&room_id, &event_id, sender, origin_server_ts, data))?;
+                    for token in tokens {
+                        insert_token.execute((token, &room_id, &event_id))?;
+                    }
+                }
+
+                Result::<_, Error>::Ok(())
+            })
+            .await
+    }
+
+    async fn get_indexed_event(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Result<Option<SearchIndexEntry>> {
+        let room_id = self.encode_key(keys::SEARCH_EVENT, room_id);
+        let event_id = self.encode_key(keys::SEARCH_EVENT, event_id);
+
+        let data: Option<Vec<u8>> = self
+            .acquire()
+            .await?
+            .query_row(
+                "SELECT data FROM search_event WHERE room_id = ? AND event_id = ?",
+                (room_id, event_id),
+                |row| row.get(0),
+            )
+            .await
+            .optional()?;
+
+        data.map(|data| self.deserialize_json(&data)).transpose()
+    }
+
+    async fn remove_indexed_event(&self, room_id: &RoomId, event_id: &EventId) -> Result<()> {
+        let room_id = self.encode_key(keys::SEARCH_EVENT, room_id);
+        let event_id = self.encode_key(keys::SEARCH_EVENT, event_id);
+
+        self.acquire()
+            .await?
+            .with_transaction(move |txn| {
+                txn.execute(
+                    "DELETE FROM search_token WHERE room_id = ? AND event_id = ?",
+                    (&room_id, &event_id),
+                )?;
+                txn.execute(
+                    "DELETE FROM search_event WHERE room_id = ? AND event_id = ?",
+                    (&room_id, &event_id),
+                )?;
+
+                Result::<_, Error>::Ok(())
+            })
+            .await
+    }
+
+    async fn search_index(&self, query: &SearchIndexQuery) -> Result<Vec<SearchIndexEntry>> {
+        let mut sql = "SELECT data FROM search_event AS e WHERE 1".to_owned();
+        let mut params = Vec::new();
+
+        let tokens = search_index_tokens(&query.text);
+        if !tokens.is_empty() {
+            // Tokens are unique per event, so the event contains all the tokens if
+            // the number of matching tokens is the number of tokens.
+            sql.push_str(&format!(
+                " AND (SELECT COUNT(*) FROM search_token AS t \
+                 WHERE t.room_id = e.room_id AND t.event_id = e.event_id \
+                 AND t.token IN ({})) = ?",
+                repeat_vars(tokens.len())
+            ));
+            params.extend(
+                tokens
+                    .iter()
+                    .map(|token| Value::Blob(self.encode_key(keys::SEARCH_TOKEN, token).to_vec())),
+            );
+            params.push(Value::Integer(tokens.len() as i64));
+        }
+
+        if let Some(room_id) = &query.room_id {
+            sql.push_str(" AND e.room_id = ?");
+            params.push(Value::Blob(self.encode_key(keys::SEARCH_EVENT, room_id).to_vec()));
+        }
+        if let Some(sender) = &query.sender {
+            sql.push_str(" AND e.sender = ?");
+            params.push(Value::Blob(self.encode_key(keys::SEARCH_EVENT, sender).to_vec()));
+        }
+        if let Some(since) = query.since {
+            sql.push_str(" AND e.origin_server_ts >= ?");
+            params.push(Value::Integer(since.0.into()));
+        }
+        if let Some(until) = query.until {
+            sql.push_str(" AND e.origin_server_ts <= ?");
+            params.push(Value::Integer(until.0.into()));
+        }
+
+        sql.push_str(" ORDER BY e.origin_server_ts DESC LIMIT ?");
+        params.push(Value::Integer(query.limit.try_into().unwrap_or(i64::MAX)));
+
+        let results: Vec<Vec<u8>> = self
+            .acquire()
+            .await?
+            .prepare(sql, move |mut stmt| {
+                stmt.query_map(rusqlite::params_from_iter(params), |row| row.get(0))?.collect()
+            })
+            .await?;
+
+        results.iter().map(|data| self.deserialize_json(data)).collect()
+    }
+
+    async fn clear_search_index(&self) -> Result<()> {
+        self.acquire()
+            .await?
+            .with_transaction(|txn| {
+                txn.execute("DELETE FROM search_token", ())?;
+                txn.execute("DELETE FROM search_event", ())?;
+
+                Result::<_, Error>::Ok(())
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_base::{
+        event_cache_store::{EventCacheStore, EventCacheStoreError},
+        event_cache_store_integration_tests,
+    };
+    use once_cell::sync::Lazy;
+    use tempfile::{tempdir, TempDir};
+
+    use super::SqliteEventCacheStore;
+
+    static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    async fn get_event_cache_store() -> Result<impl EventCacheStore, EventCacheStoreError> {
+        let name = NUM.fetch_add(1, SeqCst).to_string();
+        let tmpdir_path = TMP_DIR.path().join(name);
+
+        Ok(SqliteEventCacheStore::open(tmpdir_path.to_str().unwrap(), None).await.unwrap())
+    }
+
+    event_cache_store_integration_tests!();
+}
+
+#[cfg(test)]
+mod encrypted_tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_base::{
+        event_cache_store::{EventCacheStore, EventCacheStoreError},
+        event_cache_store_integration_tests,
+    };
+    use once_cell::sync::Lazy;
+    use tempfile::{tempdir, TempDir};
+
+    use super::SqliteEventCacheStore;
+
+    static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    async fn get_event_cache_store() -> Result<impl EventCacheStore, EventCacheStoreError> {
+        let name = NUM.fetch_add(1, SeqCst).to_string();
+        let tmpdir_path = TMP_DIR.path().join(name);
+
+        Ok(SqliteEventCacheStore::open(
+            tmpdir_path.to_str().unwrap(),
+            Some("default_test_password"),
+        )
+        .await
+        .unwrap())
+    }
+
+    event_cache_store_integration_tests!();
+}
@@ -12,7 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 #![cfg_attr(
-    not(any(feature = "state-store", feature = "crypto-store")),
+    not(any(
+        feature = "state-store",
+        feature = "crypto-store",
+        feature = "event-cache-store",
+        feature = "media-cache-store"
+    )),
     allow(dead_code, unused_imports)
 )]
 
@@ -22,6 +27,10 @@ use matrix_sdk_store_encryption::StoreCipher;
 #[cfg(feature = "crypto-store")]
 mod crypto_store;
 mod error;
+#[cfg(feature = "event-cache-store")]
+mod event_cache_store;
+#[cfg(feature = "media-cache-store")]
+mod media_cache_store;
 #[cfg(feature = "state-store")]
 mod state_store;
 mod utils;
@@ -29,6 +38,10 @@ mod utils;
 #[cfg(feature = "crypto-store")]
 pub use self::crypto_store::SqliteCryptoStore;
 pub use self::error::OpenStoreError;
+#[cfg(feature = "event-cache-store")]
+pub use self::event_cache_store::SqliteEventCacheStore;
+#[cfg(feature = "media-cache-store")]
+pub use self::media_cache_store::SqliteMediaCacheStore;
 #[cfg(feature = "state-store")]
 pub use self::state_store::SqliteStateStore;
 use self::utils::SqliteObjectStoreExt;
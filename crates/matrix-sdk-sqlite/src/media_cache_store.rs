@@ -0,0 +1,465 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    borrow::Cow,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use deadpool_sqlite::{Object as SqliteConn, Pool as SqlitePool, Runtime};
+use matrix_sdk_base::{
+    media::{MediaRequest, UniqueKey},
+    media_cache_store::{MediaCacheEntry, MediaCacheStore},
+};
+use matrix_sdk_store_encryption::StoreCipher;
+use ruma::{MilliSecondsSinceUnixEpoch, MxcUri, OwnedMxcUri, UInt};
+use rusqlite::OptionalExtension;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::fs;
+use tracing::debug;
+
+use crate::{
+    error::{Error, Result},
+    get_or_create_store_cipher,
+    utils::{load_db_version, Key, SqliteObjectExt},
+    OpenStoreError, SqliteObjectStoreExt,
+};
+
+mod keys {
+    // Tables
+    pub const MEDIA: &str = "media";
+    pub const URL_PREVIEW: &str = "url_preview";
+}
+
+const DATABASE_VERSION: u8 = 2;
+
+/// A sqlite based media cache store.
+#[derive(Clone)]
+pub struct SqliteMediaCacheStore {
+    store_cipher: Option<Arc<StoreCipher>>,
+    path: Option<PathBuf>,
+    pool: SqlitePool,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for SqliteMediaCacheStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            f.debug_struct("SqliteMediaCacheStore").field("path", &path).finish()
+        } else {
+            f.debug_struct("SqliteMediaCacheStore").field("path", &"memory store").finish()
+        }
+    }
+}
+
+impl SqliteMediaCacheStore {
+    /// Open the sqlite-based media cache store at the given path using the
+    /// given passphrase to encrypt private data.
+    pub async fn open(
+        path: impl AsRef<Path>,
+        passphrase: Option<&str>,
+    ) -> Result<Self, OpenStoreError> {
+        let path = path.as_ref();
+        let pool = create_pool(path).await?;
+        let mut this = Self::open_with_pool(pool, passphrase).await?;
+        this.path = Some(path.to_owned());
+
+        Ok(this)
+    }
+
+    /// Create a sqlite-based media cache store using the given sqlite database
+    /// pool. The given passphrase will be used to encrypt private data.
+    pub async fn open_with_pool(
+        pool: SqlitePool,
+        passphrase: Option<&str>,
+    ) -> Result<Self, OpenStoreError> {
+        let conn = pool.get().await?;
+        let version = load_db_version(&conn).await?;
+        run_migrations(&conn, version).await?;
+
+        let store_cipher = match passphrase {
+            Some(p) => Some(Arc::new(get_or_create_store_cipher(p, &conn).await?)),
+            None => None,
+        };
+
+        Ok(Self { store_cipher, path: None, pool })
+    }
+
+    fn encode_value(&self, value: Vec<u8>) -> Result<Vec<u8>> {
+        if let Some(key) = &self.store_cipher {
+            let encrypted = key.encrypt_value_data(value)?;
+            Ok(rmp_serde::to_vec_named(&encrypted)?)
+        } else {
+            Ok(value)
+        }
+    }
+
+    fn serialize_json(&self, value: &impl Serialize) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(value)?;
+        self.encode_value(serialized)
+    }
+
+    fn decode_value<'a>(&self, value: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        if let Some(key) = &self.store_cipher {
+            let encrypted = rmp_serde::from_slice(value)?;
+            let decrypted = key.decrypt_value_data(encrypted)?;
+            Ok(Cow::Owned(decrypted))
+        } else {
+            Ok(Cow::Borrowed(value))
+        }
+    }
+
+    fn deserialize_json<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        let decoded = self.decode_value(data)?;
+        Ok(serde_json::from_slice(&decoded)?)
+    }
+
+    fn encode_key(&self, table_name: &str, key: impl AsRef<[u8]>) -> Key {
+        let bytes = key.as_ref();
+        if let Some(store_cipher) = &self.store_cipher {
+            Key::Hashed(store_cipher.hash_key(table_name, bytes))
+        } else {
+            Key::Plain(bytes.to_owned())
+        }
+    }
+
+    async fn acquire(&self) -> Result<SqliteConn> {
+        Ok(self.pool.get().await?)
+    }
+}
+
+async fn create_pool(path: &Path) -> Result<SqlitePool, OpenStoreError> {
+    fs::create_dir_all(path).await.map_err(OpenStoreError::CreateDir)?;
+    let cfg = deadpool_sqlite::Config::new(path.join("matrix-sdk-media-cache.sqlite3"));
+    Ok(cfg.create_pool(Runtime::Tokio1)?)
+}
+
+/// Run migrations for the given version of the database.
+async fn run_migrations(conn: &SqliteConn, version: u8) -> Result<()> {
+    if version == 0 {
+        debug!("Creating database");
+    } else if version < DATABASE_VERSION {
+        debug!(version, new_version = DATABASE_VERSION, "Upgrading database");
+    } else {
+        return Ok(());
+    }
+
+    if version < 1 {
+        // First turn on WAL mode, this can't be done in the transaction, it fails with
+        // the error message: "cannot change into wal mode from within a transaction".
+        conn.execute_batch("PRAGMA journal_mode = wal;").await?;
+        conn.with_transaction(|txn| {
+            txn.execute_batch(include_str!("../migrations/media_cache_store/001_init.sql"))
+        })
+        .await?;
+    }
+
+    if version < 2 {
+        conn.with_transaction(|txn| {
+            txn.execute_batch(include_str!("../migrations/media_cache_store/002_url_previews.sql"))
+        })
+        .await?;
+    }
+
+    conn.set_kv("version", vec![DATABASE_VERSION]).await?;
+
+    Ok(())
+}
+
+/// The key of a URL preview, before it is encoded.
+fn url_preview_key(url: &str, ts: Option<MilliSecondsSinceUnixEpoch>) -> Result<Vec<u8>> {
+    // Serialize both parts, so the key can't be ambiguous whatever the URL.
+    Ok(serde_json::to_vec(&(url, ts))?)
+}
+
+/// Convert a timestamp to its representation in the database.
+fn encode_ts(ts: MilliSecondsSinceUnixEpoch) -> i64 {
+    ts.0.into()
+}
+
+/// Convert a timestamp from its representation in the database.
+fn decode_ts(ts: i64) -> MilliSecondsSinceUnixEpoch {
+    MilliSecondsSinceUnixEpoch(UInt::try_from(ts).unwrap_or_default())
+}
+
+#[async_trait]
+impl MediaCacheStore for SqliteMediaCacheStore {
+    type Error = Error;
+
+    async fn add_media_content(
+        &self,
+        request: &MediaRequest,
+        content: Vec<u8>,
+        pinned: bool,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<()> {
+        let unique_key = request.unique_key();
+        let uri = request.uri();
+
+        let key = self.encode_key(keys::MEDIA, &unique_key);
+        let encoded_uri = self.encode_key(keys::MEDIA, uri);
+        let size = i64::try_from(content.len()).unwrap_or(i64::MAX);
+        let now = encode_ts(now);
+        let metadata = self.serialize_json(&(unique_key, uri))?;
+        let data = self.encode_value(content)?;
+
+        self.acquire()
+            .await?
+            .execute(
+                "INSERT INTO media \
+                 (key, uri, size, added_at, last_accessed_at, pinned, metadata, data) \
+                 VALUES (?1, ?2, ?3, ?4, ?4, ?5, ?6, ?7) \
+                 ON CONFLICT (key) DO UPDATE SET \
+                 size = excluded.size, added_at = excluded.added_at, \
+                 last_accessed_at = excluded.last_accessed_at, \
+                 pinned = pinned OR excluded.pinned, data = excluded.data",
+                (key, encoded_uri, size, now, pinned, metadata, data),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_media_content(
+        &self,
+        request: &MediaRequest,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<Option<Vec<u8>>> {
+        let key = self.encode_key(keys::MEDIA, request.unique_key());
+        let now = encode_ts(now);
+
+        let data: Option<Vec<u8>> = self
+            .acquire()
+            .await?
+            .with_transaction(move |txn| {
+                txn.execute("UPDATE media SET last_accessed_at = ? WHERE key = ?", (now, &key))?;
+                txn.query_row("SELECT data FROM media WHERE key = ?", (&key,), |row| row.get(0))
+                    .optional()
+            })
+            .await?;
+
+        data.map(|data| Ok(self.decode_value(&data)?.into_owned())).transpose()
+    }
+
+    async fn set_media_pinned(&self, request: &MediaRequest, pinned: bool) -> Result<()> {
+        let key = self.encode_key(keys::MEDIA, request.unique_key());
+
+        self.acquire()
+            .await?
+            .execute("UPDATE media SET pinned = ? WHERE key = ?", (pinned, key))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove_media_content(&self, request: &MediaRequest) -> Result<()> {
+        let key = self.encode_key(keys::MEDIA, request.unique_key());
+
+        self.acquire().await?.execute("DELETE FROM media WHERE key = ?", (key,)).await?;
+
+        Ok(())
+    }
+
+    async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<()> {
+        let uri = self.encode_key(keys::MEDIA, uri);
+
+        self.acquire().await?.execute("DELETE FROM media WHERE uri = ?", (uri,)).await?;
+
+        Ok(())
+    }
+
+    async fn media_entries(&self) -> Result<Vec<MediaCacheEntry>> {
+        let rows: Vec<(Vec<u8>, i64, i64, i64, bool)> = self
+            .acquire()
+            .await?
+            .prepare(
+                "SELECT metadata, size, added_at, last_accessed_at, pinned FROM media",
+                |mut stmt| {
+                    stmt.query_map((), |row| {
+                        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+                    })?
+                    .collect()
+                },
+            )
+            .await?;
+
+        rows.into_iter()
+            .map(|(metadata, size, added_at, last_accessed_at, pinned)| {
+                let (key, uri): (String, OwnedMxcUri) = self.deserialize_json(&metadata)?;
+
+                Ok(MediaCacheEntry {
+                    key,
+                    uri,
+                    size: size.try_into().unwrap_or_default(),
+                    added_at: decode_ts(added_at),
+                    last_accessed_at: decode_ts(last_accessed_at),
+                    pinned,
+                })
+            })
+            .collect()
+    }
+
+    async fn remove_media_entries(&self, keys: &[String]) -> Result<()> {
+        let keys = keys.iter().map(|key| self.encode_key(keys::MEDIA, key)).collect::<Vec<_>>();
+
+        self.acquire()
+            .await?
+            .with_transaction(move |txn| {
+                let mut stmt = txn.prepare_cached("DELETE FROM media WHERE key = ?")?;
+                for key in keys {
+                    stmt.execute((key,))?;
+                }
+
+                Result::<_, Error>::Ok(())
+            })
+            .await
+    }
+
+    async fn clear_media_cache(&self) -> Result<()> {
+        self.acquire()
+            .await?
+            .with_transaction(|txn| {
+                txn.execute("DELETE FROM media", ())?;
+                txn.execute("DELETE FROM url_preview", ())?;
+
+                Result::<_, Error>::Ok(())
+            })
+            .await
+    }
+
+    async fn add_url_preview(
+        &self,
+        url: &str,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+        data: Vec<u8>,
+        now: MilliSecondsSinceUnixEpoch,
+    ) -> Result<()> {
+        let key = self.encode_key(keys::URL_PREVIEW, url_preview_key(url, ts)?);
+        let size = i64::try_from(data.len()).unwrap_or(i64::MAX);
+        let now = encode_ts(now);
+        let data = self.encode_value(data)?;
+
+        self.acquire()
+            .await?
+            .execute(
+                "INSERT OR REPLACE INTO url_preview (key, size, added_at, data) \
+                 VALUES (?, ?, ?, ?)",
+                (key, size, now, data),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_url_preview(
+        &self,
+        url: &str,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+    ) -> Result<Option<Vec<u8>>> {
+        let key = self.encode_key(keys::URL_PREVIEW, url_preview_key(url, ts)?);
+
+        let data: Option<Vec<u8>> = self
+            .acquire()
+            .await?
+            .query_row("SELECT data FROM url_preview WHERE key = ?", (key,), |row| row.get(0))
+            .await
+            .optional()?;
+
+        data.map(|data| Ok(self.decode_value(&data)?.into_owned())).transpose()
+    }
+
+    async fn remove_url_previews_added_before(
+        &self,
+        before: MilliSecondsSinceUnixEpoch,
+    ) -> Result<()> {
+        let before = encode_ts(before);
+
+        self.acquire()
+            .await?
+            .execute("DELETE FROM url_preview WHERE added_at < ?", (before,))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn url_previews_size(&self) -> Result<usize> {
+        let size: i64 = self
+            .acquire()
+            .await?
+            .query_row("SELECT COALESCE(SUM(size), 0) FROM url_preview", (), |row| row.get(0))
+            .await?;
+
+        Ok(size.try_into().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_base::{
+        media_cache_store::{MediaCacheStore, MediaCacheStoreError},
+        media_cache_store_integration_tests,
+    };
+    use once_cell::sync::Lazy;
+    use tempfile::{tempdir, TempDir};
+
+    use super::SqliteMediaCacheStore;
+
+    static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    async fn get_media_cache_store() -> Result<impl MediaCacheStore, MediaCacheStoreError> {
+        let name = NUM.fetch_add(1, SeqCst).to_string();
+        let tmpdir_path = TMP_DIR.path().join(name);
+
+        Ok(SqliteMediaCacheStore::open(tmpdir_path.to_str().unwrap(), None).await.unwrap())
+    }
+
+    media_cache_store_integration_tests!();
+}
+
+#[cfg(test)]
+mod encrypted_tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_base::{
+        media_cache_store::{MediaCacheStore, MediaCacheStoreError},
+        media_cache_store_integration_tests,
+    };
+    use once_cell::sync::Lazy;
+    use tempfile::{tempdir, TempDir};
+
+    use super::SqliteMediaCacheStore;
+
+    static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    async fn get_media_cache_store() -> Result<impl MediaCacheStore, MediaCacheStoreError> {
+        let name = NUM.fetch_add(1, SeqCst).to_string();
+        let tmpdir_path = TMP_DIR.path().join(name);
+
+        Ok(SqliteMediaCacheStore::open(
+            tmpdir_path.to_str().unwrap(),
+            Some("default_test_password"),
+        )
+        .await
+        .unwrap())
+    }
+
+    media_cache_store_integration_tests!();
+}
@@ -10,8 +10,7 @@ use async_trait::async_trait;
 use deadpool_sqlite::{Object as SqliteConn, Pool as SqlitePool, Runtime};
 use matrix_sdk_base::{
     deserialized_responses::{RawAnySyncOrStrippedState, SyncOrStrippedState},
-    media::{MediaRequest, UniqueKey},
-    store::migration_helpers::RoomInfoV1,
+    store::{migration_helpers::RoomInfoV1, QueuedRequest},
     MinimalRoomMemberEvent, RoomInfo, RoomMemberships, RoomState, StateChanges, StateStore,
     StateStoreDataKey, StateStoreDataValue,
 };
@@ -29,7 +28,8 @@ use ruma::{
         GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType,
     },
     serde::Raw,
-    CanonicalJsonObject, EventId, OwnedEventId, OwnedUserId, RoomId, RoomVersionId, UserId,
+    CanonicalJsonObject, EventId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId,
+    TransactionId, UserId,
 };
 use rusqlite::{OptionalExtension, Transaction};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -54,10 +54,10 @@ mod keys {
     pub const PROFILE: &str = "profile";
     pub const RECEIPT: &str = "receipt";
     pub const DISPLAY_NAME: &str = "display_name";
-    pub const MEDIA: &str = "media";
+    pub const SEND_QUEUE: &str = "send_queue_request";
 }
 
-const DATABASE_VERSION: u8 = 3;
+const DATABASE_VERSION: u8 = 5;
 
 /// A sqlite based cryptostore.
 #[derive(Clone)]
@@ -213,6 +213,26 @@ impl SqliteStateStore {
             .await?;
         }
 
+        if from < 4 && to >= 4 {
+            conn.with_transaction(move |txn| {
+                // Create the send queue table.
+                txn.execute_batch(include_str!("../migrations/state_store/004_send_queue.sql"))?;
+
+                Result::<_, Error>::Ok(())
+            })
+            .await?;
+        }
+
+        if from < 5 && to >= 5 {
+            conn.with_transaction(move |txn| {
+                // Drop the media table, the media are in the media cache store now.
+                txn.execute_batch(include_str!("../migrations/state_store/005_drop_media.sql"))?;
+
+                Result::<_, Error>::Ok(())
+            })
+            .await?;
+        }
+
         conn.set_kv("version", vec![to]).await?;
 
         Ok(())
@@ -824,36 +844,6 @@ trait SqliteObjectStateStoreExt: SqliteObjectExt {
             )
             .await?)
     }
-
-    async fn set_media(&self, uri: Key, format: Key, data: Vec<u8>) -> Result<()> {
-        self.execute(
-            "INSERT OR REPLACE INTO media (uri, format, data) VALUES (?, ?, ?)",
-            (uri, format, data),
-        )
-        .await?;
-        Ok(())
-    }
-
-    async fn get_media(&self, uri: Key, format: Key) -> Result<Option<Vec<u8>>> {
-        Ok(self
-            .query_row(
-                "SELECT data FROM media WHERE uri = ? AND format = ?",
-                (uri, format),
-                |row| row.get(0),
-            )
-            .await
-            .optional()?)
-    }
-
-    async fn remove_media(&self, uri: Key, format: Key) -> Result<()> {
-        self.execute("DELETE FROM media WHERE uri = ? AND format = ?", (uri, format)).await?;
-        Ok(())
-    }
-
-    async fn remove_uri_medias(&self, uri: Key) -> Result<()> {
-        self.execute("DELETE FROM media WHERE uri = ?", (uri,)).await?;
-        Ok(())
-    }
 }
 
 #[async_trait]
@@ -960,7 +950,8 @@ impl StateStore for SqliteStateStore {
                 }
 
                 for (room_id, room_info) in room_infos {
-                    let stripped = room_info.state() == RoomState::Invited;
+                    let stripped =
+                        matches!(room_info.state(), RoomState::Invited | RoomState::Knocked);
                     // Remove non-stripped data for stripped rooms and vice-versa.
                     this.remove_maybe_stripped_room_data(txn, &room_id, !stripped)?;
 
@@ -1373,8 +1364,10 @@ impl StateStore for SqliteStateStore {
     }
 
     async fn get_stripped_room_infos(&self) -> Result<Vec<RoomInfo>> {
-        let states =
-            vec![self.encode_key(keys::ROOM_INFO, serde_json::to_string(&RoomState::Invited)?)];
+        let states = [RoomState::Invited, RoomState::Knocked]
+            .iter()
+            .map(|state| Ok(self.encode_key(keys::ROOM_INFO, serde_json::to_string(state)?)))
+            .collect::<Result<Vec<_>>>()?;
         self.acquire()
             .await?
             .get_room_infos(states)
@@ -1535,31 +1528,6 @@ impl StateStore for SqliteStateStore {
         Ok(previous)
     }
 
-    async fn add_media_content(&self, request: &MediaRequest, content: Vec<u8>) -> Result<()> {
-        let uri = self.encode_key(keys::MEDIA, request.source.unique_key());
-        let format = self.encode_key(keys::MEDIA, request.format.unique_key());
-        let data = self.encode_value(content)?;
-        self.acquire().await?.set_media(uri, format, data).await
-    }
-
-    async fn get_media_content(&self, request: &MediaRequest) -> Result<Option<Vec<u8>>> {
-        let uri = self.encode_key(keys::MEDIA, request.source.unique_key());
-        let format = self.encode_key(keys::MEDIA, request.format.unique_key());
-        let data = self.acquire().await?.get_media(uri, format).await?;
-        data.map(|v| self.decode_value(&v).map(Into::into)).transpose()
-    }
-
-    async fn remove_media_content(&self, request: &MediaRequest) -> Result<()> {
-        let uri = self.encode_key(keys::MEDIA, request.source.unique_key());
-        let format = self.encode_key(keys::MEDIA, request.format.unique_key());
-        self.acquire().await?.remove_media(uri, format).await
-    }
-
-    async fn remove_media_content_for_uri(&self, uri: &ruma::MxcUri) -> Result<()> {
-        let uri = self.encode_key(keys::MEDIA, uri);
-        self.acquire().await?.remove_uri_medias(uri).await
-    }
-
     async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
         let this = self.clone();
         let room_id = room_id.to_owned();
@@ -1592,6 +1560,90 @@ impl StateStore for SqliteStateStore {
             })
             .await
     }
+
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<()> {
+        let room_id_key = self.encode_key(keys::SEND_QUEUE, room_id);
+        let room_id_value = self.serialize_value(&room_id.to_owned())?;
+        let transaction_id = self.encode_key(keys::SEND_QUEUE, &request.transaction_id);
+        let data = self.serialize_json(&request)?;
+
+        self.acquire()
+            .await?
+            .with_transaction(move |txn| {
+                // Replace the request in place if it's already queued, to keep its position.
+                let updated = txn
+                    .prepare_cached(
+                        "UPDATE send_queue_request SET data = ?
+                         WHERE room_id = ? AND transaction_id = ?",
+                    )?
+                    .execute((&data, &room_id_key, &transaction_id))?;
+
+                if updated == 0 {
+                    txn.prepare_cached(
+                        "INSERT INTO send_queue_request (room_id, room_id_val, transaction_id, data)
+                         VALUES (?, ?, ?, ?)",
+                    )?
+                    .execute((room_id_key, room_id_value, transaction_id, data))?;
+                }
+
+                Result::<_, Error>::Ok(())
+            })
+            .await
+    }
+
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool> {
+        let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+        let transaction_id = self.encode_key(keys::SEND_QUEUE, transaction_id);
+
+        let removed = self
+            .acquire()
+            .await?
+            .execute(
+                "DELETE FROM send_queue_request WHERE room_id = ? AND transaction_id = ?",
+                (room_id, transaction_id),
+            )
+            .await?;
+
+        Ok(removed > 0)
+    }
+
+    async fn load_send_queue_requests(&self, room_id: &RoomId) -> Result<Vec<QueuedRequest>> {
+        let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+
+        let data: Vec<Vec<u8>> = self
+            .acquire()
+            .await?
+            .prepare(
+                "SELECT data FROM send_queue_request WHERE room_id = ? ORDER BY id",
+                move |mut stmt| stmt.query((room_id,))?.mapped(|row| row.get(0)).collect(),
+            )
+            .await?;
+
+        data.iter().map(|value| self.deserialize_json(value)).collect()
+    }
+
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>> {
+        // The encryption of the room ID isn't deterministic, so group by the hashed
+        // room ID instead.
+        let room_ids: Vec<Vec<u8>> = self
+            .acquire()
+            .await?
+            .prepare(
+                "SELECT room_id_val FROM send_queue_request GROUP BY room_id",
+                move |mut stmt| stmt.query(())?.mapped(|row| row.get(0)).collect(),
+            )
+            .await?;
+
+        room_ids.iter().map(|value| self.deserialize_value(value)).collect()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1621,7 +1673,7 @@ mod tests {
         Ok(SqliteStateStore::open(tmpdir_path.to_str().unwrap(), None).await.unwrap())
     }
 
-    statestore_integration_tests!(with_media_tests);
+    statestore_integration_tests!();
 }
 
 #[cfg(test)]
@@ -1646,7 +1698,7 @@ mod encrypted_tests {
             .unwrap())
     }
 
-    statestore_integration_tests!(with_media_tests);
+    statestore_integration_tests!();
 }
 
 #[cfg(test)]
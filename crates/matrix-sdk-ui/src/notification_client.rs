@@ -13,34 +13,60 @@
 // limitations under the License.
 
 use std::{
+    collections::{BTreeMap, BTreeSet},
+    num::NonZeroUsize,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
+use async_stream::stream;
+use futures_core::Stream;
 use futures_util::{pin_mut, StreamExt as _};
-use matrix_sdk::{room::Room, Client, ClientBuildError, SlidingSyncList, SlidingSyncMode};
+use matrix_sdk::{
+    config::RequestConfig,
+    executor::{spawn, JoinHandle},
+    notification_settings::NotificationSettings,
+    room::Room,
+    Client, ClientBuildError, HttpError, SlidingSyncList, SlidingSyncMode,
+};
 use matrix_sdk_base::{
     crypto::{vodozemac, MegolmError},
     deserialized_responses::TimelineEvent,
+    ring_buffer::RingBuffer,
     RoomState, StoreError,
 };
 use ruma::{
-    api::client::sync::sync_events::v4::{
-        AccountDataConfig, RoomSubscription, SyncRequestListFilters,
+    api::client::{
+        room::get_room_event,
+        sync::sync_events::v4::{AccountDataConfig, RoomSubscription, SyncRequestListFilters},
     },
     assign,
     events::{
-        room::{member::StrippedRoomMemberEvent, message::SyncRoomMessageEvent},
+        forwarded_room_key::ToDeviceForwardedRoomKeyEvent,
+        fully_read::FullyReadEventContent,
+        receipt::{ReceiptThread, ReceiptType},
+        room::{
+            encrypted::{EncryptedEventScheme, OriginalSyncRoomEncryptedEvent},
+            member::StrippedRoomMemberEvent,
+            message::SyncRoomMessageEvent,
+        },
+        room_key::ToDeviceRoomKeyEvent,
         AnyFullStateEventContent, AnyStateEvent, AnySyncMessageLikeEvent, AnySyncTimelineEvent,
         FullStateEventContent, StateEventType, TimelineEventType,
     },
     html::RemoveReplyFallback,
     push::Action,
     serde::Raw,
-    uint, EventId, OwnedEventId, RoomId, UserId,
+    uint, EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId,
+    UInt, UserId,
 };
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
 use thiserror::Error;
-use tokio::sync::Mutex as AsyncMutex;
+use tokio::{
+    sync::{mpsc, Mutex as AsyncMutex},
+    time::timeout,
+};
 use tracing::{debug, info, instrument, trace, warn};
 
 use crate::{
@@ -105,6 +131,13 @@ pub struct NotificationClient {
     ///
     /// Same reasoning as [`Self::notification_sync_mutex`].
     encryption_sync_mutex: AsyncMutex<()>,
+
+    /// The maximum time [`Self::get_notification_from_push`] may take.
+    push_time_budget: Duration,
+
+    /// The maximum size of an event fetched by
+    /// [`Self::get_notification_from_push`], in bytes.
+    max_push_event_size: usize,
 }
 
 impl NotificationClient {
@@ -480,8 +513,9 @@ impl NotificationClient {
             return Err(Error::UnknownRoom);
         };
 
-        let (mut timeline_event, state_events) =
-            room.event_with_context(event_id, true).await?.ok_or(Error::ContextMissingEvent)?;
+        let response = room.event_with_context(event_id, true, uint!(0)).await?;
+        let mut timeline_event = response.event.ok_or(Error::ContextMissingEvent)?;
+        let state_events = response.state;
 
         if let Some(decrypted_event) =
             self.retry_decryption(&room, timeline_event.event.cast_ref()).await?
@@ -510,6 +544,140 @@ impl NotificationClient {
     }
 }
 
+impl NotificationClient {
+    /// The maximum backoff when waiting for the cross-process lock of the
+    /// crypto store in [`Self::get_notification_from_push`], in milliseconds.
+    const PUSH_LOCK_MAX_BACKOFF_MS: u32 = 500;
+
+    /// Fetches the content of a notification from a push payload, without
+    /// running any sync.
+    ///
+    /// This is a lightweight alternative to [`Self::get_notification`], for
+    /// processes that are woken up by a push notification with tight time and
+    /// memory constraints, like the iOS Notification Service Extension:
+    ///
+    /// - the event is fetched with a single `/rooms/{roomId}/event/{eventId}`
+    ///   request,
+    /// - it is decrypted with the keys from the crypto store, under the
+    ///   cross-process lock if the client runs in its own process,
+    /// - the sender and room information come from the state store, so the
+    ///   room must be known by the parent client.
+    ///
+    /// The whole process must complete within the time budget set with
+    /// [`NotificationClientBuilder::push_time_budget`], and events larger than
+    /// [`NotificationClientBuilder::max_push_event_size`] are rejected.
+    ///
+    /// An event that couldn't be decrypted is still returned, encrypted. A
+    /// `None` result means the notification has been filtered out by the
+    /// user's push rules.
+    #[instrument(skip_all, fields(room_id = ?payload.room_id, event_id = ?payload.event_id))]
+    pub async fn get_notification_from_push(
+        &self,
+        payload: &PushPayload,
+    ) -> Result<Option<NotificationItem>, Error> {
+        timeout(self.push_time_budget, self.get_notification_from_push_inner(payload))
+            .await
+            .map_err(|_| Error::PushTimeBudgetExceeded)?
+    }
+
+    async fn get_notification_from_push_inner(
+        &self,
+        payload: &PushPayload,
+    ) -> Result<Option<NotificationItem>, Error> {
+        let Some(room) = self.parent_client.get_room(&payload.room_id) else {
+            return Err(Error::UnknownRoom);
+        };
+
+        let request =
+            get_room_event::v3::Request::new(payload.room_id.clone(), payload.event_id.clone());
+        // Don't even download events that would exceed the memory limits of the
+        // process.
+        let config = RequestConfig::short_retry().max_response_size(self.max_push_event_size);
+        let raw_event: Raw<AnySyncTimelineEvent> =
+            match self.parent_client.send(request, Some(config)).await {
+                Ok(response) => response.event.cast(),
+                Err(HttpError::ResponseTooLarge { max_size }) => {
+                    return Err(Error::PushEventTooLarge(max_size));
+                }
+                Err(err) => return Err(matrix_sdk::Error::from(err).into()),
+            };
+
+        let event_type = raw_event
+            .get_field::<TimelineEventType>("type")
+            .ok()
+            .flatten()
+            .ok_or(Error::InvalidRumaEvent)?;
+
+        let decrypted_event = if is_event_encrypted(event_type) {
+            self.decrypt_push_event(&room, &raw_event).await?
+        } else {
+            None
+        };
+
+        let (raw_event, push_actions) = match decrypted_event {
+            Some(event) => (event.event.cast(), event.push_actions),
+            None => {
+                let push_actions = room.event_push_actions(&raw_event).await?;
+                (raw_event, push_actions)
+            }
+        };
+
+        if let Some(push_actions) = &push_actions {
+            if self.filter_by_push_rules && !push_actions.iter().any(|a| a.should_notify()) {
+                return Ok(None);
+            }
+        }
+
+        let mut item = NotificationItem::new(
+            &room,
+            RawNotificationEvent::Timeline(raw_event),
+            push_actions.as_deref(),
+            Vec::new(),
+        )
+        .await?;
+        item.unread_count = payload.unread_count;
+
+        Ok(Some(item))
+    }
+
+    /// Try to decrypt an event fetched from a push payload.
+    ///
+    /// Returns `None` if the event couldn't be decrypted.
+    async fn decrypt_push_event(
+        &self,
+        room: &Room,
+        raw_event: &Raw<AnySyncTimelineEvent>,
+    ) -> Result<Option<TimelineEvent>, Error> {
+        // The main app may write to the crypto store at the same time; taking the
+        // cross-process lock makes sure the `OlmMachine` is reloaded if needed.
+        let _lock_guard = match self.process_setup {
+            NotificationProcessSetup::MultipleProcesses => {
+                let encryption = self.parent_client.encryption();
+
+                match encryption.enable_cross_process_store_lock(Self::LOCK_ID.to_owned()).await {
+                    Ok(()) | Err(matrix_sdk::Error::BadCryptoStoreState) => {
+                        // Ignore; the lock is already set up for this process.
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+
+                encryption.spin_lock_store(Some(Self::PUSH_LOCK_MAX_BACKOFF_MS)).await?
+            }
+
+            NotificationProcessSetup::SingleProcess { .. } => None,
+        };
+
+        match room.decrypt_event(raw_event.cast_ref()).await {
+            Ok(event) => Ok(Some(event)),
+            Err(err) => {
+                // An undecrypted notification is still better than no notification.
+                debug!("Couldn't decrypt the event of the push notification: {err}");
+                Ok(None)
+            }
+        }
+    }
+}
+
 fn is_event_encrypted(event_type: TimelineEventType) -> bool {
     let is_still_encrypted = matches!(event_type, ruma::events::TimelineEventType::RoomEncrypted);
 
@@ -527,6 +695,70 @@ pub enum NotificationStatus {
     EventFilteredOut,
 }
 
+/// The default value of [`NotificationClientBuilder::push_time_budget`].
+const DEFAULT_PUSH_TIME_BUDGET: Duration = Duration::from_secs(10);
+
+/// The default value of [`NotificationClientBuilder::max_push_event_size`].
+const DEFAULT_MAX_PUSH_EVENT_SIZE: usize = 65536;
+
+/// The content of a push notification sent for a pusher with the
+/// `event_id_only` format.
+///
+/// See [`NotificationClient::get_notification_from_push`].
+#[derive(Clone, Debug)]
+pub struct PushPayload {
+    /// The ID of the room of the event.
+    pub room_id: OwnedRoomId,
+
+    /// The ID of the event that triggered the notification.
+    pub event_id: OwnedEventId,
+
+    /// The number of unread notifications of the user, if the push gateway
+    /// sent it.
+    pub unread_count: Option<u64>,
+}
+
+impl PushPayload {
+    /// Create a new `PushPayload` for the given event.
+    pub fn new(room_id: OwnedRoomId, event_id: OwnedEventId) -> Self {
+        Self { room_id, event_id, unread_count: None }
+    }
+
+    /// Parse the payload of a push notification, as forwarded by the push
+    /// gateway.
+    ///
+    /// The unread count is read from the `counts.unread` field defined by the
+    /// push gateway API, or from the flattened `unread_count` or `unread`
+    /// fields, possibly as strings, sent by common push gateways.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        #[derive(Deserialize)]
+        struct Counts {
+            unread: Option<UInt>,
+        }
+
+        #[derive(Deserialize)]
+        struct RawPushPayload {
+            room_id: OwnedRoomId,
+            event_id: OwnedEventId,
+            counts: Option<Counts>,
+            #[serde(alias = "unread")]
+            unread_count: Option<JsonValue>,
+        }
+
+        let payload: RawPushPayload = serde_json::from_str(json)?;
+
+        let unread_count = payload.counts.and_then(|counts| counts.unread).map(u64::from).or_else(
+            || match payload.unread_count? {
+                JsonValue::Number(count) => count.as_u64(),
+                JsonValue::String(count) => count.parse().ok(),
+                _ => None,
+            },
+        );
+
+        Ok(Self { room_id: payload.room_id, event_id: payload.event_id, unread_count })
+    }
+}
+
 /// Builder for a `NotificationClient`.
 ///
 /// Fields have the same meaning as in `NotificationClient`.
@@ -541,6 +773,9 @@ pub struct NotificationClientBuilder {
 
     /// Is the notification client running on its own process or not?
     process_setup: NotificationProcessSetup,
+
+    push_time_budget: Duration,
+    max_push_event_size: usize,
 }
 
 impl NotificationClientBuilder {
@@ -550,7 +785,14 @@ impl NotificationClientBuilder {
     ) -> Result<Self, Error> {
         let client = parent_client.notification_client().await?;
 
-        Ok(Self { client, parent_client, filter_by_push_rules: false, process_setup })
+        Ok(Self {
+            client,
+            parent_client,
+            filter_by_push_rules: false,
+            process_setup,
+            push_time_budget: DEFAULT_PUSH_TIME_BUDGET,
+            max_push_event_size: DEFAULT_MAX_PUSH_EVENT_SIZE,
+        })
     }
 
     /// Filter out the notification event according to the push rules present in
@@ -560,6 +802,25 @@ impl NotificationClientBuilder {
         self
     }
 
+    /// Set the maximum time
+    /// [`NotificationClient::get_notification_from_push`] may take.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn push_time_budget(mut self, push_time_budget: Duration) -> Self {
+        self.push_time_budget = push_time_budget;
+        self
+    }
+
+    /// Set the maximum size of an event fetched by
+    /// [`NotificationClient::get_notification_from_push`], in bytes.
+    ///
+    /// Defaults to 65536 bytes, the maximum size of an event allowed by the
+    /// spec.
+    pub fn max_push_event_size(mut self, max_push_event_size: usize) -> Self {
+        self.max_push_event_size = max_push_event_size;
+        self
+    }
+
     /// Finishes configuring the `NotificationClient`.
     pub fn build(self) -> NotificationClient {
         NotificationClient {
@@ -569,10 +830,398 @@ impl NotificationClientBuilder {
             notification_sync_mutex: AsyncMutex::new(()),
             encryption_sync_mutex: AsyncMutex::new(()),
             process_setup: self.process_setup,
+            push_time_budget: self.push_time_budget,
+            max_push_event_size: self.max_push_event_size,
+        }
+    }
+}
+
+/// The maximum number of events remembered by [`local_notifications`], to not
+/// evaluate the same event twice.
+const MAX_LOCAL_NOTIFICATIONS_SEEN_EVENTS: usize = 1000;
+
+/// The maximum number of events that couldn't be decrypted yet, that are kept
+/// by [`local_notifications`] to be evaluated once their room key is received.
+const MAX_LOCAL_NOTIFICATIONS_PENDING_UTDS: usize = 1000;
+
+/// Get a stream of the notifications triggered by the events received by the
+/// sync loop of the given client.
+///
+/// This is meant for clients that aren't woken up by push notifications, like
+/// desktop clients or bots. Every incoming timeline event, decrypted if
+/// possible, and every invite is evaluated against the push rules managed by
+/// [`NotificationSettings`], so the room notification modes and the keywords
+/// are respected. The events that should notify are emitted as
+/// [`NotificationItem`]s, with [`NotificationItem::is_noisy`] and
+/// [`NotificationItem::has_mention`] reflecting the sound and highlight tweaks.
+///
+/// Events sent by the current user never notify, and neither do events at or
+/// before the read receipts or the fully-read marker of the current user, so
+/// already read events don't notify again when a room's timeline is received
+/// again. A given event notifies at most once. Events that can't be decrypted
+/// are evaluated once their room key is received, from another device or from
+/// the key backup.
+///
+/// The events are observed as long as the returned stream is alive.
+pub async fn local_notifications(client: &Client) -> impl Stream<Item = NotificationItem> {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let state = Arc::new(LocalNotifications {
+        settings: client.notification_settings().await,
+        sender,
+        seen_events: Mutex::new(RingBuffer::new(
+            NonZeroUsize::new(MAX_LOCAL_NOTIFICATIONS_SEEN_EVENTS).unwrap(),
+        )),
+        utds: Default::default(),
+    });
+
+    let timeline_event_handler = client.add_event_handler({
+        let state = state.clone();
+        move |raw: Raw<AnySyncTimelineEvent>, room: Room| {
+            let state = state.clone();
+            async move { state.on_timeline_event(&room, raw).await }
+        }
+    });
+
+    let stripped_member_handler =
+        client.add_event_handler({
+            let state = state.clone();
+            move |raw: Raw<StrippedRoomMemberEvent>, room: Room| {
+                let state = state.clone();
+                async move {
+                    state.send_notification(&room, RawNotificationEvent::Invite(raw), None).await
+                }
+            }
+        });
+
+    let room_key_handler = client.add_event_handler({
+        let state = state.clone();
+        move |event: ToDeviceRoomKeyEvent, client: Client| {
+            let state = state.clone();
+            async move {
+                let sessions = BTreeMap::from([(
+                    event.content.room_id,
+                    BTreeSet::from([event.content.session_id]),
+                )]);
+                state.retry_decryption(&client, Some(sessions)).await;
+            }
+        }
+    });
+
+    let forwarded_room_key_handler = client.add_event_handler({
+        let state = state.clone();
+        move |event: ToDeviceForwardedRoomKeyEvent, client: Client| {
+            let state = state.clone();
+            async move {
+                let sessions = BTreeMap::from([(
+                    event.content.room_id,
+                    BTreeSet::from([event.content.session_id]),
+                )]);
+                state.retry_decryption(&client, Some(sessions)).await;
+            }
+        }
+    });
+
+    let guards = [
+        client.event_handler_drop_guard(timeline_event_handler),
+        client.event_handler_drop_guard(stripped_member_handler),
+        client.event_handler_drop_guard(room_key_handler),
+        client.event_handler_drop_guard(forwarded_room_key_handler),
+    ];
+
+    // Retry to decrypt the events when their room key is downloaded from the
+    // key backup.
+    let room_keys_from_backups_task = RoomKeysFromBackupsTask(spawn({
+        let client = client.clone();
+        let room_keys_stream = client.encryption().backups().room_keys_stream();
+
+        async move {
+            pin_mut!(room_keys_stream);
+
+            while let Some(room_keys) = room_keys_stream.next().await {
+                let sessions = match room_keys {
+                    Ok(room_keys) => Some(
+                        room_keys
+                            .keys
+                            .into_iter()
+                            .map(|(room_id, sessions)| {
+                                (room_id, sessions.into_values().flatten().collect())
+                            })
+                            .collect(),
+                    ),
+                    // Some room keys were missed, retry all the events.
+                    Err(_) => None,
+                };
+
+                state.retry_decryption(&client, sessions).await;
+            }
+        }
+    }));
+
+    stream! {
+        // Keep the event handlers and the task alive as long as the stream.
+        let _guards = guards;
+        let _room_keys_from_backups_task = room_keys_from_backups_task;
+
+        while let Some(item) = receiver.recv().await {
+            yield item;
         }
     }
 }
 
+/// The task of [`local_notifications`] retrying to decrypt the events when
+/// their room key is downloaded from the key backup, aborted when dropped.
+struct RoomKeysFromBackupsTask(JoinHandle<()>);
+
+impl Drop for RoomKeysFromBackupsTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// The state shared by the event handlers of [`local_notifications`].
+struct LocalNotifications {
+    /// The notification settings of the current user.
+    settings: NotificationSettings,
+
+    /// The sender of the notifications, to the stream returned by
+    /// [`local_notifications`].
+    sender: mpsc::UnboundedSender<NotificationItem>,
+
+    /// The IDs and timestamps of the latest events that were evaluated or
+    /// that are targeted by a read marker of the current user.
+    seen_events: Mutex<RingBuffer<(OwnedEventId, MilliSecondsSinceUnixEpoch)>>,
+
+    /// The events that couldn't be decrypted yet, by room and Megolm session
+    /// ID.
+    utds: Mutex<BTreeMap<(OwnedRoomId, String), Vec<Raw<AnySyncTimelineEvent>>>>,
+}
+
+impl LocalNotifications {
+    /// Evaluate a timeline event, received by a sync or decrypted later.
+    async fn on_timeline_event(&self, room: &Room, raw: Raw<AnySyncTimelineEvent>) {
+        let (Ok(Some(event_id)), Ok(Some(origin_server_ts))) = (
+            raw.get_field::<OwnedEventId>("event_id"),
+            raw.get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts"),
+        ) else {
+            warn!(room_id = ?room.room_id(), "Ignoring a timeline event without ID or timestamp");
+            return;
+        };
+
+        if self.seen_event_ts(&event_id).is_some() {
+            trace!(?event_id, "Ignoring an event that was already evaluated");
+            return;
+        }
+
+        if let Some(session_id) = megolm_session_id(&raw) {
+            // Evaluate the event once its room key is received.
+            self.add_utd(room.room_id(), session_id, raw);
+            return;
+        }
+
+        self.seen_events.lock().unwrap().push((event_id, origin_server_ts));
+
+        self.send_notification(room, RawNotificationEvent::Timeline(raw), Some(origin_server_ts))
+            .await;
+    }
+
+    /// Get the timestamp of the given event, if it was seen recently.
+    fn seen_event_ts(&self, event_id: &EventId) -> Option<MilliSecondsSinceUnixEpoch> {
+        let seen_events = self.seen_events.lock().unwrap();
+        seen_events.iter().find(|(seen_event_id, _)| seen_event_id == event_id).map(|(_, ts)| *ts)
+    }
+
+    /// Remember an event that couldn't be decrypted, to evaluate it once its
+    /// room key is received.
+    fn add_utd(&self, room_id: &RoomId, session_id: String, raw: Raw<AnySyncTimelineEvent>) {
+        let mut utds = self.utds.lock().unwrap();
+
+        if utds.values().map(Vec::len).sum::<usize>() >= MAX_LOCAL_NOTIFICATIONS_PENDING_UTDS {
+            trace!(?room_id, "Too many events waiting for their room key, ignoring this one");
+            return;
+        }
+
+        let events = utds.entry((room_id.to_owned(), session_id)).or_default();
+        let event_id = raw.get_field::<OwnedEventId>("event_id").ok().flatten();
+        if !events
+            .iter()
+            .any(|ev| ev.get_field::<OwnedEventId>("event_id").ok().flatten() == event_id)
+        {
+            events.push(raw);
+        }
+    }
+
+    /// Try to decrypt and evaluate the events waiting for their room key.
+    ///
+    /// If `sessions` is `None`, all the waiting events are retried, otherwise
+    /// only the ones of the given Megolm sessions, by room.
+    async fn retry_decryption(
+        &self,
+        client: &Client,
+        sessions: Option<BTreeMap<OwnedRoomId, BTreeSet<String>>>,
+    ) {
+        let utds = {
+            let mut utds = self.utds.lock().unwrap();
+
+            match sessions {
+                Some(sessions) => sessions
+                    .into_iter()
+                    .flat_map(|(room_id, session_ids)| {
+                        session_ids.into_iter().map(move |session_id| (room_id.clone(), session_id))
+                    })
+                    .filter_map(|key| utds.remove_entry(&key))
+                    .collect::<Vec<_>>(),
+                None => std::mem::take(&mut *utds).into_iter().collect(),
+            }
+        };
+
+        for ((room_id, session_id), events) in utds {
+            let Some(room) = client.get_room(&room_id) else { continue };
+
+            for raw in events {
+                match room.decrypt_event(raw.cast_ref()).await {
+                    Ok(event) => self.on_timeline_event(&room, event.event.cast()).await,
+                    // The room key might still be unusable for this event, e.g. if it was
+                    // received at a later index of the session.
+                    Err(_) => self.add_utd(&room_id, session_id.clone(), raw),
+                }
+            }
+        }
+    }
+
+    /// Get the timestamp of the latest event read by the current user in the
+    /// room, according to their read receipts and their fully-read marker.
+    async fn read_marker_ts(
+        &self,
+        room: &Room,
+    ) -> Result<Option<MilliSecondsSinceUnixEpoch>, Error> {
+        let own_user_id = room.own_user_id();
+        let mut event_ids = Vec::new();
+
+        for (receipt_type, thread) in [
+            (ReceiptType::Read, ReceiptThread::Unthreaded),
+            (ReceiptType::Read, ReceiptThread::Main),
+            (ReceiptType::ReadPrivate, ReceiptThread::Unthreaded),
+            (ReceiptType::ReadPrivate, ReceiptThread::Main),
+        ] {
+            if let Some((event_id, _)) =
+                room.load_user_receipt(receipt_type, thread, own_user_id).await?
+            {
+                event_ids.push(event_id);
+            }
+        }
+
+        if let Some(fully_read) = room.account_data_static::<FullyReadEventContent>().await? {
+            match fully_read.deserialize() {
+                Ok(fully_read) => event_ids.push(fully_read.content.event_id),
+                Err(err) => warn!("Failed to deserialize the fully-read marker: {err}"),
+            }
+        }
+
+        let mut latest_ts = None;
+
+        for event_id in event_ids {
+            let ts = match self.seen_event_ts(&event_id) {
+                Some(ts) => ts,
+                None => {
+                    // The event was received before the notifications were observed, fetch it
+                    // to know where the marker is.
+                    let ts = match room.event(&event_id).await {
+                        Ok(event) => event.event.get_field("origin_server_ts").ok().flatten(),
+                        Err(err) => {
+                            warn!(?event_id, "Failed to fetch the event of a read marker: {err}");
+                            None
+                        }
+                    };
+                    let Some(ts) = ts else { continue };
+
+                    self.seen_events.lock().unwrap().push((event_id, ts));
+                    ts
+                }
+            };
+
+            latest_ts = latest_ts.max(Some(ts));
+        }
+
+        Ok(latest_ts)
+    }
+
+    /// Evaluate the push rules for an event, and send the resulting
+    /// notification, if the event should notify.
+    ///
+    /// `origin_server_ts` is the timestamp of the event if it's a timeline
+    /// event, used to ignore the events that were already read.
+    async fn send_notification(
+        &self,
+        room: &Room,
+        raw_event: RawNotificationEvent,
+        origin_server_ts: Option<MilliSecondsSinceUnixEpoch>,
+    ) {
+        match self.local_notification(room, raw_event, origin_server_ts).await {
+            Ok(Some(item)) => {
+                // The stream has been dropped if this fails, nothing to do.
+                let _ = self.sender.send(item);
+            }
+            Ok(None) => {}
+            Err(err) => {
+                warn!(room_id = ?room.room_id(), "Couldn't build a local notification: {err}");
+            }
+        }
+    }
+
+    /// Build a notification for an event, if the push rules say the event
+    /// should notify and it wasn't read yet.
+    async fn local_notification(
+        &self,
+        room: &Room,
+        raw_event: RawNotificationEvent,
+        origin_server_ts: Option<MilliSecondsSinceUnixEpoch>,
+    ) -> Result<Option<NotificationItem>, Error> {
+        let settings = &self.settings;
+        let (sender, push_actions) = match &raw_event {
+            RawNotificationEvent::Timeline(raw) => (
+                raw.get_field::<OwnedUserId>("sender"),
+                settings.event_push_actions(room, raw).await?,
+            ),
+            RawNotificationEvent::Invite(raw) => (
+                raw.get_field::<OwnedUserId>("sender"),
+                settings.event_push_actions(room, raw).await?,
+            ),
+        };
+
+        let sender = sender.map_err(|_| Error::InvalidRumaEvent)?;
+        if sender.as_deref() == Some(room.own_user_id()) {
+            trace!("Ignoring an event sent by the current user");
+            return Ok(None);
+        }
+
+        let Some(push_actions) =
+            push_actions.filter(|actions| actions.iter().any(|a| a.should_notify()))
+        else {
+            return Ok(None);
+        };
+
+        if let Some(origin_server_ts) = origin_server_ts {
+            if self.read_marker_ts(room).await?.is_some_and(|read_ts| origin_server_ts <= read_ts) {
+                trace!("Ignoring an event that was already read");
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(NotificationItem::new(room, raw_event, Some(&push_actions), Vec::new()).await?))
+    }
+}
+
+/// Get the ID of the Megolm session of the given event, if it's an encrypted
+/// event.
+fn megolm_session_id(raw: &Raw<AnySyncTimelineEvent>) -> Option<String> {
+    let event = raw.deserialize_as::<OriginalSyncRoomEncryptedEvent>().ok()?;
+
+    match event.content.scheme {
+        EncryptedEventScheme::MegolmV1AesSha2(content) => Some(content.session_id),
+        _ => None,
+    }
+}
+
 /// The Notification event as it was fetched from remote for the
 /// given `event_id`, represented as Raw but decrypted, thus only
 /// whether it is an invite or regular Timeline event has been
@@ -640,6 +1289,12 @@ pub struct NotificationItem {
     /// It is set if and only if the push actions could be determined.
     pub is_noisy: Option<bool>,
     pub has_mention: Option<bool>,
+
+    /// The number of unread notifications of the user, as sent by the push
+    /// gateway, e.g. to update the badge of the app.
+    ///
+    /// It is only set by [`NotificationClient::get_notification_from_push`].
+    pub unread_count: Option<u64>,
 }
 
 impl NotificationItem {
@@ -721,6 +1376,7 @@ impl NotificationItem {
             joined_members_count: room.joined_members_count(),
             is_noisy,
             has_mention,
+            unread_count: None,
         };
 
         Ok(item)
@@ -749,6 +1405,16 @@ pub enum Error {
     #[error("the event was missing in the `/context` query")]
     ContextMissingEvent,
 
+    /// When calling `get_notification_from_push`, the notification couldn't
+    /// be fetched within the time budget.
+    #[error("the notification couldn't be fetched within the time budget")]
+    PushTimeBudgetExceeded,
+
+    /// When calling `get_notification_from_push`, the event was larger than
+    /// the maximum allowed size, in bytes.
+    #[error("the event is larger than the maximum size of {0} bytes")]
+    PushEventTooLarge(usize),
+
     /// An error forwarded from the client.
     #[error(transparent)]
     SdkError(#[from] matrix_sdk::Error),
@@ -59,10 +59,12 @@ mod any;
 mod category;
 mod favourite;
 mod fuzzy_match_room_name;
+mod knocked;
 mod non_left;
 mod none;
 mod normalized_match_room_name;
 mod not;
+mod space;
 mod unread;
 
 pub use all::new_filter as new_filter_all;
@@ -70,11 +72,13 @@ pub use any::new_filter as new_filter_any;
 pub use category::{new_filter as new_filter_category, RoomCategory};
 pub use favourite::new_filter as new_filter_favourite;
 pub use fuzzy_match_room_name::new_filter as new_filter_fuzzy_match_room_name;
+pub use knocked::new_filter as new_filter_knocked;
 use matrix_sdk::RoomListEntry;
 pub use non_left::new_filter as new_filter_non_left;
 pub use none::new_filter as new_filter_none;
 pub use normalized_match_room_name::new_filter as new_filter_normalized_match_room_name;
 pub use not::new_filter as new_filter_not;
+pub use space::new_filter as new_filter_space;
 use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 pub use unread::new_filter as new_filter_unread;
 
@@ -0,0 +1,141 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, RwLock},
+};
+
+use matrix_sdk::{event_handler::EventHandlerDropGuard, space::Space, Room, RoomListEntry};
+use ruma::{events::space::child::SyncSpaceChildEvent, OwnedRoomId};
+use tracing::{trace, warn};
+
+use super::Filter;
+
+struct SpaceRoomMatcher {
+    room_ids: Arc<RwLock<BTreeSet<OwnedRoomId>>>,
+    /// The handler keeping `room_ids` up to date, removed with the filter.
+    _space_child_handler: Option<EventHandlerDropGuard>,
+}
+
+impl SpaceRoomMatcher {
+    fn new(room_ids: BTreeSet<OwnedRoomId>) -> Self {
+        Self { room_ids: Arc::new(RwLock::new(room_ids)), _space_child_handler: None }
+    }
+
+    fn matches(&self, room_list_entry: &RoomListEntry) -> bool {
+        if !matches!(room_list_entry, RoomListEntry::Filled(_) | RoomListEntry::Invalidated(_)) {
+            return false;
+        }
+
+        room_list_entry
+            .as_room_id()
+            .is_some_and(|room_id| self.room_ids.read().unwrap().contains(room_id))
+    }
+}
+
+/// Create a new filter that will accept all filled or invalidated entries, but
+/// filters out rooms that don't belong to the given space or to one of its
+/// nested sub-spaces (see [`Space::joined_descendants`]).
+///
+/// The rooms of the space are computed again when the `m.space.child` state
+/// events of the space or of its sub-spaces change, and the entries of the
+/// rooms that were added to or removed from it are filtered again.
+pub async fn new_filter(space: &Space) -> matrix_sdk::Result<impl Filter> {
+    let mut matcher = SpaceRoomMatcher::new(space.joined_descendants().await?);
+
+    let client = space.client();
+    let handle = client.add_event_handler({
+        let space = space.clone();
+        let room_ids = matcher.room_ids.clone();
+
+        move |_: SyncSpaceChildEvent, room: Room| {
+            let space = space.clone();
+            let room_ids = room_ids.clone();
+
+            async move {
+                if room.room_id() == space.room_id()
+                    || room_ids.read().unwrap().contains(room.room_id())
+                {
+                    update_room_ids(&space, &room_ids).await;
+                }
+            }
+        }
+    });
+    matcher._space_child_handler = Some(client.event_handler_drop_guard(handle));
+
+    Ok(move |room_list_entry: &RoomListEntry| -> bool { matcher.matches(room_list_entry) })
+}
+
+/// Compute the rooms of the space again, and trigger a room list update for
+/// the rooms that were added to or removed from it.
+async fn update_room_ids(space: &Space, room_ids: &RwLock<BTreeSet<OwnedRoomId>>) {
+    let new_room_ids = match space.joined_descendants().await {
+        Ok(room_ids) => room_ids,
+        Err(error) => {
+            warn!(space_id = ?space.room_id(), "Failed to load the rooms of the space: {error}");
+            return;
+        }
+    };
+
+    let changed_room_ids = {
+        let mut room_ids = room_ids.write().unwrap();
+        let changed_room_ids =
+            room_ids.symmetric_difference(&new_room_ids).cloned().collect::<Vec<_>>();
+        *room_ids = new_room_ids;
+        changed_room_ids
+    };
+
+    let client = space.client();
+    for room_id in changed_room_ids {
+        trace!(space_id = ?space.room_id(), ?room_id, "Room added to or removed from the space");
+
+        if let Some(room) = client.get_room(&room_id) {
+            room.set_room_info(room.clone_info(), true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, ops::Not};
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::SpaceRoomMatcher;
+
+    #[test]
+    fn test_room_in_space() {
+        let matcher = SpaceRoomMatcher::new(BTreeSet::from([
+            room_id!("!r0:bar.org").to_owned(),
+            room_id!("!sub_space:bar.org").to_owned(),
+        ]));
+
+        assert!(matcher.matches(&RoomListEntry::Empty).not());
+        assert!(matcher.matches(&RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned())));
+        assert!(matcher.matches(&RoomListEntry::Invalidated(room_id!("!r0:bar.org").to_owned())));
+        assert!(matcher.matches(&RoomListEntry::Filled(room_id!("!sub_space:bar.org").to_owned())));
+    }
+
+    #[test]
+    fn test_room_not_in_space() {
+        let matcher = SpaceRoomMatcher::new(BTreeSet::from([room_id!("!r0:bar.org").to_owned()]));
+
+        assert!(matcher.matches(&RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned())).not());
+        assert!(matcher
+            .matches(&RoomListEntry::Invalidated(room_id!("!r1:bar.org").to_owned()))
+            .not());
+    }
+}
@@ -64,6 +64,7 @@
 pub mod filters;
 mod room;
 mod room_list;
+pub mod sorters;
 mod state;
 
 use std::{future::ready, num::NonZeroUsize, sync::Arc, time::Duration};
@@ -517,7 +518,7 @@ pub enum Error {
     TimelineAlreadyExists(OwnedRoomId),
 
     #[error("An error occurred while initializing the timeline")]
-    InitializingTimeline(#[source] EventCacheError),
+    InitializingTimeline(#[source] crate::timeline::Error),
 
     #[error("The attached event cache ran into an error")]
     EventCache(#[from] EventCacheError),
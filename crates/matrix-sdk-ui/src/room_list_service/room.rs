@@ -168,6 +168,7 @@ impl Room {
             .add_initial_events(
                 self.inner.room.room_id(),
                 self.inner.sliding_sync_room.timeline_queue().iter().cloned().collect(),
+                self.inner.sliding_sync_room.prev_batch(),
             )
             .await?;
 
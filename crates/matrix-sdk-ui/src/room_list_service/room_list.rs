@@ -12,7 +12,11 @@
 // See the License for that specific language governing permissions and
 // limitations under the License.
 
-use std::{future::ready, sync::Arc};
+use std::{
+    future::ready,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
 
 use async_cell::sync::AsyncCell;
 use async_rx::StreamExt as _;
@@ -28,7 +32,7 @@ use matrix_sdk::{
 use matrix_sdk_base::RoomInfoUpdate;
 use tokio::{select, sync::broadcast};
 
-use super::{filters::Filter, Error, State};
+use super::{filters::Filter, sorters::Sorter, Error, State};
 
 /// A `RoomList` represents a list of rooms, from a
 /// [`RoomListService`](super::RoomListService).
@@ -114,14 +118,19 @@ impl RoomList {
     }
 
     /// Similar to [`Self::entries`] except that it's possible to provide a
-    /// filter that will filter out room list entries, and that it's also
-    /// possible to “paginate” over the entries by `page_size`.
+    /// filter that will filter out room list entries, a sorter that will sort
+    /// them on the client side, and that it's also possible to “paginate” over
+    /// the entries by `page_size`.
     ///
     /// The returned stream will only start yielding diffs once a filter is set
     /// through the returned [`RoomListDynamicEntriesController`]. For every
-    /// call to [`RoomListDynamicEntriesController::set_filter`], the stream
-    /// will yield a [`VectorDiff::Clear`] followed by any updates of the
-    /// room list under that filter (until the next reset).
+    /// call to [`RoomListDynamicEntriesController::set_filter`], or to
+    /// [`RoomListDynamicEntriesController::set_sorter`] once a filter is set,
+    /// the stream will yield a [`VectorDiff::Reset`] followed by any updates of
+    /// the room list under that filter and sorter (until the next reset).
+    ///
+    /// Entries are sorted again when their room receives a [`RoomInfoUpdate`]
+    /// from `roominfo_update_recv`.
     pub fn entries_with_dynamic_adapters(
         &self,
         page_size: usize,
@@ -130,13 +139,13 @@ impl RoomList {
     {
         let list = self.sliding_sync_list.clone();
 
-        let filter_fn_cell = AsyncCell::shared();
+        let adapters_cell = AsyncCell::shared();
 
         let limit = SharedObservable::<usize>::new(page_size);
         let limit_stream = limit.subscribe();
 
         let dynamic_entries_controller = RoomListDynamicEntriesController::new(
-            filter_fn_cell.clone(),
+            adapters_cell.clone(),
             page_size,
             limit,
             list.maximum_number_of_rooms_stream(),
@@ -144,14 +153,25 @@ impl RoomList {
 
         let stream = stream! {
             loop {
-                let filter_fn = filter_fn_cell.take().await;
+                let DynamicAdapters { filter, sorter } = adapters_cell.take().await;
                 let (raw_values, raw_stream) = list.room_list_stream();
 
                 // Combine normal stream events with other updates from rooms
                 let merged_stream = merge_stream_and_receiver(raw_values.clone(), raw_stream, roominfo_update_recv.resubscribe());
 
                 let (values, stream) = (raw_values, merged_stream)
-                    .filter(filter_fn)
+                    .filter(move |room_list_entry| (*filter)(room_list_entry));
+
+                // Without a sorter, entries are kept in the order of the server.
+                let (values, stream) = match sorter {
+                    Some(sorter) => {
+                        let (values, stream) = (values, stream).sort_by(move |left, right| (*sorter)(left, right));
+                        (values, Box::pin(stream) as EntriesStream)
+                    }
+                    None => (values, Box::pin(stream) as EntriesStream),
+                };
+
+                let (values, stream) = (values, stream)
                     .dynamic_limit_with_initial_value(page_size, limit_stream.clone());
 
                 // Clearing the stream before chaining with the real stream.
@@ -257,12 +277,26 @@ pub enum RoomListLoadingState {
 /// Type alias for a boxed filter function.
 pub type BoxedFilterFn = Box<dyn Filter + Send + Sync>;
 
+/// Type alias for a boxed sorter function.
+pub type BoxedSorterFn = Box<dyn Sorter + Send + Sync>;
+
+/// Type alias for the stream of filtered, and maybe sorted, entries.
+type EntriesStream = Pin<Box<dyn Stream<Item = Vec<VectorDiff<RoomListEntry>>> + Send>>;
+
+/// The adapters applied on the [`RoomList`] dynamic entries.
+struct DynamicAdapters {
+    filter: Arc<BoxedFilterFn>,
+    sorter: Option<Arc<BoxedSorterFn>>,
+}
+
 /// Controller for the [`RoomList`] dynamic entries.
 ///
 /// To get one value of this type, use
 /// [`RoomList::entries_with_dynamic_adapters`]
 pub struct RoomListDynamicEntriesController {
-    filter: Arc<AsyncCell<BoxedFilterFn>>,
+    adapters: Arc<AsyncCell<DynamicAdapters>>,
+    filter: Mutex<Option<Arc<BoxedFilterFn>>>,
+    sorter: Mutex<Option<Arc<BoxedSorterFn>>>,
     page_size: usize,
     limit: SharedObservable<usize>,
     maximum_number_of_rooms: Subscriber<Option<u32>>,
@@ -270,12 +304,19 @@ pub struct RoomListDynamicEntriesController {
 
 impl RoomListDynamicEntriesController {
     fn new(
-        filter: Arc<AsyncCell<BoxedFilterFn>>,
+        adapters: Arc<AsyncCell<DynamicAdapters>>,
         page_size: usize,
         limit_stream: SharedObservable<usize>,
         maximum_number_of_rooms: Subscriber<Option<u32>>,
     ) -> Self {
-        Self { filter, page_size, limit: limit_stream, maximum_number_of_rooms }
+        Self {
+            adapters,
+            filter: Mutex::new(None),
+            sorter: Mutex::new(None),
+            page_size,
+            limit: limit_stream,
+            maximum_number_of_rooms,
+        }
     }
 
     /// Set the filter.
@@ -283,15 +324,47 @@ impl RoomListDynamicEntriesController {
     /// If the associated stream has been dropped, returns `false` to indicate
     /// the operation didn't have an effect.
     pub fn set_filter(&self, filter: BoxedFilterFn) -> bool {
-        if Arc::strong_count(&self.filter) == 1 {
-            // there is no other reference to the boxed filter fn, setting it
-            // would be pointless (no new references can be created from self,
-            // either)
-            false
-        } else {
-            self.filter.set(filter);
-            true
+        if self.is_stream_dropped() {
+            return false;
         }
+
+        let filter = Arc::new(filter);
+        *self.filter.lock().unwrap() = Some(filter.clone());
+
+        let sorter = self.sorter.lock().unwrap().clone();
+        self.adapters.set(DynamicAdapters { filter, sorter });
+
+        true
+    }
+
+    /// Set the sorter.
+    ///
+    /// The sorter is applied once a filter is set with [`Self::set_filter`].
+    /// If a filter is already set, the entries are sorted again with the new
+    /// sorter.
+    ///
+    /// If the associated stream has been dropped, returns `false` to indicate
+    /// the operation didn't have an effect.
+    pub fn set_sorter(&self, sorter: BoxedSorterFn) -> bool {
+        if self.is_stream_dropped() {
+            return false;
+        }
+
+        let sorter = Arc::new(sorter);
+        *self.sorter.lock().unwrap() = Some(sorter.clone());
+
+        if let Some(filter) = self.filter.lock().unwrap().clone() {
+            self.adapters.set(DynamicAdapters { filter, sorter: Some(sorter) });
+        }
+
+        true
+    }
+
+    /// Whether the stream associated to this controller has been dropped.
+    fn is_stream_dropped(&self) -> bool {
+        // There is no other reference to the adapters, setting them would be
+        // pointless (no new references can be created from self, either).
+        Arc::strong_count(&self.adapters) == 1
     }
 
     /// Add one page, i.e. view `page_size` more entries in the room list if
@@ -0,0 +1,79 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use super::{super::room_list::BoxedSorterFn, Sorter};
+
+/// Create a new sorter that will run multiple sorters. When the first sorter
+/// returns [`Ordering::Equal`], the next sorter is called, and so on.
+pub fn new_sorter(sorters: Vec<BoxedSorterFn>) -> impl Sorter {
+    move |left, right| -> Ordering {
+        sorters
+            .iter()
+            .map(|sorter| sorter(left, right))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::new_sorter;
+
+    #[test]
+    fn test_no_sorter() {
+        let left = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let right = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        let sorter = new_sorter(vec![]);
+
+        assert_eq!(sorter(&left, &right), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_first_sorter_wins() {
+        let left = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let right = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        let sorter1 = |_: &_, _: &_| Ordering::Greater;
+        let sorter2 = |_: &_, _: &_| Ordering::Less;
+        let sorter = new_sorter(vec![Box::new(sorter1), Box::new(sorter2)]);
+
+        assert_eq!(sorter(&left, &right), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_next_sorter_breaks_ties() {
+        let left = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let right = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        let sorter1 = |_: &_, _: &_| Ordering::Equal;
+        let sorter2 = |_: &_, _: &_| Ordering::Less;
+        let sorter = new_sorter(vec![Box::new(sorter1), Box::new(sorter2)]);
+
+        assert_eq!(sorter(&left, &right), Ordering::Less);
+
+        let sorter1 = |_: &_, _: &_| Ordering::Equal;
+        let sorter2 = |_: &_, _: &_| Ordering::Equal;
+        let sorter = new_sorter(vec![Box::new(sorter1), Box::new(sorter2)]);
+
+        assert_eq!(sorter(&left, &right), Ordering::Equal);
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A collection of room sorters.
+//!
+//! The room list can provide an access to the rooms per list, like with
+//! [`super::RoomList::entries_with_dynamic_adapters`]. The provided collection
+//! of rooms can be sorted with these sorters, on the client side, with data
+//! that the server doesn't know about. A classical usage would be the
+//! following:
+//!
+//! ```rust
+//! use matrix_sdk::Client;
+//! use matrix_sdk_ui::room_list_service::{
+//!     sorters, RoomListDynamicEntriesController,
+//! };
+//!
+//! fn configure_room_list(
+//!     client: &Client,
+//!     entries_controller: &RoomListDynamicEntriesController,
+//! ) {
+//!     // Unread rooms first,
+//!     // _then_ the most recent rooms,
+//!     // _then_ by name.
+//!     entries_controller.set_sorter(Box::new(
+//!         // Lexicographic
+//!         sorters::new_sorter_lexicographic(vec![
+//!             // Unread
+//!             Box::new(sorters::new_sorter_unread(client)),
+//!             // Recency
+//!             Box::new(sorters::new_sorter_recency(client)),
+//!             // Name
+//!             Box::new(sorters::new_sorter_name(client)),
+//!         ]),
+//!     ));
+//! }
+//! ```
+
+mod lexicographic;
+mod name;
+mod recency;
+mod unread;
+
+use std::cmp::Ordering;
+
+pub use lexicographic::new_sorter as new_sorter_lexicographic;
+use matrix_sdk::RoomListEntry;
+pub use name::new_sorter as new_sorter_name;
+pub use recency::new_sorter as new_sorter_recency;
+pub use unread::new_sorter as new_sorter_unread;
+
+/// A trait “alias” that represents a _sorter_.
+///
+/// A sorter is simply a function that receives two `&RoomListEntry` and
+/// returns their [`Ordering`].
+pub trait Sorter: Fn(&RoomListEntry, &RoomListEntry) -> Ordering {}
+
+impl<F> Sorter for F where F: Fn(&RoomListEntry, &RoomListEntry) -> Ordering {}
+
+/// Compare two optional values, so that the present values come first.
+///
+/// Entries for which a sorter can't find the data it sorts on, e.g. because
+/// the entry is empty, are sorted after the other ones.
+fn cmp_present_first<T>(
+    left: Option<T>,
+    right: Option<T>,
+    cmp: impl FnOnce(T, T) -> Ordering,
+) -> Ordering {
+    match (left, right) {
+        (Some(left), Some(right)) => cmp(left, right),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::cmp_present_first;
+
+    #[test]
+    fn test_cmp_present_first() {
+        assert_eq!(cmp_present_first(Some(1), Some(2), |l, r| l.cmp(&r)), Ordering::Less);
+        assert_eq!(cmp_present_first(Some(2), Some(1), |l, r| l.cmp(&r)), Ordering::Greater);
+        assert_eq!(cmp_present_first(Some(2), None, |l, r| l.cmp(&r)), Ordering::Less);
+        assert_eq!(cmp_present_first(None, Some(1), |l, r| l.cmp(&r)), Ordering::Greater);
+        assert_eq!(cmp_present_first::<u8>(None, None, |l, r| l.cmp(&r)), Ordering::Equal);
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use matrix_sdk::{Client, RoomListEntry};
+
+use super::{cmp_present_first, Sorter};
+
+struct NameMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<String>,
+{
+    name: F,
+}
+
+impl<F> NameMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<String>,
+{
+    fn matches(&self, left: &RoomListEntry, right: &RoomListEntry) -> Ordering {
+        cmp_present_first((self.name)(left), (self.name)(right), |left, right| {
+            left.to_lowercase().cmp(&right.to_lowercase())
+        })
+    }
+}
+
+/// Create a new sorter that will sort rooms alphabetically by name, case
+/// insensitively.
+///
+/// Rooms are fetched from the `Client`. Entries without a name come last.
+pub fn new_sorter(client: &Client) -> impl Sorter {
+    let client = client.clone();
+
+    let matcher = NameMatcher {
+        name: move |room_list_entry| {
+            let room_id = room_list_entry.as_room_id()?;
+            let room = client.get_room(room_id)?;
+
+            room.name()
+        },
+    };
+
+    move |left, right| -> Ordering { matcher.matches(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::NameMatcher;
+
+    #[test]
+    fn test_alphabetical_case_insensitive() {
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let r1 = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        let matcher = NameMatcher {
+            name: |room_list_entry| {
+                if room_list_entry.as_room_id()? == room_id!("!r0:bar.org") {
+                    Some("banana".to_owned())
+                } else {
+                    Some("Apple".to_owned())
+                }
+            },
+        };
+
+        assert_eq!(matcher.matches(&r0, &r1), Ordering::Greater);
+        assert_eq!(matcher.matches(&r1, &r0), Ordering::Less);
+    }
+
+    #[test]
+    fn test_no_name_last() {
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let r1 = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        let matcher = NameMatcher {
+            name: |room_list_entry| {
+                (room_list_entry.as_room_id()? == room_id!("!r0:bar.org")).then(|| "Zoo".to_owned())
+            },
+        };
+
+        assert_eq!(matcher.matches(&r0, &r1), Ordering::Less);
+        assert_eq!(matcher.matches(&r1, &r0), Ordering::Greater);
+        assert_eq!(matcher.matches(&r1, &r1), Ordering::Equal);
+    }
+}
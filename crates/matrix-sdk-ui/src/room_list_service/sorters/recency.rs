@@ -0,0 +1,111 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use matrix_sdk::{Client, RoomListEntry};
+use ruma::MilliSecondsSinceUnixEpoch;
+
+use super::{cmp_present_first, Sorter};
+
+struct RecencyMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<MilliSecondsSinceUnixEpoch>,
+{
+    timestamp: F,
+}
+
+impl<F> RecencyMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<MilliSecondsSinceUnixEpoch>,
+{
+    fn matches(&self, left: &RoomListEntry, right: &RoomListEntry) -> Ordering {
+        // The most recent room comes first.
+        cmp_present_first((self.timestamp)(left), (self.timestamp)(right), |left, right| {
+            right.cmp(&left)
+        })
+    }
+}
+
+/// Create a new sorter that will sort rooms by recency, i.e. by the timestamp
+/// of their latest event, the most recent first.
+///
+/// The latest event is the one computed by the client, so it can be a
+/// decrypted event. Entries without a latest event come last.
+pub fn new_sorter(client: &Client) -> impl Sorter {
+    let client = client.clone();
+
+    let matcher = RecencyMatcher {
+        timestamp: move |room_list_entry| {
+            let room_id = room_list_entry.as_room_id()?;
+            let room = client.get_room(room_id)?;
+
+            room.latest_event()?
+                .event()
+                .event
+                .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+                .ok()
+                .flatten()
+        },
+    };
+
+    move |left, right| -> Ordering { matcher.matches(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::{room_id, uint, MilliSecondsSinceUnixEpoch};
+
+    use super::RecencyMatcher;
+
+    #[test]
+    fn test_most_recent_first() {
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let r1 = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        let matcher = RecencyMatcher {
+            timestamp: |room_list_entry| {
+                let ts = if room_list_entry.as_room_id()? == room_id!("!r0:bar.org") {
+                    uint!(10)
+                } else {
+                    uint!(20)
+                };
+
+                Some(MilliSecondsSinceUnixEpoch(ts))
+            },
+        };
+
+        assert_eq!(matcher.matches(&r0, &r1), Ordering::Greater);
+        assert_eq!(matcher.matches(&r1, &r0), Ordering::Less);
+        assert_eq!(matcher.matches(&r0, &r0), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_no_timestamp_last() {
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+
+        let matcher = RecencyMatcher {
+            timestamp: |room_list_entry| {
+                room_list_entry.as_room_id().map(|_| MilliSecondsSinceUnixEpoch(uint!(10)))
+            },
+        };
+
+        assert_eq!(matcher.matches(&r0, &RoomListEntry::Empty), Ordering::Less);
+        assert_eq!(matcher.matches(&RoomListEntry::Empty, &r0), Ordering::Greater);
+        assert_eq!(matcher.matches(&RoomListEntry::Empty, &RoomListEntry::Empty), Ordering::Equal);
+    }
+}
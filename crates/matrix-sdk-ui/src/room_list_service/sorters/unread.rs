@@ -0,0 +1,98 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use matrix_sdk::{Client, RoomListEntry};
+
+use super::{cmp_present_first, Sorter};
+
+struct UnreadMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<bool>,
+{
+    is_unread: F,
+}
+
+impl<F> UnreadMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<bool>,
+{
+    fn matches(&self, left: &RoomListEntry, right: &RoomListEntry) -> Ordering {
+        // Unread rooms come first, i.e. `true` before `false`.
+        cmp_present_first((self.is_unread)(left), (self.is_unread)(right), |left, right| {
+            right.cmp(&left)
+        })
+    }
+}
+
+/// Create a new sorter that will put the unread rooms first.
+///
+/// A room is unread if it has unread notifications (different from unread
+/// messages), or if it is marked as unread. Two unread, or two read, rooms are
+/// considered equal, so this sorter is best combined with other sorters with
+/// [`super::new_sorter_lexicographic`].
+pub fn new_sorter(client: &Client) -> impl Sorter {
+    let client = client.clone();
+
+    let matcher = UnreadMatcher {
+        is_unread: move |room_list_entry| {
+            let room_id = room_list_entry.as_room_id()?;
+            let room = client.get_room(room_id)?;
+
+            Some(room.read_receipts().num_notifications > 0 || room.is_marked_unread())
+        },
+    };
+
+    move |left, right| -> Ordering { matcher.matches(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::UnreadMatcher;
+
+    #[test]
+    fn test_unread_first() {
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let r1 = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        let matcher = UnreadMatcher {
+            is_unread: |room_list_entry| {
+                Some(room_list_entry.as_room_id()? == room_id!("!r1:bar.org"))
+            },
+        };
+
+        assert_eq!(matcher.matches(&r0, &r1), Ordering::Greater);
+        assert_eq!(matcher.matches(&r1, &r0), Ordering::Less);
+        assert_eq!(matcher.matches(&r0, &r0), Ordering::Equal);
+        assert_eq!(matcher.matches(&r1, &r1), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_unknown_rooms_last() {
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+
+        let matcher = UnreadMatcher {
+            is_unread: |room_list_entry| room_list_entry.as_room_id().map(|_| false),
+        };
+
+        assert_eq!(matcher.matches(&r0, &RoomListEntry::Empty), Ordering::Less);
+        assert_eq!(matcher.matches(&RoomListEntry::Empty, &r0), Ordering::Greater);
+    }
+}
@@ -26,7 +26,7 @@
 use std::sync::{Arc, Mutex};
 
 use eyeball::{SharedObservable, Subscriber};
-use futures_core::Future;
+use futures_core::{Future, Stream};
 use futures_util::{pin_mut, StreamExt as _};
 use matrix_sdk::Client;
 use thiserror::Error;
@@ -41,6 +41,7 @@ use tracing::{error, info, instrument, trace, warn, Instrument, Level};
 
 use crate::{
     encryption_sync_service::{self, EncryptionSyncPermit, EncryptionSyncService, WithLocking},
+    notification_client::{self, NotificationItem},
     room_list_service::{self, RoomListService},
 };
 
@@ -128,6 +129,14 @@ impl SyncService {
         self.state.subscribe()
     }
 
+    /// Get a stream of the notifications triggered by the events received by
+    /// this sync service.
+    ///
+    /// See [`notification_client::local_notifications`] for the details.
+    pub async fn notifications(&self) -> impl Stream<Item = NotificationItem> {
+        notification_client::local_notifications(self.room_list_service.client()).await
+    }
+
     /// The role of the scheduler task is to wait for a termination message
     /// (`TerminationReport`), sent either because we wanted to stop both
     /// syncs, or because one of the syncs failed (in which case we'll stop
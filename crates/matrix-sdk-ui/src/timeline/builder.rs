@@ -12,29 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::BTreeSet, sync::Arc};
+use std::{collections::BTreeSet, iter, sync::Arc};
 
 use eyeball::SharedObservable;
 use futures_util::{pin_mut, StreamExt};
 use matrix_sdk::{
-    event_cache::{self, RoomEventCacheUpdate},
+    event_cache::RoomEventCacheUpdate,
     executor::spawn,
+    send_queue::{LocalEcho, RoomSendQueueError, RoomSendQueueUpdate},
     Room,
 };
 use matrix_sdk_base::sync::JoinedRoomUpdate;
 use ruma::{
-    events::{receipt::ReceiptType, AnySyncTimelineEvent},
-    RoomVersionId,
+    events::{
+        receipt::ReceiptType, relation::Thread, room::encrypted::Relation,
+        AnyMessageLikeEventContent, AnySyncMessageLikeEvent, AnySyncTimelineEvent,
+    },
+    EventId, RoomVersionId,
 };
-use tokio::sync::{broadcast, mpsc, Notify};
+use tokio::sync::{broadcast, Notify};
 use tracing::{info, info_span, trace, warn, Instrument, Span};
 
 #[cfg(feature = "e2e-encryption")]
 use super::to_device::{handle_forwarded_room_key_event, handle_room_key_event};
 use super::{
+    event_item::RemoteEventOrigin,
     inner::{TimelineInner, TimelineInnerSettings},
-    queue::send_queued_messages,
-    BackPaginationStatus, Timeline, TimelineDropHandle,
+    BackPaginationStatus, Error, EventSendState, Timeline, TimelineDropHandle, TimelineFocus,
 };
 
 /// Builder that allows creating and configuring various parts of a
@@ -101,6 +105,22 @@ impl TimelineBuilder {
         self
     }
 
+    /// Choose what the timeline is focused on.
+    ///
+    /// When focused on an event, [`build`](Self::build) loads the event and
+    /// its context, and fails if the event can't be found.
+    ///
+    /// When focused on a thread, the timeline starts empty and
+    /// [`Timeline::paginate_backwards`] loads the replies of the thread, then
+    /// its root. Only the events of the thread are added to the timeline, in
+    /// addition to the [`event_filter`](Self::event_filter).
+    ///
+    /// Defaults to [`TimelineFocus::Live`].
+    pub fn with_focus(mut self, focus: TimelineFocus) -> Self {
+        self.settings.focus = focus;
+        self
+    }
+
     /// Whether to add events that failed to deserialize to the timeline.
     ///
     /// Defaults to `true`.
@@ -109,6 +129,22 @@ impl TimelineBuilder {
         self
     }
 
+    /// Whether back-pagination should continue into the room that this room
+    /// replaces, once the start of this room is reached.
+    ///
+    /// This happens only if the predecessor room is known to the client, and
+    /// is repeated for the predecessors of the predecessor room. The
+    /// `m.room.create` event of the room separates the events of the two
+    /// rooms in the timeline.
+    ///
+    /// This has no effect for timelines focused on a thread.
+    ///
+    /// Defaults to `false`.
+    pub fn paginate_predecessor_rooms(mut self, paginate: bool) -> Self {
+        self.settings.paginate_predecessor_rooms = paginate;
+        self
+    }
+
     /// Create a [`Timeline`] with the options set on this builder.
     #[tracing::instrument(
         skip(self),
@@ -116,10 +152,20 @@ impl TimelineBuilder {
             room_id = ?self.room.room_id(),
             track_read_receipts = self.settings.track_read_receipts,
             prev_token = self.prev_token,
+            focus = ?self.settings.focus,
         )
     )]
-    pub async fn build(self) -> event_cache::Result<Timeline> {
-        let Self { room, prev_token, settings } = self;
+    pub async fn build(self) -> Result<Timeline, Error> {
+        let Self { room, prev_token, mut settings } = self;
+
+        if let TimelineFocus::Thread { root_event_id } = &settings.focus {
+            let root_event_id = root_event_id.clone();
+            let event_filter = settings.event_filter.clone();
+            settings.event_filter =
+                Arc::new(move |event: &AnySyncTimelineEvent, room_version: &RoomVersionId| {
+                    is_in_thread(event, &root_event_id) && event_filter(event, room_version)
+                });
+        }
 
         let client = room.client();
         let event_cache = client.event_cache();
@@ -128,7 +174,44 @@ impl TimelineBuilder {
         event_cache.subscribe()?;
 
         let (room_event_cache, event_cache_drop) = event_cache.for_room(room.room_id()).await?;
-        let (events, mut event_subscriber) = room_event_cache.subscribe().await?;
+        let (mut events, mut event_subscriber) = room_event_cache.subscribe().await?;
+
+        let mut next_token = None;
+        let mut origin = RemoteEventOrigin::Cache;
+        let prev_token = match &settings.focus {
+            // Fall back to the token persisted by the event cache, so back-pagination
+            // resumes from the oldest cached event after a restart.
+            TimelineFocus::Live => match prev_token {
+                Some(token) => Some(token),
+                None => room_event_cache.back_pagination_token().await?,
+            },
+            TimelineFocus::Event { target, num_context_events } => {
+                let context = room
+                    .event_with_context(target, true, (*num_context_events).into())
+                    .await
+                    .map_err(Error::FocusedEventLoadingFailed)?;
+                let event = context.event.ok_or(Error::FocusedEventNotFound)?;
+
+                // The events before the target event are in reverse chronological order.
+                events = context
+                    .events_before
+                    .into_iter()
+                    .rev()
+                    .chain(iter::once(event))
+                    .chain(context.events_after)
+                    .map(Into::into)
+                    .collect();
+                next_token = context.next_batch_token;
+                origin = RemoteEventOrigin::Pagination;
+                context.prev_batch_token
+            }
+            // A thread is loaded from scratch with the relations API, the cached events and
+            // their token only make sense for the live timeline.
+            TimelineFocus::Thread { .. } => {
+                events.clear();
+                None
+            }
+        };
 
         let has_events = !events.is_empty();
         let track_read_marker_and_receipts = settings.track_read_receipts;
@@ -141,8 +224,9 @@ impl TimelineBuilder {
         }
 
         if has_events {
-            inner.add_initial_events(events, prev_token).await;
+            inner.add_initial_events(events, prev_token, origin).await;
         }
+        inner.set_forward_pagination_token(next_token).await;
         if track_read_marker_and_receipts {
             inner.load_fully_read_event().await;
         }
@@ -169,27 +253,39 @@ impl TimelineBuilder {
                         Ok(up) => up,
                         Err(broadcast::error::RecvError::Closed) => break,
                         Err(broadcast::error::RecvError::Lagged(_)) => {
-                            warn!("Lagged behind sync responses, resetting timeline");
-                            inner.clear().await;
+                            if inner.is_live().await {
+                                warn!("Lagged behind sync responses, resetting timeline");
+                                inner.clear().await;
+                            }
                             continue;
                         }
                     };
 
                     match update {
                         RoomEventCacheUpdate::Clear => {
-                            trace!("Clearing the timeline.");
-                            inner.clear().await;
+                            if inner.is_live().await {
+                                trace!("Clearing the timeline.");
+                                inner.clear().await;
+                            }
                         }
 
                         RoomEventCacheUpdate::Append {
-                            events,
-                            prev_batch,
+                            mut events,
+                            mut prev_batch,
                             account_data,
                             ephemeral,
                             ambiguity_changes,
                         } => {
                             trace!("Received new events");
 
+                            // Until forward-pagination reaches the live end of the room, adding
+                            // new events would leave a gap in the timeline.
+                            if !inner.is_live().await {
+                                trace!("Timeline isn't live, ignoring the new events");
+                                events.clear();
+                                prev_batch = None;
+                            }
+
                             // XXX this timeline and the joined room updates are synthetic, until
                             // we get rid of `handle_joined_room_update` by adding all functionality
                             // back in the event cache, and replacing it with a simple
@@ -286,22 +382,57 @@ impl TimelineBuilder {
             })
         };
 
-        let (msg_sender, msg_receiver) = mpsc::channel(1);
-        info!("Starting message-sending loop");
-        spawn(send_queued_messages(inner.clone(), room.clone(), msg_receiver));
+        let send_queue = room.send_queue();
+        let mut send_queue_updates = send_queue.subscribe();
+
+        match send_queue.local_echoes().await {
+            Ok(local_echoes) => {
+                for local_echo in local_echoes {
+                    handle_local_echo(&inner, local_echo).await;
+                }
+            }
+            Err(error) => warn!("Couldn't load the unsent events: {error}"),
+        }
+
+        let send_queue_join_handle = spawn({
+            let inner = inner.clone();
+
+            let span = info_span!(parent: Span::none(), "send_queue_update_handler", room_id = ?room.room_id());
+            span.follows_from(Span::current());
+
+            async move {
+                info!("Listening to the send queue updates");
+
+                loop {
+                    let update = match send_queue_updates.recv().await {
+                        Ok(update) => update,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                        Err(broadcast::error::RecvError::Lagged(num_missed)) => {
+                            warn!("Lagged behind {num_missed} send queue updates");
+                            continue;
+                        }
+                    };
+
+                    handle_send_queue_update(&inner, update).await;
+                }
+            }
+            .instrument(span)
+        });
 
         let timeline = Timeline {
             inner,
+            event_cache: room_event_cache,
             back_pagination_mtx: Default::default(),
+            forward_pagination_mtx: Default::default(),
             back_pagination_status: SharedObservable::new(BackPaginationStatus::Idle),
             sync_response_notify,
-            msg_sender,
             drop_handle: Arc::new(TimelineDropHandle {
                 client,
                 event_handler_handles: handles,
                 room_update_join_handle,
                 ignore_user_list_update_join_handle,
                 room_key_from_backups_join_handle,
+                send_queue_join_handle,
                 _event_cache_drop_handle: event_cache_drop,
             }),
         };
@@ -317,3 +448,87 @@ impl TimelineBuilder {
         Ok(timeline)
     }
 }
+
+/// Add an event that is in the room's send queue to the timeline, as a local
+/// echo.
+async fn handle_local_echo(inner: &TimelineInner, local_echo: LocalEcho) {
+    let content = match local_echo.deserialize_content() {
+        Ok(AnyMessageLikeEventContent::Reaction(_)) => {
+            // Local reactions are handled by `Timeline::toggle_reaction`.
+            trace!("Ignoring a queued reaction");
+            return;
+        }
+        Ok(content) => content,
+        Err(error) => {
+            warn!("Couldn't deserialize the content of a local echo: {error}");
+            return;
+        }
+    };
+
+    let txn_id = local_echo.transaction_id;
+    inner.handle_local_event(txn_id.clone(), content).await;
+
+    if local_echo.is_wedged {
+        // The error that wedged the event was in a previous session.
+        let error = Arc::new(matrix_sdk::Error::SendQueue(RoomSendQueueError::WedgedEvent));
+        inner.update_event_send_state(&txn_id, EventSendState::SendingFailed { error }).await;
+    }
+}
+
+async fn handle_send_queue_update(inner: &TimelineInner, update: RoomSendQueueUpdate) {
+    match update {
+        RoomSendQueueUpdate::NewLocalEvent(local_echo) => {
+            handle_local_echo(inner, local_echo).await;
+        }
+
+        RoomSendQueueUpdate::CancelledLocalEvent { transaction_id } => {
+            inner.discard_local_echo(&transaction_id).await;
+        }
+
+        RoomSendQueueUpdate::RetryEvent { transaction_id } => {
+            // The timeline that retried the event has already updated it; other
+            // timelines update it once it's sent.
+            trace!(txn_id = %transaction_id, "Event is being retried");
+        }
+
+        RoomSendQueueUpdate::SendError { transaction_id, error, is_recoverable } => {
+            if is_recoverable {
+                // The send queue will retry by itself, keep the event as not sent yet.
+                trace!(txn_id = %transaction_id, "Recoverable error when sending an event");
+            } else {
+                inner
+                    .update_event_send_state(
+                        &transaction_id,
+                        EventSendState::SendingFailed { error },
+                    )
+                    .await;
+            }
+        }
+
+        RoomSendQueueUpdate::SentEvent { transaction_id, event_id } => {
+            inner.update_event_send_state(&transaction_id, EventSendState::Sent { event_id }).await;
+        }
+    }
+}
+
+/// Whether the given event belongs to the given thread.
+///
+/// This is the case for the thread root and the events in the thread, but also
+/// for the edits, reactions and redactions, which only apply to the events that
+/// are in the timeline.
+fn is_in_thread(event: &AnySyncTimelineEvent, root_event_id: &EventId) -> bool {
+    if event.event_id() == root_event_id {
+        return true;
+    }
+
+    let AnySyncTimelineEvent::MessageLike(event) = event else { return false };
+    if let AnySyncMessageLikeEvent::RoomRedaction(_) = event {
+        return true;
+    }
+
+    match event.original_content().and_then(|content| content.relation()) {
+        Some(Relation::Thread(Thread { event_id, .. })) => event_id == root_event_id,
+        Some(Relation::Annotation(_) | Relation::Replacement(_)) => true,
+        _ => false,
+    }
+}
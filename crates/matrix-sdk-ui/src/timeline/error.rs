@@ -14,6 +14,7 @@
 
 use std::fmt;
 
+use matrix_sdk::{event_cache::EventCacheError, send_queue::RoomSendQueueError};
 use thiserror::Error;
 
 /// Errors specific to the timeline.
@@ -55,6 +56,23 @@ pub enum Error {
     /// Could not get user
     #[error("User ID is not available")]
     UserIdNotAvailable,
+
+    /// The event could not be queued for sending.
+    #[error(transparent)]
+    SendQueueError(#[from] RoomSendQueueError),
+
+    /// The event cache ran into an error.
+    #[error(transparent)]
+    EventCacheError(#[from] EventCacheError),
+
+    /// The event the timeline is focused on couldn't be loaded.
+    #[error("Failed to load the focused event")]
+    FocusedEventLoadingFailed(#[source] matrix_sdk::Error),
+
+    /// The event the timeline is focused on doesn't exist, or isn't visible to
+    /// the current user.
+    #[error("The focused event was not found")]
+    FocusedEventNotFound,
 }
 
 #[derive(Error)]
@@ -198,8 +198,8 @@ impl TimelineEventKind {
 pub(super) enum TimelineItemPosition {
     Start,
     End {
-        /// Whether this event is coming from a local cache.
-        from_cache: bool,
+        /// Where this event is coming from.
+        origin: RemoteEventOrigin,
     },
     #[cfg(feature = "e2e-encryption")]
     Update(usize),
@@ -292,7 +292,16 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
                     self.handle_room_message_edit(re);
                 }
                 AnyMessageLikeEventContent::RoomMessage(c) => {
+                    let thread_root = as_variant!(
+                        &c.relates_to,
+                        Some(message::Relation::Thread(thread)) => thread.event_id.clone()
+                    );
+
                     self.add(should_add, TimelineItemContent::message(c, relations, self.items));
+
+                    if let Some(thread_root) = thread_root {
+                        self.handle_thread_reply(&thread_root);
+                    }
                 }
                 AnyMessageLikeEventContent::RoomEncrypted(c) => {
                     // TODO: Handle replacements if the replaced event is also UTD
@@ -406,6 +415,7 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
                 msgtype,
                 in_reply_to: msg.in_reply_to.clone(),
                 thread_root: msg.thread_root.clone(),
+                thread_summary: msg.thread_summary.clone(),
                 edited: true,
             });
 
@@ -419,6 +429,39 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
         });
     }
 
+    /// Update the summary of the thread root for a remote reply.
+    ///
+    /// The replies that are older than the latest reply of the summary bundled
+    /// with the thread root are already counted in it.
+    #[instrument(skip_all, fields(thread_root = ?thread_root))]
+    fn handle_thread_reply(&mut self, thread_root: &EventId) {
+        let Flow::Remote { event_id, .. } = &self.ctx.flow else {
+            return;
+        };
+
+        let event_id = event_id.clone();
+        let sender = self.ctx.sender.clone();
+        let timestamp = self.ctx.timestamp;
+        let is_own_event = self.ctx.is_own_event;
+
+        update_timeline_item!(self, thread_root, "thread reply", |event_item| {
+            let TimelineItemContent::Message(msg) = event_item.content() else {
+                info!("Thread root is {}, discarding", event_item.content().debug_string());
+                return None;
+            };
+
+            let Some(new_msg) = msg.with_thread_reply(event_id, sender, timestamp, is_own_event)
+            else {
+                trace!("Reply already counted in the thread summary");
+                return None;
+            };
+
+            trace!("Updating thread summary");
+            let new_content = TimelineItemContent::Message(new_msg);
+            Some(event_item.with_content(new_content, event_item.latest_edit_json().cloned()))
+        });
+    }
+
     // Redacted reaction events are no-ops so don't need to be handled
     #[instrument(skip_all, fields(relates_to_event_id = ?c.relates_to.event_id))]
     fn handle_reaction(&mut self, c: ReactionEventContent) {
@@ -772,9 +815,7 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
 
                 let origin = match position {
                     TimelineItemPosition::Start => RemoteEventOrigin::Pagination,
-                    // We only paginate backwards for now, so End only happens for syncs
-                    TimelineItemPosition::End { from_cache: true } => RemoteEventOrigin::Cache,
-                    TimelineItemPosition::End { from_cache: false } => RemoteEventOrigin::Sync,
+                    TimelineItemPosition::End { origin } => *origin,
                     #[cfg(feature = "e2e-encryption")]
                     TimelineItemPosition::Update(idx) => self.items[*idx]
                         .as_event()
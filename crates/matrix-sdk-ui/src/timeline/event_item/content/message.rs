@@ -21,7 +21,7 @@ use matrix_sdk::deserialized_responses::TimelineEvent;
 use ruma::{
     assign,
     events::{
-        relation::{InReplyTo, Thread},
+        relation::{BundledThread, InReplyTo, Thread},
         room::{
             message,
             message::{
@@ -33,9 +33,10 @@ use ruma::{
         BundledMessageLikeRelations,
     },
     html::RemoveReplyFallback,
-    OwnedEventId, OwnedUserId, RoomVersionId, UserId,
+    EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId, RoomVersionId, UserId,
 };
 use tracing::error;
+use url::Url;
 
 use super::TimelineItemContent;
 use crate::{
@@ -54,6 +55,8 @@ pub struct Message {
     pub(in crate::timeline) in_reply_to: Option<InReplyToDetails>,
     /// Event ID of the thread root, if this is a threaded message.
     pub(in crate::timeline) thread_root: Option<OwnedEventId>,
+    /// Summary of the thread rooted at this message, if any.
+    pub(in crate::timeline) thread_summary: Option<ThreadSummary>,
     pub(in crate::timeline) edited: bool,
 }
 
@@ -83,6 +86,8 @@ impl Message {
             }
         });
 
+        let thread_summary = relations.thread.map(|thread| ThreadSummary::from_bundled(&thread));
+
         let mut thread_root = None;
         let in_reply_to = c.relates_to.and_then(|relation| match relation {
             message::Relation::Reply { in_reply_to } => {
@@ -116,7 +121,7 @@ impl Message {
             }
         };
 
-        Self { msgtype, in_reply_to, thread_root, edited }
+        Self { msgtype, in_reply_to, thread_root, thread_summary, edited }
     }
 
     /// Get the `msgtype`-specific data of this message.
@@ -131,6 +136,30 @@ impl Message {
         self.msgtype.body()
     }
 
+    /// Get the first web link in the body of this message, if any.
+    ///
+    /// Only text, notice and emote messages are considered. This can be used
+    /// to show a preview of the link with
+    /// [`Media::get_url_preview()`](matrix_sdk::media::Media::get_url_preview).
+    pub fn first_link(&self) -> Option<Url> {
+        let body = match &self.msgtype {
+            MessageType::Text(content) => &content.body,
+            MessageType::Notice(content) => &content.body,
+            MessageType::Emote(content) => &content.body,
+            _ => return None,
+        };
+
+        body.split_whitespace().find_map(|word| {
+            // Links can be wrapped in brackets, or followed by punctuation.
+            let start = word.find("https://").or_else(|| word.find("http://"))?;
+            let link = word[start..].trim_end_matches(|c: char| {
+                matches!(c, '.' | ',' | ':' | ';' | '!' | '?' | ')' | ']' | '>' | '"' | '\'')
+            });
+
+            Url::parse(link).ok().filter(|url| url.host().is_some())
+        })
+    }
+
     /// Get the event this message is replying to, if any.
     pub fn in_reply_to(&self) -> Option<&InReplyToDetails> {
         self.in_reply_to.as_ref()
@@ -141,6 +170,18 @@ impl Message {
         self.thread_root.is_some()
     }
 
+    /// Get the event ID of the root of the thread this message is part of, if
+    /// any.
+    pub fn thread_root(&self) -> Option<&EventId> {
+        self.thread_root.as_deref()
+    }
+
+    /// Get the summary of the thread rooted at this message, if it is the root
+    /// of a thread.
+    pub fn thread_summary(&self) -> Option<&ThreadSummary> {
+        self.thread_summary.as_ref()
+    }
+
     /// Get the edit state of this message (has been edited: `true` /
     /// `false`).
     pub fn is_edited(&self) -> bool {
@@ -160,6 +201,75 @@ impl Message {
     pub(in crate::timeline) fn with_in_reply_to(&self, in_reply_to: InReplyToDetails) -> Self {
         Self { in_reply_to: Some(in_reply_to), ..self.clone() }
     }
+
+    /// Clone this message, and count a new reply in the thread rooted at it.
+    ///
+    /// Returns `None` if the reply is already counted in the summary, i.e. if
+    /// it isn't more recent than the latest known reply.
+    pub(in crate::timeline) fn with_thread_reply(
+        &self,
+        event_id: OwnedEventId,
+        sender: OwnedUserId,
+        timestamp: MilliSecondsSinceUnixEpoch,
+        is_own_reply: bool,
+    ) -> Option<Self> {
+        let mut summary = self.thread_summary.clone().unwrap_or_default();
+        if summary.latest_reply_event_id.as_ref() == Some(&event_id)
+            || summary.latest_reply_ts.is_some_and(|latest_ts| latest_ts > timestamp)
+        {
+            return None;
+        }
+
+        summary.num_replies += 1;
+        summary.latest_reply_event_id = Some(event_id);
+        summary.latest_reply_sender = Some(sender);
+        summary.latest_reply_ts = Some(timestamp);
+        summary.current_user_participated |= is_own_reply;
+
+        Some(Self { thread_summary: Some(summary), ..self.clone() })
+    }
+}
+
+/// A summary of the thread rooted at a message.
+#[derive(Clone, Debug, Default)]
+pub struct ThreadSummary {
+    pub(in crate::timeline) num_replies: u64,
+    pub(in crate::timeline) latest_reply_event_id: Option<OwnedEventId>,
+    pub(in crate::timeline) latest_reply_sender: Option<OwnedUserId>,
+    pub(in crate::timeline) latest_reply_ts: Option<MilliSecondsSinceUnixEpoch>,
+    pub(in crate::timeline) current_user_participated: bool,
+}
+
+impl ThreadSummary {
+    fn from_bundled(thread: &BundledThread) -> Self {
+        Self {
+            num_replies: thread.count.into(),
+            latest_reply_event_id: thread.latest_event.get_field("event_id").ok().flatten(),
+            latest_reply_sender: thread.latest_event.get_field("sender").ok().flatten(),
+            latest_reply_ts: thread.latest_event.get_field("origin_server_ts").ok().flatten(),
+            current_user_participated: thread.current_user_participated,
+        }
+    }
+
+    /// The number of replies in the thread.
+    pub fn num_replies(&self) -> u64 {
+        self.num_replies
+    }
+
+    /// The event ID of the latest reply in the thread, if known.
+    pub fn latest_reply_event_id(&self) -> Option<&EventId> {
+        self.latest_reply_event_id.as_deref()
+    }
+
+    /// The sender of the latest reply in the thread, if known.
+    pub fn latest_reply_sender(&self) -> Option<&UserId> {
+        self.latest_reply_sender.as_deref()
+    }
+
+    /// Whether the current user sent a reply in the thread.
+    pub fn current_user_participated(&self) -> bool {
+        self.current_user_participated
+    }
 }
 
 impl From<Message> for RoomMessageEventContent {
@@ -195,12 +305,13 @@ fn make_relates_to(
 #[cfg(not(tarpaulin_include))]
 impl fmt::Debug for Message {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Self { msgtype: _, in_reply_to, thread_root, edited } = self;
+        let Self { msgtype: _, in_reply_to, thread_root, thread_summary, edited } = self;
         // since timeline items are logged, don't include all fields here so
         // people don't leak personal data in bug reports
         f.debug_struct("Message")
             .field("in_reply_to", in_reply_to)
             .field("thread_root", thread_root)
+            .field("thread_summary", thread_summary)
             .field("edited", edited)
             .finish_non_exhaustive()
     }
@@ -301,3 +412,63 @@ impl RepliedToEvent {
         Ok(Self { content, sender, sender_profile })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ruma::events::room::message::{
+        LocationMessageEventContent, MessageType, RoomMessageEventContent,
+    };
+
+    use super::Message;
+
+    fn first_link_of(content: RoomMessageEventContent) -> Option<String> {
+        let message = Message {
+            msgtype: content.msgtype,
+            in_reply_to: None,
+            thread_root: None,
+            thread_summary: None,
+            edited: false,
+        };
+        message.first_link().map(String::from)
+    }
+
+    #[test]
+    fn first_link_in_text_message() {
+        let link = first_link_of(RoomMessageEventContent::text_plain(
+            "Have a look at https://matrix.org/blog/ and http://example.org",
+        ));
+        assert_eq!(link.as_deref(), Some("https://matrix.org/blog/"));
+    }
+
+    #[test]
+    fn first_link_trims_punctuation() {
+        let link = first_link_of(RoomMessageEventContent::text_plain(
+            "The spec (https://spec.matrix.org/latest/).",
+        ));
+        assert_eq!(link.as_deref(), Some("https://spec.matrix.org/latest/"));
+
+        let link =
+            first_link_of(RoomMessageEventContent::notice_plain("See <https://matrix.org>!"));
+        assert_eq!(link.as_deref(), Some("https://matrix.org/"));
+    }
+
+    #[test]
+    fn no_first_link() {
+        let link = first_link_of(RoomMessageEventContent::text_plain(
+            "No link, ftp://matrix.org or https://",
+        ));
+        assert_eq!(link, None);
+
+        let link = first_link_of(RoomMessageEventContent::text_plain("mailto:alice@example.org"));
+        assert_eq!(link, None);
+
+        // Only text, notice and emote messages are considered.
+        let link = first_link_of(RoomMessageEventContent::new(MessageType::Location(
+            LocationMessageEventContent::new(
+                "https://matrix.org".to_owned(),
+                "geo:51.5008,0.1247".to_owned(),
+            ),
+        )));
+        assert_eq!(link, None);
+    }
+}
@@ -57,7 +57,7 @@ use crate::timeline::{polls::PollState, TimelineItem};
 
 mod message;
 
-pub use self::message::{InReplyToDetails, Message, RepliedToEvent};
+pub use self::message::{InReplyToDetails, Message, RepliedToEvent, ThreadSummary};
 
 /// The content of an [`EventTimelineItem`][super::EventTimelineItem].
 #[derive(Clone, Debug)]
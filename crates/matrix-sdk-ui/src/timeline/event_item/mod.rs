@@ -35,7 +35,7 @@ pub use self::{
     content::{
         AnyOtherFullStateEventContent, EncryptedMessage, InReplyToDetails, MemberProfileChange,
         MembershipChange, Message, OtherState, RepliedToEvent, RoomMembershipChange, Sticker,
-        TimelineItemContent,
+        ThreadSummary, TimelineItemContent,
     },
     local::EventSendState,
     reactions::{BundledReactions, ReactionGroup},
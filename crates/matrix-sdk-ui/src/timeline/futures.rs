@@ -6,7 +6,7 @@ use matrix_sdk_base::boxed_into_future;
 use mime::Mime;
 use tracing::{Instrument as _, Span};
 
-use super::{Error, Timeline};
+use super::{wait_for_send_queue, Error, Timeline};
 
 pub struct SendAttachment<'a> {
     timeline: &'a Timeline,
@@ -56,13 +56,19 @@ impl<'a> IntoFuture for SendAttachment<'a> {
                 .expect("path was created from UTF-8 string, hence filename part is UTF-8 too");
             let data = fs::read(&url).map_err(|_| Error::InvalidAttachmentData)?;
 
-            timeline
-                .room()
+            let send_queue = timeline.room().send_queue();
+            let mut updates = send_queue.subscribe();
+
+            let txn_id = send_queue
                 .send_attachment(body, &mime_type, data, config)
                 .with_send_progress_observable(send_progress)
                 .await
                 .map_err(|_| Error::FailedSendingAttachment)?;
 
+            wait_for_send_queue(&send_queue, &mut updates, &txn_id)
+                .await
+                .ok_or(Error::FailedSendingAttachment)?;
+
             Ok(())
         };
 
@@ -48,7 +48,7 @@ use ruma::{
         AnyMessageLikeEventContent, AnySyncMessageLikeEvent, AnySyncTimelineEvent,
         MessageLikeEventType,
     },
-    EventId, OwnedEventId, OwnedTransactionId, RoomVersionId, TransactionId, UserId,
+    EventId, OwnedEventId, OwnedRoomId, OwnedTransactionId, RoomVersionId, TransactionId, UserId,
 };
 use tokio::sync::{RwLock, RwLockWriteGuard};
 use tracing::{debug, error, field::debug, info, instrument, trace, warn};
@@ -58,13 +58,14 @@ use tracing::{field, info_span, Instrument as _};
 #[cfg(feature = "e2e-encryption")]
 use super::traits::Decryptor;
 use super::{
-    event_item::EventItemIdentifier,
+    event_item::{EventItemIdentifier, RemoteEventOrigin},
     pagination::PaginationTokens,
     reactions::ReactionToggleResult,
     traits::RoomDataProvider,
     util::{rfind_event_by_id, rfind_event_item, RelativePosition},
     AnnotationKey, EventSendState, EventTimelineItem, InReplyToDetails, Message, Profile,
-    RepliedToEvent, TimelineDetails, TimelineItem, TimelineItemContent, TimelineItemKind,
+    RepliedToEvent, TimelineDetails, TimelineFocus, TimelineItem, TimelineItemContent,
+    TimelineItemKind,
 };
 use crate::timeline::TimelineEventFilterFn;
 
@@ -109,6 +110,11 @@ pub(super) struct TimelineInnerSettings {
     pub(super) event_filter: Arc<TimelineEventFilterFn>,
     /// Are unparsable events added as timeline items of their own kind?
     pub(super) add_failed_to_parse: bool,
+    /// What the timeline is focused on.
+    pub(super) focus: TimelineFocus,
+    /// Does back-pagination continue into the room this room replaces, once
+    /// the start of this room is reached?
+    pub(super) paginate_predecessor_rooms: bool,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -117,6 +123,8 @@ impl fmt::Debug for TimelineInnerSettings {
         f.debug_struct("TimelineInnerSettings")
             .field("track_read_receipts", &self.track_read_receipts)
             .field("add_failed_to_parse", &self.add_failed_to_parse)
+            .field("focus", &self.focus)
+            .field("paginate_predecessor_rooms", &self.paginate_predecessor_rooms)
             .finish_non_exhaustive()
     }
 }
@@ -127,6 +135,8 @@ impl Default for TimelineInnerSettings {
             track_read_receipts: false,
             event_filter: Arc::new(default_event_filter),
             add_failed_to_parse: true,
+            focus: TimelineFocus::default(),
+            paginate_predecessor_rooms: false,
         }
     }
 }
@@ -223,6 +233,14 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         self
     }
 
+    pub(super) fn focus(&self) -> &TimelineFocus {
+        &self.settings.focus
+    }
+
+    pub(super) fn paginates_predecessor_rooms(&self) -> bool {
+        self.settings.paginate_predecessor_rooms
+    }
+
     /// Get a copy of the current items in the list.
     ///
     /// Cheap because `im::Vector` is cheap to clone.
@@ -382,13 +400,28 @@ impl<P: RoomDataProvider> TimelineInner<P> {
     pub(super) async fn populate_initial_user_receipt(&mut self, receipt_type: ReceiptType) {
         let own_user_id = self.room_data_provider.own_user_id().to_owned();
 
-        let mut read_receipt = self
-            .room_data_provider
-            .load_user_receipt(receipt_type.clone(), ReceiptThread::Unthreaded, &own_user_id)
-            .await;
+        // A receipt in the focused thread is more precise than an unthreaded one.
+        let mut read_receipt = None;
+        if let TimelineFocus::Thread { root_event_id } = &self.settings.focus {
+            read_receipt = self
+                .room_data_provider
+                .load_user_receipt(
+                    receipt_type.clone(),
+                    ReceiptThread::Thread(root_event_id.clone()),
+                    &own_user_id,
+                )
+                .await;
+        }
 
-        // Fallback to the one in the main thread.
         if read_receipt.is_none() {
+            read_receipt = self
+                .room_data_provider
+                .load_user_receipt(receipt_type.clone(), ReceiptThread::Unthreaded, &own_user_id)
+                .await;
+        }
+
+        // Fallback to the one in the main thread.
+        if read_receipt.is_none() && !matches!(self.settings.focus, TimelineFocus::Thread { .. }) {
             read_receipt = self
                 .room_data_provider
                 .load_user_receipt(receipt_type.clone(), ReceiptThread::Main, &own_user_id)
@@ -408,6 +441,7 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         &mut self,
         events: Vec<SyncTimelineEvent>,
         back_pagination_token: Option<String>,
+        origin: RemoteEventOrigin,
     ) {
         if events.is_empty() {
             return;
@@ -418,6 +452,7 @@ impl<P: RoomDataProvider> TimelineInner<P> {
             .add_initial_events(
                 events,
                 back_pagination_token,
+                origin,
                 &self.room_data_provider,
                 &self.settings,
             )
@@ -450,6 +485,21 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         let profile = self.room_data_provider.profile_from_user_id(&sender).await;
 
         let mut state = self.state.write().await;
+
+        // The local echo would be added after the last loaded event, which isn't the
+        // end of the room until forward-pagination reaches it.
+        if state.forward_pagination_token().is_some() {
+            trace!("Timeline isn't live, ignoring the local echo");
+            return;
+        }
+
+        // The same local echo can be added by the timeline that sent it, and through
+        // the updates of the room's send queue.
+        if rfind_event_item(&state.items, |it| it.transaction_id() == Some(&*txn_id)).is_some() {
+            trace!("Local echo already in the timeline");
+            return;
+        }
+
         state.handle_local_event(sender, profile, txn_id, content);
     }
 
@@ -681,6 +731,64 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         Some(state.back_pagination_token()?.to_owned())
     }
 
+    /// Get the forward-pagination token of the last [`EventTimelineItem`].
+    ///
+    /// Returns `None` if the timeline is live, i.e. its end is the live end of
+    /// the room.
+    pub(super) async fn forward_pagination_token(&self) -> Option<String> {
+        let state = self.state.read().await;
+        Some(state.forward_pagination_token()?.to_owned())
+    }
+
+    pub(super) async fn set_forward_pagination_token(&self, token: Option<String>) {
+        self.state.write().await.set_forward_pagination_token(token);
+    }
+
+    /// The ID of the room that back-pagination continues in, if it isn't
+    /// the room of the timeline.
+    pub(super) async fn back_pagination_room_id(&self) -> Option<OwnedRoomId> {
+        self.state.read().await.back_pagination_room_id().map(ToOwned::to_owned)
+    }
+
+    pub(super) async fn set_back_pagination_room_id(&self, room_id: Option<OwnedRoomId>) {
+        self.state.write().await.set_back_pagination_room_id(room_id);
+    }
+
+    /// Whether the end of the timeline is the live end of the room.
+    pub(super) async fn is_live(&self) -> bool {
+        self.state.read().await.forward_pagination_token().is_none()
+    }
+
+    /// Handle a list of forward-paginated events.
+    ///
+    /// Returns `None` if the forward-pagination token changed since the
+    /// request was made, or if the number of items added or updated exceeds
+    /// `u16::MAX`, which should practically never happen.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The events from forward-pagination
+    ///
+    /// * `from` - The forward-pagination token used for the request
+    ///
+    /// * `to` - The forward-pagination token for loading further events, or
+    ///   `None` if the live end of the room was reached
+    pub(super) async fn handle_forward_paginated_events(
+        &self,
+        events: Vec<TimelineEvent>,
+        from: &str,
+        to: Option<String>,
+    ) -> Option<HandleManyEventsResult> {
+        let mut state = self.state.write().await;
+        if state.forward_pagination_token() != Some(from) {
+            return None;
+        }
+
+        state
+            .handle_forward_paginated_events(events, to, &self.room_data_provider, &self.settings)
+            .await
+    }
+
     /// Handle a list of back-paginated events.
     ///
     /// Returns the number of timeline updates that were made. Short-circuits
@@ -697,6 +805,22 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         &self,
         events: Vec<TimelineEvent>,
         pagination_tokens: PaginationTokens,
+    ) -> Result<HandleManyEventsResult, HandleBackPaginatedEventsError> {
+        self.handle_back_paginated_events_with(events, pagination_tokens, &self.room_data_provider)
+            .await
+    }
+
+    /// Handle a list of back-paginated events, using the given room data
+    /// provider rather than the one of the timeline.
+    ///
+    /// This is used for the events of the predecessor rooms of the room of
+    /// the timeline, which have their own members, receipts and room
+    /// version.
+    pub(super) async fn handle_back_paginated_events_with(
+        &self,
+        events: Vec<TimelineEvent>,
+        pagination_tokens: PaginationTokens,
+        room_data_provider: &P,
     ) -> Result<HandleManyEventsResult, HandleBackPaginatedEventsError> {
         let mut state = self.state.write().await;
         if pagination_tokens.check_from {
@@ -711,7 +835,7 @@ impl<P: RoomDataProvider> TimelineInner<P> {
             .handle_back_paginated_events(
                 events,
                 pagination_tokens.to,
-                &self.room_data_provider,
+                room_data_provider,
                 &self.settings,
             )
             .await
@@ -958,7 +1082,11 @@ impl<P: RoomDataProvider> TimelineInner<P> {
     #[cfg(test)]
     pub(super) async fn handle_read_receipts(&self, receipt_event_content: ReceiptEventContent) {
         let own_user_id = self.room_data_provider.own_user_id();
-        self.state.write().await.handle_read_receipts(receipt_event_content, own_user_id);
+        self.state.write().await.handle_read_receipts(
+            receipt_event_content,
+            own_user_id,
+            &self.settings.focus,
+        );
     }
 
     /// Get the latest read receipt for the given user.
@@ -979,6 +1107,25 @@ impl<P: RoomDataProvider> TimelineInner<P> {
     ) -> Option<OwnedEventId> {
         self.state.read().await.latest_user_read_receipt_timeline_event_id(user_id)
     }
+
+    /// Get the number of remote events from other users after the latest read
+    /// receipt of the current user.
+    pub(super) async fn num_unread_events(&self) -> usize {
+        let own_user_id = self.room_data_provider.own_user_id();
+        let state = self.state.read().await;
+        let read_event_id = state.latest_user_read_receipt_timeline_event_id(own_user_id);
+
+        state
+            .items
+            .iter()
+            .rev()
+            .filter_map(|item| item.as_event())
+            .take_while(|event| {
+                read_event_id.is_none() || event.event_id() != read_event_id.as_deref()
+            })
+            .filter(|event| event.is_remote_event() && !event.is_own())
+            .count()
+    }
 }
 
 impl TimelineInner {
@@ -32,8 +32,8 @@ use ruma::{
         AnyMessageLikeEventContent, AnyRoomAccountDataEvent, AnySyncEphemeralRoomEvent,
     },
     push::Action,
-    EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedTransactionId, OwnedUserId,
-    RoomVersionId, UserId,
+    EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedTransactionId,
+    OwnedUserId, RoomId, RoomVersionId, UserId,
 };
 use tracing::{debug, error, instrument, trace, warn};
 
@@ -45,14 +45,14 @@ use crate::{
             Flow, HandleEventResult, TimelineEventContext, TimelineEventHandler, TimelineEventKind,
             TimelineItemPosition,
         },
-        event_item::EventItemIdentifier,
+        event_item::{EventItemIdentifier, RemoteEventOrigin},
         polls::PollPendingEvents,
         reactions::{ReactionToggleResult, Reactions},
         read_receipts::ReadReceipts,
         traits::RoomDataProvider,
         util::{rfind_event_by_id, rfind_event_item, timestamp_to_date, RelativePosition},
-        AnnotationKey, Error as TimelineError, Profile, ReactionSenderData, TimelineItem,
-        TimelineItemKind, VirtualTimelineItem,
+        AnnotationKey, Error as TimelineError, Profile, ReactionSenderData, TimelineFocus,
+        TimelineItem, TimelineItemKind, VirtualTimelineItem,
     },
 };
 
@@ -78,11 +78,28 @@ impl TimelineInnerState {
         Some(token)
     }
 
+    pub(super) fn forward_pagination_token(&self) -> Option<&str> {
+        self.meta.forward_pagination_token.as_deref()
+    }
+
+    pub(super) fn set_forward_pagination_token(&mut self, token: Option<String>) {
+        self.meta.forward_pagination_token = token;
+    }
+
+    pub(super) fn back_pagination_room_id(&self) -> Option<&RoomId> {
+        self.meta.back_pagination_room_id.as_deref()
+    }
+
+    pub(super) fn set_back_pagination_room_id(&mut self, room_id: Option<OwnedRoomId>) {
+        self.meta.back_pagination_room_id = room_id;
+    }
+
     #[tracing::instrument(skip_all)]
     pub(super) async fn add_initial_events<P: RoomDataProvider>(
         &mut self,
         events: Vec<SyncTimelineEvent>,
         mut back_pagination_token: Option<String>,
+        origin: RemoteEventOrigin,
         room_data_provider: &P,
         settings: &TimelineInnerSettings,
     ) {
@@ -93,7 +110,7 @@ impl TimelineInnerState {
             let (event_id, _) = txn
                 .handle_remote_event(
                     event,
-                    TimelineItemPosition::End { from_cache: true },
+                    TimelineItemPosition::End { origin },
                     room_data_provider,
                     settings,
                 )
@@ -140,7 +157,7 @@ impl TimelineInnerState {
             for raw_event in update.ephemeral {
                 match raw_event.deserialize() {
                     Ok(AnySyncEphemeralRoomEvent::Receipt(ev)) => {
-                        txn.handle_explicit_read_receipts(ev.content, own_user_id);
+                        txn.handle_explicit_read_receipts(ev.content, own_user_id, &settings.focus);
                     }
                     Ok(_) => {}
                     Err(e) => {
@@ -193,6 +210,39 @@ impl TimelineInnerState {
         Some(total)
     }
 
+    /// Handle events from forward-pagination, adding them at the end of the
+    /// timeline.
+    #[instrument(skip_all)]
+    pub(super) async fn handle_forward_paginated_events<P: RoomDataProvider>(
+        &mut self,
+        events: Vec<TimelineEvent>,
+        forward_pagination_token: Option<String>,
+        room_data_provider: &P,
+        settings: &TimelineInnerSettings,
+    ) -> Option<HandleManyEventsResult> {
+        let mut txn = self.transaction();
+
+        let mut total = HandleManyEventsResult::default();
+        for event in events {
+            let (_, res) = txn
+                .handle_remote_event(
+                    event.into(),
+                    TimelineItemPosition::End { origin: RemoteEventOrigin::Pagination },
+                    room_data_provider,
+                    settings,
+                )
+                .await;
+
+            total.items_added = total.items_added.checked_add(res.item_added as u16)?;
+            total.items_updated = total.items_updated.checked_add(res.items_updated)?;
+        }
+
+        txn.meta.forward_pagination_token = forward_pagination_token;
+        txn.commit();
+
+        Some(total)
+    }
+
     #[cfg(test)]
     pub(super) async fn handle_live_event<P: RoomDataProvider>(
         &mut self,
@@ -429,9 +479,10 @@ impl TimelineInnerState {
         &mut self,
         receipt_event_content: ReceiptEventContent,
         own_user_id: &UserId,
+        focus: &TimelineFocus,
     ) {
         let mut txn = self.transaction();
-        txn.handle_explicit_read_receipts(receipt_event_content, own_user_id);
+        txn.handle_explicit_read_receipts(receipt_event_content, own_user_id, focus);
         txn.commit();
     }
 
@@ -483,7 +534,11 @@ impl TimelineInnerStateTransaction<'_> {
             trace!("Handling event {} out of {num_events}", i + 1);
             let (event_id, _) = self.handle_live_event(event, room_data_provider, settings).await;
 
-            // Back-pagination token, if any, is added to the first added event.
+            // Back-pagination token, if any, is added to the first added event. Sync tokens
+            // can only be used to paginate the live timeline, though.
+            if settings.focus != TimelineFocus::Live {
+                continue;
+            }
             if let Some(event_id) = event_id {
                 if let Some(token) = timeline.prev_batch.take() {
                     trace!(token, ?event_id, "Adding back-pagination token to the back");
@@ -496,7 +551,7 @@ impl TimelineInnerStateTransaction<'_> {
     /// Handle a live remote event.
     ///
     /// Shorthand for `handle_remote_event` with a `position` of
-    /// `TimelineItemPosition::End { from_cache: false }`.
+    /// `TimelineItemPosition::End { origin: RemoteEventOrigin::Sync }`.
     async fn handle_live_event<P: RoomDataProvider>(
         &mut self,
         event: SyncTimelineEvent,
@@ -505,7 +560,7 @@ impl TimelineInnerStateTransaction<'_> {
     ) -> (Option<OwnedEventId>, HandleEventResult) {
         self.handle_remote_event(
             event,
-            TimelineItemPosition::End { from_cache: false },
+            TimelineItemPosition::End { origin: RemoteEventOrigin::Sync },
             room_data_provider,
             settings,
         )
@@ -673,6 +728,8 @@ impl TimelineInnerStateTransaction<'_> {
         // before attempting to update it for each new timeline item.
         self.has_up_to_date_read_marker_item = true;
         self.back_pagination_tokens.clear();
+        self.forward_pagination_token = None;
+        self.back_pagination_room_id = None;
 
         debug!(remaining_items = self.items.len(), "Timeline cleared");
     }
@@ -806,6 +863,18 @@ pub(in crate::timeline) struct TimelineInnerMetadata {
     ///
     /// Private because it's not needed by `TimelineEventHandler`.
     back_pagination_tokens: VecDeque<(OwnedEventId, String)>,
+
+    /// Forward-pagination token, when the end of the timeline isn't the live
+    /// end of the room.
+    ///
+    /// Private because it's not needed by `TimelineEventHandler`.
+    forward_pagination_token: Option<String>,
+
+    /// The predecessor room that back-pagination continues in, once the
+    /// start of the room of the timeline was reached.
+    ///
+    /// Private because it's not needed by `TimelineEventHandler`.
+    back_pagination_room_id: Option<OwnedRoomId>,
 }
 
 impl TimelineInnerMetadata {
@@ -824,6 +893,8 @@ impl TimelineInnerMetadata {
             in_flight_reaction: Default::default(),
             room_version,
             back_pagination_tokens: VecDeque::new(),
+            forward_pagination_token: None,
+            back_pagination_room_id: None,
         }
     }
 
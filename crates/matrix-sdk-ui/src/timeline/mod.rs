@@ -24,10 +24,11 @@ use futures_core::Stream;
 use imbl::Vector;
 use matrix_sdk::{
     attachment::AttachmentConfig,
-    event_cache::EventCacheDropHandles,
+    event_cache::{EventCacheDropHandles, RoomEventCache},
     event_handler::EventHandlerHandle,
     executor::JoinHandle,
     room::{Receipts, Room},
+    send_queue::{RoomSendQueue, RoomSendQueueUpdate},
     Client, Result,
 };
 use matrix_sdk_base::RoomState;
@@ -42,11 +43,12 @@ use ruma::{
         },
         reaction::ReactionEventContent,
         receipt::{Receipt, ReceiptThread},
-        relation::Annotation,
+        relation::{Annotation, Thread},
         room::{
             message::{
-                AddMentions, ForwardThread, OriginalRoomMessageEvent, ReplacementMetadata,
-                RoomMessageEventContent, RoomMessageEventContentWithoutRelation,
+                AddMentions, ForwardThread, OriginalRoomMessageEvent, Relation,
+                ReplacementMetadata, RoomMessageEventContent,
+                RoomMessageEventContentWithoutRelation,
             },
             redaction::RoomRedactionEventContent,
         },
@@ -56,7 +58,10 @@ use ruma::{
     TransactionId, UserId,
 };
 use thiserror::Error;
-use tokio::sync::{mpsc::Sender, Mutex, Notify};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    Mutex, Notify,
+};
 use tracing::{debug, error, info, instrument, trace, warn};
 
 use self::futures::SendAttachment;
@@ -71,7 +76,6 @@ mod inner;
 mod item;
 mod pagination;
 mod polls;
-mod queue;
 mod reactions;
 mod read_receipts;
 mod sliding_sync_ext;
@@ -90,7 +94,7 @@ pub use self::{
         AnyOtherFullStateEventContent, BundledReactions, EncryptedMessage, EventItemOrigin,
         EventSendState, EventTimelineItem, InReplyToDetails, MemberProfileChange, MembershipChange,
         Message, OtherState, Profile, ReactionGroup, RepliedToEvent, RoomMembershipChange, Sticker,
-        TimelineDetails, TimelineItemContent,
+        ThreadSummary, TimelineDetails, TimelineItemContent,
     },
     event_type_filter::TimelineEventTypeFilter,
     inner::default_event_filter,
@@ -104,11 +108,64 @@ pub use self::{
 };
 use self::{
     inner::{ReactionAction, TimelineInner},
-    queue::LocalMessage,
     reactions::ReactionToggleResult,
     util::rfind_event_by_id,
 };
 
+/// What a [`Timeline`] is focused on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimelineFocus {
+    /// The live timeline of the room, containing all its events.
+    #[default]
+    Live,
+
+    /// A window of the room around a given event, typically used to jump to a
+    /// permalink.
+    ///
+    /// The timeline starts with the event and some context around it, and can
+    /// be extended in both directions with [`Timeline::paginate_backwards`]
+    /// and [`Timeline::paginate_forwards`]. Once forward-pagination reaches
+    /// the live end of the room, new events received via sync are added to
+    /// it, like in the live timeline.
+    Event {
+        /// The ID of the event to focus on.
+        target: OwnedEventId,
+        /// The maximum number of events to load around the target event.
+        num_context_events: u16,
+    },
+
+    /// A single thread, containing its root event and all its replies.
+    ///
+    /// The thread is loaded with [`Timeline::paginate_backwards`], and new
+    /// replies received via sync are added to it.
+    Thread {
+        /// The ID of the root event of the thread.
+        root_event_id: OwnedEventId,
+    },
+}
+
+impl TimelineFocus {
+    /// Whether read receipts in the given thread apply to this focus.
+    pub(super) fn includes_receipt_thread(&self, thread: &ReceiptThread) -> bool {
+        match (self, thread) {
+            (_, ReceiptThread::Unthreaded)
+            | (Self::Live | Self::Event { .. }, ReceiptThread::Main) => true,
+            (Self::Thread { root_event_id }, ReceiptThread::Thread(thread_root)) => {
+                thread_root == root_event_id
+            }
+            _ => false,
+        }
+    }
+
+    /// The thread of the read receipts sent for this focus.
+    fn receipt_thread(&self) -> ReceiptThread {
+        match self {
+            Self::Live | Self::Event { .. } => ReceiptThread::Unthreaded,
+            Self::Thread { root_event_id } => ReceiptThread::Thread(root_event_id.clone()),
+        }
+    }
+}
+
 /// A high-level view into a regular¹ room's contents.
 ///
 /// ¹ This type is meant to be used in the context of rooms without a
@@ -118,15 +175,20 @@ use self::{
 pub struct Timeline {
     inner: TimelineInner,
 
+    /// The event cache of the room, which keeps track of the back-paginated
+    /// events.
+    event_cache: RoomEventCache,
+
     /// Mutex that ensures only a single pagination is running at once
     back_pagination_mtx: Mutex<()>,
+    /// Mutex that ensures only a single forward-pagination is running at once
+    forward_pagination_mtx: Mutex<()>,
     /// Observable for whether a pagination is currently running
     back_pagination_status: SharedObservable<BackPaginationStatus>,
 
     /// Notifier for handled sync responses.
     sync_response_notify: Arc<Notify>,
 
-    msg_sender: Sender<LocalMessage>,
     drop_handle: Arc<TimelineDropHandle>,
 }
 
@@ -153,6 +215,11 @@ impl Timeline {
         self.inner.room()
     }
 
+    /// Get what this timeline is focused on.
+    pub fn focus(&self) -> &TimelineFocus {
+        self.inner.focus()
+    }
+
     /// Clear all timeline items.
     pub async fn clear(&self) {
         self.inner.clear().await;
@@ -194,6 +261,30 @@ impl Timeline {
         }
     }
 
+    /// Add more events to the end of the timeline, when it's focused on an
+    /// event and hasn't caught up with the live end of the room yet.
+    ///
+    /// Returns `true` if the live end of the room was reached, in which case
+    /// new events received via sync are added to the timeline from now on.
+    #[instrument(skip_all, fields(room_id = ?self.room().room_id(), num_events))]
+    pub async fn paginate_forwards(&self, num_events: u16) -> Result<bool> {
+        // Ignore extra forward pagination requests if one is already running.
+        let Ok(_guard) = self.forward_pagination_mtx.try_lock() else {
+            info!("Couldn't acquire forward pagination mutex, another request must be running");
+            return Ok(false);
+        };
+
+        self.paginate_forwards_impl(num_events).await
+    }
+
+    /// Whether the end of the timeline is the live end of the room.
+    ///
+    /// This is only `false` for a timeline focused on an event, until
+    /// [`Self::paginate_forwards`] reaches the live end of the room.
+    pub async fn is_live(&self) -> bool {
+        self.inner.is_live().await
+    }
+
     /// Retry decryption of previously un-decryptable events given a list of
     /// session IDs whose keys have been imported.
     ///
@@ -286,21 +377,46 @@ impl Timeline {
     /// If the encryption feature is enabled, this method will transparently
     /// encrypt the room message if the room is encrypted.
     ///
-    /// If sending the message fails, the local echo item will change its
-    /// `send_state` to [`EventSendState::SendingFailed`].
+    /// The message is persisted in the room's [send queue], so it's sent even
+    /// if the timeline is dropped, or after a restart if the app is killed.
+    /// Network errors are retried automatically; if sending the message fails
+    /// otherwise, the local echo item will change its `send_state` to
+    /// [`EventSendState::SendingFailed`].
     ///
     /// # Arguments
     ///
     /// * `content` - The content of the message event.
     ///
+    /// If the timeline is focused on a thread, room messages without a
+    /// relation are sent as replies in the thread.
+    ///
     /// [`MessageLikeUnsigned`]: ruma::events::MessageLikeUnsigned
     /// [`SyncMessageLikeEvent`]: ruma::events::SyncMessageLikeEvent
+    /// [send queue]: matrix_sdk::send_queue::RoomSendQueue
     #[instrument(skip(self, content), fields(room_id = ?self.room().room_id()))]
     pub async fn send(&self, content: AnyMessageLikeEventContent) {
+        let content = match (self.inner.focus(), content) {
+            (
+                TimelineFocus::Thread { root_event_id },
+                AnyMessageLikeEventContent::RoomMessage(mut content),
+            ) if content.relates_to.is_none() => {
+                let latest_event_id =
+                    self.inner.latest_event_id().await.unwrap_or_else(|| root_event_id.clone());
+                content.relates_to =
+                    Some(Relation::Thread(Thread::plain(root_event_id.clone(), latest_event_id)));
+                content.into()
+            }
+            (_, content) => content,
+        };
+
         let txn_id = TransactionId::new();
         self.inner.handle_local_event(txn_id.clone(), content.clone()).await;
-        if self.msg_sender.send(LocalMessage { content, txn_id }).await.is_err() {
-            error!("Internal error: timeline message receiver is closed");
+
+        if let Err(error) =
+            self.room().send_queue().send_with_transaction_id(content, txn_id.clone()).await
+        {
+            error!("Failed to queue the message: {error}");
+            self.inner.discard_local_echo(&txn_id).await;
         }
     }
 
@@ -505,7 +621,8 @@ impl Timeline {
         }
     }
 
-    /// Send a reaction event to the homeserver
+    /// Send a reaction event to the homeserver, through the send queue of the
+    /// room.
     async fn send_reaction(
         &self,
         annotation: &Annotation,
@@ -519,21 +636,37 @@ impl Timeline {
 
         let event_content =
             AnyMessageLikeEventContent::Reaction(ReactionEventContent::from(annotation.clone()));
-        let response = room.send(event_content).with_transaction_id(&txn_id).await;
 
-        match response {
-            Ok(response) => {
-                ReactionToggleResult::AddSuccess { event_id: response.event_id, txn_id }
-            }
-            Err(error) => {
-                error!("Failed to send reaction: {error}");
+        let send_queue = room.send_queue();
+        let mut updates = send_queue.subscribe();
+
+        if let Err(error) = send_queue.send_with_transaction_id(event_content, txn_id.clone()).await
+        {
+            error!("Failed to queue reaction: {error}");
+            return ReactionToggleResult::AddFailure { txn_id };
+        }
+
+        match wait_for_send_queue(&send_queue, &mut updates, &txn_id).await {
+            Some(event_id) => ReactionToggleResult::AddSuccess { event_id, txn_id },
+            None => {
+                error!("Failed to send reaction");
+
+                // Don't leave a wedged reaction in the queue, the reaction is toggled
+                // off locally.
+                if let Err(error) = send_queue.cancel(&txn_id).await {
+                    warn!("Failed to remove the reaction from the send queue: {error}");
+                }
+
                 ReactionToggleResult::AddFailure { txn_id }
             }
         }
     }
 
-    /// Sends an attachment to the room. It does not currently support local
-    /// echoes
+    /// Sends an attachment to the room, through the send queue of the room.
+    ///
+    /// A local echo of the attachment is added to the timeline as soon as it's
+    /// queued. The returned future resolves once the attachment has been
+    /// sent.
     ///
     /// If the encryption feature is enabled, this method will transparently
     /// encrypt the room message if the room is encrypted.
@@ -601,9 +734,10 @@ impl Timeline {
         };
 
         debug!("Retrying failed local echo");
-        let txn_id = txn_id.to_owned();
-        if self.msg_sender.send(LocalMessage { content, txn_id }).await.is_err() {
-            error!("Internal error: timeline message receiver is closed");
+        let send_queue = self.room().send_queue();
+        if !send_queue.retry(txn_id).await? {
+            // The event isn't in the send queue anymore, queue it again.
+            send_queue.send_with_transaction_id(content, txn_id.to_owned()).await?;
         }
 
         Ok(())
@@ -611,7 +745,8 @@ impl Timeline {
 
     /// Discard a local echo for a message that failed to send.
     ///
-    /// Returns whether the local echo with the given transaction ID was found.
+    /// Returns whether the event was removed from the send queue, and so won't
+    /// be sent. An event that is being sent can't be cancelled anymore.
     ///
     /// # Argument
     ///
@@ -622,7 +757,22 @@ impl Timeline {
     ///   event from reaching the server.
     #[instrument(skip(self))]
     pub async fn cancel_send(&self, txn_id: &TransactionId) -> bool {
-        self.inner.discard_local_echo(txn_id).await
+        match self.room().send_queue().cancel(txn_id).await {
+            Ok(true) => {
+                // The local echo might have been discarded already when handling the update
+                // of the send queue.
+                self.inner.discard_local_echo(txn_id).await;
+                true
+            }
+            Ok(false) => {
+                debug!("The event isn't in the send queue, or is being sent");
+                false
+            }
+            Err(error) => {
+                warn!("Failed to remove the event from the send queue: {error}");
+                false
+            }
+        }
     }
 
     /// Fetch unavailable details about the event with the given ID.
@@ -697,6 +847,15 @@ impl Timeline {
         self.inner.latest_user_read_receipt_timeline_event_id(user_id).await
     }
 
+    /// Get the number of events in this timeline that were sent by other
+    /// users after the latest read receipt of the current user.
+    ///
+    /// If the timeline is focused on a thread, this is the number of unread
+    /// replies in the thread.
+    pub async fn num_unread_events(&self) -> usize {
+        self.inner.num_unread_events().await
+    }
+
     /// Send the given receipt.
     ///
     /// This uses [`Room::send_single_receipt`] internally, but checks
@@ -776,14 +935,15 @@ impl Timeline {
     /// latest event, be it visible or not.
     ///
     /// This works even if the latest event belongs to a thread, as a threaded
-    /// reply also belongs to the unthreaded timeline. No threaded receipt
-    /// will be sent here (see also #3123).
+    /// reply also belongs to the unthreaded timeline. If the timeline is
+    /// focused on a thread, a receipt for this thread is sent instead.
     ///
     /// Returns a boolean indicating if we sent the request or not.
     #[instrument(skip(self), fields(room_id = ?self.room().room_id()))]
     pub async fn mark_as_read(&self, receipt_type: ReceiptType) -> Result<bool> {
         if let Some(event_id) = self.inner.latest_event_id().await {
-            self.send_single_receipt(receipt_type, ReceiptThread::Unthreaded, event_id).await
+            let thread = self.inner.focus().receipt_thread();
+            self.send_single_receipt(receipt_type, thread, event_id).await
         } else {
             trace!("can't mark room as read because there's no latest event id");
             Ok(false)
@@ -816,6 +976,7 @@ struct TimelineDropHandle {
     room_update_join_handle: JoinHandle<()>,
     ignore_user_list_update_join_handle: JoinHandle<()>,
     room_key_from_backups_join_handle: JoinHandle<()>,
+    send_queue_join_handle: JoinHandle<()>,
     _event_cache_drop_handle: Arc<EventCacheDropHandles>,
 }
 
@@ -827,6 +988,7 @@ impl Drop for TimelineDropHandle {
         self.room_update_join_handle.abort();
         self.ignore_user_list_update_join_handle.abort();
         self.room_key_from_backups_join_handle.abort();
+        self.send_queue_join_handle.abort();
     }
 }
 
@@ -857,3 +1019,49 @@ impl<S: Stream> Stream for TimelineStream<S> {
 
 pub type TimelineEventFilterFn =
     dyn Fn(&AnySyncTimelineEvent, &RoomVersionId) -> bool + Send + Sync;
+
+/// Wait until the event with the given transaction ID has been sent by the send
+/// queue, returning its event ID.
+///
+/// Returns `None` if sending the event failed with an error that won't be
+/// retried automatically, or if the event was removed from the queue.
+async fn wait_for_send_queue(
+    send_queue: &RoomSendQueue,
+    updates: &mut broadcast::Receiver<RoomSendQueueUpdate>,
+    txn_id: &TransactionId,
+) -> Option<OwnedEventId> {
+    loop {
+        match updates.recv().await {
+            Ok(RoomSendQueueUpdate::SentEvent { transaction_id, event_id })
+                if transaction_id == txn_id =>
+            {
+                return Some(event_id);
+            }
+
+            Ok(RoomSendQueueUpdate::SendError {
+                transaction_id, is_recoverable: false, ..
+            })
+            | Ok(RoomSendQueueUpdate::CancelledLocalEvent { transaction_id })
+                if transaction_id == txn_id =>
+            {
+                return None;
+            }
+
+            Ok(_) => {}
+
+            Err(RecvError::Lagged(_)) => {
+                // The update about the event might have been missed, check that it's still
+                // waiting to be sent.
+                match send_queue.local_echoes().await {
+                    Ok(local_echoes)
+                        if local_echoes
+                            .iter()
+                            .any(|echo| echo.transaction_id == txn_id && !echo.is_wedged) => {}
+                    _ => return None,
+                }
+            }
+
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
@@ -14,12 +14,20 @@
 
 use std::{fmt, ops::ControlFlow, pin::pin, sync::Arc, time::Duration};
 
-use matrix_sdk::{room::MessagesOptions, Result};
+use matrix_sdk::{
+    deserialized_responses::{RawSyncOrStrippedState, TimelineEvent},
+    room::{MessagesOptions, RelationsOptions},
+    Result, Room,
+};
 use matrix_sdk_base::timeout::timeout;
-use ruma::assign;
-use tracing::{error, info, instrument, trace, warn};
+use ruma::{
+    assign,
+    events::{relation::RelationType, room::create::RoomCreateEventContent},
+    EventId, OwnedEventId,
+};
+use tracing::{debug, error, info, instrument, trace, warn};
 
-use super::{inner::HandleBackPaginatedEventsError, Timeline};
+use super::{inner::HandleBackPaginatedEventsError, Timeline, TimelineFocus};
 
 impl Timeline {
     /// Run back-pagination.
@@ -44,8 +52,16 @@ impl Timeline {
         // `wait_for_token` option is set
         const WAIT_FOR_TOKEN_TIMEOUT: Duration = Duration::from_secs(3);
 
+        // A predecessor room is paginated from its end, there's no sync token to wait
+        // for.
+        let in_predecessor_room = self.inner.back_pagination_room_id().await.is_some();
+
         let mut from = match self.inner.back_pagination_token().await {
-            None if options.wait_for_token => {
+            // A thread is paginated from its latest reply, there's no sync token to wait for.
+            None if options.wait_for_token
+                && *self.inner.focus() == TimelineFocus::Live
+                && !in_predecessor_room =>
+            {
                 trace!("Waiting for back-pagination token from sync...");
 
                 let wait_for_token = pin!(async {
@@ -81,6 +97,12 @@ impl Timeline {
         while let Some(limit) = options.next_event_limit(outcome) {
             match self.paginate_backwards_until_new_token(limit, from, &mut outcome).await? {
                 PaginateBackwardsOnceResult::Success { from: None, .. } => {
+                    if self.continue_in_predecessor_room().await {
+                        // Start from the end of the predecessor room.
+                        from = None;
+                        continue;
+                    }
+
                     trace!("Start of timeline was reached");
                     return Ok(ControlFlow::Break(BackPaginationStatus::TimelineStartReached));
                 }
@@ -147,19 +169,49 @@ impl Timeline {
         check_from: bool,
         outcome: &mut PaginationOutcome,
     ) -> Result<PaginateBackwardsOnceResult> {
-        trace!("Requesting messages");
+        // The room of the timeline, or one of its predecessors. It is never a predecessor
+        // room for a thread.
+        let room = self.back_pagination_room().await;
+
+        let (chunk, end) = match self.inner.focus() {
+            focus @ (TimelineFocus::Live | TimelineFocus::Event { .. }) => {
+                trace!(room_id = ?room.room_id(), "Requesting messages");
+
+                let messages = room
+                    .messages(assign!(MessagesOptions::backward(), {
+                        from: from.clone(),
+                        limit: limit.into(),
+                    }))
+                    .await?;
+
+                // Let the event cache fill the gap this token refers to; it's only a cache,
+                // so failing to do so isn't fatal for the timeline. It only knows about the
+                // live timeline of the room though.
+                if *focus == TimelineFocus::Live && room.room_id() == self.room().room_id() {
+                    if let Err(err) = self
+                        .event_cache
+                        .add_back_paginated_events(
+                            from.as_deref(),
+                            messages.chunk.clone(),
+                            messages.end.clone(),
+                        )
+                        .await
+                    {
+                        warn!("Failed to save back-paginated events in the event cache: {err}");
+                    }
+                }
 
-        let messages = self
-            .room()
-            .messages(assign!(MessagesOptions::backward(), {
-                from: from.clone(),
-                limit: limit.into(),
-            }))
-            .await?;
-        let chunk_len = messages.chunk.len();
+                (messages.chunk, messages.end)
+            }
+            TimelineFocus::Thread { root_event_id } => {
+                self.paginate_thread_backwards_once(root_event_id, from.clone(), limit).await?
+            }
+        };
+        let chunk_len = chunk.len();
 
-        let tokens = PaginationTokens { from, check_from, to: messages.end.clone() };
-        let res = match self.inner.handle_back_paginated_events(messages.chunk, tokens).await {
+        let tokens = PaginationTokens { from, check_from, to: end.clone() };
+        // Process the events with the data of the room they belong to.
+        let res = match self.inner.handle_back_paginated_events_with(chunk, tokens, &room).await {
             Ok(result) => result,
             Err(HandleBackPaginatedEventsError::TokenMismatch) => {
                 return Ok(PaginateBackwardsOnceResult::TokenMismatch);
@@ -187,12 +239,166 @@ impl Timeline {
 
         Ok(match update_outcome() {
             Some(()) => PaginateBackwardsOnceResult::Success {
-                from: messages.end,
+                from: end,
                 back_pagination_token_updated: res.back_pagination_token_updated,
             },
             None => PaginateBackwardsOnceResult::ResultOverflow,
         })
     }
+
+    /// The room to back-paginate in.
+    ///
+    /// This is the room of the timeline, or one of its predecessors once its
+    /// start was reached.
+    async fn back_pagination_room(&self) -> Room {
+        self.inner
+            .back_pagination_room_id()
+            .await
+            .and_then(|room_id| self.room().client().get_room(&room_id))
+            .unwrap_or_else(|| self.room().clone())
+    }
+
+    /// Switch back-pagination to the predecessor of the room that is
+    /// back-paginated, if the timeline is configured to do so and the
+    /// predecessor room is known.
+    ///
+    /// Returns `true` if back-pagination should continue in the predecessor
+    /// room.
+    async fn continue_in_predecessor_room(&self) -> bool {
+        if !self.inner.paginates_predecessor_rooms()
+            || matches!(self.inner.focus(), TimelineFocus::Thread { .. })
+        {
+            return false;
+        }
+
+        let room = self.back_pagination_room().await;
+        let Some(predecessor) = room.predecessor_room() else {
+            return false;
+        };
+
+        if predecessor.room_id == self.room().room_id() {
+            warn!("The predecessor of a predecessor room is the room of the timeline");
+            return false;
+        }
+
+        if self.room().client().get_room(&predecessor.room_id).is_none() {
+            info!(
+                predecessor_room_id = ?predecessor.room_id,
+                "Predecessor room isn't known, can't paginate into it"
+            );
+            return false;
+        }
+
+        self.add_room_create_separator(&room).await;
+
+        trace!(predecessor_room_id = ?predecessor.room_id, "Continuing in the predecessor room");
+        self.inner.set_back_pagination_room_id(Some(predecessor.room_id)).await;
+
+        true
+    }
+
+    /// Make sure the `m.room.create` event of the given room is at the start
+    /// of the timeline, to separate its events from the events of its
+    /// predecessor room.
+    ///
+    /// It is usually received with the last back-paginated events of the room,
+    /// but the homeserver might not return it, e.g. depending on the history
+    /// visibility of the room, in which case it is loaded from the store.
+    async fn add_room_create_separator(&self, room: &Room) {
+        let raw_event = match room.get_state_event_static::<RoomCreateEventContent>().await {
+            Ok(Some(RawSyncOrStrippedState::Sync(raw_event))) => raw_event,
+            Ok(_) => {
+                debug!(room_id = ?room.room_id(), "The create event of the room isn't known");
+                return;
+            }
+            Err(err) => {
+                warn!(room_id = ?room.room_id(), "Failed to load the create event of the room: {err}");
+                return;
+            }
+        };
+
+        let Ok(Some(event_id)) = raw_event.get_field::<OwnedEventId>("event_id") else {
+            warn!(room_id = ?room.room_id(), "Failed to parse the create event of the room");
+            return;
+        };
+
+        if self.item_by_event_id(&event_id).await.is_some() {
+            return;
+        }
+
+        let event = TimelineEvent::new(raw_event.cast());
+        if let Err(err) = self
+            .inner
+            .handle_back_paginated_events_with(vec![event], PaginationTokens::default(), room)
+            .await
+        {
+            warn!("Failed to add the create event of the room to the timeline: {err:?}");
+        }
+    }
+
+    /// Do a single forward-pagination request, if the timeline isn't live.
+    ///
+    /// Returns `Ok(true)` if the live end of the room was reached.
+    pub(super) async fn paginate_forwards_impl(&self, num_events: u16) -> Result<bool> {
+        let Some(from) = self.inner.forward_pagination_token().await else {
+            trace!("Timeline is already live");
+            return Ok(true);
+        };
+
+        trace!("Requesting messages");
+
+        let messages = self
+            .room()
+            .messages(assign!(MessagesOptions::forward(), {
+                from: Some(from.clone()),
+                limit: num_events.into(),
+            }))
+            .await?;
+
+        // The server omits the end token once there are no more events to return, but
+        // an empty chunk means the same.
+        let to = if messages.chunk.is_empty() { None } else { messages.end };
+
+        match self.inner.handle_forward_paginated_events(messages.chunk, &from, to).await {
+            Some(_) => Ok(self.inner.is_live().await),
+            None => {
+                info!("End of timeline was altered since pagination was started, ignoring");
+                Ok(false)
+            }
+        }
+    }
+
+    /// Load the replies of the focused thread that are older than `from`.
+    ///
+    /// Once all the replies are loaded, the thread root is added at the start
+    /// of the chunk, and the returned token is `None`.
+    async fn paginate_thread_backwards_once(
+        &self,
+        root_event_id: &EventId,
+        from: Option<String>,
+        limit: u16,
+    ) -> Result<(Vec<TimelineEvent>, Option<String>)> {
+        trace!("Requesting thread replies");
+
+        let mut relations = self
+            .room()
+            .relations(
+                root_event_id,
+                assign!(RelationsOptions::backward(), {
+                    from,
+                    limit: Some(limit.into()),
+                    rel_type: Some(RelationType::Thread),
+                }),
+            )
+            .await?;
+
+        if relations.next_batch.is_none() {
+            // Back-paginated chunks go from the most recent to the oldest event.
+            relations.chunk.push(self.room().event(root_event_id).await?);
+        }
+
+        Ok((relations.chunk, relations.next_batch))
+    }
 }
 
 /// Options for pagination.
@@ -33,7 +33,7 @@ use super::{
     },
     traits::RoomDataProvider,
     util::{rfind_event_by_id, RelativePosition},
-    TimelineItem,
+    TimelineFocus, TimelineItem,
 };
 
 /// In-memory caches for read receipts.
@@ -349,6 +349,7 @@ impl TimelineInnerStateTransaction<'_> {
         &mut self,
         receipt_event_content: ReceiptEventContent,
         own_user_id: &UserId,
+        focus: &TimelineFocus,
     ) {
         for (event_id, receipt_types) in receipt_event_content.0 {
             for (receipt_type, receipts) in receipt_types {
@@ -358,7 +359,7 @@ impl TimelineInnerStateTransaction<'_> {
                 }
 
                 for (user_id, receipt) in receipts {
-                    if !matches!(receipt.thread, ReceiptThread::Unthreaded | ReceiptThread::Main) {
+                    if !focus.includes_receipt_thread(&receipt.thread) {
                         continue;
                     }
 
@@ -34,8 +34,8 @@ use stream_assert::assert_next_matches;
 
 use super::TestTimeline;
 use crate::timeline::{
-    event_item::AnyOtherFullStateEventContent, MembershipChange, TimelineDetails,
-    TimelineItemContent, TimelineItemKind, VirtualTimelineItem,
+    event_item::{AnyOtherFullStateEventContent, RemoteEventOrigin},
+    MembershipChange, TimelineDetails, TimelineItemContent, TimelineItemKind, VirtualTimelineItem,
 };
 
 #[async_test]
@@ -59,6 +59,7 @@ async fn initial_events() {
                 ),
             ],
             None,
+            RemoteEventOrigin::Cache,
         )
         .await;
 
@@ -249,6 +250,7 @@ async fn dedup_initial() {
                 event_c,
             ],
             None,
+            RemoteEventOrigin::Cache,
         )
         .await;
 
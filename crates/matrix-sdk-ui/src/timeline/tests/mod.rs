@@ -68,6 +68,7 @@ mod reaction_group;
 mod reactions;
 mod read_receipts;
 mod redaction;
+mod threads;
 mod virt;
 
 struct TestTimeline {
@@ -27,7 +27,7 @@ use ruma::{
 use stream_assert::assert_next_matches;
 
 use crate::timeline::{
-    event_item::EventItemIdentifier,
+    event_item::{EventItemIdentifier, RemoteEventOrigin},
     inner::ReactionAction,
     reactions::ReactionToggleResult,
     tests::{assert_event_is_updated, assert_no_more_updates, TestTimeline},
@@ -260,6 +260,7 @@ async fn initial_reaction_timestamp_is_stored() {
                 )),
             ],
             None,
+            RemoteEventOrigin::Cache,
         )
         .await;
 
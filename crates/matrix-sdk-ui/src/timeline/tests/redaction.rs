@@ -35,7 +35,10 @@ use ruma::{
 use stream_assert::assert_next_matches;
 
 use super::TestTimeline;
-use crate::timeline::{AnyOtherFullStateEventContent, TimelineDetails, TimelineItemContent};
+use crate::timeline::{
+    event_item::RemoteEventOrigin, AnyOtherFullStateEventContent, TimelineDetails,
+    TimelineItemContent,
+};
 
 #[async_test]
 async fn redact_state_event() {
@@ -153,6 +156,7 @@ async fn reaction_redaction_timeline_filter() {
                     .make_sync_redacted_message_event(*ALICE, RedactedReactionEventContent::new()),
             )],
             None,
+            RemoteEventOrigin::Cache,
         )
         .await;
     // Timeline items are actually empty.
@@ -0,0 +1,316 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use assert_matches2::assert_let;
+use eyeball_im::VectorDiff;
+use matrix_sdk::deserialized_responses::SyncTimelineEvent;
+use matrix_sdk_test::{async_test, sync_timeline_event, ALICE, BOB};
+use ruma::{
+    event_id,
+    events::{
+        receipt::{Receipt, ReceiptThread, ReceiptType},
+        relation::Thread,
+        room::message::{Relation, RoomMessageEventContent},
+    },
+    owned_event_id, uint, EventId, MilliSecondsSinceUnixEpoch,
+};
+use stream_assert::{assert_next_matches, assert_pending};
+
+use super::{ReadReceiptMap, TestRoomDataProvider, TestTimeline};
+use crate::timeline::{
+    event_item::RemoteEventOrigin, inner::TimelineInnerSettings, TimelineFocus, TimelineItemContent,
+};
+
+fn thread_reply(root_event_id: &EventId, body: &str) -> RoomMessageEventContent {
+    let mut content = RoomMessageEventContent::text_plain(body);
+    content.relates_to =
+        Some(Relation::Thread(Thread::plain(root_event_id.to_owned(), root_event_id.to_owned())));
+    content
+}
+
+#[async_test]
+async fn live_thread_reply_updates_summary() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+
+    let root_event_id = event_id!("$root");
+    timeline
+        .handle_live_message_event_with_id(
+            *BOB,
+            root_event_id,
+            RoomMessageEventContent::text_plain("Thread root"),
+        )
+        .await;
+
+    let root = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_let!(TimelineItemContent::Message(msg) = root.content());
+    assert!(msg.thread_summary().is_none());
+
+    let mut reply = RoomMessageEventContent::text_plain("Thread reply");
+    reply.relates_to =
+        Some(Relation::Thread(Thread::plain(root_event_id.to_owned(), root_event_id.to_owned())));
+    timeline.handle_live_message_event(*BOB, reply).await;
+
+    let reply = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_let!(TimelineItemContent::Message(msg) = reply.content());
+    assert_eq!(msg.thread_root(), Some(root_event_id));
+
+    let root = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    assert_let!(TimelineItemContent::Message(msg) = root.content());
+    let summary = msg.thread_summary().unwrap();
+    assert_eq!(summary.num_replies(), 1);
+    assert_eq!(summary.latest_reply_event_id(), reply.event_id());
+    assert_eq!(summary.latest_reply_sender(), Some(*BOB));
+    assert!(!summary.current_user_participated());
+
+    let mut own_reply = RoomMessageEventContent::text_plain("Own reply");
+    own_reply.relates_to = Some(Relation::Thread(Thread::plain(
+        root_event_id.to_owned(),
+        reply.event_id().unwrap().to_owned(),
+    )));
+    timeline.handle_live_message_event(*ALICE, own_reply).await;
+
+    let own_reply = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let root = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    assert_let!(TimelineItemContent::Message(msg) = root.content());
+    let summary = msg.thread_summary().unwrap();
+    assert_eq!(summary.num_replies(), 2);
+    assert_eq!(summary.latest_reply_event_id(), own_reply.event_id());
+    assert_eq!(summary.latest_reply_sender(), Some(*ALICE));
+    assert!(summary.current_user_participated());
+}
+
+#[async_test]
+async fn thread_reply_from_cache_updates_summary() {
+    let mut timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+
+    let root_event_id = event_id!("$root");
+    let root = timeline.event_builder.make_sync_message_event_with_id(
+        *BOB,
+        root_event_id,
+        RoomMessageEventContent::text_plain("Thread root"),
+    );
+    let reply =
+        timeline.event_builder.make_sync_message_event(*BOB, thread_reply(root_event_id, "Reply"));
+    timeline
+        .inner
+        .add_initial_events(
+            vec![SyncTimelineEvent::new(root), SyncTimelineEvent::new(reply)],
+            None,
+            RemoteEventOrigin::Cache,
+        )
+        .await;
+
+    let _root = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let reply = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+
+    let root = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    assert_let!(TimelineItemContent::Message(msg) = root.content());
+    let summary = msg.thread_summary().unwrap();
+    assert_eq!(summary.num_replies(), 1);
+    assert_eq!(summary.latest_reply_event_id(), reply.event_id());
+}
+
+#[async_test]
+async fn bundled_thread_reply_is_not_counted_twice() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+
+    let root_event_id = event_id!("$root");
+    timeline
+        .handle_live_custom_event(sync_timeline_event!({
+            "content": {
+                "body": "Thread root",
+                "msgtype": "m.text",
+            },
+            "event_id": root_event_id,
+            "origin_server_ts": 0,
+            "sender": *BOB,
+            "type": "m.room.message",
+            "unsigned": {
+                "m.relations": {
+                    "m.thread": {
+                        "latest_event": {
+                            "content": {
+                                "body": "First reply",
+                                "msgtype": "m.text",
+                                "m.relates_to": {
+                                    "rel_type": "m.thread",
+                                    "event_id": root_event_id,
+                                },
+                            },
+                            "event_id": "$reply1",
+                            "origin_server_ts": 0,
+                            "sender": *BOB,
+                            "type": "m.room.message",
+                        },
+                        "count": 1,
+                        "current_user_participated": false,
+                    },
+                },
+            },
+        }))
+        .await;
+
+    let root = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_let!(TimelineItemContent::Message(msg) = root.content());
+    assert_eq!(msg.thread_summary().unwrap().num_replies(), 1);
+
+    // The reply in the bundled summary doesn't update it.
+    timeline
+        .handle_live_message_event_with_id(
+            *BOB,
+            event_id!("$reply1"),
+            thread_reply(root_event_id, "First reply"),
+        )
+        .await;
+    let _reply = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_pending!(stream);
+
+    // A more recent reply does.
+    timeline
+        .handle_live_message_event_with_id(
+            *ALICE,
+            event_id!("$reply2"),
+            thread_reply(root_event_id, "Second reply"),
+        )
+        .await;
+    let _reply = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+
+    let root = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    assert_let!(TimelineItemContent::Message(msg) = root.content());
+    let summary = msg.thread_summary().unwrap();
+    assert_eq!(summary.num_replies(), 2);
+    assert_eq!(summary.latest_reply_event_id(), Some(event_id!("$reply2")));
+    assert!(summary.current_user_participated());
+}
+
+#[async_test]
+async fn thread_focus_read_receipts_and_unread_count() {
+    let root_event_id = event_id!("$root");
+    let timeline = TestTimeline::new().with_settings(TimelineInnerSettings {
+        track_read_receipts: true,
+        focus: TimelineFocus::Thread { root_event_id: root_event_id.to_owned() },
+        ..Default::default()
+    });
+
+    timeline
+        .handle_live_message_event_with_id(
+            *BOB,
+            root_event_id,
+            RoomMessageEventContent::text_plain("Thread root"),
+        )
+        .await;
+    timeline
+        .handle_live_message_event_with_id(
+            *BOB,
+            event_id!("$reply1"),
+            thread_reply(root_event_id, "First reply"),
+        )
+        .await;
+    timeline
+        .handle_live_message_event_with_id(
+            *BOB,
+            event_id!("$reply2"),
+            thread_reply(root_event_id, "Second reply"),
+        )
+        .await;
+    assert_eq!(timeline.inner.num_unread_events().await, 3);
+
+    // A receipt in another thread is ignored.
+    timeline
+        .handle_read_receipts([(
+            owned_event_id!("$reply2"),
+            ReceiptType::Read,
+            ALICE.to_owned(),
+            ReceiptThread::Thread(owned_event_id!("$other_root")),
+        )])
+        .await;
+    assert_eq!(timeline.inner.num_unread_events().await, 3);
+
+    // A receipt in the focused thread is used.
+    timeline
+        .handle_read_receipts([(
+            owned_event_id!("$reply1"),
+            ReceiptType::Read,
+            ALICE.to_owned(),
+            ReceiptThread::Thread(root_event_id.to_owned()),
+        )])
+        .await;
+    assert_eq!(
+        timeline.inner.latest_user_read_receipt_timeline_event_id(*ALICE).await.as_deref(),
+        Some(event_id!("$reply1"))
+    );
+    assert_eq!(timeline.inner.num_unread_events().await, 1);
+
+    // A receipt in the main thread is ignored.
+    timeline
+        .handle_read_receipts([(
+            owned_event_id!("$reply2"),
+            ReceiptType::Read,
+            ALICE.to_owned(),
+            ReceiptThread::Main,
+        )])
+        .await;
+    assert_eq!(timeline.inner.num_unread_events().await, 1);
+
+    // An unthreaded receipt is used.
+    timeline
+        .handle_read_receipts([(
+            owned_event_id!("$reply2"),
+            ReceiptType::Read,
+            ALICE.to_owned(),
+            ReceiptThread::Unthreaded,
+        )])
+        .await;
+    assert_eq!(timeline.inner.num_unread_events().await, 0);
+
+    // Own replies are never unread.
+    timeline.handle_live_message_event(*ALICE, thread_reply(root_event_id, "Own reply")).await;
+    assert_eq!(timeline.inner.num_unread_events().await, 0);
+}
+
+#[async_test]
+async fn thread_focus_initial_threaded_receipt() {
+    let root_event_id = owned_event_id!("$root");
+    let thread_receipt_event_id = owned_event_id!("$thread_receipt");
+
+    let mut initial_user_receipts = ReadReceiptMap::new();
+    let receipts = initial_user_receipts.entry(ReceiptType::Read).or_default();
+    receipts.entry(ReceiptThread::Unthreaded).or_default().insert(
+        ALICE.to_owned(),
+        (
+            owned_event_id!("$unthreaded_receipt"),
+            Receipt::new(MilliSecondsSinceUnixEpoch(uint!(10))),
+        ),
+    );
+    receipts.entry(ReceiptThread::Thread(root_event_id.clone())).or_default().insert(
+        ALICE.to_owned(),
+        (thread_receipt_event_id.clone(), Receipt::new(MilliSecondsSinceUnixEpoch(uint!(5)))),
+    );
+
+    let mut timeline = TestTimeline::with_room_data_provider(
+        TestRoomDataProvider::with_initial_user_receipts(initial_user_receipts),
+    )
+    .with_settings(TimelineInnerSettings {
+        track_read_receipts: true,
+        focus: TimelineFocus::Thread { root_event_id },
+        ..Default::default()
+    });
+    timeline.inner.populate_initial_user_receipt(ReceiptType::Read).await;
+
+    let (receipt_event_id, _) = timeline.inner.latest_user_read_receipt(*ALICE).await.unwrap();
+    assert_eq!(receipt_event_id, thread_receipt_event_id);
+}
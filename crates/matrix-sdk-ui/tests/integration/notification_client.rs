@@ -4,11 +4,16 @@ use std::{
 };
 
 use assert_matches::assert_matches;
+use futures_util::{pin_mut, FutureExt, StreamExt};
 use matrix_sdk::config::SyncSettings;
-use matrix_sdk_test::{async_test, sync_timeline_event, JoinedRoomBuilder, SyncResponseBuilder};
+use matrix_sdk_test::{
+    async_test, sync_timeline_event, EphemeralTestEvent, JoinedRoomBuilder,
+    RoomAccountDataTestEvent, StateTestEvent, SyncResponseBuilder,
+};
 use matrix_sdk_ui::{
     notification_client::{
-        NotificationClient, NotificationEvent, NotificationProcessSetup, NotificationStatus,
+        local_notifications, Error, NotificationClient, NotificationEvent,
+        NotificationProcessSetup, NotificationStatus, PushPayload,
     },
     sync_service::SyncService,
 };
@@ -309,3 +314,279 @@ async fn test_notification_client_sliding_sync() {
     assert_eq!(item.room_display_name, room_name);
     assert_eq!(item.is_noisy, Some(false));
 }
+
+#[async_test]
+async fn test_local_notifications() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client().await;
+
+    let notifications = local_notifications(&client).await;
+    pin_mut!(notifications);
+
+    let sender = user_id!("@user:example.org");
+    let my_user_id = client.user_id().unwrap().to_owned();
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_state_event(StateTestEvent::Member)
+            .add_timeline_event(sync_timeline_event!({
+                "content": {
+                    "body": "Hello world!",
+                    "msgtype": "m.text",
+                },
+                "event_id": "$message",
+                "origin_server_ts": 152049794,
+                "sender": sender,
+                "type": "m.room.message",
+            }))
+            .add_timeline_event(sync_timeline_event!({
+                "content": {
+                    "body": "Hello me!",
+                    "msgtype": "m.text",
+                },
+                "event_id": "$own_message",
+                "origin_server_ts": 152049795,
+                "sender": my_user_id,
+                "type": "m.room.message",
+            }))
+            .add_timeline_event(sync_timeline_event!({
+                "content": {
+                    "body": "Hello example!",
+                    "msgtype": "m.text",
+                    "m.mentions": {
+                        "user_ids": [my_user_id],
+                    },
+                },
+                "event_id": "$mention",
+                "origin_server_ts": 152049796,
+                "sender": sender,
+                "type": "m.room.message",
+            })),
+    );
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    mock_encryption_state(&server, false).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+
+    // A regular message notifies silently.
+    let item = notifications.next().now_or_never().flatten().unwrap();
+    assert_matches!(item.event, NotificationEvent::Timeline(event) => {
+        assert_eq!(event.event_id(), event_id!("$message"));
+    });
+    assert_eq!(item.is_noisy, Some(false));
+    assert_eq!(item.has_mention, Some(false));
+
+    // The message sent by the current user is ignored, and the mention notifies
+    // with a sound and a highlight.
+    let item = notifications.next().now_or_never().flatten().unwrap();
+    assert_matches!(item.event, NotificationEvent::Timeline(event) => {
+        assert_eq!(event.event_id(), event_id!("$mention"));
+    });
+    assert_eq!(item.is_noisy, Some(true));
+    assert_eq!(item.has_mention, Some(true));
+
+    assert!(notifications.next().now_or_never().is_none());
+}
+
+#[async_test]
+async fn test_local_notifications_ignore_read_and_seen_events() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client().await;
+
+    let notifications = local_notifications(&client).await;
+    pin_mut!(notifications);
+
+    let sender = user_id!("@user:example.org");
+    let my_user_id = client.user_id().unwrap().to_owned();
+
+    let read_event = sync_timeline_event!({
+        "content": {
+            "body": "Hello world!",
+            "msgtype": "m.text",
+        },
+        "event_id": "$read",
+        "origin_server_ts": 152049794,
+        "sender": sender,
+        "type": "m.room.message",
+    });
+    let unread_event = sync_timeline_event!({
+        "content": {
+            "body": "Are you there?",
+            "msgtype": "m.text",
+        },
+        "event_id": "$unread",
+        "origin_server_ts": 152049795,
+        "sender": sender,
+        "type": "m.room.message",
+    });
+
+    // The first event was read on another device.
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_state_event(StateTestEvent::Member)
+            .add_timeline_event(read_event.clone())
+            .add_timeline_event(unread_event.clone())
+            .add_ephemeral_event(EphemeralTestEvent::Custom(json!({
+                "content": {
+                    "$read": {
+                        "m.read": {
+                            my_user_id.clone(): {
+                                "ts": 152049800,
+                            },
+                        },
+                    },
+                },
+                "type": "m.receipt",
+            }))),
+    );
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    mock_encryption_state(&server, false).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+    server.reset().await;
+
+    let item = notifications.next().now_or_never().flatten().unwrap();
+    assert_matches!(item.event, NotificationEvent::Timeline(event) => {
+        assert_eq!(event.event_id(), event_id!("$unread"));
+    });
+    assert!(notifications.next().now_or_never().is_none());
+
+    // The room's timeline is received again, the events don't notify again.
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_timeline_event(read_event)
+            .add_timeline_event(unread_event),
+    );
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+    server.reset().await;
+
+    assert!(notifications.next().now_or_never().is_none());
+
+    // A new event that is already behind the fully-read marker doesn't notify.
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_timeline_event(sync_timeline_event!({
+                "content": {
+                    "body": "Never mind",
+                    "msgtype": "m.text",
+                },
+                "event_id": "$fully_read",
+                "origin_server_ts": 152049796,
+                "sender": sender,
+                "type": "m.room.message",
+            }))
+            .add_account_data(RoomAccountDataTestEvent::Custom(json!({
+                "content": {
+                    "event_id": "$fully_read",
+                },
+                "type": "m.fully_read",
+            }))),
+    );
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+    server.reset().await;
+
+    assert!(notifications.next().now_or_never().is_none());
+}
+
+#[async_test]
+async fn test_notification_client_from_push() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client().await;
+
+    let event_id = event_id!("$example_event_id");
+    let sender = user_id!("@user:example.org");
+    let event_json = json!({
+        "content": {
+            "body": "Hello world!",
+            "msgtype": "m.text",
+        },
+        "room_id": room_id,
+        "event_id": event_id,
+        "origin_server_ts": 152049794,
+        "sender": sender,
+        "type": "m.room.message",
+    });
+
+    // The room and the sender are known from a previous sync.
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id).add_state_event(StateTestEvent::Member).add_state_event(
+            StateTestEvent::Custom(json!({
+                "content": {
+                    "avatar_url": "https://example.org/avatar.jpeg",
+                    "displayname": "John Mastodon",
+                    "membership": "join",
+                },
+                "event_id": "$151800140517rfvjc:example.org",
+                "origin_server_ts": 151800140,
+                "sender": sender,
+                "state_key": sender,
+                "type": "m.room.member",
+            })),
+        ),
+    );
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+    server.reset().await;
+
+    // The event is fetched with a single `/rooms/*/event/` request.
+    Mock::given(method("GET"))
+        .and(path(format!("/_matrix/client/r0/rooms/{room_id}/event/{event_id}")))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(event_json))
+        .expect(2)
+        .mount(&server)
+        .await;
+    mock_encryption_state(&server, false).await;
+
+    let payload = PushPayload::from_json(
+        &json!({
+            "room_id": room_id,
+            "event_id": event_id,
+            "unread_count": "3",
+            "prio": "high",
+        })
+        .to_string(),
+    )
+    .unwrap();
+    assert_eq!(payload.unread_count, Some(3));
+
+    let notification_client =
+        NotificationClient::builder(client.clone(), NotificationProcessSetup::MultipleProcesses)
+            .await
+            .unwrap()
+            .build();
+
+    let item = notification_client
+        .get_notification_from_push(&payload)
+        .await
+        .unwrap()
+        .expect("the notification should be found");
+
+    assert_matches!(item.event, NotificationEvent::Timeline(event) => {
+        assert_eq!(event.event_type(), TimelineEventType::RoomMessage);
+    });
+    assert_eq!(item.sender_display_name.as_deref(), Some("John Mastodon"));
+    assert_eq!(item.sender_avatar_url.as_deref(), Some("https://example.org/avatar.jpeg"));
+    assert_eq!(item.unread_count, Some(3));
+
+    // Events larger than the maximum size are rejected.
+    let notification_client =
+        NotificationClient::builder(client, NotificationProcessSetup::MultipleProcesses)
+            .await
+            .unwrap()
+            .max_push_event_size(16)
+            .build();
+
+    assert_matches!(
+        notification_client.get_notification_from_push(&payload).await,
+        Err(Error::PushEventTooLarge(16))
+    );
+}
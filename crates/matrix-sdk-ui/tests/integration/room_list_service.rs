@@ -7,12 +7,16 @@ use assert_matches::assert_matches;
 use eyeball_im::VectorDiff;
 use futures_util::{pin_mut, FutureExt, StreamExt};
 use imbl::vector;
-use matrix_sdk::Client;
+use matrix_sdk::{config::SyncSettings, Client};
 use matrix_sdk_base::sync::UnreadNotificationsCount;
-use matrix_sdk_test::async_test;
+use matrix_sdk_test::{async_test, sync_timeline_event, JoinedRoomBuilder, SyncResponseBuilder};
 use matrix_sdk_ui::{
     room_list_service::{
-        filters::{new_filter_fuzzy_match_room_name, new_filter_non_left, new_filter_none},
+        filters::{
+            new_filter_fuzzy_match_room_name, new_filter_non_left, new_filter_none,
+            new_filter_space,
+        },
+        sorters::new_sorter_name,
         Error, Input, InputResult, RoomListEntry, RoomListLoadingState, State, SyncIndicator,
         ALL_ROOMS_LIST_NAME as ALL_ROOMS, INVITES_LIST_NAME as INVITES,
         VISIBLE_ROOMS_LIST_NAME as VISIBLE_ROOMS,
@@ -32,7 +36,7 @@ use tokio::{spawn, sync::mpsc::channel, task::yield_now};
 use wiremock::MockServer;
 
 use crate::{
-    logged_in_client,
+    logged_in_client, mock_sync,
     timeline::sliding_sync::{assert_timeline_stream, timeline_event},
 };
 
@@ -2094,6 +2098,92 @@ async fn test_dynamic_entries_stream_manual_update() -> Result<(), Error> {
     Ok(())
 }
 
+#[async_test]
+async fn test_dynamic_entries_stream_with_sorter() -> Result<(), Error> {
+    let (client, server, room_list) = new_room_list_service().await?;
+
+    let sync = room_list.sync();
+    pin_mut!(sync);
+
+    let all_rooms = room_list.all_rooms().await?;
+
+    let (dynamic_entries_stream, dynamic_entries) =
+        all_rooms.entries_with_dynamic_adapters(5, client.roominfo_update_receiver());
+    pin_mut!(dynamic_entries_stream);
+
+    sync_then_assert_request_and_fake_response! {
+        [server, room_list, sync]
+        states = Init => SettingUp,
+        assert request >= {
+            "lists": {
+                ALL_ROOMS: {
+                    "ranges": [[0, 19]],
+                },
+            },
+        },
+        respond with = {
+            "pos": "0",
+            "lists": {
+                ALL_ROOMS: {
+                    "count": 2,
+                    "ops": [
+                        {
+                            "op": "SYNC",
+                            "range": [0, 1],
+                            "room_ids": [
+                                "!r0:bar.org",
+                                "!r1:bar.org",
+                            ],
+                        },
+                    ],
+                },
+            },
+            "rooms": {
+                "!r0:bar.org": {
+                    "name": "Matrix Foobar",
+                    "initial": true,
+                    "timeline": [],
+                },
+                "!r1:bar.org": {
+                    "name": "Matrix Bar",
+                    "initial": true,
+                    "timeline": [],
+                },
+            },
+        },
+    };
+
+    // Setting a sorter before a filter doesn't start the stream.
+    assert!(dynamic_entries.set_sorter(Box::new(new_sorter_name(&client))));
+    assert_pending!(dynamic_entries_stream);
+
+    // Now, let's define a filter.
+    dynamic_entries.set_filter(Box::new(new_filter_fuzzy_match_room_name(&client, "mat")));
+
+    // The entries are sorted by name, not in the order of the server.
+    assert_entries_batch! {
+        [dynamic_entries_stream]
+        reset [ F("!r1:bar.org"), F("!r0:bar.org") ];
+        end;
+    };
+    assert_pending!(dynamic_entries_stream);
+
+    // Replacing the sorter sorts the entries again.
+    let by_name = new_sorter_name(&client);
+    dynamic_entries.set_sorter(Box::new(move |left: &RoomListEntry, right: &RoomListEntry| {
+        by_name(right, left)
+    }));
+
+    assert_entries_batch! {
+        [dynamic_entries_stream]
+        reset [ F("!r0:bar.org"), F("!r1:bar.org") ];
+        end;
+    };
+    assert_pending!(dynamic_entries_stream);
+
+    Ok(())
+}
+
 #[async_test]
 async fn test_invites_stream() -> Result<(), Error> {
     let (_, server, room_list) = new_room_list_service().await?;
@@ -3018,3 +3108,70 @@ async fn test_sync_indicator() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[async_test]
+async fn test_space_filter_follows_space_children() {
+    let (client, server) = logged_in_client().await;
+
+    let space_id = room_id!("!space:localhost");
+    let room_a = room_id!("!a:localhost");
+    let room_b = room_id!("!b:localhost");
+
+    let space_child_event = |child_id: &str, ts: u64| {
+        sync_timeline_event!({
+            "content": { "via": ["localhost"] },
+            "event_id": format!("$child_{child_id}"),
+            "origin_server_ts": ts,
+            "sender": "@example:localhost",
+            "state_key": child_id,
+            "type": "m.space.child",
+        })
+    };
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder
+        .add_joined_room(
+            JoinedRoomBuilder::new(space_id)
+                .add_timeline_event(sync_timeline_event!({
+                    "content": { "creator": "@example:localhost", "type": "m.space" },
+                    "event_id": "$create",
+                    "origin_server_ts": 1,
+                    "sender": "@example:localhost",
+                    "state_key": "",
+                    "type": "m.room.create",
+                }))
+                .add_timeline_event(space_child_event(room_a.as_str(), 2)),
+        )
+        .add_joined_room(JoinedRoomBuilder::new(room_a))
+        .add_joined_room(JoinedRoomBuilder::new(room_b));
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::new()).await.unwrap();
+    server.reset().await;
+
+    let space = client.get_space(space_id).unwrap();
+    let filter = new_filter_space(&space).await.unwrap();
+    assert!(filter(&RoomListEntry::Filled(room_a.to_owned())));
+    assert!(filter(&RoomListEntry::Filled(room_b.to_owned())).not());
+
+    let mut roominfo_update_recv = client.roominfo_update_receiver();
+
+    // A room is added to the space.
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(space_id).add_timeline_event(space_child_event(room_b.as_str(), 3)),
+    );
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::new()).await.unwrap();
+    server.reset().await;
+
+    assert!(filter(&RoomListEntry::Filled(room_a.to_owned())));
+    assert!(filter(&RoomListEntry::Filled(room_b.to_owned())));
+
+    // The room list is told to filter the entry of the room again.
+    let mut updated_room_ids = Vec::new();
+    while let Ok(update) = roominfo_update_recv.try_recv() {
+        if update.trigger_room_list_update {
+            updated_room_ids.push(update.room_id);
+        }
+    }
+    assert!(updated_room_ids.iter().any(|room_id| room_id == room_b));
+}
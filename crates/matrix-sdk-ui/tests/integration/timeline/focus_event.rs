@@ -0,0 +1,201 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use assert_matches::assert_matches;
+use assert_matches2::assert_let;
+use eyeball_im::VectorDiff;
+use matrix_sdk::config::SyncSettings;
+use matrix_sdk_test::{async_test, JoinedRoomBuilder, SyncResponseBuilder};
+use matrix_sdk_ui::timeline::{
+    Error, EventItemOrigin, EventTimelineItem, RoomExt, TimelineFocus, TimelineItemContent,
+};
+use ruma::{event_id, events::room::message::RoomMessageEventContent, room_id, RoomId};
+use serde_json::{json, Value as JsonValue};
+use stream_assert::{assert_next_matches, assert_pending};
+use wiremock::{
+    matchers::{header, method, path_regex, query_param},
+    Mock, ResponseTemplate,
+};
+
+use crate::{logged_in_client, mock_encryption_state, mock_sync};
+
+fn message_event(room_id: &RoomId, event_id: &str, body: &str, ts: u64) -> JsonValue {
+    json!({
+        "content": {
+            "body": body,
+            "msgtype": "m.text",
+        },
+        "event_id": event_id,
+        "origin_server_ts": ts,
+        "sender": "@alice:example.org",
+        "type": "m.room.message",
+        "room_id": room_id,
+    })
+}
+
+fn item_body(item: &EventTimelineItem) -> &str {
+    assert_let!(TimelineItemContent::Message(msg) = item.content());
+    msg.body()
+}
+
+#[async_test]
+async fn test_new_focused() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/context/.*"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "events_before": [
+                message_event(room_id, "$before2", "second before", 1002),
+                message_event(room_id, "$before1", "first before", 1001),
+            ],
+            "event": message_event(room_id, "$target", "target", 1003),
+            "events_after": [
+                message_event(room_id, "$after1", "first after", 1004),
+            ],
+            "start": "prev1",
+            "end": "next1",
+            "state": [],
+        })))
+        .expect(1)
+        .named("context")
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = room
+        .timeline_builder()
+        .with_focus(TimelineFocus::Event {
+            target: event_id!("$target").to_owned(),
+            num_context_events: 20,
+        })
+        .build()
+        .await
+        .unwrap();
+    server.reset().await;
+
+    assert!(!timeline.is_live().await);
+
+    let (items, mut timeline_stream) =
+        timeline.subscribe_filter_map(|item| item.as_event().cloned()).await;
+    assert_eq!(items.len(), 4);
+    assert_eq!(item_body(&items[0]), "first before");
+    assert_eq!(item_body(&items[1]), "second before");
+    assert_eq!(item_body(&items[2]), "target");
+    assert_eq!(item_body(&items[3]), "first after");
+    for item in &items {
+        assert_matches!(item.origin(), Some(EventItemOrigin::Pagination));
+    }
+
+    // The local echo isn't added until the timeline reaches the end of the room.
+    mock_encryption_state(&server, false).await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$sent" })))
+        .mount(&server)
+        .await;
+
+    timeline.send(RoomMessageEventContent::text_plain("Hello, World!").into()).await;
+    assert_pending!(timeline_stream);
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("from", "next1"))
+        .and(query_param("dir", "f"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [message_event(room_id, "$after2", "second after", 1005)],
+            "start": "next1",
+            "end": "next2",
+        })))
+        .expect(1)
+        .named("messages_forward_1")
+        .mount(&server)
+        .await;
+
+    let reached_live = timeline.paginate_forwards(10).await.unwrap();
+    assert!(!reached_live);
+    assert!(!timeline.is_live().await);
+
+    let item = assert_next_matches!(timeline_stream, VectorDiff::PushBack { value } => value);
+    assert_eq!(item_body(&item), "second after");
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("from", "next2"))
+        .and(query_param("dir", "f"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [],
+            "start": "next2",
+        })))
+        .expect(1)
+        .named("messages_forward_2")
+        .mount(&server)
+        .await;
+
+    let reached_live = timeline.paginate_forwards(10).await.unwrap();
+    assert!(reached_live);
+    assert!(timeline.is_live().await);
+}
+
+#[async_test]
+async fn test_focused_event_not_found() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/context/.*"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "Event not found.",
+        })))
+        .expect(1)
+        .named("context")
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(room_id).unwrap();
+    let result = room
+        .timeline_builder()
+        .with_focus(TimelineFocus::Event {
+            target: event_id!("$target").to_owned(),
+            num_context_events: 20,
+        })
+        .build()
+        .await;
+
+    assert_matches!(result, Err(Error::FocusedEventLoadingFailed(_)));
+}
@@ -30,12 +30,14 @@ use crate::{logged_in_client, mock_sync};
 
 mod echo;
 mod edit;
+mod focus_event;
 mod pagination;
 mod profiles;
 mod queue;
 mod read_receipts;
 mod replies;
 mod subscribe;
+mod threads;
 
 pub(crate) mod sliding_sync;
 
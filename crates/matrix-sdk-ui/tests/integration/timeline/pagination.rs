@@ -20,11 +20,12 @@ use eyeball_im::VectorDiff;
 use futures_util::future::{join, join3};
 use matrix_sdk::config::SyncSettings;
 use matrix_sdk_test::{
-    async_test, EventBuilder, JoinedRoomBuilder, StateTestEvent, SyncResponseBuilder, ALICE, BOB,
+    async_test, sync_timeline_event, EventBuilder, JoinedRoomBuilder, StateTestEvent,
+    SyncResponseBuilder, ALICE, BOB,
 };
 use matrix_sdk_ui::timeline::{
     AnyOtherFullStateEventContent, BackPaginationStatus, PaginationOptions, RoomExt,
-    TimelineItemContent, VirtualTimelineItem,
+    TimelineDetails, TimelineItemContent, VirtualTimelineItem,
 };
 use once_cell::sync::Lazy;
 use ruma::{
@@ -445,6 +446,131 @@ async fn test_timeline_reset_while_paginating() {
     server.verify().await;
 }
 
+#[async_test]
+async fn test_back_pagination_into_predecessor_room() {
+    let old_room_id = room_id!("!oldroom:example.org");
+    let new_room_id = room_id!("!newroom:example.org");
+    let (client, server) = logged_in_client().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder
+        .add_joined_room(
+            JoinedRoomBuilder::new(old_room_id)
+                .add_timeline_event(sync_timeline_event!({
+                    "content": {
+                        "body": "This room has been replaced",
+                        "replacement_room": new_room_id,
+                    },
+                    "event_id": "$tombstone",
+                    "origin_server_ts": 1,
+                    "sender": "@example:example.org",
+                    "state_key": "",
+                    "type": "m.room.tombstone",
+                }))
+                .add_state_event(StateTestEvent::Custom(json!({
+                    "content": {
+                        "displayname": "Old name",
+                        "membership": "join",
+                    },
+                    "event_id": "$old_member",
+                    "origin_server_ts": 0,
+                    "sender": "@example:example.org",
+                    "state_key": "@example:example.org",
+                    "type": "m.room.member",
+                }))),
+        )
+        .add_joined_room(JoinedRoomBuilder::new(new_room_id).add_timeline_event(
+            sync_timeline_event!({
+                "content": {
+                    "creator": "@example:example.org",
+                    "room_version": "10",
+                    "predecessor": { "room_id": old_room_id, "event_id": "$tombstone" },
+                },
+                "event_id": "$create",
+                "origin_server_ts": 2,
+                "sender": "@example:example.org",
+                "state_key": "",
+                "type": "m.room.create",
+            }),
+        ));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(new_room_id).unwrap();
+    assert_eq!(room.predecessor_room().unwrap().room_id, old_room_id);
+    assert_eq!(
+        client.get_room(old_room_id).unwrap().successor_room().unwrap().room_id,
+        new_room_id
+    );
+
+    let timeline = room.timeline_builder().paginate_predecessor_rooms(true).build().await.unwrap();
+    let mut back_pagination_status = timeline.back_pagination_status();
+    assert_eq!(back_pagination_status.next_now(), BackPaginationStatus::Idle);
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*newroom.*/messages$"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [],
+            "start": "t_new",
+        })))
+        .expect(1)
+        .named("messages_new_room")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*oldroom.*/messages$"))
+        .and(query_param_is_missing("from"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [{
+                "content": { "body": "Hello from the old room", "msgtype": "m.text" },
+                "event_id": "$old_message",
+                "origin_server_ts": 0,
+                "room_id": old_room_id,
+                "sender": "@example:example.org",
+                "type": "m.room.message",
+            }],
+            "start": "t_old",
+        })))
+        .expect(1)
+        .named("messages_old_room")
+        .mount(&server)
+        .await;
+
+    // The start of the new room is reached, pagination continues in the old room.
+    timeline.paginate_backwards(PaginationOptions::simple_request(10)).await.unwrap();
+    assert_eq!(back_pagination_status.next_now(), BackPaginationStatus::Idle);
+
+    timeline.paginate_backwards(PaginationOptions::simple_request(10)).await.unwrap();
+    assert_eq!(back_pagination_status.next_now(), BackPaginationStatus::TimelineStartReached);
+
+    let items = timeline.items().await;
+    let bodies = items
+        .iter()
+        .filter_map(|item| item.as_event()?.content().as_message().map(|msg| msg.body().to_owned()))
+        .collect::<Vec<_>>();
+    assert_eq!(bodies, ["Hello from the old room"]);
+
+    // The create event of the new room separates the events of the two rooms.
+    let event_ids = items
+        .iter()
+        .filter_map(|item| item.as_event()?.event_id().map(ToString::to_string))
+        .collect::<Vec<_>>();
+    assert_eq!(event_ids, ["$old_message", "$create"]);
+
+    // The events of the old room are processed with the members of the old room.
+    let old_message = items.iter().find_map(|item| item.as_event()).unwrap();
+    assert_let!(TimelineDetails::Ready(profile) = old_message.sender_profile());
+    assert_eq!(profile.display_name.as_deref(), Some("Old name"));
+
+    server.verify().await;
+}
+
 pub static ROOM_MESSAGES_BATCH_1: Lazy<JsonValue> = Lazy::new(|| {
     json!({
         "chunk": [
@@ -0,0 +1,157 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use assert_matches2::assert_let;
+use matrix_sdk::config::SyncSettings;
+use matrix_sdk_test::{async_test, JoinedRoomBuilder, SyncResponseBuilder};
+use matrix_sdk_ui::timeline::{
+    EventTimelineItem, PaginationOptions, RoomExt, TimelineFocus, TimelineItemContent,
+};
+use ruma::{api::client::receipt::create_receipt::v3::ReceiptType, event_id, room_id, RoomId};
+use serde_json::{json, Value as JsonValue};
+use wiremock::{
+    matchers::{
+        body_partial_json, header, method, path_regex, query_param, query_param_is_missing,
+    },
+    Mock, ResponseTemplate,
+};
+
+use crate::{logged_in_client, mock_sync};
+
+fn message_event(room_id: &RoomId, event_id: &str, body: &str, ts: u64) -> JsonValue {
+    json!({
+        "content": {
+            "body": body,
+            "msgtype": "m.text",
+        },
+        "event_id": event_id,
+        "origin_server_ts": ts,
+        "sender": "@alice:example.org",
+        "type": "m.room.message",
+        "room_id": room_id,
+    })
+}
+
+fn thread_reply_event(room_id: &RoomId, event_id: &str, body: &str, ts: u64) -> JsonValue {
+    let mut event = message_event(room_id, event_id, body, ts);
+    event["content"]["m.relates_to"] = json!({
+        "rel_type": "m.thread",
+        "event_id": "$root",
+    });
+    event
+}
+
+fn item_body(item: &EventTimelineItem) -> &str {
+    assert_let!(TimelineItemContent::Message(msg) = item.content());
+    msg.body()
+}
+
+#[async_test]
+async fn test_thread_focus_pagination() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = room
+        .timeline_builder()
+        .with_focus(TimelineFocus::Thread { root_event_id: event_id!("$root").to_owned() })
+        .build()
+        .await
+        .unwrap();
+
+    let (items, _) = timeline.subscribe_filter_map(|item| item.as_event().cloned()).await;
+    assert!(items.is_empty());
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v1/rooms/.*/relations/\$root/m\.thread$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("dir", "b"))
+        .and(query_param_is_missing("from"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [thread_reply_event(room_id, "$reply2", "second reply", 1002)],
+            "next_batch": "page2",
+        })))
+        .expect(1)
+        .named("relations_1")
+        .mount(&server)
+        .await;
+
+    timeline.paginate_backwards(PaginationOptions::simple_request(10)).await.unwrap();
+
+    let (items, _) = timeline.subscribe_filter_map(|item| item.as_event().cloned()).await;
+    assert_eq!(items.len(), 1);
+    assert_eq!(item_body(&items[0]), "second reply");
+    server.reset().await;
+
+    // Once all the replies are loaded, the thread root is loaded too.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v1/rooms/.*/relations/\$root/m\.thread$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("dir", "b"))
+        .and(query_param("from", "page2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [thread_reply_event(room_id, "$reply1", "first reply", 1001)],
+        })))
+        .expect(1)
+        .named("relations_2")
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/event/\$root"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(message_event(
+            room_id,
+            "$root",
+            "thread root",
+            1000,
+        )))
+        .expect(1)
+        .named("root_event")
+        .mount(&server)
+        .await;
+
+    timeline.paginate_backwards(PaginationOptions::simple_request(10)).await.unwrap();
+
+    let (items, _) = timeline.subscribe_filter_map(|item| item.as_event().cloned()).await;
+    assert_eq!(items.len(), 3);
+    assert_eq!(item_body(&items[0]), "thread root");
+    assert_eq!(item_body(&items[1]), "first reply");
+    assert_eq!(item_body(&items[2]), "second reply");
+    server.reset().await;
+
+    // The receipt is sent in the thread.
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/receipt/m\.read/\$reply2"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({ "thread_id": "$root" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .named("thread_read_receipt")
+        .mount(&server)
+        .await;
+
+    let has_sent = timeline.mark_as_read(ReceiptType::Read).await.unwrap();
+    assert!(has_sent);
+    server.reset().await;
+}
@@ -49,7 +49,14 @@ use ruma::{
 use serde::Deserialize;
 use tracing::error;
 
-use crate::{config::RequestConfig, Client, Error, HttpError, Result};
+use crate::{
+    config::RequestConfig,
+    uiaa::{UiaaDriver, UiaaHandler},
+    Client, Error, HttpError, Result,
+};
+
+/// The owner of the account's avatar when it is pinned in the media cache.
+const AVATAR_OWNER: &str = "account";
 
 /// A high-level API to manage the client owner's account.
 ///
@@ -184,6 +191,9 @@ impl Account {
     /// If a thumbnail is requested no guarantee on the size of the image is
     /// given.
     ///
+    /// The avatar is pinned in the media cache, so it is not evicted until the
+    /// avatar of the account changes.
+    ///
     /// # Arguments
     ///
     /// * `format` - The desired format of the avatar.
@@ -210,8 +220,9 @@ impl Account {
     pub async fn get_avatar(&self, format: MediaFormat) -> Result<Option<Vec<u8>>> {
         if let Some(url) = self.get_avatar_url().await? {
             let request = MediaRequest { source: MediaSource::Plain(url), format };
-            Ok(Some(self.client.media().get_media_content(&request, true).await?))
+            Ok(Some(self.client.media().get_pinned_media_content(AVATAR_OWNER, &request).await?))
         } else {
+            self.client.media().unpin_media_content(AVATAR_OWNER).await?;
             Ok(None)
         }
     }
@@ -328,6 +339,22 @@ impl Account {
         Ok(self.client.send(request, None).await?)
     }
 
+    /// Change the password of the account, completing the
+    /// [User-Interactive Authentication][uiaa] with the given handler.
+    ///
+    /// See [`Account::change_password()`] for more details.
+    ///
+    /// [uiaa]: https://spec.matrix.org/v1.2/client-server-api/#user-interactive-authentication-api
+    pub async fn change_password_with_uiaa(
+        &self,
+        new_password: &str,
+        handler: &impl UiaaHandler,
+    ) -> Result<change_password::v3::Response> {
+        UiaaDriver::new(&self.client, handler)
+            .run(|auth_data| self.change_password(new_password, auth_data))
+            .await
+    }
+
     /// Deactivate this account definitively.
     ///
     /// # Arguments
@@ -377,6 +404,22 @@ impl Account {
         Ok(self.client.send(request, None).await?)
     }
 
+    /// Deactivate this account definitively, completing the
+    /// [User-Interactive Authentication][uiaa] with the given handler.
+    ///
+    /// See [`Account::deactivate()`] for more details.
+    ///
+    /// [uiaa]: https://spec.matrix.org/v1.2/client-server-api/#user-interactive-authentication-api
+    pub async fn deactivate_with_uiaa(
+        &self,
+        id_server: Option<&str>,
+        handler: &impl UiaaHandler,
+    ) -> Result<deactivate::v3::Response> {
+        UiaaDriver::new(&self.client, handler)
+            .run(|auth_data| self.deactivate(id_server, auth_data))
+            .await
+    }
+
     /// Get the registered [Third Party Identifiers][3pid] on the homeserver of
     /// the account.
     ///
@@ -29,6 +29,8 @@ use ruma::{
     OwnedTransactionId, TransactionId, UInt,
 };
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::media::ByteStream;
 #[cfg(feature = "image-proc")]
 use crate::ImageError;
 
@@ -184,6 +186,26 @@ pub struct Thumbnail {
     pub info: Option<BaseThumbnailInfo>,
 }
 
+/// The raw bytes of an attachment to upload.
+pub(crate) enum AttachmentData {
+    /// The bytes are held in memory.
+    Bytes(Vec<u8>),
+    /// The bytes are streamed.
+    #[cfg(not(target_arch = "wasm32"))]
+    Stream {
+        /// The stream of bytes.
+        stream: ByteStream,
+        /// The total size of the attachment in bytes.
+        size: u64,
+    },
+}
+
+impl From<Vec<u8>> for AttachmentData {
+    fn from(data: Vec<u8>) -> Self {
+        Self::Bytes(data)
+    }
+}
+
 /// Configuration for sending an attachment.
 #[derive(Debug)]
 pub struct AttachmentConfig {
@@ -278,6 +300,58 @@ impl AttachmentConfig {
         self.info = Some(info);
         self
     }
+
+    /// Generate the thumbnail of the attachment, if it was requested with
+    /// [`AttachmentConfig::generate_thumbnail()`] and no thumbnail was
+    /// provided.
+    ///
+    /// Returns the data of the attachment, and the config with the generated
+    /// thumbnail, if any.
+    #[cfg(feature = "image-proc")]
+    pub(crate) async fn generate_thumbnail_if_needed(
+        self,
+        content_type: &mime::Mime,
+        data: Vec<u8>,
+    ) -> Result<(Vec<u8>, Self), ImageError> {
+        if !self.generate_thumbnail || self.thumbnail.is_some() {
+            return Ok((data, self));
+        }
+
+        let content_type = content_type.clone();
+        let thumbnail_size = self.thumbnail_size;
+        let make_thumbnail = move |data: Vec<u8>| {
+            let res = generate_image_thumbnail(&content_type, Cursor::new(&data), thumbnail_size);
+            (data, res)
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let (data, res) = tokio::task::spawn_blocking(move || make_thumbnail(data))
+            .await
+            .expect("Task join error");
+
+        #[cfg(target_arch = "wasm32")]
+        let (data, res) = make_thumbnail(data);
+
+        let thumbnail = match res {
+            Ok((thumbnail_data, thumbnail_info)) => Some(Thumbnail {
+                data: thumbnail_data,
+                content_type: mime::IMAGE_JPEG,
+                info: Some(thumbnail_info),
+            }),
+            Err(ImageError::ThumbnailBiggerThanOriginal | ImageError::FormatNotSupported) => None,
+            Err(error) => return Err(error),
+        };
+
+        let config = Self {
+            txn_id: self.txn_id,
+            info: self.info,
+            thumbnail,
+            generate_thumbnail: false,
+            thumbnail_size: None,
+        };
+
+        Ok((data, config))
+    }
 }
 
 impl Default for AttachmentConfig {
@@ -13,7 +13,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{fmt, sync::Arc};
+use std::{collections::BTreeMap, fmt, sync::Arc};
 
 use matrix_sdk_base::{store::StoreConfig, BaseClient};
 use ruma::{
@@ -321,6 +321,7 @@ impl ClientBuilder {
     /// than `build()` doing it using a `get_supported_versions` request.
     ///
     /// This is helpful for test code that doesn't care to mock that endpoint.
+    /// The homeserver is then assumed not to advertise any unstable feature.
     pub fn server_versions(mut self, value: impl IntoIterator<Item = MatrixVersion>) -> Self {
         self.server_versions = Some(value.into_iter().collect());
         self
@@ -499,6 +500,10 @@ impl ClientBuilder {
             oidc: OidcCtx::new(authentication_server_info, allow_insecure_oidc),
         });
 
+        // The unstable features of the server are unknown if its versions are set
+        // manually, assume that it doesn't advertise any.
+        let unstable_features = self.server_versions.as_ref().map(|_| BTreeMap::new());
+
         let event_cache = OnceCell::new();
         let inner = ClientInner::new(
             auth_ctx,
@@ -508,6 +513,7 @@ impl ClientBuilder {
             http_client,
             base_client,
             self.server_versions,
+            unstable_features,
             self.respect_login_well_known,
             event_cache,
             #[cfg(feature = "e2e-encryption")]
@@ -592,9 +598,18 @@ async fn build_store_config(
     let store_config = match builder_config {
         #[cfg(feature = "sqlite")]
         BuilderStoreConfig::Sqlite { path, passphrase } => {
-            let store_config = StoreConfig::new().state_store(
-                matrix_sdk_sqlite::SqliteStateStore::open(&path, passphrase.as_deref()).await?,
-            );
+            let store_config = StoreConfig::new()
+                .state_store(
+                    matrix_sdk_sqlite::SqliteStateStore::open(&path, passphrase.as_deref()).await?,
+                )
+                .event_cache_store(
+                    matrix_sdk_sqlite::SqliteEventCacheStore::open(&path, passphrase.as_deref())
+                        .await?,
+                )
+                .media_cache_store(
+                    matrix_sdk_sqlite::SqliteMediaCacheStore::open(&path, passphrase.as_deref())
+                        .await?,
+                );
 
             #[cfg(feature = "e2e-encryption")]
             let store_config = store_config.crypto_store(
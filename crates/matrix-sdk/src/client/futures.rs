@@ -20,27 +20,14 @@ use cfg_vis::cfg_vis;
 use eyeball::SharedObservable;
 #[cfg(not(target_arch = "wasm32"))]
 use eyeball::Subscriber;
-#[cfg(feature = "experimental-oidc")]
-use mas_oidc_client::{
-    error::{
-        Error as OidcClientError, ErrorBody as OidcErrorBody, HttpError as OidcHttpError,
-        TokenRefreshError, TokenRequestError,
-    },
-    types::errors::ClientErrorCode,
-};
 use matrix_sdk_common::boxed_into_future;
-use ruma::api::{client::error::ErrorKind, error::FromHttpResponseError, OutgoingRequest};
-#[cfg(feature = "experimental-oidc")]
-use tracing::error;
-use tracing::trace;
+use ruma::api::{error::FromHttpResponseError, MatrixVersion, OutgoingRequest};
 
 use super::super::Client;
-#[cfg(feature = "experimental-oidc")]
-use crate::oidc::OidcError;
 use crate::{
     config::RequestConfig,
     error::{HttpError, HttpResult},
-    RefreshTokenError, TransmissionProgress,
+    TransmissionProgress,
 };
 
 /// `IntoFuture` returned by [`Client::send`].
@@ -48,6 +35,7 @@ use crate::{
 pub struct SendRequest<R> {
     pub(crate) client: Client,
     pub(crate) homeserver_override: Option<String>,
+    pub(crate) server_versions_override: Option<Box<[MatrixVersion]>>,
     pub(crate) request: R,
     pub(crate) config: Option<RequestConfig>,
     pub(crate) send_progress: SharedObservable<TransmissionProgress>,
@@ -79,6 +67,16 @@ impl<R> SendRequest<R> {
         self
     }
 
+    /// Build the request with the given Matrix versions, rather than with the
+    /// ones supported by the homeserver.
+    pub(crate) fn with_server_versions_override(
+        mut self,
+        server_versions_override: Option<Box<[MatrixVersion]>>,
+    ) -> Self {
+        self.server_versions_override = server_versions_override;
+        self
+    }
+
     /// Get a subscriber to observe the progress of sending the request
     /// body.
     #[cfg(not(target_arch = "wasm32"))]
@@ -97,81 +95,32 @@ where
     boxed_into_future!();
 
     fn into_future(self) -> Self::IntoFuture {
-        let Self { client, request, config, send_progress, homeserver_override } = self;
+        let Self {
+            client,
+            request,
+            config,
+            send_progress,
+            homeserver_override,
+            server_versions_override,
+        } = self;
 
         Box::pin(async move {
             let res = Box::pin(client.send_inner(
                 request.clone(),
                 config,
                 homeserver_override.clone(),
+                server_versions_override.clone(),
                 send_progress.clone(),
             ))
             .await;
 
-            // An `M_UNKNOWN_TOKEN` error can potentially be fixed with a token refresh.
-            if let Err(Some(ErrorKind::UnknownToken { soft_logout })) =
-                res.as_ref().map_err(HttpError::client_api_error_kind)
-            {
-                trace!("Token refresh: Unknown token error received.");
-
-                // If automatic token refresh isn't supported, there is nothing more to do.
-                if !client.inner.auth_ctx.handle_refresh_tokens {
-                    trace!("Token refresh: Automatic refresh disabled.");
-                    client.broadcast_unknown_token(soft_logout);
-                    return res;
-                }
-
-                // Try to refresh the token and retry the request.
-                if let Err(refresh_error) = client.refresh_access_token().await {
-                    match &refresh_error {
-                        RefreshTokenError::RefreshTokenRequired => {
-                            trace!("Token refresh: The session doesn't have a refresh token.");
-                            // Refreshing access tokens is not supported by this `Session`, ignore.
-                            client.broadcast_unknown_token(soft_logout);
-                        }
-
-                        #[cfg(feature = "experimental-oidc")]
-                        RefreshTokenError::Oidc(oidc_error) => {
-                            match **oidc_error {
-                                OidcError::Oidc(OidcClientError::TokenRefresh(
-                                    TokenRefreshError::Token(TokenRequestError::Http(
-                                        OidcHttpError {
-                                            body:
-                                                Some(OidcErrorBody {
-                                                    error: ClientErrorCode::InvalidGrant,
-                                                    ..
-                                                }),
-                                            ..
-                                        },
-                                    )),
-                                )) => {
-                                    error!("Token refresh: OIDC refresh_token rejected with invalid grant");
-                                    // The refresh was denied, signal to sign out the user.
-                                    client.broadcast_unknown_token(soft_logout);
-                                }
-                                _ => {
-                                    trace!("Token refresh: OIDC refresh encountered a problem.");
-                                    // The refresh failed for other reasons, no
-                                    // need to sign out.
-                                }
-                            };
-                            return Err(refresh_error.into());
-                        }
-
-                        _ => {
-                            trace!("Token refresh: Token refresh failed.");
-                            // This isn't necessarily correct, but matches the behaviour when
-                            // implementing OIDC.
-                            client.broadcast_unknown_token(soft_logout);
-                            return Err(refresh_error.into());
-                        }
-                    }
-                } else {
-                    trace!("Token refresh: Refresh succeeded, retrying request.");
+            if let Err(error) = &res {
+                if client.refresh_access_token_after_error(error).await? {
                     return Box::pin(client.send_inner(
                         request,
                         config,
                         homeserver_override,
+                        server_versions_override,
                         send_progress,
                     ))
                     .await;
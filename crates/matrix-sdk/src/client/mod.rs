@@ -24,9 +24,18 @@ use std::{
 
 use eyeball::{SharedObservable, Subscriber};
 use futures_core::Stream;
+#[cfg(feature = "experimental-oidc")]
+use mas_oidc_client::{
+    error::{
+        Error as OidcClientError, ErrorBody as OidcErrorBody, HttpError as OidcHttpError,
+        TokenRefreshError, TokenRequestError,
+    },
+    types::errors::ClientErrorCode,
+};
 #[cfg(feature = "e2e-encryption")]
 use matrix_sdk_base::crypto::store::LockableCryptoStore;
 use matrix_sdk_base::{
+    media_cache_store::MediaCachePolicy,
     store::DynStateStore,
     sync::{Notification, RoomUpdates},
     BaseClient, RoomInfoUpdate, RoomState, RoomStateFilter, SendOutsideWasm, SessionMeta,
@@ -34,7 +43,7 @@ use matrix_sdk_base::{
 };
 use matrix_sdk_common::instant::Instant;
 #[cfg(feature = "e2e-encryption")]
-use ruma::events::{room::encryption::RoomEncryptionEventContent, InitialStateEvent};
+use ruma::events::room::encryption::RoomEncryptionEventContent;
 use ruma::{
     api::{
         client::{
@@ -46,7 +55,9 @@ use ruma::{
                 get_capabilities::{self, Capabilities},
                 get_supported_versions,
             },
+            error::ErrorKind,
             filter::{create_filter::v3::Request as FilterUploadRequest, FilterDefinition},
+            knock::knock_room,
             membership::{join_room_by_id, join_room_by_id_or_alias},
             profile::get_profile,
             push::{set_pusher, Pusher},
@@ -60,6 +71,10 @@ use ruma::{
         MatrixVersion, OutgoingRequest,
     },
     assign,
+    events::{
+        room::join_rules::{JoinRule, RoomJoinRulesEventContent},
+        InitialStateEvent,
+    },
     push::Ruleset,
     DeviceId, OwnedDeviceId, OwnedEventId, OwnedRoomId, OwnedServerName, RoomAliasId, RoomId,
     RoomOrAliasId, ServerName, UInt, UserId,
@@ -71,7 +86,7 @@ use url::Url;
 
 use self::futures::SendRequest;
 #[cfg(feature = "experimental-oidc")]
-use crate::oidc::Oidc;
+use crate::oidc::{Oidc, OidcError};
 use crate::{
     authentication::{AuthCtx, AuthData, ReloadSessionCallback, SaveSessionCallback},
     config::RequestConfig,
@@ -83,8 +98,14 @@ use crate::{
     },
     http_client::HttpClient,
     matrix_auth::MatrixAuth,
+    media::CacheCleanUpState,
     notification_settings::NotificationSettings,
+    pushers::Pushers,
+    search::SearchMessages,
+    send_queue::{SendQueue, SendQueueData},
+    space::Space,
     sync::{RoomUpdate, SyncResponse},
+    uiaa::{UiaaDriver, UiaaHandler},
     Account, AuthApi, AuthSession, Error, Media, RefreshTokenError, Result, Room,
     TransmissionProgress,
 };
@@ -110,6 +131,10 @@ type NotificationHandlerFn =
 #[cfg(target_arch = "wasm32")]
 type NotificationHandlerFn = Box<dyn Fn(Notification, Room, Client) -> NotificationHandlerFut>;
 
+/// The unstable feature advertised by servers that support the stable
+/// authenticated media endpoints before Matrix 1.11.
+const AUTHENTICATED_MEDIA_UNSTABLE_FEATURE: &str = "org.matrix.msc3916.stable";
+
 /// Enum controlling if a loop running callbacks should continue or abort.
 ///
 /// This is mainly used in the [`sync_with_callback`] method, the return value
@@ -235,6 +260,10 @@ pub(crate) struct ClientInner {
     /// The Matrix versions the server supports (well-known ones only)
     server_versions: OnceCell<Box<[MatrixVersion]>>,
 
+    /// The unstable features advertised by the server, and whether they are
+    /// enabled.
+    unstable_features: OnceCell<BTreeMap<String, bool>>,
+
     /// Collection of locks individual client methods might want to use, either
     /// to ensure that only a single call to a method happens at once or to
     /// deduplicate multiple calls to a method.
@@ -273,6 +302,17 @@ pub(crate) struct ClientInner {
     /// It becomes active when [`EventCache::subscribe`] is called.
     pub(crate) event_cache: OnceCell<EventCache>,
 
+    /// The send queues of the rooms, see [`Client::send_queue`].
+    pub(crate) send_queue_data: SendQueueData,
+
+    /// The policy used to keep the size of the media cache in check, loaded
+    /// from the state store when it's first used, see
+    /// [`Media::set_cache_policy`].
+    pub(crate) media_cache_policy: OnceCell<StdRwLock<MediaCachePolicy>>,
+
+    /// The state of the automatic clean-up of the media cache.
+    pub(crate) media_cache_clean_up: StdMutex<CacheCleanUpState>,
+
     /// End-to-end encryption related state.
     #[cfg(feature = "e2e-encryption")]
     pub(crate) e2ee: EncryptionData,
@@ -292,6 +332,7 @@ impl ClientInner {
         http_client: HttpClient,
         base_client: BaseClient,
         server_versions: Option<Box<[MatrixVersion]>>,
+        unstable_features: Option<BTreeMap<String, bool>>,
         respect_login_well_known: bool,
         event_cache: OnceCell<EventCache>,
         #[cfg(feature = "e2e-encryption")] encryption_settings: EncryptionSettings,
@@ -305,6 +346,7 @@ impl ClientInner {
             base_client,
             locks: Default::default(),
             server_versions: OnceCell::new_with(server_versions),
+            unstable_features: OnceCell::new_with(unstable_features),
             typing_notice_times: Default::default(),
             event_handlers: Default::default(),
             notification_handlers: Default::default(),
@@ -315,6 +357,9 @@ impl ClientInner {
             respect_login_well_known,
             sync_beat: event_listener::Event::new(),
             event_cache,
+            send_queue_data: Default::default(),
+            media_cache_policy: OnceCell::new(),
+            media_cache_clean_up: Default::default(),
             #[cfg(feature = "e2e-encryption")]
             e2ee: EncryptionData::new(encryption_settings),
         };
@@ -325,7 +370,12 @@ impl ClientInner {
         #[cfg(feature = "e2e-encryption")]
         client.e2ee.initialize_room_key_tasks(&client);
 
-        let _ = client.event_cache.get_or_init(|| async { EventCache::new(&client) }).await;
+        let _ = client
+            .event_cache
+            .get_or_init(|| async {
+                EventCache::new(&client, client.base_client.event_cache_store().clone())
+            })
+            .await;
 
         client
     }
@@ -551,6 +601,11 @@ impl Client {
         Media::new(self.clone())
     }
 
+    /// Get the pushers manager of the client.
+    pub fn pushers(&self) -> Pushers {
+        Pushers::new(self.clone())
+    }
+
     /// Access the OpenID Connect API of the client.
     #[cfg(feature = "experimental-oidc")]
     pub fn oidc(&self) -> Oidc {
@@ -909,6 +964,15 @@ impl Client {
             .collect()
     }
 
+    /// Returns the rooms this client knows about that the user has knocked on.
+    pub fn knocked_rooms(&self) -> Vec<Room> {
+        self.base_client()
+            .get_rooms_filtered(RoomStateFilter::KNOCKED)
+            .into_iter()
+            .map(|room| Room::new(self.clone(), room))
+            .collect()
+    }
+
     /// Returns the left rooms this client knows about.
     pub fn left_rooms(&self) -> Vec<Room> {
         self.base_client()
@@ -927,6 +991,18 @@ impl Client {
         self.base_client().get_room(room_id).map(|room| Room::new(self.clone(), room))
     }
 
+    /// Get the space with the given ID.
+    ///
+    /// Returns `None` if the room isn't known or isn't a space.
+    pub fn get_space(&self, room_id: &RoomId) -> Option<Space> {
+        self.get_room(room_id).and_then(Space::from_room)
+    }
+
+    /// Get the spaces the user has joined.
+    pub fn joined_spaces(&self) -> Vec<Space> {
+        self.joined_rooms().into_iter().filter_map(Space::from_room).collect()
+    }
+
     /// Resolve a room alias to a room id and a list of servers which know
     /// about it.
     ///
@@ -1109,6 +1185,35 @@ impl Client {
         Ok(Room::new(self.clone(), base_room))
     }
 
+    /// Knock on a room, to ask its moderators to let the user in.
+    ///
+    /// The room must use the `knock` or `knock_restricted` join rule. Returns
+    /// the room, in the [`RoomState::Knocked`] state.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id_or_alias` - The `RoomId` or `RoomAliasId` of the room to
+    ///   knock on.
+    ///
+    /// * `reason` - The reason for knocking, shown to the moderators of the
+    ///   room.
+    ///
+    /// * `server_names` - The servers to try to knock through.
+    pub async fn knock(
+        &self,
+        room_id_or_alias: &RoomOrAliasId,
+        reason: Option<String>,
+        server_names: &[OwnedServerName],
+    ) -> Result<Room> {
+        let request = assign!(knock_room::v3::Request::new(room_id_or_alias.to_owned()), {
+            reason,
+            server_name: server_names.to_owned(),
+        });
+        let response = self.send(request, None).await?;
+        let base_room = self.base_client().room_knocked(&response.room_id).await?;
+        Ok(Room::new(self.clone(), base_room))
+    }
+
     /// Search the homeserver's directory of public rooms.
     ///
     /// Sends a request to "_matrix/client/r0/publicRooms", returns
@@ -1237,6 +1342,30 @@ impl Client {
         self.create_room(request).await
     }
 
+    /// Create a room that users can knock on, to ask to join it.
+    ///
+    /// Convenience shorthand for [`create_room`][Self::create_room] with the
+    /// `knock` join rule, which replaces any `m.room.join_rules` event in the
+    /// initial state of the given request. The room version must support
+    /// knocking, which is the case of the default room version of recent
+    /// homeservers.
+    ///
+    /// The pending knocks can then be observed with
+    /// [`Room::subscribe_to_knock_requests`].
+    pub async fn create_knockable_room(
+        &self,
+        mut request: create_room::v3::Request,
+    ) -> Result<Room> {
+        request.initial_state.retain(|event| {
+            event.get_field::<String>("type").ok().flatten().as_deref() != Some("m.room.join_rules")
+        });
+        request.initial_state.push(
+            InitialStateEvent::new(RoomJoinRulesEventContent::new(JoinRule::Knock)).to_raw_any(),
+        );
+
+        self.create_room(request).await
+    }
+
     /// Search the homeserver's directory for public rooms with a filter.
     ///
     /// # Arguments
@@ -1328,6 +1457,7 @@ impl Client {
             config,
             send_progress: Default::default(),
             homeserver_override: None,
+            server_versions_override: None,
         }
     }
 
@@ -1336,6 +1466,7 @@ impl Client {
         request: Request,
         config: Option<RequestConfig>,
         homeserver_override: Option<String>,
+        server_versions_override: Option<Box<[MatrixVersion]>>,
         send_progress: SharedObservable<TransmissionProgress>,
     ) -> HttpResult<Request::IncomingResponse>
     where
@@ -1349,6 +1480,11 @@ impl Client {
 
         let access_token = self.access_token();
 
+        let server_versions = match server_versions_override.as_deref() {
+            Some(server_versions) => server_versions,
+            None => self.server_versions().await?,
+        };
+
         self.inner
             .http_client
             .send(
@@ -1356,12 +1492,107 @@ impl Client {
                 config,
                 homeserver,
                 access_token.as_deref(),
-                self.server_versions().await?,
+                server_versions,
                 send_progress,
             )
             .await
     }
 
+    /// Send the given request with its body replaced by the given stream.
+    ///
+    /// Contrary to [`Client::send()`], the request is never retried, since the
+    /// stream can only be consumed once. If the access token is unknown, it is
+    /// still refreshed like with [`Client::send()`], so the next requests
+    /// succeed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) async fn send_with_body_stream<Request>(
+        &self,
+        request: Request,
+        body: reqwest::Body,
+        content_length: u64,
+        config: Option<RequestConfig>,
+    ) -> HttpResult<Request::IncomingResponse>
+    where
+        Request: OutgoingRequest + Debug,
+        HttpError: From<FromHttpResponseError<Request::EndpointError>>,
+    {
+        let access_token = self.access_token();
+
+        let res = self
+            .inner
+            .http_client
+            .send_with_body_stream(
+                request,
+                body,
+                content_length,
+                config,
+                self.homeserver().to_string(),
+                access_token.as_deref(),
+                self.server_versions().await?,
+            )
+            .await;
+
+        if let Err(error) = &res {
+            self.refresh_access_token_after_error(error).await?;
+        }
+
+        res
+    }
+
+    /// Send the given request and return the raw response, so its body can be
+    /// streamed.
+    ///
+    /// If `range_start` is not zero, only the bytes of the body starting at
+    /// this offset are requested. The request is built with
+    /// `server_versions_override` if it is set, rather than with the versions
+    /// supported by the homeserver.
+    ///
+    /// Like with [`Client::send()`], the request is retried once if the access
+    /// token is unknown and could be refreshed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) async fn send_for_body_stream<Request>(
+        &self,
+        request: Request,
+        range_start: u64,
+        config: Option<RequestConfig>,
+        server_versions_override: Option<&[MatrixVersion]>,
+    ) -> HttpResult<reqwest::Response>
+    where
+        Request: OutgoingRequest + Clone + Debug,
+        HttpError: From<FromHttpResponseError<Request::EndpointError>>,
+    {
+        let server_versions = match server_versions_override {
+            Some(server_versions) => server_versions,
+            None => self.server_versions().await?,
+        };
+
+        let send = |request: Request| async move {
+            let access_token = self.access_token();
+
+            self.inner
+                .http_client
+                .send_for_body_stream(
+                    request,
+                    range_start,
+                    config,
+                    self.homeserver().to_string(),
+                    access_token.as_deref(),
+                    server_versions,
+                )
+                .await
+        };
+
+        let res = send(request.clone()).await;
+
+        if let Err(error) = &res {
+            if self.refresh_access_token_after_error(error).await? {
+                return send(request).await;
+            }
+        }
+
+        res
+    }
+
     fn broadcast_unknown_token(&self, soft_logout: &bool) {
         _ = self
             .inner
@@ -1370,8 +1601,82 @@ impl Client {
             .send(SessionChange::UnknownToken { soft_logout: *soft_logout });
     }
 
-    async fn request_server_versions(&self) -> HttpResult<Box<[MatrixVersion]>> {
-        let server_versions: Box<[MatrixVersion]> = self
+    /// Try to refresh the access token after a request failed with the given
+    /// error.
+    ///
+    /// Returns `Ok(true)` if the error is an `M_UNKNOWN_TOKEN` error and the
+    /// access token was refreshed, so the request can be retried, `Ok(false)`
+    /// if the error should be returned as is, and an error if refreshing the
+    /// access token failed.
+    pub(crate) async fn refresh_access_token_after_error(
+        &self,
+        error: &HttpError,
+    ) -> HttpResult<bool> {
+        // An `M_UNKNOWN_TOKEN` error can potentially be fixed with a token refresh.
+        let Some(ErrorKind::UnknownToken { soft_logout }) = error.client_api_error_kind() else {
+            return Ok(false);
+        };
+
+        trace!("Token refresh: Unknown token error received.");
+
+        // If automatic token refresh isn't supported, there is nothing more to do.
+        if !self.inner.auth_ctx.handle_refresh_tokens {
+            trace!("Token refresh: Automatic refresh disabled.");
+            self.broadcast_unknown_token(soft_logout);
+            return Ok(false);
+        }
+
+        // Try to refresh the token.
+        let Err(refresh_error) = self.refresh_access_token().await else {
+            trace!("Token refresh: Refresh succeeded, retrying request.");
+            return Ok(true);
+        };
+
+        match &refresh_error {
+            RefreshTokenError::RefreshTokenRequired => {
+                trace!("Token refresh: The session doesn't have a refresh token.");
+                // Refreshing access tokens is not supported by this `Session`, ignore.
+                self.broadcast_unknown_token(soft_logout);
+                Ok(false)
+            }
+
+            #[cfg(feature = "experimental-oidc")]
+            RefreshTokenError::Oidc(oidc_error) => {
+                match **oidc_error {
+                    OidcError::Oidc(OidcClientError::TokenRefresh(TokenRefreshError::Token(
+                        TokenRequestError::Http(OidcHttpError {
+                            body: Some(OidcErrorBody { error: ClientErrorCode::InvalidGrant, .. }),
+                            ..
+                        }),
+                    ))) => {
+                        error!("Token refresh: OIDC refresh_token rejected with invalid grant");
+                        // The refresh was denied, signal to sign out the user.
+                        self.broadcast_unknown_token(soft_logout);
+                    }
+                    _ => {
+                        trace!("Token refresh: OIDC refresh encountered a problem.");
+                        // The refresh failed for other reasons, no need to sign out.
+                    }
+                };
+                Err(refresh_error.into())
+            }
+
+            _ => {
+                trace!("Token refresh: Token refresh failed.");
+                // This isn't necessarily correct, but matches the behaviour when
+                // implementing OIDC.
+                self.broadcast_unknown_token(soft_logout);
+                Err(refresh_error.into())
+            }
+        }
+    }
+
+    /// Request the Matrix versions supported by the server, and the unstable
+    /// features it advertises.
+    async fn request_server_versions(
+        &self,
+    ) -> HttpResult<(Box<[MatrixVersion]>, BTreeMap<String, bool>)> {
+        let response = self
             .inner
             .http_client
             .send(
@@ -1382,27 +1687,88 @@ impl Client {
                 &[MatrixVersion::V1_0],
                 Default::default(),
             )
-            .await?
-            .known_versions()
-            .collect();
+            .await?;
 
-        if server_versions.is_empty() {
-            Ok(vec![MatrixVersion::V1_0].into())
+        let server_versions: Box<[MatrixVersion]> = response.known_versions().collect();
+        let server_versions = if server_versions.is_empty() {
+            vec![MatrixVersion::V1_0].into()
         } else {
-            Ok(server_versions)
-        }
+            server_versions
+        };
+
+        Ok((server_versions, response.unstable_features))
     }
 
     pub(crate) async fn server_versions(&self) -> HttpResult<&[MatrixVersion]> {
         let server_versions = self
             .inner
             .server_versions
-            .get_or_try_init(|| Box::pin(self.request_server_versions()))
+            .get_or_try_init(|| {
+                Box::pin(async {
+                    let (server_versions, unstable_features) =
+                        self.request_server_versions().await?;
+                    // This only fails if it was already set by a concurrent request.
+                    let _ = self.inner.unstable_features.set(unstable_features);
+                    Ok::<_, HttpError>(server_versions)
+                })
+            })
             .await?;
 
         Ok(server_versions)
     }
 
+    /// The unstable features advertised by the server, and whether they are
+    /// enabled.
+    ///
+    /// They are requested with the server versions, and cached with them.
+    pub(crate) async fn unstable_features(&self) -> HttpResult<&BTreeMap<String, bool>> {
+        let unstable_features = self
+            .inner
+            .unstable_features
+            .get_or_try_init(|| {
+                Box::pin(async {
+                    let (server_versions, unstable_features) =
+                        self.request_server_versions().await?;
+                    // This only fails if it was already set by a concurrent request.
+                    let _ = self.inner.server_versions.set(server_versions);
+                    Ok::<_, HttpError>(unstable_features)
+                })
+            })
+            .await?;
+
+        Ok(unstable_features)
+    }
+
+    /// Whether the server supports the authenticated media endpoints.
+    ///
+    /// This is the case if it supports Matrix 1.11, or if it advertises the
+    /// `org.matrix.msc3916.stable` unstable feature.
+    pub(crate) async fn supports_authenticated_media(&self) -> HttpResult<bool> {
+        if self.server_versions().await?.contains(&MatrixVersion::V1_11) {
+            return Ok(true);
+        }
+
+        Ok(self.unstable_features().await?.get(AUTHENTICATED_MEDIA_UNSTABLE_FEATURE) == Some(&true))
+    }
+
+    /// The Matrix versions to build the requests to the authenticated media
+    /// endpoints with.
+    ///
+    /// Servers advertising the `org.matrix.msc3916.stable` unstable feature
+    /// support the stable endpoints even if they don't support Matrix 1.11,
+    /// so this version is added to make ruma select the stable endpoints.
+    pub(crate) async fn authenticated_media_server_versions(
+        &self,
+    ) -> HttpResult<Box<[MatrixVersion]>> {
+        let mut server_versions = self.server_versions().await?.to_vec();
+
+        if !server_versions.contains(&MatrixVersion::V1_11) {
+            server_versions.push(MatrixVersion::V1_11);
+        }
+
+        Ok(server_versions.into())
+    }
+
     /// Get information of all our own devices.
     ///
     /// # Examples
@@ -1481,6 +1847,22 @@ impl Client {
         self.send(request, None).await
     }
 
+    /// Delete the given devices from the server, completing the
+    /// [User-Interactive Authentication][uiaa] with the given handler.
+    ///
+    /// See [`Client::delete_devices()`] for more details.
+    ///
+    /// [uiaa]: https://spec.matrix.org/v1.2/client-server-api/#user-interactive-authentication-api
+    pub async fn delete_devices_with_uiaa(
+        &self,
+        devices: &[OwnedDeviceId],
+        handler: &impl UiaaHandler,
+    ) -> Result<delete_devices::v3::Response> {
+        UiaaDriver::new(self, handler)
+            .run(|auth_data| self.delete_devices(devices, auth_data))
+            .await
+    }
+
     /// Change the display name of a device owned by the current user.
     ///
     /// Returns a `update_device::Response` which specifies the result
@@ -1973,6 +2355,8 @@ impl Client {
     }
 
     /// Sets a given pusher
+    ///
+    /// See [`Client::pushers`] to manage all the pushers of the user.
     pub async fn set_pusher(&self, pusher: Pusher) -> HttpResult<set_pusher::v3::Response> {
         let request = set_pusher::v3::Request::post(pusher);
         self.send(request, None).await
@@ -2008,6 +2392,7 @@ impl Client {
                 self.inner.http_client.clone(),
                 self.inner.base_client.clone_with_in_memory_state_store(),
                 self.inner.server_versions.get().cloned(),
+                self.inner.unstable_features.get().cloned(),
                 self.inner.respect_login_well_known,
                 self.inner.event_cache.clone(),
                 #[cfg(feature = "e2e-encryption")]
@@ -2035,6 +2420,35 @@ impl Client {
         // SAFETY: always initialized in the `Client` ctor.
         self.inner.event_cache.get().unwrap()
     }
+
+    /// The [`SendQueue`] of this [`Client`], which persists the events to send
+    /// to the rooms until they've been sent.
+    pub fn send_queue(&self) -> SendQueue {
+        SendQueue::new(self.clone())
+    }
+
+    /// Search for messages matching the given search term in all the rooms of
+    /// the current user, with the homeserver's `/search` endpoint.
+    ///
+    /// The returned [`SearchMessages`] can be used to customize the search
+    /// before awaiting it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, ruma::api::client::search::search_events::v3::OrderBy};
+    /// # async {
+    /// # let client: Client = todo!();
+    /// let results = client.search("lunch").order_by(OrderBy::Recent).await?;
+    ///
+    /// for result in results.results {
+    ///     println!("Found {} in {}", result.event_id, result.room_id);
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn search(&self, search_term: impl Into<String>) -> SearchMessages {
+        SearchMessages::new(self.clone(), None, search_term.into())
+    }
 }
 
 // The http mocking library is not supported for wasm32
@@ -45,17 +45,19 @@ pub struct RequestConfig {
     pub(crate) retry_limit: Option<u64>,
     pub(crate) retry_timeout: Option<Duration>,
     pub(crate) force_auth: bool,
+    pub(crate) max_response_size: Option<usize>,
 }
 
 #[cfg(not(tarpaulin_include))]
 impl Debug for RequestConfig {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Self { timeout, retry_limit, retry_timeout, force_auth } = self;
+        let Self { timeout, retry_limit, retry_timeout, force_auth, max_response_size } = self;
 
         let mut res = fmt.debug_struct("RequestConfig");
         res.field("timeout", timeout)
             .maybe_field("retry_limit", retry_limit)
-            .maybe_field("retry_timeout", retry_timeout);
+            .maybe_field("retry_timeout", retry_timeout)
+            .maybe_field("max_response_size", max_response_size);
 
         if *force_auth {
             res.field("force_auth", &true);
@@ -72,6 +74,7 @@ impl Default for RequestConfig {
             retry_limit: Default::default(),
             retry_timeout: Default::default(),
             force_auth: false,
+            max_response_size: None,
         }
     }
 }
@@ -127,6 +130,19 @@ impl RequestConfig {
         self.force_auth = true;
         self
     }
+
+    /// Set the maximum size of the body of the response, in bytes.
+    ///
+    /// The request fails with [`HttpError::ResponseTooLarge`] as soon as the
+    /// body is known to be larger, without loading the rest of it in memory.
+    /// The default is no limit.
+    ///
+    /// [`HttpError::ResponseTooLarge`]: crate::HttpError::ResponseTooLarge
+    #[must_use]
+    pub fn max_response_size(mut self, max_response_size: usize) -> Self {
+        self.max_response_size = Some(max_response_size);
+        self
+    }
 }
 
 #[cfg(test)]
@@ -141,9 +157,11 @@ mod tests {
             .force_auth()
             .retry_timeout(Duration::from_secs(32))
             .retry_limit(4)
-            .timeout(Duration::from_secs(600));
+            .timeout(Duration::from_secs(600))
+            .max_response_size(1024);
 
         assert!(cfg.force_auth);
+        assert_eq!(cfg.max_response_size, Some(1024));
         assert_eq!(cfg.retry_limit, Some(4));
         assert_eq!(cfg.retry_timeout, Some(Duration::from_secs(32)));
         assert_eq!(cfg.timeout, Duration::from_secs(600));
@@ -510,7 +510,12 @@ impl Backups {
         Ok(())
     }
 
-    fn room_keys_stream(
+    /// Subscribe to a stream that notifies when room keys are downloaded from
+    /// the key backup, for any room.
+    ///
+    /// See [`Backups::room_keys_for_room_stream`] to only get the room keys
+    /// of a single room.
+    pub fn room_keys_stream(
         &self,
     ) -> impl Stream<Item = Result<RoomKeyImportResult, BroadcastStreamRecvError>> {
         BroadcastStream::new(self.client.inner.e2ee.backup_state.room_keys_broadcaster.subscribe())
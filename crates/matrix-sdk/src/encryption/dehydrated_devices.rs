@@ -0,0 +1,498 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The dehydrated devices module
+//!
+//! A dehydrated device is a device whose private keys are encrypted with a
+//! pickle key and stored on the homeserver, as defined in [MSC3814]. Other
+//! devices can send room keys to the dehydrated device while all our real
+//! devices are offline. When we log in again, we rehydrate the device to
+//! recover the room keys it received.
+//!
+//! The pickle key is stored in the [`SecretStore`], so any device that can
+//! open the secret store can rehydrate the dehydrated device. Since the
+//! one-time keys of a dehydrated device get used up, the device is replaced
+//! with a new one every time it is rehydrated, and periodically while the
+//! client is running.
+//!
+//! [MSC3814]: https://github.com/matrix-org/matrix-spec-proposals/pull/3814
+
+use std::sync::Arc;
+
+use matrix_sdk_base::crypto::{
+    dehydrated_devices::DehydrationError,
+    vodozemac::{base64_decode, base64_encode},
+    OlmError,
+};
+use rand::{thread_rng, RngCore};
+use ruma::{
+    api::client::{
+        dehydrated_device::{delete_dehydrated_device, get_dehydrated_device, get_events},
+        error::ErrorKind,
+    },
+    assign,
+    events::secret::request::SecretName,
+    MilliSecondsSinceUnixEpoch, OwnedDeviceId,
+};
+use thiserror::Error;
+use tracing::{info, instrument, trace, warn};
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{
+    secret_storage::{SecretStorageError, SecretStore},
+    tasks::DehydratedDeviceRotationTask,
+};
+use crate::{executor::spawn, Client, HttpError};
+
+/// The name of the secret containing the pickle key of the dehydrated device.
+const PICKLE_KEY_SECRET_NAME: &str = "org.matrix.msc3814";
+
+/// The key under which the pickle key is cached in the crypto store, so the
+/// device can be replaced without opening the secret store.
+const PICKLE_KEY_STORE_KEY: &str = "dehydrated_device_pickle_key";
+
+/// The key under which the time of the last upload of a dehydrated device is
+/// stored in the crypto store, so the device is replaced on time across
+/// restarts.
+pub(super) const UPLOAD_TIME_STORE_KEY: &str = "dehydrated_device_upload_time";
+
+/// The display name of the dehydrated devices we create.
+const DEVICE_DISPLAY_NAME: &str = "Dehydrated device";
+
+/// Error type for the [`DehydratedDevices`] subsystem.
+#[derive(Debug, Error)]
+pub enum DehydratedDeviceError {
+    /// A typical SDK error.
+    #[error(transparent)]
+    Sdk(#[from] crate::Error),
+
+    /// Error in the secret storage subsystem.
+    #[error(transparent)]
+    SecretStorage(#[from] SecretStorageError),
+
+    /// The dehydrated device couldn't be created or rehydrated.
+    #[error(transparent)]
+    Dehydration(#[from] DehydrationError),
+
+    /// The events sent to the dehydrated device couldn't be processed.
+    #[error(transparent)]
+    Olm(#[from] OlmError),
+
+    /// The pickle key stored in the secret store isn't a valid base64-encoded
+    /// 32 bytes key.
+    #[error("The pickle key of the dehydrated device is invalid")]
+    InvalidPickleKey,
+}
+
+impl From<HttpError> for DehydratedDeviceError {
+    fn from(error: HttpError) -> Self {
+        Self::Sdk(error.into())
+    }
+}
+
+impl From<matrix_sdk_base::crypto::CryptoStoreError> for DehydratedDeviceError {
+    fn from(error: matrix_sdk_base::crypto::CryptoStoreError) -> Self {
+        Self::Sdk(error.into())
+    }
+}
+
+/// The dehydrated devices manager for the [`Client`].
+#[derive(Debug)]
+pub struct DehydratedDevices {
+    pub(super) client: Client,
+}
+
+impl DehydratedDevices {
+    /// Create a new dehydrated device and upload it to the homeserver.
+    ///
+    /// The pickle key of the device is taken from the secret store, or
+    /// generated and uploaded to the secret store if it doesn't contain one
+    /// yet. The device will then be replaced periodically, for as long as the
+    /// client is alive.
+    ///
+    /// The private cross-signing keys must be available, since the dehydrated
+    /// device needs to be signed by our self-signing key.
+    ///
+    /// Returns the ID of the new dehydrated device.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://example.com")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let secret_store = client
+    ///     .encryption()
+    ///     .secret_storage()
+    ///     .open_secret_store("It's a secret to everybody")
+    ///     .await?;
+    ///
+    /// let device_id =
+    ///     client.encryption().dehydrated_devices().create(&secret_store).await?;
+    ///
+    /// println!("Uploaded the dehydrated device {device_id}");
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[instrument(skip_all)]
+    pub async fn create(
+        &self,
+        secret_store: &SecretStore,
+    ) -> Result<OwnedDeviceId, DehydratedDeviceError> {
+        let pickle_key = match self.get_pickle_key(secret_store).await? {
+            Some(pickle_key) => pickle_key,
+            None => {
+                info!("Generating a new pickle key for the dehydrated device");
+
+                let mut pickle_key = Zeroizing::new([0u8; 32]);
+                thread_rng().fill_bytes(pickle_key.as_mut_slice());
+
+                let mut encoded = base64_encode(pickle_key.as_slice());
+                secret_store.put_secret(SecretName::from(PICKLE_KEY_SECRET_NAME), &encoded).await?;
+                encoded.zeroize();
+
+                pickle_key
+            }
+        };
+
+        self.save_pickle_key(&pickle_key).await?;
+        let device_id = self.upload(&pickle_key).await?;
+        self.start_rotation();
+
+        Ok(device_id)
+    }
+
+    /// Rehydrate the dehydrated device stored on the homeserver, to recover
+    /// the room keys that were sent to it.
+    ///
+    /// The pickle key of the device is taken from the secret store. Once all
+    /// the events of the dehydrated device have been processed, it is
+    /// replaced with a new one, that will be replaced periodically for as long
+    /// as the client is alive.
+    ///
+    /// This is called automatically in the background by
+    /// [`SecretStore::import_secrets()`].
+    ///
+    /// Returns the number of room keys that were recovered, or `None` if there
+    /// is no pickle key in the secret store or no dehydrated device on the
+    /// homeserver.
+    #[instrument(skip_all)]
+    pub async fn rehydrate(
+        &self,
+        secret_store: &SecretStore,
+    ) -> Result<Option<usize>, DehydratedDeviceError> {
+        let Some(pickle_key) = self.get_pickle_key(secret_store).await? else {
+            trace!("There is no pickle key in the secret store, not rehydrating");
+            return Ok(None);
+        };
+
+        self.rehydrate_with_pickle_key(pickle_key).await
+    }
+
+    /// Rehydrate the dehydrated device in the background, once the secrets
+    /// have been imported from the secret store.
+    ///
+    /// Only the pickle key is fetched from the secret store before returning,
+    /// the events of the dehydrated device are fetched and processed in a
+    /// spawned task.
+    pub(crate) async fn rehydrate_in_background(
+        &self,
+        secret_store: &SecretStore,
+    ) -> Result<(), DehydratedDeviceError> {
+        let Some(pickle_key) = self.get_pickle_key(secret_store).await? else {
+            trace!("There is no pickle key in the secret store, not rehydrating");
+            return Ok(());
+        };
+
+        let this = Self { client: self.client.clone() };
+
+        spawn(async move {
+            if let Err(e) = this.rehydrate_with_pickle_key(pickle_key).await {
+                warn!("Couldn't rehydrate the dehydrated device: {e:?}");
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn rehydrate_with_pickle_key(
+        &self,
+        pickle_key: Zeroizing<[u8; 32]>,
+    ) -> Result<Option<usize>, DehydratedDeviceError> {
+        let request = get_dehydrated_device::unstable::Request::new();
+
+        let response = match self.client.send(request, None).await {
+            Ok(response) => response,
+            Err(error) if error.client_api_error_kind() == Some(&ErrorKind::NotFound) => {
+                trace!("There is no dehydrated device on the homeserver");
+                return Ok(None);
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let device_id = response.device_id;
+        let room_key_count = {
+            let olm_machine = self.client.olm_machine().await;
+            let olm_machine = olm_machine.as_ref().ok_or(crate::Error::NoOlmMachine)?;
+
+            let rehydrated = olm_machine
+                .dehydrated_devices()
+                .rehydrate(&pickle_key, &device_id, response.device_data)
+                .await?;
+
+            let mut next_batch = None;
+            let mut room_key_count = 0;
+
+            loop {
+                let request = assign!(get_events::unstable::Request::new(device_id.clone()), {
+                    next_batch,
+                });
+                let response = self.client.send(request, None).await?;
+
+                if response.events.is_empty() {
+                    break;
+                }
+
+                room_key_count += rehydrated.receive_events(response.events).await?.len();
+
+                // Without a token, the next request would return the same events again.
+                match response.next_batch {
+                    Some(token) => next_batch = Some(token),
+                    None => break,
+                }
+            }
+
+            room_key_count
+        };
+
+        info!(%device_id, room_key_count, "Rehydrated the dehydrated device");
+
+        self.client.encryption().backups().maybe_trigger_backup();
+
+        // The one-time keys of the device might have been used, replace it.
+        self.save_pickle_key(&pickle_key).await?;
+        self.upload(&pickle_key).await?;
+        self.start_rotation();
+
+        Ok(Some(room_key_count))
+    }
+
+    /// Delete the dehydrated device from the homeserver, and stop replacing it
+    /// periodically.
+    ///
+    /// The pickle key is left in the secret store, so a new dehydrated device
+    /// can be created later with the same key.
+    pub async fn delete(&self) -> Result<(), DehydratedDeviceError> {
+        self.client.inner.e2ee.tasks.lock().unwrap().dehydrated_device_rotation = None;
+
+        if let Some(olm_machine) = self.client.olm_machine().await.as_ref() {
+            olm_machine.store().remove_custom_value(PICKLE_KEY_STORE_KEY).await?;
+            olm_machine.store().remove_custom_value(UPLOAD_TIME_STORE_KEY).await?;
+        }
+
+        let request = delete_dehydrated_device::unstable::Request::new();
+
+        match self.client.send(request, None).await {
+            Ok(_) => Ok(()),
+            Err(error) if error.client_api_error_kind() == Some(&ErrorKind::NotFound) => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Replace the dehydrated device with a new one, using the cached pickle
+    /// key.
+    ///
+    /// Does nothing if we never created or rehydrated a dehydrated device.
+    pub(crate) async fn rotate(&self) -> Result<(), DehydratedDeviceError> {
+        let Some(pickle_key) = self.cached_pickle_key().await? else {
+            return Ok(());
+        };
+
+        self.upload(&pickle_key).await?;
+
+        Ok(())
+    }
+
+    /// Get the time at which we last uploaded a dehydrated device, if we
+    /// know it.
+    pub(crate) async fn last_upload_time(
+        &self,
+    ) -> Result<Option<MilliSecondsSinceUnixEpoch>, DehydratedDeviceError> {
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(crate::Error::NoOlmMachine)?;
+
+        Ok(olm_machine.store().get_value(UPLOAD_TIME_STORE_KEY).await?)
+    }
+
+    /// Resume the periodic replacement of the dehydrated device, if we
+    /// created or rehydrated one in a previous session.
+    pub(crate) async fn setup(&self) -> Result<(), DehydratedDeviceError> {
+        if self.cached_pickle_key().await?.is_some() {
+            self.start_rotation();
+        }
+
+        Ok(())
+    }
+
+    /// Create a new dehydrated device and upload it, replacing the existing
+    /// one.
+    async fn upload(&self, pickle_key: &[u8; 32]) -> Result<OwnedDeviceId, DehydratedDeviceError> {
+        let request = {
+            let olm_machine = self.client.olm_machine().await;
+            let olm_machine = olm_machine.as_ref().ok_or(crate::Error::NoOlmMachine)?;
+
+            let device = olm_machine.dehydrated_devices().create().await?;
+            device.keys_for_upload(DEVICE_DISPLAY_NAME.to_owned(), pickle_key).await?
+        };
+
+        let response = self.client.send(request, None).await?;
+
+        info!(device_id = %response.device_id, "Uploaded a new dehydrated device");
+
+        if let Some(olm_machine) = self.client.olm_machine().await.as_ref() {
+            olm_machine
+                .store()
+                .set_value(UPLOAD_TIME_STORE_KEY, &MilliSecondsSinceUnixEpoch::now())
+                .await?;
+        }
+
+        Ok(response.device_id)
+    }
+
+    /// Get the pickle key from the secret store.
+    async fn get_pickle_key(
+        &self,
+        secret_store: &SecretStore,
+    ) -> Result<Option<Zeroizing<[u8; 32]>>, DehydratedDeviceError> {
+        let secret = secret_store.get_secret(SecretName::from(PICKLE_KEY_SECRET_NAME)).await?;
+        secret.map(|secret| decode_pickle_key(Zeroizing::new(secret))).transpose()
+    }
+
+    /// Get the pickle key cached in the crypto store.
+    async fn cached_pickle_key(
+        &self,
+    ) -> Result<Option<Zeroizing<[u8; 32]>>, DehydratedDeviceError> {
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(crate::Error::NoOlmMachine)?;
+
+        let secret = olm_machine.store().get_value::<String>(PICKLE_KEY_STORE_KEY).await?;
+        secret.map(|secret| decode_pickle_key(Zeroizing::new(secret))).transpose()
+    }
+
+    /// Cache the pickle key in the crypto store.
+    async fn save_pickle_key(&self, pickle_key: &[u8; 32]) -> Result<(), DehydratedDeviceError> {
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(crate::Error::NoOlmMachine)?;
+
+        let encoded = Zeroizing::new(base64_encode(pickle_key));
+        olm_machine.store().set_value(PICKLE_KEY_STORE_KEY, &*encoded).await?;
+
+        Ok(())
+    }
+
+    /// Start replacing the dehydrated device periodically, if we're not
+    /// already doing it.
+    fn start_rotation(&self) {
+        let mut tasks = self.client.inner.e2ee.tasks.lock().unwrap();
+
+        if tasks.dehydrated_device_rotation.is_none() {
+            tasks.dehydrated_device_rotation =
+                Some(DehydratedDeviceRotationTask::new(Arc::downgrade(&self.client.inner)));
+        }
+    }
+}
+
+/// Decode a base64-encoded pickle key.
+fn decode_pickle_key(
+    encoded: Zeroizing<String>,
+) -> Result<Zeroizing<[u8; 32]>, DehydratedDeviceError> {
+    let decoded = Zeroizing::new(
+        base64_decode(encoded.as_str()).map_err(|_| DehydratedDeviceError::InvalidPickleKey)?,
+    );
+
+    let mut pickle_key = Zeroizing::new([0u8; 32]);
+
+    if decoded.len() != pickle_key.len() {
+        return Err(DehydratedDeviceError::InvalidPickleKey);
+    }
+
+    pickle_key.copy_from_slice(&decoded);
+
+    Ok(pickle_key)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use matrix_sdk_test::async_test;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{header, method, path_regex},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::test_utils::logged_in_client;
+
+    #[async_test]
+    async fn rotate() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+        let dehydrated_devices = client.encryption().dehydrated_devices();
+
+        {
+            let _scope = Mock::given(method("PUT"))
+                .and(path_regex(r"/dehydrated_device$"))
+                .respond_with(ResponseTemplate::new(500))
+                .expect(0)
+                .named("dehydrated device PUT")
+                .mount_as_scoped(&server)
+                .await;
+
+            // We never created a dehydrated device, so there is nothing to replace.
+            dehydrated_devices
+                .rotate()
+                .await
+                .expect("Rotating without a cached pickle key should succeed");
+
+            assert!(dehydrated_devices.last_upload_time().await.unwrap().is_none());
+        }
+
+        // The dehydrated device needs to be signed by our self-signing key.
+        client.olm_machine().await.as_ref().unwrap().bootstrap_cross_signing(false).await.unwrap();
+        dehydrated_devices.save_pickle_key(&[0u8; 32]).await.unwrap();
+
+        Mock::given(method("PUT"))
+            .and(path_regex(r"/dehydrated_device$"))
+            .and(header("authorization", "Bearer 1234"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "device_id": "DEHYDRATED" })),
+            )
+            .expect(1)
+            .named("dehydrated device PUT")
+            .mount(&server)
+            .await;
+
+        dehydrated_devices
+            .rotate()
+            .await
+            .expect("We should be able to replace the dehydrated device with the cached key");
+
+        assert!(
+            dehydrated_devices.last_upload_time().await.unwrap().is_some(),
+            "The upload time of the new dehydrated device should have been saved"
+        );
+
+        server.verify().await;
+    }
+}
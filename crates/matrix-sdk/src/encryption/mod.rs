@@ -19,19 +19,21 @@
 use std::{
     collections::{BTreeMap, HashSet},
     io::{Cursor, Read, Write},
-    iter,
+    iter, mem,
     path::PathBuf,
     sync::{Arc, Mutex as StdMutex},
 };
 
+use bytes::BytesMut;
 use eyeball::SharedObservable;
 use futures_core::Stream;
 use futures_util::{
     future::try_join,
-    stream::{self, StreamExt},
+    stream::{self, StreamExt, TryStreamExt},
 };
 use matrix_sdk_base::crypto::{
-    CrossSigningBootstrapRequests, OlmMachine, OutgoingRequest, RoomMessageRequest, ToDeviceRequest,
+    AttachmentChunkEncryptor, CrossSigningBootstrapRequests, OlmMachine, OutgoingRequest,
+    RoomMessageRequest, ToDeviceRequest,
 };
 use matrix_sdk_common::executor::spawn;
 use ruma::{
@@ -48,11 +50,7 @@ use ruma::{
     },
     assign,
     events::room::{
-        message::{
-            AudioMessageEventContent, FileInfo, FileMessageEventContent, ImageMessageEventContent,
-            MessageType, VideoInfo, VideoMessageEventContent,
-        },
-        ImageInfo, MediaSource, ThumbnailInfo,
+        message::MessageType, EncryptedFile, EncryptedFileInit, MediaSource, ThumbnailInfo,
     },
     DeviceId, OwnedDeviceId, OwnedUserId, TransactionId, UserId,
 };
@@ -61,25 +59,31 @@ use tracing::{debug, error, instrument, trace, warn};
 
 use self::{
     backups::{types::BackupClientState, Backups},
+    dehydrated_devices::DehydratedDevices,
     futures::PrepareEncryptedFile,
     identities::{DeviceUpdates, IdentityUpdates},
     recovery::{Recovery, RecoveryState},
     secret_storage::SecretStorage,
     tasks::{BackupDownloadTask, BackupUploadingTask, ClientTasks},
 };
+#[cfg(not(target_arch = "wasm32"))]
+use crate::media::ByteStream;
 use crate::{
-    attachment::{AttachmentInfo, Thumbnail},
+    attachment::{AttachmentData, AttachmentInfo, Thumbnail},
     client::ClientInner,
     encryption::{
         identities::{Device, UserDevices},
         verification::{SasVerification, Verification, VerificationRequest},
     },
     error::HttpResult,
+    media::make_attachment_type,
     store_locks::CrossProcessStoreLockGuard,
+    uiaa::{UiaaDriver, UiaaHandler},
     Client, Error, Result, Room, TransmissionProgress,
 };
 
 pub mod backups;
+pub mod dehydrated_devices;
 pub mod futures;
 pub mod identities;
 pub mod recovery;
@@ -274,7 +278,7 @@ impl Client {
         &self,
         body: &str,
         content_type: &mime::Mime,
-        data: Vec<u8>,
+        data: AttachmentData,
         info: Option<AttachmentInfo>,
         thumbnail: Option<Thumbnail>,
         send_progress: SharedObservable<TransmissionProgress>,
@@ -283,59 +287,73 @@ impl Client {
             self.upload_encrypted_thumbnail(thumbnail, content_type, send_progress.clone());
 
         let upload_attachment = async {
-            let mut cursor = Cursor::new(data);
-            self.prepare_encrypted_file(content_type, &mut cursor)
-                .with_send_progress_observable(send_progress)
-                .await
+            match data {
+                AttachmentData::Bytes(data) => {
+                    let mut cursor = Cursor::new(data);
+                    self.prepare_encrypted_file(content_type, &mut cursor)
+                        .with_send_progress_observable(send_progress)
+                        .await
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                AttachmentData::Stream { stream, size } => {
+                    self.upload_encrypted_stream(content_type, stream, size, send_progress).await
+                }
+            }
         };
 
         let ((thumbnail_source, thumbnail_info), file) =
             try_join(upload_thumbnail, upload_attachment).await?;
 
-        Ok(match content_type.type_() {
-            mime::IMAGE => {
-                let info = assign!(info.map(ImageInfo::from).unwrap_or_default(), {
-                    mimetype: Some(content_type.as_ref().to_owned()),
-                    thumbnail_source,
-                    thumbnail_info
-                });
-                let content = assign!(ImageMessageEventContent::encrypted(body.to_owned(), file), {
-                    info: Some(Box::new(info))
-                });
-                MessageType::Image(content)
-            }
-            mime::AUDIO => {
-                let audio_message_event_content =
-                    AudioMessageEventContent::encrypted(body.to_owned(), file);
-                MessageType::Audio(crate::media::update_audio_message_event(
-                    audio_message_event_content,
-                    content_type,
-                    info,
-                ))
-            }
-            mime::VIDEO => {
-                let info = assign!(info.map(VideoInfo::from).unwrap_or_default(), {
-                    mimetype: Some(content_type.as_ref().to_owned()),
-                    thumbnail_source,
-                    thumbnail_info
-                });
-                let content = assign!(VideoMessageEventContent::encrypted(body.to_owned(), file), {
-                    info: Some(Box::new(info))
-                });
-                MessageType::Video(content)
-            }
-            _ => {
-                let info = assign!(info.map(FileInfo::from).unwrap_or_default(), {
-                    mimetype: Some(content_type.as_ref().to_owned()),
-                    thumbnail_source,
-                    thumbnail_info
-                });
-                let content = assign!(FileMessageEventContent::encrypted(body.to_owned(), file), {
-                    info: Some(Box::new(info))
-                });
-                MessageType::File(content)
+        Ok(make_attachment_type(
+            content_type,
+            body,
+            MediaSource::Encrypted(Box::new(file)),
+            info,
+            thumbnail_source,
+            thumbnail_info,
+        ))
+    }
+
+    /// Encrypt the given stream on the fly while uploading it, and construct
+    /// an [`EncryptedFile`] from the result.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn upload_encrypted_stream(
+        &self,
+        content_type: &mime::Mime,
+        stream: ByteStream,
+        size: u64,
+        send_progress: SharedObservable<TransmissionProgress>,
+    ) -> Result<EncryptedFile> {
+        // The stream is consumed by the HTTP client, so share the encryptor to be
+        // able to finish it once the upload is done.
+        let encryptor = Arc::new(StdMutex::new(AttachmentChunkEncryptor::new()));
+
+        let stream = stream.map_ok({
+            let encryptor = encryptor.clone();
+            move |chunk| {
+                let mut chunk = BytesMut::from(&chunk[..]);
+                encryptor.lock().unwrap().encrypt_chunk(&mut chunk);
+                chunk.freeze()
             }
-        })
+        });
+
+        // AES-CTR doesn't change the size of the data.
+        let response = self
+            .media()
+            .upload_stream(content_type, stream, size)
+            .with_send_progress_observable(send_progress)
+            .await?;
+
+        let keys = mem::take(&mut *encryptor.lock().unwrap()).finish();
+
+        Ok(EncryptedFileInit {
+            url: response.content_uri,
+            key: keys.key,
+            iv: keys.iv,
+            hashes: keys.hashes,
+            v: keys.version,
+        }
+        .into())
     }
 
     async fn upload_encrypted_thumbnail(
@@ -922,6 +940,21 @@ impl Encryption {
         Ok(())
     }
 
+    /// Create and upload a new cross signing identity, completing the
+    /// [User-Interactive Authentication][uiaa] with the given handler.
+    ///
+    /// See [`Encryption::bootstrap_cross_signing()`] for more details.
+    ///
+    /// [uiaa]: https://spec.matrix.org/v1.2/client-server-api/#user-interactive-authentication-api
+    pub async fn bootstrap_cross_signing_with_uiaa(
+        &self,
+        handler: &impl UiaaHandler,
+    ) -> Result<()> {
+        UiaaDriver::new(&self.client, handler)
+            .run(|auth_data| self.bootstrap_cross_signing(auth_data))
+            .await
+    }
+
     /// Query the user's own device keys, if, and only if, we didn't have their
     /// identity in the first place.
     async fn ensure_initial_key_query(&self) -> Result<()> {
@@ -1155,6 +1188,11 @@ impl Encryption {
         Recovery { client: self.client.to_owned() }
     }
 
+    /// Get the dehydrated devices manager of the client.
+    pub fn dehydrated_devices(&self) -> DehydratedDevices {
+        DehydratedDevices { client: self.client.to_owned() }
+    }
+
     /// Enables the crypto-store cross-process lock.
     ///
     /// This may be required if there are multiple processes that may do writes
@@ -1309,6 +1347,9 @@ impl Encryption {
             if let Err(e) = this.recovery().setup().await {
                 error!("Couldn't setup and resume recovery {e:?}");
             }
+            if let Err(e) = this.dehydrated_devices().setup().await {
+                error!("Couldn't resume the rotation of the dehydrated device {e:?}");
+            }
         }));
 
         Ok(())
@@ -416,6 +416,13 @@ impl SecretStore {
 
         self.maybe_enable_backups().await?;
 
+        // Recover the room keys that were sent while all our devices were offline.
+        if let Err(e) =
+            self.client.encryption().dehydrated_devices().rehydrate_in_background(self).await
+        {
+            warn!("Couldn't rehydrate the dehydrated device: {e:?}");
+        }
+
         Ok(())
     }
 
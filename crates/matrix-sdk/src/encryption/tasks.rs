@@ -16,7 +16,7 @@ use std::{collections::BTreeMap, sync::Weak, time::Duration};
 
 use futures_util::future::join_all;
 use matrix_sdk_common::failures_cache::FailuresCache;
-use ruma::OwnedRoomId;
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedRoomId};
 use tokio::sync::mpsc::{self, UnboundedReceiver};
 use tracing::{trace, warn};
 
@@ -33,6 +33,8 @@ pub(crate) struct ClientTasks {
     pub(crate) upload_room_keys: Option<BackupUploadingTask>,
     #[cfg(feature = "e2e-encryption")]
     pub(crate) download_room_keys: Option<BackupDownloadTask>,
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) dehydrated_device_rotation: Option<DehydratedDeviceRotationTask>,
     pub(crate) setup_e2ee: Option<JoinHandle<()>>,
 }
 
@@ -90,6 +92,95 @@ impl BackupUploadingTask {
     }
 }
 
+#[cfg(feature = "e2e-encryption")]
+pub(crate) struct DehydratedDeviceRotationTask {
+    #[allow(dead_code)]
+    join_handle: JoinHandle<()>,
+}
+
+#[cfg(feature = "e2e-encryption")]
+impl Drop for DehydratedDeviceRotationTask {
+    fn drop(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.join_handle.abort();
+    }
+}
+
+#[cfg(feature = "e2e-encryption")]
+impl DehydratedDeviceRotationTask {
+    /// How long a dehydrated device stays on the homeserver before we replace
+    /// it, so it doesn't run out of one-time keys.
+    const ROTATION_PERIOD: Duration = Duration::from_secs(60 * 60 * 24 * 7);
+
+    /// How long we wait before trying again to replace the dehydrated device,
+    /// if it failed.
+    const RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+    pub(crate) fn new(client: Weak<ClientInner>) -> Self {
+        let join_handle = spawn(async move {
+            Self::run(client).await;
+        });
+
+        Self { join_handle }
+    }
+
+    async fn run(client: Weak<ClientInner>) {
+        loop {
+            let Some(delay) = Self::next_rotation_delay(&client).await else {
+                trace!("Client got dropped, shutting down the task");
+                break;
+            };
+
+            sleep(delay).await;
+
+            if let Some(client) = client.upgrade() {
+                let client = Client { inner: client };
+
+                if let Err(e) = client.encryption().dehydrated_devices().rotate().await {
+                    warn!("Error replacing the dehydrated device {e:?}");
+                    sleep(Self::RETRY_DELAY).await;
+                }
+            } else {
+                trace!("Client got dropped, shutting down the task");
+                break;
+            }
+        }
+    }
+
+    /// How long to wait before replacing the dehydrated device, based on the
+    /// time of its upload, so the rotation isn't delayed by restarts of the
+    /// client.
+    ///
+    /// Returns `None` if the client got dropped.
+    async fn next_rotation_delay(client: &Weak<ClientInner>) -> Option<Duration> {
+        let client = Client { inner: client.upgrade()? };
+
+        let upload_time = match client.encryption().dehydrated_devices().last_upload_time().await {
+            Ok(upload_time) => upload_time,
+            Err(e) => {
+                warn!("Couldn't load the upload time of the dehydrated device {e:?}");
+                None
+            }
+        };
+
+        let elapsed = upload_time.map_or(Duration::ZERO, |upload_time| {
+            let now = MilliSecondsSinceUnixEpoch::now();
+            Duration::from_millis(now.get().saturating_sub(upload_time.get()).into())
+        });
+
+        Some(Self::ROTATION_PERIOD.saturating_sub(elapsed))
+    }
+}
+
+#[cfg(feature = "e2e-encryption")]
+async fn sleep(delay: Duration) {
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::sleep(delay).await;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(delay).await;
+}
+
 pub type RoomKeyInfo = (OwnedRoomId, String);
 pub type TaskQueue = BTreeMap<RoomKeyInfo, JoinHandle<()>>;
 
@@ -196,3 +287,56 @@ impl BackupDownloadTask {
         }
     }
 }
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use matrix_sdk_test::async_test;
+    use ruma::{MilliSecondsSinceUnixEpoch, UInt};
+
+    use super::DehydratedDeviceRotationTask;
+    use crate::{
+        encryption::dehydrated_devices::UPLOAD_TIME_STORE_KEY, test_utils::logged_in_client, Client,
+    };
+
+    async fn set_upload_time(client: &Client, elapsed: Duration) {
+        let now = MilliSecondsSinceUnixEpoch::now();
+        let upload_time =
+            MilliSecondsSinceUnixEpoch(now.0 - UInt::new(elapsed.as_secs() * 1000).unwrap());
+
+        let olm_machine = client.olm_machine().await;
+        olm_machine
+            .as_ref()
+            .unwrap()
+            .store()
+            .set_value(UPLOAD_TIME_STORE_KEY, &upload_time)
+            .await
+            .unwrap();
+    }
+
+    #[async_test]
+    async fn next_rotation_delay() {
+        let client = logged_in_client(None).await;
+        let weak_client = Arc::downgrade(&client.inner);
+        let period = DehydratedDeviceRotationTask::ROTATION_PERIOD;
+
+        // Without a known upload time, we wait for a full period.
+        let delay = DehydratedDeviceRotationTask::next_rotation_delay(&weak_client).await;
+        assert_eq!(delay, Some(period));
+
+        // The time elapsed since the upload is deducted from the period.
+        let elapsed = Duration::from_secs(60 * 60 * 24 * 2);
+        set_upload_time(&client, elapsed).await;
+
+        let delay = DehydratedDeviceRotationTask::next_rotation_delay(&weak_client).await.unwrap();
+        assert!(delay <= period - elapsed);
+        assert!(delay > period - elapsed - Duration::from_secs(60));
+
+        // A device that is older than the period is replaced right away.
+        set_upload_time(&client, period * 2).await;
+
+        let delay = DehydratedDeviceRotationTask::next_rotation_delay(&weak_client).await;
+        assert_eq!(delay, Some(Duration::ZERO));
+    }
+}
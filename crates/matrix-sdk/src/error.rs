@@ -23,7 +23,9 @@ use matrix_sdk_base::crypto::ScanError;
 use matrix_sdk_base::crypto::{
     CryptoStoreError, DecryptorError, KeyExportError, MegolmError, OlmError,
 };
-use matrix_sdk_base::{Error as SdkBaseError, RoomState, StoreError};
+use matrix_sdk_base::{
+    media_cache_store::MediaCacheStoreError, Error as SdkBaseError, RoomState, StoreError,
+};
 use reqwest::Error as ReqwestError;
 use ruma::{
     api::{
@@ -112,6 +114,16 @@ pub enum HttpError {
     /// An error occurred while refreshing the access token.
     #[error(transparent)]
     RefreshToken(#[from] RefreshTokenError),
+
+    /// The body of the response is larger than the maximum size set with
+    /// [`RequestConfig::max_response_size()`].
+    ///
+    /// [`RequestConfig::max_response_size()`]: crate::config::RequestConfig::max_response_size
+    #[error("the response is larger than the maximum size of {max_size} bytes")]
+    ResponseTooLarge {
+        /// The maximum size of the response, in bytes.
+        max_size: usize,
+    },
 }
 
 #[rustfmt::skip] // stop rustfmt breaking the `<code>` in docs across multiple lines
@@ -219,6 +231,10 @@ pub enum Error {
     #[error(transparent)]
     StateStore(#[from] StoreError),
 
+    /// An error occurred in the media cache store.
+    #[error(transparent)]
+    MediaCacheStore(#[from] MediaCacheStoreError),
+
     /// An error encountered when trying to parse an identifier.
     #[error(transparent)]
     Identifier(#[from] IdParseError),
@@ -266,6 +282,14 @@ pub enum Error {
     #[error(transparent)]
     Oidc(#[from] crate::oidc::OidcError),
 
+    /// An error occurred during a User-Interactive Authentication session.
+    #[error(transparent)]
+    Uiaa(#[from] crate::uiaa::UiaaError),
+
+    /// An error occurred in the send queue of a room.
+    #[error(transparent)]
+    SendQueue(#[from] crate::send_queue::RoomSendQueueError),
+
     /// A concurrent request to a deduplicated request has failed.
     #[error("a concurrent request failed; see logs for details")]
     ConcurrentRequestFailed,
@@ -32,25 +32,48 @@
 //!   `RoomInfo`, and that may update a `RoomListService`.
 //! - [ ] provide read receipts for each message.
 //! - [ ] backwards and forward pagination, and reconcile results with cached
-//!   timelines.
+//!   timelines. Events received from sync and back-pagination are merged and
+//!   deduplicated, and gaps left by limited syncs are remembered, so
+//!   back-pagination can fill them.
 //! - [ ] retry decryption upon receiving new keys (from an encryption sync
 //!   service or from a key backup).
 //! - [ ] expose the latest event for a given room.
-//! - [ ] caching of events on-disk.
+//! - [x] caching of events on-disk.
+//! - [x] local search index of the messages, including the ones of encrypted
+//!   rooms, see [`EventCache::set_search_index_enabled`].
 
 #![forbid(missing_docs)]
 
+#[cfg(feature = "e2e-encryption")]
+use std::collections::BTreeSet;
 use std::{
     collections::BTreeMap,
     fmt::Debug,
     sync::{Arc, OnceLock, Weak},
 };
 
+#[cfg(feature = "e2e-encryption")]
+use futures_core::Stream;
+#[cfg(feature = "e2e-encryption")]
+use futures_util::{pin_mut, StreamExt};
+
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk_base::crypto::RoomKeyImportResult;
+pub use matrix_sdk_base::event_cache_store::{SearchIndexEntry, SearchIndexQuery};
 use matrix_sdk_base::{
-    deserialized_responses::{AmbiguityChange, SyncTimelineEvent},
+    deserialized_responses::{AmbiguityChange, SyncTimelineEvent, TimelineEvent},
+    event_cache_store::{DynEventCacheStore, EventCacheStoreError},
     sync::{JoinedRoomUpdate, LeftRoomUpdate, RoomUpdates, Timeline},
+    StoreError,
+};
+use matrix_sdk_common::{
+    executor::{spawn, JoinHandle},
+    linked_chunk::{LinkedChunkError, Update},
+};
+#[cfg(feature = "e2e-encryption")]
+use ruma::events::{
+    forwarded_room_key::ToDeviceForwardedRoomKeyEvent, room_key::ToDeviceRoomKeyEvent,
 };
-use matrix_sdk_common::executor::{spawn, JoinHandle};
 use ruma::{
     events::{AnyRoomAccountDataEvent, AnySyncEphemeralRoomEvent},
     serde::Raw,
@@ -60,11 +83,17 @@ use tokio::sync::{
     broadcast::{error::RecvError, Receiver, Sender},
     Mutex, RwLock,
 };
+#[cfg(feature = "e2e-encryption")]
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
 use tracing::{error, trace};
 
-use self::store::{EventCacheStore, MemoryStore};
+use self::{
+    search_index::SearchIndex,
+    store::{Gap, RoomEvents},
+};
 use crate::{client::ClientInner, Client, Room};
 
+mod search_index;
 mod store;
 
 /// An error observed in the [`EventCache`].
@@ -89,6 +118,19 @@ pub enum EventCacheError {
     /// times where we try to use the client.
     #[error("The owning client of the event cache has been dropped.")]
     ClientDropped,
+
+    /// An error happened when manipulating the events of a room.
+    #[error(transparent)]
+    LinkedChunk(#[from] LinkedChunkError),
+
+    /// An error happened when reading from or writing to the event cache
+    /// store.
+    #[error(transparent)]
+    Store(#[from] EventCacheStoreError),
+
+    /// An error happened when reading from or writing to the state store.
+    #[error(transparent)]
+    StateStore(#[from] StoreError),
 }
 
 /// A result using the [`EventCacheError`].
@@ -97,6 +139,11 @@ pub type Result<T> = std::result::Result<T, EventCacheError>;
 /// Hold handles to the tasks spawn by a [`RoomEventCache`].
 pub struct EventCacheDropHandles {
     listen_updates_task: JoinHandle<()>,
+
+    /// The task retrying to decrypt the events to index, when room keys are
+    /// downloaded from the key backup.
+    #[cfg(feature = "e2e-encryption")]
+    room_keys_from_backups_task: JoinHandle<()>,
 }
 
 impl Debug for EventCacheDropHandles {
@@ -108,6 +155,8 @@ impl Debug for EventCacheDropHandles {
 impl Drop for EventCacheDropHandles {
     fn drop(&mut self) {
         self.listen_updates_task.abort();
+        #[cfg(feature = "e2e-encryption")]
+        self.room_keys_from_backups_task.abort();
     }
 }
 
@@ -129,12 +178,13 @@ impl Debug for EventCache {
 }
 
 impl EventCache {
-    /// Create a new [`EventCache`] for the given client.
-    pub(crate) fn new(client: &Arc<ClientInner>) -> Self {
-        let store = Arc::new(MemoryStore::new());
+    /// Create a new [`EventCache`] for the given client, using the given store
+    /// as its backend.
+    pub(crate) fn new(client: &Arc<ClientInner>, store: Arc<DynEventCacheStore>) -> Self {
         let inner = Arc::new(EventCacheInner {
             client: Arc::downgrade(client),
             by_room: Default::default(),
+            search_index: Arc::new(SearchIndex::new(store.clone())),
             store,
             process_lock: Default::default(),
             drop_handles: Default::default(),
@@ -157,12 +207,74 @@ impl EventCache {
             let listen_updates_task =
                 spawn(Self::listen_task(self.inner.clone(), room_updates_feed));
 
-            Arc::new(EventCacheDropHandles { listen_updates_task })
+            // Retry to decrypt the events to index when their room key is received.
+            #[cfg(feature = "e2e-encryption")]
+            let room_keys_from_backups_task = {
+                client.add_event_handler({
+                    let search_index = self.inner.search_index.clone();
+                    move |event: ToDeviceRoomKeyEvent, client: Client| async move {
+                        let sessions = BTreeMap::from([(
+                            event.content.room_id,
+                            BTreeSet::from([event.content.session_id]),
+                        )]);
+                        search_index.retry_decryption(&client, Some(sessions)).await;
+                    }
+                });
+                client.add_event_handler({
+                    let search_index = self.inner.search_index.clone();
+                    move |event: ToDeviceForwardedRoomKeyEvent, client: Client| async move {
+                        let sessions = BTreeMap::from([(
+                            event.content.room_id,
+                            BTreeSet::from([event.content.session_id]),
+                        )]);
+                        search_index.retry_decryption(&client, Some(sessions)).await;
+                    }
+                });
+
+                let room_keys_stream = client.encryption().backups().room_keys_stream();
+                spawn(Self::room_keys_from_backups_task(self.inner.clone(), room_keys_stream))
+            };
+
+            Arc::new(EventCacheDropHandles {
+                listen_updates_task,
+                #[cfg(feature = "e2e-encryption")]
+                room_keys_from_backups_task,
+            })
         });
 
         Ok(())
     }
 
+    #[cfg(feature = "e2e-encryption")]
+    async fn room_keys_from_backups_task(
+        inner: Arc<EventCacheInner>,
+        room_keys_stream: impl Stream<
+            Item = std::result::Result<RoomKeyImportResult, BroadcastStreamRecvError>,
+        >,
+    ) {
+        pin_mut!(room_keys_stream);
+
+        while let Some(update) = room_keys_stream.next().await {
+            let Ok(client) = inner.client() else { break };
+
+            let sessions = match update {
+                Ok(import_result) => Some(
+                    import_result
+                        .keys
+                        .into_iter()
+                        .map(|(room_id, sessions)| {
+                            (room_id, sessions.into_values().flatten().collect())
+                        })
+                        .collect(),
+                ),
+                // We lagged, so retry every event.
+                Err(_) => None,
+            };
+
+            inner.search_index.retry_decryption(&client, sessions).await;
+        }
+    }
+
     async fn listen_task(
         inner: Arc<EventCacheInner>,
         mut room_updates_feed: Receiver<RoomUpdates>,
@@ -188,13 +300,12 @@ impl EventCache {
                     // Forget everything we know; we could have missed events, and we have
                     // no way to reconcile at the moment!
                     // TODO: implement Smart Matching™,
-                    let mut by_room = inner.by_room.write().await;
-                    for room_id in by_room.keys() {
-                        if let Err(err) = inner.store.clear_room_events(room_id).await {
+                    let by_room = inner.by_room.read().await;
+                    for room in by_room.values() {
+                        if let Err(err) = room.inner.reset().await {
                             error!("unable to clear room after room updates lag: {err}");
                         }
                     }
-                    by_room.clear();
                 }
 
                 Err(RecvError::Closed) => {
@@ -221,25 +332,74 @@ impl EventCache {
 
     /// Add an initial set of events to the event cache, reloaded from a cache.
     ///
+    /// If `events` is empty, the events persisted in the event cache store
+    /// are kept as is.
+    ///
     /// TODO: temporary for API compat, as the event cache should take care of
     /// its own store.
     pub async fn add_initial_events(
         &self,
         room_id: &RoomId,
         events: Vec<SyncTimelineEvent>,
+        prev_batch: Option<String>,
     ) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+
         let room_cache = self.inner.for_room(room_id).await?;
 
         // We could have received events during a previous sync; remove them all, since
         // we can't know where to insert the "initial events" with respect to
         // them.
-        self.inner.store.clear_room_events(room_id).await?;
-        let _ = room_cache.inner.sender.send(RoomEventCacheUpdate::Clear);
+        room_cache.inner.reset().await?;
 
-        room_cache.inner.append_events(events).await?;
+        room_cache.inner.append_events(events, prev_batch).await?;
 
         Ok(())
     }
+
+    /// Enable or disable the local search index.
+    ///
+    /// When enabled, the messages received by the event cache, from sync or
+    /// back-pagination, are added to a search index in the event cache store,
+    /// after they have been decrypted. This allows to search the messages of
+    /// encrypted rooms with [`EventCache::search`], which the homeserver
+    /// can't do.
+    ///
+    /// The event cache must have subscribed to sync responses with
+    /// [`EventCache::subscribe`] for the messages to be indexed.
+    ///
+    /// Messages that can't be decrypted yet are indexed once their room key is
+    /// received, if it's received while the client is running.
+    ///
+    /// It is disabled by default, and the setting is persisted in the state
+    /// store. Disabling it stops indexing new messages, but doesn't remove the
+    /// indexed ones; use [`EventCache::clear_search_index`] for that.
+    pub async fn set_search_index_enabled(&self, enabled: bool) -> Result<()> {
+        self.inner.search_index.set_enabled(&self.inner.client()?, enabled).await
+    }
+
+    /// Whether the local search index is enabled.
+    pub async fn is_search_index_enabled(&self) -> Result<bool> {
+        self.inner.search_index.is_enabled(&self.inner.client()?).await
+    }
+
+    /// Search the messages in the local search index.
+    ///
+    /// Returns the matching messages, from the most recent to the oldest one.
+    /// Edited messages match with the text of their latest known edit, if it
+    /// was sent by the sender of the original message.
+    pub async fn search(&self, query: SearchIndexQuery) -> Result<Vec<SearchIndexEntry>> {
+        Ok(self.inner.store.search_index(&query).await?)
+    }
+
+    /// Remove all the messages from the local search index.
+    ///
+    /// This is called when logging out.
+    pub async fn clear_search_index(&self) -> Result<()> {
+        Ok(self.inner.store.clear_search_index().await?)
+    }
 }
 
 struct EventCacheInner {
@@ -251,7 +411,10 @@ struct EventCacheInner {
     by_room: RwLock<BTreeMap<OwnedRoomId, RoomEventCache>>,
 
     /// Backend used for storage.
-    store: Arc<dyn EventCacheStore>,
+    store: Arc<DynEventCacheStore>,
+
+    /// The local search index, fed by the events of all the rooms.
+    search_index: Arc<SearchIndex>,
 
     /// A lock to make sure that despite multiple updates coming to the
     /// `EventCache`, it will only handle one at a time.
@@ -328,7 +491,25 @@ impl EventCacheInner {
                     .get_room(room_id)
                     .ok_or_else(|| EventCacheError::RoomNotFound(room_id.to_owned()))?;
 
-                let room_event_cache = RoomEventCache::new(room, self.store.clone());
+                let raw_chunks = self.store.reload_linked_chunk(room_id).await?;
+                let events = match RoomEvents::from_raw_chunks(raw_chunks) {
+                    Ok(events) => events,
+                    Err(error) => {
+                        // This is only a cache: start over rather than failing.
+                        error!(%room_id, "Failed to reload the events of the room: {error}");
+                        self.store
+                            .handle_linked_chunk_updates(room_id, vec![Update::Clear])
+                            .await?;
+                        RoomEvents::new()
+                    }
+                };
+
+                let room_event_cache = RoomEventCache::new(
+                    room,
+                    self.store.clone(),
+                    self.search_index.clone(),
+                    events,
+                );
 
                 by_room_guard.insert(room_id.to_owned(), room_event_cache.clone());
 
@@ -353,22 +534,73 @@ impl Debug for RoomEventCache {
 }
 
 impl RoomEventCache {
-    /// Create a new [`RoomEventCache`] using the given room and store.
-    fn new(room: Room, store: Arc<dyn EventCacheStore>) -> Self {
-        Self { inner: Arc::new(RoomEventCacheInner::new(room, store)) }
+    /// Create a new [`RoomEventCache`] using the given room and store, and the
+    /// events previously loaded from this store.
+    fn new(
+        room: Room,
+        store: Arc<DynEventCacheStore>,
+        search_index: Arc<SearchIndex>,
+        events: RoomEvents,
+    ) -> Self {
+        Self { inner: Arc::new(RoomEventCacheInner::new(room, store, search_index, events)) }
     }
 
     /// Subscribe to room updates for this room, after getting the initial list
     /// of events. XXX: Could/should it use some kind of `Observable`
     /// instead? Or not something async, like explicit handlers as our event
     /// handlers?
+    ///
+    /// The initial events are the ones known to be contiguous with the live
+    /// end of the room, i.e. the events since the most recent gap.
     pub async fn subscribe(
         &self,
     ) -> Result<(Vec<SyncTimelineEvent>, Receiver<RoomEventCacheUpdate>)> {
-        Ok((
-            self.inner.store.room_events(self.inner.room.room_id()).await?,
-            self.inner.sender.subscribe(),
-        ))
+        let events = self.inner.events.read().await;
+
+        Ok((events.events_since_last_gap().cloned().collect(), self.inner.sender.subscribe()))
+    }
+
+    /// Returns the token to back-paginate from the oldest event returned by
+    /// [`RoomEventCache::subscribe`], if any.
+    pub async fn back_pagination_token(&self) -> Result<Option<String>> {
+        Ok(self.inner.events.read().await.last_gap().map(|gap| gap.prev_token.clone()))
+    }
+
+    /// Save events that have been back-paginated from the given token.
+    ///
+    /// If the token belongs to a known gap, the events fill this gap; otherwise
+    /// they are considered older than any known event. Events already known
+    /// are deduplicated.
+    ///
+    /// `events` must be in the order returned by the `/messages` endpoint
+    /// when paginating backwards, i.e. from the most recent to the oldest one,
+    /// and `next_token` is the token to continue back-paginating, if the
+    /// start of the room hasn't been reached.
+    pub async fn add_back_paginated_events(
+        &self,
+        token: Option<&str>,
+        events: Vec<TimelineEvent>,
+        next_token: Option<String>,
+    ) -> Result<()> {
+        let events = events.into_iter().rev().map(SyncTimelineEvent::from).collect::<Vec<_>>();
+        let new_gap = next_token.map(|prev_token| Gap { prev_token });
+
+        self.inner.search_index.index(&self.inner.room, &events).await;
+
+        let mut room_events = self.inner.events.write().await;
+
+        match token.and_then(|token| room_events.gap_with_token(token)) {
+            Some(gap_identifier) => {
+                trace!("filling a known gap with back-paginated events");
+                room_events.replace_gap_at(events, new_gap, gap_identifier)?;
+            }
+            None => {
+                trace!("no gap found for the back-pagination token, prepending events");
+                room_events.push_events_front(events, new_gap);
+            }
+        }
+
+        self.inner.save(&mut room_events).await
     }
 }
 
@@ -378,7 +610,13 @@ struct RoomEventCacheInner {
     sender: Sender<RoomEventCacheUpdate>,
 
     /// A pointer to the store implementation used for this event cache.
-    store: Arc<dyn EventCacheStore>,
+    store: Arc<DynEventCacheStore>,
+
+    /// The local search index, shared with the other rooms.
+    search_index: Arc<SearchIndex>,
+
+    /// The events of the room, with the gaps left by limited syncs.
+    events: RwLock<RoomEvents>,
 
     /// The Client [`Room`] this event cache pertains to.
     room: Room,
@@ -387,9 +625,38 @@ struct RoomEventCacheInner {
 impl RoomEventCacheInner {
     /// Creates a new cache for a room, and subscribes to room updates, so as
     /// to handle new timeline events.
-    fn new(room: Room, store: Arc<dyn EventCacheStore>) -> Self {
+    fn new(
+        room: Room,
+        store: Arc<DynEventCacheStore>,
+        search_index: Arc<SearchIndex>,
+        events: RoomEvents,
+    ) -> Self {
         let sender = Sender::new(32);
-        Self { room, store, sender }
+        Self { room, store, search_index, events: RwLock::new(events), sender }
+    }
+
+    /// Persist the modifications of the given events of this room in the
+    /// store.
+    async fn save(&self, events: &mut RoomEvents) -> Result<()> {
+        let updates = events.take_updates();
+
+        if !updates.is_empty() {
+            self.store.handle_linked_chunk_updates(self.room.room_id(), updates).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove all the events of this room, notifying observers.
+    async fn reset(&self) -> Result<()> {
+        let mut events = self.events.write().await;
+        events.reset();
+        self.save(&mut events).await?;
+        drop(events);
+
+        let _ = self.sender.send(RoomEventCacheUpdate::Clear);
+
+        Ok(())
     }
 
     async fn handle_joined_room_update(&self, updates: JoinedRoomUpdate) -> Result<()> {
@@ -410,18 +677,29 @@ impl RoomEventCacheInner {
         account_data: Vec<Raw<AnyRoomAccountDataEvent>>,
         ambiguity_changes: BTreeMap<OwnedEventId, AmbiguityChange>,
     ) -> Result<()> {
+        let mut room_events = self.events.write().await;
+
         if timeline.limited {
-            // Ideally we'd try to reconcile existing events against those received in the
-            // timeline, but we're not there yet. In the meanwhile, clear the
-            // items from the room. TODO: implement Smart Matching™.
-            trace!("limited timeline, clearing all previous events");
-            self.store.clear_room_events(self.room.room_id()).await?;
+            // The previous events aren't contiguous with the new ones anymore: remember
+            // the hole between them, so back-pagination can fill it later. Observers only
+            // know about contiguous events, so they have to start over.
+            trace!("limited timeline, adding a gap");
             let _ = self.sender.send(RoomEventCacheUpdate::Clear);
         }
 
+        if timeline.limited || room_events.is_empty() {
+            if let Some(prev_token) = timeline.prev_batch.clone() {
+                room_events.push_gap(Gap { prev_token });
+            }
+        }
+
         // Add all the events to the backend.
         trace!("adding new events");
-        self.store.add_room_events(self.room.room_id(), timeline.events.clone()).await?;
+        room_events.push_events(timeline.events.clone());
+        self.save(&mut room_events).await?;
+        drop(room_events);
+
+        self.search_index.index(&self.room, &timeline.events).await;
 
         // Propagate events to observers.
         let _ = self.sender.send(RoomEventCacheUpdate::Append {
@@ -443,8 +721,23 @@ impl RoomEventCacheInner {
 
     /// Append a set of events to the room cache and storage, notifying
     /// observers.
-    async fn append_events(&self, events: Vec<SyncTimelineEvent>) -> Result<()> {
-        self.store.add_room_events(self.room.room_id(), events.clone()).await?;
+    ///
+    /// If `prev_batch` is set, a gap is added before the events.
+    async fn append_events(
+        &self,
+        events: Vec<SyncTimelineEvent>,
+        prev_batch: Option<String>,
+    ) -> Result<()> {
+        {
+            let mut room_events = self.events.write().await;
+            if let Some(prev_token) = prev_batch {
+                room_events.push_gap(Gap { prev_token });
+            }
+            room_events.push_events(events.clone());
+            self.save(&mut room_events).await?;
+        }
+
+        self.search_index.index(&self.room, &events).await;
 
         let _ = self.sender.send(RoomEventCacheUpdate::Append {
             events,
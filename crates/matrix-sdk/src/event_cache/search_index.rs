@@ -0,0 +1,275 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Feeding the local search index of the event cache store.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+#[cfg(feature = "e2e-encryption")]
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex as StdMutex,
+};
+
+use matrix_sdk_base::{
+    deserialized_responses::SyncTimelineEvent,
+    event_cache_store::{DynEventCacheStore, SearchIndexEntry},
+};
+#[cfg(feature = "e2e-encryption")]
+use ruma::{
+    events::room::encrypted::{EncryptedEventScheme, OriginalSyncRoomEncryptedEvent},
+    serde::Raw,
+    OwnedRoomId, RoomId,
+};
+use ruma::{
+    events::{
+        room::message::Relation, AnySyncMessageLikeEvent, AnySyncTimelineEvent,
+        SyncMessageLikeEvent,
+    },
+    RoomVersionId,
+};
+use tokio::sync::OnceCell;
+use tracing::{error, trace};
+
+use super::Result;
+use crate::{Client, Room};
+
+/// The key of the custom value of the state store holding whether the search
+/// index is enabled.
+const ENABLED_KEY: &[u8] = b"search_index_enabled";
+
+/// The maximum number of events that couldn't be decrypted yet, that are kept
+/// to be indexed once their room key is received.
+#[cfg(feature = "e2e-encryption")]
+const MAX_PENDING_UTDS: usize = 1000;
+
+/// The local search index, shared by all the rooms of the event cache.
+pub(super) struct SearchIndex {
+    /// Whether the events should be indexed, loaded lazily from the state
+    /// store.
+    enabled: OnceCell<AtomicBool>,
+
+    /// The store holding the index.
+    store: Arc<DynEventCacheStore>,
+
+    /// The events that couldn't be decrypted yet, by room and Megolm session
+    /// ID.
+    #[cfg(feature = "e2e-encryption")]
+    utds: StdMutex<BTreeMap<(OwnedRoomId, String), Vec<Raw<OriginalSyncRoomEncryptedEvent>>>>,
+}
+
+impl SearchIndex {
+    pub(super) fn new(store: Arc<DynEventCacheStore>) -> Self {
+        Self {
+            enabled: OnceCell::new(),
+            store,
+            #[cfg(feature = "e2e-encryption")]
+            utds: Default::default(),
+        }
+    }
+
+    async fn enabled(&self, client: &Client) -> Result<&AtomicBool> {
+        self.enabled
+            .get_or_try_init(|| async {
+                let value = client.store().get_custom_value(ENABLED_KEY).await?;
+                Ok(AtomicBool::new(value.is_some_and(|value| value == [1])))
+            })
+            .await
+    }
+
+    pub(super) async fn is_enabled(&self, client: &Client) -> Result<bool> {
+        Ok(self.enabled(client).await?.load(Ordering::SeqCst))
+    }
+
+    pub(super) async fn set_enabled(&self, client: &Client, enabled: bool) -> Result<()> {
+        client.store().set_custom_value(ENABLED_KEY, vec![enabled.into()]).await?;
+        self.enabled(client).await?.store(enabled, Ordering::SeqCst);
+
+        #[cfg(feature = "e2e-encryption")]
+        if !enabled {
+            self.utds.lock().unwrap().clear();
+        }
+
+        Ok(())
+    }
+
+    /// Index the messages among the given events of the room, if indexing is
+    /// enabled.
+    ///
+    /// Edits replace the indexed text of the original event, and redacted
+    /// events are removed from the index. Events that couldn't be decrypted
+    /// are indexed once their room key is received, see
+    /// [`SearchIndex::retry_decryption`].
+    pub(super) async fn index(&self, room: &Room, events: &[SyncTimelineEvent]) {
+        match self.is_enabled(&room.client()).await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(err) => {
+                error!("Failed to load whether the search index is enabled: {err}");
+                return;
+            }
+        }
+
+        if let Err(err) = self.index_impl(room, events).await {
+            error!(room_id = ?room.room_id(), "Failed to index events for local search: {err}");
+        }
+    }
+
+    async fn index_impl(&self, room: &Room, events: &[SyncTimelineEvent]) -> Result<()> {
+        let room_id = room.room_id();
+        let room_version = room.clone_info().room_version().cloned().unwrap_or(RoomVersionId::V1);
+
+        let mut entries: Vec<SearchIndexEntry> = Vec::new();
+
+        for event in events {
+            let Ok(deserialized) = event.event.deserialize() else { continue };
+
+            match deserialized {
+                AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+                    SyncMessageLikeEvent::Original(ev),
+                )) => match ev.content.relates_to {
+                    Some(Relation::Replacement(replacement)) => {
+                        // Only the sender of the original event can edit it.
+                        let original = match entries
+                            .iter()
+                            .rev()
+                            .find(|entry| entry.event_id == replacement.event_id)
+                        {
+                            Some(entry) => Some(entry.clone()),
+                            None => {
+                                self.store.get_indexed_event(room_id, &replacement.event_id).await?
+                            }
+                        };
+
+                        match original {
+                            Some(original) if original.sender == ev.sender => {
+                                entries.push(SearchIndexEntry {
+                                    body: replacement.new_content.msgtype.body().to_owned(),
+                                    ..original
+                                });
+                            }
+                            Some(_) => {
+                                trace!(event_id = ?ev.event_id, "Ignoring edit from another sender");
+                            }
+                            None => {
+                                trace!(event_id = ?ev.event_id, "Ignoring edit of unknown event");
+                            }
+                        }
+                    }
+                    _ => entries.push(SearchIndexEntry {
+                        room_id: room_id.to_owned(),
+                        body: ev.content.msgtype.body().to_owned(),
+                        event_id: ev.event_id,
+                        sender: ev.sender,
+                        origin_server_ts: ev.origin_server_ts,
+                    }),
+                },
+
+                AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomRedaction(ev)) => {
+                    if let Some(redacts) = ev.redacts(&room_version) {
+                        // Keep the order of the events, in case the redacted event is indexed
+                        // in the same batch.
+                        if !entries.is_empty() {
+                            self.store.index_events(std::mem::take(&mut entries)).await?;
+                        }
+                        self.store.remove_indexed_event(room_id, redacts).await?;
+                    }
+                }
+
+                #[cfg(feature = "e2e-encryption")]
+                AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomEncrypted(
+                    SyncMessageLikeEvent::Original(ev),
+                )) => {
+                    if let EncryptedEventScheme::MegolmV1AesSha2(content) = ev.content.scheme {
+                        self.add_utd(room_id, content.session_id, event.event.cast_ref().clone());
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        if !entries.is_empty() {
+            self.store.index_events(entries).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Remember an event that couldn't be decrypted, to index it once its
+    /// room key is received.
+    #[cfg(feature = "e2e-encryption")]
+    fn add_utd(
+        &self,
+        room_id: &RoomId,
+        session_id: String,
+        event: Raw<OriginalSyncRoomEncryptedEvent>,
+    ) {
+        let mut utds = self.utds.lock().unwrap();
+
+        if utds.values().map(Vec::len).sum::<usize>() >= MAX_PENDING_UTDS {
+            trace!(?room_id, "Too many events waiting for their room key, not indexing this one");
+            return;
+        }
+
+        utds.entry((room_id.to_owned(), session_id)).or_default().push(event);
+    }
+
+    /// Try to decrypt and index the events waiting for their room key.
+    ///
+    /// If `sessions` is `None`, all the waiting events are retried, otherwise
+    /// only the ones of the given Megolm sessions, by room.
+    #[cfg(feature = "e2e-encryption")]
+    pub(super) async fn retry_decryption(
+        &self,
+        client: &Client,
+        sessions: Option<BTreeMap<OwnedRoomId, BTreeSet<String>>>,
+    ) {
+        let utds = {
+            let mut utds = self.utds.lock().unwrap();
+
+            match sessions {
+                Some(sessions) => sessions
+                    .into_iter()
+                    .flat_map(|(room_id, session_ids)| {
+                        session_ids.into_iter().map(move |session_id| (room_id.clone(), session_id))
+                    })
+                    .filter_map(|key| utds.remove_entry(&key))
+                    .collect::<Vec<_>>(),
+                None => std::mem::take(&mut *utds).into_iter().collect(),
+            }
+        };
+
+        for ((room_id, session_id), events) in utds {
+            let Some(room) = client.get_room(&room_id) else { continue };
+
+            let mut decrypted = Vec::new();
+            for event in events {
+                match room.decrypt_event(&event).await {
+                    Ok(event) => decrypted.push(event.into()),
+                    // The room key might still be unusable for this event, e.g. if it was
+                    // received at a later index of the session.
+                    Err(_) => self.add_utd(&room_id, session_id.clone(), event),
+                }
+            }
+
+            if !decrypted.is_empty() {
+                trace!(?room_id, ?session_id, "Indexing {} decrypted events", decrypted.len());
+                self.index(&room, &decrypted).await;
+            }
+        }
+    }
+}
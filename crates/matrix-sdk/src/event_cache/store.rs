@@ -12,59 +12,312 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::BTreeMap;
-
-use async_trait::async_trait;
-use matrix_sdk_base::deserialized_responses::SyncTimelineEvent;
-use ruma::{OwnedRoomId, RoomId};
-use tokio::sync::RwLock;
-
-use super::Result;
-
-/// A store that can be remember information about the event cache.
-///
-/// It really acts as a cache, in the sense that clearing the backing data
-/// should not have any irremediable effect, other than providing a lesser user
-/// experience.
-#[async_trait]
-pub trait EventCacheStore: Send + Sync {
-    /// Returns all the known events for the given room.
-    async fn room_events(&self, room: &RoomId) -> Result<Vec<SyncTimelineEvent>>;
-
-    /// Adds all the events to the given room.
-    async fn add_room_events(&self, room: &RoomId, events: Vec<SyncTimelineEvent>) -> Result<()>;
-
-    /// Clear all the events from the given room.
-    async fn clear_room_events(&self, room: &RoomId) -> Result<()>;
-}
+use std::{collections::HashSet, fmt};
+
+pub use matrix_sdk_base::event_cache_store::Gap;
+use matrix_sdk_base::{
+    deserialized_responses::SyncTimelineEvent,
+    event_cache_store::{RoomEventsRawChunk, RoomEventsUpdate},
+};
+use matrix_sdk_common::linked_chunk::{
+    ChunkContent, ChunkIdentifier, LinkedChunk, LinkedChunkError,
+};
 
-/// An [`EventCacheStore`] implementation that keeps all the information in
-/// memory.
-pub(crate) struct MemoryStore {
-    /// All the events per room, in sync order.
-    by_room: RwLock<BTreeMap<OwnedRoomId, Vec<SyncTimelineEvent>>>,
+/// The events of a room, with the gaps left by limited syncs.
+pub struct RoomEvents {
+    chunks: LinkedChunk<SyncTimelineEvent, Gap>,
 }
 
-impl MemoryStore {
-    /// Create a new empty [`MemoryStore`].
+impl RoomEvents {
+    /// Create a new empty [`RoomEvents`].
     pub fn new() -> Self {
-        Self { by_room: Default::default() }
+        Self { chunks: LinkedChunk::new() }
     }
-}
 
-#[async_trait]
-impl EventCacheStore for MemoryStore {
-    async fn room_events(&self, room: &RoomId) -> Result<Vec<SyncTimelineEvent>> {
-        Ok(self.by_room.read().await.get(room).cloned().unwrap_or_default())
+    /// Rebuild a [`RoomEvents`] from the chunks loaded from an
+    /// [`EventCacheStore`](matrix_sdk_base::event_cache_store::EventCacheStore).
+    pub fn from_raw_chunks(raw_chunks: Vec<RoomEventsRawChunk>) -> Result<Self, LinkedChunkError> {
+        Ok(Self { chunks: LinkedChunk::from_raw_chunks(raw_chunks)? })
     }
 
-    async fn add_room_events(&self, room: &RoomId, events: Vec<SyncTimelineEvent>) -> Result<()> {
-        self.by_room.write().await.entry(room.to_owned()).or_default().extend(events);
-        Ok(())
+    /// Take the modifications made since the last call, to persist them in an
+    /// [`EventCacheStore`](matrix_sdk_base::event_cache_store::EventCacheStore).
+    pub fn take_updates(&mut self) -> Vec<RoomEventsUpdate> {
+        self.chunks.take_updates()
+    }
+
+    /// Whether there are no events or gaps at all.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Remove all the events and gaps.
+    pub fn reset(&mut self) {
+        self.chunks.clear();
     }
 
-    async fn clear_room_events(&self, room: &RoomId) -> Result<()> {
-        let _ = self.by_room.write().await.remove(room);
+    /// Push events at the end, after removing any event already known with the
+    /// same event ID.
+    pub fn push_events<I>(&mut self, events: I)
+    where
+        I: IntoIterator<Item = SyncTimelineEvent>,
+    {
+        let events = events.into_iter().collect::<Vec<_>>();
+        self.remove_duplicates(&events);
+        self.chunks.push_items_back(events);
+    }
+
+    /// Push a gap at the end.
+    pub fn push_gap(&mut self, gap: Gap) {
+        self.chunks.push_gap_back(gap);
+    }
+
+    /// Find the most recent gap with the given back-pagination token.
+    pub fn gap_with_token(&self, prev_token: &str) -> Option<ChunkIdentifier> {
+        self.chunks.chunk_identifier(|chunk| {
+            matches!(chunk.content(), ChunkContent::Gap(gap) if gap.prev_token == prev_token)
+        })
+    }
+
+    /// Insert events that have been back-paginated with the token of the given
+    /// gap, replacing that gap.
+    ///
+    /// `events` must be ordered from the oldest to the most recent one. If
+    /// `new_gap` is set, it is inserted before the events, as the history
+    /// continues further back.
+    pub fn replace_gap_at(
+        &mut self,
+        events: Vec<SyncTimelineEvent>,
+        new_gap: Option<Gap>,
+        gap_identifier: ChunkIdentifier,
+    ) -> Result<(), LinkedChunkError> {
+        if let Some(new_gap) = new_gap {
+            self.chunks.insert_gap_before(new_gap, gap_identifier)?;
+        }
+
+        self.remove_duplicates(&events);
+        self.chunks.replace_gap_at(events, gap_identifier)?;
+
         Ok(())
     }
+
+    /// Insert events that have been back-paginated from an unknown position,
+    /// at the beginning.
+    ///
+    /// `events` must be ordered from the oldest to the most recent one.
+    pub fn push_events_front(&mut self, events: Vec<SyncTimelineEvent>, new_gap: Option<Gap>) {
+        // A placeholder gap gives us a position to insert at, which is replaced right
+        // after.
+        let placeholder = self.chunks.push_gap_front(Gap { prev_token: String::new() });
+
+        self.replace_gap_at(events, new_gap, placeholder)
+            .expect("the placeholder gap has just been inserted");
+    }
+
+    /// Iterate over all the known events, from the oldest to the most recent
+    /// one, ignoring gaps.
+    pub fn events(&self) -> impl Iterator<Item = &SyncTimelineEvent> {
+        self.chunks.items().map(|(_, event)| event)
+    }
+
+    /// Iterate over the events since the most recent gap, from the oldest to
+    /// the most recent one.
+    ///
+    /// This is the part of the history that is known to be contiguous with the
+    /// live end of the room.
+    pub fn events_since_last_gap(&self) -> impl Iterator<Item = &SyncTimelineEvent> {
+        let last_gap_position =
+            self.chunks.chunks().rposition(|chunk| chunk.is_gap()).map_or(0, |pos| pos + 1);
+
+        self.chunks.chunks().skip(last_gap_position).flat_map(|chunk| match chunk.content() {
+            ChunkContent::Items(events) => events.as_slice(),
+            ChunkContent::Gap(_) => &[],
+        })
+    }
+
+    /// The most recent gap, if any.
+    pub fn last_gap(&self) -> Option<&Gap> {
+        self.chunks.chunks().rev().find_map(|chunk| match chunk.content() {
+            ChunkContent::Gap(gap) => Some(gap),
+            ChunkContent::Items(_) => None,
+        })
+    }
+
+    /// Remove the known events which have the same event ID as one of the
+    /// given events.
+    fn remove_duplicates(&mut self, events: &[SyncTimelineEvent]) {
+        let event_ids = events.iter().filter_map(|event| event.event_id()).collect::<HashSet<_>>();
+
+        if event_ids.is_empty() {
+            return;
+        }
+
+        let positions = self
+            .chunks
+            .items()
+            .filter(|(_, event)| event.event_id().is_some_and(|id| event_ids.contains(&id)))
+            .map(|(position, _)| position)
+            .collect::<Vec<_>>();
+
+        // Remove the most recent events first, so the positions of the other
+        // duplicates stay valid.
+        for position in positions.into_iter().rev() {
+            self.chunks.remove_item_at(position).expect("the position has just been found");
+        }
+    }
+}
+
+impl Default for RoomEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for RoomEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RoomEvents").field("chunks", &self.chunks).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_base::{
+        deserialized_responses::SyncTimelineEvent,
+        linked_chunk::{ChunkContent, ChunkIdentifier, RawChunk, Update},
+    };
+    use matrix_sdk_test::{sync_timeline_event, ALICE};
+    use ruma::{event_id, EventId, OwnedEventId};
+
+    use super::{Gap, RoomEvents};
+
+    fn event(event_id: &EventId) -> SyncTimelineEvent {
+        SyncTimelineEvent::new(sync_timeline_event!({
+            "content": { "body": "hello", "msgtype": "m.text" },
+            "event_id": event_id,
+            "origin_server_ts": 1,
+            "sender": *ALICE,
+            "type": "m.room.message",
+        }))
+    }
+
+    fn event_ids<'a>(events: impl Iterator<Item = &'a SyncTimelineEvent>) -> Vec<OwnedEventId> {
+        events.map(|event| event.event_id().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_push_events_deduplicates() {
+        let mut room_events = RoomEvents::new();
+
+        room_events.push_events([event(event_id!("$a")), event(event_id!("$b"))]);
+        room_events.push_gap(Gap { prev_token: "t".to_owned() });
+        room_events.push_events([event(event_id!("$a")), event(event_id!("$c"))]);
+
+        assert_eq!(
+            event_ids(room_events.events()),
+            [event_id!("$b"), event_id!("$a"), event_id!("$c")]
+        );
+        assert_eq!(
+            event_ids(room_events.events_since_last_gap()),
+            [event_id!("$a"), event_id!("$c")]
+        );
+    }
+
+    #[test]
+    fn test_replace_gap_fills_the_right_hole() {
+        let mut room_events = RoomEvents::new();
+
+        room_events.push_gap(Gap { prev_token: "old".to_owned() });
+        room_events.push_events([event(event_id!("$a"))]);
+        room_events.push_gap(Gap { prev_token: "recent".to_owned() });
+        room_events.push_events([event(event_id!("$d"))]);
+
+        let gap = room_events.gap_with_token("recent").unwrap();
+        room_events
+            .replace_gap_at(
+                vec![event(event_id!("$b")), event(event_id!("$c"))],
+                Some(Gap { prev_token: "further".to_owned() }),
+                gap,
+            )
+            .unwrap();
+
+        assert_eq!(
+            event_ids(room_events.events()),
+            [event_id!("$a"), event_id!("$b"), event_id!("$c"), event_id!("$d")]
+        );
+        assert_eq!(room_events.last_gap().unwrap().prev_token, "further");
+        assert!(room_events.gap_with_token("recent").is_none());
+        assert!(room_events.gap_with_token("old").is_some());
+    }
+
+    #[test]
+    fn test_updates_roundtrip() {
+        let mut room_events = RoomEvents::new();
+        assert!(room_events.is_empty());
+
+        room_events.push_gap(Gap { prev_token: "t".to_owned() });
+        room_events.push_events([event(event_id!("$a"))]);
+        room_events.push_events_front(vec![event(event_id!("$z"))], None);
+
+        // Apply the updates to raw chunks, like a store would.
+        let mut raw_chunks = Vec::<RawChunk<_, _>>::new();
+        for update in room_events.take_updates() {
+            match update {
+                Update::NewItemsChunk { previous, new, next } => {
+                    relink(&mut raw_chunks, previous, next, Some(new));
+                    raw_chunks.push(RawChunk {
+                        identifier: new,
+                        previous,
+                        next,
+                        content: ChunkContent::Items(Vec::new()),
+                    });
+                }
+                Update::NewGapChunk { previous, new, next, gap } => {
+                    relink(&mut raw_chunks, previous, next, Some(new));
+                    raw_chunks.push(RawChunk {
+                        identifier: new,
+                        previous,
+                        next,
+                        content: ChunkContent::Gap(gap),
+                    });
+                }
+                Update::RemoveChunk(identifier) => {
+                    let index =
+                        raw_chunks.iter().position(|raw| raw.identifier == identifier).unwrap();
+                    let raw = raw_chunks.remove(index);
+                    relink(&mut raw_chunks, raw.previous, raw.next, None);
+                }
+                Update::PushItems { at, items } => {
+                    let raw = raw_chunks
+                        .iter_mut()
+                        .find(|raw| raw.identifier == at.chunk_identifier())
+                        .unwrap();
+                    let ChunkContent::Items(raw_items) = &mut raw.content else {
+                        panic!("items can only be pushed in a chunk of items");
+                    };
+                    raw_items.splice(at.index()..at.index(), items);
+                }
+                update => panic!("unexpected update: {update:?}"),
+            }
+        }
+
+        let restored = RoomEvents::from_raw_chunks(raw_chunks).unwrap();
+        assert_eq!(event_ids(restored.events()), [event_id!("$z"), event_id!("$a")]);
+        assert_eq!(restored.last_gap().unwrap().prev_token, "t");
+    }
+
+    fn relink(
+        raw_chunks: &mut [RawChunk<SyncTimelineEvent, Gap>],
+        previous: Option<ChunkIdentifier>,
+        next: Option<ChunkIdentifier>,
+        new: Option<ChunkIdentifier>,
+    ) {
+        for raw in raw_chunks.iter_mut() {
+            if Some(raw.identifier) == previous {
+                raw.next = new.or(next);
+            }
+            if Some(raw.identifier) == next {
+                raw.previous = new.or(previous);
+            }
+        }
+    }
 }
@@ -201,7 +201,8 @@ pub struct TransmissionProgress {
 
 async fn response_to_http_response(
     mut response: reqwest::Response,
-) -> Result<http::Response<Bytes>, reqwest::Error> {
+    max_size: Option<usize>,
+) -> Result<http::Response<Bytes>, HttpError> {
     let status = response.status();
 
     let mut http_builder = http::Response::builder().status(status);
@@ -213,11 +214,56 @@ async fn response_to_http_response(
         }
     }
 
-    let body = response.bytes().await?;
+    let body = match max_size {
+        Some(max_size) => read_bounded_body(response, max_size).await?,
+        None => response.bytes().await?,
+    };
 
     Ok(http_builder.body(body).expect("Can't construct a response using the given body"))
 }
 
+/// Read the body of the given response, failing as soon as it is known to be
+/// larger than `max_size`, so it is never fully loaded in memory.
+async fn read_bounded_body(
+    #[allow(unused_mut)] mut response: reqwest::Response,
+    max_size: usize,
+) -> Result<Bytes, HttpError> {
+    let too_large = || HttpError::ResponseTooLarge { max_size };
+
+    if response.content_length().is_some_and(|length| length > max_size as u64) {
+        return Err(too_large());
+    }
+
+    // The body can't be read by chunks on WebAssembly.
+    #[cfg(target_arch = "wasm32")]
+    let body = {
+        let body = response.bytes().await?;
+
+        if body.len() > max_size {
+            return Err(too_large());
+        }
+
+        body
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let body = {
+        let mut body = BytesMut::new();
+
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > max_size {
+                return Err(too_large());
+            }
+
+            body.extend_from_slice(&chunk);
+        }
+
+        body.freeze()
+    };
+
+    Ok(body)
+}
+
 #[cfg(feature = "experimental-oidc")]
 impl tower::Service<http::Request<Bytes>> for HttpClient {
     type Response = http::Response<Bytes>;
@@ -235,7 +281,7 @@ impl tower::Service<http::Request<Bytes>> for HttpClient {
         let inner = self.inner.clone();
 
         let fut = async move {
-            native::send_request(&inner, &req, DEFAULT_REQUEST_TIMEOUT, Default::default())
+            native::send_request(&inner, &req, DEFAULT_REQUEST_TIMEOUT, None, Default::default())
                 .await
                 .map_err(Into::into)
         };
@@ -23,12 +23,12 @@ use backoff::{future::retry, Error as RetryError, ExponentialBackoff};
 use bytes::Bytes;
 use bytesize::ByteSize;
 use eyeball::SharedObservable;
-use http::header::CONTENT_LENGTH;
+use http::header::{CONTENT_LENGTH, RANGE};
 use reqwest::Certificate;
 use ruma::api::{
     client::error::{ErrorBody as ClientApiErrorBody, ErrorKind as ClientApiErrorKind},
     error::FromHttpResponseError,
-    IncomingResponse, OutgoingRequest,
+    AuthScheme, IncomingResponse, MatrixVersion, OutgoingRequest,
 };
 use tracing::{info, warn};
 
@@ -93,9 +93,15 @@ impl HttpClient {
                     }
                 };
 
-                let response = send_request(&self.inner, &request, config.timeout, send_progress)
-                    .await
-                    .map_err(error_type)?;
+                let response = send_request(
+                    &self.inner,
+                    &request,
+                    config.timeout,
+                    config.max_response_size,
+                    send_progress,
+                )
+                .await
+                .map_err(error_type)?;
 
                 let status_code = response.status();
                 let response_size = ByteSize(response.body().len().try_into().unwrap_or(u64::MAX));
@@ -123,6 +129,97 @@ impl HttpClient {
 
         retry::<_, HttpError, _, _, _>(backoff, send_request).await
     }
+
+    /// Send the given request, with its body replaced by the given stream.
+    ///
+    /// The request is never retried, since the stream can only be consumed
+    /// once.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn send_with_body_stream<R>(
+        &self,
+        request: R,
+        body: reqwest::Body,
+        content_length: u64,
+        config: Option<RequestConfig>,
+        homeserver: String,
+        access_token: Option<&str>,
+        server_versions: &[MatrixVersion],
+    ) -> Result<R::IncomingResponse, HttpError>
+    where
+        R: OutgoingRequest + Debug,
+        HttpError: From<FromHttpResponseError<R::EndpointError>>,
+    {
+        let auth_scheme = R::METADATA.authentication;
+        if !matches!(auth_scheme, AuthScheme::AccessToken | AuthScheme::None) {
+            return Err(HttpError::NotClientRequest);
+        }
+
+        let config = config.unwrap_or(self.request_config);
+        let request =
+            self.serialize_request(request, config, homeserver, access_token, server_versions)?;
+
+        let mut request = reqwest::Request::try_from(request.map(|_| body))?;
+        // The body is streamed, so reqwest / hyper can't know how large it is.
+        request.headers_mut().insert(CONTENT_LENGTH, content_length.into());
+        *request.timeout_mut() = Some(config.timeout);
+
+        let response =
+            response_to_http_response(self.inner.execute(request).await?, config.max_response_size)
+                .await?;
+        Ok(R::IncomingResponse::try_from_http_response(response)?)
+    }
+
+    /// Send the given request, and return the response without reading its
+    /// body, so it can be streamed.
+    ///
+    /// If `range_start` is not zero, only the bytes of the body from this
+    /// offset are requested. The server might ignore it and return the whole
+    /// body, with a `200 OK` status instead of `206 Partial Content`.
+    ///
+    /// Returns an error if the server responded with an error status.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn send_for_body_stream<R>(
+        &self,
+        request: R,
+        range_start: u64,
+        config: Option<RequestConfig>,
+        homeserver: String,
+        access_token: Option<&str>,
+        server_versions: &[MatrixVersion],
+    ) -> Result<reqwest::Response, HttpError>
+    where
+        R: OutgoingRequest + Debug,
+        HttpError: From<FromHttpResponseError<R::EndpointError>>,
+    {
+        let auth_scheme = R::METADATA.authentication;
+        if !matches!(auth_scheme, AuthScheme::AccessToken | AuthScheme::None) {
+            return Err(HttpError::NotClientRequest);
+        }
+
+        let config = config.unwrap_or(self.request_config);
+        let request =
+            self.serialize_request(request, config, homeserver, access_token, server_versions)?;
+
+        let mut request = reqwest::Request::try_from(request)?;
+        if range_start > 0 {
+            let range = format!("bytes={range_start}-").try_into().expect("valid header value");
+            request.headers_mut().insert(RANGE, range);
+        }
+        *request.timeout_mut() = Some(config.timeout);
+
+        let response = self.inner.execute(request).await?;
+
+        if let Err(error) = response.error_for_status_ref() {
+            // Error bodies are small, let ruma parse them.
+            let response = response_to_http_response(response, None).await?;
+            R::IncomingResponse::try_from_http_response(response)?;
+            // Ruma should never accept an error status, fall back to the
+            // generic HTTP error if it did.
+            return Err(error.into());
+        }
+
+        Ok(response)
+    }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -185,6 +282,7 @@ pub(super) async fn send_request(
     client: &reqwest::Client,
     request: &http::Request<Bytes>,
     timeout: Duration,
+    max_response_size: Option<usize>,
     send_progress: SharedObservable<TransmissionProgress>,
 ) -> Result<http::Response<Bytes>, HttpError> {
     use std::convert::Infallible;
@@ -227,7 +325,7 @@ pub(super) async fn send_request(
     };
 
     let response = client.execute(request).await?;
-    Ok(response_to_http_response(response).await?)
+    response_to_http_response(response, max_response_size).await
 }
 
 // Clones all request parts except the extensions which can't be cloned.
@@ -26,7 +26,7 @@ impl HttpClient {
     pub(super) async fn send_request<R>(
         &self,
         request: http::Request<Bytes>,
-        _config: RequestConfig,
+        config: RequestConfig,
         _send_progress: SharedObservable<TransmissionProgress>,
     ) -> Result<R::IncomingResponse, HttpError>
     where
@@ -34,7 +34,9 @@ impl HttpClient {
         HttpError: From<FromHttpResponseError<R::EndpointError>>,
     {
         let request = reqwest::Request::try_from(request)?;
-        let response = response_to_http_response(self.inner.execute(request).await?).await?;
+        let response =
+            response_to_http_response(self.inner.execute(request).await?, config.max_response_size)
+                .await?;
 
         let status_code = response.status();
         let response_size = ByteSize(response.body().len().try_into().unwrap_or(u64::MAX));
@@ -23,9 +23,9 @@ pub use matrix_sdk_base::crypto;
 pub use matrix_sdk_base::{
     deserialized_responses,
     store::{DynStateStore, MemoryStore, StateStoreExt},
-    DisplayName, Room as BaseRoom, RoomCreateWithCreatorEventContent, RoomInfo,
+    DisplayName, PredecessorRoom, Room as BaseRoom, RoomCreateWithCreatorEventContent, RoomInfo,
     RoomMember as BaseRoomMember, RoomMemberships, RoomState, SessionMeta, StateChanges,
-    StateStore, StoreError,
+    StateStore, StoreError, SuccessorRoom,
 };
 pub use matrix_sdk_common::*;
 pub use reqwest;
@@ -47,7 +47,11 @@ pub mod media;
 pub mod notification_settings;
 #[cfg(feature = "experimental-oidc")]
 pub mod oidc;
+pub mod pushers;
 pub mod room;
+pub mod search;
+pub mod send_queue;
+pub mod space;
 pub mod utils;
 pub mod futures {
     //! Named futures returned from methods on types in [the crate root][crate].
@@ -57,6 +61,7 @@ pub mod futures {
 #[cfg(feature = "experimental-sliding-sync")]
 pub mod sliding_sync;
 pub mod sync;
+pub mod uiaa;
 #[cfg(feature = "experimental-widgets")]
 pub mod widget;
 
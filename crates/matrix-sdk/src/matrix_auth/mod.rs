@@ -35,6 +35,7 @@ use ruma::{
         },
         OutgoingRequest, SendAccessToken,
     },
+    assign,
     serde::JsonObject,
 };
 use serde::{Deserialize, Serialize};
@@ -44,6 +45,7 @@ use crate::{
     authentication::AuthData,
     client::SessionChange,
     error::{HttpError, HttpResult},
+    uiaa::{UiaaDriver, UiaaHandler},
     Client, Error, RefreshTokenError, Result,
 };
 
@@ -470,7 +472,7 @@ impl MatrixAuth {
         };
 
         let request = refresh_token::v3::Request::new(refresh_token);
-        let res = self.client.send_inner(request, None, None, Default::default()).await;
+        let res = self.client.send_inner(request, None, None, None, Default::default()).await;
 
         match res {
             Ok(res) => {
@@ -569,10 +571,36 @@ impl MatrixAuth {
         }
         Ok(response)
     }
+
+    /// Register a user to the server, completing the
+    /// [User-Interactive Authentication][uiaa] with the given handler.
+    ///
+    /// The `auth` field of the request is ignored. See
+    /// [`MatrixAuth::register()`] for more details.
+    ///
+    /// [uiaa]: https://spec.matrix.org/v1.2/client-server-api/#user-interactive-authentication-api
+    pub async fn register_with_uiaa(
+        &self,
+        request: register::v3::Request,
+        handler: &impl UiaaHandler,
+    ) -> Result<register::v3::Response> {
+        UiaaDriver::new(&self.client, handler)
+            .run(|auth| self.register(assign!(request.clone(), { auth })))
+            .await
+    }
+
     /// Log out the current user.
+    ///
+    /// On success, the local search index of the event cache is cleared.
     pub async fn logout(&self) -> HttpResult<logout::v3::Response> {
         let request = logout::v3::Request::new();
-        self.client.send(request, None).await
+        let response = self.client.send(request, None).await?;
+
+        if let Err(err) = self.client.event_cache().clear_search_index().await {
+            error!("Failed to clear the local search index after logging out: {err}");
+        }
+
+        Ok(response)
     }
 
     /// Get the current access token and optional refresh token for this
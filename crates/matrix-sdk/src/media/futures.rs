@@ -0,0 +1,442 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Named futures returned from methods on types in [the `media` module][super].
+
+#![deny(unreachable_pub)]
+
+use std::{
+    fmt,
+    future::IntoFuture,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+#[cfg(feature = "e2e-encryption")]
+use bytes::BytesMut;
+use eyeball::{SharedObservable, Subscriber};
+use futures_core::Stream;
+use futures_util::{stream::BoxStream, StreamExt, TryStreamExt};
+use matrix_sdk_common::boxed_into_future;
+use mime::Mime;
+use reqwest::StatusCode;
+use ruma::api::client::media::create_content;
+#[cfg(feature = "e2e-encryption")]
+use tokio::{fs::File, io::AsyncReadExt};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt,
+};
+use tracing::debug;
+
+use super::{ByteStream, MediaRequest};
+use crate::{Client, HttpError, HttpResult, Result, RumaApiError, TransmissionProgress};
+
+/// The size of the chunks read from disk when decrypting a downloaded file.
+#[cfg(feature = "e2e-encryption")]
+const DECRYPTION_CHUNK_SIZE: usize = 8192;
+
+/// Future returned by [`Media::upload_stream`] and [`Media::upload_reader`].
+///
+/// [`Media::upload_stream`]: super::Media::upload_stream
+/// [`Media::upload_reader`]: super::Media::upload_reader
+pub struct UploadStream {
+    client: Client,
+    content_type: Mime,
+    stream: ByteStream,
+    size: u64,
+    send_progress: SharedObservable<TransmissionProgress>,
+}
+
+impl UploadStream {
+    pub(crate) fn new(client: Client, content_type: Mime, stream: ByteStream, size: u64) -> Self {
+        Self { client, content_type, stream, size, send_progress: Default::default() }
+    }
+
+    /// Replace the default `SharedObservable` used for tracking upload
+    /// progress.
+    ///
+    /// Note that any subscribers obtained from
+    /// [`subscribe_to_send_progress`][Self::subscribe_to_send_progress]
+    /// will be invalidated by this.
+    pub fn with_send_progress_observable(
+        mut self,
+        send_progress: SharedObservable<TransmissionProgress>,
+    ) -> Self {
+        self.send_progress = send_progress;
+        self
+    }
+
+    /// Get a subscriber to observe the progress of sending the request
+    /// body.
+    pub fn subscribe_to_send_progress(&self) -> Subscriber<TransmissionProgress> {
+        self.send_progress.subscribe()
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for UploadStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UploadStream")
+            .field("content_type", &self.content_type)
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl IntoFuture for UploadStream {
+    type Output = HttpResult<create_content::v3::Response>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { client, content_type, stream, size, send_progress } = self;
+
+        Box::pin(async move {
+            let total = usize::try_from(size).unwrap_or(usize::MAX);
+            let mut current = 0;
+            let stream = stream.map_ok(move |chunk| {
+                current += chunk.len();
+                send_progress.set(TransmissionProgress { current, total });
+                chunk
+            });
+
+            // The body is replaced by the stream when sending the request.
+            let request = ruma::assign!(create_content::v3::Request::new(Vec::new()), {
+                content_type: Some(content_type.essence_str().to_owned()),
+            });
+            let request_config = client.request_config().timeout(super::upload_timeout(size));
+
+            client
+                .send_with_body_stream(
+                    request,
+                    reqwest::Body::wrap_stream(stream),
+                    size,
+                    Some(request_config),
+                )
+                .await
+        })
+    }
+}
+
+/// A stream of the content of a media file, returned by
+/// [`Media::get_media_stream`].
+///
+/// If the media is encrypted and encryption is enabled, the content is
+/// decrypted on the fly. In that case, the integrity of the whole file is
+/// only verified at the end of the stream, which yields an error if the
+/// check fails. Any data received before that should be considered as
+/// untrusted.
+///
+/// [`Media::get_media_stream`]: super::Media::get_media_stream
+pub struct MediaStream {
+    size: Option<u64>,
+    inner: BoxStream<'static, Result<Bytes>>,
+}
+
+impl MediaStream {
+    /// The size of the media in bytes, if the server provided it.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for MediaStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MediaStream").field("size", &self.size).finish_non_exhaustive()
+    }
+}
+
+impl Stream for MediaStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Future returned by [`Media::get_media_stream`].
+///
+/// [`Media::get_media_stream`]: super::Media::get_media_stream
+pub struct GetMediaStream {
+    client: Client,
+    request: MediaRequest,
+    progress: SharedObservable<TransmissionProgress>,
+}
+
+impl GetMediaStream {
+    pub(crate) fn new(client: Client, request: MediaRequest) -> Self {
+        Self { client, request, progress: Default::default() }
+    }
+
+    /// Replace the default `SharedObservable` used for tracking download
+    /// progress.
+    ///
+    /// Note that any subscribers obtained from
+    /// [`subscribe_to_progress`][Self::subscribe_to_progress] will be
+    /// invalidated by this.
+    pub fn with_progress_observable(
+        mut self,
+        progress: SharedObservable<TransmissionProgress>,
+    ) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Get a subscriber to observe the progress of receiving the media.
+    ///
+    /// The progress is updated as the returned [`MediaStream`] is consumed.
+    pub fn subscribe_to_progress(&self) -> Subscriber<TransmissionProgress> {
+        self.progress.subscribe()
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for GetMediaStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GetMediaStream").field("request", &self.request).finish_non_exhaustive()
+    }
+}
+
+impl IntoFuture for GetMediaStream {
+    type Output = Result<MediaStream>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { client, request, progress } = self;
+
+        Box::pin(async move {
+            let response = client.media().send_media_stream_request(&request, 0).await?;
+            let size = response.content_length();
+            let total = size.and_then(|size| usize::try_from(size).ok()).unwrap_or_default();
+
+            #[cfg(feature = "e2e-encryption")]
+            let mut decryptor = match &request.source {
+                ruma::events::room::MediaSource::Encrypted(file) => {
+                    Some(matrix_sdk_base::crypto::AttachmentChunkDecryptor::new(
+                        file.as_ref().clone().into(),
+                    )?)
+                }
+                ruma::events::room::MediaSource::Plain(_) => None,
+            };
+
+            let inner = async_stream::stream! {
+                let mut body = response.bytes_stream();
+                let mut current = 0;
+
+                while let Some(chunk) = body.next().await {
+                    let chunk = match chunk {
+                        Ok(chunk) => chunk,
+                        Err(error) => {
+                            yield Err(HttpError::from(error).into());
+                            return;
+                        }
+                    };
+
+                    current += chunk.len();
+                    progress.set(TransmissionProgress { current, total: total.max(current) });
+
+                    #[cfg(feature = "e2e-encryption")]
+                    let chunk = match &mut decryptor {
+                        Some(decryptor) => {
+                            let mut chunk = BytesMut::from(&chunk[..]);
+                            decryptor.decrypt_chunk(&mut chunk);
+                            chunk.freeze()
+                        }
+                        None => chunk,
+                    };
+
+                    yield Ok(chunk);
+                }
+
+                #[cfg(feature = "e2e-encryption")]
+                if let Some(decryptor) = decryptor {
+                    if let Err(error) = decryptor.finish() {
+                        yield Err(error.into());
+                    }
+                }
+            };
+
+            Ok(MediaStream { size, inner: inner.boxed() })
+        })
+    }
+}
+
+/// Future returned by [`Media::download_to_file`].
+///
+/// [`Media::download_to_file`]: super::Media::download_to_file
+pub struct DownloadToFile {
+    client: Client,
+    request: MediaRequest,
+    path: PathBuf,
+    progress: SharedObservable<TransmissionProgress>,
+}
+
+impl DownloadToFile {
+    pub(crate) fn new(client: Client, request: MediaRequest, path: PathBuf) -> Self {
+        Self { client, request, path, progress: Default::default() }
+    }
+
+    /// Replace the default `SharedObservable` used for tracking download
+    /// progress.
+    ///
+    /// Note that any subscribers obtained from
+    /// [`subscribe_to_progress`][Self::subscribe_to_progress] will be
+    /// invalidated by this.
+    pub fn with_progress_observable(
+        mut self,
+        progress: SharedObservable<TransmissionProgress>,
+    ) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Get a subscriber to observe the progress of the download.
+    ///
+    /// When resuming a download, the progress starts at the number of bytes
+    /// that were already downloaded.
+    pub fn subscribe_to_progress(&self) -> Subscriber<TransmissionProgress> {
+        self.progress.subscribe()
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for DownloadToFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DownloadToFile")
+            .field("request", &self.request)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl IntoFuture for DownloadToFile {
+    type Output = Result<()>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { client, request, path, progress } = self;
+
+        Box::pin(async move {
+            let part_path = part_file_path(&path);
+            let mut part_file =
+                OpenOptions::new().create(true).append(true).open(&part_path).await?;
+            let mut downloaded = part_file.metadata().await?.len();
+
+            let response =
+                match client.media().send_media_stream_request(&request, downloaded).await {
+                    Ok(response) => Some(response),
+                    // The part file already contains the whole file.
+                    Err(error) if downloaded > 0 && is_range_not_satisfiable(&error) => None,
+                    Err(error) => return Err(error.into()),
+                };
+
+            if let Some(response) = response {
+                if downloaded > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+                    debug!("The server doesn't support resuming downloads, starting over");
+                    part_file.set_len(0).await?;
+                    downloaded = 0;
+                }
+
+                let total =
+                    response.content_length().map_or(0, |length| downloaded.saturating_add(length));
+                let total = usize::try_from(total).unwrap_or(usize::MAX);
+                let mut current = usize::try_from(downloaded).unwrap_or(usize::MAX);
+
+                let mut body = response.bytes_stream();
+                while let Some(chunk) = body.next().await {
+                    let chunk = chunk.map_err(HttpError::from)?;
+                    part_file.write_all(&chunk).await?;
+
+                    current += chunk.len();
+                    progress.set(TransmissionProgress { current, total: total.max(current) });
+                }
+
+                part_file.sync_all().await?;
+            }
+
+            drop(part_file);
+
+            #[cfg(feature = "e2e-encryption")]
+            if let ruma::events::room::MediaSource::Encrypted(file) = &request.source {
+                let result = decrypt_file(&part_path, &path, file.as_ref().clone().into()).await;
+
+                if result.is_err() {
+                    // Don't leave a corrupted file behind, and start over on the next try.
+                    let _ = fs::remove_file(&path).await;
+                }
+                fs::remove_file(&part_path).await?;
+
+                return result;
+            }
+
+            Ok(fs::rename(&part_path, &path).await?)
+        })
+    }
+}
+
+/// The path of the file where the raw data is downloaded before it is moved
+/// to `path`.
+fn part_file_path(path: &Path) -> PathBuf {
+    let mut part_path = path.as_os_str().to_owned();
+    part_path.push(".part");
+    part_path.into()
+}
+
+/// Whether the given error is a `416 Range Not Satisfiable` status.
+fn is_range_not_satisfiable(error: &HttpError) -> bool {
+    let status_code = match error.as_ruma_api_error() {
+        Some(RumaApiError::ClientApi(error)) => error.status_code,
+        Some(RumaApiError::Other(error)) => error.status_code,
+        _ => return false,
+    };
+
+    status_code == StatusCode::RANGE_NOT_SATISFIABLE
+}
+
+/// Decrypt the file at `source` chunk by chunk into a file at `destination`.
+#[cfg(feature = "e2e-encryption")]
+async fn decrypt_file(
+    source: &Path,
+    destination: &Path,
+    info: matrix_sdk_base::crypto::MediaEncryptionInfo,
+) -> Result<()> {
+    let mut decryptor = matrix_sdk_base::crypto::AttachmentChunkDecryptor::new(info)?;
+
+    let mut source = File::open(source).await?;
+    let mut destination = File::create(destination).await?;
+    let mut buf = vec![0; DECRYPTION_CHUNK_SIZE];
+
+    loop {
+        let read = source.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+
+        let chunk = &mut buf[..read];
+        decryptor.decrypt_chunk(chunk);
+        destination.write_all(chunk).await?;
+    }
+
+    decryptor.finish()?;
+    destination.sync_all().await?;
+
+    Ok(())
+}
@@ -0,0 +1,1354 @@
+// Copyright 2021 Kévin Commaille
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! High-level media API.
+
+#[cfg(feature = "e2e-encryption")]
+use std::io::Read;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    fmt,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+use std::{future::Future, sync::RwLock as StdRwLock, time::Duration};
+
+#[cfg(not(target_arch = "wasm32"))]
+use bytes::Bytes;
+use eyeball::SharedObservable;
+#[cfg(not(target_arch = "wasm32"))]
+use futures_core::Stream;
+use futures_util::future::try_join;
+use http::StatusCode;
+pub use matrix_sdk_base::{
+    media::*,
+    media_cache_store::{MediaCachePolicy, MediaCacheStats, MediaEvictionPolicy},
+};
+use matrix_sdk_common::instant::Instant;
+use mime::Mime;
+use ruma::{
+    api::{
+        client::{
+            authenticated_media,
+            error::ErrorKind,
+            media::{create_content, get_content, get_content_thumbnail, get_media_preview},
+        },
+        MatrixVersion, OutgoingRequest, SendAccessToken,
+    },
+    assign,
+    events::room::{
+        message::{
+            AudioInfo, AudioMessageEventContent, FileInfo, FileMessageEventContent,
+            ImageMessageEventContent, MessageType, UnstableAudioDetailsContentBlock,
+            UnstableVoiceContentBlock, VideoInfo, VideoMessageEventContent,
+        },
+        ImageInfo, MediaSource, ThumbnailInfo,
+    },
+    MilliSecondsSinceUnixEpoch, MxcUri, OwnedMxcUri, UInt,
+};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+#[cfg(not(target_arch = "wasm32"))]
+use tempfile::{Builder as TempFileBuilder, NamedTempFile, TempDir};
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::{
+    fs::File as TokioFile,
+    io::{AsyncRead, AsyncWriteExt},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use tokio_util::io::ReaderStream;
+use tracing::{debug, warn};
+use url::Url;
+
+#[cfg(not(target_arch = "wasm32"))]
+use self::futures::{DownloadToFile, GetMediaStream, UploadStream};
+use crate::{
+    attachment::{AttachmentData, AttachmentInfo, Thumbnail},
+    futures::SendRequest,
+    Client, Error, HttpError, Result, TransmissionProgress,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod futures;
+
+/// A conservative upload speed of 1Mbps
+const DEFAULT_UPLOAD_SPEED: u64 = 125_000;
+/// 5 min minimal upload request timeout, used to clamp the request timeout.
+const MIN_UPLOAD_REQUEST_TIMEOUT: Duration = Duration::from_secs(60 * 5);
+/// The request timeout of streamed downloads, which can be very large.
+#[cfg(not(target_arch = "wasm32"))]
+const MEDIA_STREAM_REQUEST_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+/// The interval after which the media cache is cleaned up when a file is
+/// added even if it isn't full, to evict the expired files.
+const CACHE_CLEAN_UP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The key of the custom value of the state store holding the
+/// [`MediaCachePolicy`].
+const CACHE_POLICY_KEY: &[u8] = b"media_cache_policy";
+
+/// The prefix of the keys of the custom values of the state store holding the
+/// media files pinned for an owner by [`Media::get_pinned_media_content`].
+const PINNED_MEDIA_KEY_PREFIX: &str = "media_pinned";
+
+/// The state of the automatic clean-up of the media cache.
+#[derive(Debug, Default)]
+pub(crate) struct CacheCleanUpState {
+    /// An upper bound of the size of the media cache, if it is known.
+    ///
+    /// This is the size after the last clean-up, plus the size of the files
+    /// added since then.
+    size: Option<usize>,
+
+    /// When the media cache was last cleaned up.
+    cleaned_up_at: Option<Instant>,
+}
+
+/// Build the request to download the given media with the endpoints of the
+/// given [`MediaUrlPolicy`], and evaluate `$send` with it.
+///
+/// The endpoints don't have the same request type, so this can't be a
+/// function.
+macro_rules! with_download_request {
+    (($policy:expr, $uri:expr, $thumbnail_size:expr), |$request:ident| $send:expr) => {
+        match ($policy, $thumbnail_size) {
+            (MediaUrlPolicy::Authenticated, None) => {
+                let $request = authenticated_media::get_content::v1::Request::from_uri($uri)?;
+                $send
+            }
+            (MediaUrlPolicy::Authenticated, Some(size)) => {
+                let $request = authenticated_media::get_content_thumbnail::v1::Request::from_uri(
+                    $uri,
+                    size.width,
+                    size.height,
+                )?;
+                $send
+            }
+            (MediaUrlPolicy::Unauthenticated, None) => {
+                let $request = get_content::v3::Request::from_url($uri)?;
+                $send
+            }
+            (MediaUrlPolicy::Unauthenticated, Some(size)) => {
+                let $request =
+                    get_content_thumbnail::v3::Request::from_url($uri, size.width, size.height)?;
+                $send
+            }
+        }
+    };
+}
+
+/// A stream of bytes that can be used as the body of a request.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) type ByteStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send + Sync>>;
+
+/// The endpoints used to download media from the homeserver.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaUrlPolicy {
+    /// The authenticated media endpoints, under `/_matrix/client/v1/media`.
+    ///
+    /// Requests to these endpoints need the access token of the user in the
+    /// `Authorization` header.
+    Authenticated,
+
+    /// The legacy endpoints, under `/_matrix/media/v3`, that don't require
+    /// authentication.
+    Unauthenticated,
+}
+
+impl MediaUrlPolicy {
+    /// Whether requests with this policy need the access token of the user.
+    pub fn requires_authentication(&self) -> bool {
+        matches!(self, Self::Authenticated)
+    }
+}
+
+/// The preview of a URL, as returned by the homeserver.
+///
+/// This contains the [OpenGraph] data of the page that the homeserver
+/// understands. All the fields are optional because the page might not
+/// provide them.
+///
+/// [OpenGraph]: https://ogp.me/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct UrlPreview {
+    /// The canonical URL of the page, from `og:url`.
+    pub url: Option<String>,
+
+    /// The title of the page, from `og:title`.
+    pub title: Option<String>,
+
+    /// The description of the page, from `og:description`.
+    pub description: Option<String>,
+
+    /// The name of the website, from `og:site_name`.
+    pub site_name: Option<String>,
+
+    /// The image of the page, re-hosted by the homeserver, from `og:image`.
+    pub image: Option<OwnedMxcUri>,
+
+    /// The width of the image, in pixels, from `og:image:width`.
+    pub image_width: Option<UInt>,
+
+    /// The height of the image, in pixels, from `og:image:height`.
+    pub image_height: Option<UInt>,
+
+    /// The size of the image, in bytes, from `matrix:image:size`.
+    pub image_size: Option<UInt>,
+
+    /// The MIME type of the image, from `og:image:type`.
+    pub image_type: Option<String>,
+}
+
+impl UrlPreview {
+    /// Parse the JSON object returned by the homeserver.
+    ///
+    /// Invalid fields are ignored, because the data comes from arbitrary web
+    /// pages.
+    fn from_json(json: &[u8]) -> Result<Self> {
+        let map: JsonMap<String, JsonValue> = serde_json::from_slice(json)?;
+
+        let string = |key: &str| {
+            map.get(key)
+                .and_then(JsonValue::as_str)
+                .filter(|s| !s.is_empty())
+                .map(ToOwned::to_owned)
+        };
+        // Some homeservers send the dimensions as strings.
+        let uint = |key: &str| match map.get(key)? {
+            JsonValue::Number(n) => n.as_u64().and_then(|n| UInt::try_from(n).ok()),
+            JsonValue::String(s) => s.parse().ok(),
+            _ => None,
+        };
+
+        Ok(Self {
+            url: string("og:url"),
+            title: string("og:title"),
+            description: string("og:description"),
+            site_name: string("og:site_name"),
+            image: string("og:image").map(Into::into).filter(|uri: &OwnedMxcUri| uri.is_valid()),
+            image_width: uint("og:image:width"),
+            image_height: uint("og:image:height"),
+            image_size: uint("matrix:image:size"),
+            image_type: string("og:image:type"),
+        })
+    }
+}
+
+/// A high-level API to interact with the media API.
+#[derive(Debug, Clone)]
+pub struct Media {
+    /// The underlying HTTP client.
+    client: Client,
+}
+
+/// A file handle that takes ownership of a media file on disk. When the handle
+/// is dropped, the file will be removed from the disk.
+#[derive(Debug)]
+#[cfg(not(target_arch = "wasm32"))]
+pub struct MediaFileHandle {
+    /// The temporary file that contains the media.
+    file: NamedTempFile,
+    /// An intermediary temporary directory used in certain cases.
+    ///
+    /// Only stored for its `Drop` semantics.
+    _directory: Option<TempDir>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl MediaFileHandle {
+    /// Get the media file's path.
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// Persist the media file to the given path.
+    pub fn persist(self, path: &Path) -> Result<File, PersistError> {
+        self.file.persist(path).map_err(|e| PersistError {
+            error: e.error,
+            file: Self { file: e.file, _directory: self._directory },
+        })
+    }
+}
+
+/// Error returned when [`MediaFileHandle::persist`] fails.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct PersistError {
+    /// The underlying IO error.
+    pub error: io::Error,
+    /// The temporary file that couldn't be persisted.
+    pub file: MediaFileHandle,
+}
+
+#[cfg(not(any(target_arch = "wasm32", tarpaulin_include)))]
+impl fmt::Debug for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PersistError({:?})", self.error)
+    }
+}
+
+#[cfg(not(any(target_arch = "wasm32", tarpaulin_include)))]
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to persist temporary file: {}", self.error)
+    }
+}
+
+/// `IntoFuture` returned by [`Media::upload`].
+pub type SendUploadRequest = SendRequest<create_content::v3::Request>;
+
+impl Media {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// Upload some media to the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_type` - The type of the media, this will be used as the
+    /// content-type header.
+    ///
+    /// * `reader` - A `Reader` that will be used to fetch the raw bytes of the
+    /// media.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::fs;
+    /// # use matrix_sdk::{Client, ruma::room_id};
+    /// # use url::Url;
+    /// # use mime;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let mut client = Client::new(homeserver).await?;
+    /// let image = fs::read("/home/example/my-cat.jpg")?;
+    ///
+    /// let response = client.media().upload(&mime::IMAGE_JPEG, image).await?;
+    ///
+    /// println!("Cat URI: {}", response.content_uri);
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn upload(&self, content_type: &Mime, data: Vec<u8>) -> SendUploadRequest {
+        let timeout = upload_timeout(data.len() as u64);
+
+        let request = assign!(create_content::v3::Request::new(data), {
+            content_type: Some(content_type.essence_str().to_owned()),
+        });
+
+        let request_config = self.client.request_config().timeout(timeout);
+        self.client.send(request, Some(request_config))
+    }
+
+    /// Upload some media to the server, streaming it from the given stream
+    /// instead of holding it in memory.
+    ///
+    /// The upload can't be resumed, if it fails it needs to start over with a
+    /// new stream. It can be cancelled by dropping the returned future.
+    ///
+    /// # Arguments
+    ///
+    /// * `content_type` - The type of the media, this will be used as the
+    /// content-type header.
+    ///
+    /// * `stream` - The stream of the raw bytes of the media.
+    ///
+    /// * `size` - The total size of the media in bytes. It must match the
+    /// number of bytes produced by the stream.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn upload_stream(
+        &self,
+        content_type: &Mime,
+        stream: impl Stream<Item = io::Result<Bytes>> + Send + Sync + 'static,
+        size: u64,
+    ) -> UploadStream {
+        UploadStream::new(self.client.clone(), content_type.clone(), Box::pin(stream), size)
+    }
+
+    /// Upload some media to the server, reading it from the given reader
+    /// instead of holding it in memory.
+    ///
+    /// This is a convenience method that calls
+    /// [`upload_stream()`](Self::upload_stream).
+    ///
+    /// # Arguments
+    ///
+    /// * `content_type` - The type of the media, this will be used as the
+    /// content-type header.
+    ///
+    /// * `reader` - The reader of the raw bytes of the media.
+    ///
+    /// * `size` - The total size of the media in bytes. It must match the
+    /// number of bytes produced by the reader.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let file = tokio::fs::File::open("/home/example/my-cat-video.mp4").await?;
+    /// let size = file.metadata().await?.len();
+    ///
+    /// let content_type: mime::Mime = "video/mp4".parse()?;
+    ///
+    /// let upload = client.media().upload_reader(&content_type, file, size);
+    /// let progress = upload.subscribe_to_send_progress();
+    /// let response = upload.await?;
+    ///
+    /// println!("Cat video URI: {}", response.content_uri);
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn upload_reader(
+        &self,
+        content_type: &Mime,
+        reader: impl AsyncRead + Send + Sync + 'static,
+        size: u64,
+    ) -> UploadStream {
+        self.upload_stream(content_type, ReaderStream::new(reader), size)
+    }
+
+    /// Get a media file's content as a stream of bytes, instead of loading it
+    /// in memory.
+    ///
+    /// If the content is encrypted and encryption is enabled, the content will
+    /// be decrypted on the fly, see [`MediaStream`](futures::MediaStream) for
+    /// the caveats.
+    ///
+    /// The media cache is not used.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `MediaRequest` of the content.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn get_media_stream(&self, request: &MediaRequest) -> GetMediaStream {
+        GetMediaStream::new(self.client.clone(), request.clone())
+    }
+
+    /// Download a media file's content to the given path, without loading it
+    /// in memory.
+    ///
+    /// If the content is encrypted and encryption is enabled, the content will
+    /// be decrypted once the download is complete.
+    ///
+    /// The data is first downloaded to a file next to `path`, with a `.part`
+    /// extension. The download can be cancelled by dropping the returned
+    /// future, and resumed by calling this method again with the same
+    /// arguments, if the server supports it. The media cache is not used.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `MediaRequest` of the content.
+    ///
+    /// * `path` - The path of the file to write the content to. It is
+    ///   overwritten if it already exists.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn download_to_file(
+        &self,
+        request: &MediaRequest,
+        path: impl Into<PathBuf>,
+    ) -> DownloadToFile {
+        DownloadToFile::new(self.client.clone(), request.clone(), path.into())
+    }
+
+    /// Send the request for the given media, and return the response so its
+    /// body can be streamed.
+    ///
+    /// If `range_start` is not zero, only the bytes of the file starting at
+    /// this offset are requested.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn send_media_stream_request(
+        &self,
+        request: &MediaRequest,
+        range_start: u64,
+    ) -> Result<reqwest::Response> {
+        let (uri, thumbnail_size) = download_target(request);
+
+        self.send_with_fallback(|policy| async move {
+            let config = Some(self.client.request_config().timeout(MEDIA_STREAM_REQUEST_TIMEOUT));
+            let versions = self.server_versions_for(policy).await?;
+
+            let response = with_download_request!((policy, uri, thumbnail_size), |request| {
+                self.client
+                    .send_for_body_stream(request, range_start, config, Some(&*versions))
+                    .await
+            });
+
+            Ok::<_, Error>(response?)
+        })
+        .await
+    }
+
+    /// Call `send` with the endpoints of the current [`MediaUrlPolicy`].
+    ///
+    /// If the homeserver doesn't recognize the authenticated media endpoints,
+    /// even though it advertises support for them, `send` is called again
+    /// with the legacy endpoints.
+    async fn send_with_fallback<T, F, Fut>(&self, send: F) -> Result<T>
+    where
+        F: Fn(MediaUrlPolicy) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let policy = self.url_policy().await?;
+
+        match send(policy).await {
+            Err(error)
+                if policy == MediaUrlPolicy::Authenticated
+                    && is_unrecognized_endpoint_error(&error) =>
+            {
+                warn!(
+                    "The homeserver doesn't recognize the authenticated media endpoints, \
+                     falling back to the legacy endpoints"
+                );
+                send(MediaUrlPolicy::Unauthenticated).await
+            }
+            result => result,
+        }
+    }
+
+    /// The Matrix versions to build the requests to the endpoints of the given
+    /// policy with.
+    async fn server_versions_for(&self, policy: MediaUrlPolicy) -> Result<Box<[MatrixVersion]>> {
+        Ok(match policy {
+            MediaUrlPolicy::Authenticated => {
+                self.client.authenticated_media_server_versions().await?
+            }
+            MediaUrlPolicy::Unauthenticated => self.client.server_versions().await?.into(),
+        })
+    }
+
+    /// The endpoints that are used to download media from the homeserver.
+    ///
+    /// The authenticated media endpoints are used if the homeserver advertises
+    /// support for them in its `/versions` response, with Matrix 1.11 or the
+    /// `org.matrix.msc3916.stable` unstable feature, otherwise the legacy
+    /// unauthenticated endpoints are used.
+    ///
+    /// Apps that don't download media with the SDK, for example to render
+    /// them in a webview, can use this to know how to request the URLs
+    /// returned by [`Media::download_url()`].
+    pub async fn url_policy(&self) -> Result<MediaUrlPolicy> {
+        Ok(if self.client.supports_authenticated_media().await? {
+            MediaUrlPolicy::Authenticated
+        } else {
+            MediaUrlPolicy::Unauthenticated
+        })
+    }
+
+    /// Get the URL to download the given media from the homeserver, according
+    /// to the current [`MediaUrlPolicy`].
+    ///
+    /// If the media is encrypted, the data at this URL needs to be decrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `MediaRequest` of the content.
+    pub async fn download_url(&self, request: &MediaRequest) -> Result<Url> {
+        let policy = self.url_policy().await?;
+        let versions = self.server_versions_for(policy).await?;
+        let (uri, thumbnail_size) = download_target(request);
+
+        with_download_request!((policy, uri, thumbnail_size), |request| {
+            self.request_url(request, &versions)
+        })
+    }
+
+    /// Get the URL of the given request.
+    fn request_url(
+        &self,
+        request: impl OutgoingRequest,
+        server_versions: &[MatrixVersion],
+    ) -> Result<Url> {
+        // The access token is only needed to build the request, it is sent in a
+        // header so it doesn't appear in the URL.
+        let access_token = self.client.access_token();
+        let send_access_token = match access_token.as_deref() {
+            Some(access_token) => SendAccessToken::Always(access_token),
+            None => SendAccessToken::None,
+        };
+
+        let request = request
+            .try_into_http_request::<Vec<u8>>(
+                self.client.homeserver().as_str(),
+                send_access_token,
+                server_versions,
+            )
+            .map_err(HttpError::from)?;
+
+        Ok(Url::parse(&request.uri().to_string())?)
+    }
+
+    /// Download the raw content of the given media, using the endpoints of the
+    /// current [`MediaUrlPolicy`].
+    async fn download_raw_content(&self, request: &MediaRequest) -> Result<Vec<u8>> {
+        let (uri, thumbnail_size) = download_target(request);
+
+        self.send_with_fallback(|policy| async move {
+            let versions = Some(self.server_versions_for(policy).await?);
+
+            let content = with_download_request!((policy, uri, thumbnail_size), |request| {
+                self.client.send(request, None).with_server_versions_override(versions).await?.file
+            });
+
+            Ok::<_, Error>(content)
+        })
+        .await
+    }
+
+    /// Get the preview of a URL.
+    ///
+    /// The preview is cached in the media cache, by URL and timestamp, until
+    /// it expires according to the [`MediaCachePolicy`].
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL to preview.
+    ///
+    /// * `ts` - The preferred point in time to return a preview for. The
+    ///   homeserver may return a newer version if it doesn't have the requested
+    ///   version available.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let preview =
+    ///     client.media().get_url_preview("https://matrix.org", None).await?;
+    ///
+    /// if let Some(title) = preview.title {
+    ///     println!("Link to {title}");
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn get_url_preview(
+        &self,
+        url: &str,
+        ts: Option<MilliSecondsSinceUnixEpoch>,
+    ) -> Result<UrlPreview> {
+        let cache_store = self.client.base_client().media_cache_store();
+
+        if let Some(data) = cache_store.get_url_preview(url, ts).await? {
+            return UrlPreview::from_json(&data);
+        }
+
+        let data = self
+            .send_with_fallback(|policy| async move {
+                let versions = Some(self.server_versions_for(policy).await?);
+
+                let data = match policy {
+                    MediaUrlPolicy::Authenticated => {
+                        let request = assign!(
+                            authenticated_media::get_media_preview::v1::Request::new(
+                                url.to_owned()
+                            ),
+                            { ts }
+                        );
+                        self.client
+                            .send(request, None)
+                            .with_server_versions_override(versions)
+                            .await?
+                            .data
+                    }
+                    MediaUrlPolicy::Unauthenticated => {
+                        let request =
+                            assign!(get_media_preview::v3::Request::new(url.to_owned()), { ts });
+                        self.client
+                            .send(request, None)
+                            .with_server_versions_override(versions)
+                            .await?
+                            .data
+                    }
+                };
+
+                Ok::<_, Error>(data)
+            })
+            .await?;
+        let data = data.map_or_else(|| b"{}".to_vec(), |data| data.get().as_bytes().to_vec());
+
+        let preview = UrlPreview::from_json(&data)?;
+        let size = data.len();
+
+        cache_store.add_url_preview(url, ts, data, MilliSecondsSinceUnixEpoch::now()).await?;
+        self.clean_up_cache_after_add(size).await?;
+
+        Ok(preview)
+    }
+
+    /// Gets a media file by copying it to a temporary location on disk.
+    ///
+    /// The file won't be encrypted even if it is encrypted on the server.
+    ///
+    /// Returns a `MediaFileHandle` which takes ownership of the file. When the
+    /// handle is dropped, the file will be deleted from the temporary location.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `MediaRequest` of the content.
+    ///
+    /// * `content_type` - The type of the media, this will be used to set the
+    ///   temporary file's extension.
+    ///
+    /// * `use_cache` - If we should use the media cache for this request.
+    ///
+    /// * `temp_dir` - Path to a directory where temporary directories can be
+    ///   created. If not provided, a default, global temporary directory will
+    ///   be used; this may not work properly on Android, where the default
+    ///   location may require root access on some older Android versions.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn get_media_file(
+        &self,
+        request: &MediaRequest,
+        body: Option<String>,
+        content_type: &Mime,
+        use_cache: bool,
+        temp_dir: Option<String>,
+    ) -> Result<MediaFileHandle> {
+        let data = self.get_media_content(request, use_cache).await?;
+
+        let inferred_extension = mime2ext::mime2ext(content_type);
+
+        let body_path = body.as_ref().map(Path::new);
+        let filename = body_path.and_then(|f| f.file_name().and_then(|f| f.to_str()));
+        let filename_with_extension = body_path.and_then(|f| {
+            if f.extension().is_some() {
+                f.file_name().and_then(|f| f.to_str())
+            } else {
+                None
+            }
+        });
+
+        let (temp_file, temp_dir) = match (filename, filename_with_extension, inferred_extension) {
+            // If the body is a file name and has an extension use that
+            (Some(_), Some(filename_with_extension), Some(_)) => {
+                // Use an intermediary directory to avoid conflicts
+                let temp_dir = temp_dir.map(TempDir::new_in).unwrap_or_else(TempDir::new)?;
+                let temp_file = TempFileBuilder::new()
+                    .prefix(filename_with_extension)
+                    .rand_bytes(0)
+                    .tempfile_in(&temp_dir)?;
+                (temp_file, Some(temp_dir))
+            }
+            // If the body is a file name but doesn't have an extension try inferring one for it
+            (Some(filename), None, Some(inferred_extension)) => {
+                // Use an intermediary directory to avoid conflicts
+                let temp_dir = temp_dir.map(TempDir::new_in).unwrap_or_else(TempDir::new)?;
+                let temp_file = TempFileBuilder::new()
+                    .prefix(filename)
+                    .suffix(&(".".to_owned() + inferred_extension))
+                    .rand_bytes(0)
+                    .tempfile_in(&temp_dir)?;
+                (temp_file, Some(temp_dir))
+            }
+            // If the only thing we have is an inferred extension then use that together with a
+            // randomly generated file name
+            (None, None, Some(inferred_extension)) => (
+                TempFileBuilder::new()
+                    .suffix(&&(".".to_owned() + inferred_extension))
+                    .tempfile()?,
+                None,
+            ),
+            // Otherwise just use a completely random file name
+            _ => (TempFileBuilder::new().tempfile()?, None),
+        };
+
+        let mut file = TokioFile::from_std(temp_file.reopen()?);
+        file.write_all(&data).await?;
+        // Make sure the file metadata is flushed to disk.
+        file.sync_all().await?;
+
+        Ok(MediaFileHandle { file: temp_file, _directory: temp_dir })
+    }
+
+    /// Get a media file's content.
+    ///
+    /// If the content is encrypted and encryption is enabled, the content will
+    /// be decrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `MediaRequest` of the content.
+    ///
+    /// * `use_cache` - If we should use the media cache for this request.
+    pub async fn get_media_content(
+        &self,
+        request: &MediaRequest,
+        use_cache: bool,
+    ) -> Result<Vec<u8>> {
+        let cache = use_cache.then_some(false);
+        self.get_media_content_with_cache(request, cache).await
+    }
+
+    /// Get the avatar of the given owner, and pin it in the media cache so it is
+    /// not evicted.
+    ///
+    /// Avatars are displayed often and should always be available. The media
+    /// previously pinned for the same owner with another URI are unpinned, so
+    /// the old avatars can be evicted.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - A string that uniquely identifies the owner of the avatar,
+    ///   e.g. a room ID.
+    ///
+    /// * `request` - The `MediaRequest` of the avatar.
+    pub(crate) async fn get_pinned_media_content(
+        &self,
+        owner: &str,
+        request: &MediaRequest,
+    ) -> Result<Vec<u8>> {
+        let content = self.get_media_content_with_cache(request, Some(true)).await?;
+
+        let store = self.client.store();
+        let store_key = format!("{PINNED_MEDIA_KEY_PREFIX}:{owner}");
+        let pinned: Vec<MediaRequest> = match store.get_custom_value(store_key.as_bytes()).await? {
+            Some(value) => serde_json::from_slice(&value)?,
+            None => Vec::new(),
+        };
+
+        let (mut pinned, stale): (Vec<_>, Vec<_>) =
+            pinned.into_iter().partition(|pinned| pinned.uri() == request.uri());
+
+        let key = request.unique_key();
+        let is_new = !pinned.iter().any(|pinned| pinned.unique_key() == key);
+        if is_new {
+            pinned.push(request.clone());
+        }
+
+        // The stale media might still be the avatar of another owner, in which
+        // case it will be pinned again the next time it is requested for that
+        // owner.
+        for request in &stale {
+            self.set_media_pinned(request, false).await?;
+        }
+
+        if is_new || !stale.is_empty() {
+            store.set_custom_value(store_key.as_bytes(), serde_json::to_vec(&pinned)?).await?;
+        }
+
+        Ok(content)
+    }
+
+    /// Unpin the media pinned for the given owner by
+    /// [`Media::get_pinned_media_content`], e.g. when the owner doesn't have an
+    /// avatar anymore.
+    pub(crate) async fn unpin_media_content(&self, owner: &str) -> Result<()> {
+        let store = self.client.store();
+        let store_key = format!("{PINNED_MEDIA_KEY_PREFIX}:{owner}");
+        let Some(value) = store.remove_custom_value(store_key.as_bytes()).await? else {
+            return Ok(());
+        };
+
+        for request in serde_json::from_slice::<Vec<MediaRequest>>(&value)? {
+            self.set_media_pinned(&request, false).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get a media file's content.
+    ///
+    /// `cache` is `None` if the media cache should not be used, otherwise it
+    /// is whether the content should be pinned in the media cache.
+    async fn get_media_content_with_cache(
+        &self,
+        request: &MediaRequest,
+        cache: Option<bool>,
+    ) -> Result<Vec<u8>> {
+        let cache_store = self.client.base_client().media_cache_store();
+
+        // Read from the cache.
+        if let Some(pinned) = cache {
+            let now = MilliSecondsSinceUnixEpoch::now();
+            if let Some(content) = cache_store.get_media_content(request, now).await? {
+                if pinned {
+                    cache_store.set_media_pinned(request, true).await?;
+                }
+
+                return Ok(content);
+            }
+        };
+
+        let content: Vec<u8> = self.download_raw_content(request).await?;
+
+        #[cfg(feature = "e2e-encryption")]
+        let content: Vec<u8> = if let MediaSource::Encrypted(file) = &request.source {
+            let content_len = content.len();
+            let mut cursor = std::io::Cursor::new(content);
+            let mut reader = matrix_sdk_base::crypto::AttachmentDecryptor::new(
+                &mut cursor,
+                file.as_ref().clone().into(),
+            )?;
+
+            // Encrypted size should be the same as the decrypted size,
+            // rounded up to a cipher block.
+            let mut decrypted = Vec::with_capacity(content_len);
+
+            reader.read_to_end(&mut decrypted)?;
+
+            decrypted
+        } else {
+            content
+        };
+
+        if let Some(pinned) = cache {
+            if self.cache_policy().await?.is_cacheable(content.len()) {
+                let now = MilliSecondsSinceUnixEpoch::now();
+                cache_store.add_media_content(request, content.clone(), pinned, now).await?;
+                self.clean_up_cache_after_add(content.len()).await?;
+            }
+        }
+
+        Ok(content)
+    }
+
+    /// Remove a media file's content from the media cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `MediaRequest` of the content.
+    pub async fn remove_media_content(&self, request: &MediaRequest) -> Result<()> {
+        Ok(self.client.base_client().media_cache_store().remove_media_content(request).await?)
+    }
+
+    /// Delete all the media content corresponding to the given
+    /// uri from the media cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `uri` - The `MxcUri` of the files.
+    pub async fn remove_media_content_for_uri(&self, uri: &MxcUri) -> Result<()> {
+        Ok(self.client.base_client().media_cache_store().remove_media_content_for_uri(uri).await?)
+    }
+
+    /// Set whether a media file in the media cache is pinned.
+    ///
+    /// Pinned files are not evicted from the media cache when it is full or
+    /// when they expire, see [`MediaCachePolicy`]. Does nothing if the file is
+    /// not in the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The `MediaRequest` of the content.
+    ///
+    /// * `pinned` - Whether the file should be pinned.
+    pub async fn set_media_pinned(&self, request: &MediaRequest, pinned: bool) -> Result<()> {
+        Ok(self.client.base_client().media_cache_store().set_media_pinned(request, pinned).await?)
+    }
+
+    /// The policy used to keep the size of the media cache in check.
+    ///
+    /// Defaults to [`MediaCachePolicy::new()`].
+    pub async fn cache_policy(&self) -> Result<MediaCachePolicy> {
+        Ok(*self.cache_policy_lock().await?.read().unwrap())
+    }
+
+    /// Set the policy used to keep the size of the media cache in check.
+    ///
+    /// The policy is persisted in the state store. The new policy is applied
+    /// the next time a file is added to the cache, or when calling
+    /// [`Media::clean_up_cache()`].
+    pub async fn set_cache_policy(&self, policy: MediaCachePolicy) -> Result<()> {
+        self.client
+            .store()
+            .set_custom_value(CACHE_POLICY_KEY, serde_json::to_vec(&policy)?)
+            .await?;
+        *self.cache_policy_lock().await?.write().unwrap() = policy;
+
+        // Apply the new policy with the next added file.
+        self.client.inner.media_cache_clean_up.lock().unwrap().cleaned_up_at = None;
+
+        Ok(())
+    }
+
+    /// The lock around the media cache policy, loaded from the state store the
+    /// first time it is accessed.
+    async fn cache_policy_lock(&self) -> Result<&StdRwLock<MediaCachePolicy>> {
+        self.client
+            .inner
+            .media_cache_policy
+            .get_or_try_init(|| async {
+                let policy = match self.client.store().get_custom_value(CACHE_POLICY_KEY).await? {
+                    Some(value) => serde_json::from_slice(&value)?,
+                    None => MediaCachePolicy::default(),
+                };
+                Ok::<_, Error>(StdRwLock::new(policy))
+            })
+            .await
+    }
+
+    /// Get statistics about the content of the media cache.
+    pub async fn cache_stats(&self) -> Result<MediaCacheStats> {
+        let entries = self.client.base_client().media_cache_store().media_entries().await?;
+        Ok(MediaCacheStats::from_entries(&entries))
+    }
+
+    /// Evict the files and URL previews from the media cache that don't
+    /// respect the current [`MediaCachePolicy`].
+    ///
+    /// This is called automatically when a file is added to the cache.
+    pub async fn clean_up_cache(&self) -> Result<()> {
+        let cache_store = self.client.base_client().media_cache_store();
+        let policy = self.cache_policy().await?;
+        let now = MilliSecondsSinceUnixEpoch::now();
+
+        if let Some(expiry) = policy.expiry {
+            let expiry = UInt::new_saturating(expiry.as_millis().try_into().unwrap_or(u64::MAX));
+            let before = MilliSecondsSinceUnixEpoch(now.0.saturating_sub(expiry));
+            cache_store.remove_url_previews_added_before(before).await?;
+        }
+
+        // The URL previews count towards the size of the cache, so the media
+        // files are evicted to make room for them.
+        let url_previews_size = cache_store.url_previews_size().await?;
+        let entries = cache_store.media_entries().await?;
+
+        let evicted = policy.entries_to_evict(&entries, url_previews_size, now);
+        if !evicted.is_empty() {
+            debug!(count = evicted.len(), "Evicting media from the cache");
+            cache_store.remove_media_entries(&evicted).await?;
+        }
+
+        let size = entries
+            .iter()
+            .filter(|entry| !evicted.contains(&entry.key))
+            .map(|entry| entry.size)
+            .fold(url_previews_size, usize::saturating_add);
+        *self.client.inner.media_cache_clean_up.lock().unwrap() =
+            CacheCleanUpState { size: Some(size), cleaned_up_at: Some(Instant::now()) };
+
+        Ok(())
+    }
+
+    /// Clean up the media cache after a file or a URL preview of the given size
+    /// was added to it, if it might be full or if it wasn't cleaned up for
+    /// some time.
+    ///
+    /// This avoids loading the metadata of all the files in the cache every
+    /// time a file is added.
+    async fn clean_up_cache_after_add(&self, size: usize) -> Result<()> {
+        let policy = self.cache_policy().await?;
+
+        let needs_clean_up = {
+            let mut state = self.client.inner.media_cache_clean_up.lock().unwrap();
+            state.size = state.size.map(|total| total.saturating_add(size));
+
+            let is_full = policy
+                .max_cache_size
+                .is_some_and(|max| state.size.map_or(true, |total| total > max));
+            let is_stale =
+                state.cleaned_up_at.map_or(true, |at| at.elapsed() >= CACHE_CLEAN_UP_INTERVAL);
+
+            is_full || is_stale
+        };
+
+        if needs_clean_up {
+            self.clean_up_cache().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove all the files from the media cache, including the pinned ones.
+    pub async fn clear_cache(&self) -> Result<()> {
+        self.client.base_client().media_cache_store().clear_media_cache().await?;
+        self.client.inner.media_cache_clean_up.lock().unwrap().size = Some(0);
+        Ok(())
+    }
+
+    /// Get the file of the given media event content.
+    ///
+    /// If the content is encrypted and encryption is enabled, the content will
+    /// be decrypted.
+    ///
+    /// Returns `Ok(None)` if the event content has no file.
+    ///
+    /// This is a convenience method that calls the
+    /// [`get_media_content`](#method.get_media_content) method.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_content` - The media event content.
+    ///
+    /// * `use_cache` - If we should use the media cache for this file.
+    pub async fn get_file(
+        &self,
+        event_content: &impl MediaEventContent,
+        use_cache: bool,
+    ) -> Result<Option<Vec<u8>>> {
+        let Some(source) = event_content.source() else { return Ok(None) };
+        let file = self
+            .get_media_content(&MediaRequest { source, format: MediaFormat::File }, use_cache)
+            .await?;
+        Ok(Some(file))
+    }
+
+    /// Remove the file of the given media event content from the cache.
+    ///
+    /// This is a convenience method that calls the
+    /// [`remove_media_content`](#method.remove_media_content) method.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_content` - The media event content.
+    pub async fn remove_file(&self, event_content: &impl MediaEventContent) -> Result<()> {
+        if let Some(source) = event_content.source() {
+            self.remove_media_content(&MediaRequest { source, format: MediaFormat::File }).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get a thumbnail of the given media event content.
+    ///
+    /// If the content is encrypted and encryption is enabled, the content will
+    /// be decrypted.
+    ///
+    /// Returns `Ok(None)` if the event content has no thumbnail.
+    ///
+    /// This is a convenience method that calls the
+    /// [`get_media_content`](#method.get_media_content) method.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_content` - The media event content.
+    ///
+    /// * `size` - The _desired_ size of the thumbnail. The actual thumbnail may
+    ///   not match the size specified.
+    ///
+    /// * `use_cache` - If we should use the media cache for this thumbnail.
+    pub async fn get_thumbnail(
+        &self,
+        event_content: &impl MediaEventContent,
+        size: MediaThumbnailSize,
+        use_cache: bool,
+    ) -> Result<Option<Vec<u8>>> {
+        let Some(source) = event_content.thumbnail_source() else { return Ok(None) };
+        let thumbnail = self
+            .get_media_content(
+                &MediaRequest { source, format: MediaFormat::Thumbnail(size) },
+                use_cache,
+            )
+            .await?;
+        Ok(Some(thumbnail))
+    }
+
+    /// Remove the thumbnail of the given media event content from the cache.
+    ///
+    /// This is a convenience method that calls the
+    /// [`remove_media_content`](#method.remove_media_content) method.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_content` - The media event content.
+    ///
+    /// * `size` - The _desired_ size of the thumbnail. Must match the size
+    ///   requested with [`get_thumbnail`](#method.get_thumbnail).
+    pub async fn remove_thumbnail(
+        &self,
+        event_content: &impl MediaEventContent,
+        size: MediaThumbnailSize,
+    ) -> Result<()> {
+        if let Some(source) = event_content.source() {
+            self.remove_media_content(&MediaRequest {
+                source,
+                format: MediaFormat::Thumbnail(size),
+            })
+            .await?
+        }
+
+        Ok(())
+    }
+
+    /// Upload the file bytes in `data` and construct an attachment
+    /// message with `body`, `content_type`, `info` and `thumbnail`.
+    pub(crate) async fn prepare_attachment_message(
+        &self,
+        body: &str,
+        content_type: &Mime,
+        data: AttachmentData,
+        info: Option<AttachmentInfo>,
+        thumbnail: Option<Thumbnail>,
+        send_progress: SharedObservable<TransmissionProgress>,
+    ) -> Result<MessageType> {
+        let upload_thumbnail = self.upload_thumbnail(thumbnail, send_progress.clone());
+
+        let upload_attachment = async move {
+            let response = match data {
+                AttachmentData::Bytes(data) => {
+                    self.upload(content_type, data)
+                        .with_send_progress_observable(send_progress)
+                        .await
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                AttachmentData::Stream { stream, size } => {
+                    self.upload_stream(content_type, stream, size)
+                        .with_send_progress_observable(send_progress)
+                        .await
+                }
+            };
+
+            response.map_err(crate::Error::from)
+        };
+
+        let ((thumbnail_source, thumbnail_info), response) =
+            try_join(upload_thumbnail, upload_attachment).await?;
+
+        Ok(make_attachment_type(
+            content_type,
+            body,
+            MediaSource::Plain(response.content_uri),
+            info,
+            thumbnail_source,
+            thumbnail_info,
+        ))
+    }
+
+    async fn upload_thumbnail(
+        &self,
+        thumbnail: Option<Thumbnail>,
+        send_progress: SharedObservable<TransmissionProgress>,
+    ) -> Result<(Option<MediaSource>, Option<Box<ThumbnailInfo>>)> {
+        if let Some(thumbnail) = thumbnail {
+            let response = self
+                .upload(&thumbnail.content_type, thumbnail.data)
+                .with_send_progress_observable(send_progress)
+                .await?;
+            let url = response.content_uri;
+
+            let thumbnail_info = assign!(
+                thumbnail.info
+                    .as_ref()
+                    .map(|info| ThumbnailInfo::from(info.clone()))
+                    .unwrap_or_default(),
+                { mimetype: Some(thumbnail.content_type.as_ref().to_owned()) }
+            );
+
+            Ok((Some(MediaSource::Plain(url)), Some(Box::new(thumbnail_info))))
+        } else {
+            Ok((None, None))
+        }
+    }
+}
+
+/// The URI to download and the size of the thumbnail to request for the given
+/// media request.
+///
+/// Thumbnails of encrypted media can't be generated by the homeserver, so the
+/// file is always downloaded for them.
+fn download_target(request: &MediaRequest) -> (&MxcUri, Option<&MediaThumbnailSize>) {
+    match (&request.source, &request.format) {
+        (MediaSource::Encrypted(file), _) => (&*file.url, None),
+        (MediaSource::Plain(uri), MediaFormat::File) => (&**uri, None),
+        (MediaSource::Plain(uri), MediaFormat::Thumbnail(size)) => (&**uri, Some(size)),
+    }
+}
+
+/// Whether the given error means that the homeserver doesn't recognize the
+/// endpoint of the request.
+///
+/// Reverse proxies can answer with a 404 without a Matrix error code, but a
+/// `M_NOT_FOUND` error means that the media doesn't exist.
+fn is_unrecognized_endpoint_error(error: &Error) -> bool {
+    let Some(api_error) = error.as_client_api_error() else {
+        return false;
+    };
+
+    match error.client_api_error_kind() {
+        Some(ErrorKind::Unrecognized) => true,
+        Some(_) => false,
+        None => api_error.status_code == StatusCode::NOT_FOUND,
+    }
+}
+
+/// The request timeout of an upload of the given size.
+fn upload_timeout(size: u64) -> Duration {
+    std::cmp::max(Duration::from_secs(size / DEFAULT_UPLOAD_SPEED), MIN_UPLOAD_REQUEST_TIMEOUT)
+}
+
+/// Construct the message type of an attachment, with the given sources of the
+/// file and its thumbnail.
+pub(crate) fn make_attachment_type(
+    content_type: &Mime,
+    body: &str,
+    source: MediaSource,
+    info: Option<AttachmentInfo>,
+    thumbnail_source: Option<MediaSource>,
+    thumbnail_info: Option<Box<ThumbnailInfo>>,
+) -> MessageType {
+    match content_type.type_() {
+        mime::IMAGE => {
+            let info = assign!(info.map(ImageInfo::from).unwrap_or_default(), {
+                mimetype: Some(content_type.as_ref().to_owned()),
+                thumbnail_source,
+                thumbnail_info,
+            });
+            let content = assign!(ImageMessageEventContent::new(body.to_owned(), source), {
+                info: Some(Box::new(info))
+            });
+            MessageType::Image(content)
+        }
+        mime::AUDIO => {
+            let content = AudioMessageEventContent::new(body.to_owned(), source);
+            MessageType::Audio(update_audio_message_event(content, content_type, info))
+        }
+        mime::VIDEO => {
+            let info = assign!(info.map(VideoInfo::from).unwrap_or_default(), {
+                mimetype: Some(content_type.as_ref().to_owned()),
+                thumbnail_source,
+                thumbnail_info,
+            });
+            let content = assign!(VideoMessageEventContent::new(body.to_owned(), source), {
+                info: Some(Box::new(info))
+            });
+            MessageType::Video(content)
+        }
+        _ => {
+            let info = assign!(info.map(FileInfo::from).unwrap_or_default(), {
+                mimetype: Some(content_type.as_ref().to_owned()),
+                thumbnail_source,
+                thumbnail_info,
+            });
+            let content = assign!(FileMessageEventContent::new(body.to_owned(), source), {
+                info: Some(Box::new(info))
+            });
+            MessageType::File(content)
+        }
+    }
+}
+
+fn update_audio_message_event(
+    mut audio_message_event_content: AudioMessageEventContent,
+    content_type: &Mime,
+    info: Option<AttachmentInfo>,
+) -> AudioMessageEventContent {
+    if let Some(AttachmentInfo::Voice { audio_info, waveform: Some(waveform_vec) }) = &info {
+        if let Some(duration) = audio_info.duration {
+            let waveform = waveform_vec.iter().map(|v| (*v).into()).collect();
+            audio_message_event_content.audio =
+                Some(UnstableAudioDetailsContentBlock::new(duration, waveform));
+        }
+        audio_message_event_content.voice = Some(UnstableVoiceContentBlock::new());
+    }
+
+    let audio_info = assign!(info.map(AudioInfo::from).unwrap_or_default(), {mimetype: Some(content_type.as_ref().to_owned()), });
+    audio_message_event_content.info(Box::new(audio_info))
+}
@@ -9,6 +9,7 @@ use ruma::{
     },
     events::push_rules::PushRulesEvent,
     push::{Action, PredefinedUnderrideRuleId, RuleKind, Ruleset, Tweak},
+    serde::Raw,
     RoomId,
 };
 use tokio::sync::{
@@ -25,7 +26,7 @@ mod rules;
 
 use crate::{
     config::RequestConfig, error::NotificationSettingsError, event_handler::EventHandlerDropGuard,
-    Client, Result,
+    Client, Result, Room,
 };
 
 /// Enum representing the push notification modes for a room.
@@ -122,6 +123,27 @@ impl NotificationSettings {
         self.changes_sender.subscribe()
     }
 
+    /// Get the push actions for the given event in the given room, according
+    /// to the current notification settings.
+    ///
+    /// Unlike [`Room::event_push_actions`], this takes into account the local
+    /// changes made to the settings that haven't been synced back yet.
+    ///
+    /// Returns `None` if the push context of the room couldn't be aggregated,
+    /// see [`Room::push_context`].
+    pub async fn event_push_actions<T>(
+        &self,
+        room: &Room,
+        event: &Raw<T>,
+    ) -> Result<Option<Vec<Action>>> {
+        let Some(push_context) = room.push_context().await? else {
+            debug!("Could not aggregate push context");
+            return Ok(None);
+        };
+
+        Ok(Some(self.rules.read().await.ruleset.get_actions(event, &push_context).to_owned()))
+    }
+
     /// Get the user defined notification mode for a room.
     pub async fn get_user_defined_room_notification_mode(
         &self,
@@ -523,7 +545,7 @@ mod tests {
     use matrix_sdk_test::{
         async_test,
         notification_settings::{build_ruleset, get_server_default_ruleset},
-        test_json,
+        sync_timeline_event, test_json, JoinedRoomBuilder, StateTestEvent, SyncResponseBuilder,
     };
     use ruma::{
         push::{
@@ -621,6 +643,55 @@ mod tests {
         assert_eq!(custom_rules[1], (RuleKind::Room, room_id.to_string()));
     }
 
+    #[async_test]
+    async fn test_event_push_actions() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+        let room_id = get_test_room_id();
+
+        let mut sync_builder = SyncResponseBuilder::new();
+        sync_builder.add_joined_room(
+            JoinedRoomBuilder::new(&room_id).add_state_event(StateTestEvent::Member),
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/sync"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(sync_builder.build_json_sync_response()),
+            )
+            .mount(&server)
+            .await;
+
+        client.sync_once(SyncSettings::default()).await.unwrap();
+        let room = client.get_room(&room_id).unwrap();
+
+        let event = sync_timeline_event!({
+            "content": {
+                "body": "Hello world",
+                "msgtype": "m.text",
+            },
+            "event_id": "$message",
+            "origin_server_ts": 152037280,
+            "sender": "@alice:example.org",
+            "type": "m.room.message",
+        });
+
+        // With the default rules, a message notifies.
+        let settings = from_insert_rules(&client, vec![]);
+        let actions = settings.event_push_actions(&room, &event).await.unwrap().unwrap();
+        assert!(actions.iter().any(Action::should_notify));
+
+        // The room is in `MentionsAndKeywordsOnly` mode, a message doesn't notify.
+        let settings = from_insert_rules(&client, vec![(RuleKind::Room, &room_id, false)]);
+        let actions = settings.event_push_actions(&room, &event).await.unwrap().unwrap();
+        assert!(actions.is_empty());
+
+        // The room is muted, a message doesn't notify.
+        let settings = from_insert_rules(&client, vec![(RuleKind::Override, &room_id, false)]);
+        let actions = settings.event_push_actions(&room, &event).await.unwrap().unwrap();
+        assert!(actions.is_empty());
+    }
+
     #[async_test]
     async fn test_get_user_defined_room_notification_mode_none() {
         let server = MockServer::start().await;
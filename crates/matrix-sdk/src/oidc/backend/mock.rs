@@ -14,7 +14,10 @@
 
 //! Test implementation of the OIDC backend.
 
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
 
 use http::StatusCode;
 use mas_oidc_client::{
@@ -27,23 +30,26 @@ use mas_oidc_client::{
     requests::authorization_code::{AuthorizationRequestData, AuthorizationValidationData},
     types::{
         client_credentials::ClientCredentials,
-        errors::ClientErrorCode,
+        errors::{ClientError, ClientErrorCode},
         iana::oauth::OAuthTokenTypeHint,
         oidc::{ProviderMetadata, ProviderMetadataVerificationError, VerifiedProviderMetadata},
         registration::{ClientRegistrationResponse, VerifiedClientMetadata},
+        scope::Scope,
         IdToken,
     },
 };
 use url::Url;
 
 use super::{OidcBackend, OidcError, RefreshedSessionTokens};
-use crate::oidc::{AuthorizationCode, OidcSessionTokens};
+use crate::oidc::{device_code::DeviceAuthorizationResponse, AuthorizationCode, OidcSessionTokens};
 
 pub(crate) const ISSUER_URL: &str = "https://oidc.example.com/issuer";
 pub(crate) const AUTHORIZATION_URL: &str = "https://oidc.example.com/authorization";
 pub(crate) const REVOCATION_URL: &str = "https://oidc.example.com/revocation";
 pub(crate) const TOKEN_URL: &str = "https://oidc.example.com/token";
 pub(crate) const JWKS_URL: &str = "https://oidc.example.com/jwks";
+pub(crate) const DEVICE_AUTHORIZATION_URL: &str = "https://oidc.example.com/device_authorization";
+pub(crate) const DEVICE_VERIFICATION_URL: &str = "https://oidc.example.com/link";
 
 #[derive(Debug)]
 pub(crate) struct MockImpl {
@@ -62,6 +68,9 @@ pub(crate) struct MockImpl {
     /// Must be an HTTPS URL.
     revocation_endpoint: String,
 
+    /// Must be an HTTPS URL, if any.
+    device_authorization_endpoint: Option<String>,
+
     /// The next session tokens that will be returned by a login or refresh.
     next_session_tokens: Option<OidcSessionTokens>,
 
@@ -74,6 +83,13 @@ pub(crate) struct MockImpl {
     /// Tokens that have been revoked with `revoke_token`.
     pub revoked_tokens: Arc<Mutex<Vec<String>>>,
 
+    /// The errors that will be returned by the next polls of the token
+    /// endpoint with a device code, before returning the next session tokens.
+    device_code_errors: Arc<Mutex<VecDeque<ClientErrorCode>>>,
+
+    /// Number of polls of the token endpoint with a device code.
+    pub num_device_code_polls: Arc<Mutex<u32>>,
+
     /// Should we only accept insecure flags during discovery?
     is_insecure: bool,
 }
@@ -86,10 +102,13 @@ impl MockImpl {
             token_endpoint: TOKEN_URL.to_owned(),
             jwks_uri: JWKS_URL.to_owned(),
             revocation_endpoint: REVOCATION_URL.to_owned(),
+            device_authorization_endpoint: Some(DEVICE_AUTHORIZATION_URL.to_owned()),
             next_session_tokens: None,
             expected_refresh_token: None,
             num_refreshes: Default::default(),
             revoked_tokens: Default::default(),
+            device_code_errors: Default::default(),
+            num_device_code_polls: Default::default(),
             is_insecure: false,
         }
     }
@@ -104,6 +123,16 @@ impl MockImpl {
         self
     }
 
+    pub fn device_code_errors(self, errors: impl IntoIterator<Item = ClientErrorCode>) -> Self {
+        self.device_code_errors.lock().unwrap().extend(errors);
+        self
+    }
+
+    pub fn without_device_authorization(mut self) -> Self {
+        self.device_authorization_endpoint = None;
+        self
+    }
+
     pub fn mark_insecure(mut self) -> Self {
         self.is_insecure = true;
         self
@@ -132,6 +161,10 @@ impl OidcBackend for MockImpl {
             revocation_endpoint: Some(Url::parse(&self.revocation_endpoint).unwrap()),
             token_endpoint: Some(Url::parse(&self.token_endpoint).unwrap()),
             jwks_uri: Some(Url::parse(&self.jwks_uri).unwrap()),
+            device_authorization_endpoint: self
+                .device_authorization_endpoint
+                .as_deref()
+                .map(|url| Url::parse(url).unwrap()),
             response_types_supported: Some(vec![]),
             subject_types_supported: Some(vec![]),
             id_token_signing_alg_values_supported: Some(vec![]),
@@ -186,6 +219,41 @@ impl OidcBackend for MockImpl {
         Ok(())
     }
 
+    async fn request_device_authorization(
+        &self,
+        _device_authorization_endpoint: &Url,
+        _client_credentials: ClientCredentials,
+        _scope: Scope,
+    ) -> Result<DeviceAuthorizationResponse, OidcError> {
+        Ok(DeviceAuthorizationResponse {
+            device_code: "d3v1c3c0d3".to_owned(),
+            user_code: "ABCD-EFGH".to_owned(),
+            verification_uri: Url::parse(DEVICE_VERIFICATION_URL).unwrap(),
+            verification_uri_complete: Some(
+                Url::parse(&format!("{DEVICE_VERIFICATION_URL}?code=ABCD-EFGH")).unwrap(),
+            ),
+            expires_in: 600,
+            // Don't wait between polls in tests.
+            interval: Some(0),
+        })
+    }
+
+    async fn exchange_device_code(
+        &self,
+        _provider_metadata: VerifiedProviderMetadata,
+        _client_credentials: ClientCredentials,
+        device_code: String,
+    ) -> Result<OidcSessionTokens, OidcError> {
+        assert_eq!(device_code, "d3v1c3c0d3");
+        *self.num_device_code_polls.lock().unwrap() += 1;
+
+        if let Some(error) = self.device_code_errors.lock().unwrap().pop_front() {
+            return Err(OidcError::DeviceCodeGrant(ClientError::from(error)));
+        }
+
+        Ok(self.next_session_tokens.clone().expect("missing next session tokens in testing"))
+    }
+
     async fn refresh_access_token(
         &self,
         _provider_metadata: VerifiedProviderMetadata,
@@ -23,12 +23,15 @@ use mas_oidc_client::{
         iana::oauth::OAuthTokenTypeHint,
         oidc::VerifiedProviderMetadata,
         registration::{ClientRegistrationResponse, VerifiedClientMetadata},
+        scope::Scope,
         IdToken,
     },
 };
 use url::Url;
 
-use super::{AuthorizationCode, OidcError, OidcSessionTokens};
+use super::{
+    device_code::DeviceAuthorizationResponse, AuthorizationCode, OidcError, OidcSessionTokens,
+};
 
 pub(crate) mod server;
 
@@ -88,4 +91,20 @@ pub(super) trait OidcBackend: std::fmt::Debug + Send + Sync {
         token: String,
         token_type_hint: Option<OAuthTokenTypeHint>,
     ) -> Result<(), OidcError>;
+
+    async fn request_device_authorization(
+        &self,
+        device_authorization_endpoint: &Url,
+        client_credentials: ClientCredentials,
+        scope: Scope,
+    ) -> Result<DeviceAuthorizationResponse, OidcError>;
+
+    /// Returns an [`OidcError::DeviceCodeGrant`] if the provider returned an
+    /// error, including while the authorization is pending.
+    async fn exchange_device_code(
+        &self,
+        provider_metadata: VerifiedProviderMetadata,
+        client_credentials: ClientCredentials,
+        device_code: String,
+    ) -> Result<OidcSessionTokens, OidcError>;
 }
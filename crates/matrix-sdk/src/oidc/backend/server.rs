@@ -32,20 +32,29 @@ use mas_oidc_client::{
     },
     types::{
         client_credentials::ClientCredentials,
+        errors::ClientError,
         iana::oauth::OAuthTokenTypeHint,
         oidc::VerifiedProviderMetadata,
         registration::{ClientRegistrationResponse, VerifiedClientMetadata},
+        scope::Scope,
         IdToken,
     },
 };
+use serde::de::DeserializeOwned;
 use url::Url;
 
 use super::{OidcBackend, OidcError, RefreshedSessionTokens};
 use crate::{
-    oidc::{rng, AuthorizationCode, OidcSessionTokens},
+    oidc::{
+        device_code::{DeviceAuthorizationResponse, DeviceCodeTokenResponse},
+        rng, AuthorizationCode, OidcSessionTokens,
+    },
     Client,
 };
 
+/// The grant type of the Device Authorization Grant.
+const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
 #[derive(Debug)]
 pub(crate) struct OidcServer {
     client: Client,
@@ -67,6 +76,54 @@ impl OidcServer {
     async fn fetch_jwks(&self, uri: &Url) -> Result<PublicJsonWebKeySet, OidcError> {
         fetch_jwks(&self.http_service(), uri).await.map_err(Into::into)
     }
+
+    /// Send a form request to an endpoint of the provider that requires client
+    /// authentication.
+    ///
+    /// Only the client authentication methods that don't need to sign a JWT
+    /// are supported.
+    ///
+    /// An error response from the provider is returned as an
+    /// [`OidcError::DeviceCodeGrant`].
+    async fn send_form_request<T: DeserializeOwned>(
+        &self,
+        endpoint: &Url,
+        client_credentials: ClientCredentials,
+        mut form: Vec<(&'static str, String)>,
+    ) -> Result<T, OidcError> {
+        let mut request = self.client.inner.http_client.inner.post(endpoint.clone());
+
+        match client_credentials {
+            ClientCredentials::None { client_id } => {
+                form.push(("client_id", client_id));
+            }
+            ClientCredentials::ClientSecretPost { client_id, client_secret } => {
+                form.push(("client_id", client_id));
+                form.push(("client_secret", client_secret));
+            }
+            ClientCredentials::ClientSecretBasic { client_id, client_secret } => {
+                request = request.basic_auth(client_id, Some(client_secret));
+            }
+            _ => {
+                return Err(OidcError::UnknownError(
+                    "unsupported client authentication method".into(),
+                ))
+            }
+        }
+
+        let response = request.form(&form).send().await.map_err(OidcError::Request)?;
+        let status_error = response.error_for_status_ref().err();
+        let body = response.bytes().await.map_err(OidcError::Request)?;
+
+        if let Some(status_error) = status_error {
+            return Err(match serde_json::from_slice::<ClientError>(&body) {
+                Ok(error) => OidcError::DeviceCodeGrant(error),
+                Err(_) => OidcError::Request(status_error),
+            });
+        }
+
+        serde_json::from_slice(&body).map_err(|error| OidcError::UnknownError(error.into()))
+    }
 }
 
 #[async_trait::async_trait]
@@ -208,4 +265,44 @@ impl OidcBackend for OidcServer {
         )
         .await?)
     }
+
+    async fn request_device_authorization(
+        &self,
+        device_authorization_endpoint: &Url,
+        client_credentials: ClientCredentials,
+        scope: Scope,
+    ) -> Result<DeviceAuthorizationResponse, OidcError> {
+        self.send_form_request(
+            device_authorization_endpoint,
+            client_credentials,
+            vec![("scope", scope.to_string())],
+        )
+        .await
+    }
+
+    async fn exchange_device_code(
+        &self,
+        provider_metadata: VerifiedProviderMetadata,
+        client_credentials: ClientCredentials,
+        device_code: String,
+    ) -> Result<OidcSessionTokens, OidcError> {
+        let response: DeviceCodeTokenResponse = self
+            .send_form_request(
+                provider_metadata.token_endpoint(),
+                client_credentials,
+                vec![
+                    ("grant_type", DEVICE_CODE_GRANT_TYPE.to_owned()),
+                    ("device_code", device_code),
+                ],
+            )
+            .await?;
+
+        Ok(OidcSessionTokens {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+            // The ID token is only used as a hint when refreshing the tokens or
+            // logging out, so it's fine not to keep one.
+            latest_id_token: None,
+        })
+    }
 }
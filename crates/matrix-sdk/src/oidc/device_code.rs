@@ -0,0 +1,112 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+//! Types for the [OAuth 2.0 Device Authorization Grant].
+//!
+//! [OAuth 2.0 Device Authorization Grant]: https://datatracker.ietf.org/doc/html/rfc8628
+
+use std::{fmt, time::Duration};
+
+use serde::Deserialize;
+use url::Url;
+
+/// The default polling interval of the token endpoint, if the provider
+/// doesn't return one.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The data returned by [`Oidc::login_with_device_code()`], to present to the
+/// user.
+///
+/// The user must visit the verification URI on another device and enter the
+/// user code, while [`Oidc::finish_device_code_login()`] waits for them to
+/// authorize this client.
+///
+/// [`Oidc::login_with_device_code()`]: super::Oidc::login_with_device_code
+/// [`Oidc::finish_device_code_login()`]: super::Oidc::finish_device_code_login
+#[derive(Clone)]
+pub struct OidcDeviceAuthorizationData {
+    /// The code that the user must enter at the verification URI.
+    pub user_code: String,
+
+    /// The URI that the user must visit to authorize this client.
+    pub verification_uri: Url,
+
+    /// The verification URI with the user code already included, for example
+    /// to display as a QR code.
+    pub verification_uri_complete: Option<Url>,
+
+    /// The time after which the user code expires.
+    pub expires_in: Duration,
+
+    /// The code used to poll the token endpoint.
+    pub(super) device_code: String,
+
+    /// The minimum time to wait between two polls of the token endpoint.
+    pub(super) interval: Duration,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for OidcDeviceAuthorizationData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OidcDeviceAuthorizationData")
+            .field("user_code", &self.user_code)
+            .field("verification_uri", &self.verification_uri)
+            .field("verification_uri_complete", &self.verification_uri_complete)
+            .field("expires_in", &self.expires_in)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A successful response of the device authorization endpoint.
+#[derive(Clone, Deserialize)]
+pub(super) struct DeviceAuthorizationResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: Url,
+    #[serde(default)]
+    pub verification_uri_complete: Option<Url>,
+    pub expires_in: u64,
+    #[serde(default)]
+    pub interval: Option<u64>,
+}
+
+impl From<DeviceAuthorizationResponse> for OidcDeviceAuthorizationData {
+    fn from(response: DeviceAuthorizationResponse) -> Self {
+        Self {
+            user_code: response.user_code,
+            verification_uri: response.verification_uri,
+            verification_uri_complete: response.verification_uri_complete,
+            expires_in: Duration::from_secs(response.expires_in),
+            device_code: response.device_code,
+            interval: response.interval.map_or(DEFAULT_INTERVAL, Duration::from_secs),
+        }
+    }
+}
+
+/// A successful response of the token endpoint for the device code grant.
+#[derive(Deserialize)]
+pub(super) struct DeviceCodeTokenResponse {
+    pub access_token: String,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+/// Wait for the given duration before polling the token endpoint again.
+pub(super) async fn sleep(delay: Duration) {
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::sleep(delay).await;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(delay).await;
+}
@@ -76,6 +76,16 @@
 //! provided redirect URI, with a code in the query that will allow to finish
 //! the authorization process by calling [`Oidc::finish_authorization()`].
 //!
+//! Clients that can't open a web browser, like CLI tools or TV apps, can use
+//! [`Oidc::login_with_device_code()`] instead, which returns a code that the
+//! user must enter on another device, and then wait for the authorization with
+//! [`Oidc::finish_device_code_login()`].
+//!
+//! With the `qrcode` feature, a new device can also be logged in by scanning a
+//! QR code with a device that is already logged in, which also shares the
+//! secrets needed for the new device to be verified. See the [`qrcode`] module
+//! for more details.
+//!
 //! # Persisting/restoring a session
 //!
 //! A full OIDC session requires two parts:
@@ -165,7 +175,7 @@
 //! [`AuthenticateError::InsufficientScope`]: ruma::api::client::error::AuthenticateError
 //! [`examples/oidc-cli`]: https://github.com/matrix-org/matrix-rust-sdk/tree/main/examples/oidc-cli
 
-use std::{collections::HashMap, fmt, sync::Arc};
+use std::{collections::HashMap, fmt, sync::Arc, time::Duration};
 
 use as_variant::as_variant;
 use eyeball::SharedObservable;
@@ -175,7 +185,7 @@ use mas_oidc_client::{
     requests::authorization_code::AuthorizationValidationData,
     types::{
         client_credentials::ClientCredentials,
-        errors::ClientError,
+        errors::{ClientError, ClientErrorCode},
         iana::oauth::OAuthTokenTypeHint,
         oidc::VerifiedProviderMetadata,
         registration::{ClientRegistrationResponse, VerifiedClientMetadata},
@@ -197,13 +207,17 @@ mod auth_code_builder;
 mod backend;
 mod cross_process;
 mod data_serde;
+mod device_code;
 mod end_session_builder;
+#[cfg(all(feature = "e2e-encryption", feature = "qrcode"))]
+pub mod qrcode;
 pub mod registrations;
 #[cfg(test)]
 mod tests;
 
 pub use self::{
     auth_code_builder::{OidcAuthCodeUrlBuilder, OidcAuthorizationData},
+    device_code::OidcDeviceAuthorizationData,
     end_session_builder::{OidcEndSessionData, OidcEndSessionUrlBuilder},
 };
 use self::{
@@ -875,26 +889,225 @@ impl Oidc {
         redirect_uri: Url,
         device_id: Option<String>,
     ) -> Result<OidcAuthCodeUrlBuilder, OidcError> {
-        // Generate the device ID if it is not provided.
-        let device_id = if let Some(device_id) = device_id {
-            device_id
-        } else {
-            rand::thread_rng()
-                .sample_iter(&rand::distributions::Alphanumeric)
-                .map(char::from)
-                .take(10)
-                .collect::<String>()
+        let scope = login_scope(device_id)?;
+        Ok(OidcAuthCodeUrlBuilder::new(self.clone(), scope, redirect_uri))
+    }
+
+    /// Login via OpenID Connect with the [Device Authorization Grant].
+    ///
+    /// This is meant for devices that can't open a web browser, or where it is
+    /// impractical to enter credentials, like CLI tools or TVs. The user
+    /// authorizes this client on another device, by visiting the returned
+    /// verification URI and entering the user code.
+    ///
+    /// This should be called after the registered client has been restored
+    /// with [`Oidc::restore_registered_client()`].
+    ///
+    /// After presenting the returned data to the user,
+    /// [`Oidc::finish_device_code_login()`] must be called to wait for the
+    /// authorization and complete the login.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_id` - The unique ID that will be associated with the session.
+    ///   If not set, a random one will be generated. It can be an existing
+    ///   device ID from a previous login call. Note that this should be done
+    ///   only if the client also holds the corresponding encryption keys.
+    ///
+    /// Returns an error if the provider doesn't support the Device
+    /// Authorization Grant, or if the request fails.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # async fn example(client: Client) -> anyhow::Result<()> {
+    /// let oidc = client.oidc();
+    ///
+    /// let data = oidc.login_with_device_code(None).await?;
+    ///
+    /// println!(
+    ///     "Visit {} and enter the code {}",
+    ///     data.verification_uri, data.user_code
+    /// );
+    ///
+    /// oidc.finish_device_code_login(data).await?;
+    ///
+    /// // The client is now logged in.
+    /// let _me = client.whoami().await?;
+    /// # Ok(()) }
+    /// ```
+    ///
+    /// [Device Authorization Grant]: https://datatracker.ietf.org/doc/html/rfc8628
+    pub async fn login_with_device_code(
+        &self,
+        device_id: Option<String>,
+    ) -> Result<OidcDeviceAuthorizationData, OidcError> {
+        let data = self.data().ok_or(OidcError::NotAuthenticated)?;
+        let provider_metadata = self.provider_metadata().await?;
+        let device_authorization_endpoint = provider_metadata
+            .device_authorization_endpoint
+            .as_ref()
+            .ok_or(OidcError::NoDeviceAuthorizationSupport)?;
+
+        let scope = login_scope(device_id)?;
+
+        let response = self
+            .backend
+            .request_device_authorization(
+                device_authorization_endpoint,
+                data.credentials.clone(),
+                scope,
+            )
+            .await?;
+
+        Ok(response.into())
+    }
+
+    /// Finish the login with the Device Authorization Grant.
+    ///
+    /// This polls the provider until the user authorized this client on
+    /// another device, then completes the login like
+    /// [`Oidc::finish_login()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data returned by [`Oidc::login_with_device_code()`].
+    ///
+    /// Returns an error if the user denied the authorization, if the user code
+    /// expired before the user authorized this client, or if a request fails.
+    pub async fn finish_device_code_login(&self, data: OidcDeviceAuthorizationData) -> Result<()> {
+        let auth_data = self.data().ok_or(OidcError::NotAuthenticated)?;
+        let provider_metadata = self.provider_metadata().await?;
+        let mut interval = data.interval;
+
+        let session_tokens = loop {
+            device_code::sleep(interval).await;
+
+            let result = self
+                .backend
+                .exchange_device_code(
+                    provider_metadata.clone(),
+                    auth_data.credentials.clone(),
+                    data.device_code.clone(),
+                )
+                .await;
+
+            match result {
+                Ok(session_tokens) => break session_tokens,
+                Err(OidcError::DeviceCodeGrant(error)) => match error.error {
+                    ClientErrorCode::AuthorizationPending => {}
+                    ClientErrorCode::SlowDown => {
+                        // The interval must be increased by 5 seconds, as per RFC 8628.
+                        interval += Duration::from_secs(5);
+                        trace!("Polling the token endpoint every {interval:?}");
+                    }
+                    _ => return Err(OidcError::DeviceCodeGrant(error).into()),
+                },
+                Err(error) => return Err(error.into()),
+            }
         };
 
-        let scope = [
-            ScopeToken::Openid,
-            ScopeToken::MatrixApi(MatrixApiScopeToken::Full),
-            ScopeToken::try_with_matrix_device(device_id).or(Err(OidcError::InvalidDeviceId))?,
-        ]
-        .into_iter()
-        .collect();
+        self.set_session_tokens(session_tokens);
+        self.finish_login().await
+    }
 
-        Ok(OidcAuthCodeUrlBuilder::new(self.clone(), scope, redirect_uri))
+    /// Log in this new device by scanning the QR code displayed by a device
+    /// that is already logged in.
+    ///
+    /// The client must have been registered with the provider, with
+    /// [`Oidc::register_client()`] or [`Oidc::restore_registered_client()`].
+    ///
+    /// The returned future reports its progress: the check code to display,
+    /// which the user must enter on the other device, and the code of the
+    /// [Device Authorization Grant]. Once it resolves, this device is logged in
+    /// and verified with the cross-signing keys of the other device.
+    ///
+    /// # Arguments
+    ///
+    /// * `qr_code_data` - The data of the scanned QR code.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, oidc::qrcode::{CheckCodeState, LoginProgress, QrCodeData}};
+    /// # use futures_util::StreamExt;
+    /// # async fn example(client: Client, qr_code_bytes: Vec<u8>) -> anyhow::Result<()> {
+    /// let qr_code_data = QrCodeData::from_bytes(qr_code_bytes)?;
+    /// let oidc = client.oidc();
+    ///
+    /// let login = oidc.login_with_qr_code(&qr_code_data);
+    /// let mut progress = login.subscribe_to_progress();
+    ///
+    /// tokio::spawn(async move {
+    ///     while let Some(state) = progress.next().await {
+    ///         if let LoginProgress::EstablishingSecureChannel {
+    ///             check_code: CheckCodeState::Display(check_code),
+    ///         } = state
+    ///         {
+    ///             println!("Enter {:02} on your other device", check_code.to_digit());
+    ///         }
+    ///     }
+    /// });
+    ///
+    /// login.await?;
+    /// # Ok(()) }
+    /// ```
+    ///
+    /// [Device Authorization Grant]: https://datatracker.ietf.org/doc/html/rfc8628
+    #[cfg(all(feature = "e2e-encryption", feature = "qrcode"))]
+    pub fn login_with_qr_code<'a>(
+        &'a self,
+        qr_code_data: &'a qrcode::QrCodeData,
+    ) -> qrcode::LoginWithQrCode<'a> {
+        qrcode::LoginWithQrCode::new(self, Some(qr_code_data))
+    }
+
+    /// Log in this new device by displaying a QR code, that a device that is
+    /// already logged in must scan.
+    ///
+    /// This is the same as [`Oidc::login_with_qr_code()`], except that the
+    /// progress of the returned future first contains the QR code to display,
+    /// and then a [`CheckCodeSender`] to send the check code that the user
+    /// enters, as displayed by the other device.
+    ///
+    /// [`CheckCodeSender`]: qrcode::CheckCodeSender
+    #[cfg(all(feature = "e2e-encryption", feature = "qrcode"))]
+    pub fn login_with_generated_qr_code(&self) -> qrcode::LoginWithQrCode<'_> {
+        qrcode::LoginWithQrCode::new(self, None)
+    }
+
+    /// Let a new device log in by scanning the QR code it displays.
+    ///
+    /// This client must be logged in, and have the private cross-signing keys,
+    /// which are shared with the new device so it is verified.
+    ///
+    /// The returned future reports its progress: the check code to display,
+    /// which the user must enter on the new device, and the URI to open in a
+    /// browser, so the user can authorize the new device with the provider.
+    ///
+    /// # Arguments
+    ///
+    /// * `qr_code_data` - The data of the scanned QR code.
+    #[cfg(all(feature = "e2e-encryption", feature = "qrcode"))]
+    pub fn grant_login_with_qr_code<'a>(
+        &'a self,
+        qr_code_data: &'a qrcode::QrCodeData,
+    ) -> qrcode::GrantLoginWithQrCode<'a> {
+        qrcode::GrantLoginWithQrCode::new(self, Some(qr_code_data))
+    }
+
+    /// Let a new device log in by displaying a QR code that it must scan.
+    ///
+    /// This is the same as [`Oidc::grant_login_with_qr_code()`], except that
+    /// the progress of the returned future first contains the QR code to
+    /// display, and then a [`CheckCodeSender`] to send the check code that the
+    /// user enters, as displayed by the new device.
+    ///
+    /// [`CheckCodeSender`]: qrcode::CheckCodeSender
+    #[cfg(all(feature = "e2e-encryption", feature = "qrcode"))]
+    pub fn grant_login_with_generated_qr_code(&self) -> qrcode::GrantLoginWithQrCode<'_> {
+        qrcode::GrantLoginWithQrCode::new(self, None)
     }
 
     /// Finish the login process.
@@ -1220,6 +1433,8 @@ impl Oidc {
     /// [`OidcEndSessionUrlBuilder`] will be provided to build the URL allowing
     /// the user to log out from their account in the provider's interface.
     ///
+    /// On success, the local search index of the event cache is cleared.
+    ///
     /// [RP-Initiated Logout]: https://openid.net/specs/openid-connect-rpinitiated-1_0.html
     pub async fn logout(&self) -> Result<Option<OidcEndSessionUrlBuilder>, OidcError> {
         let provider_metadata = self.provider_metadata().await?;
@@ -1265,6 +1480,10 @@ impl Oidc {
             manager.on_logout().await?;
         }
 
+        if let Err(err) = self.client.event_cache().clear_search_index().await {
+            error!("Failed to clear the local search index after logging out: {err}");
+        }
+
         Ok(end_session_builder)
     }
 }
@@ -1448,6 +1667,22 @@ pub enum OidcError {
     #[error("no token revocation support")]
     NoRevocationSupport,
 
+    /// The OpenID Connect Provider doesn't support the Device Authorization
+    /// Grant.
+    #[error("no device authorization support")]
+    NoDeviceAuthorizationSupport,
+
+    /// The provider returned an error during the Device Authorization Grant.
+    ///
+    /// This is the case if the user denied the authorization, or if the user
+    /// code expired.
+    #[error("device authorization failed: {}", .0.error)]
+    DeviceCodeGrant(ClientError),
+
+    /// An error occurred when sending a request to the provider.
+    #[error(transparent)]
+    Request(reqwest::Error),
+
     /// An error occurred generating a random value.
     #[error(transparent)]
     Rand(rand::Error),
@@ -1470,6 +1705,29 @@ where
     }
 }
 
+/// The scope to request to log in with the given device ID.
+///
+/// A random device ID is generated if it is not provided.
+fn login_scope(device_id: Option<String>) -> Result<Scope, OidcError> {
+    let device_id = if let Some(device_id) = device_id {
+        device_id
+    } else {
+        rand::thread_rng()
+            .sample_iter(&rand::distributions::Alphanumeric)
+            .map(char::from)
+            .take(10)
+            .collect::<String>()
+    };
+
+    Ok([
+        ScopeToken::Openid,
+        ScopeToken::MatrixApi(MatrixApiScopeToken::Full),
+        ScopeToken::try_with_matrix_device(device_id).or(Err(OidcError::InvalidDeviceId))?,
+    ]
+    .into_iter()
+    .collect())
+}
+
 fn rng() -> Result<StdRng, OidcError> {
     StdRng::from_rng(rand::thread_rng()).map_err(OidcError::Rand)
 }
@@ -0,0 +1,280 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+//! The side of the existing device, that lets a new device log in.
+
+use std::{future::IntoFuture, time::Duration};
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk_common::boxed_into_future;
+use ruma::{DeviceId, OwnedDeviceId};
+use tracing::{info, trace};
+use url::Url;
+
+use super::{
+    login::{connect_generated_qr_code, unexpected_message, QrCodeProgress},
+    messages::{
+        BackupSecrets, CrossSigningSecrets, LoginFailureReason, LoginProtocolType, QrAuthMessage,
+    },
+    secure_channel::EstablishedSecureChannel,
+    CheckCodeState, QrCodeData, QrCodeLoginError, QrCodeModeData,
+};
+use crate::{
+    oidc::{device_code::sleep, Oidc},
+    Client,
+};
+
+/// The number of times we check whether the new device appeared on the
+/// homeserver, after it reported that it logged in.
+const DEVICE_QUERY_ATTEMPTS: u32 = 5;
+
+/// The time to wait between two checks of whether the new device appeared.
+const DEVICE_QUERY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The progress of the login of a new device with a QR code, on the existing
+/// device.
+#[derive(Clone, Debug, Default)]
+pub enum GrantLoginProgress {
+    /// The login is starting.
+    #[default]
+    Starting,
+    /// The QR code to display is ready, the new device must scan it.
+    ///
+    /// This is only reported by
+    /// [`Oidc::grant_login_with_generated_qr_code()`].
+    QrReady {
+        /// The data of the QR code to display.
+        qr_code_data: QrCodeData,
+    },
+    /// The new device connected, the secure channel is being established.
+    EstablishingSecureChannel {
+        /// The check code that the user must compare on both devices.
+        check_code: CheckCodeState,
+    },
+    /// The user must authorize the new device with the OpenID Connect
+    /// Provider.
+    WaitingForAuth {
+        /// The URI to open in a browser to authorize the new device.
+        verification_uri: Url,
+    },
+    /// The new device logged in and is receiving the secrets.
+    SyncingSecrets,
+    /// The login is done, the new device is logged in and verified.
+    Done,
+}
+
+impl From<QrCodeProgress> for GrantLoginProgress {
+    fn from(progress: QrCodeProgress) -> Self {
+        match progress {
+            QrCodeProgress::QrReady(qr_code_data) => Self::QrReady { qr_code_data },
+            QrCodeProgress::EstablishingSecureChannel(check_code) => {
+                Self::EstablishingSecureChannel { check_code }
+            }
+        }
+    }
+}
+
+/// Future returned by [`Oidc::grant_login_with_qr_code()`] and
+/// [`Oidc::grant_login_with_generated_qr_code()`].
+#[allow(missing_debug_implementations)]
+pub struct GrantLoginWithQrCode<'a> {
+    oidc: &'a Oidc,
+    /// The QR code scanned by this device, or `None` if this device must
+    /// generate it.
+    qr_code_data: Option<&'a QrCodeData>,
+    state: SharedObservable<GrantLoginProgress>,
+}
+
+impl<'a> GrantLoginWithQrCode<'a> {
+    pub(crate) fn new(oidc: &'a Oidc, qr_code_data: Option<&'a QrCodeData>) -> Self {
+        Self { oidc, qr_code_data, state: Default::default() }
+    }
+
+    /// Get a subscriber to observe the progress of the login.
+    pub fn subscribe_to_progress(&self) -> Subscriber<GrantLoginProgress> {
+        self.state.subscribe()
+    }
+}
+
+impl<'a> IntoFuture for GrantLoginWithQrCode<'a> {
+    type Output = Result<(), QrCodeLoginError>;
+    boxed_into_future!(extra_bounds: 'a);
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            let Self { oidc, qr_code_data, state } = self;
+            let client = &oidc.client;
+
+            // Don't bother the user if we can't verify the new device.
+            let cross_signing = export_cross_signing_keys(client).await?;
+
+            let mut channel = match qr_code_data {
+                Some(qr_code_data) => {
+                    // The new device displays the QR code.
+                    if qr_code_data.mode_data != QrCodeModeData::Login {
+                        return Err(QrCodeLoginError::InvalidQrCodeMode);
+                    }
+
+                    let mut channel = EstablishedSecureChannel::from_qr_code(
+                        client.inner.http_client.inner.clone(),
+                        qr_code_data,
+                    )
+                    .await?;
+
+                    state.set(GrantLoginProgress::EstablishingSecureChannel {
+                        check_code: CheckCodeState::Display(channel.check_code()),
+                    });
+
+                    // We must tell the new device how it can log in.
+                    channel
+                        .send_json(&QrAuthMessage::LoginProtocols {
+                            protocols: vec![LoginProtocolType::DeviceAuthorizationGrant],
+                            homeserver: client.homeserver(),
+                        })
+                        .await?;
+
+                    channel
+                }
+                None => {
+                    let user_id = client.user_id().ok_or(crate::Error::AuthenticationRequired)?;
+                    let mode_data = QrCodeModeData::Reciprocate {
+                        server_name: user_id.server_name().to_string(),
+                    };
+
+                    connect_generated_qr_code(client, mode_data, &state).await?
+                }
+            };
+
+            let (authorization_grant, device_id) = match channel.receive_json().await? {
+                QrAuthMessage::LoginProtocol {
+                    protocol,
+                    device_authorization_grant,
+                    device_id,
+                } => {
+                    if protocol != LoginProtocolType::DeviceAuthorizationGrant {
+                        let reason = LoginFailureReason::UnsupportedProtocol;
+                        channel.send_json(&QrAuthMessage::failure(reason.clone())).await?;
+                        return Err(QrCodeLoginError::LoginFailure { reason, homeserver: None });
+                    }
+
+                    (device_authorization_grant, OwnedDeviceId::from(device_id))
+                }
+                message => return Err(unexpected_message(&mut channel, message).await),
+            };
+
+            // Make sure the new device doesn't reuse the ID of one of our devices.
+            if find_own_device(client, &device_id).await? {
+                channel
+                    .send_json(&QrAuthMessage::failure(LoginFailureReason::DeviceAlreadyExists))
+                    .await?;
+                return Err(QrCodeLoginError::DeviceAlreadyExists);
+            }
+
+            channel.send_json(&QrAuthMessage::LoginProtocolAccepted).await?;
+
+            state.set(GrantLoginProgress::WaitingForAuth {
+                verification_uri: authorization_grant
+                    .verification_uri_complete
+                    .unwrap_or(authorization_grant.verification_uri),
+            });
+
+            match channel.receive_json().await? {
+                QrAuthMessage::LoginSuccess => {}
+                message => return Err(unexpected_message(&mut channel, message).await),
+            }
+
+            state.set(GrantLoginProgress::SyncingSecrets);
+
+            // The new device uploaded its keys before telling us it logged in, but
+            // the homeserver might take some time to serve them.
+            let mut found = false;
+
+            for _ in 0..DEVICE_QUERY_ATTEMPTS {
+                if find_own_device(client, &device_id).await? {
+                    found = true;
+                    break;
+                }
+
+                trace!(%device_id, "The new device wasn't found yet");
+                sleep(DEVICE_QUERY_INTERVAL).await;
+            }
+
+            if !found {
+                channel
+                    .send_json(&QrAuthMessage::failure(LoginFailureReason::DeviceNotFound))
+                    .await?;
+                return Err(QrCodeLoginError::DeviceNotFound);
+            }
+
+            let backup = export_backup_key(client).await?;
+            channel.send_json(&QrAuthMessage::LoginSecrets { cross_signing, backup }).await?;
+
+            info!(%device_id, "Sent the secrets to the new device");
+
+            state.set(GrantLoginProgress::Done);
+
+            Ok(())
+        })
+    }
+}
+
+/// Export our private cross-signing keys, which we need to share all of.
+async fn export_cross_signing_keys(
+    client: &Client,
+) -> Result<CrossSigningSecrets, QrCodeLoginError> {
+    let olm_machine = client.olm_machine().await;
+    let olm_machine = olm_machine.as_ref().ok_or(crate::Error::NoOlmMachine)?;
+
+    let export =
+        olm_machine.export_cross_signing_keys().await?.ok_or(QrCodeLoginError::MissingSecrets)?;
+
+    match (&export.master_key, &export.self_signing_key, &export.user_signing_key) {
+        (Some(master_key), Some(self_signing_key), Some(user_signing_key)) => {
+            Ok(CrossSigningSecrets {
+                master_key: master_key.clone(),
+                self_signing_key: self_signing_key.clone(),
+                user_signing_key: user_signing_key.clone(),
+            })
+        }
+        _ => Err(QrCodeLoginError::MissingSecrets),
+    }
+}
+
+/// Export the key of the active backup, if we have one.
+async fn export_backup_key(client: &Client) -> Result<Option<BackupSecrets>, QrCodeLoginError> {
+    let olm_machine = client.olm_machine().await;
+    let olm_machine = olm_machine.as_ref().ok_or(crate::Error::NoOlmMachine)?;
+
+    let backup_keys = olm_machine.backup_machine().get_backup_keys().await?;
+
+    Ok(backup_keys.decryption_key.zip(backup_keys.backup_version).map(|(key, backup_version)| {
+        BackupSecrets {
+            algorithm: "m.megolm_backup.v1.curve25519-aes-sha2".to_owned(),
+            key: key.to_base64(),
+            backup_version,
+        }
+    }))
+}
+
+/// Query the devices of our own user, and check whether the given device is
+/// one of them.
+async fn find_own_device(client: &Client, device_id: &DeviceId) -> Result<bool, QrCodeLoginError> {
+    let olm_machine = client.olm_machine().await;
+    let olm_machine = olm_machine.as_ref().ok_or(crate::Error::NoOlmMachine)?;
+
+    let (request_id, request) = olm_machine.query_keys_for_users([olm_machine.user_id()]);
+    client.keys_query(&request_id, request.device_keys).await?;
+
+    Ok(olm_machine.get_device(olm_machine.user_id(), device_id, None).await?.is_some())
+}
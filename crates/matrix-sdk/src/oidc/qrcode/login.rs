@@ -0,0 +1,313 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+//! The side of the new device, that wants to log in.
+
+use std::future::IntoFuture;
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk_base::crypto::CrossSigningKeyExport;
+use matrix_sdk_common::boxed_into_future;
+use ruma::DeviceId;
+use tracing::{info, trace};
+
+use super::{
+    messages::{
+        AuthorizationGrant, BackupSecrets, CrossSigningSecrets, LoginFailureReason,
+        LoginProtocolType, QrAuthMessage,
+    },
+    secure_channel::{EstablishedSecureChannel, SecureChannel},
+    CheckCodeSender, CheckCodeState, QrCodeData, QrCodeLoginError, QrCodeModeData,
+    SecureChannelError,
+};
+use crate::{
+    oidc::{Oidc, OidcError},
+    Client,
+};
+
+/// The progress of the login of the new device with a QR code.
+#[derive(Clone, Debug, Default)]
+pub enum LoginProgress {
+    /// The login is starting.
+    #[default]
+    Starting,
+    /// The QR code to display is ready, the other device must scan it.
+    ///
+    /// This is only reported by [`Oidc::login_with_generated_qr_code()`].
+    QrReady {
+        /// The data of the QR code to display.
+        qr_code_data: QrCodeData,
+    },
+    /// The other device connected, the secure channel is being established.
+    EstablishingSecureChannel {
+        /// The check code that the user must compare on both devices.
+        check_code: CheckCodeState,
+    },
+    /// The user must authorize this device on the other device.
+    WaitingForToken {
+        /// The code that the user might need to confirm on the other device.
+        user_code: String,
+    },
+    /// This device logged in and is receiving the secrets from the other
+    /// device.
+    SyncingSecrets,
+    /// The login is done, this device is logged in and verified.
+    Done,
+}
+
+/// Future returned by [`Oidc::login_with_qr_code()`] and
+/// [`Oidc::login_with_generated_qr_code()`].
+#[allow(missing_debug_implementations)]
+pub struct LoginWithQrCode<'a> {
+    oidc: &'a Oidc,
+    /// The QR code scanned by this device, or `None` if this device must
+    /// generate it.
+    qr_code_data: Option<&'a QrCodeData>,
+    state: SharedObservable<LoginProgress>,
+}
+
+impl<'a> LoginWithQrCode<'a> {
+    pub(crate) fn new(oidc: &'a Oidc, qr_code_data: Option<&'a QrCodeData>) -> Self {
+        Self { oidc, qr_code_data, state: Default::default() }
+    }
+
+    /// Get a subscriber to observe the progress of the login.
+    pub fn subscribe_to_progress(&self) -> Subscriber<LoginProgress> {
+        self.state.subscribe()
+    }
+}
+
+impl<'a> IntoFuture for LoginWithQrCode<'a> {
+    type Output = Result<(), QrCodeLoginError>;
+    boxed_into_future!(extra_bounds: 'a);
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            let Self { oidc, qr_code_data, state } = self;
+            let client = &oidc.client;
+
+            let mut channel = match qr_code_data {
+                Some(qr_code_data) => {
+                    // The existing device displays the QR code.
+                    if !matches!(qr_code_data.mode_data, QrCodeModeData::Reciprocate { .. }) {
+                        return Err(QrCodeLoginError::InvalidQrCodeMode);
+                    }
+
+                    let channel = EstablishedSecureChannel::from_qr_code(
+                        client.inner.http_client.inner.clone(),
+                        qr_code_data,
+                    )
+                    .await?;
+
+                    state.set(LoginProgress::EstablishingSecureChannel {
+                        check_code: CheckCodeState::Display(channel.check_code()),
+                    });
+
+                    channel
+                }
+                None => {
+                    let mut channel =
+                        connect_generated_qr_code(client, QrCodeModeData::Login, &state).await?;
+
+                    // The existing device must first tell us how we can log in.
+                    match channel.receive_json().await? {
+                        QrAuthMessage::LoginProtocols { protocols, homeserver } => {
+                            trace!(%homeserver, "Received the login protocols");
+
+                            if !protocols.contains(&LoginProtocolType::DeviceAuthorizationGrant) {
+                                let reason = LoginFailureReason::UnsupportedProtocol;
+                                channel.send_json(&QrAuthMessage::failure(reason.clone())).await?;
+                                return Err(QrCodeLoginError::LoginFailure {
+                                    reason,
+                                    homeserver: None,
+                                });
+                            }
+                        }
+                        message => return Err(unexpected_message(&mut channel, message).await),
+                    }
+
+                    channel
+                }
+            };
+
+            let device_id = DeviceId::new();
+            let authorization_data =
+                oidc.login_with_device_code(Some(device_id.to_string())).await?;
+
+            channel
+                .send_json(&QrAuthMessage::LoginProtocol {
+                    protocol: LoginProtocolType::DeviceAuthorizationGrant,
+                    device_authorization_grant: AuthorizationGrant {
+                        verification_uri: authorization_data.verification_uri.clone(),
+                        verification_uri_complete: authorization_data
+                            .verification_uri_complete
+                            .clone(),
+                    },
+                    device_id: device_id.to_string(),
+                })
+                .await?;
+
+            match channel.receive_json().await? {
+                QrAuthMessage::LoginProtocolAccepted => {}
+                QrAuthMessage::LoginFailure { reason, homeserver } => {
+                    return Err(QrCodeLoginError::LoginFailure { reason, homeserver });
+                }
+                message => return Err(unexpected_message(&mut channel, message).await),
+            }
+
+            state.set(LoginProgress::WaitingForToken {
+                user_code: authorization_data.user_code.clone(),
+            });
+
+            if let Err(error) = oidc.finish_device_code_login(authorization_data).await {
+                if matches!(error, crate::Error::Oidc(OidcError::DeviceCodeGrant(_))) {
+                    channel
+                        .send_json(&QrAuthMessage::failure(
+                            LoginFailureReason::AuthorizationExpired,
+                        ))
+                        .await?;
+                }
+
+                return Err(error.into());
+            }
+
+            // Upload our device keys, so the other device can find and verify this
+            // device.
+            client.send_outgoing_requests().await?;
+
+            channel.send_json(&QrAuthMessage::LoginSuccess).await?;
+
+            state.set(LoginProgress::SyncingSecrets);
+
+            match channel.receive_json().await? {
+                QrAuthMessage::LoginSecrets { cross_signing, backup } => {
+                    import_secrets(client, cross_signing, backup).await?;
+                }
+                QrAuthMessage::LoginFailure { reason, homeserver } => {
+                    return Err(QrCodeLoginError::LoginFailure { reason, homeserver });
+                }
+                message => return Err(unexpected_message(&mut channel, message).await),
+            }
+
+            state.set(LoginProgress::Done);
+
+            Ok(())
+        })
+    }
+}
+
+/// Display a QR code and wait for the other device to scan it.
+///
+/// This is used by both sides of the login, so it's generic over the progress
+/// type.
+pub(super) async fn connect_generated_qr_code<P>(
+    client: &Client,
+    mode_data: QrCodeModeData,
+    state: &SharedObservable<P>,
+) -> Result<EstablishedSecureChannel, QrCodeLoginError>
+where
+    P: Clone + From<QrCodeProgress>,
+{
+    let channel =
+        SecureChannel::new(client.inner.http_client.inner.clone(), &client.homeserver(), mode_data)
+            .await?;
+
+    state.set(QrCodeProgress::QrReady(channel.qr_code_data().clone()).into());
+
+    let channel = channel.connect().await?;
+
+    let (sender, receiver) = CheckCodeSender::new();
+    state.set(QrCodeProgress::EstablishingSecureChannel(CheckCodeState::Input(sender)).into());
+
+    let check_code = receiver.await.map_err(|_| SecureChannelError::CheckCodeCancelled)?;
+
+    Ok(channel.confirm(check_code)?)
+}
+
+/// The progress updates shared by both sides of the login, when displaying
+/// the QR code.
+pub(super) enum QrCodeProgress {
+    QrReady(QrCodeData),
+    EstablishingSecureChannel(CheckCodeState),
+}
+
+impl From<QrCodeProgress> for LoginProgress {
+    fn from(progress: QrCodeProgress) -> Self {
+        match progress {
+            QrCodeProgress::QrReady(qr_code_data) => Self::QrReady { qr_code_data },
+            QrCodeProgress::EstablishingSecureChannel(check_code) => {
+                Self::EstablishingSecureChannel { check_code }
+            }
+        }
+    }
+}
+
+/// Tell the other device that we received an unexpected message, and return
+/// the corresponding error.
+pub(super) async fn unexpected_message(
+    channel: &mut EstablishedSecureChannel,
+    message: QrAuthMessage,
+) -> QrCodeLoginError {
+    if let QrAuthMessage::LoginFailure { reason, homeserver } = message {
+        return QrCodeLoginError::LoginFailure { reason, homeserver };
+    }
+
+    let failure = QrAuthMessage::failure(LoginFailureReason::UnexpectedMessageReceived);
+
+    match channel.send_json(&failure).await {
+        Ok(()) => QrCodeLoginError::UnexpectedMessage(message.message_type()),
+        Err(error) => error.into(),
+    }
+}
+
+/// Import the secrets received from the existing device, and verify this
+/// device with the self-signing key.
+async fn import_secrets(
+    client: &Client,
+    cross_signing: CrossSigningSecrets,
+    backup: Option<BackupSecrets>,
+) -> Result<(), QrCodeLoginError> {
+    let olm_machine = client.olm_machine().await;
+    let olm_machine = olm_machine.as_ref().ok_or(crate::Error::NoOlmMachine)?;
+
+    // Make sure we have the public cross-signing keys, the private parts are only
+    // imported if they match.
+    let (request_id, request) = olm_machine.query_keys_for_users([olm_machine.user_id()]);
+    client.keys_query(&request_id, request.device_keys).await?;
+
+    let export = CrossSigningKeyExport {
+        master_key: Some(cross_signing.master_key),
+        self_signing_key: Some(cross_signing.self_signing_key),
+        user_signing_key: Some(cross_signing.user_signing_key),
+    };
+    let status = olm_machine.import_cross_signing_keys(export).await?;
+
+    if status.has_self_signing {
+        if let Some(own_device) = client.encryption().get_own_device().await? {
+            own_device.verify().await?;
+
+            // Attach the signature we just uploaded to the device in storage.
+            let (request_id, request) = olm_machine.query_keys_for_users([olm_machine.user_id()]);
+            client.keys_query(&request_id, request.device_keys).await?;
+
+            info!("Successfully signed our own device, the device is now verified");
+        }
+    }
+
+    if let Some(backup) = backup {
+        client.encryption().backups().maybe_enable_backups(&backup.key).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,178 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+//! The messages exchanged between the two devices over the secure channel.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The login protocols that can be used to log in the new device.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum LoginProtocolType {
+    /// The [OAuth 2.0 Device Authorization Grant].
+    ///
+    /// [OAuth 2.0 Device Authorization Grant]: https://datatracker.ietf.org/doc/html/rfc8628
+    DeviceAuthorizationGrant,
+}
+
+/// The reason why the login of the new device failed, as reported by one of
+/// the two devices.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginFailureReason {
+    /// The user didn't authorize the new device in time.
+    AuthorizationExpired,
+    /// The device ID chosen by the new device is already in use.
+    DeviceAlreadyExists,
+    /// The new device didn't appear on the homeserver after it logged in.
+    DeviceNotFound,
+    /// A message that wasn't expected at this stage was received.
+    UnexpectedMessageReceived,
+    /// None of the login protocols of the new device are supported.
+    UnsupportedProtocol,
+    /// The user declined the login on one of the devices.
+    UserCancelled,
+}
+
+/// The part of the Device Authorization Grant that the existing device needs
+/// to authorize the new device.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(super) struct AuthorizationGrant {
+    pub verification_uri: Url,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification_uri_complete: Option<Url>,
+}
+
+/// The private cross-signing keys, encoded as unpadded base64.
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) struct CrossSigningSecrets {
+    pub master_key: String,
+    pub self_signing_key: String,
+    pub user_signing_key: String,
+}
+
+/// The key of the active room key backup.
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) struct BackupSecrets {
+    pub algorithm: String,
+    /// The backup decryption key, encoded as unpadded base64.
+    pub key: String,
+    pub backup_version: String,
+}
+
+/// A message exchanged between the two devices.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub(super) enum QrAuthMessage {
+    /// The login protocols supported by the existing device.
+    #[serde(rename = "m.login.protocols")]
+    LoginProtocols { protocols: Vec<LoginProtocolType>, homeserver: Url },
+
+    /// The login protocol chosen by the new device.
+    #[serde(rename = "m.login.protocol")]
+    LoginProtocol {
+        protocol: LoginProtocolType,
+        device_authorization_grant: AuthorizationGrant,
+        device_id: String,
+    },
+
+    /// The existing device accepted the login protocol.
+    #[serde(rename = "m.login.protocol_accepted")]
+    LoginProtocolAccepted,
+
+    /// The new device logged in successfully.
+    #[serde(rename = "m.login.success")]
+    LoginSuccess,
+
+    /// The login failed on one of the devices.
+    #[serde(rename = "m.login.failure")]
+    LoginFailure {
+        reason: LoginFailureReason,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        homeserver: Option<Url>,
+    },
+
+    /// The secrets shared by the existing device with the new device.
+    #[serde(rename = "m.login.secrets")]
+    LoginSecrets {
+        cross_signing: CrossSigningSecrets,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        backup: Option<BackupSecrets>,
+    },
+}
+
+impl QrAuthMessage {
+    /// A failure message with the given reason.
+    pub(super) fn failure(reason: LoginFailureReason) -> Self {
+        Self::LoginFailure { reason, homeserver: None }
+    }
+
+    /// The type of the message.
+    pub(super) fn message_type(&self) -> &'static str {
+        match self {
+            Self::LoginProtocols { .. } => "m.login.protocols",
+            Self::LoginProtocol { .. } => "m.login.protocol",
+            Self::LoginProtocolAccepted => "m.login.protocol_accepted",
+            Self::LoginSuccess => "m.login.success",
+            Self::LoginFailure { .. } => "m.login.failure",
+            Self::LoginSecrets { .. } => "m.login.secrets",
+        }
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for QrAuthMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Don't leak the secrets in the logs.
+        f.debug_struct("QrAuthMessage").field("type", &self.message_type()).finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{LoginFailureReason, LoginProtocolType, QrAuthMessage};
+
+    #[test]
+    fn test_serialize_messages() {
+        let message = QrAuthMessage::LoginProtocols {
+            protocols: vec![LoginProtocolType::DeviceAuthorizationGrant],
+            homeserver: "https://matrix.example.org".parse().unwrap(),
+        };
+        assert_eq!(
+            serde_json::to_value(message).unwrap(),
+            json!({
+                "type": "m.login.protocols",
+                "protocols": ["device_authorization_grant"],
+                "homeserver": "https://matrix.example.org/",
+            })
+        );
+
+        let message = QrAuthMessage::failure(LoginFailureReason::UserCancelled);
+        assert_eq!(
+            serde_json::to_value(message).unwrap(),
+            json!({ "type": "m.login.failure", "reason": "user_cancelled" })
+        );
+
+        let message = QrAuthMessage::LoginProtocolAccepted;
+        assert_eq!(
+            serde_json::to_value(message).unwrap(),
+            json!({ "type": "m.login.protocol_accepted" })
+        );
+    }
+}
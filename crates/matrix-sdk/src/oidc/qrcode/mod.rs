@@ -0,0 +1,241 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+//! Log in a new device by scanning a QR code, as defined in [MSC4108].
+//!
+//! One of the two devices displays a QR code, that the other device scans.
+//! This lets both devices establish a secure channel through a rendezvous
+//! session on the homeserver. Over this channel:
+//!
+//! 1. The new device starts an [OAuth 2.0 Device Authorization Grant], and
+//!    sends the verification URI to the existing device.
+//! 2. The existing device opens the verification URI, so the user can authorize
+//!    the new device with the OpenID Connect Provider.
+//! 3. The new device logs in, and the existing device sends it the private
+//!    cross-signing keys and the backup key, so the new device is verified and
+//!    can access the room key backup.
+//!
+//! The new device uses [`Oidc::login_with_qr_code()`] or
+//! [`Oidc::login_with_generated_qr_code()`], and the existing device uses
+//! [`Oidc::grant_login_with_qr_code()`] or
+//! [`Oidc::grant_login_with_generated_qr_code()`]. The futures returned by
+//! these methods report their progress, which contains the QR code to display
+//! and the check code that the user must compare on both devices.
+//!
+//! [MSC4108]: https://github.com/matrix-org/matrix-spec-proposals/pull/4108
+//! [OAuth 2.0 Device Authorization Grant]: https://datatracker.ietf.org/doc/html/rfc8628
+//! [`Oidc::login_with_qr_code()`]: super::Oidc::login_with_qr_code
+//! [`Oidc::login_with_generated_qr_code()`]: super::Oidc::login_with_generated_qr_code
+//! [`Oidc::grant_login_with_qr_code()`]: super::Oidc::grant_login_with_qr_code
+//! [`Oidc::grant_login_with_generated_qr_code()`]: super::Oidc::grant_login_with_generated_qr_code
+
+use std::sync::{Arc, Mutex};
+
+pub use matrix_sdk_base::crypto::matrix_sdk_qrcode::{
+    DecodingError, EncodingError, QrCodeData, QrCodeModeData,
+};
+use matrix_sdk_base::crypto::{vodozemac, SecretImportError};
+use thiserror::Error;
+use tokio::sync::oneshot;
+use url::Url;
+
+use super::OidcError;
+use crate::encryption::identities::ManualVerifyError;
+
+mod grant;
+mod login;
+mod messages;
+mod rendezvous;
+mod secure_channel;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests;
+
+pub use self::{
+    grant::{GrantLoginProgress, GrantLoginWithQrCode},
+    login::{LoginProgress, LoginWithQrCode},
+    messages::LoginFailureReason,
+};
+
+/// An error that happened during the login of a new device with a QR code.
+#[derive(Debug, Error)]
+pub enum QrCodeLoginError {
+    /// An error happened with the OpenID Connect Provider.
+    #[error(transparent)]
+    Oidc(#[from] OidcError),
+
+    /// An error happened with the secure channel between the two devices.
+    #[error(transparent)]
+    SecureChannel(#[from] SecureChannelError),
+
+    /// The other device reported that the login failed.
+    #[error("the other device reported that the login failed: {reason:?}")]
+    LoginFailure {
+        /// The reason of the failure.
+        reason: LoginFailureReason,
+        /// The homeserver that the new device should use, if the failure was
+        /// caused by the new device using the wrong homeserver.
+        homeserver: Option<Url>,
+    },
+
+    /// The other device sent a message that wasn't expected at this stage.
+    #[error("received an unexpected message: {0}")]
+    UnexpectedMessage(&'static str),
+
+    /// The QR code has a mode that doesn't match the role of this device.
+    #[error("the QR code wasn't generated for this kind of device")]
+    InvalidQrCodeMode,
+
+    /// The existing device doesn't have the private cross-signing keys, so it
+    /// can't verify the new device.
+    #[error("the private cross-signing keys are missing")]
+    MissingSecrets,
+
+    /// The new device didn't appear on the homeserver after it logged in.
+    #[error("the new device wasn't found on the homeserver")]
+    DeviceNotFound,
+
+    /// The device ID chosen by the new device is already in use.
+    #[error("the device ID of the new device is already in use")]
+    DeviceAlreadyExists,
+
+    /// The private cross-signing keys sent by the existing device couldn't be
+    /// imported.
+    #[error(transparent)]
+    SecretImport(#[from] SecretImportError),
+
+    /// This device couldn't be signed with the imported self-signing key.
+    #[error(transparent)]
+    Verification(#[from] ManualVerifyError),
+
+    /// An error happened in the rest of the SDK.
+    #[error(transparent)]
+    Sdk(#[from] Box<crate::Error>),
+}
+
+impl From<crate::Error> for QrCodeLoginError {
+    fn from(error: crate::Error) -> Self {
+        Self::Sdk(Box::new(error))
+    }
+}
+
+impl From<matrix_sdk_base::crypto::CryptoStoreError> for QrCodeLoginError {
+    fn from(error: matrix_sdk_base::crypto::CryptoStoreError) -> Self {
+        crate::Error::from(error).into()
+    }
+}
+
+/// An error that happened with the secure channel between the two devices.
+#[derive(Debug, Error)]
+pub enum SecureChannelError {
+    /// An error happened when communicating with the rendezvous server.
+    #[error(transparent)]
+    Rendezvous(#[from] reqwest::Error),
+
+    /// The URL of the rendezvous server is invalid.
+    #[error(transparent)]
+    InvalidUrl(#[from] url::ParseError),
+
+    /// The rendezvous server didn't return an `ETag` header.
+    #[error("the rendezvous server didn't return an ETag")]
+    MissingEtag,
+
+    /// The message received from the other device couldn't be decoded.
+    #[error(transparent)]
+    MessageDecode(#[from] vodozemac::ecies::MessageDecodeError),
+
+    /// The secure channel couldn't be established, or a message couldn't be
+    /// decrypted.
+    #[error(transparent)]
+    Ecies(#[from] vodozemac::ecies::Error),
+
+    /// The message received from the other device couldn't be deserialized.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// The first message exchanged over the secure channel is invalid.
+    #[error("the secure channel couldn't be established")]
+    InvalidInitialMessage,
+
+    /// The check code entered by the user doesn't match the one of the secure
+    /// channel.
+    #[error("the check code doesn't match")]
+    InvalidCheckCode,
+
+    /// The check code was never entered, because the [`CheckCodeSender`] was
+    /// dropped.
+    #[error("the check code wasn't entered")]
+    CheckCodeCancelled,
+}
+
+/// The check code of the secure channel, that the user must compare on both
+/// devices to make sure that the right device scanned the QR code.
+///
+/// The check code is a number between 0 and 99, that should be displayed as
+/// two digits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckCode(u8);
+
+impl CheckCode {
+    pub(super) fn new(bytes: [u8; 2]) -> Self {
+        Self((bytes[0] % 10) * 10 + bytes[1] % 10)
+    }
+
+    /// The check code as a number between 0 and 99.
+    pub fn to_digit(&self) -> u8 {
+        self.0
+    }
+}
+
+/// Where the check code of the secure channel comes from.
+#[derive(Clone, Debug)]
+pub enum CheckCodeState {
+    /// The check code must be displayed to the user, so they can enter it on
+    /// the other device.
+    Display(CheckCode),
+    /// The user must enter the check code displayed on the other device.
+    Input(CheckCodeSender),
+}
+
+/// An error when sending the check code with a [`CheckCodeSender`].
+#[derive(Debug, Error)]
+pub enum CheckCodeSenderError {
+    /// The check code was already sent.
+    #[error("the check code was already sent")]
+    AlreadySent,
+    /// The login was aborted, so nobody is waiting for the check code anymore.
+    #[error("the login was aborted")]
+    CannotSend,
+}
+
+/// The sender of the check code entered by the user.
+#[derive(Clone, Debug)]
+pub struct CheckCodeSender {
+    inner: Arc<Mutex<Option<oneshot::Sender<u8>>>>,
+}
+
+impl CheckCodeSender {
+    pub(super) fn new() -> (Self, oneshot::Receiver<u8>) {
+        let (sender, receiver) = oneshot::channel();
+        (Self { inner: Arc::new(Mutex::new(Some(sender))) }, receiver)
+    }
+
+    /// Send the check code entered by the user, as a number between 0 and 99.
+    ///
+    /// The login fails if it doesn't match the check code of the other
+    /// device.
+    pub fn send(&self, check_code: u8) -> Result<(), CheckCodeSenderError> {
+        let sender = self.inner.lock().unwrap().take().ok_or(CheckCodeSenderError::AlreadySent)?;
+        sender.send(check_code).map_err(|_| CheckCodeSenderError::CannotSend)
+    }
+}
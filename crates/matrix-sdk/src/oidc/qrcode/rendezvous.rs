@@ -0,0 +1,294 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+//! A client for the rendezvous sessions of [MSC4108].
+//!
+//! A rendezvous session is a mailbox on the rendezvous server that two
+//! devices use to exchange messages. Each message replaces the previous one,
+//! and the `ETag` header is used to detect when the content of the mailbox
+//! changed.
+//!
+//! [MSC4108]: https://github.com/matrix-org/matrix-spec-proposals/pull/4108
+
+use std::time::Duration;
+
+use http::{
+    header::{CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH},
+    StatusCode,
+};
+use serde::Deserialize;
+use tracing::{instrument, trace};
+use url::Url;
+
+use super::SecureChannelError;
+use crate::oidc::device_code::sleep;
+
+/// The path of the endpoint to create a rendezvous session.
+const RENDEZVOUS_PATH: &str = "_matrix/client/unstable/org.matrix.msc4108/rendezvous";
+
+/// The time to wait between two polls of the rendezvous session.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The response of the rendezvous server when creating a session.
+#[derive(Deserialize)]
+struct CreateSessionResponse {
+    url: Url,
+}
+
+/// A rendezvous session, used as a transport for the secure channel.
+pub(super) struct RendezvousChannel {
+    client: reqwest::Client,
+    rendezvous_url: Url,
+    etag: String,
+}
+
+impl RendezvousChannel {
+    /// Create a new rendezvous session on the given rendezvous server.
+    #[instrument(skip(client))]
+    pub(super) async fn create(
+        client: reqwest::Client,
+        rendezvous_server: &Url,
+    ) -> Result<Self, SecureChannelError> {
+        let endpoint = rendezvous_server.join(RENDEZVOUS_PATH)?;
+
+        let response = client
+            .post(endpoint)
+            .header(CONTENT_TYPE, "text/plain")
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let etag = get_etag(&response)?;
+        let CreateSessionResponse { url } = response.json().await?;
+
+        trace!(rendezvous_url = %url, "Created a rendezvous session");
+
+        Ok(Self { client, rendezvous_url: url, etag })
+    }
+
+    /// Join an existing rendezvous session at the given URL.
+    #[instrument(skip(client))]
+    pub(super) async fn join(
+        client: reqwest::Client,
+        rendezvous_url: &Url,
+    ) -> Result<Self, SecureChannelError> {
+        let response = client.get(rendezvous_url.clone()).send().await?.error_for_status()?;
+        let etag = get_etag(&response)?;
+
+        Ok(Self { client, rendezvous_url: rendezvous_url.clone(), etag })
+    }
+
+    /// The URL of the rendezvous session.
+    pub(super) fn rendezvous_url(&self) -> &Url {
+        &self.rendezvous_url
+    }
+
+    /// Replace the content of the rendezvous session with the given message.
+    pub(super) async fn send(&mut self, message: String) -> Result<(), SecureChannelError> {
+        let response = self
+            .client
+            .put(self.rendezvous_url.clone())
+            .header(IF_MATCH, &self.etag)
+            .header(CONTENT_TYPE, "text/plain")
+            .body(message)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        self.etag = get_etag(&response)?;
+
+        Ok(())
+    }
+
+    /// Wait for the content of the rendezvous session to change, and return
+    /// it.
+    pub(super) async fn receive(&mut self) -> Result<String, SecureChannelError> {
+        loop {
+            let response = self
+                .client
+                .get(self.rendezvous_url.clone())
+                .header(IF_NONE_MATCH, &self.etag)
+                .send()
+                .await?;
+
+            if response.status() == StatusCode::NOT_MODIFIED {
+                sleep(POLL_INTERVAL).await;
+                continue;
+            }
+
+            let response = response.error_for_status()?;
+            self.etag = get_etag(&response)?;
+
+            let message = response.text().await?;
+
+            // The session was only touched but doesn't contain a message yet.
+            if message.is_empty() {
+                sleep(POLL_INTERVAL).await;
+                continue;
+            }
+
+            return Ok(message);
+        }
+    }
+}
+
+fn get_etag(response: &reqwest::Response) -> Result<String, SecureChannelError> {
+    response
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(ToOwned::to_owned)
+        .ok_or(SecureChannelError::MissingEtag)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(super) mod test {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use http::header::{ETAG, IF_MATCH, IF_NONE_MATCH};
+    use matrix_sdk_test::async_test;
+    use serde_json::json;
+    use url::Url;
+    use wiremock::{
+        matchers::{method, path, path_regex},
+        Mock, MockServer, Request, Respond, ResponseTemplate,
+    };
+
+    use super::RendezvousChannel;
+
+    #[derive(Default)]
+    struct Sessions {
+        counter: AtomicU64,
+        /// The content and ETag of each session.
+        sessions: Mutex<HashMap<String, (String, String)>>,
+    }
+
+    impl Sessions {
+        fn next_etag(&self) -> String {
+            self.counter.fetch_add(1, Ordering::SeqCst).to_string()
+        }
+    }
+
+    struct CreateSession {
+        server_url: Url,
+        sessions: Arc<Sessions>,
+    }
+
+    impl Respond for CreateSession {
+        fn respond(&self, _: &Request) -> ResponseTemplate {
+            let id = self.sessions.next_etag();
+            let etag = self.sessions.next_etag();
+            self.sessions
+                .sessions
+                .lock()
+                .unwrap()
+                .insert(id.clone(), (String::new(), etag.clone()));
+
+            let url = self.server_url.join(&format!("rendezvous/{id}")).unwrap();
+
+            ResponseTemplate::new(201)
+                .insert_header(ETAG.as_str(), etag)
+                .set_body_json(json!({ "url": url.as_str() }))
+        }
+    }
+
+    struct Session {
+        sessions: Arc<Sessions>,
+    }
+
+    impl Respond for Session {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let id = request.url.path_segments().unwrap().last().unwrap().to_owned();
+            let mut sessions = self.sessions.sessions.lock().unwrap();
+            let Some((content, etag)) = sessions.get_mut(&id) else {
+                return ResponseTemplate::new(404);
+            };
+
+            let header = |name: &http::HeaderName| {
+                request.headers.get(name.as_str()).map(|values| values.last().as_str().to_owned())
+            };
+
+            if request.method == wiremock::http::Method::Put {
+                if header(&IF_MATCH).as_ref() != Some(etag) {
+                    return ResponseTemplate::new(412);
+                }
+
+                *content = String::from_utf8(request.body.clone()).unwrap();
+                *etag = self.sessions.next_etag();
+
+                ResponseTemplate::new(202).insert_header(ETAG.as_str(), etag.as_str())
+            } else if header(&IF_NONE_MATCH).as_ref() == Some(etag) {
+                ResponseTemplate::new(304).insert_header(ETAG.as_str(), etag.as_str())
+            } else {
+                ResponseTemplate::new(200)
+                    .insert_header(ETAG.as_str(), etag.as_str())
+                    .set_body_string(content.as_str())
+            }
+        }
+    }
+
+    /// A local stand-in for a rendezvous server.
+    pub(crate) struct MockedRendezvousServer {
+        pub(crate) server: MockServer,
+    }
+
+    impl MockedRendezvousServer {
+        pub(crate) async fn new() -> Self {
+            let server = MockServer::start().await;
+            let server_url = Url::parse(&server.uri()).unwrap();
+            let sessions = Arc::new(Sessions::default());
+
+            Mock::given(method("POST"))
+                .and(path("/_matrix/client/unstable/org.matrix.msc4108/rendezvous"))
+                .respond_with(CreateSession { server_url, sessions: sessions.clone() })
+                .mount(&server)
+                .await;
+
+            Mock::given(path_regex("^/rendezvous/[0-9]+$"))
+                .respond_with(Session { sessions })
+                .mount(&server)
+                .await;
+
+            Self { server }
+        }
+
+        pub(crate) fn url(&self) -> Url {
+            Url::parse(&self.server.uri()).unwrap()
+        }
+    }
+
+    #[async_test]
+    async fn test_rendezvous_channel() {
+        let server = MockedRendezvousServer::new().await;
+        let client = reqwest::Client::new();
+
+        let mut alice = RendezvousChannel::create(client.clone(), &server.url()).await.unwrap();
+        let mut bob = RendezvousChannel::join(client, alice.rendezvous_url()).await.unwrap();
+
+        bob.send("Hello Alice".to_owned()).await.unwrap();
+        assert_eq!(alice.receive().await.unwrap(), "Hello Alice");
+
+        alice.send("Hello Bob".to_owned()).await.unwrap();
+        assert_eq!(bob.receive().await.unwrap(), "Hello Bob");
+
+        bob.send("Bye Alice".to_owned()).await.unwrap();
+        assert_eq!(alice.receive().await.unwrap(), "Bye Alice");
+    }
+}
@@ -0,0 +1,243 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+//! The secure channel used to exchange messages between the two devices.
+//!
+//! The channel is encrypted with the ECIES scheme of vodozemac: the scanning
+//! device establishes the channel with the public key of the QR code, and
+//! sends its own ephemeral public key in the first message.
+
+use matrix_sdk_base::crypto::{
+    matrix_sdk_qrcode::{QrCodeData, QrCodeModeData},
+    vodozemac::ecies::{Ecies, EstablishedEcies, InitialMessage, Message},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use url::Url;
+
+use super::{rendezvous::RendezvousChannel, CheckCode, SecureChannelError};
+
+/// The message sent by the scanning device to start the secure channel.
+const LOGIN_INITIATE_MESSAGE: &str = "MATRIX_QR_CODE_LOGIN_INITIATE";
+
+/// The message sent by the displaying device to confirm the secure channel.
+const LOGIN_OK_MESSAGE: &str = "MATRIX_QR_CODE_LOGIN_OK";
+
+/// The secure channel of the device displaying the QR code, before the other
+/// device scanned it.
+pub(super) struct SecureChannel {
+    channel: RendezvousChannel,
+    qr_code_data: QrCodeData,
+    ecies: Ecies,
+}
+
+impl SecureChannel {
+    /// Create a new rendezvous session on the given rendezvous server, and
+    /// the data of the QR code to display.
+    pub(super) async fn new(
+        http_client: reqwest::Client,
+        rendezvous_server: &Url,
+        mode_data: QrCodeModeData,
+    ) -> Result<Self, SecureChannelError> {
+        let channel = RendezvousChannel::create(http_client, rendezvous_server).await?;
+        let ecies = Ecies::new();
+
+        let qr_code_data = QrCodeData {
+            public_key: ecies.public_key(),
+            rendezvous_url: channel.rendezvous_url().clone(),
+            mode_data,
+        };
+
+        Ok(Self { channel, qr_code_data, ecies })
+    }
+
+    /// The data of the QR code to display.
+    pub(super) fn qr_code_data(&self) -> &QrCodeData {
+        &self.qr_code_data
+    }
+
+    /// Wait for the other device to scan the QR code and to start the secure
+    /// channel.
+    pub(super) async fn connect(
+        mut self,
+    ) -> Result<AlmostEstablishedSecureChannel, SecureChannelError> {
+        let message = InitialMessage::decode(&self.channel.receive().await?)?;
+        let result = self.ecies.establish_inbound_channel(&message)?;
+        let mut ecies = result.ecies;
+
+        if result.message != LOGIN_INITIATE_MESSAGE.as_bytes() {
+            return Err(SecureChannelError::InvalidInitialMessage);
+        }
+
+        let message = ecies.encrypt(LOGIN_OK_MESSAGE.as_bytes()).encode();
+        self.channel.send(message).await?;
+
+        Ok(AlmostEstablishedSecureChannel {
+            secure_channel: EstablishedSecureChannel { channel: self.channel, ecies },
+        })
+    }
+}
+
+/// The secure channel of the device displaying the QR code, before the user
+/// confirmed the check code displayed by the other device.
+pub(super) struct AlmostEstablishedSecureChannel {
+    secure_channel: EstablishedSecureChannel,
+}
+
+impl AlmostEstablishedSecureChannel {
+    /// Compare the check code entered by the user with ours.
+    ///
+    /// If they don't match, somebody else scanned the QR code and the channel
+    /// must not be used.
+    pub(super) fn confirm(
+        self,
+        check_code: u8,
+    ) -> Result<EstablishedSecureChannel, SecureChannelError> {
+        if check_code == self.secure_channel.check_code().to_digit() {
+            Ok(self.secure_channel)
+        } else {
+            Err(SecureChannelError::InvalidCheckCode)
+        }
+    }
+}
+
+/// A secure channel that can be used to exchange messages.
+pub(super) struct EstablishedSecureChannel {
+    channel: RendezvousChannel,
+    ecies: EstablishedEcies,
+}
+
+impl EstablishedSecureChannel {
+    /// Join the secure channel of the device that displays the given QR code.
+    pub(super) async fn from_qr_code(
+        http_client: reqwest::Client,
+        qr_code_data: &QrCodeData,
+    ) -> Result<Self, SecureChannelError> {
+        let mut channel =
+            RendezvousChannel::join(http_client, &qr_code_data.rendezvous_url).await?;
+
+        let result = Ecies::new().establish_outbound_channel(
+            qr_code_data.public_key,
+            LOGIN_INITIATE_MESSAGE.as_bytes(),
+        )?;
+        let mut ecies = result.ecies;
+
+        channel.send(result.message.encode()).await?;
+
+        let response = Message::decode(&channel.receive().await?)?;
+
+        if ecies.decrypt(&response)? != LOGIN_OK_MESSAGE.as_bytes() {
+            return Err(SecureChannelError::InvalidInitialMessage);
+        }
+
+        Ok(Self { channel, ecies })
+    }
+
+    /// The check code of this secure channel, that must be the same on both
+    /// devices.
+    pub(super) fn check_code(&self) -> CheckCode {
+        CheckCode::new(*self.ecies.check_code().as_bytes())
+    }
+
+    /// Encrypt and send the given message to the other device.
+    pub(super) async fn send_json(
+        &mut self,
+        message: &impl Serialize,
+    ) -> Result<(), SecureChannelError> {
+        let plaintext = serde_json::to_vec(message)?;
+        let message = self.ecies.encrypt(&plaintext).encode();
+
+        self.channel.send(message).await
+    }
+
+    /// Wait for the next message of the other device and decrypt it.
+    pub(super) async fn receive_json<T: DeserializeOwned>(
+        &mut self,
+    ) -> Result<T, SecureChannelError> {
+        let message = Message::decode(&self.channel.receive().await?)?;
+        let plaintext = self.ecies.decrypt(&message)?;
+
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod test {
+    use assert_matches::assert_matches;
+    use matrix_sdk_base::crypto::matrix_sdk_qrcode::QrCodeModeData;
+    use matrix_sdk_test::async_test;
+    use serde_json::{json, Value as JsonValue};
+
+    use super::{EstablishedSecureChannel, SecureChannel};
+    use crate::oidc::qrcode::{rendezvous::test::MockedRendezvousServer, SecureChannelError};
+
+    async fn establish_channels(
+        server: &MockedRendezvousServer,
+    ) -> (EstablishedSecureChannel, EstablishedSecureChannel) {
+        let client = reqwest::Client::new();
+
+        let displaying =
+            SecureChannel::new(client.clone(), &server.url(), QrCodeModeData::Login).await.unwrap();
+        let qr_code_data = displaying.qr_code_data().clone();
+
+        let scanning = tokio::spawn(async move {
+            EstablishedSecureChannel::from_qr_code(client, &qr_code_data).await.unwrap()
+        });
+
+        let almost_established = displaying.connect().await.unwrap();
+        let scanning = scanning.await.unwrap();
+
+        let displaying = almost_established.confirm(scanning.check_code().to_digit()).unwrap();
+
+        (displaying, scanning)
+    }
+
+    #[async_test]
+    async fn test_secure_channel() {
+        let server = MockedRendezvousServer::new().await;
+        let (mut displaying, mut scanning) = establish_channels(&server).await;
+
+        assert_eq!(displaying.check_code(), scanning.check_code());
+
+        scanning.send_json(&json!({ "type": "m.login.protocols" })).await.unwrap();
+        let message: JsonValue = displaying.receive_json().await.unwrap();
+        assert_eq!(message, json!({ "type": "m.login.protocols" }));
+
+        displaying.send_json(&json!({ "type": "m.login.protocol" })).await.unwrap();
+        let message: JsonValue = scanning.receive_json().await.unwrap();
+        assert_eq!(message, json!({ "type": "m.login.protocol" }));
+    }
+
+    #[async_test]
+    async fn test_secure_channel_invalid_check_code() {
+        let server = MockedRendezvousServer::new().await;
+        let client = reqwest::Client::new();
+
+        let displaying =
+            SecureChannel::new(client.clone(), &server.url(), QrCodeModeData::Login).await.unwrap();
+        let qr_code_data = displaying.qr_code_data().clone();
+
+        let scanning = tokio::spawn(async move {
+            EstablishedSecureChannel::from_qr_code(client, &qr_code_data).await.unwrap()
+        });
+
+        let almost_established = displaying.connect().await.unwrap();
+        let scanning = scanning.await.unwrap();
+
+        let wrong_check_code = (scanning.check_code().to_digit() + 1) % 100;
+        assert_matches!(
+            almost_established.confirm(wrong_check_code),
+            Err(SecureChannelError::InvalidCheckCode)
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::sync::Arc;
+
+use assert_matches::assert_matches;
+use futures_util::StreamExt;
+use matrix_sdk_test::{async_test, test_json};
+use ruma::api::client::discovery::discover_homeserver::AuthenticationServerInfo;
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, ResponseTemplate,
+};
+
+use super::{
+    messages::{LoginFailureReason, LoginProtocolType, QrAuthMessage},
+    rendezvous::test::MockedRendezvousServer,
+    secure_channel::EstablishedSecureChannel,
+    CheckCodeState, LoginProgress, QrCodeLoginError, QrCodeModeData,
+};
+use crate::{
+    oidc::{
+        backend::mock::{MockImpl, DEVICE_VERIFICATION_URL, ISSUER_URL},
+        tests::mock_registered_client_data,
+        Oidc, OidcSessionTokens,
+    },
+    test_utils::{logged_in_client, test_client_builder},
+};
+
+#[async_test]
+async fn test_login_with_generated_qr_code() {
+    let rendezvous = MockedRendezvousServer::new().await;
+    let server = &rendezvous.server;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/(r0|v3)/account/whoami"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "user_id": "@joe:example.org",
+            "device_id": "D3V1C31D",
+        })))
+        .mount(server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/(r0|v3)/keys/upload"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::KEYS_UPLOAD))
+        .expect(1..)
+        .mount(server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/(r0|v3)/keys/query"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "device_keys": {} })))
+        .mount(server)
+        .await;
+
+    let client = test_client_builder(Some(server.uri())).build().await.unwrap();
+    let session_tokens = OidcSessionTokens {
+        access_token: "4cc3ss".to_owned(),
+        refresh_token: None,
+        latest_id_token: None,
+    };
+    let backend = Arc::new(MockImpl::new().next_session_tokens(session_tokens));
+    let oidc = Oidc { client, backend };
+
+    let issuer_info = AuthenticationServerInfo::new(ISSUER_URL.to_owned(), None);
+    let (client_credentials, client_metadata) = mock_registered_client_data();
+    oidc.restore_registered_client(issuer_info, client_metadata, client_credentials);
+
+    let login = oidc.login_with_generated_qr_code();
+    let mut progress = login.subscribe_to_progress();
+
+    // The existing device, that scans the QR code.
+    let existing_device = tokio::spawn(async move {
+        let qr_code_data = assert_matches!(
+            progress.next().await,
+            Some(LoginProgress::QrReady { qr_code_data }) => qr_code_data
+        );
+        assert_eq!(qr_code_data.mode_data, QrCodeModeData::Login);
+
+        let mut channel =
+            EstablishedSecureChannel::from_qr_code(reqwest::Client::new(), &qr_code_data)
+                .await
+                .unwrap();
+
+        // The user enters the check code displayed by the existing device.
+        let sender = assert_matches!(
+            progress.next().await,
+            Some(LoginProgress::EstablishingSecureChannel {
+                check_code: CheckCodeState::Input(sender)
+            }) => sender
+        );
+        sender.send(channel.check_code().to_digit()).unwrap();
+
+        channel
+            .send_json(&QrAuthMessage::LoginProtocols {
+                protocols: vec![LoginProtocolType::DeviceAuthorizationGrant],
+                homeserver: "https://example.org".parse().unwrap(),
+            })
+            .await
+            .unwrap();
+
+        let grant = assert_matches!(
+            channel.receive_json().await.unwrap(),
+            QrAuthMessage::LoginProtocol { device_authorization_grant, .. } => device_authorization_grant
+        );
+        assert_eq!(grant.verification_uri.as_str(), DEVICE_VERIFICATION_URL);
+
+        channel.send_json(&QrAuthMessage::LoginProtocolAccepted).await.unwrap();
+
+        assert_matches!(channel.receive_json().await.unwrap(), QrAuthMessage::LoginSuccess);
+
+        // Pretend we couldn't find the new device, so we don't need to share real
+        // secrets.
+        channel
+            .send_json(&QrAuthMessage::failure(LoginFailureReason::DeviceNotFound))
+            .await
+            .unwrap();
+    });
+
+    assert_matches!(
+        login.await,
+        Err(QrCodeLoginError::LoginFailure { reason: LoginFailureReason::DeviceNotFound, .. })
+    );
+    existing_device.await.unwrap();
+
+    // The new device is logged in, even though it isn't verified.
+    assert!(oidc.full_session().is_some());
+}
+
+#[async_test]
+async fn test_login_with_qr_code_invalid_mode() {
+    let rendezvous = MockedRendezvousServer::new().await;
+    let client = test_client_builder(Some(rendezvous.server.uri())).build().await.unwrap();
+    let oidc = Oidc { client, backend: Arc::new(MockImpl::new()) };
+
+    // A QR code displayed by a new device can't be used to log in.
+    let qr_code_data = super::QrCodeData {
+        public_key: matrix_sdk_base::crypto::vodozemac::Curve25519PublicKey::from_bytes([0; 32]),
+        rendezvous_url: rendezvous.url(),
+        mode_data: QrCodeModeData::Login,
+    };
+
+    assert_matches!(
+        oidc.login_with_qr_code(&qr_code_data).await,
+        Err(QrCodeLoginError::InvalidQrCodeMode)
+    );
+}
+
+#[async_test]
+async fn test_grant_login_without_cross_signing_keys() {
+    let client = logged_in_client(None).await;
+    let oidc = Oidc { client, backend: Arc::new(MockImpl::new()) };
+
+    // We can't verify the new device without the private cross-signing keys.
+    assert_matches!(
+        oidc.grant_login_with_generated_qr_code().await,
+        Err(QrCodeLoginError::MissingSecrets)
+    );
+}
@@ -23,12 +23,12 @@ use serde_json::json;
 use stream_assert::{assert_next_matches, assert_pending};
 use url::Url;
 use wiremock::{
-    matchers::{method, path},
+    matchers::{method, path, path_regex},
     Mock, MockServer, ResponseTemplate,
 };
 
 use super::{
-    backend::mock::{MockImpl, AUTHORIZATION_URL, ISSUER_URL},
+    backend::mock::{MockImpl, AUTHORIZATION_URL, DEVICE_VERIFICATION_URL, ISSUER_URL},
     AuthorizationCode, AuthorizationError, AuthorizationResponse, Oidc,
     OidcAccountManagementAction, OidcError, OidcSession, OidcSessionTokens,
     RedirectUriQueryParseError, UserSession,
@@ -444,3 +444,95 @@ async fn test_insecure_clients() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[async_test]
+async fn test_login_with_device_code() -> anyhow::Result<()> {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/(r0|v3)/account/whoami"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "user_id": "@joe:example.org",
+            "device_id": "D3V1C31D",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = test_client_builder(Some(server.uri())).build().await?;
+
+    let session_tokens = OidcSessionTokens {
+        access_token: "4cc3ss".to_owned(),
+        refresh_token: Some("r3fr3$h".to_owned()),
+        latest_id_token: None,
+    };
+    let backend =
+        Arc::new(MockImpl::new().next_session_tokens(session_tokens.clone()).device_code_errors([
+            ClientErrorCode::AuthorizationPending,
+            ClientErrorCode::AuthorizationPending,
+        ]));
+    let oidc = Oidc { client: client.clone(), backend: backend.clone() };
+
+    let issuer_info = AuthenticationServerInfo::new(ISSUER_URL.to_owned(), None);
+    let (client_credentials, client_metadata) = mock_registered_client_data();
+    oidc.restore_registered_client(issuer_info, client_metadata, client_credentials);
+
+    let data = oidc.login_with_device_code(Some("D3V1C31D".to_owned())).await?;
+    assert_eq!(data.user_code, "ABCD-EFGH");
+    assert_eq!(data.verification_uri.as_str(), DEVICE_VERIFICATION_URL);
+    assert!(data.verification_uri_complete.is_some());
+
+    // The polls continue while the authorization is pending.
+    oidc.finish_device_code_login(data).await?;
+    assert_eq!(*backend.num_device_code_polls.lock().unwrap(), 3);
+
+    assert_eq!(oidc.session_tokens(), Some(session_tokens));
+    let session = oidc.full_session().context("missing full session")?;
+    assert_eq!(session.user.meta.user_id, "@joe:example.org");
+    assert_eq!(session.user.meta.device_id, "D3V1C31D");
+
+    Ok(())
+}
+
+#[async_test]
+async fn test_login_with_device_code_errors() -> anyhow::Result<()> {
+    let client = test_client_builder(Some("https://example.org".to_owned())).build().await?;
+    let issuer_info = AuthenticationServerInfo::new(ISSUER_URL.to_owned(), None);
+    let (client_credentials, client_metadata) = mock_registered_client_data();
+
+    // The provider doesn't support the device authorization grant.
+    let oidc = Oidc {
+        client: client.clone(),
+        backend: Arc::new(MockImpl::new().without_device_authorization()),
+    };
+    oidc.restore_registered_client(
+        issuer_info.clone(),
+        client_metadata.clone(),
+        client_credentials.clone(),
+    );
+
+    let res = oidc.login_with_device_code(None).await;
+    assert_matches!(res, Err(OidcError::NoDeviceAuthorizationSupport));
+
+    // The user denies the authorization.
+    let backend = Arc::new(MockImpl::new().device_code_errors([
+        ClientErrorCode::AuthorizationPending,
+        ClientErrorCode::AccessDenied,
+    ]));
+    let oidc = Oidc { client: client.clone(), backend: backend.clone() };
+    oidc.restore_registered_client(issuer_info, client_metadata, client_credentials);
+
+    let data = oidc.login_with_device_code(None).await?;
+    let res = oidc.finish_device_code_login(data).await;
+
+    assert_matches!(
+        res,
+        Err(crate::Error::Oidc(OidcError::DeviceCodeGrant(error))) => {
+            assert_eq!(error.error, ClientErrorCode::AccessDenied);
+        }
+    );
+    assert_eq!(*backend.num_device_code_polls.lock().unwrap(), 2);
+    assert!(oidc.session_tokens().is_none());
+
+    Ok(())
+}
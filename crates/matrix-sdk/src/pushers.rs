@@ -0,0 +1,342 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! High-level pushers API.
+//!
+//! A pusher is registered on the homeserver for a device, and forwards the
+//! notifications of the user to a push gateway, or by email. See [`Pushers`].
+
+pub use ruma::api::client::push::{PushFormat, Pusher, PusherIds, PusherKind};
+use ruma::{
+    api::client::push::{get_pushers, set_pusher, EmailPusherData, HttpPusherData, PusherInit},
+    assign,
+};
+use serde_json::Value as JsonValue;
+use tracing::{debug, instrument};
+
+use crate::{error::HttpResult, Client, Result};
+
+/// The app ID of the email pushers, as defined in the spec.
+const EMAIL_APP_ID: &str = "m.email";
+
+/// The prefix of the keys of the custom values of the state store holding the
+/// pushkey last registered by [`Pushers::ensure_pusher`], by app ID.
+const LAST_PUSHKEY_KEY_PREFIX: &str = "pusher_last_pushkey";
+
+/// A high-level API to manage the pushers of the current user.
+///
+/// Get an instance with [`Client::pushers`].
+#[derive(Debug, Clone)]
+pub struct Pushers {
+    client: Client,
+}
+
+impl Pushers {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// Get all the pushers of the current user, for all their devices.
+    pub async fn get(&self) -> HttpResult<Vec<Pusher>> {
+        let request = get_pushers::v3::Request::new();
+        Ok(self.client.send(request, None).await?.pushers)
+    }
+
+    /// Create a pusher, or update the pusher with the same app ID and pushkey.
+    pub async fn set(&self, pusher: Pusher) -> HttpResult<()> {
+        let request = set_pusher::v3::Request::post(pusher);
+        self.client.send(request, None).await?;
+        Ok(())
+    }
+
+    /// Delete the pusher with the given app ID and pushkey.
+    pub async fn delete(&self, ids: PusherIds) -> HttpResult<()> {
+        let request = set_pusher::v3::Request::delete(ids);
+        self.client.send(request, None).await?;
+        Ok(())
+    }
+
+    /// Make sure the given pusher is registered for this device.
+    ///
+    /// This is meant to be called every time the app launches, with the
+    /// current push token of the device as the pushkey:
+    ///
+    /// * if the pusher is already registered with the same parameters, nothing
+    ///   is sent to the homeserver,
+    /// * otherwise, the pusher is created or updated,
+    /// * if the pushkey differs from the one registered by the previous call on
+    ///   this device for the same app ID, e.g. because the push token of the
+    ///   device changed, the previous pusher is deleted.
+    ///
+    /// The pushkey is persisted in the state store, so the pushers of the other
+    /// devices are never deleted, even if they share the same device display
+    /// name.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{pushers::PusherBuilder, Client};
+    /// # async {
+    /// # let client: Client = todo!();
+    /// # let push_token = "";
+    /// let pusher = PusherBuilder::http(
+    ///     "org.example.app.ios",
+    ///     push_token,
+    ///     "https://push.example.org/_matrix/push/v1/notify",
+    /// )
+    /// .app_display_name("Example")
+    /// .device_display_name("My iPhone")
+    /// .build();
+    ///
+    /// client.pushers().ensure_pusher(pusher).await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    #[instrument(skip_all, fields(app_id = %pusher.ids.app_id))]
+    pub async fn ensure_pusher(&self, pusher: Pusher) -> Result<()> {
+        let store = self.client.store();
+        let store_key = format!("{LAST_PUSHKEY_KEY_PREFIX}:{}", pusher.ids.app_id);
+
+        let last_pushkey = store
+            .get_custom_value(store_key.as_bytes())
+            .await?
+            .and_then(|value| String::from_utf8(value).ok());
+
+        let mut is_registered = false;
+        let mut stale_pusher = None;
+
+        for existing in self.get().await? {
+            if existing.ids.app_id != pusher.ids.app_id {
+                continue;
+            }
+
+            if existing.ids.pushkey == pusher.ids.pushkey {
+                is_registered = is_same_pusher(&existing, &pusher);
+            } else if last_pushkey.as_ref() == Some(&existing.ids.pushkey) {
+                stale_pusher = Some(existing.ids);
+            }
+        }
+
+        let pushkey = pusher.ids.pushkey.clone();
+
+        if is_registered {
+            debug!("The pusher is already registered");
+        } else {
+            debug!("Registering the pusher");
+            self.set(pusher).await?;
+        }
+
+        // Only remove the stale pusher once the new one is registered, to avoid
+        // missing notifications in between.
+        if let Some(ids) = stale_pusher {
+            debug!("Removing the previous pusher of the device");
+            self.delete(ids).await?;
+        }
+
+        if last_pushkey.as_ref() != Some(&pushkey) {
+            store.set_custom_value(store_key.as_bytes(), pushkey.into_bytes()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the two pushers have the same parameters.
+fn is_same_pusher(left: &Pusher, right: &Pusher) -> bool {
+    // `Pusher` doesn't implement `PartialEq`, compare the serialized pushers
+    // instead.
+    match (serde_json::to_value(left), serde_json::to_value(right)) {
+        (Ok(left), Ok(right)) => left == right,
+        _ => false,
+    }
+}
+
+/// A builder for a [`Pusher`], to register with [`Pushers::set`] or
+/// [`Pushers::ensure_pusher`].
+#[derive(Debug, Clone)]
+pub struct PusherBuilder {
+    ids: PusherIds,
+    kind: PusherKind,
+    app_display_name: String,
+    device_display_name: String,
+    profile_tag: Option<String>,
+    lang: String,
+}
+
+impl PusherBuilder {
+    /// Create a builder for an HTTP pusher, forwarding the notifications to a
+    /// push gateway.
+    ///
+    /// The notifications only contain the IDs of the event and of the room
+    /// (`event_id_only` format), so the content of the events isn't sent to
+    /// the push gateway. Use [`PusherBuilder::format`] to change it.
+    ///
+    /// # Arguments
+    ///
+    /// * `app_id` - The reverse-DNS identifier of the app, e.g.
+    ///   `org.example.app.ios`.
+    ///
+    /// * `pushkey` - The push token of the device, used by the push gateway to
+    ///   reach it.
+    ///
+    /// * `url` - The URL of the `/_matrix/push/v1/notify` endpoint of the push
+    ///   gateway.
+    pub fn http(
+        app_id: impl Into<String>,
+        pushkey: impl Into<String>,
+        url: impl Into<String>,
+    ) -> Self {
+        let data = assign!(HttpPusherData::new(url.into()), {
+            format: Some(PushFormat::EventIdOnly),
+        });
+
+        Self::new(PusherIds::new(pushkey.into(), app_id.into()), PusherKind::Http(data))
+    }
+
+    /// Create a builder for an email pusher, sending the notifications to the
+    /// given email address.
+    ///
+    /// The email address must be bound to the account of the user.
+    pub fn email(address: impl Into<String>) -> Self {
+        Self::new(
+            PusherIds::new(address.into(), EMAIL_APP_ID.to_owned()),
+            PusherKind::Email(EmailPusherData::new()),
+        )
+    }
+
+    fn new(ids: PusherIds, kind: PusherKind) -> Self {
+        Self {
+            ids,
+            kind,
+            app_display_name: String::new(),
+            device_display_name: String::new(),
+            profile_tag: None,
+            lang: "en".to_owned(),
+        }
+    }
+
+    /// Set the format of the notifications sent to the push gateway.
+    ///
+    /// `None` means the full events are sent. It has no effect on email
+    /// pushers.
+    pub fn format(mut self, format: Option<PushFormat>) -> Self {
+        if let PusherKind::Http(data) = &mut self.kind {
+            data.format = format;
+        }
+        self
+    }
+
+    /// Set the payload that the push gateway receives with every notification.
+    ///
+    /// It has no effect on email pushers.
+    pub fn default_payload(mut self, default_payload: JsonValue) -> Self {
+        if let PusherKind::Http(data) = &mut self.kind {
+            data.default_payload = default_payload;
+        }
+        self
+    }
+
+    /// Set the human-readable name of the app.
+    pub fn app_display_name(mut self, app_display_name: impl Into<String>) -> Self {
+        self.app_display_name = app_display_name.into();
+        self
+    }
+
+    /// Set the human-readable name of the device.
+    pub fn device_display_name(mut self, device_display_name: impl Into<String>) -> Self {
+        self.device_display_name = device_display_name.into();
+        self
+    }
+
+    /// Set the profile tag, selecting the set of push rules the pusher uses.
+    pub fn profile_tag(mut self, profile_tag: impl Into<String>) -> Self {
+        self.profile_tag = Some(profile_tag.into());
+        self
+    }
+
+    /// Set the preferred language of the notifications, as an ISO 639-1 code.
+    ///
+    /// Defaults to `en`.
+    pub fn lang(mut self, lang: impl Into<String>) -> Self {
+        self.lang = lang.into();
+        self
+    }
+
+    /// Build the pusher.
+    pub fn build(self) -> Pusher {
+        PusherInit {
+            ids: self.ids,
+            kind: self.kind,
+            app_display_name: self.app_display_name,
+            device_display_name: self.device_display_name,
+            profile_tag: self.profile_tag,
+            lang: self.lang,
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use ruma::api::client::push::{PushFormat, PusherKind};
+    use serde_json::json;
+
+    use super::{is_same_pusher, PusherBuilder};
+
+    #[test]
+    fn test_http_pusher_builder() {
+        let pusher = PusherBuilder::http("org.example.app", "token", "https://push.example.org")
+            .app_display_name("Example")
+            .device_display_name("Device")
+            .lang("fr")
+            .build();
+
+        assert_eq!(pusher.ids.app_id, "org.example.app");
+        assert_eq!(pusher.ids.pushkey, "token");
+        assert_eq!(pusher.app_display_name, "Example");
+        assert_eq!(pusher.device_display_name, "Device");
+        assert_eq!(pusher.lang, "fr");
+        assert_matches!(pusher.kind, PusherKind::Http(data) => {
+            assert_eq!(data.url, "https://push.example.org");
+            assert_matches!(data.format, Some(PushFormat::EventIdOnly));
+        });
+
+        let pusher = PusherBuilder::http("org.example.app", "token", "https://push.example.org")
+            .format(None)
+            .default_payload(json!({ "aps": { "mutable-content": 1 } }))
+            .build();
+
+        assert_matches!(pusher.kind, PusherKind::Http(data) => {
+            assert_matches!(data.format, None);
+            assert_eq!(data.default_payload, json!({ "aps": { "mutable-content": 1 } }));
+        });
+    }
+
+    #[test]
+    fn test_email_pusher_builder() {
+        let pusher = PusherBuilder::email("alice@example.org").format(None).build();
+
+        assert_eq!(pusher.ids.app_id, "m.email");
+        assert_eq!(pusher.ids.pushkey, "alice@example.org");
+        assert_matches!(pusher.kind, PusherKind::Email(_));
+    }
+
+    #[test]
+    fn test_is_same_pusher() {
+        let builder = PusherBuilder::http("org.example.app", "token", "https://push.example.org");
+
+        assert!(is_same_pusher(&builder.clone().build(), &builder.clone().build()));
+        assert!(!is_same_pusher(&builder.clone().build(), &builder.lang("fr").build()));
+    }
+}
@@ -17,10 +17,10 @@
 #![deny(unreachable_pub)]
 
 use std::future::IntoFuture;
-#[cfg(feature = "image-proc")]
-use std::io::Cursor;
 
 use eyeball::SharedObservable;
+#[cfg(not(target_arch = "wasm32"))]
+use eyeball::Subscriber;
 use matrix_sdk_common::boxed_into_future;
 use mime::Mime;
 #[cfg(doc)]
@@ -39,11 +39,8 @@ use crate::{
     attachment::AttachmentConfig, utils::IntoRawMessageLikeEventContent, Result,
     TransmissionProgress,
 };
-#[cfg(feature = "image-proc")]
-use crate::{
-    attachment::{generate_image_thumbnail, Thumbnail},
-    error::ImageError,
-};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{attachment::AttachmentData, media::ByteStream};
 
 /// Future returned by [`Room::send`].
 #[allow(missing_debug_implementations)]
@@ -258,71 +255,96 @@ impl<'a> IntoFuture for SendAttachment<'a> {
     fn into_future(self) -> Self::IntoFuture {
         let Self { room, body, content_type, data, config, tracing_span, send_progress } = self;
         let fut = async move {
-            if config.thumbnail.is_some() {
-                room.prepare_and_send_attachment(body, content_type, data, config, send_progress)
-                    .await
-            } else {
-                #[cfg(not(feature = "image-proc"))]
-                let thumbnail = None;
-
-                #[cfg(feature = "image-proc")]
-                let data_slot;
-                #[cfg(feature = "image-proc")]
-                let (data, thumbnail) = if config.generate_thumbnail {
-                    let content_type = content_type.clone();
-                    let make_thumbnail = move |data| {
-                        let res = generate_image_thumbnail(
-                            &content_type,
-                            Cursor::new(&data),
-                            config.thumbnail_size,
-                        );
-                        (data, res)
-                    };
-
-                    #[cfg(not(target_arch = "wasm32"))]
-                    let (data, res) = tokio::task::spawn_blocking(move || make_thumbnail(data))
-                        .await
-                        .expect("Task join error");
-
-                    #[cfg(target_arch = "wasm32")]
-                    let (data, res) = make_thumbnail(data);
-
-                    let thumbnail = match res {
-                        Ok((thumbnail_data, thumbnail_info)) => {
-                            data_slot = thumbnail_data;
-                            Some(Thumbnail {
-                                data: data_slot,
-                                content_type: mime::IMAGE_JPEG,
-                                info: Some(thumbnail_info),
-                            })
-                        }
-                        Err(
-                            ImageError::ThumbnailBiggerThanOriginal
-                            | ImageError::FormatNotSupported,
-                        ) => None,
-                        Err(error) => return Err(error.into()),
-                    };
-
-                    (data, thumbnail)
-                } else {
-                    (data, None)
-                };
-
-                let config = AttachmentConfig {
-                    txn_id: config.txn_id,
-                    info: config.info,
-                    thumbnail,
-                    #[cfg(feature = "image-proc")]
-                    generate_thumbnail: false,
-                    #[cfg(feature = "image-proc")]
-                    thumbnail_size: None,
-                };
-
-                room.prepare_and_send_attachment(body, content_type, data, config, send_progress)
-                    .await
-            }
+            #[cfg(feature = "image-proc")]
+            let (data, config) = config.generate_thumbnail_if_needed(content_type, data).await?;
+
+            room.prepare_and_send_attachment(body, content_type, data.into(), config, send_progress)
+                .await
         };
 
         Box::pin(fut.instrument(tracing_span))
     }
 }
+
+/// Future returned by [`Room::send_attachment_stream`].
+#[cfg(not(target_arch = "wasm32"))]
+pub struct SendAttachmentStream<'a> {
+    room: &'a Room,
+    body: &'a str,
+    content_type: &'a Mime,
+    stream: ByteStream,
+    size: u64,
+    config: AttachmentConfig,
+    tracing_span: Span,
+    send_progress: SharedObservable<TransmissionProgress>,
+}
+
+#[cfg(all(not(target_arch = "wasm32"), not(tarpaulin_include)))]
+impl std::fmt::Debug for SendAttachmentStream<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SendAttachmentStream")
+            .field("room_id", &self.room.room_id())
+            .field("content_type", &self.content_type)
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<'a> SendAttachmentStream<'a> {
+    pub(crate) fn new(
+        room: &'a Room,
+        body: &'a str,
+        content_type: &'a Mime,
+        stream: ByteStream,
+        size: u64,
+        config: AttachmentConfig,
+    ) -> Self {
+        Self {
+            room,
+            body,
+            content_type,
+            stream,
+            size,
+            config,
+            tracing_span: Span::current(),
+            send_progress: Default::default(),
+        }
+    }
+
+    /// Replace the default `SharedObservable` used for tracking upload
+    /// progress.
+    pub fn with_send_progress_observable(
+        mut self,
+        send_progress: SharedObservable<TransmissionProgress>,
+    ) -> Self {
+        self.send_progress = send_progress;
+        self
+    }
+
+    /// Get a subscriber to observe the progress of the upload.
+    pub fn subscribe_to_send_progress(&self) -> Subscriber<TransmissionProgress> {
+        self.send_progress.subscribe()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<'a> IntoFuture for SendAttachmentStream<'a> {
+    type Output = Result<send_message_event::v3::Response>;
+    boxed_into_future!(extra_bounds: 'a);
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { room, body, content_type, stream, size, config, tracing_span, send_progress } =
+            self;
+
+        let fut = room.prepare_and_send_attachment(
+            body,
+            content_type,
+            AttachmentData::Stream { stream, size },
+            config,
+            send_progress,
+        );
+
+        Box::pin(fut.instrument(tracing_span))
+    }
+}
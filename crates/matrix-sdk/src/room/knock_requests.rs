@@ -0,0 +1,94 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk_base::deserialized_responses::MemberEvent;
+use ruma::{MilliSecondsSinceUnixEpoch, OwnedMxcUri, OwnedUserId, RoomId};
+
+use super::{Room, RoomMember};
+use crate::Result;
+
+/// A request from a user to join a room, sent by knocking on it.
+///
+/// Knock requests are returned by [`Room::knock_requests`] and
+/// [`Room::subscribe_to_knock_requests`].
+#[derive(Debug, Clone)]
+pub struct KnockRequest {
+    room: Room,
+
+    /// The ID of the user who knocked.
+    pub member_id: OwnedUserId,
+
+    /// The display name of the user who knocked, if any.
+    pub display_name: Option<String>,
+
+    /// The avatar of the user who knocked, if any.
+    pub avatar_url: Option<OwnedMxcUri>,
+
+    /// The reason given by the user for knocking, if any.
+    pub reason: Option<String>,
+
+    /// When the user knocked, if known.
+    pub timestamp: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+impl KnockRequest {
+    pub(super) fn new(room: Room, member: &RoomMember) -> Self {
+        let event = member.event();
+
+        let timestamp = match &**event {
+            MemberEvent::Sync(e) => Some(e.origin_server_ts()),
+            MemberEvent::Stripped(_) => None,
+        };
+
+        Self {
+            room,
+            member_id: member.user_id().to_owned(),
+            display_name: member.display_name().map(ToOwned::to_owned),
+            avatar_url: member.avatar_url().map(ToOwned::to_owned),
+            reason: event.original_content().and_then(|content| content.reason.clone()),
+            timestamp,
+        }
+    }
+
+    /// The ID of the room that was knocked on.
+    pub fn room_id(&self) -> &RoomId {
+        self.room.room_id()
+    }
+
+    /// Accept the request, by inviting the user in the room.
+    pub async fn accept(&self) -> Result<()> {
+        self.room.invite_user_by_id(&self.member_id).await
+    }
+
+    /// Decline the request, by kicking the user from the room.
+    ///
+    /// The user can knock again afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - The reason for declining the request, shown to the user.
+    pub async fn decline(&self, reason: Option<&str>) -> Result<()> {
+        self.room.kick_user(&self.member_id, reason).await
+    }
+
+    /// Decline the request, and ban the user from the room so they can't knock
+    /// again.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - The reason for banning the user.
+    pub async fn decline_and_ban(&self, reason: Option<&str>) -> Result<()> {
+        self.room.ban_user(&self.member_id, reason).await
+    }
+}
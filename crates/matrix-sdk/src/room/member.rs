@@ -1,6 +1,6 @@
 use std::ops::Deref;
 
-use ruma::events::room::MediaSource;
+use ruma::{events::room::MediaSource, OwnedRoomId};
 
 use crate::{
     media::{MediaFormat, MediaRequest},
@@ -12,6 +12,7 @@ use crate::{
 pub struct RoomMember {
     inner: BaseRoomMember,
     pub(crate) client: Client,
+    room_id: OwnedRoomId,
 }
 
 impl Deref for RoomMember {
@@ -23,8 +24,8 @@ impl Deref for RoomMember {
 }
 
 impl RoomMember {
-    pub(crate) fn new(client: Client, member: BaseRoomMember) -> Self {
-        Self { inner: member, client }
+    pub(crate) fn new(client: Client, room_id: OwnedRoomId, member: BaseRoomMember) -> Self {
+        Self { inner: member, client, room_id }
     }
 
     /// Gets the avatar of this member, if set.
@@ -33,6 +34,9 @@ impl RoomMember {
     /// If a thumbnail is requested no guarantee on the size of the image is
     /// given.
     ///
+    /// The avatar is pinned in the media cache, so it is not evicted until the
+    /// avatar of the member changes.
+    ///
     /// # Arguments
     ///
     /// * `format` - The desired format of the avatar.
@@ -60,9 +64,13 @@ impl RoomMember {
     /// # };
     /// ```
     pub async fn avatar(&self, format: MediaFormat) -> Result<Option<Vec<u8>>> {
-        let Some(url) = self.avatar_url() else { return Ok(None) };
+        let owner = format!("{} {}", self.room_id, self.user_id());
+        let Some(url) = self.avatar_url() else {
+            self.client.media().unpin_media_content(&owner).await?;
+            return Ok(None);
+        };
         let request = MediaRequest { source: MediaSource::Plain(url.to_owned()), format };
-        Ok(Some(self.client.media().get_media_content(&request, true).await?))
+        Ok(Some(self.client.media().get_pinned_media_content(&owner, &request).await?))
     }
 
     /// Adds the room member to the current account data's ignore list
@@ -21,7 +21,7 @@ use ruma::{
         Direction,
     },
     assign,
-    events::AnyStateEvent,
+    events::{relation::RelationType, AnyStateEvent},
     serde::Raw,
     uint, RoomId, UInt,
 };
@@ -139,3 +139,110 @@ pub struct Messages {
     /// A list of state events relevant to showing the `chunk`.
     pub state: Vec<Raw<AnyStateEvent>>,
 }
+
+/// Options for [`relations`][super::Room::relations].
+///
+/// See that method and
+/// <https://spec.matrix.org/v1.10/client-server-api/#get_matrixclientv1roomsroomidrelationseventid>
+/// for details.
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct RelationsOptions {
+    /// The token to start returning events from.
+    ///
+    /// This token can be obtained from a `next_batch` or `prev_batch` token
+    /// returned by a previous `relations` call.
+    pub from: Option<String>,
+
+    /// The direction to return events in.
+    pub dir: Direction,
+
+    /// The maximum number of events to return.
+    ///
+    /// The homeserver picks a default value if this isn't set.
+    pub limit: Option<UInt>,
+
+    /// Only return the events with this relation type.
+    pub rel_type: Option<RelationType>,
+}
+
+impl RelationsOptions {
+    /// Creates `RelationsOptions` with the given direction.
+    ///
+    /// All other parameters will be defaulted.
+    pub fn new(dir: Direction) -> Self {
+        Self { from: None, dir, limit: None, rel_type: None }
+    }
+
+    /// Creates `RelationsOptions` with `dir` set to `Backward`.
+    ///
+    /// If no `from` token is set afterwards, pagination will start at the
+    /// most recent relation.
+    pub fn backward() -> Self {
+        Self::new(Direction::Backward)
+    }
+
+    /// Creates `RelationsOptions` with `dir` set to `Forward`.
+    ///
+    /// If no `from` token is set afterwards, pagination will start at the
+    /// oldest relation.
+    pub fn forward() -> Self {
+        Self::new(Direction::Forward)
+    }
+}
+
+/// The result of a `Room::relations` call.
+///
+/// In short, this is a possibly decrypted version of the response of a
+/// `relations` api call.
+#[derive(Debug)]
+pub struct Relations {
+    /// A list of events relating to the parent event.
+    pub chunk: Vec<TimelineEvent>,
+
+    /// The token to use to fetch the next batch of events, in the direction
+    /// of the pagination.
+    ///
+    /// If this is `None`, there are no more events to fetch.
+    pub next_batch: Option<String>,
+
+    /// The token to use to fetch the previous batch of events.
+    pub prev_batch: Option<String>,
+}
+
+/// The result of a [`super::Room::event_with_context`] query.
+///
+/// This is a wrapper around
+/// [`ruma::api::client::context::get_context::v3::Response`], with events
+/// decrypted if needs be.
+#[derive(Debug, Default)]
+pub struct EventWithContextResponse {
+    /// The event targeted by the `/context` query.
+    pub event: Option<TimelineEvent>,
+
+    /// Events before the target event, if a non-zero context size was
+    /// requested.
+    ///
+    /// Like the corresponding Ruma response, these are in reverse
+    /// chronological order.
+    pub events_before: Vec<TimelineEvent>,
+
+    /// Events after the target event, if a non-zero context size was
+    /// requested.
+    ///
+    /// Like the corresponding Ruma response, these are in chronological
+    /// order.
+    pub events_after: Vec<TimelineEvent>,
+
+    /// Token to paginate backwards, aka "start" token.
+    pub prev_batch_token: Option<String>,
+
+    /// Token to paginate forwards, aka "end" token.
+    pub next_batch_token: Option<String>,
+
+    /// State events related to the request.
+    ///
+    /// If lazy-loading of members was requested, this may contain room
+    /// membership events.
+    pub state: Vec<Raw<AnyStateEvent>>,
+}
@@ -2,6 +2,8 @@
 
 use std::{borrow::Borrow, collections::BTreeMap, ops::Deref, time::Duration};
 
+#[cfg(not(target_arch = "wasm32"))]
+use bytes::Bytes;
 use eyeball::SharedObservable;
 use futures_core::Stream;
 use futures_util::stream::FuturesUnordered;
@@ -35,7 +37,8 @@ use ruma::{
         read_marker::set_read_marker,
         receipt::create_receipt,
         redact::redact_event,
-        room::{get_room_event, report_content},
+        relations::{get_relating_events, get_relating_events_with_rel_type},
+        room::{get_room_event, report_content, upgrade_room},
         state::{get_state_events_for_key, send_state_event},
         tag::{create_tag, delete_tag},
         typing::create_typing_event::{self, v3::Typing},
@@ -49,6 +52,7 @@ use ruma::{
             avatar::{self, RoomAvatarEventContent},
             encryption::RoomEncryptionEventContent,
             history_visibility::HistoryVisibility,
+            member::{MembershipState, SyncRoomMemberEvent},
             message::RoomMessageEventContent,
             name::RoomNameEventContent,
             power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent},
@@ -59,42 +63,48 @@ use ruma::{
         space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
         tag::{TagInfo, TagName},
         typing::SyncTypingEvent,
-        AnyRoomAccountDataEvent, AnyStateEvent, EmptyStateKey, MessageLikeEventContent,
+        AnyRoomAccountDataEvent, AnyTimelineEvent, EmptyStateKey, MessageLikeEventContent,
         MessageLikeEventType, RedactContent, RedactedStateEventContent, RoomAccountDataEvent,
         RoomAccountDataEventContent, RoomAccountDataEventType, StateEventContent, StateEventType,
         StaticEventContent, StaticStateEventContent, SyncStateEvent,
     },
     push::{Action, PushConditionRoomCtx},
     serde::Raw,
-    uint, EventId, Int, MatrixToUri, MatrixUri, MxcUri, OwnedEventId, OwnedRoomId, OwnedServerName,
-    OwnedTransactionId, OwnedUserId, TransactionId, UInt, UserId,
+    EventId, Int, MatrixToUri, MatrixUri, MxcUri, OwnedEventId, OwnedRoomId, OwnedServerName,
+    OwnedTransactionId, OwnedUserId, RoomVersionId, TransactionId, UInt, UserId,
 };
 use serde::de::DeserializeOwned;
 use thiserror::Error;
 use tokio::sync::broadcast;
 use tracing::{debug, info, instrument, warn};
 
+#[cfg(not(target_arch = "wasm32"))]
+use self::futures::SendAttachmentStream;
 use self::futures::{SendAttachment, SendMessageLikeEvent, SendRawMessageLikeEvent};
 use crate::{
-    attachment::AttachmentConfig,
+    attachment::{AttachmentConfig, AttachmentData},
     error::WrongRoomState,
     event_handler::{EventHandler, EventHandlerDropGuard, EventHandlerHandle, SyncEvent},
     media::{MediaFormat, MediaRequest},
     notification_settings::{IsEncrypted, IsOneToOne, RoomNotificationMode},
     room::power_levels::{RoomPowerLevelChanges, RoomPowerLevelsExt},
+    search::SearchMessages,
+    send_queue::RoomSendQueue,
     sync::RoomUpdate,
     utils::{IntoRawMessageLikeEventContent, IntoRawStateEventContent},
     BaseRoom, Client, Error, HttpError, HttpResult, Result, RoomState, TransmissionProgress,
 };
 
 pub mod futures;
+mod knock_requests;
 mod member;
 mod messages;
 pub mod power_levels;
 
 pub use self::{
+    knock_requests::KnockRequest,
     member::{RoomMember, RoomMemberRole},
-    messages::{Messages, MessagesOptions},
+    messages::{EventWithContextResponse, Messages, MessagesOptions, Relations, RelationsOptions},
 };
 
 /// A struct containing methods that are common for Joined, Invited and Left
@@ -129,12 +139,15 @@ impl Room {
 
     /// Leave this room.
     ///
-    /// Only invited and joined rooms can be left.
+    /// Only invited, knocked and joined rooms can be left.
     #[doc(alias = "reject_invitation")]
     pub async fn leave(&self) -> Result<()> {
         let state = self.state();
         if state == RoomState::Left {
-            return Err(Error::WrongRoomState(WrongRoomState::new("Joined or Invited", state)));
+            return Err(Error::WrongRoomState(WrongRoomState::new(
+                "Joined, Invited or Knocked",
+                state,
+            )));
         }
 
         let request = leave_room::v3::Request::new(self.inner.room_id().to_owned());
@@ -179,6 +192,50 @@ impl Room {
         self.client.clone()
     }
 
+    /// Get the [`RoomSendQueue`] of this room.
+    ///
+    /// Events sent through it are persisted in the state store until they've
+    /// been sent, and sent one at a time in the background.
+    pub fn send_queue(&self) -> RoomSendQueue {
+        self.client.send_queue().for_room(self.clone())
+    }
+
+    /// Search for messages matching the given search term in this room, with
+    /// the homeserver's `/search` endpoint.
+    ///
+    /// The returned [`SearchMessages`] can be used to customize the search
+    /// before awaiting it. The homeserver can't search the messages of
+    /// encrypted rooms.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, ruma::{room_id, uint}};
+    /// # async {
+    /// # let client: Client = todo!();
+    /// let room = client.get_room(room_id!("!roomid:example.com")).unwrap();
+    /// let results = room
+    ///     .search_messages("lunch")
+    ///     .context(uint!(1), uint!(1), false)
+    ///     .await?;
+    ///
+    /// if let Some(next_batch) = results.next_batch {
+    ///     let more_results = room
+    ///         .search_messages("lunch")
+    ///         .context(uint!(1), uint!(1), false)
+    ///         .next_batch(next_batch)
+    ///         .await?;
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn search_messages(&self, search_term: impl Into<String>) -> SearchMessages {
+        SearchMessages::new(
+            self.client.clone(),
+            Some(self.room_id().to_owned()),
+            search_term.into(),
+        )
+    }
+
     /// Get the sync state of this room, i.e. whether it was fully synced with
     /// the server.
     pub fn is_synced(&self) -> bool {
@@ -191,6 +248,9 @@ impl Room {
     /// If a thumbnail is requested no guarantee on the size of the image is
     /// given.
     ///
+    /// The avatar is pinned in the media cache, so it is not evicted until the
+    /// avatar of the room changes.
+    ///
     /// # Arguments
     ///
     /// * `format` - The desired format of the avatar.
@@ -215,9 +275,13 @@ impl Room {
     /// # };
     /// ```
     pub async fn avatar(&self, format: MediaFormat) -> Result<Option<Vec<u8>>> {
-        let Some(url) = self.avatar_url() else { return Ok(None) };
+        let owner = self.room_id().as_str();
+        let Some(url) = self.avatar_url() else {
+            self.client.media().unpin_media_content(owner).await?;
+            return Ok(None);
+        };
         let request = MediaRequest { source: MediaSource::Plain(url.to_owned()), format };
-        Ok(Some(self.client.media().get_media_content(&request, true).await?))
+        Ok(Some(self.client.media().get_pinned_media_content(owner, &request).await?))
     }
 
     /// Sends a request to `/_matrix/client/r0/rooms/{room_id}/messages` and
@@ -254,44 +318,121 @@ impl Room {
         let request = options.into_request(room_id);
         let http_response = self.client.send(request, None).await?;
 
-        #[allow(unused_mut)]
         let mut response = Messages {
             start: http_response.start,
             end: http_response.end,
-            #[cfg(not(feature = "e2e-encryption"))]
-            chunk: http_response.chunk.into_iter().map(TimelineEvent::new).collect(),
-            #[cfg(feature = "e2e-encryption")]
             chunk: Vec::with_capacity(http_response.chunk.len()),
             state: http_response.state,
         };
 
-        #[cfg(feature = "e2e-encryption")]
         for event in http_response.chunk {
-            let decrypted_event = if let Ok(AnySyncTimelineEvent::MessageLike(
-                AnySyncMessageLikeEvent::RoomEncrypted(SyncMessageLikeEvent::Original(_)),
-            )) = event.deserialize_as::<AnySyncTimelineEvent>()
-            {
-                if let Ok(event) = self.decrypt_event(event.cast_ref()).await {
-                    event
-                } else {
-                    TimelineEvent::new(event)
-                }
-            } else {
-                TimelineEvent::new(event)
-            };
-            response.chunk.push(decrypted_event);
+            response.chunk.push(self.try_decrypt_event(event).await);
+        }
+
+        self.set_push_actions(&mut response.chunk).await?;
+
+        Ok(response)
+    }
+
+    /// Sends a request to
+    /// `/_matrix/client/v1/rooms/{room_id}/relations/{event_id}` and returns
+    /// a `Relations` struct that contains a chunk of events relating to the
+    /// given event.
+    ///
+    /// If [`RelationsOptions::rel_type`] is set, only the events with this
+    /// relation type are returned.
+    ///
+    /// With the encryption feature, events are decrypted if possible. If
+    /// decryption fails for an individual event, that event is returned
+    /// undecrypted.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use matrix_sdk::{room::RelationsOptions, Client};
+    /// # use matrix_sdk::ruma::{
+    /// #     assign, event_id, events::relation::RelationType, room_id,
+    /// # };
+    /// # use url::Url;
+    ///
+    /// # let homeserver = Url::parse("http://example.com").unwrap();
+    /// # async {
+    /// let options = assign!(RelationsOptions::backward(), {
+    ///     rel_type: Some(RelationType::Thread),
+    /// });
+    ///
+    /// let mut client = Client::new(homeserver).await.unwrap();
+    /// let room = client.get_room(room_id!("!roomid:example.com")).unwrap();
+    /// let thread = room.relations(event_id!("$root"), options).await.unwrap();
+    /// # };
+    /// ```
+    #[instrument(skip(self, options), fields(room_id = ?self.inner.room_id(), ?options))]
+    pub async fn relations(
+        &self,
+        event_id: &EventId,
+        options: RelationsOptions,
+    ) -> Result<Relations> {
+        let room_id = self.inner.room_id().to_owned();
+        let event_id = event_id.to_owned();
+
+        let (chunk, next_batch, prev_batch) = if let Some(rel_type) = options.rel_type {
+            let request = assign!(
+                get_relating_events_with_rel_type::v1::Request::new(room_id, event_id, rel_type),
+                { from: options.from, dir: options.dir, limit: options.limit }
+            );
+            let response = self.client.send(request, None).await?;
+            (response.chunk, response.next_batch, response.prev_batch)
+        } else {
+            let request = assign!(get_relating_events::v1::Request::new(room_id, event_id), {
+                from: options.from,
+                dir: options.dir,
+                limit: options.limit,
+            });
+            let response = self.client.send(request, None).await?;
+            (response.chunk, response.next_batch, response.prev_batch)
+        };
+
+        let mut relations =
+            Relations { chunk: Vec::with_capacity(chunk.len()), next_batch, prev_batch };
+
+        for event in chunk {
+            relations.chunk.push(self.try_decrypt_event(event.cast()).await);
         }
 
+        self.set_push_actions(&mut relations.chunk).await?;
+
+        Ok(relations)
+    }
+
+    /// Decrypt the given event if it is encrypted, falling back to the
+    /// undecrypted event if decryption fails.
+    pub(crate) async fn try_decrypt_event(&self, event: Raw<AnyTimelineEvent>) -> TimelineEvent {
+        #[cfg(feature = "e2e-encryption")]
+        if let Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomEncrypted(
+            SyncMessageLikeEvent::Original(_),
+        ))) = event.deserialize_as::<AnySyncTimelineEvent>()
+        {
+            if let Ok(event) = self.decrypt_event(event.cast_ref()).await {
+                return event;
+            }
+        }
+
+        TimelineEvent::new(event)
+    }
+
+    /// Compute the push actions of the given events with the push rules of
+    /// the current user.
+    async fn set_push_actions(&self, events: &mut [TimelineEvent]) -> Result<()> {
         if let Some(push_context) = self.push_context().await? {
             let push_rules = self.client().account().push_rules().await?;
 
-            for event in &mut response.chunk {
+            for event in events {
                 event.push_actions =
                     Some(push_rules.get_actions(&event.event, &push_context).to_owned());
             }
         }
 
-        Ok(response)
+        Ok(())
     }
 
     /// Register a handler for events of a specific type, within this room.
@@ -346,6 +487,67 @@ impl Room {
         (drop_guard, receiver)
     }
 
+    /// Get the pending requests to join this room, i.e. the members that have
+    /// knocked on it.
+    ///
+    /// *Note*: This method will fetch the members from the homeserver if the
+    /// member list isn't synchronized due to member lazy loading.
+    pub async fn knock_requests(&self) -> Result<Vec<KnockRequest>> {
+        Ok(self
+            .members(RoomMemberships::KNOCK)
+            .await?
+            .iter()
+            .map(|member| KnockRequest::new(self.clone(), member))
+            .collect())
+    }
+
+    /// Subscribe to the pending requests to join this room.
+    ///
+    /// The returned stream yields the current [`Room::knock_requests`] first,
+    /// then the updated list every time a sync response contains a member
+    /// event that knocks on the room, or that resolves a knock.
+    ///
+    /// The stream ends when the returned [`EventHandlerDropGuard`] is dropped.
+    pub fn subscribe_to_knock_requests(
+        &self,
+    ) -> (EventHandlerDropGuard, impl Stream<Item = Vec<KnockRequest>>) {
+        let (sender, mut receiver) = broadcast::channel(16);
+        let member_event_handler_handle = self.client.add_room_event_handler(
+            self.room_id(),
+            move |event: SyncRoomMemberEvent| async move {
+                let prev_membership = event
+                    .as_original()
+                    .and_then(|event| event.unsigned.prev_content.as_ref())
+                    .map(|content| &content.membership);
+
+                if *event.membership() == MembershipState::Knock
+                    || prev_membership == Some(&MembershipState::Knock)
+                {
+                    // Ignore the result. It can only fail if there are no listeners.
+                    let _ = sender.send(());
+                }
+            },
+        );
+        let drop_guard = self.client().event_handler_drop_guard(member_event_handler_handle);
+
+        let room = self.clone();
+        let stream = async_stream::stream! {
+            loop {
+                match room.knock_requests().await {
+                    Ok(requests) => yield requests,
+                    Err(error) => warn!(room_id = ?room.room_id(), "Failed to load the knock requests: {error}"),
+                }
+
+                match receiver.recv().await {
+                    Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        };
+
+        (drop_guard, stream)
+    }
+
     /// Fetch the event with the given `EventId` in this room.
     pub async fn event(&self, event_id: &EventId) -> Result<TimelineEvent> {
         let request =
@@ -369,15 +571,31 @@ impl Room {
 
     /// Fetch the event with the given `EventId` in this room, using the
     /// `/context` endpoint to get more information.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - The ID of the event to fetch.
+    ///
+    /// * `lazy_load_members` - Whether to only return the state of the members
+    ///   that sent the returned events.
+    ///
+    /// * `context_size` - The maximum number of events to return around the
+    ///   fetched event. The homeserver shares them between the events before
+    ///   and the events after it.
+    ///
+    /// With the encryption feature, events are decrypted if possible. If
+    /// decryption fails for an individual event, that event is returned
+    /// undecrypted.
     pub async fn event_with_context(
         &self,
         event_id: &EventId,
         lazy_load_members: bool,
-    ) -> Result<Option<(TimelineEvent, Vec<Raw<AnyStateEvent>>)>> {
+        context_size: UInt,
+    ) -> Result<EventWithContextResponse> {
         let mut request =
             context::get_context::v3::Request::new(self.room_id().to_owned(), event_id.to_owned());
 
-        request.limit = uint!(0);
+        request.limit = context_size;
 
         if lazy_load_members {
             request.filter.lazy_load_options =
@@ -386,23 +604,37 @@ impl Room {
 
         let response = self.client.send(request, None).await?;
 
-        let Some(event) = response.event else {
-            return Ok(None);
+        let event = match response.event {
+            Some(event) => {
+                let mut event = self.try_decrypt_event(event).await;
+                if event.push_actions.is_none() {
+                    event.push_actions = self.event_push_actions(&event.event).await?;
+                }
+                Some(event)
+            }
+            None => None,
         };
 
-        #[cfg(feature = "e2e-encryption")]
-        if let Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomEncrypted(
-            SyncMessageLikeEvent::Original(_),
-        ))) = event.deserialize_as::<AnySyncTimelineEvent>()
-        {
-            if let Ok(event) = self.decrypt_event(event.cast_ref()).await {
-                return Ok(Some((event, response.state)));
-            }
+        let mut events_before = Vec::with_capacity(response.events_before.len());
+        for event in response.events_before {
+            events_before.push(self.try_decrypt_event(event).await);
         }
+        self.set_push_actions(&mut events_before).await?;
 
-        let push_actions = self.event_push_actions(&event).await?;
+        let mut events_after = Vec::with_capacity(response.events_after.len());
+        for event in response.events_after {
+            events_after.push(self.try_decrypt_event(event).await);
+        }
+        self.set_push_actions(&mut events_after).await?;
 
-        Ok(Some((TimelineEvent { event, encryption_info: None, push_actions }, response.state)))
+        Ok(EventWithContextResponse {
+            event,
+            events_before,
+            events_after,
+            prev_batch_token: response.start,
+            next_batch_token: response.end,
+            state: response.state,
+        })
     }
 
     pub(crate) async fn request_members(&self) -> Result<()> {
@@ -589,7 +821,7 @@ impl Room {
             .inner
             .get_member(user_id)
             .await?
-            .map(|member| RoomMember::new(self.client.clone(), member)))
+            .map(|member| RoomMember::new(self.client.clone(), self.room_id().to_owned(), member)))
     }
 
     /// Get members for this room, with the given memberships.
@@ -619,7 +851,7 @@ impl Room {
             .members(memberships)
             .await?
             .into_iter()
-            .map(|member| RoomMember::new(self.client.clone(), member))
+            .map(|member| RoomMember::new(self.client.clone(), self.room_id().to_owned(), member))
             .collect())
     }
 
@@ -849,6 +1081,51 @@ impl Room {
             .collect::<FuturesUnordered<_>>())
     }
 
+    /// Upgrade this room to the given room version.
+    ///
+    /// The homeserver creates a new room with this version, copies the
+    /// important state of this room to it, and tombstones this room with an
+    /// `m.room.tombstone` event pointing to the new room.
+    ///
+    /// Returns the ID of the new room.
+    #[instrument(skip(self), fields(room_id = ?self.room_id()))]
+    pub async fn upgrade(&self, new_version: RoomVersionId) -> Result<OwnedRoomId> {
+        self.ensure_room_joined()?;
+
+        let request = upgrade_room::v3::Request::new(self.room_id().to_owned(), new_version);
+        let response = self.client.send(request, None).await?;
+
+        Ok(response.replacement_room)
+    }
+
+    /// Join the room that replaces this room, if it has been tombstoned.
+    ///
+    /// The new room is joined through the servers returned by
+    /// [`Room::route`], which should also know the new room.
+    ///
+    /// Returns `None` if this room hasn't been tombstoned. If the new room is
+    /// already joined, it is returned without sending a request.
+    #[instrument(skip(self), fields(room_id = ?self.room_id()))]
+    pub async fn join_successor(&self) -> Result<Option<Room>> {
+        let Some(successor) = self.successor_room() else {
+            return Ok(None);
+        };
+
+        if let Some(room) = self.client.get_room(&successor.room_id) {
+            if room.state() == RoomState::Joined {
+                return Ok(Some(room));
+            }
+        }
+
+        let server_names = self.route().await?;
+        let room = self
+            .client
+            .join_room_by_id_or_alias((&*successor.room_id).into(), &server_names)
+            .await?;
+
+        Ok(Some(room))
+    }
+
     /// Read account data in this room, from storage.
     pub async fn account_data(
         &self,
@@ -1720,6 +1997,43 @@ impl Room {
         SendAttachment::new(self, body, content_type, data, config)
     }
 
+    /// Send an attachment to this room, streaming its data instead of holding
+    /// it in memory.
+    ///
+    /// This works like [`send_attachment()`](Self::send_attachment), except
+    /// that a thumbnail can't be generated from the stream. A thumbnail can
+    /// still be provided with [`AttachmentConfig::with_thumbnail()`]. If the
+    /// room is encrypted, the data is encrypted on the fly.
+    ///
+    /// The upload can't be resumed, if it fails it needs to start over with a
+    /// new stream. It can be cancelled by dropping the returned future.
+    ///
+    /// # Arguments
+    /// * `body` - A textual representation of the media that is going to be
+    /// uploaded. Usually the file name.
+    ///
+    /// * `content_type` - The type of the media, this will be used as the
+    /// content-type header.
+    ///
+    /// * `stream` - The stream of the raw bytes of the media.
+    ///
+    /// * `size` - The total size of the media in bytes. It must match the
+    /// number of bytes produced by the stream.
+    ///
+    /// * `config` - Metadata and configuration for the attachment.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[instrument(skip_all)]
+    pub fn send_attachment_stream<'a>(
+        &'a self,
+        body: &'a str,
+        content_type: &'a Mime,
+        stream: impl Stream<Item = std::io::Result<Bytes>> + Send + Sync + 'static,
+        size: u64,
+        config: AttachmentConfig,
+    ) -> SendAttachmentStream<'a> {
+        SendAttachmentStream::new(self, body, content_type, Box::pin(stream), size, config)
+    }
+
     /// Prepare and send an attachment to this room.
     ///
     /// This will upload the given data that the reader produces using the
@@ -1746,7 +2060,7 @@ impl Room {
         &'a self,
         body: &'a str,
         content_type: &'a Mime,
-        data: Vec<u8>,
+        data: AttachmentData,
         config: AttachmentConfig,
         send_progress: SharedObservable<TransmissionProgress>,
     ) -> Result<send_message_event::v3::Response> {
@@ -0,0 +1,270 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Server-side search of the messages in the rooms of the current user.
+//!
+//! See [`Client::search`] and [`Room::search_messages`].
+//!
+//! [`Room::search_messages`]: crate::Room::search_messages
+
+use std::{collections::BTreeMap, future::IntoFuture};
+
+use matrix_sdk_common::{boxed_into_future, deserialized_responses::TimelineEvent};
+use ruma::{
+    api::client::{
+        filter::RoomEventFilter,
+        search::search_events::v3::{
+            self, Categories, Criteria, EventContext, OrderBy, SearchKeys, UserProfile,
+        },
+    },
+    events::{AnyStateEvent, AnyTimelineEvent},
+    serde::Raw,
+    OwnedEventId, OwnedRoomId, OwnedUserId, UInt,
+};
+use tracing::{instrument, warn};
+
+use crate::{Client, Result};
+
+/// Future returned by [`Client::search`] and
+/// [`Room::search_messages`](crate::Room::search_messages).
+///
+/// Only the events that the homeserver can read are searched, which excludes
+/// the messages of encrypted rooms.
+#[allow(missing_debug_implementations)]
+pub struct SearchMessages {
+    client: Client,
+    room_id: Option<OwnedRoomId>,
+    criteria: Criteria,
+    next_batch: Option<String>,
+}
+
+impl SearchMessages {
+    pub(crate) fn new(client: Client, room_id: Option<OwnedRoomId>, search_term: String) -> Self {
+        Self { client, room_id, criteria: Criteria::new(search_term), next_batch: None }
+    }
+
+    /// Set the order of the results.
+    ///
+    /// The homeserver orders results by rank if this isn't set.
+    pub fn order_by(mut self, order_by: OrderBy) -> Self {
+        self.criteria.order_by = Some(order_by);
+        self
+    }
+
+    /// Set the keys of the events to search in.
+    ///
+    /// The homeserver searches in `content.body`, `content.name` and
+    /// `content.topic` if this isn't set.
+    pub fn keys(mut self, keys: Vec<SearchKeys>) -> Self {
+        self.criteria.keys = Some(keys);
+        self
+    }
+
+    /// Set a filter to apply to the searched events.
+    ///
+    /// When searching in a single room, the `rooms` field of the filter is
+    /// overridden to only contain this room.
+    pub fn filter(mut self, filter: RoomEventFilter) -> Self {
+        self.criteria.filter = filter;
+        self
+    }
+
+    /// Request some context around each result.
+    ///
+    /// # Arguments
+    ///
+    /// * `before_limit` - The number of events to return before each result.
+    ///
+    /// * `after_limit` - The number of events to return after each result.
+    ///
+    /// * `include_profile` - Whether to return the profiles of the senders of
+    ///   the returned events, as they were at the time of each result.
+    pub fn context(mut self, before_limit: UInt, after_limit: UInt, include_profile: bool) -> Self {
+        let mut event_context = EventContext::new();
+        event_context.before_limit = before_limit;
+        event_context.after_limit = after_limit;
+        event_context.include_profile = include_profile;
+        self.criteria.event_context = event_context;
+        self
+    }
+
+    /// Whether to return the current state of the rooms of the results.
+    pub fn include_state(mut self, include_state: bool) -> Self {
+        self.criteria.include_state = Some(include_state);
+        self
+    }
+
+    /// Continue a previous search, with the [`SearchResults::next_batch`]
+    /// token it returned.
+    ///
+    /// The other options must be the same as the ones of the previous search.
+    pub fn next_batch(mut self, next_batch: impl Into<String>) -> Self {
+        self.next_batch = Some(next_batch.into());
+        self
+    }
+}
+
+impl IntoFuture for SearchMessages {
+    type Output = Result<SearchResults>;
+    boxed_into_future!();
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { client, room_id, mut criteria, next_batch } = self;
+
+        Box::pin(async move {
+            if let Some(room_id) = room_id {
+                criteria.filter.rooms = Some(vec![room_id]);
+            }
+
+            let mut search_categories = Categories::new();
+            search_categories.room_events = Some(criteria);
+
+            let mut request = v3::Request::new(search_categories);
+            request.next_batch = next_batch;
+
+            let response = client.send(request, None).await?;
+            Ok(SearchResults::from_response(&client, response.search_categories.room_events).await)
+        })
+    }
+}
+
+/// The results of a search, returned by [`SearchMessages`].
+#[derive(Debug)]
+pub struct SearchResults {
+    /// An approximation of the total number of results, if the homeserver
+    /// provides it.
+    pub count: Option<UInt>,
+
+    /// The results in this batch.
+    pub results: Vec<SearchResult>,
+
+    /// The words that the homeserver matched, which can be highlighted in the
+    /// results.
+    pub highlights: Vec<String>,
+
+    /// The token to get the next batch of results, with
+    /// [`SearchMessages::next_batch`].
+    ///
+    /// If this is `None`, there are no more results.
+    pub next_batch: Option<String>,
+
+    /// The current state of the rooms of the results, if it was requested
+    /// with [`SearchMessages::include_state`].
+    pub state: BTreeMap<OwnedRoomId, Vec<Raw<AnyStateEvent>>>,
+}
+
+impl SearchResults {
+    #[instrument(skip_all)]
+    async fn from_response(client: &Client, response: v3::ResultRoomEvents) -> Self {
+        let mut results = Vec::with_capacity(response.results.len());
+
+        for result in response.results {
+            let Some(event) = result.result else { continue };
+
+            let Some(room_id) = event.get_field::<OwnedRoomId>("room_id").ok().flatten() else {
+                warn!("Search result without a room ID, ignoring");
+                continue;
+            };
+            let Some(event_id) = event.get_field::<OwnedEventId>("event_id").ok().flatten() else {
+                warn!("Search result without an event ID, ignoring");
+                continue;
+            };
+
+            let decrypt = Decryptor { client, room_id: &room_id };
+            let event = decrypt.event(event).await;
+            let events_before = decrypt.events(result.context.events_before).await;
+            let events_after = decrypt.events(result.context.events_after).await;
+
+            results.push(SearchResult {
+                room_id,
+                event_id,
+                event,
+                rank: result.rank,
+                events_before,
+                events_after,
+                start: result.context.start,
+                end: result.context.end,
+                profile_info: result.context.profile_info,
+            });
+        }
+
+        Self {
+            count: response.count,
+            results,
+            highlights: response.highlights,
+            next_batch: response.next_batch,
+            state: response.state,
+        }
+    }
+}
+
+/// A single result of a search.
+///
+/// The `room_id` and `event_id` can be used to open the room at the matching
+/// event, for example with an event-focused timeline.
+#[derive(Debug)]
+pub struct SearchResult {
+    /// The ID of the room of the matching event.
+    pub room_id: OwnedRoomId,
+
+    /// The ID of the matching event.
+    pub event_id: OwnedEventId,
+
+    /// The matching event.
+    pub event: TimelineEvent,
+
+    /// The rank of the result, if the results are ordered by rank.
+    pub rank: Option<f64>,
+
+    /// Events before the matching event, if context was requested, in reverse
+    /// chronological order.
+    pub events_before: Vec<TimelineEvent>,
+
+    /// Events after the matching event, if context was requested, in
+    /// chronological order.
+    pub events_after: Vec<TimelineEvent>,
+
+    /// A token to paginate backwards from the start of the context.
+    pub start: Option<String>,
+
+    /// A token to paginate forwards from the end of the context.
+    pub end: Option<String>,
+
+    /// The profiles of the senders of the events, if they were requested with
+    /// [`SearchMessages::context`].
+    pub profile_info: BTreeMap<OwnedUserId, UserProfile>,
+}
+
+/// Decrypts the events of a search result, if the room is known.
+struct Decryptor<'a> {
+    client: &'a Client,
+    room_id: &'a OwnedRoomId,
+}
+
+impl Decryptor<'_> {
+    async fn event(&self, event: Raw<AnyTimelineEvent>) -> TimelineEvent {
+        match self.client.get_room(self.room_id) {
+            Some(room) => room.try_decrypt_event(event).await,
+            None => TimelineEvent::new(event),
+        }
+    }
+
+    async fn events(&self, events: Vec<Raw<AnyTimelineEvent>>) -> Vec<TimelineEvent> {
+        let mut decrypted = Vec::with_capacity(events.len());
+        for event in events {
+            decrypted.push(self.event(event).await);
+        }
+        decrypted
+    }
+}
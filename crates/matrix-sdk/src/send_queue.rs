@@ -0,0 +1,1029 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A send queue, persisting the events to send to a room until they've been
+//! sent.
+//!
+//! Each room has its own [`RoomSendQueue`], which can be retrieved with
+//! [`Room::send_queue`]. Events pushed to it are saved in the state store
+//! first, so they survive the application being killed, and are then sent one
+//! at a time by a background task. The content of the queued attachments is
+//! kept in the media cache store until it's sent.
+//!
+//! Network errors are retried with an exponential backoff. Any other error
+//! marks the event as *wedged*: it stays in the queue, but won't be sent again
+//! until [`RoomSendQueue::retry`] is called, or it's removed with
+//! [`RoomSendQueue::cancel`]. The events queued after a wedged event aren't
+//! sent either in the meantime, to keep the order of the messages.
+//!
+//! Events queued during a previous session aren't sent again until
+//! [`SendQueue::respawn_tasks_for_rooms_with_unsent_events`] is called.
+
+#[cfg(feature = "e2e-encryption")]
+use std::io::Cursor;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    future::IntoFuture,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex as StdMutex, RwLock as StdRwLock, Weak,
+    },
+    time::Duration,
+};
+
+use eyeball::SharedObservable;
+pub use matrix_sdk_base::store::QueuedRequestKind;
+use matrix_sdk_base::{
+    media::{MediaFormat, MediaRequest},
+    media_cache_store::MediaCacheStoreError,
+    store::QueuedRequest,
+    RoomState, StoreError,
+};
+use matrix_sdk_common::{boxed_into_future, executor::spawn};
+use mime::Mime;
+use ruma::{
+    api::client::error::{ErrorBody, ErrorKind},
+    assign,
+    events::{
+        room::{
+            message::{MessageType, RoomMessageEventContent},
+            MediaSource, ThumbnailInfo,
+        },
+        AnyMessageLikeEventContent, EventContent, EventContentFromType,
+    },
+    serde::Raw,
+    MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedMxcUri, OwnedRoomId, OwnedTransactionId,
+    TransactionId,
+};
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tracing::{debug, info, instrument, trace, warn, Instrument, Span};
+
+#[cfg(feature = "image-proc")]
+use crate::ImageError;
+use crate::{
+    attachment::AttachmentConfig, client::ClientInner, error::RumaApiError,
+    media::make_attachment_type, Client, Error, HttpError, Room, TransmissionProgress,
+};
+
+/// The server name of the local MXC URIs of the queued attachments, in the
+/// media cache store.
+const ATTACHMENT_SERVER_NAME: &str = "send-queue.localhost";
+
+/// The longest delay between two attempts to send an event, after a network
+/// error.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// A client-wide send queue, giving access to the send queue of each room.
+#[derive(Debug, Clone)]
+pub struct SendQueue {
+    client: Client,
+}
+
+impl SendQueue {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    fn data(&self) -> &SendQueueData {
+        &self.client.inner.send_queue_data
+    }
+
+    /// Get the send queue of the given room, spawning its sending task if
+    /// needed.
+    pub(crate) fn for_room(&self, room: Room) -> RoomSendQueue {
+        let data = self.data();
+
+        let mut rooms = data.rooms.write().unwrap();
+        if let Some(room_queue) = rooms.get(room.room_id()) {
+            return room_queue.clone();
+        }
+
+        let room_queue = RoomSendQueue::new(data.enabled.clone(), &room);
+        rooms.insert(room.room_id().to_owned(), room_queue.clone());
+        room_queue
+    }
+
+    /// Resume sending the events that were queued during a previous session.
+    ///
+    /// This should be called once the client has been restored, so the rooms
+    /// are known.
+    pub async fn respawn_tasks_for_rooms_with_unsent_events(
+        &self,
+    ) -> Result<(), RoomSendQueueError> {
+        let room_ids = self.client.store().load_rooms_with_unsent_requests().await?;
+
+        for room_id in room_ids {
+            if let Some(room) = self.client.get_room(&room_id) {
+                let room_queue = self.for_room(room);
+                room_queue.notify();
+            } else {
+                warn!(%room_id, "Unknown room with unsent events");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Enable or disable the sending of events, for all the rooms.
+    ///
+    /// Events can still be queued while sending is disabled; they're sent once
+    /// it's enabled again.
+    pub fn set_enabled(&self, enabled: bool) {
+        let data = self.data();
+        data.enabled.store(enabled, Ordering::SeqCst);
+
+        if enabled {
+            for room_queue in data.rooms.read().unwrap().values() {
+                room_queue.notify();
+            }
+        }
+    }
+
+    /// Whether the sending of events is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.data().enabled.load(Ordering::SeqCst)
+    }
+}
+
+/// The send queue data shared by all the clones of a [`Client`].
+#[derive(Debug)]
+pub(crate) struct SendQueueData {
+    /// The send queue of each room, created on demand.
+    rooms: StdRwLock<BTreeMap<OwnedRoomId, RoomSendQueue>>,
+
+    /// Whether sending events is enabled, for all the rooms.
+    enabled: Arc<AtomicBool>,
+}
+
+impl Default for SendQueueData {
+    fn default() -> Self {
+        Self { rooms: Default::default(), enabled: Arc::new(AtomicBool::new(true)) }
+    }
+}
+
+/// An event that has been queued, but not sent yet.
+#[derive(Clone, Debug)]
+pub struct LocalEcho {
+    /// The transaction ID the event will be sent with.
+    pub transaction_id: OwnedTransactionId,
+    /// What is to be sent.
+    pub kind: QueuedRequestKind,
+    /// Whether sending the event failed with an error that won't go away by
+    /// itself, so it's waiting for [`RoomSendQueue::retry`].
+    pub is_wedged: bool,
+}
+
+impl LocalEcho {
+    /// Deserialize the content of the event.
+    ///
+    /// The content of an attachment is an `m.room.message` event, whose media
+    /// sources point to the local copies of the file and of its thumbnail in
+    /// the media cache store, until they're uploaded.
+    pub fn deserialize_content(&self) -> serde_json::Result<AnyMessageLikeEventContent> {
+        match &self.kind {
+            QueuedRequestKind::Event { event_type, content } => {
+                AnyMessageLikeEventContent::from_parts(event_type, content.json())
+            }
+            QueuedRequestKind::Attachment { content, .. } => {
+                content.deserialize().map(AnyMessageLikeEventContent::RoomMessage)
+            }
+        }
+    }
+}
+
+impl From<QueuedRequest> for LocalEcho {
+    fn from(request: QueuedRequest) -> Self {
+        Self {
+            transaction_id: request.transaction_id,
+            kind: request.kind,
+            is_wedged: request.is_wedged,
+        }
+    }
+}
+
+/// An update of a [`RoomSendQueue`].
+#[derive(Clone, Debug)]
+pub enum RoomSendQueueUpdate {
+    /// A new event has been queued.
+    NewLocalEvent(LocalEcho),
+
+    /// A queued event has been removed from the queue before being sent.
+    CancelledLocalEvent {
+        /// The transaction ID of the cancelled event.
+        transaction_id: OwnedTransactionId,
+    },
+
+    /// A wedged event is going to be sent again.
+    RetryEvent {
+        /// The transaction ID of the event.
+        transaction_id: OwnedTransactionId,
+    },
+
+    /// Sending an event failed.
+    ///
+    /// If the error is recoverable, the event will be sent again
+    /// automatically. Otherwise, the event is wedged.
+    SendError {
+        /// The transaction ID of the event.
+        transaction_id: OwnedTransactionId,
+        /// The error that happened when sending the event.
+        error: Arc<Error>,
+        /// Whether sending the event will be attempted again automatically.
+        is_recoverable: bool,
+    },
+
+    /// An event has been sent, and removed from the queue.
+    SentEvent {
+        /// The transaction ID of the event.
+        transaction_id: OwnedTransactionId,
+        /// The event ID assigned by the server.
+        event_id: OwnedEventId,
+    },
+}
+
+/// An error when using a [`RoomSendQueue`].
+#[derive(Debug, thiserror::Error)]
+pub enum RoomSendQueueError {
+    /// Events can only be queued in joined rooms.
+    #[error("the room isn't in the joined state")]
+    RoomNotJoined,
+
+    /// The client or the room has been dropped.
+    #[error("the room is now missing from the client")]
+    RoomDisappeared,
+
+    /// An event was queued while another one was being sent with the same
+    /// transaction ID.
+    #[error("an event with the same transaction ID is already being sent")]
+    TransactionIdInUse,
+
+    /// An error happened when saving or loading the queue.
+    #[error(transparent)]
+    StorageError(#[from] StoreError),
+
+    /// An error happened when saving or removing the content of an
+    /// attachment.
+    #[error(transparent)]
+    MediaCacheStorageError(#[from] MediaCacheStoreError),
+
+    /// An error happened when serializing the content of an event.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// An error happened when generating the thumbnail of an attachment.
+    #[cfg(feature = "image-proc")]
+    #[error(transparent)]
+    ThumbnailError(#[from] ImageError),
+
+    /// The content of an attachment is missing from the media cache store, so
+    /// it can't be uploaded.
+    #[error("the content of the attachment is missing from the media cache")]
+    MissingMediaContent,
+
+    /// The event couldn't be sent in a previous session, and the error that
+    /// wedged it is not known anymore.
+    #[error("the event couldn't be sent in a previous session")]
+    WedgedEvent,
+}
+
+/// The send queue of a room.
+///
+/// Get one with [`Room::send_queue`].
+#[derive(Debug, Clone)]
+pub struct RoomSendQueue {
+    inner: Arc<RoomSendQueueInner>,
+
+    /// Wakes up the sending task.
+    ///
+    /// The sending task stops once all the handles on the queue, and so all
+    /// the senders, have been dropped.
+    notifier: mpsc::UnboundedSender<()>,
+}
+
+#[derive(Debug)]
+struct RoomSendQueueInner {
+    room: WeakRoom,
+
+    /// Whether sending events is enabled, for all the rooms.
+    enabled: Arc<AtomicBool>,
+
+    /// The sender of the updates of this queue.
+    updates: broadcast::Sender<RoomSendQueueUpdate>,
+
+    /// Serializes the accesses to the persisted queue, and holds the
+    /// transaction ID of the event being sent, if any.
+    being_sent: Mutex<Option<OwnedTransactionId>>,
+
+    /// The observables of the progress of the upload of the attachments queued
+    /// during this session, by transaction ID.
+    upload_progress: StdMutex<HashMap<OwnedTransactionId, SharedObservable<TransmissionProgress>>>,
+}
+
+impl RoomSendQueue {
+    fn new(enabled: Arc<AtomicBool>, room: &Room) -> Self {
+        let inner = Arc::new(RoomSendQueueInner {
+            room: WeakRoom::new(room),
+            enabled,
+            updates: broadcast::Sender::new(32),
+            being_sent: Mutex::new(None),
+            upload_progress: Default::default(),
+        });
+
+        let (notifier, receiver) = mpsc::unbounded_channel();
+        spawn(Self::sending_task(inner.clone(), receiver));
+
+        Self { inner, notifier }
+    }
+
+    /// Wake up the sending task, to send the next events of the queue.
+    fn notify(&self) {
+        let _ = self.notifier.send(());
+    }
+
+    /// Queue a message-like event to be sent to the room.
+    ///
+    /// Returns the transaction ID the event will be sent with.
+    pub async fn send(
+        &self,
+        content: AnyMessageLikeEventContent,
+    ) -> Result<OwnedTransactionId, RoomSendQueueError> {
+        let transaction_id = TransactionId::new();
+        self.send_with_transaction_id(content, transaction_id.clone()).await?;
+        Ok(transaction_id)
+    }
+
+    /// Queue a message-like event to be sent to the room, with the given
+    /// transaction ID.
+    pub async fn send_with_transaction_id(
+        &self,
+        content: AnyMessageLikeEventContent,
+        transaction_id: OwnedTransactionId,
+    ) -> Result<(), RoomSendQueueError> {
+        let event_type = content.event_type().to_string();
+        let content = Raw::new(&content)?.cast();
+
+        self.push(transaction_id, QueuedRequestKind::Event { event_type, content }).await
+    }
+
+    /// Queue a media file to be uploaded, and then sent to the room.
+    ///
+    /// The content of the file, and of its thumbnail if any, is saved in the
+    /// media cache store until it's been sent. The local echo of the event is
+    /// an `m.room.message` whose media sources can be used to load them from
+    /// the media cache in the meantime.
+    ///
+    /// The returned future resolves with the transaction ID the event will be
+    /// sent with, once the attachment has been queued.
+    pub fn send_attachment<'a>(
+        &'a self,
+        body: &'a str,
+        content_type: &'a Mime,
+        data: Vec<u8>,
+        config: AttachmentConfig,
+    ) -> SendAttachment<'a> {
+        SendAttachment::new(self, body, content_type, data, config)
+    }
+
+    /// Subscribe to the updates of the queue.
+    ///
+    /// To not miss any update, subscribe before calling
+    /// [`RoomSendQueue::local_echoes`].
+    pub fn subscribe(&self) -> broadcast::Receiver<RoomSendQueueUpdate> {
+        self.inner.updates.subscribe()
+    }
+
+    /// Get the events that haven't been sent yet, in the order they'll be
+    /// sent.
+    pub async fn local_echoes(&self) -> Result<Vec<LocalEcho>, RoomSendQueueError> {
+        let _guard = self.inner.being_sent.lock().await;
+
+        let room = self.inner.room.get().ok_or(RoomSendQueueError::RoomDisappeared)?;
+        let requests = room.client.store().load_send_queue_requests(room.room_id()).await?;
+
+        Ok(requests.into_iter().map(Into::into).collect())
+    }
+
+    /// Remove an event from the queue, before it's sent.
+    ///
+    /// Returns `false` if the event couldn't be found, or if it's currently
+    /// being sent.
+    #[instrument(skip(self))]
+    pub async fn cancel(&self, transaction_id: &TransactionId) -> Result<bool, RoomSendQueueError> {
+        let being_sent = self.inner.being_sent.lock().await;
+        if being_sent.as_deref() == Some(transaction_id) {
+            debug!("Can't cancel an event that is being sent");
+            return Ok(false);
+        }
+
+        let room = self.inner.room.get().ok_or(RoomSendQueueError::RoomDisappeared)?;
+        if !room.client.store().remove_send_queue_request(room.room_id(), transaction_id).await? {
+            return Ok(false);
+        }
+        remove_attachment_content(&room, transaction_id).await;
+        self.inner.upload_progress.lock().unwrap().remove(transaction_id);
+
+        let _ = self.inner.updates.send(RoomSendQueueUpdate::CancelledLocalEvent {
+            transaction_id: transaction_id.to_owned(),
+        });
+
+        // The cancelled event might have been blocking the queue.
+        drop(being_sent);
+        self.notify();
+
+        Ok(true)
+    }
+
+    /// Send a wedged event again.
+    ///
+    /// The event is moved to the end of the queue, so it's sent after the
+    /// events that were queued in the meantime.
+    ///
+    /// Returns `false` if the event couldn't be found.
+    #[instrument(skip(self))]
+    pub async fn retry(&self, transaction_id: &TransactionId) -> Result<bool, RoomSendQueueError> {
+        {
+            let _guard = self.inner.being_sent.lock().await;
+
+            let room = self.inner.room.get().ok_or(RoomSendQueueError::RoomDisappeared)?;
+            let store = room.client.store();
+
+            let Some(mut request) = store
+                .load_send_queue_requests(room.room_id())
+                .await?
+                .into_iter()
+                .find(|req| req.transaction_id == transaction_id)
+            else {
+                return Ok(false);
+            };
+
+            // Remove the request before saving it again, to move it to the end of the queue.
+            store.remove_send_queue_request(room.room_id(), transaction_id).await?;
+            request.is_wedged = false;
+            store.save_send_queue_request(room.room_id(), request).await?;
+        }
+
+        let _ = self
+            .inner
+            .updates
+            .send(RoomSendQueueUpdate::RetryEvent { transaction_id: transaction_id.to_owned() });
+        self.notify();
+
+        Ok(true)
+    }
+
+    async fn push(
+        &self,
+        transaction_id: OwnedTransactionId,
+        kind: QueuedRequestKind,
+    ) -> Result<(), RoomSendQueueError> {
+        {
+            let being_sent = self.inner.being_sent.lock().await;
+            if being_sent.as_ref() == Some(&transaction_id) {
+                return Err(RoomSendQueueError::TransactionIdInUse);
+            }
+
+            let room = self.inner.room.get().ok_or(RoomSendQueueError::RoomDisappeared)?;
+            if room.state() != RoomState::Joined {
+                return Err(RoomSendQueueError::RoomNotJoined);
+            }
+
+            let request = QueuedRequest { transaction_id, kind, is_wedged: false };
+
+            // Remove any request with the same transaction ID first, so the new one is
+            // added at the end of the queue.
+            let store = room.client.store();
+            store.remove_send_queue_request(room.room_id(), &request.transaction_id).await?;
+            store.save_send_queue_request(room.room_id(), request.clone()).await?;
+
+            let _ = self.inner.updates.send(RoomSendQueueUpdate::NewLocalEvent(request.into()));
+        }
+
+        self.notify();
+
+        Ok(())
+    }
+
+    /// Send the events of the queue, one at a time, every time the queue is
+    /// notified.
+    ///
+    /// The task only holds a weak reference to the client, through
+    /// [`WeakRoom`], and stops once the client or all the handles on the queue
+    /// have been dropped.
+    #[instrument(skip_all)]
+    async fn sending_task(
+        inner: Arc<RoomSendQueueInner>,
+        mut receiver: mpsc::UnboundedReceiver<()>,
+    ) {
+        info!("Spawned the sending task");
+
+        let mut attempt = 0;
+
+        while receiver.recv().await.is_some() {
+            // Send the events until the queue is empty, or sending is blocked.
+            loop {
+                let Some(room) = inner.room.get() else {
+                    info!("The room has been dropped, stopping the sending task");
+                    return;
+                };
+
+                if !inner.enabled.load(Ordering::SeqCst) {
+                    trace!("Sending is disabled, waiting");
+                    break;
+                }
+
+                let request = {
+                    let mut being_sent = inner.being_sent.lock().await;
+
+                    let next =
+                        match room.client.store().load_send_queue_requests(room.room_id()).await {
+                            Ok(requests) => requests.into_iter().next(),
+                            Err(error) => {
+                                warn!("Couldn't load the queue: {error}");
+                                None
+                            }
+                        };
+
+                    *being_sent = next
+                        .as_ref()
+                        .filter(|req| !req.is_wedged)
+                        .map(|req| req.transaction_id.clone());
+                    next
+                };
+
+                let Some(request) = request else {
+                    trace!("Nothing to send, waiting");
+                    break;
+                };
+
+                if request.is_wedged {
+                    trace!(txn_id = %request.transaction_id, "The queue is blocked by a wedged event, waiting");
+                    break;
+                }
+
+                if room.state() != RoomState::Joined {
+                    info!("The room isn't joined anymore, waiting");
+                    *inner.being_sent.lock().await = None;
+                    break;
+                }
+
+                let transaction_id = request.transaction_id.clone();
+                trace!(txn_id = %transaction_id, "Sending an event");
+
+                let send_progress = inner
+                    .upload_progress
+                    .lock()
+                    .unwrap()
+                    .get(&transaction_id)
+                    .cloned()
+                    .unwrap_or_default();
+
+                match send_request(&room, request, send_progress).await {
+                    Ok(event_id) => {
+                        attempt = 0;
+
+                        {
+                            let mut being_sent = inner.being_sent.lock().await;
+                            if let Err(error) = room
+                                .client
+                                .store()
+                                .remove_send_queue_request(room.room_id(), &transaction_id)
+                                .await
+                            {
+                                warn!("Couldn't remove the sent event from the queue: {error}");
+                            }
+                            remove_attachment_content(&room, &transaction_id).await;
+                            *being_sent = None;
+                        }
+                        inner.upload_progress.lock().unwrap().remove(&transaction_id);
+
+                        let _ = inner
+                            .updates
+                            .send(RoomSendQueueUpdate::SentEvent { transaction_id, event_id });
+                    }
+
+                    Err(error) => {
+                        let is_recoverable = is_recoverable(&error);
+
+                        if !is_recoverable {
+                            attempt = 0;
+
+                            let mut being_sent = inner.being_sent.lock().await;
+                            if let Err(error) = mark_as_wedged(&room, &transaction_id).await {
+                                warn!("Couldn't mark the event as wedged: {error}");
+                            }
+                            *being_sent = None;
+                        } else {
+                            *inner.being_sent.lock().await = None;
+                        }
+
+                        warn!(txn_id = %transaction_id, is_recoverable, "Failed to send an event: {error}");
+
+                        let _ = inner.updates.send(RoomSendQueueUpdate::SendError {
+                            transaction_id,
+                            error: Arc::new(error),
+                            is_recoverable,
+                        });
+
+                        if is_recoverable {
+                            let delay = retry_delay(attempt);
+                            attempt += 1;
+
+                            debug!("Retrying in {delay:?}");
+                            // Don't keep the client alive while waiting.
+                            drop(room);
+                            sleep(delay).await;
+                        }
+                    }
+                }
+            }
+        }
+
+        info!("The send queue has been dropped, stopping the sending task");
+    }
+}
+
+/// Future returned by [`RoomSendQueue::send_attachment`].
+pub struct SendAttachment<'a> {
+    queue: &'a RoomSendQueue,
+    body: &'a str,
+    content_type: &'a Mime,
+    data: Vec<u8>,
+    config: AttachmentConfig,
+    tracing_span: Span,
+    send_progress: SharedObservable<TransmissionProgress>,
+}
+
+impl<'a> SendAttachment<'a> {
+    fn new(
+        queue: &'a RoomSendQueue,
+        body: &'a str,
+        content_type: &'a Mime,
+        data: Vec<u8>,
+        config: AttachmentConfig,
+    ) -> Self {
+        Self {
+            queue,
+            body,
+            content_type,
+            data,
+            config,
+            tracing_span: Span::current(),
+            send_progress: Default::default(),
+        }
+    }
+
+    /// Replace the default `SharedObservable` used for tracking the progress
+    /// of the upload.
+    ///
+    /// The progress is only reported while the attachment is uploaded by the
+    /// queue during this session.
+    pub fn with_send_progress_observable(
+        mut self,
+        send_progress: SharedObservable<TransmissionProgress>,
+    ) -> Self {
+        self.send_progress = send_progress;
+        self
+    }
+}
+
+impl fmt::Debug for SendAttachment<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendAttachment")
+            .field("body", &self.body)
+            .field("content_type", &self.content_type)
+            .field("size", &self.data.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> IntoFuture for SendAttachment<'a> {
+    type Output = Result<OwnedTransactionId, RoomSendQueueError>;
+    boxed_into_future!(extra_bounds: 'a);
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { queue, body, content_type, data, config, tracing_span, send_progress } = self;
+
+        let fut = async move {
+            let room = queue.inner.room.get().ok_or(RoomSendQueueError::RoomDisappeared)?;
+            let transaction_id = config.txn_id.clone().unwrap_or_else(TransactionId::new);
+
+            #[cfg(feature = "image-proc")]
+            let (data, config) = config.generate_thumbnail_if_needed(content_type, data).await?;
+
+            queue
+                .inner
+                .upload_progress
+                .lock()
+                .unwrap()
+                .insert(transaction_id.clone(), send_progress);
+
+            let result = async {
+                let media_cache_store = room.client.base_client().media_cache_store();
+                let now = MilliSecondsSinceUnixEpoch::now();
+
+                // Pin the file and its thumbnail so they're not evicted from the cache
+                // before they're sent.
+                let (thumbnail_source, thumbnail_info, thumbnail_content_type) =
+                    if let Some(thumbnail) = config.thumbnail {
+                        let request = thumbnail_request(&transaction_id);
+                        media_cache_store
+                            .add_media_content(&request, thumbnail.data, true, now)
+                            .await?;
+
+                        #[rustfmt::skip]
+                        let thumbnail_info =
+                            assign!(thumbnail.info.map(ThumbnailInfo::from).unwrap_or_default(), {
+                                mimetype: Some(thumbnail.content_type.as_ref().to_owned())
+                            });
+
+                        (
+                            Some(request.source),
+                            Some(Box::new(thumbnail_info)),
+                            Some(thumbnail.content_type.to_string()),
+                        )
+                    } else {
+                        (None, None, None)
+                    };
+
+                let request = attachment_request(&transaction_id);
+                media_cache_store.add_media_content(&request, data, true, now).await?;
+
+                let content = RoomMessageEventContent::new(make_attachment_type(
+                    content_type,
+                    body,
+                    request.source,
+                    config.info,
+                    thumbnail_source,
+                    thumbnail_info,
+                ));
+
+                let kind = QueuedRequestKind::Attachment {
+                    content: Raw::new(&content)?,
+                    content_type: content_type.to_string(),
+                    thumbnail_content_type,
+                };
+
+                queue.push(transaction_id.clone(), kind).await
+            }
+            .await;
+
+            if let Err(error) = result {
+                remove_attachment_content(&room, &transaction_id).await;
+                queue.inner.upload_progress.lock().unwrap().remove(&transaction_id);
+                return Err(error);
+            }
+
+            Ok(transaction_id)
+        };
+
+        Box::pin(fut.instrument(tracing_span))
+    }
+}
+
+/// A weak reference to a [`Room`], which doesn't keep its [`Client`] alive.
+#[derive(Debug)]
+struct WeakRoom {
+    client: Weak<ClientInner>,
+    room_id: OwnedRoomId,
+}
+
+impl WeakRoom {
+    fn new(room: &Room) -> Self {
+        Self { client: Arc::downgrade(&room.client.inner), room_id: room.room_id().to_owned() }
+    }
+
+    fn get(&self) -> Option<Room> {
+        let client = Client { inner: self.client.upgrade()? };
+        client.get_room(&self.room_id)
+    }
+}
+
+/// Send a queued event, returning its event ID.
+async fn send_request(
+    room: &Room,
+    request: QueuedRequest,
+    send_progress: SharedObservable<TransmissionProgress>,
+) -> Result<OwnedEventId, Error> {
+    let QueuedRequest { transaction_id, kind, .. } = request;
+
+    let response = match kind {
+        QueuedRequestKind::Event { event_type, content } => {
+            room.send_raw(&event_type, content).with_transaction_id(&transaction_id).await?
+        }
+        QueuedRequestKind::Attachment { content, content_type, thumbnail_content_type } => {
+            let mut content = content.deserialize()?;
+
+            let thumbnail_source = match thumbnail_content_type {
+                Some(thumbnail_content_type) => Some(
+                    upload_media(
+                        room,
+                        &thumbnail_request(&transaction_id),
+                        &thumbnail_content_type,
+                        send_progress.clone(),
+                    )
+                    .await?,
+                ),
+                None => None,
+            };
+            let source = upload_media(
+                room,
+                &attachment_request(&transaction_id),
+                &content_type,
+                send_progress,
+            )
+            .await?;
+
+            update_media_sources(&mut content.msgtype, source, thumbnail_source);
+
+            room.send(content).with_transaction_id(&transaction_id).await?
+        }
+    };
+
+    Ok(response.event_id)
+}
+
+/// Upload a media file that was saved in the media cache store, encrypting it
+/// if the room is encrypted.
+async fn upload_media(
+    room: &Room,
+    request: &MediaRequest,
+    content_type: &str,
+    send_progress: SharedObservable<TransmissionProgress>,
+) -> Result<MediaSource, Error> {
+    let Some(data) = room
+        .client
+        .base_client()
+        .media_cache_store()
+        .get_media_content(request, MilliSecondsSinceUnixEpoch::now())
+        .await?
+    else {
+        warn!(uri = %request.uri(), "The content of the attachment is missing");
+        return Err(RoomSendQueueError::MissingMediaContent.into());
+    };
+
+    let content_type = content_type.parse::<Mime>().unwrap_or(mime::APPLICATION_OCTET_STREAM);
+
+    #[cfg(feature = "e2e-encryption")]
+    if room.is_encrypted().await? {
+        let file = room
+            .client
+            .prepare_encrypted_file(&content_type, &mut Cursor::new(data))
+            .with_send_progress_observable(send_progress)
+            .await?;
+        return Ok(MediaSource::Encrypted(Box::new(file)));
+    }
+
+    let response = room
+        .client
+        .media()
+        .upload(&content_type, data)
+        .with_send_progress_observable(send_progress)
+        .await?;
+
+    Ok(MediaSource::Plain(response.content_uri))
+}
+
+/// Replace the local placeholder sources of an attachment with the sources of
+/// the uploaded file and thumbnail.
+fn update_media_sources(
+    msgtype: &mut MessageType,
+    source: MediaSource,
+    thumbnail_source: Option<MediaSource>,
+) {
+    match msgtype {
+        MessageType::Image(content) => {
+            content.source = source;
+            if let Some(info) = &mut content.info {
+                info.thumbnail_source = thumbnail_source;
+            }
+        }
+        MessageType::Video(content) => {
+            content.source = source;
+            if let Some(info) = &mut content.info {
+                info.thumbnail_source = thumbnail_source;
+            }
+        }
+        MessageType::File(content) => {
+            content.source = source;
+            if let Some(info) = &mut content.info {
+                info.thumbnail_source = thumbnail_source;
+            }
+        }
+        MessageType::Audio(content) => {
+            content.source = source;
+        }
+        _ => {}
+    }
+}
+
+/// Whether sending an event that failed with the given error might succeed
+/// later without any change.
+fn is_recoverable(error: &Error) -> bool {
+    let Error::Http(error) = error else {
+        return false;
+    };
+
+    match error {
+        HttpError::Reqwest(_) => true,
+        HttpError::Api(_) => match error.as_ruma_api_error() {
+            Some(RumaApiError::ClientApi(error)) => {
+                error.status_code.is_server_error()
+                    || matches!(
+                        &error.body,
+                        ErrorBody::Standard { kind: ErrorKind::LimitExceeded { .. }, .. }
+                    )
+            }
+            Some(RumaApiError::Other(error)) => error.status_code.is_server_error(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The delay before the given attempt to send an event again.
+fn retry_delay(attempt: u32) -> Duration {
+    Duration::from_secs(1 << attempt.min(6)).min(MAX_RETRY_DELAY)
+}
+
+async fn sleep(delay: Duration) {
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::sleep(delay).await;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(delay).await;
+}
+
+/// The request of the content of a queued attachment, in the media cache
+/// store.
+fn attachment_request(transaction_id: &TransactionId) -> MediaRequest {
+    let uri = OwnedMxcUri::from(format!("mxc://{ATTACHMENT_SERVER_NAME}/{transaction_id}"));
+    MediaRequest { source: MediaSource::Plain(uri), format: MediaFormat::File }
+}
+
+/// The request of the thumbnail of a queued attachment, in the media cache
+/// store.
+fn thumbnail_request(transaction_id: &TransactionId) -> MediaRequest {
+    let uri =
+        OwnedMxcUri::from(format!("mxc://{ATTACHMENT_SERVER_NAME}/{transaction_id}-thumbnail"));
+    MediaRequest { source: MediaSource::Plain(uri), format: MediaFormat::File }
+}
+
+/// Remove the content of a queued attachment and of its thumbnail from the
+/// media cache store, if any.
+async fn remove_attachment_content(room: &Room, transaction_id: &TransactionId) {
+    let media_cache_store = room.client.base_client().media_cache_store();
+
+    for request in [attachment_request(transaction_id), thumbnail_request(transaction_id)] {
+        if let Err(error) = media_cache_store.remove_media_content(&request).await {
+            warn!(txn_id = %transaction_id, "Couldn't remove the content of an attachment: {error}");
+        }
+    }
+}
+
+async fn mark_as_wedged(
+    room: &Room,
+    transaction_id: &TransactionId,
+) -> Result<(), RoomSendQueueError> {
+    let store = room.client.store();
+
+    let Some(mut request) = store
+        .load_send_queue_requests(room.room_id())
+        .await?
+        .into_iter()
+        .find(|req| req.transaction_id == transaction_id)
+    else {
+        return Ok(());
+    };
+
+    request.is_wedged = true;
+    store.save_send_queue_request(room.room_id(), request).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{retry_delay, MAX_RETRY_DELAY};
+
+    #[test]
+    fn test_retry_delay_is_capped() {
+        assert_eq!(retry_delay(0), Duration::from_secs(1));
+        assert_eq!(retry_delay(3), Duration::from_secs(8));
+        assert_eq!(retry_delay(10), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+}
@@ -14,14 +14,15 @@ use ruma::{
     },
     OwnedRoomId,
 };
-use tokio::sync::{broadcast::channel, Mutex as AsyncMutex, RwLock as AsyncRwLock};
+use tokio::sync::{broadcast::channel, Mutex as AsyncMutex, OnceCell, RwLock as AsyncRwLock};
+use tracing::warn;
 use url::Url;
 
 use super::{
     cache::{format_storage_key_prefix, restore_sliding_sync_state},
     sticky_parameters::SlidingSyncStickyManager,
     Error, SlidingSync, SlidingSyncInner, SlidingSyncListBuilder, SlidingSyncPositionMarkers,
-    SlidingSyncRoom,
+    SlidingSyncRoom, Version,
 };
 use crate::{sliding_sync::SlidingSyncStickyParameters, Client, Result};
 
@@ -34,6 +35,7 @@ pub struct SlidingSyncBuilder {
     id: String,
     storage_key: String,
     sliding_sync_proxy: Option<Url>,
+    version: Option<Version>,
     client: Client,
     lists: Vec<SlidingSyncListBuilder>,
     extensions: Option<ExtensionsConfig>,
@@ -58,6 +60,7 @@ impl SlidingSyncBuilder {
                 id,
                 storage_key,
                 sliding_sync_proxy: None,
+                version: None,
                 client,
                 lists: Vec::new(),
                 extensions: None,
@@ -82,6 +85,17 @@ impl SlidingSyncBuilder {
         self
     }
 
+    /// Set the version of the Sliding Sync protocol to use.
+    ///
+    /// By default, the native simplified Sliding Sync protocol is used if the
+    /// homeserver supports it, otherwise the sliding sync proxy is used. This
+    /// method forces a specific version, and takes precedence over
+    /// [`Self::sliding_sync_proxy`].
+    pub fn version(mut self, version: Version) -> Self {
+        self.version = Some(version);
+        self
+    }
+
     /// Add the given list to the lists.
     ///
     /// Replace any list with the same name.
@@ -281,13 +295,25 @@ impl SlidingSyncBuilder {
         let rooms = AsyncRwLock::new(self.rooms);
         let lists = AsyncRwLock::new(lists);
 
-        // Use the configured sliding sync proxy, or if not set, try to use the one
-        // auto-discovered by the client, if any.
-        let sliding_sync_proxy = self.sliding_sync_proxy.or_else(|| client.sliding_sync_proxy());
+        // Use the configured version or sliding sync proxy. If none is set, use the
+        // native protocol if the homeserver supports it, otherwise try to use the
+        // sliding sync proxy auto-discovered by the client, if any.
+        let version = match (self.version, self.sliding_sync_proxy) {
+            (Some(version), _) => Some(version),
+            (None, Some(url)) => Some(Version::Proxy { url: Some(url) }),
+            (None, None) => match Version::detect(&client).await {
+                Ok(version) => Some(version),
+                Err(error) => {
+                    // Don't guess, the version is detected again before the first request.
+                    warn!(?error, "Failed to check for native sliding sync support");
+                    None
+                }
+            },
+        };
 
         Ok(SlidingSync::new(SlidingSyncInner {
             id: self.id,
-            sliding_sync_proxy,
+            version: OnceCell::new_with(version),
 
             client,
             storage_key: self.storage_key,
@@ -4,8 +4,8 @@ use imbl::Vector;
 use matrix_sdk_base::{sync::SyncResponse, PreviousEventsProvider};
 use ruma::{api::client::sync::sync_events::v4, events::AnyToDeviceEvent, serde::Raw, OwnedRoomId};
 
-use super::{SlidingSync, SlidingSyncBuilder};
-use crate::{Client, Result, SlidingSyncRoom};
+use super::{version::NATIVE_UNSTABLE_FEATURE, SlidingSync, SlidingSyncBuilder};
+use crate::{Client, HttpResult, Result, SlidingSyncRoom};
 
 impl Client {
     /// Create a [`SlidingSyncBuilder`] tied to this client, with the given
@@ -16,6 +16,19 @@ impl Client {
         Ok(SlidingSync::builder(id.into(), self.clone())?)
     }
 
+    /// Check whether the homeserver implements the simplified Sliding Sync
+    /// protocol natively, i.e. without a sliding sync proxy.
+    ///
+    /// This looks for the corresponding unstable feature in the `/versions`
+    /// response of the homeserver, which is cached by the client. If the
+    /// server versions were set with [`ClientBuilder::server_versions`], the
+    /// homeserver is assumed not to support it.
+    ///
+    /// [`ClientBuilder::server_versions`]: crate::ClientBuilder::server_versions
+    pub async fn supports_native_sliding_sync(&self) -> HttpResult<bool> {
+        Ok(self.unstable_features().await?.get(NATIVE_UNSTABLE_FEATURE) == Some(&true))
+    }
+
     /// Handle all the information provided in a sliding sync response, except
     /// for the e2ee bits.
     ///
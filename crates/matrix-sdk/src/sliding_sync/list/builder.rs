@@ -227,6 +227,7 @@ impl SlidingSyncListBuilder {
                 // We want to avoid triggering `VectorDiff::Reset` too much, hence we
                 // increase the observable capacity.
                 room_list: StdRwLock::new(ObservableVector::with_capacity(4096)),
+                room_recencies: Default::default(),
 
                 // Internal data.
                 sliding_sync_internal_channel_sender,
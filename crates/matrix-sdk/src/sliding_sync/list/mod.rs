@@ -5,7 +5,7 @@ mod room_list_entry;
 mod sticky;
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fmt::Debug,
     iter,
     ops::RangeInclusive,
@@ -16,7 +16,10 @@ use eyeball::{Observable, Subscriber};
 use eyeball_im::{ObservableVector, ObservableVectorTransaction, VectorDiff};
 use futures_core::Stream;
 use imbl::Vector;
-use ruma::{api::client::sync::sync_events::v4, assign, OwnedRoomId, TransactionId};
+use ruma::{
+    api::client::sync::sync_events::v4, assign, MilliSecondsSinceUnixEpoch, OwnedRoomId,
+    TransactionId,
+};
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast::Sender;
 use tracing::{instrument, warn};
@@ -28,7 +31,7 @@ use super::{
     sticky_parameters::{LazyTransactionId, SlidingSyncStickyManager},
     Error, SlidingSyncInternalMessage,
 };
-use crate::Result;
+use crate::{BaseRoom, Client, Result, RoomState};
 
 /// Should this [`SlidingSyncList`] be stored in the cache, and automatically
 /// reloaded from the cache upon creation?
@@ -232,13 +235,84 @@ impl SlidingSyncList {
 
         let new_changes = self.inner.update_room_list(
             maximum_number_of_rooms,
-            list_sync_operations,
             rooms_that_have_received_an_update,
+            |room_list_txn, rooms_that_have_received_an_update| {
+                if list_sync_operations.is_empty() {
+                    return Ok(false);
+                }
+
+                apply_sync_operations(
+                    list_sync_operations,
+                    room_list_txn,
+                    rooms_that_have_received_an_update,
+                )?;
+
+                Ok(true)
+            },
         )?;
 
         Ok(new_changes)
     }
 
+    /// Update the list based on a response of the native simplified sliding
+    /// sync protocol.
+    ///
+    /// The native protocol doesn't send list operations, and doesn't tell which
+    /// list a room belongs to. Instead, the rooms that have received an update
+    /// are matched against the list's filters, on the client side: the
+    /// matching rooms are moved according to the timestamp of their most
+    /// recent event, the other ones are removed from it.
+    ///
+    /// # Parameters
+    ///
+    /// - `maximum_number_of_rooms`: the `lists.$this_list.count` value.
+    /// - `rooms_by_recency`: the rooms received in the `rooms` value of the
+    ///   response with the timestamp of their most recent event in the
+    ///   response, if any, sorted from the least recent one to the most recent
+    ///   one.
+    /// - `rooms_that_have_received_an_update`: see [`Self::update`].
+    #[instrument(skip_all, fields(name = self.name()))]
+    pub(super) fn update_native(
+        &mut self,
+        maximum_number_of_rooms: u32,
+        rooms_by_recency: &[(OwnedRoomId, Option<MilliSecondsSinceUnixEpoch>)],
+        rooms_that_have_received_an_update: &[OwnedRoomId],
+        client: &Client,
+    ) -> Result<bool, Error> {
+        self.inner.update_request_generator_state(maximum_number_of_rooms)?;
+
+        let rooms = {
+            let sticky = self.inner.sticky.read().unwrap();
+            let filters = sticky.data().filters();
+
+            rooms_by_recency
+                .iter()
+                .map(|(room_id, recency)| {
+                    let is_in_list = client
+                        .get_room(room_id)
+                        .is_some_and(|room| room_matches_filters(&room, filters));
+
+                    NativeRoomUpdate { room_id: room_id.clone(), recency: *recency, is_in_list }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut room_recencies = self.inner.room_recencies.write().unwrap();
+
+        self.inner.update_room_list(
+            Some(maximum_number_of_rooms),
+            rooms_that_have_received_an_update,
+            |room_list_txn, rooms_that_have_received_an_update| {
+                Ok(apply_native_updates(
+                    &rooms,
+                    &mut room_recencies,
+                    room_list_txn,
+                    rooms_that_have_received_an_update,
+                ))
+            },
+        )
+    }
+
     /// Commit the set of sticky parameters for this list.
     pub fn maybe_commit_sticky(&mut self, txn_id: &TransactionId) {
         self.inner.sticky.write().unwrap().maybe_commit(txn_id);
@@ -247,7 +321,7 @@ impl SlidingSyncList {
     /// Manually invalidate the sticky data, so the sticky parameters are
     /// re-sent next time.
     pub fn invalidate_sticky_data(&self) {
-        let _ = self.inner.sticky.write().unwrap().data_mut();
+        self.inner.sticky.write().unwrap().invalidate();
     }
 }
 
@@ -299,6 +373,10 @@ pub(super) struct SlidingSyncListInner {
     /// The rooms in order.
     room_list: StdRwLock<ObservableVector<RoomListEntry>>,
 
+    /// The timestamp of the most recent event of the rooms in the list, when
+    /// it's known. Only used by the native protocol, to order the list.
+    room_recencies: StdRwLock<HashMap<OwnedRoomId, MilliSecondsSinceUnixEpoch>>,
+
     /// The request generator, i.e. a type that yields the appropriate list
     /// request. See [`SlidingSyncListRequestGenerator`] to learn more.
     request_generator: StdRwLock<SlidingSyncListRequestGenerator>,
@@ -384,21 +462,27 @@ impl SlidingSyncListInner {
     ///
     /// The `maximum_number_of_rooms` is the `lists.$this_list.count` value,
     /// i.e. maximum number of available rooms as defined by the server. The
-    /// `list_sync_operations` is the `list.$this_list.ops` value
-    /// received from the server for this specific list. It consists of
-    /// operations to “move” rooms' positions. Finally,
-    /// the `rooms_that_have_received_an_update` is the `rooms` value received
-    /// from the server, which represents aggregated rooms that have received an
-    /// update. Maybe their position has changed, maybe they have received a new
-    /// event in their timeline. We need this information to update the
-    /// `room_list` even if the position of the room hasn't be modified: it
-    /// helps the user to know that a room has received an update.
-    fn update_room_list(
+    /// `apply_list_updates` function moves the rooms' positions, e.g. by
+    /// running the `list.$this_list.ops` operations received from the server
+    /// for this specific list, and returns whether it changed anything.
+    /// Finally, the `rooms_that_have_received_an_update` is the `rooms` value
+    /// received from the server, which represents aggregated rooms that have
+    /// received an update. Maybe their position has changed, maybe they have
+    /// received a new event in their timeline. We need this information to
+    /// update the `room_list` even if the position of the room hasn't be
+    /// modified: it helps the user to know that a room has received an update.
+    fn update_room_list<F>(
         &self,
         maximum_number_of_rooms: Option<u32>,
-        list_sync_operations: &[v4::SyncOp],
         rooms_that_have_received_an_update: &[OwnedRoomId],
-    ) -> Result<bool, Error> {
+        apply_list_updates: F,
+    ) -> Result<bool, Error>
+    where
+        F: FnOnce(
+            &mut ObservableVectorTransaction<'_, RoomListEntry>,
+            &mut HashSet<OwnedRoomId>,
+        ) -> Result<bool, Error>,
+    {
         let mut new_changes = false;
 
         if let Some(maximum_number_of_rooms) = maximum_number_of_rooms {
@@ -441,13 +525,7 @@ impl SlidingSyncListInner {
             let mut rooms_that_have_received_an_update =
                 HashSet::from_iter(rooms_that_have_received_an_update.iter().cloned());
 
-            if !list_sync_operations.is_empty() {
-                apply_sync_operations(
-                    list_sync_operations,
-                    &mut room_list_txn,
-                    &mut rooms_that_have_received_an_update,
-                )?;
-
+            if apply_list_updates(&mut room_list_txn, &mut rooms_that_have_received_an_update)? {
                 new_changes = true;
             }
 
@@ -738,6 +816,149 @@ fn apply_sync_operations(
     Ok(())
 }
 
+/// A room of a native simplified sliding sync response, to apply to a list.
+#[derive(Debug)]
+struct NativeRoomUpdate {
+    room_id: OwnedRoomId,
+
+    /// The timestamp of the most recent event of the room in the response, if
+    /// any.
+    recency: Option<MilliSecondsSinceUnixEpoch>,
+
+    /// Whether the room belongs to the list.
+    is_in_list: bool,
+}
+
+/// Apply the updates of a native simplified sliding sync response to the room
+/// list.
+///
+/// `rooms` contains the rooms that have received an update, sorted from the
+/// least recent one to the most recent one, and `room_recencies` the timestamp
+/// of the most recent event of the rooms in the list, when it's known.
+///
+/// Rooms belonging to the list are moved before the rooms with less recent
+/// events, if they have received a more recent event than the ones known
+/// before, taking the place of an empty entry if they were not in the list
+/// yet. Rooms without new events stay in place. Other rooms are removed from
+/// the list.
+///
+/// Returns whether the room list has been modified.
+fn apply_native_updates(
+    rooms: &[NativeRoomUpdate],
+    room_recencies: &mut HashMap<OwnedRoomId, MilliSecondsSinceUnixEpoch>,
+    room_list_txn: &mut ObservableVectorTransaction<'_, RoomListEntry>,
+    rooms_that_have_received_an_update: &mut HashSet<OwnedRoomId>,
+) -> bool {
+    let mut new_changes = false;
+
+    for NativeRoomUpdate { room_id, recency, is_in_list } in rooms {
+        let position =
+            room_list_txn.iter().position(|entry| entry.as_room_id() == Some(room_id.as_ref()));
+
+        if !is_in_list {
+            let Some(index) = position else { continue };
+
+            room_list_txn.remove(index);
+            room_recencies.remove(room_id);
+        } else {
+            let previous_recency = room_recencies.get(room_id).copied();
+
+            // `None` is less than any timestamp.
+            if position.is_some() && *recency <= previous_recency {
+                // The room hasn't received a more recent event, leave it in place.
+                continue;
+            }
+
+            if let Some(index) = position {
+                room_list_txn.remove(index);
+            }
+
+            // Insert the room before the first room with a less recent event, or before
+            // the first empty entry.
+            let recency = (*recency).max(previous_recency);
+            let index = room_list_txn
+                .iter()
+                .position(|entry| match entry.as_room_id() {
+                    Some(other_room_id) => room_recencies.get(other_room_id).copied() < recency,
+                    None => true,
+                })
+                .unwrap_or(room_list_txn.len());
+
+            if position.is_none() {
+                // Make some space in the list, by removing the last empty entry, or else the
+                // last entry if the room is more recent than it.
+                let last_empty_index = room_list_txn
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, entry)| matches!(entry, RoomListEntry::Empty))
+                    .map(|(index, _)| index)
+                    .last();
+
+                match last_empty_index {
+                    Some(last_empty_index) => {
+                        room_list_txn.remove(last_empty_index);
+                    }
+                    None if index < room_list_txn.len() => {
+                        let last_index = room_list_txn.len() - 1;
+                        if let Some(last_room_id) =
+                            room_list_txn.get(last_index).and_then(RoomListEntry::as_room_id)
+                        {
+                            room_recencies.remove(last_room_id);
+                        }
+                        room_list_txn.remove(last_index);
+                    }
+                    // The list is full of more recent rooms.
+                    None => continue,
+                }
+            }
+
+            room_list_txn.insert(index, RoomListEntry::Filled(room_id.clone()));
+
+            if let Some(recency) = recency {
+                room_recencies.insert(room_id.clone(), recency);
+            }
+        }
+
+        // This `room_id` has been handled, let's remove it from the rooms to handle
+        // later.
+        rooms_that_have_received_an_update.remove(room_id);
+        new_changes = true;
+    }
+
+    new_changes
+}
+
+/// Check whether a room matches the filters of a list.
+///
+/// Only the filters that can be evaluated with the information known by the
+/// client are considered.
+fn room_matches_filters(room: &BaseRoom, filters: Option<&v4::SyncRequestListFilters>) -> bool {
+    let Some(filters) = filters else {
+        return true;
+    };
+
+    let matches = |filter: Option<bool>, value: bool| filter.map_or(true, |filter| filter == value);
+
+    if !matches(filters.is_invite, room.state() == RoomState::Invited)
+        || !matches(filters.is_dm, room.direct_targets_length() != 0)
+        || !matches(filters.is_encrypted, room.is_encrypted())
+        || !matches(filters.is_tombstoned, room.is_tombstoned())
+    {
+        return false;
+    }
+
+    if filters.room_types.is_empty() && filters.not_room_types.is_empty() {
+        return true;
+    }
+
+    let room_info = room.clone_info();
+    let room_type = room_info.room_type().map(|room_type| room_type.as_str());
+
+    (filters.room_types.is_empty()
+        || room_type.is_some_and(|room_type| filters.room_types.iter().any(|t| t == room_type)))
+        && !room_type.is_some_and(|room_type| filters.not_room_types.iter().any(|t| t == room_type))
+}
+
 /// The state the [`SlidingSyncList`] is in.
 ///
 /// The lifetime of a `SlidingSyncList` usually starts at `NotLoaded` or
@@ -896,7 +1117,7 @@ impl SlidingSyncMode {
 mod tests {
     use std::{
         cell::Cell,
-        collections::HashSet,
+        collections::{HashMap, HashSet},
         sync::{Arc, Mutex},
     };
 
@@ -906,7 +1127,7 @@ mod tests {
     use matrix_sdk_test::async_test;
     use ruma::{
         api::client::sync::sync_events::v4::{self, SlidingOp},
-        room_id, uint, OwnedRoomId,
+        room_id, uint, MilliSecondsSinceUnixEpoch, OwnedRoomId, RoomId,
     };
     use serde_json::json;
     use tokio::{
@@ -918,8 +1139,8 @@ mod tests {
     };
 
     use super::{
-        apply_sync_operations, RoomListEntry, SlidingSyncList, SlidingSyncListLoadingState,
-        SlidingSyncMode,
+        apply_native_updates, apply_sync_operations, NativeRoomUpdate, RoomListEntry,
+        SlidingSyncList, SlidingSyncListLoadingState, SlidingSyncMode,
     };
     use crate::sliding_sync::{sticky_parameters::LazyTransactionId, SlidingSyncInternalMessage};
 
@@ -2152,6 +2373,106 @@ mod tests {
         };
     }
 
+    fn native_update(room_id: &str, recency: Option<u32>, is_in_list: bool) -> NativeRoomUpdate {
+        NativeRoomUpdate {
+            room_id: RoomId::parse(room_id).unwrap(),
+            recency: recency.map(|recency| MilliSecondsSinceUnixEpoch(recency.into())),
+            is_in_list,
+        }
+    }
+
+    #[test]
+    fn test_apply_native_updates() {
+        let mut room_list = ObservableVector::from(entries![F("!r0:x.y"), F("!r1:x.y"), E, E]);
+        let mut room_recencies = HashMap::from([
+            (room_id!("!r0:x.y").to_owned(), MilliSecondsSinceUnixEpoch(20u32.into())),
+            (room_id!("!r1:x.y").to_owned(), MilliSecondsSinceUnixEpoch(10u32.into())),
+        ]);
+        let mut rooms_that_have_received_an_update =
+            HashSet::from([room_id!("!r1:x.y").to_owned(), room_id!("!r2:x.y").to_owned()]);
+
+        let rooms = [
+            // A new room takes the place of the last empty entry, after the more recent
+            // rooms.
+            native_update("!r2:x.y", Some(5), true),
+            // An existing room with a more recent event moves to the top.
+            native_update("!r1:x.y", Some(30), true),
+            // A room that doesn't belong to the list anymore is removed.
+            native_update("!r0:x.y", None, false),
+            // A room that doesn't belong to the list is ignored.
+            native_update("!r3:x.y", Some(40), false),
+        ];
+
+        let mut txn = room_list.transaction();
+        assert!(apply_native_updates(
+            &rooms,
+            &mut room_recencies,
+            &mut txn,
+            &mut rooms_that_have_received_an_update
+        ));
+        txn.commit();
+
+        assert_eq!(*room_list, entries![F("!r1:x.y"), F("!r2:x.y"), E]);
+        assert!(rooms_that_have_received_an_update.is_empty());
+        assert!(!room_recencies.contains_key(room_id!("!r0:x.y")));
+
+        // A room without a new event, or with an older event, stays in place.
+        let mut rooms_that_have_received_an_update =
+            HashSet::from([room_id!("!r2:x.y").to_owned()]);
+        let mut txn = room_list.transaction();
+        assert!(!apply_native_updates(
+            &[native_update("!r2:x.y", None, true), native_update("!r2:x.y", Some(1), true)],
+            &mut room_recencies,
+            &mut txn,
+            &mut rooms_that_have_received_an_update
+        ));
+        txn.commit();
+
+        assert_eq!(*room_list, entries![F("!r1:x.y"), F("!r2:x.y"), E]);
+        // The room is still considered updated.
+        assert!(rooms_that_have_received_an_update.contains(room_id!("!r2:x.y")));
+
+        // A new room without events takes the last empty entry. A new room is inserted
+        // between the rooms according to its most recent event, and the last room is
+        // removed when the list is full.
+        let mut txn = room_list.transaction();
+        assert!(apply_native_updates(
+            &[native_update("!r4:x.y", None, true), native_update("!r5:x.y", Some(20), true)],
+            &mut room_recencies,
+            &mut txn,
+            &mut HashSet::new()
+        ));
+        txn.commit();
+
+        assert_eq!(*room_list, entries![F("!r1:x.y"), F("!r5:x.y"), F("!r2:x.y")]);
+
+        // When the list is full, a new room that is less recent than all the rooms
+        // isn't added.
+        let mut txn = room_list.transaction();
+        assert!(apply_native_updates(
+            &[native_update("!r6:x.y", None, true), native_update("!r7:x.y", Some(6), true)],
+            &mut room_recencies,
+            &mut txn,
+            &mut HashSet::new()
+        ));
+        txn.commit();
+
+        assert_eq!(*room_list, entries![F("!r1:x.y"), F("!r5:x.y"), F("!r7:x.y")]);
+
+        // Nothing changes if no room belongs to the list.
+        let mut room_list = ObservableVector::from(entries![F("!r0:x.y")]);
+        let mut txn = room_list.transaction();
+        assert!(!apply_native_updates(
+            &[native_update("!r1:x.y", None, false)],
+            &mut HashMap::new(),
+            &mut txn,
+            &mut HashSet::new()
+        ));
+        txn.commit();
+
+        assert_eq!(*room_list, entries![F("!r0:x.y")]);
+    }
+
     #[test]
     fn test_once_built() {
         let (sender, _receiver) = channel(1);
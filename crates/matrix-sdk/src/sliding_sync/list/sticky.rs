@@ -49,6 +49,10 @@ impl SlidingSyncListStickyParameters {
     pub(super) fn set_timeline_limit(&mut self, timeline: Option<Bound>) {
         self.timeline_limit = timeline;
     }
+
+    pub(super) fn filters(&self) -> Option<&v4::SyncRequestListFilters> {
+        self.filters.as_ref()
+    }
 }
 
 impl StickyData for SlidingSyncListStickyParameters {
@@ -23,6 +23,7 @@ mod list;
 mod room;
 mod sticky_parameters;
 mod utils;
+mod version;
 
 use std::{
     collections::{BTreeMap, BTreeSet, HashSet},
@@ -40,23 +41,25 @@ use ruma::{
         error::ErrorKind,
         sync::sync_events::v4::{self, ExtensionsConfig},
     },
-    assign, OwnedEventId, OwnedRoomId, RoomId,
+    assign, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, RoomId,
 };
 use serde::{Deserialize, Serialize};
 use tokio::{
     select, spawn,
-    sync::{broadcast::Sender, Mutex as AsyncMutex, OwnedMutexGuard, RwLock as AsyncRwLock},
+    sync::{
+        broadcast::Sender, Mutex as AsyncMutex, OnceCell, OwnedMutexGuard, RwLock as AsyncRwLock,
+    },
 };
 use tracing::{debug, error, info, instrument, trace, warn, Instrument, Span};
-use url::Url;
 
 #[cfg(feature = "e2e-encryption")]
 use self::utils::JoinHandleExt as _;
-pub use self::{builder::*, error::*, list::*, room::*};
+pub use self::{builder::*, error::*, list::*, room::*, version::Version};
 use self::{
     cache::restore_sliding_sync_state,
     client::SlidingSyncResponseProcessor,
     sticky_parameters::{LazyTransactionId, SlidingSyncStickyManager, StickyData},
+    version::NativeRequest,
 };
 use crate::{config::RequestConfig, Client, Result};
 
@@ -76,8 +79,12 @@ pub(super) struct SlidingSyncInner {
     /// Used to distinguish different connections to the sliding sync proxy.
     id: String,
 
-    /// Customize the sliding sync proxy URL.
-    sliding_sync_proxy: Option<Url>,
+    /// The version of the Sliding Sync protocol to use, and where to reach
+    /// it.
+    ///
+    /// It's unset if it couldn't be detected when building the instance; it's
+    /// detected again before the next request.
+    version: OnceCell<Version>,
 
     /// The HTTP Matrix client.
     client: Client,
@@ -148,6 +155,21 @@ impl SlidingSync {
         SlidingSyncBuilder::new(id, client)
     }
 
+    /// Get the version of the Sliding Sync protocol used by this instance.
+    ///
+    /// Returns `None` if it isn't known yet, because checking whether the
+    /// homeserver supports the native protocol failed when building this
+    /// instance. It's checked again before the next request.
+    pub fn version(&self) -> Option<&Version> {
+        self.inner.version.get()
+    }
+
+    /// Get the version of the Sliding Sync protocol to use, detecting it if
+    /// it isn't known yet.
+    async fn resolve_version(&self) -> Result<&Version> {
+        Ok(self.inner.version.get_or_try_init(|| Version::detect(&self.inner.client)).await?)
+    }
+
     /// Subscribe to a given room.
     ///
     /// If the associated `Room` exists, it will be marked as
@@ -362,7 +384,14 @@ impl SlidingSync {
             lists.values_mut().for_each(|list| list.maybe_commit_sticky(txn_id));
         }
 
+        let is_native = self.inner.version.get().is_some_and(Version::is_native);
+
         let update_summary = {
+            // The rooms of the `rooms` subsection of the response, with the timestamp of
+            // their most recent event, if any. Only used by the native protocol, to order
+            // the lists.
+            let mut rooms_by_recency = Vec::new();
+
             // Update the rooms.
             let updated_rooms = {
                 let mut rooms_map = self.inner.rooms.write().await;
@@ -380,6 +409,18 @@ impl SlidingSync {
                             room_data.timeline.drain(..).map(Into::into).collect()
                         };
 
+                    if is_native {
+                        let recency = timeline.last().and_then(|event| {
+                            event
+                                .event
+                                .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+                                .ok()
+                                .flatten()
+                        });
+
+                        rooms_by_recency.push((room_id.clone(), recency));
+                    }
+
                     match rooms_map.get_mut(&room_id) {
                         // The room existed before, let's update it.
                         Some(room) => {
@@ -425,6 +466,11 @@ impl SlidingSync {
                 let mut updated_lists = Vec::with_capacity(sliding_sync_response.lists.len());
                 let mut lists = self.inner.lists.write().await;
 
+                // The native protocol doesn't send list operations: lists are ordered by
+                // recency on the client side. Rooms without any event are considered the
+                // least recent ones.
+                rooms_by_recency.sort_by_key(|(_, recency)| *recency);
+
                 // Iterate on known lists, not on lists in the response. Rooms may have been
                 // updated that were not involved in any list update.
                 for (name, list) in lists.iter_mut() {
@@ -432,7 +478,16 @@ impl SlidingSync {
                         let maximum_number_of_rooms: u32 =
                             updates.count.try_into().expect("failed to convert `count` to `u32`");
 
-                        if list.update(
+                        if is_native {
+                            if list.update_native(
+                                maximum_number_of_rooms,
+                                &rooms_by_recency,
+                                &updated_rooms,
+                                &self.inner.client,
+                            )? {
+                                updated_lists.push(name.clone());
+                            }
+                        } else if list.update(
                             Some(maximum_number_of_rooms),
                             &updates.ops,
                             &updated_rooms,
@@ -479,6 +534,10 @@ impl SlidingSync {
         BTreeSet<OwnedRoomId>,
         OwnedMutexGuard<SlidingSyncPositionMarkers>,
     )> {
+        // Parameters aren't sticky with the native protocol: they must all be sent
+        // with every request.
+        let is_native = self.resolve_version().await?.is_native();
+
         // Collect requests for lists.
         let mut requests_lists = BTreeMap::new();
 
@@ -486,6 +545,10 @@ impl SlidingSync {
             let lists = self.inner.lists.read().await;
 
             for (name, list) in lists.iter() {
+                if is_native {
+                    list.invalidate_sticky_data();
+                }
+
                 requests_lists.insert(name.clone(), list.next_request(txn_id)?);
             }
         }
@@ -546,7 +609,15 @@ impl SlidingSync {
         });
 
         // Apply sticky parameters, if needs be.
-        self.inner.sticky.write().unwrap().maybe_apply(&mut request, txn_id);
+        {
+            let mut sticky = self.inner.sticky.write().unwrap();
+
+            if is_native {
+                sticky.invalidate();
+            }
+
+            sticky.maybe_apply(&mut request, txn_id);
+        }
 
         // Set the to-device token if the extension is enabled.
         if to_device_enabled {
@@ -597,10 +668,24 @@ impl SlidingSync {
         debug!("Sending request");
 
         // Prepare the request.
-        let request =
-            self.inner.client.send(request, Some(request_config)).with_homeserver_override(
-                self.inner.sliding_sync_proxy.as_ref().map(ToString::to_string),
-            );
+        let request = {
+            let client = self.inner.client.clone();
+            let version = self.resolve_version().await?.clone();
+
+            async move {
+                match version {
+                    Version::Native => {
+                        client.send(NativeRequest::new(request), Some(request_config)).await
+                    }
+                    Version::Proxy { url } => {
+                        client
+                            .send(request, Some(request_config))
+                            .with_homeserver_override(url.as_ref().map(ToString::to_string))
+                            .await
+                    }
+                }
+            }
+        };
 
         // Send the request and get a response with end-to-end encryption support.
         //
@@ -828,7 +913,7 @@ impl SlidingSync {
         }
 
         // Force invalidation of all the sticky parameters.
-        let _ = self.inner.sticky.write().unwrap().data_mut();
+        self.inner.sticky.write().unwrap().invalidate();
 
         self.inner.lists.read().await.values().for_each(|list| list.invalidate_sticky_data());
     }
@@ -875,8 +960,8 @@ impl SlidingSync {
     }
 
     /// Get the URL to Sliding Sync.
-    pub fn sliding_sync_proxy(&self) -> Option<Url> {
-        self.inner.sliding_sync_proxy.clone()
+    pub fn sliding_sync_proxy(&self) -> Option<url::Url> {
+        self.inner.version.get().and_then(Version::overriding_url).cloned()
     }
 
     /// Read the static extension configuration for this Sliding Sync.
@@ -1081,17 +1166,21 @@ mod tests {
     use serde_json::json;
     use stream_assert::assert_pending;
     use url::Url;
-    use wiremock::{http::Method, Match, Mock, MockServer, Request, ResponseTemplate};
+    use wiremock::{
+        http::Method,
+        matchers::{method, path},
+        Match, Mock, MockServer, Request, ResponseTemplate,
+    };
 
     use super::{
         compute_limited,
         sticky_parameters::{LazyTransactionId, SlidingSyncStickyManager},
         FrozenSlidingSync, SlidingSync, SlidingSyncList, SlidingSyncListBuilder, SlidingSyncMode,
-        SlidingSyncRoom, SlidingSyncStickyParameters,
+        SlidingSyncRoom, SlidingSyncStickyParameters, Version,
     };
     use crate::{
-        sliding_sync::cache::restore_sliding_sync_state, test_utils::logged_in_client, Result,
-        RoomListEntry,
+        config::RequestConfig, sliding_sync::cache::restore_sliding_sync_state,
+        test_utils::logged_in_client, Client, Result, RoomListEntry,
     };
 
     #[derive(Copy, Clone)]
@@ -1339,6 +1428,10 @@ mod tests {
         // But here we use the latest TID, so the commit is effective.
         sticky.maybe_commit(txn_id2);
         assert!(!sticky.is_invalidated());
+
+        // The sticky parameters can be invalidated without changing them.
+        sticky.invalidate();
+        assert!(sticky.is_invalidated());
     }
 
     #[test]
@@ -1938,6 +2031,266 @@ mod tests {
         Ok(())
     }
 
+    /// A client that doesn't know the server versions, to detect the native
+    /// protocol with the `/versions` endpoint.
+    async fn client_without_server_versions(server: &MockServer) -> Client {
+        Client::builder()
+            .homeserver_url(server.uri())
+            .request_config(RequestConfig::new().disable_retry())
+            .build()
+            .await
+            .unwrap()
+    }
+
+    #[async_test]
+    async fn test_native_sliding_sync_version() -> Result<()> {
+        let server = MockServer::start().await;
+        let client = client_without_server_versions(&server).await;
+
+        // The response is cached by the client.
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/versions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "versions": ["v1.0"],
+                "unstable_features": {
+                    "org.matrix.simplified_msc3575": true,
+                },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        {
+            // The homeserver supports sliding sync natively, so it's used by default.
+            let sync = client.sliding_sync("native")?.build().await?;
+            assert_eq!(sync.version(), Some(&Version::Native));
+            assert!(sync.sliding_sync_proxy().is_none());
+        }
+
+        {
+            // …even if the client has discovered a proxy.
+            client.set_sliding_sync_proxy(Some(Url::parse("https://foo.matrix/").unwrap()));
+
+            let sync = client.sliding_sync("native")?.build().await?;
+            assert_eq!(sync.version(), Some(&Version::Native));
+        }
+
+        {
+            // …unless a proxy is configured on the sliding sync builder.
+            let url = Url::parse("https://bar.matrix/").unwrap();
+            let sync =
+                client.sliding_sync("own-proxy")?.sliding_sync_proxy(url.clone()).build().await?;
+            assert_eq!(sync.version(), Some(&Version::Proxy { url: Some(url) }));
+        }
+
+        {
+            // …or unless a version is forced.
+            let sync = client
+                .sliding_sync("forced")?
+                .version(Version::Proxy { url: None })
+                .build()
+                .await?;
+            assert_eq!(sync.version(), Some(&Version::Proxy { url: None }));
+        }
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_sliding_sync_version_is_detected_again_after_failure() -> Result<()> {
+        let server = MockServer::start().await;
+        let client = client_without_server_versions(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/versions"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/versions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "versions": ["v1.0"],
+                "unstable_features": {
+                    "org.matrix.simplified_msc3575": true,
+                },
+            })))
+            .mount(&server)
+            .await;
+
+        // The version isn't known if the homeserver can't be reached…
+        let sync = client.sliding_sync("native")?.build().await?;
+        assert_eq!(sync.version(), None);
+
+        // …and it's detected again before the next request.
+        assert_eq!(sync.resolve_version().await?, &Version::Native);
+        assert_eq!(sync.version(), Some(&Version::Native));
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_sliding_sync_version_with_known_server_versions() -> Result<()> {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        // The homeserver isn't asked for its unstable features if the server versions
+        // were set manually, and the proxy is used.
+        let sync = client.sliding_sync("proxy")?.build().await?;
+        assert_eq!(sync.version(), Some(&Version::Proxy { url: None }));
+        assert!(server.received_requests().await.unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_native_sliding_sync_orders_lists_by_recency() -> Result<()> {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        let sliding_sync = client
+            .sliding_sync("native")?
+            .version(Version::Native)
+            .add_list(
+                SlidingSyncList::builder("all")
+                    .sync_mode(SlidingSyncMode::new_selective().add_range(0..=10)),
+            )
+            .build()
+            .await?;
+
+        let room_id_0 = room_id!("!r0:bar.org");
+        let room_id_1 = room_id!("!r1:bar.org");
+        let room_id_2 = room_id!("!r2:bar.org");
+
+        let event = |event_id: &str, origin_server_ts: u64| {
+            json!({
+                "event_id": event_id,
+                "sender": "@alice:bar.org",
+                "origin_server_ts": origin_server_ts,
+                "type": "m.room.message",
+                "content": {
+                    "body": "Hello, world!",
+                    "msgtype": "m.text",
+                },
+            })
+        };
+
+        {
+            // The native endpoint is used, and lists don't contain any operation.
+            let _mock_guard = Mock::given(method("POST"))
+                .and(path("/_matrix/client/unstable/org.matrix.simplified_msc3575/sync"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "pos": "0",
+                    "lists": {
+                        "all": {
+                            "count": 3,
+                        },
+                    },
+                    "rooms": {
+                        room_id_0: {
+                            "name": "Room #0",
+                            "initial": true,
+                            "timeline": [event("$ev0", 10)],
+                        },
+                        room_id_1: {
+                            "name": "Room #1",
+                            "initial": true,
+                            "timeline": [event("$ev1", 30)],
+                        },
+                        room_id_2: {
+                            "name": "Room #2",
+                            "initial": true,
+                            "timeline": [event("$ev2", 20)],
+                        },
+                    },
+                })))
+                .expect(1)
+                .mount_as_scoped(&server)
+                .await;
+
+            let update_summary = sliding_sync.sync_once().await?;
+            assert_eq!(update_summary.lists, ["all"]);
+        }
+
+        // The most recent rooms come first.
+        assert_eq!(
+            sliding_sync.inner.lists.read().await.get("all").unwrap().room_list::<RoomListEntry>(),
+            [
+                RoomListEntry::Filled(room_id_1.to_owned()),
+                RoomListEntry::Filled(room_id_2.to_owned()),
+                RoomListEntry::Filled(room_id_0.to_owned()),
+            ]
+        );
+
+        {
+            let _mock_guard = Mock::given(method("POST"))
+                .and(path("/_matrix/client/unstable/org.matrix.simplified_msc3575/sync"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "pos": "1",
+                    "lists": {
+                        "all": {
+                            "count": 3,
+                        },
+                    },
+                    "rooms": {
+                        room_id_0: {
+                            "timeline": [event("$ev3", 40)],
+                        },
+                    },
+                })))
+                .expect(1)
+                .mount_as_scoped(&server)
+                .await;
+
+            sliding_sync.sync_once().await?;
+        }
+
+        // The room that has received a new event moves to the top.
+        assert_eq!(
+            sliding_sync.inner.lists.read().await.get("all").unwrap().room_list::<RoomListEntry>(),
+            [
+                RoomListEntry::Filled(room_id_0.to_owned()),
+                RoomListEntry::Filled(room_id_1.to_owned()),
+                RoomListEntry::Filled(room_id_2.to_owned()),
+            ]
+        );
+
+        {
+            let _mock_guard = Mock::given(method("POST"))
+                .and(path("/_matrix/client/unstable/org.matrix.simplified_msc3575/sync"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                    "pos": "2",
+                    "lists": {
+                        "all": {
+                            "count": 3,
+                        },
+                    },
+                    "rooms": {
+                        room_id_2: {
+                            "name": "Room #2 renamed",
+                        },
+                    },
+                })))
+                .expect(1)
+                .mount_as_scoped(&server)
+                .await;
+
+            sliding_sync.sync_once().await?;
+        }
+
+        // A room that hasn't received a new event stays in place.
+        assert_eq!(
+            sliding_sync.inner.lists.read().await.get("all").unwrap().room_list::<RoomListEntry>(),
+            [
+                RoomListEntry::Filled(room_id_0.to_owned()),
+                RoomListEntry::Filled(room_id_1.to_owned()),
+                RoomListEntry::Filled(room_id_2.to_owned()),
+            ]
+        );
+
+        Ok(())
+    }
+
     #[async_test]
     async fn test_limited_flag_computation() {
         let server = MockServer::start().await;
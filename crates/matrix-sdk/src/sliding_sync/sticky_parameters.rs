@@ -101,6 +101,12 @@ impl<D: StickyData> SlidingSyncStickyManager<D> {
         &self.data
     }
 
+    /// Invalidate the sticky set, so the sticky parameters are sent again with
+    /// the next request, even if they haven't changed.
+    pub fn invalidate(&mut self) {
+        self.invalidated = true;
+    }
+
     /// May apply some the managed sticky parameters to the given request.
     ///
     /// After receiving the response from this sliding sync, the caller MUST
@@ -0,0 +1,201 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Versions of the Sliding Sync protocol.
+
+use bytes::BufMut;
+use ruma::api::{
+    client::sync::sync_events::v4, error::IntoHttpError, metadata, MatrixVersion, Metadata,
+    OutgoingRequest, SendAccessToken,
+};
+use url::Url;
+
+use crate::{Client, HttpResult};
+
+/// The unstable feature advertised in `/versions` by the homeservers
+/// implementing simplified sliding sync natively.
+pub(super) const NATIVE_UNSTABLE_FEATURE: &str = "org.matrix.simplified_msc3575";
+
+/// The path segment of the sliding sync proxy endpoint.
+const PROXY_PATH: &str = "/org.matrix.msc3575/sync";
+
+/// The path segment of the native simplified sliding sync endpoint.
+const NATIVE_PATH: &str = "/org.matrix.simplified_msc3575/sync";
+
+/// The version of the Sliding Sync protocol spoken by a
+/// [`SlidingSync`][super::SlidingSync] instance.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Version {
+    /// The original Sliding Sync protocol ([MSC3575]), served by a sliding
+    /// sync proxy.
+    ///
+    /// [MSC3575]: https://github.com/matrix-org/matrix-spec-proposals/pull/3575
+    Proxy {
+        /// The URL of the proxy, or `None` to reach it at the homeserver URL.
+        url: Option<Url>,
+    },
+
+    /// The simplified Sliding Sync protocol ([MSC4186]), implemented natively
+    /// by the homeserver.
+    ///
+    /// [MSC4186]: https://github.com/matrix-org/matrix-spec-proposals/pull/4186
+    Native,
+}
+
+impl Version {
+    /// Is this the native simplified Sliding Sync protocol?
+    pub fn is_native(&self) -> bool {
+        matches!(self, Self::Native)
+    }
+
+    /// Detect the version to use with the homeserver of the given client.
+    ///
+    /// This is the native protocol if the homeserver supports it, otherwise
+    /// the sliding sync proxy auto-discovered by the client, if any.
+    pub(super) async fn detect(client: &Client) -> HttpResult<Self> {
+        if client.supports_native_sliding_sync().await? {
+            Ok(Self::Native)
+        } else {
+            Ok(Self::Proxy { url: client.sliding_sync_proxy() })
+        }
+    }
+
+    /// The URL requests must be sent to, if it's not the homeserver URL.
+    pub(super) fn overriding_url(&self) -> Option<&Url> {
+        match self {
+            Self::Proxy { url } => url.as_ref(),
+            Self::Native => None,
+        }
+    }
+}
+
+/// A Sliding Sync request sent to the native simplified sliding sync endpoint.
+///
+/// The native endpoint accepts a subset of the proxy request, and replies with
+/// a response of the same shape, minus the list operations. This type wraps a
+/// [`v4::Request`] stripped from the fields the homeserver doesn't know about,
+/// and sends it to the native endpoint.
+#[derive(Clone, Debug)]
+pub(super) struct NativeRequest(v4::Request);
+
+impl NativeRequest {
+    /// Create a new `NativeRequest` from a proxy request.
+    pub(super) fn new(mut request: v4::Request) -> Self {
+        // Parameters aren't sticky in simplified sliding sync, so there is no
+        // transaction to acknowledge, and nothing to unsubscribe from.
+        request.delta_token = None;
+        request.txn_id = None;
+        request.unsubscribe_rooms.clear();
+
+        // Rooms are always sorted by recency, and bumped by the homeserver's own
+        // rules.
+        for list in request.lists.values_mut() {
+            list.sort.clear();
+            list.bump_event_types.clear();
+        }
+
+        Self(request)
+    }
+}
+
+impl OutgoingRequest for NativeRequest {
+    type EndpointError = <v4::Request as OutgoingRequest>::EndpointError;
+    type IncomingResponse = v4::Response;
+
+    const METADATA: Metadata = metadata! {
+        method: POST,
+        rate_limited: false,
+        authentication: AccessToken,
+        history: {
+            unstable => "/_matrix/client/unstable/org.matrix.simplified_msc3575/sync",
+        }
+    };
+
+    fn try_into_http_request<T: Default + BufMut>(
+        self,
+        base_url: &str,
+        access_token: SendAccessToken<'_>,
+        considering_versions: &[MatrixVersion],
+    ) -> Result<http::Request<T>, IntoHttpError> {
+        let (mut parts, body) = self
+            .0
+            .try_into_http_request::<T>(base_url, access_token, considering_versions)?
+            .into_parts();
+
+        parts.uri = parts
+            .uri
+            .to_string()
+            .replacen(PROXY_PATH, NATIVE_PATH, 1)
+            .parse()
+            .map_err(http::Error::from)?;
+
+        Ok(http::Request::from_parts(parts, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use ruma::{
+        api::{client::sync::sync_events::v4, MatrixVersion, OutgoingRequest, SendAccessToken},
+        assign,
+    };
+    use url::Url;
+
+    use super::{NativeRequest, Version};
+
+    #[test]
+    fn test_version_overriding_url() {
+        let url = Url::parse("https://proxy.example.org").unwrap();
+
+        assert_eq!(Version::Proxy { url: Some(url.clone()) }.overriding_url(), Some(&url));
+        assert_matches!(Version::Proxy { url: None }.overriding_url(), None);
+        assert_matches!(Version::Native.overriding_url(), None);
+        assert!(Version::Native.is_native());
+        assert!(!Version::Proxy { url: None }.is_native());
+    }
+
+    #[test]
+    fn test_native_request() {
+        let list = assign!(v4::SyncRequestList::default(), {
+            sort: vec!["by_recency".to_owned()],
+        });
+
+        let request = assign!(v4::Request::new(), {
+            pos: Some("42".to_owned()),
+            txn_id: Some("txn".to_owned()),
+            delta_token: Some("delta".to_owned()),
+            lists: [("all".to_owned(), list)].into(),
+        });
+
+        let http_request = NativeRequest::new(request)
+            .try_into_http_request::<Vec<u8>>(
+                "https://example.org",
+                SendAccessToken::IfRequired("token"),
+                &[MatrixVersion::V1_0],
+            )
+            .unwrap();
+
+        assert_eq!(
+            http_request.uri().path(),
+            "/_matrix/client/unstable/org.matrix.simplified_msc3575/sync"
+        );
+        assert_eq!(http_request.uri().query(), Some("pos=42"));
+
+        let body: serde_json::Value = serde_json::from_slice(http_request.body()).unwrap();
+        assert!(body.get("txn_id").is_none());
+        assert!(body.get("delta_token").is_none());
+        assert!(body["lists"]["all"].get("sort").is_none());
+    }
+}
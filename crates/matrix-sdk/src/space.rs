@@ -0,0 +1,325 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Spaces, and the rooms they contain.
+//!
+//! A space is a room, whose `m.space.child` state events point to the rooms
+//! and sub-spaces it contains. See [`Space`] and [`Client::get_space`].
+//!
+//! [`Client::get_space`]: crate::Client::get_space
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, VecDeque},
+    ops::Deref,
+};
+
+use matrix_sdk_base::{deserialized_responses::SyncOrStrippedState, RoomState};
+use ruma::{
+    api::client::{
+        space::{get_hierarchy, SpaceHierarchyRoomsChunk},
+        state::send_state_event,
+    },
+    events::{space::child::SpaceChildEventContent, SyncStateEvent},
+    uint, MilliSecondsSinceUnixEpoch, OwnedRoomId, OwnedServerName, RoomId, UInt,
+};
+use serde_json::json;
+use tracing::{info, instrument};
+
+use crate::{Result, Room};
+
+/// The maximum number of batches of the hierarchy loaded by
+/// [`Space::suggested_rooms`].
+const MAX_SUGGESTED_ROOMS_PAGES: usize = 10;
+
+/// A space, i.e. a room of type `m.space` that contains other rooms.
+///
+/// This derefs to the [`Room`] of the space.
+#[derive(Debug, Clone)]
+pub struct Space {
+    room: Room,
+}
+
+impl Deref for Space {
+    type Target = Room;
+
+    fn deref(&self) -> &Self::Target {
+        &self.room
+    }
+}
+
+impl Space {
+    /// Get the given room as a space.
+    ///
+    /// Returns `None` if the room isn't a space.
+    pub fn from_room(room: Room) -> Option<Self> {
+        room.is_space().then_some(Self { room })
+    }
+
+    /// Get the room of this space.
+    pub fn room(&self) -> &Room {
+        &self.room
+    }
+
+    /// Get the children of this space, as advertised in its local state.
+    ///
+    /// This is empty if the user hasn't joined the space; use
+    /// [`Space::hierarchy`] instead.
+    ///
+    /// The children are sorted according to the [spec]: first the ones with a
+    /// valid `order`, then the others, by the time at which they were added.
+    ///
+    /// [spec]: https://spec.matrix.org/v1.10/client-server-api/#ordering-of-children-within-a-space
+    pub async fn children(&self) -> Result<Vec<SpaceChild>> {
+        let mut children = self
+            .room
+            .get_state_events_static::<SpaceChildEventContent>()
+            .await?
+            .into_iter()
+            .filter_map(|event| match event.deserialize() {
+                Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(e))) => {
+                    SpaceChild::new(e.state_key, e.content, e.origin_server_ts)
+                }
+                // The children of a space that hasn't been joined can be fetched with
+                // `Space::hierarchy()`.
+                Ok(SyncOrStrippedState::Sync(SyncStateEvent::Redacted(_)))
+                | Ok(SyncOrStrippedState::Stripped(_)) => None,
+                Err(e) => {
+                    info!(room_id = ?self.room_id(), "Could not deserialize m.space.child: {e}");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        children.sort_by(SpaceChild::cmp_order);
+
+        Ok(children)
+    }
+
+    /// Add a room to this space, or update it if it's already a child of this
+    /// space.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The ID of the room to add.
+    ///
+    /// * `via` - The servers to try to join the room through. It must not be
+    ///   empty.
+    ///
+    /// * `order` - The string used to sort the children of the space,
+    ///   lexicographically. Clients ignore it if it is longer than 50
+    ///   characters or contains characters outside of the `\x20` to `\x7E`
+    ///   range.
+    ///
+    /// * `suggested` - Whether the room should be suggested to the members of
+    ///   the space.
+    #[instrument(skip_all, fields(space_id = ?self.room_id(), room_id = ?room_id))]
+    pub async fn add_child(
+        &self,
+        room_id: &RoomId,
+        via: Vec<OwnedServerName>,
+        order: Option<String>,
+        suggested: bool,
+    ) -> Result<send_state_event::v3::Response> {
+        let mut content = SpaceChildEventContent::new(via);
+        content.order = order;
+        content.suggested = suggested;
+
+        self.room.send_state_event_for_key(room_id, content).await
+    }
+
+    /// Remove a room from this space.
+    #[instrument(skip_all, fields(space_id = ?self.room_id(), room_id = ?room_id))]
+    pub async fn remove_child(&self, room_id: &RoomId) -> Result<send_state_event::v3::Response> {
+        // A child without `via` is not a child anymore.
+        self.room.send_state_event_raw("m.space.child", room_id.as_str(), json!({})).await
+    }
+
+    /// Get a batch of the rooms of this space from the homeserver, including
+    /// the ones the user hasn't joined.
+    ///
+    /// The rooms are returned in depth-first order, starting with the space
+    /// itself.
+    #[instrument(skip_all, fields(space_id = ?self.room_id()))]
+    pub async fn hierarchy(&self, options: HierarchyOptions) -> Result<Hierarchy> {
+        let mut request = get_hierarchy::v1::Request::new(self.room_id().to_owned());
+        request.from = options.from;
+        request.limit = options.limit;
+        request.max_depth = options.max_depth;
+        request.suggested_only = options.suggested_only;
+
+        let response = self.room.client.send(request, None).await?;
+
+        Ok(Hierarchy { rooms: response.rooms, next_batch: response.next_batch })
+    }
+
+    /// Get the suggested children of this space that the user hasn't joined
+    /// yet, from the homeserver.
+    ///
+    /// To bound the number of requests, at most 10 batches of the hierarchy
+    /// are loaded.
+    pub async fn suggested_rooms(&self) -> Result<Vec<SpaceHierarchyRoomsChunk>> {
+        let mut options = HierarchyOptions::new();
+        options.max_depth = Some(uint!(1));
+        options.suggested_only = true;
+
+        let mut suggested = Vec::new();
+
+        for _ in 0..MAX_SUGGESTED_ROOMS_PAGES {
+            let hierarchy = self.hierarchy(options.clone()).await?;
+
+            suggested.extend(hierarchy.rooms.into_iter().filter(|room| {
+                room.room_id != self.room_id()
+                    && self
+                        .room
+                        .client
+                        .get_room(&room.room_id)
+                        .map_or(true, |room| room.state() != RoomState::Joined)
+            }));
+
+            match hierarchy.next_batch {
+                Some(next_batch) => options.from = Some(next_batch),
+                None => break,
+            }
+        }
+
+        Ok(suggested)
+    }
+
+    /// Get the IDs of the joined rooms that belong to this space, including
+    /// the ones of its nested sub-spaces, from the local state.
+    ///
+    /// The joined sub-spaces are included in the result, but not this space.
+    pub async fn joined_descendants(&self) -> Result<BTreeSet<OwnedRoomId>> {
+        let mut descendants = BTreeSet::new();
+        let mut visited_spaces = BTreeSet::from([self.room_id().to_owned()]);
+        let mut spaces = VecDeque::from([self.clone()]);
+
+        while let Some(space) = spaces.pop_front() {
+            for child in space.children().await? {
+                let Some(room) = self.room.client.get_room(&child.room_id) else { continue };
+                if room.state() != RoomState::Joined {
+                    continue;
+                }
+
+                if let Some(sub_space) = Space::from_room(room) {
+                    // Spaces can form cycles, only visit each of them once.
+                    if visited_spaces.insert(child.room_id.clone()) {
+                        spaces.push_back(sub_space);
+                    }
+                }
+
+                if child.room_id != self.room_id() {
+                    descendants.insert(child.room_id);
+                }
+            }
+        }
+
+        Ok(descendants)
+    }
+}
+
+/// A child of a [`Space`], as returned by [`Space::children`].
+#[derive(Debug, Clone)]
+pub struct SpaceChild {
+    /// The ID of the child room.
+    pub room_id: OwnedRoomId,
+
+    /// The servers to try to join the room through.
+    pub via: Vec<OwnedServerName>,
+
+    /// The string used to sort the children of the space, if it's valid.
+    pub order: Option<String>,
+
+    /// Whether the room is suggested to the members of the space.
+    pub suggested: bool,
+
+    /// When the room was added to the space.
+    pub origin_server_ts: MilliSecondsSinceUnixEpoch,
+}
+
+impl SpaceChild {
+    fn new(
+        room_id: OwnedRoomId,
+        content: SpaceChildEventContent,
+        origin_server_ts: MilliSecondsSinceUnixEpoch,
+    ) -> Option<Self> {
+        // A child without `via` has been removed from the space.
+        if content.via.is_empty() {
+            return None;
+        }
+
+        let order = content.order.filter(|order| {
+            order.len() <= 50 && order.chars().all(|c| ('\x20'..='\x7E').contains(&c))
+        });
+
+        Some(Self {
+            room_id,
+            via: content.via,
+            order,
+            suggested: content.suggested,
+            origin_server_ts,
+        })
+    }
+
+    fn cmp_order(&self, other: &Self) -> Ordering {
+        let by_order = match (&self.order, &other.order) {
+            (Some(a), Some(b)) => a.cmp(b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+
+        by_order
+            .then_with(|| self.origin_server_ts.cmp(&other.origin_server_ts))
+            .then_with(|| self.room_id.cmp(&other.room_id))
+    }
+}
+
+/// Options for [`Space::hierarchy`].
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct HierarchyOptions {
+    /// The token to continue from, returned in [`Hierarchy::next_batch`].
+    pub from: Option<String>,
+
+    /// The maximum number of rooms to return in a batch.
+    pub limit: Option<UInt>,
+
+    /// The maximum depth of sub-spaces to walk through.
+    pub max_depth: Option<UInt>,
+
+    /// Whether to only return the suggested rooms.
+    pub suggested_only: bool,
+}
+
+impl HierarchyOptions {
+    /// Creates `HierarchyOptions` to get the first batch of the hierarchy of a
+    /// space.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// A batch of the hierarchy of a space, returned by [`Space::hierarchy`].
+#[derive(Debug)]
+pub struct Hierarchy {
+    /// The rooms in this batch.
+    pub rooms: Vec<SpaceHierarchyRoomsChunk>,
+
+    /// The token to get the next batch, with [`HierarchyOptions::from`].
+    ///
+    /// If this is `None`, there are no more rooms.
+    pub next_batch: Option<String>,
+}
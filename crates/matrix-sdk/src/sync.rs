@@ -22,12 +22,15 @@ use std::{
 
 pub use matrix_sdk_base::sync::*;
 use matrix_sdk_base::{
-    debug::{DebugInvitedRoom, DebugListOfRawEventsNoId},
+    debug::{DebugInvitedRoom, DebugKnockedRoom, DebugListOfRawEventsNoId},
     instant::Instant,
     sync::SyncResponse as BaseSyncResponse,
 };
 use ruma::{
-    api::client::sync::sync_events::{self, v3::InvitedRoom},
+    api::client::sync::sync_events::{
+        self,
+        v3::{InvitedRoom, KnockedRoom},
+    },
     events::{presence::PresenceEvent, AnyGlobalAccountDataEvent, AnyToDeviceEvent},
     serde::Raw,
     OwnedRoomId, RoomId,
@@ -100,6 +103,13 @@ pub enum RoomUpdate {
         /// Updates to the room.
         updates: InvitedRoom,
     },
+    /// Updates to a room the user has knocked on.
+    Knocked {
+        /// Room object with general information on the room.
+        room: Room,
+        /// Updates to the room.
+        updates: KnockedRoom,
+    },
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -117,6 +127,11 @@ impl fmt::Debug for RoomUpdate {
                 .field("room", room)
                 .field("updates", &DebugInvitedRoom(updates))
                 .finish(),
+            Self::Knocked { room, updates } => f
+                .debug_struct("Knocked")
+                .field("room", room)
+                .field("updates", &DebugKnockedRoom(updates))
+                .finish(),
         }
     }
 }
@@ -225,6 +240,21 @@ impl Client {
             self.handle_sync_events(HandlerKind::StrippedState, Some(&room), invite_state).await?;
         }
 
+        for (room_id, room_info) in &rooms.knock {
+            let Some(room) = self.get_room(room_id) else {
+                error!(?room_id, "Can't call event handler, room not found");
+                continue;
+            };
+
+            self.send_room_update(room_id, || RoomUpdate::Knocked {
+                room: room.clone(),
+                updates: room_info.clone(),
+            });
+
+            let knock_state = &room_info.knock_state.events;
+            self.handle_sync_events(HandlerKind::StrippedState, Some(&room), knock_state).await?;
+        }
+
         debug!("Ran event handlers in {:?}", now.elapsed());
 
         let now = Instant::now();
@@ -0,0 +1,437 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Driver for the [User-Interactive Authentication API][uiaa].
+//!
+//! Some endpoints, like the ones to register an account, to delete devices or
+//! to upload cross-signing keys, require the user to authenticate again with
+//! one of the flows offered by the homeserver. The first request always fails
+//! with the list of flows, and the same request must be retried with the data
+//! of each stage of the flow until it succeeds.
+//!
+//! A [`UiaaDriver`] takes care of this dance: it selects a flow, asks a
+//! [`UiaaHandler`] for the data of each stage, and retries the request until it
+//! succeeds.
+//!
+//! [uiaa]: https://spec.matrix.org/latest/client-server-api/#user-interactive-authentication-api
+
+use std::{collections::BTreeMap, fmt, future::Future};
+
+use async_trait::async_trait;
+use matrix_sdk_common::{SendOutsideWasm, SyncOutsideWasm};
+use ruma::{
+    api::client::{
+        error::StandardErrorBody,
+        uiaa::{
+            AuthData, AuthFlow, AuthType, Dummy, EmailIdentity, FallbackAcknowledgement, Password,
+            ReCaptcha, RegistrationToken, ThirdpartyIdCredentials, UiaaInfo,
+        },
+    },
+    assign,
+    serde::JsonObject,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value as JsonValue;
+use thiserror::Error;
+use tracing::debug;
+use url::Url;
+
+use crate::{Client, Error, Result};
+
+/// The type of the stage to accept the terms of service of the homeserver.
+///
+/// It is not known by ruma, so it is handled as a custom stage.
+const TERMS_AUTH_TYPE: &str = "m.login.terms";
+
+/// The maximum number of times a request is retried with authentication data.
+///
+/// This stops the driver if the handler keeps returning wrong credentials.
+const MAX_ATTEMPTS: usize = 10;
+
+/// An error that occurred while driving a User-Interactive Authentication
+/// session.
+#[derive(Debug, Error)]
+pub enum UiaaError {
+    /// None of the flows offered by the homeserver can be completed with the
+    /// stages supported by the [`UiaaHandler`].
+    #[error("no authentication flow can be completed with the supported stages")]
+    NoSupportedFlow,
+
+    /// The request was retried too many times without completing the
+    /// authentication, for example because the [`UiaaHandler`] kept returning
+    /// wrong credentials.
+    #[error("the authentication was not completed after {0} attempts")]
+    TooManyAttempts(usize),
+}
+
+/// The current state of a User-Interactive Authentication session, as
+/// returned by the homeserver.
+#[derive(Clone, Debug)]
+pub struct UiaaState {
+    info: UiaaInfo,
+    params: BTreeMap<String, JsonValue>,
+}
+
+impl UiaaState {
+    fn new(info: UiaaInfo) -> Self {
+        let params = serde_json::from_str(info.params.get()).unwrap_or_default();
+        Self { info, params }
+    }
+
+    /// The ID of the session, if the homeserver returned one.
+    pub fn session(&self) -> Option<&str> {
+        self.info.session.as_deref()
+    }
+
+    /// The flows offered by the homeserver.
+    pub fn flows(&self) -> &[AuthFlow] {
+        &self.info.flows
+    }
+
+    /// The stages that were already completed.
+    pub fn completed(&self) -> &[AuthType] {
+        &self.info.completed
+    }
+
+    /// The error returned by the homeserver for the last attempt at completing
+    /// a stage, if any.
+    ///
+    /// For example, this is set if the password sent for the password stage
+    /// was wrong.
+    pub fn auth_error(&self) -> Option<&StandardErrorBody> {
+        self.info.auth_error.as_ref()
+    }
+
+    /// The parameters of the given stage, if any.
+    fn params<T: DeserializeOwned>(&self, auth_type: &str) -> Option<T> {
+        serde_json::from_value(self.params.get(auth_type)?.clone()).ok()
+    }
+}
+
+/// A policy that the user must accept during the terms stage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TermsPolicy {
+    /// The ID of the policy, for example `privacy_policy`.
+    pub id: String,
+
+    /// The version of the policy.
+    pub version: String,
+
+    /// The name of the policy, in English if available.
+    pub name: Option<String>,
+
+    /// The URL of the policy, in English if available.
+    pub url: Option<Url>,
+}
+
+#[derive(Deserialize)]
+struct TermsParams {
+    #[serde(default)]
+    policies: BTreeMap<String, JsonObject>,
+}
+
+#[derive(Deserialize)]
+struct LocalizedPolicy {
+    name: Option<String>,
+    url: Option<Url>,
+}
+
+impl TermsPolicy {
+    fn parse(state: &UiaaState) -> Vec<Self> {
+        let Some(params) = state.params::<TermsParams>(TERMS_AUTH_TYPE) else { return Vec::new() };
+
+        params
+            .policies
+            .into_iter()
+            .map(|(id, mut policy)| {
+                let version = match policy.remove("version") {
+                    Some(JsonValue::String(version)) => version,
+                    _ => String::new(),
+                };
+
+                // Every other field is a translation of the policy.
+                let localized = policy
+                    .remove("en")
+                    .or_else(|| policy.into_values().next())
+                    .and_then(|value| serde_json::from_value::<LocalizedPolicy>(value).ok());
+                let (name, url) = localized.map_or((None, None), |l| (l.name, l.url));
+
+                Self { id, version, name, url }
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct ReCaptchaParams {
+    public_key: String,
+}
+
+/// A handler for the stages of a User-Interactive Authentication session.
+///
+/// Each method is called when the corresponding stage is the next one to
+/// complete, and returns the data needed to complete it, or `None` if the
+/// stage is not supported or the user cancelled it. When a stage is not
+/// completed, the [`UiaaDriver`] tries another flow if possible.
+///
+/// The `m.login.dummy` stage is always completed automatically.
+///
+/// All the methods have a default implementation that doesn't support the
+/// stage, so only the supported stages need to be implemented.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait UiaaHandler: SendOutsideWasm + SyncOutsideWasm {
+    /// Complete the `m.login.password` stage.
+    ///
+    /// Returns the identifier of the user and their password. The session is
+    /// set by the driver.
+    async fn password(&self, _state: &UiaaState) -> Option<Password> {
+        None
+    }
+
+    /// Complete the `m.login.recaptcha` stage.
+    ///
+    /// Returns the response of the CAPTCHA for the given public key.
+    async fn recaptcha(&self, _state: &UiaaState, _public_key: Option<&str>) -> Option<String> {
+        None
+    }
+
+    /// Complete the `m.login.terms` stage.
+    ///
+    /// Returns whether the user accepted the given policies.
+    async fn terms(&self, _state: &UiaaState, _policies: &[TermsPolicy]) -> bool {
+        false
+    }
+
+    /// Complete the `m.login.email.identity` stage.
+    ///
+    /// Returns the credentials of the email address that was validated.
+    async fn email_identity(&self, _state: &UiaaState) -> Option<ThirdpartyIdCredentials> {
+        None
+    }
+
+    /// Complete the `m.login.registration_token` stage.
+    ///
+    /// Returns the registration token.
+    async fn registration_token(&self, _state: &UiaaState) -> Option<String> {
+        None
+    }
+
+    /// Complete a stage that is not supported natively, with the fallback web
+    /// page of the homeserver.
+    ///
+    /// The given URL should be opened in a web browser, and this should only
+    /// return once the user completed the stage in the web page, which calls
+    /// `window.onAuthDone()` or posts an `authDone` message when it is done.
+    ///
+    /// Returns whether the stage was completed.
+    async fn fallback(&self, _state: &UiaaState, _auth_type: &AuthType, _url: &Url) -> bool {
+        false
+    }
+}
+
+/// A driver for a User-Interactive Authentication session.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use matrix_sdk::{
+/// #     async_trait,
+/// #     ruma::{api::client::{account::deactivate, uiaa}},
+/// #     uiaa::{UiaaDriver, UiaaHandler, UiaaState},
+/// #     Client,
+/// # };
+/// # use url::Url;
+/// struct PasswordHandler;
+///
+/// #[async_trait]
+/// impl UiaaHandler for PasswordHandler {
+///     async fn password(&self, _state: &UiaaState) -> Option<uiaa::Password> {
+///         Some(uiaa::Password::new(
+///             uiaa::UserIdentifier::UserIdOrLocalpart("example".to_owned()),
+///             "wordpass".to_owned(),
+///         ))
+///     }
+/// }
+///
+/// # async {
+/// # let homeserver = Url::parse("http://localhost:8080")?;
+/// # let client = Client::new(homeserver).await?;
+/// let response = UiaaDriver::new(&client, &PasswordHandler)
+///     .run(|auth| {
+///         let mut request = deactivate::v3::Request::new();
+///         request.auth = auth;
+///         client.send(request, None)
+///     })
+///     .await?;
+/// # anyhow::Ok(()) };
+/// ```
+pub struct UiaaDriver<'a> {
+    handler: &'a dyn UiaaHandler,
+    homeserver: Url,
+    /// The stages that the handler didn't complete.
+    declined: Vec<AuthType>,
+}
+
+impl<'a> UiaaDriver<'a> {
+    /// Create a new `UiaaDriver` for the given client, that completes the
+    /// stages with the given handler.
+    pub fn new(client: &Client, handler: &'a dyn UiaaHandler) -> Self {
+        Self { handler, homeserver: client.homeserver(), declined: Vec::new() }
+    }
+
+    /// Send a request until it doesn't need more authentication.
+    ///
+    /// `send` is called with the authentication data to set on the request.
+    /// It is `None` for the first attempt.
+    ///
+    /// The request is retried at most 10 times with authentication data, after
+    /// which [`UiaaError::TooManyAttempts`] is returned.
+    pub async fn run<T, E, F, Fut>(&mut self, mut send: F) -> Result<T>
+    where
+        E: Into<Error>,
+        F: FnMut(Option<AuthData>) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut auth_data = None;
+        let mut attempts = 0;
+
+        loop {
+            let error = match send(auth_data.take()).await {
+                Ok(response) => return Ok(response),
+                Err(error) => error.into(),
+            };
+
+            let Some(info) = error.as_uiaa_response() else { return Err(error) };
+
+            if attempts == MAX_ATTEMPTS {
+                return Err(UiaaError::TooManyAttempts(attempts).into());
+            }
+            attempts += 1;
+
+            auth_data = Some(self.next_auth_data(UiaaState::new(info.clone())).await?);
+        }
+    }
+
+    /// Get the authentication data for the next stage of the first flow that
+    /// can be completed.
+    async fn next_auth_data(&mut self, state: UiaaState) -> Result<AuthData> {
+        let completed = state.completed();
+        let next_stages = state
+            .flows()
+            .iter()
+            .filter(|flow| flow.stages.starts_with(completed))
+            .filter_map(|flow| flow.stages.get(completed.len()));
+
+        for auth_type in next_stages {
+            if self.declined.contains(auth_type) {
+                continue;
+            }
+
+            if let Some(auth_data) = self.complete_stage(&state, auth_type).await? {
+                return Ok(auth_data);
+            }
+
+            debug!("The `{auth_type}` stage was not completed");
+            self.declined.push(auth_type.clone());
+        }
+
+        Err(UiaaError::NoSupportedFlow.into())
+    }
+
+    /// Ask the handler to complete the given stage.
+    async fn complete_stage(
+        &self,
+        state: &UiaaState,
+        auth_type: &AuthType,
+    ) -> Result<Option<AuthData>> {
+        let session = state.session().map(ToOwned::to_owned);
+
+        let auth_data = match auth_type {
+            // If the dummy stage failed, retrying it would loop forever.
+            AuthType::Dummy => state
+                .auth_error()
+                .is_none()
+                .then(|| AuthData::Dummy(assign!(Dummy::new(), { session }))),
+            AuthType::Password => self
+                .handler
+                .password(state)
+                .await
+                .map(|password| AuthData::Password(assign!(password, { session }))),
+            AuthType::ReCaptcha => {
+                let params = state.params::<ReCaptchaParams>(auth_type.as_str());
+                let public_key = params.as_ref().map(|params| params.public_key.as_str());
+
+                self.handler.recaptcha(state, public_key).await.map(|response| {
+                    AuthData::ReCaptcha(assign!(ReCaptcha::new(response), { session }))
+                })
+            }
+            AuthType::EmailIdentity => {
+                self.handler.email_identity(state).await.map(|credentials| {
+                    AuthData::EmailIdentity(assign!(EmailIdentity::new(credentials), { session }))
+                })
+            }
+            AuthType::RegistrationToken => {
+                self.handler.registration_token(state).await.map(|token| {
+                    AuthData::RegistrationToken(assign!(RegistrationToken::new(token), { session }))
+                })
+            }
+            _ if auth_type.as_str() == TERMS_AUTH_TYPE => {
+                let policies = TermsPolicy::parse(state);
+
+                if self.handler.terms(state, &policies).await {
+                    Some(AuthData::new(TERMS_AUTH_TYPE, session, JsonObject::new())?)
+                } else {
+                    None
+                }
+            }
+            _ => {
+                // The fallback needs a session to know which session to complete.
+                let Some(session) = session else { return Ok(None) };
+                let url = self.fallback_url(auth_type, &session)?;
+
+                self.handler.fallback(state, auth_type, &url).await.then(|| {
+                    AuthData::FallbackAcknowledgement(FallbackAcknowledgement::new(session))
+                })
+            }
+        };
+
+        Ok(auth_data)
+    }
+
+    /// The URL of the fallback web page for the given stage.
+    fn fallback_url(&self, auth_type: &AuthType, session: &str) -> Result<Url> {
+        let mut url = self.homeserver.clone();
+
+        // Append the segments rather than joining a relative URL, which would
+        // drop the last segment of a homeserver path without a trailing slash.
+        url.path_segments_mut()
+            .map_err(|()| url::ParseError::RelativeUrlWithCannotBeABaseBase)?
+            .pop_if_empty()
+            .extend(["_matrix", "client", "v3", "auth", auth_type.as_str(), "fallback", "web"]);
+        url.query_pairs_mut().append_pair("session", session);
+
+        Ok(url)
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for UiaaDriver<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UiaaDriver")
+            .field("homeserver", &self.homeserver)
+            .field("declined", &self.declined)
+            .finish_non_exhaustive()
+    }
+}
@@ -18,12 +18,17 @@
 use std::fmt;
 
 use async_trait::async_trait;
-use ruma::{events::AnyTimelineEvent, serde::Raw};
+use ruma::{
+    events::{AnyTimelineEvent, AnyToDeviceEvent, ToDeviceEventType},
+    serde::Raw,
+    MatrixToUri,
+};
 use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
 use tracing::{debug, error};
 
 use super::{
     filter::MatrixEventFilterInput, EventFilter, MessageLikeEventFilter, StateEventFilter,
+    ToDeviceEventFilter,
 };
 
 /// Must be implemented by a component that provides functionality of deciding
@@ -35,6 +40,17 @@ pub trait CapabilitiesProvider: Send + Sync + 'static {
     /// capabilities that the clients grants to a given widget (usually by
     /// prompting the user).
     async fn acquire_capabilities(&self, capabilities: Capabilities) -> Capabilities;
+
+    /// Navigates to the given `matrix.to` permalink, on behalf of a widget that
+    /// was granted the [`Capabilities::navigate`] capability.
+    ///
+    /// Returns an error message that is forwarded to the widget if the client
+    /// can't navigate to the permalink. By default, navigation is not
+    /// supported.
+    async fn navigate(&self, uri: MatrixToUri) -> Result<(), String> {
+        let _ = uri;
+        Err("Navigation is not supported by this client".to_owned())
+    }
 }
 
 /// Capabilities that a widget can request from a client.
@@ -51,6 +67,31 @@ pub struct Capabilities {
     /// This means clients should not offer to open the widget in a separate
     /// browser/tab/webview that is not connected to the postmessage widget-api.
     pub requires_client: bool,
+    /// This allows the widget to send delayed events, that the homeserver
+    /// sends on its behalf after a timeout, as defined in [MSC4157].
+    ///
+    /// [MSC4157]: https://github.com/matrix-org/matrix-spec-proposals/pull/4157
+    pub send_delayed_event: bool,
+    /// This allows the widget to cancel, restart or send right away the
+    /// delayed events it scheduled.
+    pub update_delayed_event: bool,
+    /// This allows the widget to ask the client to navigate to a `matrix.to`
+    /// permalink, as defined in [MSC2931].
+    ///
+    /// [MSC2931]: https://github.com/matrix-org/matrix-spec-proposals/pull/2931
+    pub navigate: bool,
+    /// This allows the widget to receive the TURN servers of the homeserver,
+    /// as defined in [MSC3846].
+    ///
+    /// [MSC3846]: https://github.com/matrix-org/matrix-spec-proposals/pull/3846
+    pub turn_servers: bool,
+    /// This allows the widget to upload files to the media repository, as
+    /// defined in [MSC4039].
+    ///
+    /// [MSC4039]: https://github.com/matrix-org/matrix-spec-proposals/pull/4039
+    pub upload_file: bool,
+    /// This allows the widget to download files from the media repository.
+    pub download_file: bool,
 }
 
 impl Capabilities {
@@ -66,13 +107,71 @@ impl Capabilities {
 
         self.read.iter().any(|f| f.matches(&filter_in))
     }
+
+    /// Tells if a given raw to-device event matches the read filter.
+    pub(super) fn raw_to_device_event_matches_read_filter(
+        &self,
+        raw: &Raw<AnyToDeviceEvent>,
+    ) -> bool {
+        let event_type = match raw.get_field::<ToDeviceEventType>("type") {
+            Ok(Some(event_type)) => event_type,
+            Ok(None) => {
+                error!("Received a to-device event without a type");
+                return false;
+            }
+            Err(err) => {
+                error!("Failed to deserialize the type of a to-device event: {err}");
+                return false;
+            }
+        };
+
+        self.read.iter().any(|f| f.matches_to_device_event_type(&event_type))
+    }
 }
 
 const SEND_EVENT: &str = "org.matrix.msc2762.send.event";
 const READ_EVENT: &str = "org.matrix.msc2762.receive.event";
 const SEND_STATE: &str = "org.matrix.msc2762.send.state_event";
 const READ_STATE: &str = "org.matrix.msc2762.receive.state_event";
+const SEND_TO_DEVICE: &str = "org.matrix.msc3819.send.to_device";
+const READ_TO_DEVICE: &str = "org.matrix.msc3819.receive.to_device";
 const REQUIRES_CLIENT: &str = "io.element.requires_client";
+const SEND_DELAYED_EVENT: &str = "org.matrix.msc4157.send.delayed_event";
+const UPDATE_DELAYED_EVENT: &str = "org.matrix.msc4157.update_delayed_event";
+const NAVIGATE: &str = "org.matrix.msc2931.navigate";
+const TURN_SERVERS: &str = "town.robin.msc3846.turn_servers";
+const UPLOAD_FILE: &str = "org.matrix.msc4039.upload_file";
+const DOWNLOAD_FILE: &str = "org.matrix.msc4039.download_file";
+
+/// Escape the given event type for a capability, since `#` separates the
+/// event type from the rest of the filter: `\` is escaped as `\\` and `#` as
+/// `\#`.
+fn escape_event_type(event_type: &str) -> String {
+    event_type.replace('\\', "\\\\").replace('#', "\\#")
+}
+
+/// Split the filter of a capability at the first unescaped `#`, see
+/// [`escape_event_type`].
+///
+/// Returns the unescaped event type before it, and the rest of the filter
+/// after it, if any.
+fn split_event_type(s: &str) -> (String, Option<&str>) {
+    let mut event_type = String::with_capacity(s.len());
+    let mut chars = s.char_indices();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, escaped)) => event_type.push(escaped),
+                None => event_type.push('\\'),
+            },
+            '#' => return (event_type, Some(&s[index + 1..])),
+            c => event_type.push(c),
+        }
+    }
+
+    (event_type, None)
+}
 
 impl Serialize for Capabilities {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -85,6 +184,9 @@ impl Serialize for Capabilities {
                 match self.0 {
                     EventFilter::MessageLike(filter) => PrintMessageLikeEventFilter(filter).fmt(f),
                     EventFilter::State(filter) => PrintStateEventFilter(filter).fmt(f),
+                    EventFilter::ToDevice(filter) => {
+                        write!(f, "{}", escape_event_type(&filter.event_type.to_string()))
+                    }
                 }
             }
         }
@@ -94,8 +196,7 @@ impl Serialize for Capabilities {
             fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
                 match self.0 {
                     MessageLikeEventFilter::WithType(event_type) => {
-                        // TODO: escape `#` as `\#` and `\` as `\\` in event_type
-                        write!(f, "{event_type}")
+                        write!(f, "{}", escape_event_type(&event_type.to_string()))
                     }
                     MessageLikeEventFilter::RoomMessageWithMsgtype(msgtype) => {
                         write!(f, "m.room.message#{msgtype}")
@@ -107,26 +208,54 @@ impl Serialize for Capabilities {
         struct PrintStateEventFilter<'a>(&'a StateEventFilter);
         impl fmt::Display for PrintStateEventFilter<'_> {
             fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-                // TODO: escape `#` as `\#` and `\` as `\\` in event_type
                 match self.0 {
-                    StateEventFilter::WithType(event_type) => write!(f, "{event_type}"),
+                    StateEventFilter::WithType(event_type) => {
+                        write!(f, "{}", escape_event_type(&event_type.to_string()))
+                    }
                     StateEventFilter::WithTypeAndStateKey(event_type, state_key) => {
-                        write!(f, "{event_type}#{state_key}")
+                        write!(f, "{}#{state_key}", escape_event_type(&event_type.to_string()))
                     }
                 }
             }
         }
 
-        let seq_len = self.requires_client as usize + self.read.len() + self.send.len();
+        let seq_len = self.requires_client as usize
+            + self.send_delayed_event as usize
+            + self.update_delayed_event as usize
+            + self.navigate as usize
+            + self.turn_servers as usize
+            + self.upload_file as usize
+            + self.download_file as usize
+            + self.read.len()
+            + self.send.len();
         let mut seq = serializer.serialize_seq(Some(seq_len))?;
 
         if self.requires_client {
             seq.serialize_element(REQUIRES_CLIENT)?;
         }
+        if self.send_delayed_event {
+            seq.serialize_element(SEND_DELAYED_EVENT)?;
+        }
+        if self.update_delayed_event {
+            seq.serialize_element(UPDATE_DELAYED_EVENT)?;
+        }
+        if self.navigate {
+            seq.serialize_element(NAVIGATE)?;
+        }
+        if self.turn_servers {
+            seq.serialize_element(TURN_SERVERS)?;
+        }
+        if self.upload_file {
+            seq.serialize_element(UPLOAD_FILE)?;
+        }
+        if self.download_file {
+            seq.serialize_element(DOWNLOAD_FILE)?;
+        }
         for filter in &self.read {
             let name = match filter {
                 EventFilter::MessageLike(_) => READ_EVENT,
                 EventFilter::State(_) => READ_STATE,
+                EventFilter::ToDevice(_) => READ_TO_DEVICE,
             };
             seq.serialize_element(&format!("{name}:{}", PrintEventFilter(filter)))?;
         }
@@ -134,6 +263,7 @@ impl Serialize for Capabilities {
             let name = match filter {
                 EventFilter::MessageLike(_) => SEND_EVENT,
                 EventFilter::State(_) => SEND_STATE,
+                EventFilter::ToDevice(_) => SEND_TO_DEVICE,
             };
             seq.serialize_element(&format!("{name}:{}", PrintEventFilter(filter)))?;
         }
@@ -149,6 +279,12 @@ impl<'de> Deserialize<'de> for Capabilities {
     {
         enum Permission {
             RequiresClient,
+            SendDelayedEvent,
+            UpdateDelayedEvent,
+            Navigate,
+            TurnServers,
+            UploadFile,
+            DownloadFile,
             Read(EventFilter),
             Send(EventFilter),
             Unknown,
@@ -163,43 +299,79 @@ impl<'de> Deserialize<'de> for Capabilities {
                 if s == REQUIRES_CLIENT {
                     return Ok(Self::RequiresClient);
                 }
+                if s == SEND_DELAYED_EVENT {
+                    return Ok(Self::SendDelayedEvent);
+                }
+                if s == UPDATE_DELAYED_EVENT {
+                    return Ok(Self::UpdateDelayedEvent);
+                }
+                if s == NAVIGATE {
+                    return Ok(Self::Navigate);
+                }
+                if s == TURN_SERVERS {
+                    return Ok(Self::TurnServers);
+                }
+                if s == UPLOAD_FILE {
+                    return Ok(Self::UploadFile);
+                }
+                if s == DOWNLOAD_FILE {
+                    return Ok(Self::DownloadFile);
+                }
 
-                match s.split_once(':') {
-                    Some((READ_EVENT, filter_s)) => Ok(Permission::Read(EventFilter::MessageLike(
-                        parse_message_event_filter(filter_s),
+                let permission = match s.split_once(':') {
+                    Some((READ_EVENT, filter_s)) => parse_message_event_filter(filter_s)
+                        .map(|filter| Permission::Read(EventFilter::MessageLike(filter))),
+                    Some((SEND_EVENT, filter_s)) => parse_message_event_filter(filter_s)
+                        .map(|filter| Permission::Send(EventFilter::MessageLike(filter))),
+                    Some((READ_STATE, filter_s)) => Some(Permission::Read(EventFilter::State(
+                        parse_state_event_filter(filter_s),
                     ))),
-                    Some((SEND_EVENT, filter_s)) => Ok(Permission::Send(EventFilter::MessageLike(
-                        parse_message_event_filter(filter_s),
+                    Some((SEND_STATE, filter_s)) => Some(Permission::Send(EventFilter::State(
+                        parse_state_event_filter(filter_s),
                     ))),
-                    Some((READ_STATE, filter_s)) => {
-                        Ok(Permission::Read(EventFilter::State(parse_state_event_filter(filter_s))))
-                    }
-                    Some((SEND_STATE, filter_s)) => {
-                        Ok(Permission::Send(EventFilter::State(parse_state_event_filter(filter_s))))
-                    }
-                    _ => {
-                        debug!("Unknown capability `{s}`");
-                        Ok(Self::Unknown)
-                    }
-                }
+                    Some((READ_TO_DEVICE, filter_s)) => parse_to_device_event_filter(filter_s)
+                        .map(|filter| Permission::Read(EventFilter::ToDevice(filter))),
+                    Some((SEND_TO_DEVICE, filter_s)) => parse_to_device_event_filter(filter_s)
+                        .map(|filter| Permission::Send(EventFilter::ToDevice(filter))),
+                    _ => None,
+                };
+
+                Ok(permission.unwrap_or_else(|| {
+                    debug!("Unknown capability `{s}`");
+                    Self::Unknown
+                }))
             }
         }
 
-        fn parse_message_event_filter(s: &str) -> MessageLikeEventFilter {
+        fn parse_message_event_filter(s: &str) -> Option<MessageLikeEventFilter> {
             match s.strip_prefix("m.room.message#") {
-                Some(msgtype) => MessageLikeEventFilter::RoomMessageWithMsgtype(msgtype.to_owned()),
-                // TODO: Replace `\\` by `\` and `\#` by `#`, enforce no unescaped `#`
-                None => MessageLikeEventFilter::WithType(s.into()),
+                Some(msgtype) => {
+                    Some(MessageLikeEventFilter::RoomMessageWithMsgtype(msgtype.to_owned()))
+                }
+                None => match split_event_type(s) {
+                    (event_type, None) => {
+                        Some(MessageLikeEventFilter::WithType(event_type.as_str().into()))
+                    }
+                    // Only `m.room.message` can be filtered by anything else than the type.
+                    (_, Some(_)) => None,
+                },
             }
         }
 
         fn parse_state_event_filter(s: &str) -> StateEventFilter {
-            // TODO: Search for un-escaped `#` only, replace `\\` by `\` and `\#` by `#`
-            match s.split_once('#') {
-                Some((event_type, state_key)) => {
-                    StateEventFilter::WithTypeAndStateKey(event_type.into(), state_key.to_owned())
-                }
-                None => StateEventFilter::WithType(s.into()),
+            match split_event_type(s) {
+                (event_type, Some(state_key)) => StateEventFilter::WithTypeAndStateKey(
+                    event_type.as_str().into(),
+                    state_key.to_owned(),
+                ),
+                (event_type, None) => StateEventFilter::WithType(event_type.as_str().into()),
+            }
+        }
+
+        fn parse_to_device_event_filter(s: &str) -> Option<ToDeviceEventFilter> {
+            match split_event_type(s) {
+                (event_type, None) => Some(ToDeviceEventFilter::new(event_type.as_str().into())),
+                (_, Some(_)) => None,
             }
         }
 
@@ -207,6 +379,12 @@ impl<'de> Deserialize<'de> for Capabilities {
         for capability in Vec::<Permission>::deserialize(deserializer)? {
             match capability {
                 Permission::RequiresClient => capabilities.requires_client = true,
+                Permission::SendDelayedEvent => capabilities.send_delayed_event = true,
+                Permission::UpdateDelayedEvent => capabilities.update_delayed_event = true,
+                Permission::Navigate => capabilities.navigate = true,
+                Permission::TurnServers => capabilities.turn_servers = true,
+                Permission::UploadFile => capabilities.upload_file = true,
+                Permission::DownloadFile => capabilities.download_file = true,
                 Permission::Read(filter) => capabilities.read.push(filter),
                 Permission::Send(filter) => capabilities.send.push(filter),
                 // ignore unknown capabilities
@@ -233,7 +411,15 @@ mod tests {
             "org.matrix.msc2762.receive.state_event:m.room.member",
             "org.matrix.msc2762.receive.state_event:org.matrix.msc3401.call.member",
             "org.matrix.msc2762.send.event:org.matrix.rageshake_request",
-            "org.matrix.msc2762.send.state_event:org.matrix.msc3401.call.member#@user:matrix.server"
+            "org.matrix.msc2762.send.state_event:org.matrix.msc3401.call.member#@user:matrix.server",
+            "org.matrix.msc3819.receive.to_device:io.element.call.encryption_keys",
+            "org.matrix.msc3819.send.to_device:io.element.call.encryption_keys",
+            "org.matrix.msc4157.send.delayed_event",
+            "org.matrix.msc4157.update_delayed_event",
+            "org.matrix.msc2931.navigate",
+            "town.robin.msc3846.turn_servers",
+            "org.matrix.msc4039.upload_file",
+            "org.matrix.msc4039.download_file"
         ]"#;
 
         let parsed = serde_json::from_str::<Capabilities>(capabilities_str).unwrap();
@@ -246,6 +432,9 @@ mod tests {
                 EventFilter::State(StateEventFilter::WithType(
                     "org.matrix.msc3401.call.member".into(),
                 )),
+                EventFilter::ToDevice(ToDeviceEventFilter::new(
+                    "io.element.call.encryption_keys".into(),
+                )),
             ],
             send: vec![
                 EventFilter::MessageLike(MessageLikeEventFilter::WithType(
@@ -255,8 +444,17 @@ mod tests {
                     "org.matrix.msc3401.call.member".into(),
                     "@user:matrix.server".into(),
                 )),
+                EventFilter::ToDevice(ToDeviceEventFilter::new(
+                    "io.element.call.encryption_keys".into(),
+                )),
             ],
             requires_client: true,
+            send_delayed_event: true,
+            update_delayed_event: true,
+            navigate: true,
+            turn_servers: true,
+            upload_file: true,
+            download_file: true,
         };
 
         assert_eq!(parsed, expected);
@@ -283,12 +481,56 @@ mod tests {
                     "org.matrix.msc3401.call.member".into(),
                     "@user:matrix.server".into(),
                 )),
+                EventFilter::ToDevice(ToDeviceEventFilter::new(
+                    "io.element.call.encryption_keys".into(),
+                )),
             ],
             requires_client: true,
+            send_delayed_event: true,
+            update_delayed_event: false,
+            navigate: false,
+            turn_servers: true,
+            upload_file: true,
+            download_file: false,
         };
 
         let capabilities_str = serde_json::to_string(&capabilities).unwrap();
         let parsed = serde_json::from_str::<Capabilities>(&capabilities_str).unwrap();
         assert_eq!(parsed, capabilities);
     }
+
+    #[test]
+    fn event_types_are_escaped() {
+        let capabilities = Capabilities {
+            read: vec![EventFilter::MessageLike(MessageLikeEventFilter::WithType(
+                "io.element.custom#type".into(),
+            ))],
+            send: vec![
+                EventFilter::State(StateEventFilter::WithTypeAndStateKey(
+                    "io.element.custom\\#state".into(),
+                    "state#key".into(),
+                )),
+                EventFilter::ToDevice(ToDeviceEventFilter::new(
+                    "io.element.custom#to_device".into(),
+                )),
+            ],
+            ..Default::default()
+        };
+
+        let capabilities_str = serde_json::to_string(&capabilities).unwrap();
+        assert_eq!(
+            capabilities_str,
+            r#"["org.matrix.msc2762.receive.event:io.element.custom\\#type","org.matrix.msc2762.send.state_event:io.element.custom\\\\\\#state#state#key","org.matrix.msc3819.send.to_device:io.element.custom\\#to_device"]"#
+        );
+
+        let parsed = serde_json::from_str::<Capabilities>(&capabilities_str).unwrap();
+        assert_eq!(parsed, capabilities);
+
+        // An unescaped `#` is only allowed before a state key or a msgtype.
+        let parsed = serde_json::from_str::<Capabilities>(
+            r#"["org.matrix.msc2762.send.event:io.element.custom#type"]"#,
+        )
+        .unwrap();
+        assert_eq!(parsed, Capabilities::default());
+    }
 }
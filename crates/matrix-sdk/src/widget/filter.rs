@@ -14,7 +14,7 @@
 
 #![allow(dead_code)] // temporary
 
-use ruma::events::{MessageLikeEventType, StateEventType, TimelineEventType};
+use ruma::events::{MessageLikeEventType, StateEventType, TimelineEventType, ToDeviceEventType};
 use serde::Deserialize;
 
 /// Different kinds of filters for timeline events.
@@ -25,6 +25,8 @@ pub enum EventFilter {
     MessageLike(MessageLikeEventFilter),
     /// Filter for state events.
     State(StateEventFilter),
+    /// Filter for to-device events.
+    ToDevice(ToDeviceEventFilter),
 }
 
 impl EventFilter {
@@ -32,6 +34,8 @@ impl EventFilter {
         match self {
             EventFilter::MessageLike(message_filter) => message_filter.matches(matrix_event),
             EventFilter::State(state_filter) => state_filter.matches(matrix_event),
+            // To-device events are never part of the timeline.
+            EventFilter::ToDevice(_) => false,
         }
     }
 
@@ -54,6 +58,10 @@ impl EventFilter {
             Self::MessageLike(filter) if filter.matches_message_like_event_type(event_type)
         )
     }
+
+    pub(super) fn matches_to_device_event_type(&self, event_type: &ToDeviceEventType) -> bool {
+        matches!(self, Self::ToDevice(filter) if &filter.event_type == event_type)
+    }
 }
 
 /// Filter for message-like events.
@@ -127,6 +135,22 @@ impl StateEventFilter {
     }
 }
 
+/// Filter for to-device events.
+#[derive(Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct ToDeviceEventFilter {
+    /// The type of the to-device events.
+    pub event_type: ToDeviceEventType,
+}
+
+impl ToDeviceEventFilter {
+    /// Create a new `ToDeviceEventFilter` matching to-device events with the
+    /// given `type`.
+    pub fn new(event_type: ToDeviceEventType) -> Self {
+        Self { event_type }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub(super) struct MatrixEventFilterInput {
     #[serde(rename = "type")]
@@ -142,11 +166,13 @@ pub(super) struct MatrixEventContent {
 
 #[cfg(test)]
 mod tests {
-    use ruma::events::{MessageLikeEventType, StateEventType, TimelineEventType};
+    use ruma::events::{
+        MessageLikeEventType, StateEventType, TimelineEventType, ToDeviceEventType,
+    };
 
     use super::{
         EventFilter, MatrixEventContent, MatrixEventFilterInput, MessageLikeEventFilter,
-        StateEventFilter,
+        StateEventFilter, ToDeviceEventFilter,
     };
 
     fn message_event(event_type: TimelineEventType) -> MatrixEventFilterInput {
@@ -372,4 +398,32 @@ mod tests {
             !room_message_filter().matches_message_like_event_type(&MessageLikeEventType::Reaction)
         );
     }
+
+    // Tests against an `io.element.call.encryption_keys` to-device filter
+    fn call_keys_to_device_filter() -> EventFilter {
+        EventFilter::ToDevice(ToDeviceEventFilter::new("io.element.call.encryption_keys".into()))
+    }
+
+    #[test]
+    fn to_device_filter_matches_to_device_event_type() {
+        assert!(call_keys_to_device_filter()
+            .matches_to_device_event_type(&"io.element.call.encryption_keys".into()));
+    }
+
+    #[test]
+    fn to_device_filter_does_not_match_other_to_device_event_type() {
+        assert!(!call_keys_to_device_filter()
+            .matches_to_device_event_type(&ToDeviceEventType::RoomKeyRequest));
+    }
+
+    #[test]
+    fn to_device_filter_does_not_match_timeline_event() {
+        assert!(!call_keys_to_device_filter()
+            .matches(&message_event("io.element.call.encryption_keys".into())));
+    }
+
+    #[test]
+    fn message_like_filter_does_not_match_to_device_event_type() {
+        assert!(!reaction_event_filter().matches_to_device_event_type(&"m.reaction".into()));
+    }
 }
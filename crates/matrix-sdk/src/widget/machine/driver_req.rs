@@ -14,13 +14,22 @@
 
 //! A high-level API for requests that we send to the matrix driver.
 
-use std::marker::PhantomData;
+use std::{collections::BTreeMap, marker::PhantomData};
 
 use ruma::{
-    api::client::account::request_openid_token,
-    events::{AnyTimelineEvent, MessageLikeEventType, StateEventType, TimelineEventType},
-    serde::Raw,
-    OwnedEventId,
+    api::client::{
+        account::request_openid_token,
+        delayed_events::update_delayed_event::{self, unstable::UpdateAction},
+        to_device::send_event_to_device,
+        voip::get_turn_server_info,
+    },
+    events::{
+        AnyTimelineEvent, AnyToDeviceEventContent, MessageLikeEventType, StateEventType,
+        TimelineEventType, ToDeviceEventType,
+    },
+    serde::{Base64, Raw},
+    to_device::DeviceIdOrAllDevices,
+    OwnedEventId, OwnedMxcUri, OwnedUserId,
 };
 use serde::Deserialize;
 use serde_json::value::RawValue as RawJsonValue;
@@ -49,6 +58,24 @@ pub(crate) enum MatrixDriverRequestData {
 
     /// Send matrix event that corresponds to the given description.
     SendMatrixEvent(SendEventRequest),
+
+    /// Send to-device events to the given devices.
+    SendToDeviceEvent(SendToDeviceRequest),
+
+    /// Cancel, restart or send right away a delayed event.
+    UpdateDelayedEvent(UpdateDelayedEventRequest),
+
+    /// Navigate to the given `matrix.to` permalink.
+    Navigate(RequestNavigation),
+
+    /// Get the TURN servers of the homeserver.
+    GetTurnServers,
+
+    /// Upload a file to the media repository.
+    UploadFile(UploadFileRequest),
+
+    /// Download a file from the media repository.
+    DownloadFile(DownloadFileRequest),
 }
 
 /// A handle to a pending `toWidget` request.
@@ -160,6 +187,10 @@ pub(crate) struct ReadMessageLikeEventRequest {
 
     /// The maximum number of events to return.
     pub(crate) limit: u32,
+
+    /// The ID of the event to stop reading at, if any. This event is not
+    /// returned.
+    pub(crate) since: Option<OwnedEventId>,
 }
 
 impl From<ReadMessageLikeEventRequest> for MatrixDriverRequestData {
@@ -217,6 +248,9 @@ pub(crate) struct SendEventRequest {
     pub(crate) state_key: Option<String>,
     /// Raw content of an event.
     pub(crate) content: Box<RawJsonValue>,
+    /// The delay in milliseconds after which the homeserver should send the
+    /// event, if it should be sent as a delayed event.
+    pub(crate) delay: Option<u64>,
 }
 
 impl From<SendEventRequest> for MatrixDriverRequestData {
@@ -226,10 +260,20 @@ impl From<SendEventRequest> for MatrixDriverRequestData {
 }
 
 impl MatrixDriverRequest for SendEventRequest {
-    type Response = OwnedEventId;
+    type Response = SendEventOutcome;
 }
 
-impl FromMatrixDriverResponse for OwnedEventId {
+/// What happened to an event sent with a [`SendEventRequest`].
+#[derive(Clone, Debug)]
+pub(crate) enum SendEventOutcome {
+    /// The event was sent to the room right away.
+    Sent(OwnedEventId),
+    /// The event was scheduled to be sent by the homeserver later, it can be
+    /// updated with the given delay ID.
+    Delayed(String),
+}
+
+impl FromMatrixDriverResponse for SendEventOutcome {
     fn from_response(ev: MatrixDriverResponse) -> Option<Self> {
         match ev {
             MatrixDriverResponse::MatrixEventSent(response) => Some(response),
@@ -240,3 +284,187 @@ impl FromMatrixDriverResponse for OwnedEventId {
         }
     }
 }
+
+/// Ask the client to send to-device events to the given devices.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct SendToDeviceRequest {
+    /// The type of the to-device events.
+    #[serde(rename = "type")]
+    pub(crate) event_type: ToDeviceEventType,
+    /// Whether the events must be encrypted for each device with Olm.
+    #[serde(default)]
+    pub(crate) encrypted: bool,
+    /// The contents of the events, for each user and device.
+    pub(crate) messages:
+        BTreeMap<OwnedUserId, BTreeMap<DeviceIdOrAllDevices, Raw<AnyToDeviceEventContent>>>,
+}
+
+impl From<SendToDeviceRequest> for MatrixDriverRequestData {
+    fn from(value: SendToDeviceRequest) -> Self {
+        MatrixDriverRequestData::SendToDeviceEvent(value)
+    }
+}
+
+impl MatrixDriverRequest for SendToDeviceRequest {
+    type Response = send_event_to_device::v3::Response;
+}
+
+impl FromMatrixDriverResponse for send_event_to_device::v3::Response {
+    fn from_response(ev: MatrixDriverResponse) -> Option<Self> {
+        match ev {
+            MatrixDriverResponse::ToDeviceSent(response) => Some(response),
+            _ => {
+                error!("bug in MatrixDriver, received wrong event response");
+                None
+            }
+        }
+    }
+}
+
+/// Ask the client to cancel, restart or send right away a delayed event.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct UpdateDelayedEventRequest {
+    /// What to do with the delayed event.
+    pub(crate) action: UpdateAction,
+    /// The ID of the delayed event, returned when it was scheduled.
+    pub(crate) delay_id: String,
+}
+
+impl From<UpdateDelayedEventRequest> for MatrixDriverRequestData {
+    fn from(value: UpdateDelayedEventRequest) -> Self {
+        MatrixDriverRequestData::UpdateDelayedEvent(value)
+    }
+}
+
+impl MatrixDriverRequest for UpdateDelayedEventRequest {
+    type Response = update_delayed_event::unstable::Response;
+}
+
+impl FromMatrixDriverResponse for update_delayed_event::unstable::Response {
+    fn from_response(ev: MatrixDriverResponse) -> Option<Self> {
+        match ev {
+            MatrixDriverResponse::DelayedEventUpdated(response) => Some(response),
+            _ => {
+                error!("bug in MatrixDriver, received wrong event response");
+                None
+            }
+        }
+    }
+}
+
+/// Ask the client to navigate to a `matrix.to` permalink.
+#[derive(Clone, Debug)]
+pub(crate) struct RequestNavigation {
+    /// The permalink to navigate to.
+    pub(crate) uri: String,
+}
+
+impl From<RequestNavigation> for MatrixDriverRequestData {
+    fn from(value: RequestNavigation) -> Self {
+        MatrixDriverRequestData::Navigate(value)
+    }
+}
+
+impl MatrixDriverRequest for RequestNavigation {
+    type Response = ();
+}
+
+impl FromMatrixDriverResponse for () {
+    fn from_response(ev: MatrixDriverResponse) -> Option<Self> {
+        match ev {
+            MatrixDriverResponse::Navigated => Some(()),
+            _ => {
+                error!("bug in MatrixDriver, received wrong event response");
+                None
+            }
+        }
+    }
+}
+
+/// Ask the client for the TURN servers of the homeserver.
+#[derive(Debug)]
+pub(crate) struct RequestTurnServers;
+
+impl From<RequestTurnServers> for MatrixDriverRequestData {
+    fn from(_: RequestTurnServers) -> Self {
+        MatrixDriverRequestData::GetTurnServers
+    }
+}
+
+impl MatrixDriverRequest for RequestTurnServers {
+    type Response = get_turn_server_info::v3::Response;
+}
+
+impl FromMatrixDriverResponse for get_turn_server_info::v3::Response {
+    fn from_response(ev: MatrixDriverResponse) -> Option<Self> {
+        match ev {
+            MatrixDriverResponse::TurnServersReceived(response) => Some(response),
+            _ => {
+                error!("bug in MatrixDriver, received wrong event response");
+                None
+            }
+        }
+    }
+}
+
+/// Ask the client to upload a file to the media repository and return its
+/// content URI as a response.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct UploadFileRequest {
+    /// The content of the file, encoded in base64.
+    pub(crate) file: Base64,
+    /// The MIME type of the file, `application/octet-stream` if it is not set.
+    pub(crate) content_type: Option<String>,
+}
+
+impl From<UploadFileRequest> for MatrixDriverRequestData {
+    fn from(value: UploadFileRequest) -> Self {
+        MatrixDriverRequestData::UploadFile(value)
+    }
+}
+
+impl MatrixDriverRequest for UploadFileRequest {
+    type Response = OwnedMxcUri;
+}
+
+impl FromMatrixDriverResponse for OwnedMxcUri {
+    fn from_response(ev: MatrixDriverResponse) -> Option<Self> {
+        match ev {
+            MatrixDriverResponse::FileUploaded(response) => Some(response),
+            _ => {
+                error!("bug in MatrixDriver, received wrong event response");
+                None
+            }
+        }
+    }
+}
+
+/// Ask the client to download a file from the media repository and return its
+/// content as a response.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct DownloadFileRequest {
+    /// The content URI of the file.
+    pub(crate) content_uri: OwnedMxcUri,
+}
+
+impl From<DownloadFileRequest> for MatrixDriverRequestData {
+    fn from(value: DownloadFileRequest) -> Self {
+        MatrixDriverRequestData::DownloadFile(value)
+    }
+}
+
+impl MatrixDriverRequest for DownloadFileRequest {
+    type Response = Vec<u8>;
+}
+
+impl FromMatrixDriverResponse for Vec<u8> {
+    fn from_response(ev: MatrixDriverResponse) -> Option<Self> {
+        match ev {
+            MatrixDriverResponse::FileDownloaded(response) => Some(response),
+            _ => {
+                error!("bug in MatrixDriver, received wrong event response");
+                None
+            }
+        }
+    }
+}
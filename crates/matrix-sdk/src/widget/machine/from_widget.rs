@@ -16,12 +16,15 @@ use std::fmt;
 
 use ruma::{
     events::{AnyTimelineEvent, MessageLikeEventType, StateEventType},
-    serde::Raw,
-    OwnedEventId, RoomId,
+    serde::{Base64, Raw},
+    OwnedEventId, OwnedMxcUri, RoomId,
 };
 use serde::{Deserialize, Serialize};
 
-use super::SendEventRequest;
+use super::{
+    DownloadFileRequest, SendEventRequest, SendToDeviceRequest, UpdateDelayedEventRequest,
+    UploadFileRequest,
+};
 use crate::widget::StateKeySelector;
 
 #[derive(Deserialize)]
@@ -34,6 +37,17 @@ pub(super) enum FromWidgetRequest {
     #[serde(rename = "org.matrix.msc2876.read_events")]
     ReadEvent(ReadEventRequest),
     SendEvent(SendEventRequest),
+    SendToDevice(SendToDeviceRequest),
+    #[serde(rename = "org.matrix.msc4157.update_delayed_event")]
+    UpdateDelayedEvent(UpdateDelayedEventRequest),
+    #[serde(rename = "org.matrix.msc2931.navigate")]
+    Navigate(NavigateRequest),
+    WatchTurnServers {},
+    UnwatchTurnServers {},
+    #[serde(rename = "org.matrix.msc4039.upload_file", alias = "m.upload_file")]
+    UploadFile(UploadFileRequest),
+    #[serde(rename = "org.matrix.msc4039.download_file", alias = "m.download_file")]
+    DownloadFile(DownloadFileRequest),
 }
 
 #[derive(Serialize)]
@@ -65,7 +79,11 @@ impl SupportedApiVersionsResponse {
                 ApiVersion::V0_0_2,
                 ApiVersion::MSC2762,
                 ApiVersion::MSC2871,
+                ApiVersion::MSC2931,
                 ApiVersion::MSC3819,
+                ApiVersion::MSC3846,
+                ApiVersion::MSC4039,
+                ApiVersion::MSC4157,
             ],
         }
     }
@@ -109,6 +127,14 @@ pub(super) enum ApiVersion {
     /// Supports access to the TURN servers.
     #[serde(rename = "town.robin.msc3846")]
     MSC3846,
+
+    /// Supports uploading and downloading files.
+    #[serde(rename = "org.matrix.msc4039")]
+    MSC4039,
+
+    /// Supports sending and updating delayed events.
+    #[serde(rename = "org.matrix.msc4157")]
+    MSC4157,
 }
 
 #[derive(Deserialize)]
@@ -124,6 +150,7 @@ pub(super) enum ReadEventRequest {
         #[serde(rename = "type")]
         event_type: MessageLikeEventType,
         limit: Option<u32>,
+        since: Option<OwnedEventId>,
     },
 }
 
@@ -135,5 +162,23 @@ pub(super) struct ReadEventResponse {
 #[derive(Serialize)]
 pub(super) struct SendEventResponse<'a> {
     pub(super) room_id: &'a RoomId,
-    pub(super) event_id: OwnedEventId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) event_id: Option<OwnedEventId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) delay_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct NavigateRequest {
+    pub(super) uri: String,
+}
+
+#[derive(Serialize)]
+pub(super) struct UploadFileResponse {
+    pub(super) content_uri: OwnedMxcUri,
+}
+
+#[derive(Serialize)]
+pub(super) struct DownloadFileResponse {
+    pub(super) file: Base64,
 }
@@ -13,13 +13,21 @@
 // limitations under the License.
 
 use ruma::{
-    api::client::account::request_openid_token, events::AnyTimelineEvent, serde::Raw, OwnedEventId,
+    api::client::{
+        account::request_openid_token, delayed_events::update_delayed_event,
+        to_device::send_event_to_device, voip::get_turn_server_info,
+    },
+    events::{AnyTimelineEvent, AnyToDeviceEvent},
+    serde::Raw,
+    OwnedMxcUri,
 };
 use serde::{de, Deserialize, Deserializer};
 use serde_json::value::RawValue as RawJsonValue;
 use uuid::Uuid;
 
-use super::{from_widget::FromWidgetRequest, to_widget::ToWidgetResponse};
+use super::{
+    driver_req::SendEventOutcome, from_widget::FromWidgetRequest, to_widget::ToWidgetResponse,
+};
 use crate::widget::Capabilities;
 
 /// Incoming event that the client API must process.
@@ -41,6 +49,18 @@ pub(crate) enum IncomingMessage {
     /// This means that the machine previously subscribed to some events
     /// (`Action::Subscribe` request).
     MatrixEventReceived(Raw<AnyTimelineEvent>),
+
+    /// The `MatrixDriver` notified the `WidgetMachine` of a new to-device
+    /// event.
+    ///
+    /// Like for matrix events, this means that the machine previously
+    /// subscribed to events.
+    ToDeviceEventReceived(Raw<AnyToDeviceEvent>),
+
+    /// The delay of a previous `Action::ScheduleTurnServersRefresh` elapsed,
+    /// the TURN servers must be fetched again if the widget still watches
+    /// them.
+    TurnServersRefreshDue,
 }
 
 pub(crate) enum MatrixDriverResponse {
@@ -54,9 +74,28 @@ pub(crate) enum MatrixDriverResponse {
     /// Client read some matrix event(s).
     /// A response to an `Action::ReadMatrixEvent` commands.
     MatrixEventRead(Vec<Raw<AnyTimelineEvent>>),
-    /// Client sent some matrix event. The response contains the event ID.
+    /// Client sent some matrix event. The response contains the event ID, or
+    /// the delay ID if the event was scheduled for later.
     /// A response to an `Action::SendMatrixEvent` command.
-    MatrixEventSent(OwnedEventId),
+    MatrixEventSent(SendEventOutcome),
+    /// Client sent some to-device events.
+    /// A response to an `Action::SendToDeviceEvent` command.
+    ToDeviceSent(send_event_to_device::v3::Response),
+    /// Client updated a delayed event.
+    /// A response to an `Action::UpdateDelayedEvent` command.
+    DelayedEventUpdated(update_delayed_event::unstable::Response),
+    /// Client navigated to a permalink.
+    /// A response to an `Action::Navigate` command.
+    Navigated,
+    /// Client received the TURN servers of the homeserver.
+    /// A response to an `Action::GetTurnServers` command.
+    TurnServersReceived(get_turn_server_info::v3::Response),
+    /// Client uploaded a file. The response contains its content URI.
+    /// A response to an `Action::UploadFile` command.
+    FileUploaded(OwnedMxcUri),
+    /// Client downloaded a file. The response contains its content.
+    /// A response to an `Action::DownloadFile` command.
+    FileDownloaded(Vec<u8>),
 }
 
 pub(super) struct IncomingWidgetMessage {
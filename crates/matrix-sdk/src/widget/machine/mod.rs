@@ -20,7 +20,7 @@ use std::{fmt, iter, time::Duration};
 
 use indexmap::IndexMap;
 use ruma::{
-    serde::{JsonObject, Raw},
+    serde::{Base64, JsonObject, Raw},
     OwnedRoomId,
 };
 use serde::Serialize;
@@ -31,18 +31,20 @@ use uuid::Uuid;
 use self::{
     driver_req::{
         AcquireCapabilities, MatrixDriverRequest, MatrixDriverRequestHandle,
-        ReadMessageLikeEventRequest, RequestOpenId,
+        ReadMessageLikeEventRequest, RequestNavigation, RequestOpenId, RequestTurnServers,
     },
     from_widget::{
-        FromWidgetErrorResponse, FromWidgetRequest, ReadEventRequest, ReadEventResponse,
-        SendEventResponse, SupportedApiVersionsResponse,
+        DownloadFileResponse, FromWidgetErrorResponse, FromWidgetRequest, NavigateRequest,
+        ReadEventRequest, ReadEventResponse, SendEventResponse, SupportedApiVersionsResponse,
+        UploadFileResponse,
     },
     incoming::{IncomingWidgetMessage, IncomingWidgetMessageKind},
     openid::{OpenIdResponse, OpenIdState},
     pending::{PendingRequests, RequestLimits},
     to_widget::{
-        NotifyCapabilitiesChanged, NotifyNewMatrixEvent, NotifyOpenIdChanged, RequestCapabilities,
-        ToWidgetRequest, ToWidgetRequestHandle, ToWidgetResponse,
+        NotifyCapabilitiesChanged, NotifyNewMatrixEvent, NotifyNewToDeviceEvent,
+        NotifyOpenIdChanged, NotifyTurnServersChanged, RequestCapabilities, ToWidgetRequest,
+        ToWidgetRequestHandle, ToWidgetResponse,
     },
 };
 #[cfg(doc)]
@@ -63,7 +65,10 @@ mod tests;
 mod to_widget;
 
 pub(crate) use self::{
-    driver_req::{MatrixDriverRequestData, ReadStateEventRequest, SendEventRequest},
+    driver_req::{
+        DownloadFileRequest, MatrixDriverRequestData, ReadStateEventRequest, SendEventOutcome,
+        SendEventRequest, SendToDeviceRequest, UpdateDelayedEventRequest, UploadFileRequest,
+    },
     incoming::{IncomingMessage, MatrixDriverResponse},
 };
 
@@ -97,6 +102,20 @@ pub(crate) enum Action {
     /// `Subscribe`.
     #[allow(dead_code)]
     Unsubscribe,
+
+    /// Send [`IncomingMessage::TurnServersRefreshDue`] back to the state
+    /// machine once the given delay has elapsed, to fetch the TURN servers
+    /// again before their credentials expire.
+    ScheduleTurnServersRefresh(Duration),
+}
+
+/// The minimum delay before fetching the TURN servers again.
+const MIN_TURN_SERVERS_REFRESH_DELAY: Duration = Duration::from_secs(30);
+
+/// The delay before fetching the TURN servers again, so their credentials are
+/// refreshed a bit before they expire after `ttl`.
+fn turn_servers_refresh_delay(ttl: Duration) -> Duration {
+    (ttl * 9 / 10).max(MIN_TURN_SERVERS_REFRESH_DELAY)
 }
 
 /// No I/O state machine.
@@ -108,6 +127,8 @@ pub(crate) struct WidgetMachine {
     pending_to_widget_requests: PendingRequests<ToWidgetRequestMeta>,
     pending_matrix_driver_requests: PendingRequests<MatrixDriverRequestMeta>,
     capabilities: CapabilitiesState,
+    turn_servers_watched: bool,
+    turn_servers_refresh_scheduled: bool,
 }
 
 impl WidgetMachine {
@@ -131,6 +152,8 @@ impl WidgetMachine {
             pending_to_widget_requests: PendingRequests::new(limits.clone()),
             pending_matrix_driver_requests: PendingRequests::new(limits),
             capabilities: CapabilitiesState::Unset,
+            turn_servers_watched: false,
+            turn_servers_refresh_scheduled: false,
         };
 
         let actions = (!init_on_content_load).then(|| machine.negotiate_capabilities());
@@ -162,6 +185,29 @@ impl WidgetMachine {
                     })
                     .unwrap_or_default()
             }
+            IncomingMessage::ToDeviceEventReceived(event) => {
+                let CapabilitiesState::Negotiated(capabilities) = &self.capabilities else {
+                    error!("Received to-device event before capabilities negotiation");
+                    return Vec::new();
+                };
+
+                capabilities
+                    .raw_to_device_event_matches_read_filter(&event)
+                    .then(|| {
+                        let action = self.send_to_widget_request(NotifyNewToDeviceEvent(event)).1;
+                        action.map(|a| vec![a]).unwrap_or_default()
+                    })
+                    .unwrap_or_default()
+            }
+            IncomingMessage::TurnServersRefreshDue => {
+                self.turn_servers_refresh_scheduled = false;
+
+                if !self.turn_servers_watched {
+                    return Vec::new();
+                }
+
+                self.fetch_turn_servers(None).into_iter().collect()
+            }
         }
     }
 
@@ -226,6 +272,40 @@ impl WidgetMachine {
                 .map(|a| vec![a])
                 .unwrap_or_default(),
 
+            FromWidgetRequest::SendToDevice(req) => self
+                .process_send_to_device_request(req, raw_request)
+                .map(|a| vec![a])
+                .unwrap_or_default(),
+
+            FromWidgetRequest::UpdateDelayedEvent(req) => self
+                .process_update_delayed_event_request(req, raw_request)
+                .map(|a| vec![a])
+                .unwrap_or_default(),
+
+            FromWidgetRequest::Navigate(req) => {
+                self.process_navigate_request(req, raw_request).map(|a| vec![a]).unwrap_or_default()
+            }
+
+            FromWidgetRequest::WatchTurnServers {} => self
+                .process_watch_turn_servers_request(raw_request)
+                .map(|a| vec![a])
+                .unwrap_or_default(),
+
+            FromWidgetRequest::UnwatchTurnServers {} => {
+                self.turn_servers_watched = false;
+                vec![self.send_from_widget_response(raw_request, JsonObject::new())]
+            }
+
+            FromWidgetRequest::UploadFile(req) => self
+                .process_upload_file_request(req, raw_request)
+                .map(|a| vec![a])
+                .unwrap_or_default(),
+
+            FromWidgetRequest::DownloadFile(req) => self
+                .process_download_file_request(req, raw_request)
+                .map(|a| vec![a])
+                .unwrap_or_default(),
+
             FromWidgetRequest::GetOpenId {} => {
                 let (request, request_action) = self.send_matrix_driver_request(RequestOpenId);
                 request.then(|res, machine| {
@@ -258,15 +338,16 @@ impl WidgetMachine {
         };
 
         match request {
-            ReadEventRequest::ReadMessageLikeEvent { event_type, limit } => {
+            ReadEventRequest::ReadMessageLikeEvent { event_type, limit, since } => {
                 let filter_fn = |f: &EventFilter| f.matches_message_like_event_type(&event_type);
                 if !capabilities.read.iter().any(filter_fn) {
                     return Some(self.send_from_widget_error_response(raw_request, "Not allowed"));
                 }
 
                 const DEFAULT_EVENT_LIMIT: u32 = 50;
-                let limit = limit.unwrap_or(DEFAULT_EVENT_LIMIT);
-                let request = ReadMessageLikeEventRequest { event_type, limit };
+                const MAX_EVENT_LIMIT: u32 = 500;
+                let limit = limit.unwrap_or(DEFAULT_EVENT_LIMIT).min(MAX_EVENT_LIMIT);
+                let request = ReadMessageLikeEventRequest { event_type, limit, since };
                 let (request, action) = self.send_matrix_driver_request(request);
                 request.then(|result, machine| {
                     let response = result.and_then(|mut events| {
@@ -342,10 +423,220 @@ impl WidgetMachine {
             return Some(self.send_from_widget_error_response(raw_request, "Not allowed"));
         }
 
+        if request.delay.is_some() && !capabilities.send_delayed_event {
+            let text = "Not allowed to send delayed events";
+            return Some(self.send_from_widget_error_response(raw_request, text));
+        }
+
         let (request, action) = self.send_matrix_driver_request(request);
         request.then(|result, machine| {
             let room_id = &machine.room_id;
-            let response = result.map(|event_id| SendEventResponse { event_id, room_id });
+            let response = result.map(|outcome| match outcome {
+                SendEventOutcome::Sent(event_id) => {
+                    SendEventResponse { room_id, event_id: Some(event_id), delay_id: None }
+                }
+                SendEventOutcome::Delayed(delay_id) => {
+                    SendEventResponse { room_id, event_id: None, delay_id: Some(delay_id) }
+                }
+            });
+            vec![machine.send_from_widget_result_response(raw_request, response)]
+        });
+        action
+    }
+
+    fn process_send_to_device_request(
+        &mut self,
+        request: SendToDeviceRequest,
+        raw_request: Raw<FromWidgetRequest>,
+    ) -> Option<Action> {
+        let CapabilitiesState::Negotiated(capabilities) = &self.capabilities else {
+            let text = "Received send to-device request before capabilities were negotiated";
+            return Some(self.send_from_widget_error_response(raw_request, text));
+        };
+
+        let filter_fn = |f: &EventFilter| f.matches_to_device_event_type(&request.event_type);
+        if !capabilities.send.iter().any(filter_fn) {
+            return Some(self.send_from_widget_error_response(raw_request, "Not allowed"));
+        }
+
+        let (request, action) = self.send_matrix_driver_request(request);
+        request.then(|result, machine| {
+            let response = result.map(|_| JsonObject::new());
+            vec![machine.send_from_widget_result_response(raw_request, response)]
+        });
+        action
+    }
+
+    fn process_update_delayed_event_request(
+        &mut self,
+        request: UpdateDelayedEventRequest,
+        raw_request: Raw<FromWidgetRequest>,
+    ) -> Option<Action> {
+        let CapabilitiesState::Negotiated(capabilities) = &self.capabilities else {
+            let text = "Received update delayed event request before capabilities were negotiated";
+            return Some(self.send_from_widget_error_response(raw_request, text));
+        };
+
+        if !capabilities.update_delayed_event {
+            return Some(self.send_from_widget_error_response(raw_request, "Not allowed"));
+        }
+
+        let (request, action) = self.send_matrix_driver_request(request);
+        request.then(|result, machine| {
+            let response = result.map(|_| JsonObject::new());
+            vec![machine.send_from_widget_result_response(raw_request, response)]
+        });
+        action
+    }
+
+    fn process_navigate_request(
+        &mut self,
+        request: NavigateRequest,
+        raw_request: Raw<FromWidgetRequest>,
+    ) -> Option<Action> {
+        let CapabilitiesState::Negotiated(capabilities) = &self.capabilities else {
+            let text = "Received navigate request before capabilities were negotiated";
+            return Some(self.send_from_widget_error_response(raw_request, text));
+        };
+
+        if !capabilities.navigate {
+            return Some(self.send_from_widget_error_response(raw_request, "Not allowed"));
+        }
+
+        let (request, action) =
+            self.send_matrix_driver_request(RequestNavigation { uri: request.uri });
+        request.then(|result, machine| {
+            let response = result.map(|()| JsonObject::new());
+            vec![machine.send_from_widget_result_response(raw_request, response)]
+        });
+        action
+    }
+
+    fn process_watch_turn_servers_request(
+        &mut self,
+        raw_request: Raw<FromWidgetRequest>,
+    ) -> Option<Action> {
+        let CapabilitiesState::Negotiated(capabilities) = &self.capabilities else {
+            let text = "Received watch TURN servers request before capabilities were negotiated";
+            return Some(self.send_from_widget_error_response(raw_request, text));
+        };
+
+        if !capabilities.turn_servers {
+            return Some(self.send_from_widget_error_response(raw_request, "Not allowed"));
+        }
+
+        self.turn_servers_watched = true;
+        self.fetch_turn_servers(Some(raw_request))
+    }
+
+    /// Fetch the TURN servers and send them to the widget if it is still
+    /// watching them, then schedule the next refresh according to the `ttl`
+    /// of the credentials.
+    ///
+    /// `raw_request` is the `watch_turn_servers` request of the widget to
+    /// respond to, or `None` if the TURN servers are refreshed.
+    fn fetch_turn_servers(
+        &mut self,
+        raw_request: Option<Raw<FromWidgetRequest>>,
+    ) -> Option<Action> {
+        let (request, action) = self.send_matrix_driver_request(RequestTurnServers);
+        request.then(|result, machine| {
+            let mut actions = Vec::new();
+
+            match result {
+                Ok(turn_servers) => {
+                    if let Some(raw_request) = raw_request {
+                        actions.push(
+                            machine.send_from_widget_response(raw_request, JsonObject::new()),
+                        );
+                    }
+
+                    // The widget might have stopped watching in the meantime.
+                    if !machine.turn_servers_watched {
+                        return actions;
+                    }
+
+                    let delay = turn_servers_refresh_delay(turn_servers.ttl);
+                    let update = NotifyTurnServersChanged {
+                        uris: turn_servers.uris,
+                        username: turn_servers.username,
+                        password: turn_servers.password,
+                    };
+                    actions.extend(machine.send_to_widget_request(update).1);
+                    actions.extend(machine.schedule_turn_servers_refresh(delay));
+                }
+                Err(msg) => match raw_request {
+                    Some(raw_request) => {
+                        machine.turn_servers_watched = false;
+                        actions.push(machine.send_from_widget_error_response(raw_request, msg));
+                    }
+                    None => {
+                        // Keep the current credentials, they might still be valid for a while.
+                        warn!("Failed to refresh the TURN servers: {msg}");
+
+                        if machine.turn_servers_watched {
+                            actions.extend(
+                                machine
+                                    .schedule_turn_servers_refresh(MIN_TURN_SERVERS_REFRESH_DELAY),
+                            );
+                        }
+                    }
+                },
+            }
+
+            actions
+        });
+        action
+    }
+
+    fn schedule_turn_servers_refresh(&mut self, delay: Duration) -> Option<Action> {
+        if self.turn_servers_refresh_scheduled {
+            return None;
+        }
+
+        self.turn_servers_refresh_scheduled = true;
+        Some(Action::ScheduleTurnServersRefresh(delay))
+    }
+
+    fn process_upload_file_request(
+        &mut self,
+        request: UploadFileRequest,
+        raw_request: Raw<FromWidgetRequest>,
+    ) -> Option<Action> {
+        let CapabilitiesState::Negotiated(capabilities) = &self.capabilities else {
+            let text = "Received upload file request before capabilities were negotiated";
+            return Some(self.send_from_widget_error_response(raw_request, text));
+        };
+
+        if !capabilities.upload_file {
+            return Some(self.send_from_widget_error_response(raw_request, "Not allowed"));
+        }
+
+        let (request, action) = self.send_matrix_driver_request(request);
+        request.then(|result, machine| {
+            let response = result.map(|content_uri| UploadFileResponse { content_uri });
+            vec![machine.send_from_widget_result_response(raw_request, response)]
+        });
+        action
+    }
+
+    fn process_download_file_request(
+        &mut self,
+        request: DownloadFileRequest,
+        raw_request: Raw<FromWidgetRequest>,
+    ) -> Option<Action> {
+        let CapabilitiesState::Negotiated(capabilities) = &self.capabilities else {
+            let text = "Received download file request before capabilities were negotiated";
+            return Some(self.send_from_widget_error_response(raw_request, text));
+        };
+
+        if !capabilities.download_file {
+            return Some(self.send_from_widget_error_response(raw_request, "Not allowed"));
+        }
+
+        let (request, action) = self.send_matrix_driver_request(request);
+        request.then(|result, machine| {
+            let response = result.map(|file| DownloadFileResponse { file: Base64::new(file) });
             vec![machine.send_from_widget_result_response(raw_request, response)]
         });
         action
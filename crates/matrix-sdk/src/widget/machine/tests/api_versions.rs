@@ -53,7 +53,11 @@ fn get_supported_api_versions() {
                     "0.0.2",
                     "org.matrix.msc2762",
                     "org.matrix.msc2871",
+                    "org.matrix.msc2931",
                     "org.matrix.msc3819",
+                    "town.robin.msc3846",
+                    "org.matrix.msc4039",
+                    "org.matrix.msc4157",
                 ]
             },
         }),
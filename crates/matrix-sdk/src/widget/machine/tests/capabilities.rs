@@ -145,7 +145,16 @@ pub(super) fn assert_capabilities_dance(
 ) {
     let capability =
         capability_str.unwrap_or("org.matrix.msc2762.receive.state_event:m.room.member");
+    assert_capabilities_dance_with(machine, actions, &[capability]);
+}
 
+/// Performs a capability "dance" for the given list of capabilities, that must
+/// be in the order in which they are serialized.
+pub(super) fn assert_capabilities_dance_with(
+    machine: &mut WidgetMachine,
+    actions: Vec<Action>,
+    capabilities: &[&str],
+) {
     // Ask widget to provide desired capabilities.
     let actions = {
         let [action]: [Action; 1] = actions.try_into().unwrap();
@@ -168,7 +177,7 @@ pub(super) fn assert_capabilities_dance(
             "action": "capabilities",
             "data": {},
             "response": {
-                "capabilities": [capability],
+                "capabilities": capabilities,
             },
         })))
     };
@@ -182,18 +191,22 @@ pub(super) fn assert_capabilities_dance(
                 data: MatrixDriverRequestData::AcquireCapabilities(data)
             } = action
         );
-        let capabilities = data.desired_capabilities;
-        assert_eq!(capabilities, from_value(json!([capability])).unwrap());
+        let desired = data.desired_capabilities;
+        assert_eq!(desired, from_value(json!(capabilities)).unwrap());
 
-        let response = Ok(MatrixDriverResponse::CapabilitiesAcquired(capabilities));
+        let response = Ok(MatrixDriverResponse::CapabilitiesAcquired(desired));
         let message = IncomingMessage::MatrixDriverResponse { request_id, response };
         machine.process(message)
     };
 
     // We get the `Subscribe` command if we requested some reading capabilities.
-    if ["org.matrix.msc2762.receive.state_event", "org.matrix.msc2762.receive.event"]
-        .into_iter()
-        .any(|c| capability.starts_with(c))
+    if [
+        "org.matrix.msc2762.receive.state_event",
+        "org.matrix.msc2762.receive.event",
+        "org.matrix.msc3819.receive.to_device",
+    ]
+    .into_iter()
+    .any(|prefix| capabilities.iter().any(|c| c.starts_with(prefix)))
     {
         let action = actions.remove(0);
         assert_matches!(action, Action::Subscribe);
@@ -211,8 +224,8 @@ pub(super) fn assert_capabilities_dance(
                 "widgetId": WIDGET_ID,
                 "action": "notify_capabilities",
                 "data": {
-                    "requested": [capability],
-                    "approved": [capability],
+                    "requested": capabilities,
+                    "approved": capabilities,
                 },
             }),
         );
@@ -223,8 +236,8 @@ pub(super) fn assert_capabilities_dance(
             "requestId": request_id,
             "action": "notify_capabilities",
             "data": {
-                "requested": [capability],
-                "approved": [capability],
+                "requested": capabilities,
+                "approved": capabilities,
             },
             "response": {},
         })));
@@ -0,0 +1,208 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use assert_matches2::assert_let;
+use ruma::{
+    api::client::delayed_events::update_delayed_event::{self, unstable::UpdateAction},
+    owned_room_id,
+};
+use serde_json::json;
+
+use super::{
+    capabilities::{assert_capabilities_dance, assert_capabilities_dance_with},
+    parse_msg, WIDGET_ID,
+};
+use crate::widget::machine::{
+    Action, IncomingMessage, MatrixDriverRequestData, MatrixDriverResponse, SendEventOutcome,
+    WidgetMachine,
+};
+
+#[test]
+fn send_delayed_event_request_handling_works() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance_with(
+        &mut machine,
+        actions,
+        &[
+            "org.matrix.msc4157.send.delayed_event",
+            "org.matrix.msc2762.send.state_event:org.matrix.msc3401.call.member",
+        ],
+    );
+
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "send-delayed-event-request-id",
+        "action": "send_event",
+        "data": {
+            "type": "org.matrix.msc3401.call.member",
+            "state_key": "_@alice:example.org_ALICEDEVICE",
+            "content": {},
+            "delay": 10000,
+        },
+    })));
+
+    let actions = {
+        let [action]: [Action; 1] = actions.try_into().unwrap();
+        assert_let!(
+            Action::MatrixDriverRequest {
+                request_id,
+                data: MatrixDriverRequestData::SendMatrixEvent(request)
+            } = action
+        );
+        assert_eq!(request.state_key.as_deref(), Some("_@alice:example.org_ALICEDEVICE"));
+        assert_eq!(request.delay, Some(10000));
+
+        machine.process(IncomingMessage::MatrixDriverResponse {
+            request_id,
+            response: Ok(MatrixDriverResponse::MatrixEventSent(SendEventOutcome::Delayed(
+                "delay-id".to_owned(),
+            ))),
+        })
+    };
+
+    // The widget gets the delay ID to be able to update the delayed event.
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, request_id) = parse_msg(&msg);
+    assert_eq!(request_id, "send-delayed-event-request-id");
+    assert_eq!(
+        msg["response"],
+        json!({
+            "room_id": "!a98sd12bjh:example.org",
+            "delay_id": "delay-id",
+        }),
+    );
+}
+
+#[test]
+fn send_delayed_event_request_without_capability() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(
+        &mut machine,
+        actions,
+        Some("org.matrix.msc2762.send.state_event:org.matrix.msc3401.call.member"),
+    );
+
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "send-delayed-event-request-id",
+        "action": "send_event",
+        "data": {
+            "type": "org.matrix.msc3401.call.member",
+            "state_key": "_@alice:example.org_ALICEDEVICE",
+            "content": {},
+            "delay": 10000,
+        },
+    })));
+
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, request_id) = parse_msg(&msg);
+    assert_eq!(request_id, "send-delayed-event-request-id");
+    assert_eq!(
+        msg["response"]["error"]["message"].as_str().unwrap(),
+        "Not allowed to send delayed events"
+    );
+}
+
+#[test]
+fn update_delayed_event_request_handling_works() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(
+        &mut machine,
+        actions,
+        Some("org.matrix.msc4157.update_delayed_event"),
+    );
+
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "update-delayed-event-request-id",
+        "action": "org.matrix.msc4157.update_delayed_event",
+        "data": {
+            "action": "restart",
+            "delay_id": "delay-id",
+        },
+    })));
+
+    let actions = {
+        let [action]: [Action; 1] = actions.try_into().unwrap();
+        assert_let!(
+            Action::MatrixDriverRequest {
+                request_id,
+                data: MatrixDriverRequestData::UpdateDelayedEvent(request)
+            } = action
+        );
+        assert_eq!(request.action, UpdateAction::Restart);
+        assert_eq!(request.delay_id, "delay-id");
+
+        machine.process(IncomingMessage::MatrixDriverResponse {
+            request_id,
+            response: Ok(MatrixDriverResponse::DelayedEventUpdated(
+                update_delayed_event::unstable::Response::new(),
+            )),
+        })
+    };
+
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, request_id) = parse_msg(&msg);
+    assert_eq!(request_id, "update-delayed-event-request-id");
+    assert_eq!(msg["response"], json!({}));
+}
+
+#[test]
+fn update_delayed_event_request_without_capability() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(&mut machine, actions, Some("org.matrix.msc4157.send.delayed_event"));
+
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "update-delayed-event-request-id",
+        "action": "org.matrix.msc4157.update_delayed_event",
+        "data": {
+            "action": "cancel",
+            "delay_id": "delay-id",
+        },
+    })));
+
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, request_id) = parse_msg(&msg);
+    assert_eq!(request_id, "update-delayed-event-request-id");
+    assert_eq!(msg["response"]["error"]["message"].as_str().unwrap(), "Not allowed");
+}
@@ -24,8 +24,14 @@ macro_rules! json_string {
 
 mod api_versions;
 mod capabilities;
+mod delayed_events;
+mod download_file;
 mod error;
+mod navigate;
 mod openid;
+mod to_device;
+mod turn_servers;
+mod upload_file;
 
 const WIDGET_ID: &str = "test-widget";
 
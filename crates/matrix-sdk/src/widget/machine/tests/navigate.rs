@@ -0,0 +1,134 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use assert_matches2::assert_let;
+use ruma::owned_room_id;
+use serde_json::json;
+
+use super::{capabilities::assert_capabilities_dance, parse_msg, WIDGET_ID};
+use crate::widget::machine::{
+    Action, IncomingMessage, MatrixDriverRequestData, MatrixDriverResponse, WidgetMachine,
+};
+
+#[test]
+fn navigate_request_handling_works() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(&mut machine, actions, Some("org.matrix.msc2931.navigate"));
+
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "navigate-request-id",
+        "action": "org.matrix.msc2931.navigate",
+        "data": {
+            "uri": "https://matrix.to/#/#room:example.org",
+        },
+    })));
+
+    let actions = {
+        let [action]: [Action; 1] = actions.try_into().unwrap();
+        assert_let!(
+            Action::MatrixDriverRequest {
+                request_id,
+                data: MatrixDriverRequestData::Navigate(request)
+            } = action
+        );
+        assert_eq!(request.uri, "https://matrix.to/#/#room:example.org");
+
+        machine.process(IncomingMessage::MatrixDriverResponse {
+            request_id,
+            response: Ok(MatrixDriverResponse::Navigated),
+        })
+    };
+
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, request_id) = parse_msg(&msg);
+    assert_eq!(request_id, "navigate-request-id");
+    assert_eq!(msg["action"], "org.matrix.msc2931.navigate");
+    assert_eq!(msg["response"], json!({}));
+}
+
+#[test]
+fn navigate_request_without_capability() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(&mut machine, actions, None);
+
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "navigate-request-id",
+        "action": "org.matrix.msc2931.navigate",
+        "data": {
+            "uri": "https://matrix.to/#/#room:example.org",
+        },
+    })));
+
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, request_id) = parse_msg(&msg);
+    assert_eq!(request_id, "navigate-request-id");
+    assert_eq!(msg["response"]["error"]["message"].as_str().unwrap(), "Not allowed");
+}
+
+#[test]
+fn navigate_driver_error_is_forwarded_to_the_widget() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(&mut machine, actions, Some("org.matrix.msc2931.navigate"));
+
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "navigate-request-id",
+        "action": "org.matrix.msc2931.navigate",
+        "data": {
+            "uri": "https://example.org",
+        },
+    })));
+
+    let actions = {
+        let [action]: [Action; 1] = actions.try_into().unwrap();
+        assert_let!(
+            Action::MatrixDriverRequest {
+                request_id,
+                data: MatrixDriverRequestData::Navigate(_)
+            } = action
+        );
+
+        machine.process(IncomingMessage::MatrixDriverResponse {
+            request_id,
+            response: Err("Invalid matrix.to URI".to_owned()),
+        })
+    };
+
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, _request_id) = parse_msg(&msg);
+    assert_eq!(msg["response"]["error"]["message"].as_str().unwrap(), "Invalid matrix.to URI");
+}
@@ -0,0 +1,194 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use assert_matches2::assert_let;
+use ruma::{
+    api::client::to_device::send_event_to_device, owned_room_id, owned_user_id, serde::Raw,
+    to_device::DeviceIdOrAllDevices,
+};
+use serde_json::json;
+
+use super::{capabilities::assert_capabilities_dance, parse_msg, WIDGET_ID};
+use crate::widget::machine::{
+    Action, IncomingMessage, MatrixDriverRequestData, MatrixDriverResponse, WidgetMachine,
+};
+
+#[test]
+fn send_to_device_request_handling_works() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(
+        &mut machine,
+        actions,
+        Some("org.matrix.msc3819.send.to_device:io.element.call.encryption_keys"),
+    );
+
+    // The widget sends encrypted to-device events, the request is forwarded
+    // to the driver.
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "send-to-device-request-id",
+        "action": "send_to_device",
+        "data": {
+            "type": "io.element.call.encryption_keys",
+            "encrypted": true,
+            "messages": {
+                "@alice:example.org": {
+                    "ALICEDEVICE": { "keys": [] },
+                },
+                "@bob:example.org": {
+                    "*": { "keys": [] },
+                },
+            },
+        },
+    })));
+
+    let actions = {
+        let [action]: [Action; 1] = actions.try_into().unwrap();
+        assert_let!(
+            Action::MatrixDriverRequest {
+                request_id,
+                data: MatrixDriverRequestData::SendToDeviceEvent(request)
+            } = action
+        );
+        assert_eq!(request.event_type.to_string(), "io.element.call.encryption_keys");
+        assert!(request.encrypted);
+        assert_eq!(request.messages.len(), 2);
+
+        let alice_messages = &request.messages[&owned_user_id!("@alice:example.org")];
+        let device_id = DeviceIdOrAllDevices::DeviceId("ALICEDEVICE".into());
+        assert_eq!(alice_messages[&device_id].json().get(), r#"{"keys":[]}"#);
+
+        let bob_messages = &request.messages[&owned_user_id!("@bob:example.org")];
+        assert!(bob_messages.contains_key(&DeviceIdOrAllDevices::AllDevices));
+
+        machine.process(IncomingMessage::MatrixDriverResponse {
+            request_id,
+            response: Ok(MatrixDriverResponse::ToDeviceSent(
+                send_event_to_device::v3::Response::new(),
+            )),
+        })
+    };
+
+    // The widget gets an empty response once the events were sent.
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, request_id) = parse_msg(&msg);
+    assert_eq!(request_id, "send-to-device-request-id");
+    assert_eq!(msg["action"], "send_to_device");
+    assert_eq!(msg["response"], json!({}));
+}
+
+#[test]
+fn send_to_device_request_for_non_allowed_event_type() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(
+        &mut machine,
+        actions,
+        Some("org.matrix.msc3819.send.to_device:io.element.call.encryption_keys"),
+    );
+
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "send-to-device-request-id",
+        "action": "send_to_device",
+        "data": {
+            "type": "m.room_key_request",
+            "messages": {
+                "@alice:example.org": {
+                    "*": {},
+                },
+            },
+        },
+    })));
+
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, request_id) = parse_msg(&msg);
+    assert_eq!(request_id, "send-to-device-request-id");
+    assert_eq!(msg["action"], "send_to_device");
+    assert_eq!(msg["response"]["error"]["message"].as_str().unwrap(), "Not allowed");
+}
+
+#[test]
+fn received_to_device_events_are_forwarded_to_the_widget() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(
+        &mut machine,
+        actions,
+        Some("org.matrix.msc3819.receive.to_device:io.element.call.encryption_keys"),
+    );
+
+    let event = json!({
+        "type": "io.element.call.encryption_keys",
+        "sender": "@alice:example.org",
+        "content": { "keys": [] },
+    });
+    let actions =
+        machine.process(IncomingMessage::ToDeviceEventReceived(Raw::new(&event).unwrap().cast()));
+
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, _request_id) = parse_msg(&msg);
+    assert_eq!(
+        msg,
+        json!({
+            "api": "toWidget",
+            "widgetId": WIDGET_ID,
+            "action": "send_to_device",
+            "data": event,
+        }),
+    );
+}
+
+#[test]
+fn received_to_device_events_not_matching_the_read_filter_are_ignored() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(
+        &mut machine,
+        actions,
+        Some("org.matrix.msc3819.receive.to_device:io.element.call.encryption_keys"),
+    );
+
+    let event = json!({
+        "type": "m.room_key_request",
+        "sender": "@alice:example.org",
+        "content": {},
+    });
+    let actions =
+        machine.process(IncomingMessage::ToDeviceEventReceived(Raw::new(&event).unwrap().cast()));
+
+    assert!(actions.is_empty());
+}
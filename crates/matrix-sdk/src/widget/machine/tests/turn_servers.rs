@@ -0,0 +1,126 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use assert_matches2::assert_let;
+use ruma::{api::client::voip::get_turn_server_info, owned_room_id};
+use serde_json::json;
+
+use super::{capabilities::assert_capabilities_dance, parse_msg, WIDGET_ID};
+use crate::widget::machine::{
+    Action, IncomingMessage, MatrixDriverRequestData, MatrixDriverResponse, WidgetMachine,
+};
+
+fn turn_servers(username: &str, ttl: u64) -> MatrixDriverResponse {
+    MatrixDriverResponse::TurnServersReceived(get_turn_server_info::v3::Response::new(
+        username.to_owned(),
+        "secret".to_owned(),
+        vec!["turn:turn.example.org:3478?transport=udp".to_owned()],
+        Duration::from_secs(ttl),
+    ))
+}
+
+/// Respond to the `GetTurnServers` request among the actions.
+fn respond_get_turn_servers(
+    machine: &mut WidgetMachine,
+    action: Action,
+    response: Result<MatrixDriverResponse, String>,
+) -> Vec<Action> {
+    assert_let!(
+        Action::MatrixDriverRequest { request_id, data: MatrixDriverRequestData::GetTurnServers } =
+            action
+    );
+    machine.process(IncomingMessage::MatrixDriverResponse { request_id, response })
+}
+
+fn watch_turn_servers(machine: &mut WidgetMachine) -> Action {
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "watch-request-id",
+        "action": "watch_turn_servers",
+        "data": {},
+    })));
+
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    action
+}
+
+fn assert_turn_servers_update(action: Action, username: &str) {
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, _request_id) = parse_msg(&msg);
+    assert_eq!(msg["action"], "update_turn_servers");
+    assert_eq!(msg["data"]["username"], username);
+}
+
+#[test]
+fn turn_servers_are_refreshed_before_they_expire() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(&mut machine, actions, Some("town.robin.msc3846.turn_servers"));
+
+    let action = watch_turn_servers(&mut machine);
+    let actions = respond_get_turn_servers(&mut machine, action, Ok(turn_servers("alice", 100)));
+
+    let [response, update, schedule]: [Action; 3] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = response);
+    let (msg, request_id) = parse_msg(&msg);
+    assert_eq!(request_id, "watch-request-id");
+    assert_eq!(msg["response"], json!({}));
+    assert_turn_servers_update(update, "alice");
+    assert_let!(Action::ScheduleTurnServersRefresh(delay) = schedule);
+    assert_eq!(delay, Duration::from_secs(90));
+
+    // The credentials are fetched again and sent to the widget once the delay
+    // elapsed.
+    let actions = machine.process(IncomingMessage::TurnServersRefreshDue);
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    let actions = respond_get_turn_servers(&mut machine, action, Ok(turn_servers("bob", 10)));
+
+    let [update, schedule]: [Action; 2] = actions.try_into().unwrap();
+    assert_turn_servers_update(update, "bob");
+    assert_let!(Action::ScheduleTurnServersRefresh(delay) = schedule);
+    assert_eq!(delay, Duration::from_secs(30));
+
+    // A failed refresh is retried later.
+    let actions = machine.process(IncomingMessage::TurnServersRefreshDue);
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    let actions = respond_get_turn_servers(&mut machine, action, Err("timeout".to_owned()));
+
+    let [schedule]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::ScheduleTurnServersRefresh(delay) = schedule);
+    assert_eq!(delay, Duration::from_secs(30));
+
+    // Once the widget stops watching the TURN servers, they are not refreshed
+    // anymore.
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "unwatch-request-id",
+        "action": "unwatch_turn_servers",
+        "data": {},
+    })));
+    let [response]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = response);
+    let (_msg, request_id) = parse_msg(&msg);
+    assert_eq!(request_id, "unwatch-request-id");
+
+    let actions = machine.process(IncomingMessage::TurnServersRefreshDue);
+    assert!(actions.is_empty());
+}
@@ -0,0 +1,139 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use assert_matches2::assert_let;
+use ruma::{owned_mxc_uri, owned_room_id};
+
+use super::{capabilities::assert_capabilities_dance, parse_msg, WIDGET_ID};
+use crate::widget::machine::{
+    Action, IncomingMessage, MatrixDriverRequestData, MatrixDriverResponse, WidgetMachine,
+};
+
+#[test]
+fn upload_file_request_handling_works() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(&mut machine, actions, Some("org.matrix.msc4039.upload_file"));
+
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "upload-file-request-id",
+        "action": "org.matrix.msc4039.upload_file",
+        "data": {
+            // "Hello, world!" in base64.
+            "file": "SGVsbG8sIHdvcmxkIQ",
+            "content_type": "text/plain",
+        },
+    })));
+
+    let actions = {
+        let [action]: [Action; 1] = actions.try_into().unwrap();
+        assert_let!(
+            Action::MatrixDriverRequest {
+                request_id,
+                data: MatrixDriverRequestData::UploadFile(request)
+            } = action
+        );
+        assert_eq!(request.file.as_bytes(), b"Hello, world!");
+        assert_eq!(request.content_type.as_deref(), Some("text/plain"));
+
+        machine.process(IncomingMessage::MatrixDriverResponse {
+            request_id,
+            response: Ok(MatrixDriverResponse::FileUploaded(owned_mxc_uri!(
+                "mxc://example.org/AQwafuaFswefuhsfAFAgsw"
+            ))),
+        })
+    };
+
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, request_id) = parse_msg(&msg);
+    assert_eq!(request_id, "upload-file-request-id");
+    assert_eq!(msg["action"], "org.matrix.msc4039.upload_file");
+    assert_eq!(msg["response"]["content_uri"], "mxc://example.org/AQwafuaFswefuhsfAFAgsw");
+}
+
+#[test]
+fn upload_file_request_without_capability() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(&mut machine, actions, None);
+
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "upload-file-request-id",
+        "action": "org.matrix.msc4039.upload_file",
+        "data": {
+            "file": "SGVsbG8sIHdvcmxkIQ",
+        },
+    })));
+
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, request_id) = parse_msg(&msg);
+    assert_eq!(request_id, "upload-file-request-id");
+    assert_eq!(msg["response"]["error"]["message"].as_str().unwrap(), "Not allowed");
+}
+
+#[test]
+fn upload_file_driver_error_is_forwarded_to_the_widget() {
+    let (mut machine, actions) = WidgetMachine::new(
+        WIDGET_ID.to_owned(),
+        owned_room_id!("!a98sd12bjh:example.org"),
+        false,
+        None,
+    );
+    assert_capabilities_dance(&mut machine, actions, Some("org.matrix.msc4039.upload_file"));
+
+    let actions = machine.process(IncomingMessage::WidgetMessage(json_string!({
+        "api": "fromWidget",
+        "widgetId": WIDGET_ID,
+        "requestId": "upload-file-request-id",
+        "action": "org.matrix.msc4039.upload_file",
+        "data": {
+            "file": "SGVsbG8sIHdvcmxkIQ",
+        },
+    })));
+
+    let actions = {
+        let [action]: [Action; 1] = actions.try_into().unwrap();
+        assert_let!(
+            Action::MatrixDriverRequest {
+                request_id,
+                data: MatrixDriverRequestData::UploadFile(request)
+            } = action
+        );
+        assert_eq!(request.content_type, None);
+
+        machine.process(IncomingMessage::MatrixDriverResponse {
+            request_id,
+            response: Err("The file is too large".to_owned()),
+        })
+    };
+
+    let [action]: [Action; 1] = actions.try_into().unwrap();
+    assert_let!(Action::SendToWidget(msg) = action);
+    let (msg, _request_id) = parse_msg(&msg);
+    assert_eq!(msg["response"]["error"]["message"].as_str().unwrap(), "The file is too large");
+}
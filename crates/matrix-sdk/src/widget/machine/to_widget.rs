@@ -14,7 +14,10 @@
 
 use std::marker::PhantomData;
 
-use ruma::{events::AnyTimelineEvent, serde::Raw};
+use ruma::{
+    events::{AnyTimelineEvent, AnyToDeviceEvent},
+    serde::Raw,
+};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::value::RawValue as RawJsonValue;
 use tracing::error;
@@ -128,5 +131,30 @@ impl ToWidgetRequest for NotifyNewMatrixEvent {
     type ResponseData = Empty;
 }
 
+/// Notify the widget that we received a new to-device event.
+/// This is a "response" to the widget subscribing to the events.
+#[derive(Serialize)]
+#[serde(transparent)]
+pub(crate) struct NotifyNewToDeviceEvent(pub(crate) Raw<AnyToDeviceEvent>);
+
+impl ToWidgetRequest for NotifyNewToDeviceEvent {
+    const ACTION: &'static str = "send_to_device";
+    type ResponseData = Empty;
+}
+
+/// Notify the widget that the TURN servers changed.
+/// This is a "response" to the widget watching the TURN servers.
+#[derive(Serialize)]
+pub(crate) struct NotifyTurnServersChanged {
+    pub(crate) uris: Vec<String>,
+    pub(crate) username: String,
+    pub(crate) password: String,
+}
+
+impl ToWidgetRequest for NotifyTurnServersChanged {
+    const ACTION: &'static str = "update_turn_servers";
+    type ResponseData = Empty;
+}
+
 #[derive(Deserialize)]
 pub(crate) struct Empty {}
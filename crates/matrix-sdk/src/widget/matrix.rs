@@ -15,31 +15,53 @@
 //! Matrix driver implementation that exposes Matrix functionality
 //! that is relevant for the widget API.
 
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, time::Duration};
 
 use matrix_sdk_base::deserialized_responses::RawAnySyncOrStrippedState;
 use ruma::{
     api::client::{
         account::request_openid_token::v3::{Request as OpenIdRequest, Response as OpenIdResponse},
+        delayed_events::{
+            delayed_message_event, delayed_state_event,
+            update_delayed_event::{self, unstable::UpdateAction},
+            DelayParameters,
+        },
         filter::RoomEventFilter,
+        to_device::send_event_to_device,
+        voip::get_turn_server_info,
     },
     assign,
     events::{
-        AnySyncTimelineEvent, AnyTimelineEvent, MessageLikeEventType, StateEventType,
-        TimelineEventType,
+        room::MediaSource, AnyMessageLikeEventContent, AnyStateEventContent, AnySyncTimelineEvent,
+        AnyTimelineEvent, AnyToDeviceEvent, AnyToDeviceEventContent, MessageLikeEventType,
+        StateEventType, TimelineEventType, ToDeviceEventType,
     },
     serde::Raw,
-    OwnedEventId, RoomId,
+    to_device::DeviceIdOrAllDevices,
+    EventId, OwnedEventId, OwnedMxcUri, OwnedUserId, RoomId, TransactionId,
 };
 use serde_json::value::RawValue as RawJsonValue;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
 use tracing::error;
+#[cfg(feature = "e2e-encryption")]
+use tracing::warn;
 
-use super::StateKeySelector;
+use super::{machine::SendEventOutcome, StateKeySelector};
 use crate::{
-    event_handler::EventHandlerDropGuard, room::MessagesOptions, HttpResult, Result, Room,
+    event_handler::EventHandlerDropGuard,
+    media::{MediaFormat, MediaRequest},
+    room::MessagesOptions,
+    HttpResult, Result, Room,
 };
 
+/// The maximum number of pages of the room timeline that are loaded to read
+/// events.
+const MAX_READ_PAGES: usize = 10;
+
+/// The messages of a to-device request, for each user and device.
+type ToDeviceMessages =
+    BTreeMap<OwnedUserId, BTreeMap<DeviceIdOrAllDevices, Raw<AnyToDeviceEventContent>>>;
+
 /// Thin wrapper around a [`Room`] that provides functionality relevant for
 /// widgets.
 pub(crate) struct MatrixDriver {
@@ -59,20 +81,63 @@ impl MatrixDriver {
     }
 
     /// Reads the latest `limit` events of a given `event_type` from the room.
+    ///
+    /// The room timeline is paginated backwards, so that events of encrypted
+    /// rooms can be matched once they were decrypted, until `limit` events
+    /// were found, the event with the `since` ID is reached, or
+    /// `MAX_READ_PAGES` pages were loaded.
     pub(crate) async fn read_message_like_events(
         &self,
         event_type: MessageLikeEventType,
         limit: u32,
+        since: Option<&EventId>,
     ) -> Result<Vec<Raw<AnyTimelineEvent>>> {
-        let options = assign!(MessagesOptions::backward(), {
-            limit: limit.into(),
-            filter: assign!(RoomEventFilter::default(), {
-                types: Some(vec![event_type.to_string()])
-            }),
-        });
+        let mut events = Vec::new();
+        if limit == 0 {
+            return Ok(events);
+        }
+
+        let event_type = event_type.to_string();
+        let mut from = None;
 
-        let messages = self.room.messages(options).await?;
-        Ok(messages.chunk.into_iter().map(|ev| ev.event.cast()).collect())
+        for _ in 0..MAX_READ_PAGES {
+            let options = assign!(MessagesOptions::backward(), {
+                from: from.take(),
+                limit: (limit - events.len() as u32).into(),
+                filter: assign!(RoomEventFilter::default(), {
+                    types: Some(vec![event_type.clone(), "m.room.encrypted".to_owned()])
+                }),
+            });
+
+            let messages = self.room.messages(options).await?;
+
+            for event in messages.chunk {
+                let event = event.event;
+                if since.is_some()
+                    && event.get_field::<OwnedEventId>("event_id").ok().flatten().as_deref()
+                        == since
+                {
+                    return Ok(events);
+                }
+
+                if event.get_field::<String>("type").ok().flatten().as_deref()
+                    == Some(event_type.as_str())
+                {
+                    events.push(event);
+                    if events.len() >= limit as usize {
+                        return Ok(events);
+                    }
+                }
+            }
+
+            match messages.end {
+                Some(end) => from = Some(end),
+                // We reached the start of the timeline.
+                None => break,
+            }
+        }
+
+        Ok(events)
     }
 
     pub(crate) async fn read_state_events(
@@ -106,23 +171,214 @@ impl MatrixDriver {
         Ok(events)
     }
 
+    /// Gets the TURN servers of the homeserver.
+    pub(crate) async fn get_turn_servers(&self) -> HttpResult<get_turn_server_info::v3::Response> {
+        self.room.client.send(get_turn_server_info::v3::Request::new(), None).await
+    }
+
+    /// Uploads the given `file` to the media repository.
+    pub(crate) async fn upload_file(
+        &self,
+        file: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> Result<OwnedMxcUri> {
+        let content_type = content_type
+            .and_then(|content_type| content_type.parse().ok())
+            .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+        Ok(self.room.client.media().upload(&content_type, file).await?.content_uri)
+    }
+
+    /// Downloads the file with the given `content_uri` from the media
+    /// repository.
+    pub(crate) async fn download_file(&self, content_uri: OwnedMxcUri) -> Result<Vec<u8>> {
+        let request =
+            MediaRequest { source: MediaSource::Plain(content_uri), format: MediaFormat::File };
+        self.room.client.media().get_media_content(&request, true).await
+    }
+
     /// Sends a given `event` to the room.
+    ///
+    /// If a `delay` is given, the event is scheduled with the homeserver, that
+    /// will send it once the delay has elapsed, unless it is updated in the
+    /// meantime.
     pub(crate) async fn send(
         &self,
         event_type: TimelineEventType,
         state_key: Option<String>,
         content: Box<RawJsonValue>,
-    ) -> Result<OwnedEventId> {
+        delay: Option<Duration>,
+    ) -> Result<SendEventOutcome> {
         let type_str = event_type.to_string();
-        Ok(match state_key {
-            Some(key) => self.room.send_state_event_raw(&type_str, &key, content).await?.event_id,
-            None => self.room.send_raw(&type_str, content).await?.event_id,
+        let room_id = self.room.room_id().to_owned();
+
+        Ok(match (state_key, delay) {
+            (None, None) => {
+                SendEventOutcome::Sent(self.room.send_raw(&type_str, content).await?.event_id)
+            }
+            (Some(key), None) => SendEventOutcome::Sent(
+                self.room.send_state_event_raw(&type_str, &key, content).await?.event_id,
+            ),
+            (None, Some(timeout)) => {
+                let request = delayed_message_event::unstable::Request::new_raw(
+                    room_id,
+                    TransactionId::new(),
+                    MessageLikeEventType::from(type_str),
+                    DelayParameters::Timeout { timeout },
+                    Raw::<AnyMessageLikeEventContent>::from_json(content),
+                );
+                SendEventOutcome::Delayed(self.room.client.send(request, None).await?.delay_id)
+            }
+            (Some(key), Some(timeout)) => {
+                let request = delayed_state_event::unstable::Request::new_raw(
+                    room_id,
+                    key,
+                    StateEventType::from(type_str),
+                    DelayParameters::Timeout { timeout },
+                    Raw::<AnyStateEventContent>::from_json(content),
+                );
+                SendEventOutcome::Delayed(self.room.client.send(request, None).await?.delay_id)
+            }
         })
     }
 
+    /// Cancels, restarts or sends right away the delayed event with the given
+    /// `delay_id`.
+    pub(crate) async fn update_delayed_event(
+        &self,
+        delay_id: String,
+        action: UpdateAction,
+    ) -> HttpResult<update_delayed_event::unstable::Response> {
+        let request = update_delayed_event::unstable::Request::new(delay_id, action);
+        self.room.client.send(request, None).await
+    }
+
+    /// Sends the given to-device `messages`.
+    ///
+    /// If `encrypted` is set, the content of each message is encrypted with
+    /// Olm for the device it is sent to, and sent as an `m.room.encrypted`
+    /// to-device event.
+    pub(crate) async fn send_to_device(
+        &self,
+        event_type: ToDeviceEventType,
+        encrypted: bool,
+        messages: ToDeviceMessages,
+    ) -> Result<send_event_to_device::v3::Response> {
+        let request = if encrypted {
+            #[cfg(feature = "e2e-encryption")]
+            {
+                let messages = self.encrypt_to_device_messages(&event_type, messages).await?;
+                send_event_to_device::v3::Request::new_raw(
+                    ToDeviceEventType::RoomEncrypted,
+                    TransactionId::new(),
+                    messages,
+                )
+            }
+
+            #[cfg(not(feature = "e2e-encryption"))]
+            return Err(crate::Error::UnknownError(
+                "Sending encrypted to-device events requires the `e2e-encryption` feature".into(),
+            ));
+        } else {
+            send_event_to_device::v3::Request::new_raw(event_type, TransactionId::new(), messages)
+        };
+
+        Ok(self.room.client.send(request, None).await?)
+    }
+
+    /// Encrypts the given to-device `messages` for each of their target
+    /// devices, establishing the missing Olm sessions first.
+    ///
+    /// The devices of the recipients are queried first if they aren't known
+    /// yet. Blacklisted devices, and devices for which the message couldn't be
+    /// encrypted, e.g. because no Olm session could be established with them,
+    /// are skipped.
+    #[cfg(feature = "e2e-encryption")]
+    async fn encrypt_to_device_messages(
+        &self,
+        event_type: &ToDeviceEventType,
+        messages: ToDeviceMessages,
+    ) -> Result<ToDeviceMessages> {
+        let client = &self.room.client;
+        self.query_keys_for_recipients(&messages).await?;
+        client.claim_one_time_keys(messages.keys().map(|user_id| &**user_id)).await?;
+
+        let event_type = event_type.to_string();
+        let mut encrypted_messages = ToDeviceMessages::new();
+
+        for (user_id, device_messages) in messages {
+            let devices = client.encryption().get_user_devices(&user_id).await?;
+            let encrypted_device_messages = encrypted_messages.entry(user_id).or_default();
+
+            for (target, content) in device_messages {
+                let content = content.deserialize_as::<serde_json::Value>()?;
+                let target_devices = match target {
+                    DeviceIdOrAllDevices::DeviceId(device_id) => {
+                        devices.get(&device_id).into_iter().collect::<Vec<_>>()
+                    }
+                    DeviceIdOrAllDevices::AllDevices => devices.devices().collect(),
+                };
+                let target_devices =
+                    target_devices.into_iter().filter(|device| !device.is_blacklisted());
+
+                for device in target_devices {
+                    match device.encrypt_event_raw(&event_type, &content).await {
+                        Ok(encrypted) => {
+                            encrypted_device_messages.insert(
+                                DeviceIdOrAllDevices::DeviceId(device.device_id().to_owned()),
+                                encrypted.cast(),
+                            );
+                        }
+                        Err(err) => {
+                            warn!(
+                                user_id = ?device.user_id(),
+                                device_id = ?device.device_id(),
+                                "Couldn't encrypt a to-device message for a device, skipping it: {err}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        encrypted_messages.retain(|_, device_messages| !device_messages.is_empty());
+
+        Ok(encrypted_messages)
+    }
+
+    /// Runs a `/keys/query` request for the recipients of the given
+    /// `messages` whose devices aren't known or are outdated.
+    #[cfg(feature = "e2e-encryption")]
+    async fn query_keys_for_recipients(&self, messages: &ToDeviceMessages) -> Result<()> {
+        let client = &self.room.client;
+        let olm = client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(crate::Error::NoOlmMachine)?;
+
+        // Start tracking the devices of the recipients that weren't tracked yet, they
+        // are considered outdated until they are queried.
+        olm.update_tracked_users(messages.keys().map(|user_id| &**user_id)).await?;
+
+        let outdated_users: Vec<_> = olm
+            .store()
+            .load_tracked_users()
+            .await?
+            .into_iter()
+            .filter(|tracked| tracked.dirty && messages.contains_key(&tracked.user_id))
+            .map(|tracked| tracked.user_id)
+            .collect();
+
+        let (request_id, request) =
+            olm.query_keys_for_users(outdated_users.iter().map(|user_id| &**user_id));
+
+        if !request.device_keys.is_empty() {
+            client.keys_query(&request_id, request.device_keys).await?;
+        }
+
+        Ok(())
+    }
+
     /// Starts forwarding new room events. Once the returned `EventReceiver`
     /// is dropped, forwarding will be stopped.
-    pub(crate) fn events(&self) -> EventReceiver {
+    pub(crate) fn events(&self) -> EventReceiver<Raw<AnyTimelineEvent>> {
         let (tx, rx) = unbounded_channel();
         let room_id = self.room.room_id().to_owned();
         let handle = self.room.add_event_handler(move |raw: Raw<AnySyncTimelineEvent>| {
@@ -133,17 +389,33 @@ impl MatrixDriver {
         let drop_guard = self.room.client().event_handler_drop_guard(handle);
         EventReceiver { rx, _drop_guard: drop_guard }
     }
+
+    /// Starts forwarding new to-device events. Once the returned
+    /// `EventReceiver` is dropped, forwarding will be stopped.
+    ///
+    /// Encrypted to-device events are forwarded once they were decrypted.
+    pub(crate) fn to_device_events(&self) -> EventReceiver<Raw<AnyToDeviceEvent>> {
+        let (tx, rx) = unbounded_channel();
+        let client = self.room.client();
+        let handle = client.add_event_handler(move |raw: Raw<AnyToDeviceEvent>| {
+            let _ = tx.send(raw);
+            async {}
+        });
+
+        let drop_guard = client.event_handler_drop_guard(handle);
+        EventReceiver { rx, _drop_guard: drop_guard }
+    }
 }
 
 /// A simple entity that wraps an `UnboundedReceiver`
-/// along with the drop guard for the room event handler.
-pub(crate) struct EventReceiver {
-    rx: UnboundedReceiver<Raw<AnyTimelineEvent>>,
+/// along with the drop guard for the event handler.
+pub(crate) struct EventReceiver<E> {
+    rx: UnboundedReceiver<E>,
     _drop_guard: EventHandlerDropGuard,
 }
 
-impl EventReceiver {
-    pub(crate) async fn recv(&mut self) -> Option<Raw<AnyTimelineEvent>> {
+impl<E> EventReceiver<E> {
+    pub(crate) async fn recv(&mut self) -> Option<E> {
         self.rx.recv().await
     }
 }
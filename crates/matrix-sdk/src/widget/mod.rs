@@ -14,9 +14,10 @@
 
 //! Widget API implementation.
 
-use std::fmt;
+use std::{fmt, time::Duration};
 
 use async_channel::{Receiver, Sender};
+use ruma::MatrixToUri;
 use serde::de::{self, Deserialize, Deserializer, Visitor};
 use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 use tokio_util::sync::{CancellationToken, DropGuard};
@@ -24,7 +25,7 @@ use tokio_util::sync::{CancellationToken, DropGuard};
 use self::{
     machine::{
         Action, IncomingMessage, MatrixDriverRequestData, MatrixDriverResponse, SendEventRequest,
-        WidgetMachine,
+        SendToDeviceRequest, UpdateDelayedEventRequest, WidgetMachine,
     },
     matrix::MatrixDriver,
 };
@@ -38,7 +39,7 @@ mod settings;
 
 pub use self::{
     capabilities::{Capabilities, CapabilitiesProvider},
-    filter::{EventFilter, MessageLikeEventFilter, StateEventFilter},
+    filter::{EventFilter, MessageLikeEventFilter, StateEventFilter, ToDeviceEventFilter},
     settings::{
         ClientProperties, EncryptionSystem, VirtualElementCallWidgetOptions, WidgetSettings,
     },
@@ -212,7 +213,11 @@ impl<T: CapabilitiesProvider> ProcessingContext<T> {
 
                     MatrixDriverRequestData::ReadMessageLikeEvent(cmd) => self
                         .matrix_driver
-                        .read_message_like_events(cmd.event_type.clone(), cmd.limit)
+                        .read_message_like_events(
+                            cmd.event_type.clone(),
+                            cmd.limit,
+                            cmd.since.as_deref(),
+                        )
                         .await
                         .map(MatrixDriverResponse::MatrixEventRead)
                         .map_err(|e| e.to_string()),
@@ -225,13 +230,62 @@ impl<T: CapabilitiesProvider> ProcessingContext<T> {
                         .map_err(|e| e.to_string()),
 
                     MatrixDriverRequestData::SendMatrixEvent(req) => {
-                        let SendEventRequest { event_type, state_key, content } = req;
+                        let SendEventRequest { event_type, state_key, content, delay } = req;
+                        let delay = delay.map(Duration::from_millis);
                         self.matrix_driver
-                            .send(event_type, state_key, content)
+                            .send(event_type, state_key, content, delay)
                             .await
                             .map(MatrixDriverResponse::MatrixEventSent)
                             .map_err(|e| e.to_string())
                     }
+
+                    MatrixDriverRequestData::SendToDeviceEvent(req) => {
+                        let SendToDeviceRequest { event_type, encrypted, messages } = req;
+                        self.matrix_driver
+                            .send_to_device(event_type, encrypted, messages)
+                            .await
+                            .map(MatrixDriverResponse::ToDeviceSent)
+                            .map_err(|e| e.to_string())
+                    }
+
+                    MatrixDriverRequestData::UpdateDelayedEvent(req) => {
+                        let UpdateDelayedEventRequest { action, delay_id } = req;
+                        self.matrix_driver
+                            .update_delayed_event(delay_id, action)
+                            .await
+                            .map(MatrixDriverResponse::DelayedEventUpdated)
+                            .map_err(|e| e.to_string())
+                    }
+
+                    MatrixDriverRequestData::Navigate(cmd) => match MatrixToUri::parse(&cmd.uri) {
+                        Ok(uri) => self
+                            .capabilities_provider
+                            .navigate(uri)
+                            .await
+                            .map(|()| MatrixDriverResponse::Navigated),
+                        Err(e) => Err(format!("Invalid matrix.to URI: {e}")),
+                    },
+
+                    MatrixDriverRequestData::GetTurnServers => self
+                        .matrix_driver
+                        .get_turn_servers()
+                        .await
+                        .map(MatrixDriverResponse::TurnServersReceived)
+                        .map_err(|e| e.to_string()),
+
+                    MatrixDriverRequestData::UploadFile(cmd) => self
+                        .matrix_driver
+                        .upload_file(cmd.file.into_inner(), cmd.content_type.as_deref())
+                        .await
+                        .map(MatrixDriverResponse::FileUploaded)
+                        .map_err(|e| e.to_string()),
+
+                    MatrixDriverRequestData::DownloadFile(cmd) => self
+                        .matrix_driver
+                        .download_file(cmd.content_uri)
+                        .await
+                        .map(MatrixDriverResponse::FileDownloaded)
+                        .map_err(|e| e.to_string()),
                 };
 
                 self.events_tx
@@ -247,8 +301,11 @@ impl<T: CapabilitiesProvider> ProcessingContext<T> {
                     };
 
                     self.event_forwarding_guard = Some(guard);
-                    let (mut matrix, events_tx) =
-                        (self.matrix_driver.events(), self.events_tx.clone());
+                    let (mut matrix, mut to_device, events_tx) = (
+                        self.matrix_driver.events(),
+                        self.matrix_driver.to_device_events(),
+                        self.events_tx.clone(),
+                    );
                     tokio::spawn(async move {
                         loop {
                             tokio::select! {
@@ -256,6 +313,9 @@ impl<T: CapabilitiesProvider> ProcessingContext<T> {
                                 Some(event) = matrix.recv() => {
                                     let _ = events_tx.send(IncomingMessage::MatrixEventReceived(event));
                                 }
+                                Some(event) = to_device.recv() => {
+                                    let _ = events_tx.send(IncomingMessage::ToDeviceEventReceived(event));
+                                }
                             }
                         }
                     });
@@ -264,6 +324,14 @@ impl<T: CapabilitiesProvider> ProcessingContext<T> {
             Action::Unsubscribe => {
                 self.event_forwarding_guard = None;
             }
+            Action::ScheduleTurnServersRefresh(delay) => {
+                let events_tx = self.events_tx.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    // The widget might be gone in the meantime.
+                    let _ = events_tx.send(IncomingMessage::TurnServersRefreshDue);
+                });
+            }
         }
 
         Ok(())
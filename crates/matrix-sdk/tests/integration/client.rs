@@ -1,13 +1,26 @@
-use std::{collections::BTreeMap, time::Duration};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
 
+use assert_matches::assert_matches;
 use assert_matches2::assert_let;
-use futures_util::FutureExt;
+use futures_util::{stream, FutureExt, TryStreamExt};
 use matrix_sdk::{
-    config::SyncSettings,
-    media::{MediaFormat, MediaRequest, MediaThumbnailSize},
+    async_trait,
+    bytes::Bytes,
+    config::{RequestConfig, StoreConfig, SyncSettings},
+    matrix_auth::{MatrixSession, MatrixSessionTokens},
+    media::{
+        MediaCachePolicy, MediaCacheStats, MediaFormat, MediaRequest, MediaThumbnailSize,
+        MediaUrlPolicy,
+    },
     sync::RoomUpdate,
+    uiaa::{TermsPolicy, UiaaError, UiaaHandler, UiaaState},
+    Client, Error,
 };
-use matrix_sdk_base::{sync::RoomUpdates, RoomState};
+use matrix_sdk_base::{store::MemoryStore, sync::RoomUpdates, RoomState, SessionMeta};
 use matrix_sdk_test::{
     async_test, sync_state_event,
     test_json::{
@@ -17,13 +30,16 @@ use matrix_sdk_test::{
     JoinedRoomBuilder, SyncResponseBuilder, DEFAULT_TEST_ROOM_ID,
 };
 use ruma::{
-    api::client::{
-        directory::{
-            get_public_rooms,
-            get_public_rooms_filtered::{self, v3::Request as PublicRoomsFilterRequest},
+    api::{
+        client::{
+            directory::{
+                get_public_rooms,
+                get_public_rooms_filtered::{self, v3::Request as PublicRoomsFilterRequest},
+            },
+            media::get_content_thumbnail::v3::Method,
+            uiaa::{self, AuthType},
         },
-        media::get_content_thumbnail::v3::Method,
-        uiaa,
+        MatrixVersion,
     },
     assign, device_id,
     directory::Filter,
@@ -40,12 +56,13 @@ use ruma::{
 use serde_json::{json, Value as JsonValue};
 use stream_assert::{assert_next_matches, assert_pending};
 use tokio_stream::wrappers::BroadcastStream;
+use url::Url;
 use wiremock::{
-    matchers::{header, method, path, path_regex},
-    Mock, Request, ResponseTemplate,
+    matchers::{body_bytes, body_partial_json, header, method, path, path_regex, query_param},
+    Mock, MockServer, Request, ResponseTemplate,
 };
 
-use crate::{logged_in_client, mock_sync, no_retry_test_client};
+use crate::{logged_in_client, mock_sync, no_retry_test_client, test_client_builder};
 
 #[async_test]
 async fn sync() {
@@ -139,6 +156,266 @@ async fn delete_devices() {
     }
 }
 
+struct TermsAndPasswordHandler {
+    policies: StdMutex<Vec<TermsPolicy>>,
+}
+
+#[async_trait]
+impl UiaaHandler for TermsAndPasswordHandler {
+    async fn password(&self, state: &UiaaState) -> Option<uiaa::Password> {
+        assert!(state.auth_error().is_none());
+
+        Some(uiaa::Password::new(
+            uiaa::UserIdentifier::UserIdOrLocalpart("example".to_owned()),
+            "wordpass".to_owned(),
+        ))
+    }
+
+    async fn terms(&self, _state: &UiaaState, policies: &[TermsPolicy]) -> bool {
+        *self.policies.lock().unwrap() = policies.to_owned();
+        true
+    }
+}
+
+#[async_test]
+async fn delete_devices_with_uiaa() {
+    let (client, server) = no_retry_test_client().await;
+
+    let flows = json!([
+        { "stages": ["m.login.recaptcha"] },
+        { "stages": ["m.login.terms", "m.login.password"] },
+    ]);
+    let params = json!({
+        "m.login.terms": {
+            "policies": {
+                "privacy_policy": {
+                    "version": "1.0",
+                    "fr": { "name": "Politique de confidentialité", "url": "https://example.org/fr" },
+                    "en": { "name": "Privacy Policy", "url": "https://example.org/en" },
+                },
+            },
+        },
+    });
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/delete_devices"))
+        .and(body_partial_json(json!({
+            "auth": {
+                "type": "m.login.password",
+                "identifier": { "type": "m.id.user", "user": "example" },
+                "password": "wordpass",
+                "session": "abcdef",
+            },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/delete_devices"))
+        .and(body_partial_json(json!({
+            "auth": { "type": "m.login.terms", "session": "abcdef" },
+        })))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "flows": flows,
+            "params": params,
+            "session": "abcdef",
+            "completed": ["m.login.terms"],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/delete_devices"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "flows": flows,
+            "params": params,
+            "session": "abcdef",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let handler = TermsAndPasswordHandler { policies: Default::default() };
+    let devices = &[device_id!("DEVICEID").to_owned()];
+    client.delete_devices_with_uiaa(devices, &handler).await.unwrap();
+
+    let policies = handler.policies.into_inner().unwrap();
+    assert_eq!(policies.len(), 1);
+    assert_eq!(policies[0].id, "privacy_policy");
+    assert_eq!(policies[0].version, "1.0");
+    assert_eq!(policies[0].name.as_deref(), Some("Privacy Policy"));
+    assert_eq!(policies[0].url.as_ref().map(|url| url.as_str()), Some("https://example.org/en"));
+}
+
+#[async_test]
+async fn uiaa_no_supported_flow() {
+    struct NoopHandler;
+
+    #[async_trait]
+    impl UiaaHandler for NoopHandler {}
+
+    let (client, server) = no_retry_test_client().await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/delete_devices"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "flows": [{ "stages": ["m.login.recaptcha"] }, { "stages": ["m.login.password"] }],
+            "params": { "m.login.recaptcha": { "public_key": "key" } },
+            "session": "abcdef",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let devices = &[device_id!("DEVICEID").to_owned()];
+    let error = client.delete_devices_with_uiaa(devices, &NoopHandler).await.unwrap_err();
+    assert_matches!(error, Error::Uiaa(UiaaError::NoSupportedFlow));
+}
+
+#[async_test]
+async fn uiaa_fallback() {
+    struct FallbackHandler {
+        url: StdMutex<Option<Url>>,
+    }
+
+    #[async_trait]
+    impl UiaaHandler for FallbackHandler {
+        async fn fallback(&self, _state: &UiaaState, auth_type: &AuthType, url: &Url) -> bool {
+            assert_eq!(auth_type.as_str(), "m.login.sso");
+            *self.url.lock().unwrap() = Some(url.clone());
+            true
+        }
+    }
+
+    let (client, server) = no_retry_test_client().await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/account/deactivate"))
+        .and(body_partial_json(json!({ "auth": { "session": "abcdef" } })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "id_server_unbind_result": "no-support" })),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/account/deactivate"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "flows": [{ "stages": ["m.login.sso"] }],
+            "params": {},
+            "session": "abcdef",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let handler = FallbackHandler { url: Default::default() };
+    client.account().deactivate_with_uiaa(None, &handler).await.unwrap();
+
+    assert_eq!(
+        handler.url.into_inner().unwrap().unwrap().as_str(),
+        format!("{}/_matrix/client/v3/auth/m.login.sso/fallback/web?session=abcdef", server.uri())
+    );
+}
+
+#[async_test]
+async fn uiaa_too_many_attempts() {
+    struct WrongPasswordHandler;
+
+    #[async_trait]
+    impl UiaaHandler for WrongPasswordHandler {
+        async fn password(&self, _state: &UiaaState) -> Option<uiaa::Password> {
+            Some(uiaa::Password::new(
+                uiaa::UserIdentifier::UserIdOrLocalpart("example".to_owned()),
+                "wrong".to_owned(),
+            ))
+        }
+    }
+
+    let (client, server) = no_retry_test_client().await;
+
+    // The first request and 10 attempts with the password.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/delete_devices"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "flows": [{ "stages": ["m.login.password"] }],
+            "params": {},
+            "session": "abcdef",
+            "errcode": "M_FORBIDDEN",
+            "error": "Invalid password",
+        })))
+        .expect(11)
+        .mount(&server)
+        .await;
+
+    let devices = &[device_id!("DEVICEID").to_owned()];
+    let error = client.delete_devices_with_uiaa(devices, &WrongPasswordHandler).await.unwrap_err();
+    assert_matches!(error, Error::Uiaa(UiaaError::TooManyAttempts(10)));
+}
+
+#[async_test]
+async fn uiaa_fallback_homeserver_with_path() {
+    struct FallbackHandler {
+        url: StdMutex<Option<Url>>,
+    }
+
+    #[async_trait]
+    impl UiaaHandler for FallbackHandler {
+        async fn fallback(&self, _state: &UiaaState, _auth_type: &AuthType, url: &Url) -> bool {
+            *self.url.lock().unwrap() = Some(url.clone());
+            true
+        }
+    }
+
+    let server = MockServer::start().await;
+    let client = Client::builder()
+        .homeserver_url(format!("{}/matrix", server.uri()))
+        .server_versions([MatrixVersion::V1_0])
+        .request_config(RequestConfig::new().disable_retry())
+        .build()
+        .await
+        .unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/matrix/_matrix/client/r0/account/deactivate"))
+        .and(body_partial_json(json!({ "auth": { "session": "abcdef" } })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "id_server_unbind_result": "no-support" })),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/matrix/_matrix/client/r0/account/deactivate"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "flows": [{ "stages": ["m.login.sso"] }],
+            "params": {},
+            "session": "abcdef",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let handler = FallbackHandler { url: Default::default() };
+    client.account().deactivate_with_uiaa(None, &handler).await.unwrap();
+
+    // The path of the homeserver is kept.
+    assert_eq!(
+        handler.url.into_inner().unwrap().unwrap().as_str(),
+        format!(
+            "{}/matrix/_matrix/client/v3/auth/m.login.sso/fallback/web?session=abcdef",
+            server.uri()
+        )
+    );
+}
+
 #[async_test]
 async fn resolve_room_alias() {
     let (client, server) = no_retry_test_client().await;
@@ -401,6 +678,553 @@ async fn get_media_file() {
         .unwrap();
 }
 
+#[async_test]
+async fn media_cache_policy() {
+    let (client, server) = logged_in_client().await;
+    let media = client.media();
+
+    media
+        .set_cache_policy(MediaCachePolicy {
+            max_cache_size: Some(20),
+            max_file_size: Some(15),
+            ..MediaCachePolicy::unlimited()
+        })
+        .await
+        .unwrap();
+
+    let files = [
+        ("a", "0123456789"),
+        ("b", "0123456789"),
+        ("c", "0123456789"),
+        ("big", "0123456789abcdef"),
+    ];
+    for (name, content) in files {
+        Mock::given(method("GET"))
+            .and(path(format!("/_matrix/media/r0/download/localhost/{name}")))
+            .respond_with(ResponseTemplate::new(200).set_body_string(content))
+            .mount(&server)
+            .await;
+    }
+
+    let request = |name: &str| MediaRequest {
+        source: MediaSource::Plain(format!("mxc://localhost/{name}").into()),
+        format: MediaFormat::File,
+    };
+
+    for (name, _) in files {
+        media.get_media_content(&request(name), true).await.unwrap();
+    }
+
+    // The largest file was not cached, and the least recently used one was
+    // evicted.
+    assert_eq!(
+        media.cache_stats().await.unwrap(),
+        MediaCacheStats { entries: 2, size: 20, pinned_entries: 0, pinned_size: 0 }
+    );
+
+    // Pinned files are kept when the cache gets smaller.
+    media.set_media_pinned(&request("b"), true).await.unwrap();
+    media
+        .set_cache_policy(MediaCachePolicy {
+            max_cache_size: Some(5),
+            ..MediaCachePolicy::unlimited()
+        })
+        .await
+        .unwrap();
+    media.clean_up_cache().await.unwrap();
+
+    assert_eq!(
+        media.cache_stats().await.unwrap(),
+        MediaCacheStats { entries: 1, size: 10, pinned_entries: 1, pinned_size: 10 }
+    );
+
+    // Clearing the cache removes pinned files too.
+    media.clear_cache().await.unwrap();
+    assert_eq!(media.cache_stats().await.unwrap(), MediaCacheStats::default());
+}
+
+#[async_test]
+async fn media_cache_policy_is_persisted() {
+    let state_store = Arc::new(MemoryStore::new());
+    let policy = MediaCachePolicy { max_cache_size: Some(20), ..MediaCachePolicy::unlimited() };
+
+    let (builder, _server) = test_client_builder().await;
+    let client = builder
+        .store_config(StoreConfig::new().state_store(state_store.clone()))
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(client.media().cache_policy().await.unwrap(), MediaCachePolicy::new());
+    client.media().set_cache_policy(policy).await.unwrap();
+
+    let (builder, _server) = test_client_builder().await;
+    let client =
+        builder.store_config(StoreConfig::new().state_store(state_store)).build().await.unwrap();
+    assert_eq!(client.media().cache_policy().await.unwrap(), policy);
+}
+
+#[async_test]
+async fn pinned_avatar_is_unpinned_when_it_changes() {
+    let (client, server) = logged_in_client().await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/profile/@example:localhost/avatar_url"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "avatar_url": "mxc://localhost/old",
+        })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/profile/@example:localhost/avatar_url"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "avatar_url": "mxc://localhost/new",
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex("^/_matrix/media/r0/download/localhost/(old|new)"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("avatar"))
+        .mount(&server)
+        .await;
+
+    client.account().get_avatar(MediaFormat::File).await.unwrap().unwrap();
+    assert_eq!(
+        client.media().cache_stats().await.unwrap(),
+        MediaCacheStats { entries: 1, size: 6, pinned_entries: 1, pinned_size: 6 }
+    );
+
+    // The new avatar is pinned, the old one can be evicted.
+    client.account().get_avatar(MediaFormat::File).await.unwrap().unwrap();
+    assert_eq!(
+        client.media().cache_stats().await.unwrap(),
+        MediaCacheStats { entries: 2, size: 12, pinned_entries: 1, pinned_size: 6 }
+    );
+}
+
+/// Create a client logged in like the one of [`logged_in_client`], that
+/// supports the given Matrix versions.
+///
+/// If `versions` is `None`, they are requested from the homeserver.
+async fn logged_in_client_with_versions(
+    versions: Option<&[MatrixVersion]>,
+) -> (Client, MockServer) {
+    let server = MockServer::start().await;
+    let mut builder = Client::builder()
+        .homeserver_url(server.uri())
+        .request_config(RequestConfig::new().disable_retry());
+
+    if let Some(versions) = versions {
+        builder = builder.server_versions(versions.to_vec());
+    }
+
+    let client = builder.build().await.unwrap();
+
+    client
+        .restore_session(MatrixSession {
+            meta: SessionMeta {
+                user_id: user_id!("@example:localhost").to_owned(),
+                device_id: device_id!("DEVICEID").to_owned(),
+            },
+            tokens: MatrixSessionTokens { access_token: "1234".to_owned(), refresh_token: None },
+        })
+        .await
+        .unwrap();
+
+    (client, server)
+}
+
+#[async_test]
+async fn authenticated_media() {
+    let (client, server) = logged_in_client_with_versions(Some(&[MatrixVersion::V1_11])).await;
+
+    let media = client.media();
+    assert_eq!(media.url_policy().await.unwrap(), MediaUrlPolicy::Authenticated);
+
+    let request = MediaRequest {
+        source: MediaSource::Plain(mxc_uri!("mxc://localhost/textfile").to_owned()),
+        format: MediaFormat::File,
+    };
+
+    assert_eq!(
+        media.download_url(&request).await.unwrap().as_str(),
+        format!("{}/_matrix/client/v1/media/download/localhost/textfile", server.uri())
+    );
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v1/media/download/localhost/textfile"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("Hello, World!"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert_eq!(media.get_media_content(&request, false).await.unwrap(), b"Hello, World!");
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v1/media/thumbnail/localhost/textfile"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("Hello"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let request = MediaRequest {
+        source: MediaSource::Plain(mxc_uri!("mxc://localhost/textfile").to_owned()),
+        format: MediaFormat::Thumbnail(MediaThumbnailSize {
+            method: Method::Scale,
+            width: uint!(100),
+            height: uint!(100),
+        }),
+    };
+    assert_eq!(media.get_media_content(&request, false).await.unwrap(), b"Hello");
+}
+
+#[async_test]
+async fn authenticated_media_unrecognized_endpoint() {
+    let (client, server) = logged_in_client_with_versions(Some(&[MatrixVersion::V1_11])).await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v1/media/download/localhost/textfile"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_UNRECOGNIZED",
+            "error": "Unrecognized request",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/media/(r0|v3)/download/localhost/textfile"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("Hello, World!"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let request = MediaRequest {
+        source: MediaSource::Plain(mxc_uri!("mxc://localhost/textfile").to_owned()),
+        format: MediaFormat::File,
+    };
+
+    // The legacy endpoint is used if the homeserver doesn't recognize the
+    // authenticated one.
+    assert_eq!(client.media().get_media_content(&request, false).await.unwrap(), b"Hello, World!");
+
+    // A missing media doesn't trigger the fallback.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v1/media/download/localhost/missing"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "Not found",
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let request = MediaRequest {
+        source: MediaSource::Plain(mxc_uri!("mxc://localhost/missing").to_owned()),
+        format: MediaFormat::File,
+    };
+    client.media().get_media_content(&request, false).await.unwrap_err();
+}
+
+#[async_test]
+async fn authenticated_media_fallback() {
+    let (client, server) = logged_in_client().await;
+
+    // The test client only supports Matrix 1.0.
+    let media = client.media();
+    assert_eq!(media.url_policy().await.unwrap(), MediaUrlPolicy::Unauthenticated);
+
+    let request = MediaRequest {
+        source: MediaSource::Plain(mxc_uri!("mxc://localhost/textfile").to_owned()),
+        format: MediaFormat::File,
+    };
+    assert_eq!(
+        media.download_url(&request).await.unwrap().as_str(),
+        format!("{}/_matrix/media/r0/download/localhost/textfile", server.uri())
+    );
+}
+
+#[async_test]
+async fn authenticated_media_unstable_feature() {
+    // The versions are fetched from the homeserver.
+    let (client, server) = logged_in_client_with_versions(None).await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/versions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "versions": ["v1.10"],
+            "unstable_features": {
+                "org.matrix.msc3916.stable": true,
+            },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let media = client.media();
+    assert_eq!(media.url_policy().await.unwrap(), MediaUrlPolicy::Authenticated);
+
+    // The stable endpoints are used, even if the server doesn't support Matrix
+    // 1.11.
+    let request = MediaRequest {
+        source: MediaSource::Plain(mxc_uri!("mxc://localhost/textfile").to_owned()),
+        format: MediaFormat::File,
+    };
+    assert_eq!(
+        media.download_url(&request).await.unwrap().as_str(),
+        format!("{}/_matrix/client/v1/media/download/localhost/textfile", server.uri())
+    );
+}
+
+#[async_test]
+async fn get_url_preview() {
+    let (client, server) = logged_in_client().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/media/(r0|v3)/preview_url"))
+        .and(query_param("url", "https://matrix.org"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "og:title": "Matrix.org",
+            "og:description": "An open network for secure, decentralised communication",
+            "og:image": "mxc://localhost/logo",
+            "og:image:width": 512,
+            "og:image:height": "256",
+            "og:image:type": "image/png",
+            "matrix:image:size": 10240,
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let media = client.media();
+    let preview = media.get_url_preview("https://matrix.org", None).await.unwrap();
+
+    assert_eq!(preview.title.as_deref(), Some("Matrix.org"));
+    assert_eq!(
+        preview.description.as_deref(),
+        Some("An open network for secure, decentralised communication")
+    );
+    assert_eq!(preview.image.as_deref(), Some(mxc_uri!("mxc://localhost/logo")));
+    assert_eq!(preview.image_width, Some(uint!(512)));
+    assert_eq!(preview.image_height, Some(uint!(256)));
+    assert_eq!(preview.image_type.as_deref(), Some("image/png"));
+    assert_eq!(preview.image_size, Some(uint!(10240)));
+    assert_eq!(preview.url, None);
+    assert_eq!(preview.site_name, None);
+
+    // The second request is served from the cache.
+    let cached = media.get_url_preview("https://matrix.org", None).await.unwrap();
+    assert_eq!(cached, preview);
+
+    // Clearing the cache also clears the previews.
+    media.clear_cache().await.unwrap();
+    server.reset().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/media/(r0|v3)/preview_url"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let preview = media.get_url_preview("https://matrix.org", None).await.unwrap();
+    assert_eq!(preview, Default::default());
+}
+
+#[async_test]
+async fn url_previews_count_towards_cache_size() {
+    let (client, server) = logged_in_client().await;
+    let media = client.media();
+
+    media
+        .set_cache_policy(MediaCachePolicy {
+            max_cache_size: Some(20),
+            ..MediaCachePolicy::unlimited()
+        })
+        .await
+        .unwrap();
+
+    Mock::given(method("GET"))
+        .and(path_regex("^/_matrix/media/r0/download/localhost/(a|b)"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("0123456789"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/media/(r0|v3)/preview_url"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .mount(&server)
+        .await;
+
+    let request = |name: &str| MediaRequest {
+        source: MediaSource::Plain(format!("mxc://localhost/{name}").into()),
+        format: MediaFormat::File,
+    };
+
+    media.get_media_content(&request("a"), true).await.unwrap();
+    media.get_media_content(&request("b"), true).await.unwrap();
+    assert_eq!(
+        media.cache_stats().await.unwrap(),
+        MediaCacheStats { entries: 2, size: 20, pinned_entries: 0, pinned_size: 0 }
+    );
+
+    // The cache is full, so the least recently used file is evicted to make
+    // room for the preview.
+    media.get_url_preview("https://matrix.org", None).await.unwrap();
+    assert_eq!(
+        media.cache_stats().await.unwrap(),
+        MediaCacheStats { entries: 1, size: 10, pinned_entries: 0, pinned_size: 0 }
+    );
+}
+
+#[async_test]
+async fn upload_stream() {
+    let (client, server) = logged_in_client().await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/media/r0/upload"))
+        .and(header("content-type", "text/plain"))
+        .and(header("content-length", "13"))
+        .and(body_bytes(b"Hello, World!".to_vec()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+          "content_uri": "mxc://localhost/textfile"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let chunks: Vec<std::io::Result<Bytes>> =
+        vec![Ok(Bytes::from_static(b"Hello, ")), Ok(Bytes::from_static(b"World!"))];
+    let upload = client.media().upload_stream(&mime::TEXT_PLAIN, stream::iter(chunks), 13);
+    let progress = upload.subscribe_to_send_progress();
+    let response = upload.await.unwrap();
+
+    assert_eq!(response.content_uri.as_str(), "mxc://localhost/textfile");
+
+    let progress = progress.get();
+    assert_eq!(progress.current, 13);
+    assert_eq!(progress.total, 13);
+}
+
+#[async_test]
+async fn get_media_stream() {
+    let (client, server) = logged_in_client().await;
+
+    let request = MediaRequest {
+        source: MediaSource::Plain(mxc_uri!("mxc://localhost/textfile").to_owned()),
+        format: MediaFormat::File,
+    };
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/download/localhost/textfile"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("Hello, World!"))
+        .mount(&server)
+        .await;
+
+    let get_stream = client.media().get_media_stream(&request);
+    let progress = get_stream.subscribe_to_progress();
+    let stream = get_stream.await.unwrap();
+    assert_eq!(stream.size(), Some(13));
+
+    let chunks: Vec<Bytes> = stream.try_collect().await.unwrap();
+    assert_eq!(chunks.concat(), b"Hello, World!");
+
+    let progress = progress.get();
+    assert_eq!(progress.current, 13);
+    assert_eq!(progress.total, 13);
+
+    // Errors from the server are returned before streaming.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/media/r0/download/localhost/missing"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "Not found",
+        })))
+        .mount(&server)
+        .await;
+
+    let request = MediaRequest {
+        source: MediaSource::Plain(mxc_uri!("mxc://localhost/missing").to_owned()),
+        format: MediaFormat::File,
+    };
+    assert!(client.media().get_media_stream(&request).await.is_err());
+}
+
+#[async_test]
+async fn download_to_file() {
+    let (client, server) = logged_in_client().await;
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("textfile.txt");
+    let part_path = dir.path().join("textfile.txt.part");
+
+    let request = MediaRequest {
+        source: MediaSource::Plain(mxc_uri!("mxc://localhost/textfile").to_owned()),
+        format: MediaFormat::File,
+    };
+
+    // Resume a download.
+    {
+        std::fs::write(&part_path, "Hello, ").unwrap();
+
+        let _mock_guard = Mock::given(method("GET"))
+            .and(path("/_matrix/media/r0/download/localhost/textfile"))
+            .and(header("range", "bytes=7-"))
+            .respond_with(ResponseTemplate::new(206).set_body_string("World!"))
+            .expect(1)
+            .mount_as_scoped(&server)
+            .await;
+
+        let download = client.media().download_to_file(&request, &file_path);
+        let progress = download.subscribe_to_progress();
+        download.await.unwrap();
+
+        assert_eq!(std::fs::read(&file_path).unwrap(), b"Hello, World!");
+        assert!(!part_path.exists());
+
+        let progress = progress.get();
+        assert_eq!(progress.current, 13);
+        assert_eq!(progress.total, 13);
+    }
+
+    // The server doesn't support resuming, the download starts over.
+    {
+        std::fs::write(&part_path, "Garbage").unwrap();
+
+        let _mock_guard = Mock::given(method("GET"))
+            .and(path("/_matrix/media/r0/download/localhost/textfile"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Hello, World (2)!"))
+            .expect(1)
+            .mount_as_scoped(&server)
+            .await;
+
+        client.media().download_to_file(&request, &file_path).await.unwrap();
+
+        assert_eq!(std::fs::read(&file_path).unwrap(), b"Hello, World (2)!");
+        assert!(!part_path.exists());
+    }
+
+    // The part file already contains the whole file.
+    {
+        std::fs::write(&part_path, "Hello, World (3)!").unwrap();
+
+        let _mock_guard = Mock::given(method("GET"))
+            .and(path("/_matrix/media/r0/download/localhost/textfile"))
+            .respond_with(ResponseTemplate::new(416).set_body_json(json!({
+                "errcode": "M_UNKNOWN",
+                "error": "Range not satisfiable",
+            })))
+            .expect(1)
+            .mount_as_scoped(&server)
+            .await;
+
+        client.media().download_to_file(&request, &file_path).await.unwrap();
+
+        assert_eq!(std::fs::read(&file_path).unwrap(), b"Hello, World (3)!");
+        assert!(!part_path.exists());
+    }
+}
+
 #[async_test]
 async fn whoami() {
     let (client, server) = logged_in_client().await;
@@ -453,7 +1277,7 @@ async fn test_subscribe_all_room_updates() {
     client.sync_once(sync_settings).await.unwrap();
 
     let room_updates = rx.recv().now_or_never().unwrap().unwrap();
-    assert_let!(RoomUpdates { leave, join, invite } = room_updates);
+    assert_let!(RoomUpdates { leave, join, invite, .. } = room_updates);
 
     // Check the left room updates.
     {
@@ -706,8 +1530,6 @@ async fn cross_signing_status() {
 #[cfg(feature = "e2e-encryption")]
 #[async_test]
 async fn test_encrypt_room_event() {
-    use std::sync::Arc;
-
     use ruma::events::room::encrypted::RoomEncryptedEventContent;
 
     let (client, server) = logged_in_client().await;
@@ -0,0 +1,428 @@
+use std::sync::{Arc, Mutex};
+
+use matrix_sdk::{encryption::secret_storage::SecretStore, Client};
+use matrix_sdk_test::async_test;
+use serde_json::{json, Value as JsonValue};
+use wiremock::{
+    matchers::{body_partial_json, header, method, path, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::logged_in_client;
+
+const SECRET_STORE_KEY: &str = "EsTj 3yST y93F SLpB jJsz eAXc 2XzA ygD3 w69H fGaN TKBj jXEd";
+
+/// The name of the secret containing the pickle key of the dehydrated device.
+const PICKLE_KEY_SECRET_NAME: &str = "org.matrix.msc3814";
+
+/// Mock the default secret storage key, and open the secret store with it.
+async fn open_secret_store(client: &Client, server: &MockServer) -> SecretStore {
+    let user_id = client.user_id().unwrap();
+    let key_id = "bmur2d9ypPUH1msSwCxQOJkuKRmJI55e";
+
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "_matrix/client/r0/user/{user_id}/account_data/m.secret_storage.default_key"
+        )))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "key": key_id })))
+        .mount(server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "_matrix/client/r0/user/{user_id}/account_data/m.secret_storage.key.{key_id}"
+        )))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "algorithm": "m.secret_storage.v1.aes-hmac-sha2",
+            "iv": "xv5b6/p3ExEw++wTyfSHEg==",
+            "mac": "ujBBbXahnTAMkmPUX2/0+VTfUh63pGyVRuBcDMgmJC8=",
+        })))
+        .mount(server)
+        .await;
+
+    client
+        .encryption()
+        .secret_storage()
+        .open_secret_store(SECRET_STORE_KEY)
+        .await
+        .expect("We should be able to open our secret store")
+}
+
+/// Mock the account data event containing the pickle key, it is missing until
+/// a secret gets uploaded.
+///
+/// Returns the content of the uploaded account data event, if any.
+async fn mock_pickle_key_secret(
+    client: &Client,
+    server: &MockServer,
+) -> Arc<Mutex<Option<JsonValue>>> {
+    let user_id = client.user_id().unwrap();
+    let account_data_path =
+        format!("_matrix/client/r0/user/{user_id}/account_data/{PICKLE_KEY_SECRET_NAME}");
+    let uploaded_content: Arc<Mutex<Option<JsonValue>>> = Default::default();
+
+    Mock::given(method("GET"))
+        .and(path(account_data_path.clone()))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with({
+            let uploaded_content = uploaded_content.clone();
+
+            move |_: &wiremock::Request| match uploaded_content.lock().unwrap().clone() {
+                Some(content) => ResponseTemplate::new(200).set_body_json(content),
+                None => ResponseTemplate::new(404).set_body_json(json!({
+                    "errcode": "M_NOT_FOUND",
+                    "error": "Account data not found"
+                })),
+            }
+        })
+        .named("pickle key account data GET")
+        .mount(server)
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path(account_data_path))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with({
+            let uploaded_content = uploaded_content.clone();
+
+            move |request: &wiremock::Request| {
+                *uploaded_content.lock().unwrap() = Some(request.body_json().unwrap());
+                ResponseTemplate::new(200).set_body_json(json!({}))
+            }
+        })
+        .named("pickle key account data PUT")
+        .mount(server)
+        .await;
+
+    uploaded_content
+}
+
+/// Mock the upload of dehydrated devices.
+///
+/// Returns the bodies of the upload requests.
+async fn mock_dehydrated_device_upload(server: &MockServer) -> Arc<Mutex<Vec<JsonValue>>> {
+    let uploads: Arc<Mutex<Vec<JsonValue>>> = Default::default();
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"/dehydrated_device$"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with({
+            let uploads = uploads.clone();
+
+            move |request: &wiremock::Request| {
+                let body: JsonValue = request.body_json().unwrap();
+                let device_id = body["device_id"].clone();
+                uploads.lock().unwrap().push(body);
+
+                ResponseTemplate::new(200).set_body_json(json!({ "device_id": device_id }))
+            }
+        })
+        .named("dehydrated device PUT")
+        .mount(server)
+        .await;
+
+    uploads
+}
+
+/// Bootstrap cross-signing, since the dehydrated devices need to be signed by
+/// our self-signing key.
+async fn bootstrap_cross_signing(client: &Client, server: &MockServer) {
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/unstable/keys/device_signing/upload"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .mount(server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/unstable/keys/signatures/upload"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "failures": {}
+        })))
+        .mount(server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/keys/upload"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "one_time_key_counts": {}
+        })))
+        .mount(server)
+        .await;
+
+    client.encryption().bootstrap_cross_signing(None).await.unwrap();
+}
+
+/// A to-device event sent to the dehydrated device, that doesn't contain a
+/// room key.
+fn to_device_event(counter: u32) -> JsonValue {
+    json!({
+        "sender": "@alice:localhost",
+        "type": "org.example.custom",
+        "content": { "counter": counter },
+    })
+}
+
+#[async_test]
+async fn create_dehydrated_device() {
+    let (client, server) = logged_in_client().await;
+
+    bootstrap_cross_signing(&client, &server).await;
+    let secret_store = open_secret_store(&client, &server).await;
+    let uploaded_secret = mock_pickle_key_secret(&client, &server).await;
+    let uploads = mock_dehydrated_device_upload(&server).await;
+
+    let device_id = client
+        .encryption()
+        .dehydrated_devices()
+        .create(&secret_store)
+        .await
+        .expect("We should be able to create a dehydrated device");
+
+    // A new pickle key was generated and stored in the secret store.
+    assert!(uploaded_secret.lock().unwrap().is_some(), "The pickle key should have been uploaded");
+    let pickle_key = secret_store
+        .get_secret(PICKLE_KEY_SECRET_NAME)
+        .await
+        .unwrap()
+        .expect("The pickle key should be in the secret store");
+
+    // The pickle key is cached, so the device can be replaced later without the
+    // secret store.
+    let cached_pickle_key = client
+        .olm_machine_for_testing()
+        .await
+        .as_ref()
+        .unwrap()
+        .store()
+        .get_value::<String>("dehydrated_device_pickle_key")
+        .await
+        .unwrap();
+    assert_eq!(cached_pickle_key.as_ref(), Some(&pickle_key));
+
+    // The device was uploaded.
+    let uploads = uploads.lock().unwrap();
+    assert_eq!(uploads.len(), 1);
+    assert_eq!(uploads[0]["device_id"], device_id.as_str());
+    assert_eq!(uploads[0]["initial_device_display_name"], "Dehydrated device");
+    assert!(uploads[0]["device_data"].is_object());
+    assert!(!uploads[0]["one_time_keys"].as_object().unwrap().is_empty());
+}
+
+#[async_test]
+async fn rehydrate_dehydrated_device() {
+    let (client, server) = logged_in_client().await;
+
+    bootstrap_cross_signing(&client, &server).await;
+    let secret_store = open_secret_store(&client, &server).await;
+    mock_pickle_key_secret(&client, &server).await;
+    let uploads = mock_dehydrated_device_upload(&server).await;
+
+    let device_id = client
+        .encryption()
+        .dehydrated_devices()
+        .create(&secret_store)
+        .await
+        .expect("We should be able to create a dehydrated device");
+    let device_data = uploads.lock().unwrap()[0]["device_data"].clone();
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"/dehydrated_device$"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device_id": device_id,
+            "device_data": device_data,
+        })))
+        .expect(1)
+        .named("dehydrated device GET")
+        .mount(&server)
+        .await;
+
+    let events_path = format!(r"/dehydrated_device/{device_id}/events$");
+
+    Mock::given(method("POST"))
+        .and(path_regex(events_path.as_str()))
+        .and(|request: &wiremock::Request| {
+            request.body_json::<JsonValue>().is_ok_and(|body| body.get("next_batch").is_none())
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "events": [to_device_event(1), to_device_event(2)],
+            "next_batch": "page2",
+        })))
+        .expect(1)
+        .named("first page of events")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(events_path.as_str()))
+        .and(body_partial_json(json!({ "next_batch": "page2" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "events": [to_device_event(3)],
+            "next_batch": "page3",
+        })))
+        .expect(1)
+        .named("second page of events")
+        .mount(&server)
+        .await;
+
+    // The empty page ends the pagination, even though it has a token.
+    Mock::given(method("POST"))
+        .and(path_regex(events_path.as_str()))
+        .and(body_partial_json(json!({ "next_batch": "page3" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "events": [],
+            "next_batch": "page4",
+        })))
+        .expect(1)
+        .named("empty page of events")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(events_path.as_str()))
+        .and(body_partial_json(json!({ "next_batch": "page4" })))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .named("page of events after the empty page")
+        .mount(&server)
+        .await;
+
+    let room_key_count = client
+        .encryption()
+        .dehydrated_devices()
+        .rehydrate(&secret_store)
+        .await
+        .expect("We should be able to rehydrate the device");
+
+    // None of the events contained a room key.
+    assert_eq!(room_key_count, Some(0));
+
+    // The rehydrated device was replaced with a new one.
+    let uploads = uploads.lock().unwrap();
+    assert_eq!(uploads.len(), 2);
+    assert_ne!(uploads[1]["device_id"], device_id.as_str());
+
+    server.verify().await;
+}
+
+#[async_test]
+async fn rehydrate_stops_without_next_batch() {
+    let (client, server) = logged_in_client().await;
+
+    bootstrap_cross_signing(&client, &server).await;
+    let secret_store = open_secret_store(&client, &server).await;
+    mock_pickle_key_secret(&client, &server).await;
+    let uploads = mock_dehydrated_device_upload(&server).await;
+
+    let device_id = client
+        .encryption()
+        .dehydrated_devices()
+        .create(&secret_store)
+        .await
+        .expect("We should be able to create a dehydrated device");
+    let device_data = uploads.lock().unwrap()[0]["device_data"].clone();
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"/dehydrated_device$"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device_id": device_id,
+            "device_data": device_data,
+        })))
+        .expect(1)
+        .named("dehydrated device GET")
+        .mount(&server)
+        .await;
+
+    // Without a token, asking for more events would return the same ones again.
+    Mock::given(method("POST"))
+        .and(path_regex(format!(r"/dehydrated_device/{device_id}/events$").as_str()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "events": [to_device_event(1)],
+        })))
+        .expect(1)
+        .named("events")
+        .mount(&server)
+        .await;
+
+    let room_key_count = client
+        .encryption()
+        .dehydrated_devices()
+        .rehydrate(&secret_store)
+        .await
+        .expect("We should be able to rehydrate the device");
+
+    assert_eq!(room_key_count, Some(0));
+    assert_eq!(uploads.lock().unwrap().len(), 2);
+
+    server.verify().await;
+}
+
+#[async_test]
+async fn rehydrate_without_pickle_key() {
+    let (client, server) = logged_in_client().await;
+    let user_id = client.user_id().unwrap();
+
+    Mock::given(method("GET"))
+        .and(path(format!("_matrix/client/r0/user/{user_id}/account_data/org.matrix.msc3814")))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "Account data not found"
+        })))
+        .expect(1)
+        .named("pickle key account data GET")
+        .mount(&server)
+        .await;
+
+    // Without a pickle key, we can't rehydrate the device, so we don't even ask
+    // for it.
+    Mock::given(method("GET"))
+        .and(path_regex(r"/dehydrated_device$"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .named("dehydrated device GET")
+        .mount(&server)
+        .await;
+
+    let secret_store = open_secret_store(&client, &server).await;
+
+    let room_key_count = client
+        .encryption()
+        .dehydrated_devices()
+        .rehydrate(&secret_store)
+        .await
+        .expect("We should be able to try to rehydrate the device");
+
+    assert_eq!(room_key_count, None);
+
+    server.verify().await;
+}
+
+#[async_test]
+async fn delete_missing_dehydrated_device() {
+    let (client, server) = logged_in_client().await;
+
+    Mock::given(method("DELETE"))
+        .and(path_regex(r"/dehydrated_device$"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "No dehydrated device found"
+        })))
+        .expect(1)
+        .named("dehydrated device DELETE")
+        .mount(&server)
+        .await;
+
+    client
+        .encryption()
+        .dehydrated_devices()
+        .delete()
+        .await
+        .expect("Deleting a missing dehydrated device should succeed");
+
+    server.verify().await;
+}
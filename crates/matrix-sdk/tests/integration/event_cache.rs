@@ -0,0 +1,131 @@
+use std::{sync::Arc, time::Duration};
+
+use assert_matches2::assert_matches;
+use matrix_sdk::{
+    config::{StoreConfig, SyncSettings},
+    event_cache::{RoomEventCacheUpdate, SearchIndexQuery},
+};
+use matrix_sdk_base::store::MemoryStore;
+use matrix_sdk_test::{
+    async_test, sync_timeline_event, JoinedRoomBuilder, SyncResponseBuilder, DEFAULT_TEST_ROOM_ID,
+};
+use ruma::{event_id, user_id};
+use tokio::time::timeout;
+
+use crate::{logged_in_client, mock_sync, test_client_builder};
+
+#[async_test]
+async fn test_search_index_is_fed_by_sync() {
+    let (client, server) = logged_in_client().await;
+    let room_id = &*DEFAULT_TEST_ROOM_ID;
+
+    let event_cache = client.event_cache();
+    event_cache.subscribe().unwrap();
+    assert!(!event_cache.is_search_index_enabled().await.unwrap());
+    event_cache.set_search_index_enabled(true).await.unwrap();
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let sync_token = client.sync_once(SyncSettings::new()).await.unwrap().next_batch;
+    server.reset().await;
+
+    let (room_event_cache, _drop_handles) = event_cache.for_room(room_id).await.unwrap();
+    let (_, mut updates) = room_event_cache.subscribe().await.unwrap();
+
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_timeline_event(sync_timeline_event!({
+                "content": { "body": "Lunch at noon?", "msgtype": "m.text" },
+                "event_id": "$lunch",
+                "origin_server_ts": 1,
+                "sender": "@alice:localhost",
+                "type": "m.room.message",
+            }))
+            .add_timeline_event(sync_timeline_event!({
+                "content": { "body": "Sure, noon works", "msgtype": "m.text" },
+                "event_id": "$reply",
+                "origin_server_ts": 2,
+                "sender": "@bob:localhost",
+                "type": "m.room.message",
+            }))
+            .add_timeline_event(sync_timeline_event!({
+                "content": {
+                    "body": "* Lunch at one?",
+                    "msgtype": "m.text",
+                    "m.new_content": { "body": "Lunch at one?", "msgtype": "m.text" },
+                    "m.relates_to": { "rel_type": "m.replace", "event_id": "$lunch" },
+                },
+                "event_id": "$edit",
+                "origin_server_ts": 3,
+                "sender": "@alice:localhost",
+                "type": "m.room.message",
+            }))
+            .add_timeline_event(sync_timeline_event!({
+                "content": {
+                    "body": "* Lunch at two?",
+                    "msgtype": "m.text",
+                    "m.new_content": { "body": "Lunch at two?", "msgtype": "m.text" },
+                    "m.relates_to": { "rel_type": "m.replace", "event_id": "$lunch" },
+                },
+                "event_id": "$forged_edit",
+                "origin_server_ts": 4,
+                "sender": "@bob:localhost",
+                "type": "m.room.message",
+            }))
+            .add_timeline_event(sync_timeline_event!({
+                "content": {},
+                "redacts": "$reply",
+                "event_id": "$redaction",
+                "origin_server_ts": 5,
+                "sender": "@bob:localhost",
+                "type": "m.room.redaction",
+            })),
+    );
+    mock_sync(&server, ev_builder.build_json_sync_response(), Some(sync_token.clone())).await;
+    client.sync_once(SyncSettings::new().token(sync_token)).await.unwrap();
+
+    // The events are indexed before observers are notified.
+    assert_matches!(
+        timeout(Duration::from_secs(1), updates.recv()).await,
+        Ok(Ok(RoomEventCacheUpdate::Append { .. }))
+    );
+
+    // The edit replaced the text of the original event, but not the one from
+    // another sender.
+    assert!(event_cache.search(SearchIndexQuery::new("noon")).await.unwrap().is_empty());
+    assert!(event_cache.search(SearchIndexQuery::new("two")).await.unwrap().is_empty());
+    let results = event_cache.search(SearchIndexQuery::new("lunch ONE")).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].event_id, event_id!("$lunch"));
+    assert_eq!(results[0].room_id, *room_id);
+    assert_eq!(results[0].body, "Lunch at one?");
+    assert_eq!(results[0].sender, user_id!("@alice:localhost"));
+
+    let mut query = SearchIndexQuery::new("lunch");
+    query.sender = Some(user_id!("@bob:localhost").to_owned());
+    assert!(event_cache.search(query).await.unwrap().is_empty());
+
+    event_cache.clear_search_index().await.unwrap();
+    assert!(event_cache.search(SearchIndexQuery::new("")).await.unwrap().is_empty());
+}
+
+#[async_test]
+async fn test_search_index_enabled_is_persisted() {
+    let state_store = Arc::new(MemoryStore::new());
+
+    let (builder, _server) = test_client_builder().await;
+    let client = builder
+        .store_config(StoreConfig::new().state_store(state_store.clone()))
+        .build()
+        .await
+        .unwrap();
+    assert!(!client.event_cache().is_search_index_enabled().await.unwrap());
+    client.event_cache().set_search_index_enabled(true).await.unwrap();
+    assert!(client.event_cache().is_search_index_enabled().await.unwrap());
+
+    let (builder, _server) = test_client_builder().await;
+    let client =
+        builder.store_config(StoreConfig::new().state_store(state_store)).build().await.unwrap();
+    assert!(client.event_cache().is_search_index_enabled().await.unwrap());
+}
@@ -18,10 +18,14 @@ use wiremock::{
 mod client;
 #[cfg(feature = "e2e-encryption")]
 mod encryption;
+mod event_cache;
 mod matrix_auth;
 mod notification;
+mod pushers;
 mod refresh_token;
 mod room;
+mod search;
+mod send_queue;
 #[cfg(feature = "experimental-widgets")]
 mod widget;
 
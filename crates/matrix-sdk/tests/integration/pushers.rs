@@ -0,0 +1,169 @@
+use matrix_sdk::pushers::PusherBuilder;
+use matrix_sdk_test::async_test;
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::logged_in_client;
+
+const APP_ID: &str = "org.example.app";
+const PUSH_GATEWAY_URL: &str = "https://push.example.org/_matrix/push/v1/notify";
+
+async fn mock_get_pushers(server: &MockServer, pushers: serde_json::Value) {
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/pushers"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "pushers": pushers })))
+        .expect(1)
+        .mount(server)
+        .await;
+}
+
+fn http_pusher_json(pushkey: &str, device_display_name: &str) -> serde_json::Value {
+    json!({
+        "app_id": APP_ID,
+        "pushkey": pushkey,
+        "kind": "http",
+        "data": {
+            "url": PUSH_GATEWAY_URL,
+            "format": "event_id_only",
+        },
+        "app_display_name": "Example",
+        "device_display_name": device_display_name,
+        "lang": "en",
+    })
+}
+
+#[async_test]
+async fn test_get_pushers() {
+    let (client, server) = logged_in_client().await;
+
+    mock_get_pushers(&server, json!([http_pusher_json("token", "Device")])).await;
+
+    let pushers = client.pushers().get().await.unwrap();
+    assert_eq!(pushers.len(), 1);
+    assert_eq!(pushers[0].ids.app_id, APP_ID);
+    assert_eq!(pushers[0].ids.pushkey, "token");
+    assert_eq!(pushers[0].device_display_name, "Device");
+}
+
+#[async_test]
+async fn test_ensure_pusher_already_registered() {
+    let (client, server) = logged_in_client().await;
+
+    mock_get_pushers(&server, json!([http_pusher_json("token", "Device")])).await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/pushers/set"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let pusher = PusherBuilder::http(APP_ID, "token", PUSH_GATEWAY_URL)
+        .app_display_name("Example")
+        .device_display_name("Device")
+        .build();
+
+    client.pushers().ensure_pusher(pusher).await.unwrap();
+}
+
+#[async_test]
+async fn test_ensure_pusher_updates_token() {
+    let (client, server) = logged_in_client().await;
+
+    // The first time, the pusher with the old token is registered.
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/pushers"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "pushers": [] })))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/pushers/set"))
+        .and(body_partial_json(http_pusher_json("old_token", "Device")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let pusher = PusherBuilder::http(APP_ID, "old_token", PUSH_GATEWAY_URL)
+        .app_display_name("Example")
+        .device_display_name("Device")
+        .build();
+
+    client.pushers().ensure_pusher(pusher).await.unwrap();
+
+    mock_get_pushers(
+        &server,
+        json!([http_pusher_json("old_token", "Device"), http_pusher_json("other", "Other device")]),
+    )
+    .await;
+
+    // The pusher with the new token is registered.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/pushers/set"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(http_pusher_json("new_token", "Device")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // The pusher with the old token of this device is removed.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/pushers/set"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({
+            "app_id": APP_ID,
+            "pushkey": "old_token",
+            "kind": null,
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let pusher = PusherBuilder::http(APP_ID, "new_token", PUSH_GATEWAY_URL)
+        .app_display_name("Example")
+        .device_display_name("Device")
+        .build();
+
+    client.pushers().ensure_pusher(pusher).await.unwrap();
+}
+
+#[async_test]
+async fn test_ensure_pusher_keeps_pushers_of_other_devices() {
+    let (client, server) = logged_in_client().await;
+
+    // Another device uses the same device display name.
+    mock_get_pushers(&server, json!([http_pusher_json("other_token", "Device")])).await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/pushers/set"))
+        .and(body_partial_json(http_pusher_json("token", "Device")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // The pusher of the other device isn't removed.
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/pushers/set"))
+        .and(body_partial_json(json!({ "pushkey": "other_token", "kind": null })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let pusher = PusherBuilder::http(APP_ID, "token", PUSH_GATEWAY_URL)
+        .app_display_name("Example")
+        .device_display_name("Device")
+        .build();
+
+    client.pushers().ensure_pusher(pusher).await.unwrap();
+}
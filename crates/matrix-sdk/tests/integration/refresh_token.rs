@@ -5,11 +5,13 @@ use std::{
 
 use assert_matches::assert_matches;
 use assert_matches2::assert_let;
-use futures_util::StreamExt;
+use futures_util::{stream, StreamExt, TryStreamExt};
 use matrix_sdk::{
+    bytes::Bytes,
     config::RequestConfig,
     executor::spawn,
     matrix_auth::{MatrixSession, MatrixSessionTokens},
+    media::{MediaFormat, MediaRequest},
     HttpError, RefreshTokenError, SessionChange,
 };
 use matrix_sdk_base::SessionMeta;
@@ -19,12 +21,14 @@ use ruma::{
         client::{account::register, error::ErrorKind},
         MatrixVersion,
     },
-    assign, device_id, user_id,
+    assign, device_id,
+    events::room::MediaSource,
+    mxc_uri, user_id,
 };
 use serde_json::json;
 use tokio::sync::{broadcast::error::TryRecvError, mpsc};
 use wiremock::{
-    matchers::{body_partial_json, header, method, path},
+    matchers::{body_partial_json, header, method, path, path_regex},
     Mock, ResponseTemplate,
 };
 
@@ -561,3 +565,95 @@ async fn refresh_token_handled_other_error() {
 
     client.whoami().await.unwrap_err();
 }
+
+#[async_test]
+async fn refresh_token_handled_media_streams() {
+    let (builder, server) = test_client_builder().await;
+    let client = builder
+        .request_config(RequestConfig::new().disable_retry())
+        .server_versions([MatrixVersion::V1_3])
+        .handle_refresh_tokens()
+        .build()
+        .await
+        .unwrap();
+    client.matrix_auth().restore_session(session()).await.unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/refresh"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::REFRESH_TOKEN))
+        .expect(1)
+        .named("`POST /refresh`")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/media/(r0|v3)/upload"))
+        .and(header(http::header::AUTHORIZATION, "Bearer 1234"))
+        .respond_with(
+            ResponseTemplate::new(401).set_body_json(&*test_json::UNKNOWN_TOKEN_SOFT_LOGOUT),
+        )
+        .expect(1)
+        .named("`POST /upload` wrong token")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/media/(r0|v3)/upload"))
+        .and(header(http::header::AUTHORIZATION, "Bearer 5678"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "content_uri": "mxc://localhost/textfile",
+        })))
+        .expect(1)
+        .named("`POST /upload` good token")
+        .mount(&server)
+        .await;
+
+    // The streamed body can't be sent again, but the token is refreshed for the
+    // next requests.
+    let chunks = || stream::iter([Ok::<_, std::io::Error>(Bytes::from_static(b"Hello, World!"))]);
+    let res = client.media().upload_stream(&mime::TEXT_PLAIN, chunks(), 13).await.unwrap_err();
+    assert_matches!(res.client_api_error_kind(), Some(ErrorKind::UnknownToken { .. }));
+
+    client.media().upload_stream(&mime::TEXT_PLAIN, chunks(), 13).await.unwrap();
+
+    // Expire the new token to check that the download is retried.
+    server.reset().await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/refresh"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "9012",
+        })))
+        .expect(1)
+        .named("`POST /refresh`")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/media/(r0|v3)/download/localhost/textfile"))
+        .and(header(http::header::AUTHORIZATION, "Bearer 5678"))
+        .respond_with(
+            ResponseTemplate::new(401).set_body_json(&*test_json::UNKNOWN_TOKEN_SOFT_LOGOUT),
+        )
+        .expect(1)
+        .named("`GET /download` wrong token")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/media/(r0|v3)/download/localhost/textfile"))
+        .and(header(http::header::AUTHORIZATION, "Bearer 9012"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("Hello, World!"))
+        .expect(1)
+        .named("`GET /download` good token")
+        .mount(&server)
+        .await;
+
+    let request = MediaRequest {
+        source: MediaSource::Plain(mxc_uri!("mxc://localhost/textfile").to_owned()),
+        format: MediaFormat::File,
+    };
+    let stream = client.media().get_media_stream(&request).await.unwrap();
+    let chunks: Vec<Bytes> = stream.try_collect().await.unwrap();
+    assert_eq!(chunks.concat(), b"Hello, World!");
+}
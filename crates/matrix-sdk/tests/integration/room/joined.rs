@@ -3,29 +3,31 @@ use std::{
     time::Duration,
 };
 
-use futures_util::future::join_all;
+use futures_util::{future::join_all, stream};
 use matrix_sdk::{
     attachment::{
         AttachmentConfig, AttachmentInfo, BaseImageInfo, BaseThumbnailInfo, BaseVideoInfo,
         Thumbnail,
     },
+    bytes::Bytes,
     config::SyncSettings,
     room::{Receipts, ReportedContentScore},
 };
 use matrix_sdk_base::RoomState;
 use matrix_sdk_test::{
-    async_test, test_json, EphemeralTestEvent, JoinedRoomBuilder, SyncResponseBuilder,
-    DEFAULT_TEST_ROOM_ID,
+    async_test, test_json, EphemeralTestEvent, JoinedRoomBuilder, StateTestEvent,
+    SyncResponseBuilder, DEFAULT_TEST_ROOM_ID,
 };
 use ruma::{
     api::client::{membership::Invite3pidInit, receipt::create_receipt::v3::ReceiptType},
     assign, event_id,
     events::{receipt::ReceiptThread, room::message::RoomMessageEventContent},
-    int, mxc_uri, owned_event_id, room_id, thirdparty, uint, user_id, OwnedUserId, TransactionId,
+    int, mxc_uri, owned_event_id, room_id, thirdparty, uint, user_id, OwnedUserId, RoomVersionId,
+    TransactionId,
 };
 use serde_json::json;
 use wiremock::{
-    matchers::{body_json, body_partial_json, header, method, path, path_regex},
+    matchers::{body_bytes, body_json, body_partial_json, header, method, path, path_regex},
     Mock, ResponseTemplate,
 };
 
@@ -381,6 +383,66 @@ async fn room_attachment_send() {
     assert_eq!(event_id!("$h29iv0s8:example.com"), response.event_id)
 }
 
+#[async_test]
+async fn room_attachment_send_stream() {
+    let (client, server) = logged_in_client().await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({
+            "body": "video.mp4",
+            "url": "mxc://example.com/AQwafuaFswefuhsfAFAgsw",
+            "info": {
+                "mimetype": "video/mp4",
+            }
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EVENT_ID))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/media/r0/upload"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(header("content-type", "video/mp4"))
+        .and(header("content-length", "11"))
+        .and(body_bytes(b"Hello world".to_vec()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+          "content_uri": "mxc://example.com/AQwafuaFswefuhsfAFAgsw"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    mock_sync(&server, &*test_json::SYNC, None).await;
+    mock_encryption_state(&server, false).await;
+
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let _response = client.sync_once(sync_settings).await.unwrap();
+
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+
+    let content_type = "video/mp4".parse().unwrap();
+    let chunks: Vec<std::io::Result<Bytes>> =
+        vec![Ok(Bytes::from_static(b"Hello ")), Ok(Bytes::from_static(b"world"))];
+    let send = room.send_attachment_stream(
+        "video.mp4",
+        &content_type,
+        stream::iter(chunks),
+        11,
+        AttachmentConfig::new(),
+    );
+    let progress = send.subscribe_to_send_progress();
+    let response = send.await.unwrap();
+
+    assert_eq!(event_id!("$h29iv0s8:example.com"), response.event_id);
+
+    let progress = progress.get();
+    assert_eq!(progress.current, 11);
+    assert_eq!(progress.total, 11);
+}
+
 #[async_test]
 async fn room_attachment_send_info() {
     let (client, server) = logged_in_client().await;
@@ -748,3 +810,77 @@ async fn subscribe_to_typing_notifications() {
     join_handle.await.unwrap();
     assert_eq!(typing_sequences.lock().unwrap().to_vec(), asserted_typing_sequences);
 }
+
+#[async_test]
+async fn upgrade_room() {
+    let (client, server) = logged_in_client().await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/.*/rooms/.*/upgrade$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_json(json!({ "new_version": "10" })))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "replacement_room": "!newroom:localhost" })),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    mock_sync(&server, &*test_json::SYNC, None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+    let new_room_id = room.upgrade(RoomVersionId::V10).await.unwrap();
+
+    assert_eq!(new_room_id, "!newroom:localhost");
+}
+
+#[async_test]
+async fn join_successor_room() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!oldroom:localhost");
+    let new_room_id = room_id!("!newroom:localhost");
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_state_event(
+        StateTestEvent::Custom(json!({
+            "content": {
+                "body": "This room has been replaced",
+                "replacement_room": new_room_id,
+            },
+            "event_id": "$tombstone",
+            "origin_server_ts": 151800140,
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.tombstone",
+        })),
+    ));
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+    server.reset().await;
+
+    // Only the first call joins the new room.
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/join/"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": new_room_id })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(room_id).unwrap();
+    assert_eq!(room.successor_room().unwrap().room_id, new_room_id);
+
+    let successor = room.join_successor().await.unwrap().unwrap();
+    assert_eq!(successor.room_id(), new_room_id);
+    assert_eq!(successor.state(), RoomState::Joined);
+
+    let successor = room.join_successor().await.unwrap().unwrap();
+    assert_eq!(successor.room_id(), new_room_id);
+
+    // A room that hasn't been tombstoned has no successor.
+    assert!(successor.join_successor().await.unwrap().is_none());
+
+    server.verify().await;
+}
@@ -0,0 +1,157 @@
+use futures_util::{pin_mut, StreamExt};
+use matrix_sdk::{config::SyncSettings, RoomState};
+use matrix_sdk_test::{
+    async_test, sync_timeline_event, JoinedRoomBuilder, SyncResponseBuilder, DEFAULT_TEST_ROOM_ID,
+};
+use ruma::{api::client::room::create_room, room_id, user_id};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path_regex},
+    Mock, ResponseTemplate,
+};
+
+use crate::{logged_in_client, mock_sync};
+
+#[async_test]
+async fn knock_room() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!knock:localhost");
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"/knock/"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({ "reason": "Let me in" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": room_id })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let room = client.knock(room_id.into(), Some("Let me in".to_owned()), &[]).await.unwrap();
+
+    assert_eq!(room.room_id(), room_id);
+    assert_eq!(room.state(), RoomState::Knocked);
+
+    let knocked_rooms = client.knocked_rooms();
+    assert_eq!(knocked_rooms.len(), 1);
+    assert_eq!(knocked_rooms[0].room_id(), room_id);
+    assert!(client.joined_rooms().is_empty());
+}
+
+#[async_test]
+async fn create_knockable_room() {
+    let (client, server) = logged_in_client().await;
+    let room_id = room_id!("!knockable:localhost");
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/createRoom"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({
+            "initial_state": [{
+                "type": "m.room.join_rules",
+                "state_key": "",
+                "content": { "join_rule": "knock" },
+            }],
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": room_id })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let room = client.create_knockable_room(create_room::v3::Request::new()).await.unwrap();
+    assert_eq!(room.room_id(), room_id);
+}
+
+#[async_test]
+async fn knock_requests() {
+    let (client, server) = logged_in_client().await;
+    let room_id = &*DEFAULT_TEST_ROOM_ID;
+    let bob = user_id!("@bob:localhost");
+
+    let knock_event = json!({
+        "content": {
+            "displayname": "Bob",
+            "membership": "knock",
+            "reason": "I'm a friend of Alice",
+        },
+        "event_id": "$knock",
+        "origin_server_ts": 151800140,
+        "room_id": room_id,
+        "sender": bob,
+        "state_key": bob,
+        "type": "m.room.member",
+    });
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/members"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "chunk": [knock_event] })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/invite"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({ "user_id": bob })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_timeline_event(
+        sync_timeline_event!({
+            "content": {
+                "displayname": "Bob",
+                "membership": "knock",
+                "reason": "I'm a friend of Alice",
+            },
+            "event_id": "$knock",
+            "origin_server_ts": 151800140,
+            "sender": bob,
+            "state_key": bob,
+            "type": "m.room.member",
+        }),
+    ));
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let sync_token = client.sync_once(SyncSettings::new()).await.unwrap().next_batch;
+
+    let room = client.get_room(room_id).unwrap();
+    let (_drop_guard, knock_requests) = room.subscribe_to_knock_requests();
+    pin_mut!(knock_requests);
+
+    // The current knock requests are returned first.
+    let requests = knock_requests.next().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].room_id(), room_id);
+    assert_eq!(requests[0].member_id, bob);
+    assert_eq!(requests[0].display_name.as_deref(), Some("Bob"));
+    assert_eq!(requests[0].reason.as_deref(), Some("I'm a friend of Alice"));
+
+    requests[0].accept().await.unwrap();
+
+    // Once Bob is invited, the knock request is resolved.
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_timeline_event(
+        sync_timeline_event!({
+            "content": {
+                "displayname": "Bob",
+                "membership": "invite",
+            },
+            "event_id": "$invite",
+            "origin_server_ts": 151800150,
+            "sender": "@example:localhost",
+            "state_key": bob,
+            "type": "m.room.member",
+            "unsigned": {
+                "prev_content": {
+                    "displayname": "Bob",
+                    "membership": "knock",
+                },
+            },
+        }),
+    ));
+    mock_sync(&server, ev_builder.build_json_sync_response(), Some(sync_token.clone())).await;
+    client.sync_once(SyncSettings::new().token(sync_token)).await.unwrap();
+
+    let requests = knock_requests.next().await.unwrap();
+    assert!(requests.is_empty());
+}
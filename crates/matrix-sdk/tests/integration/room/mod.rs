@@ -1,5 +1,6 @@
 mod common;
 mod joined;
+mod knocking;
 mod left;
 mod notification_mode;
 mod spaces;
@@ -1,14 +1,16 @@
-use std::time::Duration;
+use std::{collections::BTreeSet, time::Duration};
 
 use assert_matches2::assert_let;
 use futures_util::StreamExt;
 use matrix_sdk::{config::SyncSettings, room::ParentSpace, Client};
-use matrix_sdk_test::{async_test, test_json, DEFAULT_TEST_ROOM_ID};
+use matrix_sdk_test::{
+    async_test, test_json, JoinedRoomBuilder, SyncResponseBuilder, DEFAULT_TEST_ROOM_ID,
+};
 use once_cell::sync::Lazy;
-use ruma::{room_id, RoomId};
+use ruma::{events::AnySyncTimelineEvent, room_id, serde::Raw, RoomId};
 use serde_json::{json, Value as JsonValue};
 use wiremock::{
-    matchers::{header, method, path_regex},
+    matchers::{header, method, path_regex, query_param, query_param_is_missing},
     Mock, ResponseTemplate,
 };
 
@@ -405,3 +407,171 @@ async fn parent_space_powerlevel_too_low() {
     assert_let!(ParentSpace::Illegitimate(space) = spaces.first().unwrap());
     assert_eq!(space.room_id(), *DEFAULT_TEST_SPACE_ID);
 }
+
+fn create_event(room_type: Option<&str>) -> Raw<AnySyncTimelineEvent> {
+    let mut content = json!({ "creator": "@example:localhost", "room_version": "10" });
+    if let Some(room_type) = room_type {
+        content["type"] = room_type.into();
+    }
+
+    Raw::new(&json!({
+        "content": content,
+        "event_id": format!("$create_{}", room_type.unwrap_or("room")),
+        "origin_server_ts": 1,
+        "sender": "@example:localhost",
+        "state_key": "",
+        "type": "m.room.create",
+    }))
+    .unwrap()
+    .cast()
+}
+
+fn space_child_event(
+    child_id: &RoomId,
+    order: Option<&str>,
+    origin_server_ts: u64,
+) -> Raw<AnySyncTimelineEvent> {
+    let mut content = json!({ "via": ["localhost"] });
+    if let Some(order) = order {
+        content["order"] = order.into();
+    }
+
+    Raw::new(&json!({
+        "content": content,
+        "event_id": format!("$child_{child_id}"),
+        "origin_server_ts": origin_server_ts,
+        "sender": "@example:localhost",
+        "state_key": child_id,
+        "type": "m.space.child",
+    }))
+    .unwrap()
+    .cast()
+}
+
+#[async_test]
+async fn space_children_and_joined_descendants() {
+    let (client, server) = logged_in_client().await;
+
+    let space_id = room_id!("!space:localhost");
+    let sub_space_id = room_id!("!sub_space:localhost");
+    let room_a = room_id!("!a:localhost");
+    let room_b = room_id!("!b:localhost");
+    let unjoined_room = room_id!("!unjoined:localhost");
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder
+        .add_joined_room(
+            JoinedRoomBuilder::new(space_id)
+                .add_timeline_event(create_event(Some("m.space")))
+                .add_timeline_event(space_child_event(sub_space_id, Some("b"), 2))
+                .add_timeline_event(space_child_event(room_a, Some("a"), 3))
+                .add_timeline_event(space_child_event(unjoined_room, None, 4)),
+        )
+        .add_joined_room(
+            JoinedRoomBuilder::new(sub_space_id)
+                .add_timeline_event(create_event(Some("m.space")))
+                .add_timeline_event(space_child_event(room_b, None, 2))
+                // Cycles are ignored.
+                .add_timeline_event(space_child_event(space_id, None, 3)),
+        )
+        .add_joined_room(JoinedRoomBuilder::new(room_a).add_timeline_event(create_event(None)))
+        .add_joined_room(JoinedRoomBuilder::new(room_b).add_timeline_event(create_event(None)));
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::new()).await.unwrap();
+
+    assert!(client.get_space(room_a).is_none());
+    let space = client.get_space(space_id).unwrap();
+    assert_eq!(client.joined_spaces().len(), 2);
+
+    let children = space.children().await.unwrap();
+    let child_ids = children.iter().map(|child| child.room_id.as_str()).collect::<Vec<_>>();
+    assert_eq!(child_ids, [room_a.as_str(), sub_space_id.as_str(), unjoined_room.as_str()]);
+    assert_eq!(children[0].order.as_deref(), Some("a"));
+
+    let descendants = space.joined_descendants().await.unwrap();
+    assert_eq!(
+        descendants,
+        BTreeSet::from([room_a.to_owned(), room_b.to_owned(), sub_space_id.to_owned()])
+    );
+}
+
+#[async_test]
+async fn space_suggested_rooms() {
+    let (client, server) = logged_in_client().await;
+
+    let space_id = room_id!("!space:localhost");
+    let joined_room = room_id!("!joined:localhost");
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder
+        .add_joined_room(
+            JoinedRoomBuilder::new(space_id).add_timeline_event(create_event(Some("m.space"))),
+        )
+        .add_joined_room(JoinedRoomBuilder::new(joined_room));
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::new()).await.unwrap();
+
+    let hierarchy_room = |room_id: &RoomId| {
+        json!({
+            "room_id": room_id,
+            "num_joined_members": 1,
+            "world_readable": false,
+            "guest_can_join": false,
+            "children_state": [],
+        })
+    };
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v1/rooms/.*/hierarchy"))
+        .and(query_param("suggested_only", "true"))
+        .and(query_param("max_depth", "1"))
+        .and(query_param_is_missing("from"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "rooms": [hierarchy_room(space_id), hierarchy_room(joined_room)],
+            "next_batch": "next",
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v1/rooms/.*/hierarchy"))
+        .and(query_param("from", "next"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "rooms": [hierarchy_room(room_id!("!suggested:localhost"))],
+        })))
+        .mount(&server)
+        .await;
+
+    let space = client.get_space(space_id).unwrap();
+    let suggested = space.suggested_rooms().await.unwrap();
+    assert_eq!(suggested.len(), 1);
+    assert_eq!(suggested[0].room_id, room_id!("!suggested:localhost"));
+}
+
+#[async_test]
+async fn space_suggested_rooms_stops_after_max_pages() {
+    let (client, server) = logged_in_client().await;
+
+    let space_id = room_id!("!space:localhost");
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(space_id).add_timeline_event(create_event(Some("m.space"))),
+    );
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::new()).await.unwrap();
+
+    // The homeserver always returns a next batch.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/v1/rooms/.*/hierarchy"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "rooms": [],
+            "next_batch": "next",
+        })))
+        .expect(10)
+        .mount(&server)
+        .await;
+
+    let space = client.get_space(space_id).unwrap();
+    let suggested = space.suggested_rooms().await.unwrap();
+    assert!(suggested.is_empty());
+}
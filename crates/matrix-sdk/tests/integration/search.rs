@@ -0,0 +1,112 @@
+use matrix_sdk_test::{async_test, DEFAULT_TEST_ROOM_ID};
+use ruma::{api::client::search::search_events::v3::OrderBy, event_id, uint};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path_regex, query_param},
+    Mock, ResponseTemplate,
+};
+
+use crate::synced_client;
+
+#[async_test]
+async fn test_search_messages_in_room() {
+    let (client, server) = synced_client().await;
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/search"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({
+            "search_categories": {
+                "room_events": {
+                    "search_term": "lunch",
+                    "order_by": "recent",
+                    "filter": { "rooms": [*DEFAULT_TEST_ROOM_ID] },
+                    "event_context": { "before_limit": 1, "after_limit": 0 },
+                },
+            },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "search_categories": {
+                "room_events": {
+                    "count": 1,
+                    "highlights": ["lunch"],
+                    "next_batch": "next1",
+                    "results": [{
+                        "rank": 0.5,
+                        "result": {
+                            "content": { "body": "Let's have lunch", "msgtype": "m.text" },
+                            "event_id": "$lunch",
+                            "origin_server_ts": 1432735824653u64,
+                            "room_id": *DEFAULT_TEST_ROOM_ID,
+                            "sender": "@example:example.org",
+                            "type": "m.room.message",
+                        },
+                        "context": {
+                            "events_before": [{
+                                "content": { "body": "I'm hungry", "msgtype": "m.text" },
+                                "event_id": "$hungry",
+                                "origin_server_ts": 1432735824650u64,
+                                "room_id": *DEFAULT_TEST_ROOM_ID,
+                                "sender": "@example:example.org",
+                                "type": "m.room.message",
+                            }],
+                            "events_after": [],
+                            "start": "start1",
+                            "end": "end1",
+                        },
+                    }],
+                },
+            },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let results = room
+        .search_messages("lunch")
+        .order_by(OrderBy::Recent)
+        .context(uint!(1), uint!(0), false)
+        .await
+        .unwrap();
+
+    assert_eq!(results.count, Some(uint!(1)));
+    assert_eq!(results.highlights, ["lunch"]);
+    assert_eq!(results.next_batch.as_deref(), Some("next1"));
+    assert_eq!(results.results.len(), 1);
+
+    let result = &results.results[0];
+    assert_eq!(result.room_id, *DEFAULT_TEST_ROOM_ID);
+    assert_eq!(result.event_id, event_id!("$lunch"));
+    assert_eq!(result.rank, Some(0.5));
+    assert_eq!(result.events_before.len(), 1);
+    assert!(result.events_after.is_empty());
+    assert_eq!(result.start.as_deref(), Some("start1"));
+    assert_eq!(result.end.as_deref(), Some("end1"));
+}
+
+#[async_test]
+async fn test_search_next_batch() {
+    let (client, server) = synced_client().await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/search"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("next_batch", "next1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "search_categories": {
+                "room_events": {
+                    "results": [],
+                    "highlights": [],
+                },
+            },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let results = client.search("lunch").next_batch("next1").await.unwrap();
+
+    assert!(results.results.is_empty());
+    assert!(results.next_batch.is_none());
+}
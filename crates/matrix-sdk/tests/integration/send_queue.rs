@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+use assert_matches2::{assert_let, assert_matches};
+use matrix_sdk::{
+    attachment::AttachmentConfig,
+    media::{MediaFormat, MediaRequest},
+    send_queue::RoomSendQueueUpdate,
+};
+use matrix_sdk_test::{async_test, DEFAULT_TEST_ROOM_ID};
+use ruma::{
+    event_id,
+    events::{
+        room::message::{MessageType, RoomMessageEventContent},
+        AnyMessageLikeEventContent,
+    },
+};
+use serde_json::json;
+use tokio::time::timeout;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path, path_regex},
+    Mock, ResponseTemplate,
+};
+
+use crate::{mock_encryption_state, synced_client};
+
+#[async_test]
+async fn test_queued_events_are_persisted_until_cancelled() {
+    let (client, _server) = synced_client().await;
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+
+    client.send_queue().set_enabled(false);
+    assert!(!client.send_queue().is_enabled());
+
+    let txn_id = room
+        .send_queue()
+        .send(RoomMessageEventContent::text_plain("Hello, World!").into())
+        .await
+        .unwrap();
+
+    // Another handle on the same room's queue sees the queued event.
+    let local_echoes = room.send_queue().local_echoes().await.unwrap();
+    assert_eq!(local_echoes.len(), 1);
+    assert_eq!(local_echoes[0].transaction_id, txn_id);
+    assert!(!local_echoes[0].is_wedged);
+
+    let mut updates = room.send_queue().subscribe();
+    assert!(room.send_queue().cancel(&txn_id).await.unwrap());
+    assert_let!(
+        Ok(RoomSendQueueUpdate::CancelledLocalEvent { transaction_id }) = updates.recv().await
+    );
+    assert_eq!(transaction_id, txn_id);
+
+    assert!(room.send_queue().local_echoes().await.unwrap().is_empty());
+    assert!(!room.send_queue().cancel(&txn_id).await.unwrap());
+}
+
+#[async_test]
+async fn test_wedged_event_is_sent_after_retry() {
+    let (client, server) = synced_client().await;
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+    mock_encryption_state(&server, false).await;
+
+    let send_queue = room.send_queue();
+    let mut updates = send_queue.subscribe();
+
+    // There's no mock for sending the event yet, so the server responds with a 404.
+    let txn_id =
+        send_queue.send(RoomMessageEventContent::text_plain("Hello").into()).await.unwrap();
+
+    assert_let!(
+        Ok(Ok(RoomSendQueueUpdate::NewLocalEvent(local_echo))) =
+            timeout(Duration::from_secs(1), updates.recv()).await
+    );
+    assert_eq!(local_echo.transaction_id, txn_id);
+
+    assert_let!(
+        Ok(Ok(RoomSendQueueUpdate::SendError { transaction_id, is_recoverable, .. })) =
+            timeout(Duration::from_secs(1), updates.recv()).await
+    );
+    assert_eq!(transaction_id, txn_id);
+    assert!(!is_recoverable);
+
+    let local_echoes = send_queue.local_echoes().await.unwrap();
+    assert_eq!(local_echoes.len(), 1);
+    assert!(local_echoes[0].is_wedged);
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$sent" })))
+        .mount(&server)
+        .await;
+
+    assert!(send_queue.retry(&txn_id).await.unwrap());
+
+    assert_matches!(
+        timeout(Duration::from_secs(1), updates.recv()).await,
+        Ok(Ok(RoomSendQueueUpdate::RetryEvent { .. }))
+    );
+    assert_let!(
+        Ok(Ok(RoomSendQueueUpdate::SentEvent { transaction_id, event_id })) =
+            timeout(Duration::from_secs(1), updates.recv()).await
+    );
+    assert_eq!(transaction_id, txn_id);
+    assert_eq!(event_id, event_id!("$sent"));
+
+    assert!(send_queue.local_echoes().await.unwrap().is_empty());
+}
+
+#[async_test]
+async fn test_queued_attachment_is_uploaded_then_sent() {
+    let (client, server) = synced_client().await;
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+    mock_encryption_state(&server, false).await;
+
+    client.send_queue().set_enabled(false);
+
+    let send_queue = room.send_queue();
+    let mut updates = send_queue.subscribe();
+
+    let txn_id = send_queue
+        .send_attachment(
+            "image.jpg",
+            &mime::IMAGE_JPEG,
+            b"Hello world".to_vec(),
+            AttachmentConfig::new(),
+        )
+        .await
+        .unwrap();
+
+    // The local echo is an image message, whose content can be loaded from the
+    // media cache.
+    assert_let!(
+        Ok(Ok(RoomSendQueueUpdate::NewLocalEvent(local_echo))) =
+            timeout(Duration::from_secs(1), updates.recv()).await
+    );
+    assert_eq!(local_echo.transaction_id, txn_id);
+    assert_let!(
+        Ok(AnyMessageLikeEventContent::RoomMessage(content)) = local_echo.deserialize_content()
+    );
+    assert_let!(MessageType::Image(image) = content.msgtype);
+    assert_eq!(image.body, "image.jpg");
+
+    let local_request = MediaRequest { source: image.source, format: MediaFormat::File };
+    let data = client.media().get_media_content(&local_request, true).await.unwrap();
+    assert_eq!(data, b"Hello world");
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/media/r0/upload"))
+        .and(header("content-type", "image/jpeg"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+          "content_uri": "mxc://example.com/AQwafuaFswefuhsfAFAgsw"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // The event is sent with the uploaded file.
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/m.room.message/.*"))
+        .and(body_partial_json(json!({
+            "msgtype": "m.image",
+            "body": "image.jpg",
+            "url": "mxc://example.com/AQwafuaFswefuhsfAFAgsw",
+            "info": {
+                "mimetype": "image/jpeg",
+            }
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$sent" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    client.send_queue().set_enabled(true);
+
+    assert_let!(
+        Ok(Ok(RoomSendQueueUpdate::SentEvent { transaction_id, event_id })) =
+            timeout(Duration::from_secs(1), updates.recv()).await
+    );
+    assert_eq!(transaction_id, txn_id);
+    assert_eq!(event_id, event_id!("$sent"));
+
+    assert!(send_queue.local_echoes().await.unwrap().is_empty());
+
+    // The local copy of the file has been removed from the media cache, so it's
+    // requested from the server, which doesn't know it.
+    assert!(client.media().get_media_content(&local_request, true).await.is_err());
+}
@@ -48,6 +48,14 @@ use wiremock::{
     Mock, MockServer, ResponseTemplate,
 };
 
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk::{
+    crypto::{OlmMachine, OutgoingRequests},
+    encryption::LocalTrust,
+};
+#[cfg(feature = "e2e-encryption")]
+use ruma::{device_id, DeviceId};
+
 use crate::{logged_in_client, mock_encryption_state, mock_sync};
 
 /// Create a JSON string from a [`json!`][serde_json::json] "literal".
@@ -259,6 +267,20 @@ async fn read_messages() {
             .mount(&mock_server)
             .await;
 
+        // The driver paginates further since it didn't find enough events, and
+        // reaches the start of the timeline.
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+            .and(header("authorization", "Bearer 1234"))
+            .and(query_param("from", "t47409-4357353_219380_26003_2269"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "chunk": [],
+                "start": "t47409-4357353_219380_26003_2269",
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
         // Ask the driver to read messages
         send_request(
             &driver_handle,
@@ -356,6 +378,20 @@ async fn read_messages_with_msgtype_capabilities() {
             .mount(&mock_server)
             .await;
 
+        // The driver paginates further since it didn't find enough events, and
+        // reaches the start of the timeline.
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+            .and(header("authorization", "Bearer 1234"))
+            .and(query_param("from", "t47409-4357353_219380_26003_2269"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "chunk": [],
+                "start": "t47409-4357353_219380_26003_2269",
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
         // Ask the driver to read messages
         send_request(
             &driver_handle,
@@ -600,6 +636,264 @@ async fn send_room_name() {
     mock_server.verify().await;
 }
 
+#[async_test]
+async fn upload_file() {
+    let (_, mock_server, driver_handle) = run_test_driver(false).await;
+
+    negotiate_capabilities(&driver_handle, json!(["org.matrix.msc4039.upload_file"])).await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/media/.*/upload"))
+        .and(header("content-type", "text/plain"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(
+                json!({ "content_uri": "mxc://example.org/AQwafuaFswefuhsfAFAgsw" }),
+            ),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    send_request(
+        &driver_handle,
+        "upload-file",
+        "org.matrix.msc4039.upload_file",
+        json!({
+            // "Hello, world!" in base64.
+            "file": "SGVsbG8sIHdvcmxkIQ",
+            "content_type": "text/plain",
+        }),
+    )
+    .await;
+
+    // Receive the response
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["api"], "fromWidget");
+    assert_eq!(msg["action"], "org.matrix.msc4039.upload_file");
+    let content_uri = msg["response"]["content_uri"].as_str().unwrap();
+    assert_eq!(content_uri, "mxc://example.org/AQwafuaFswefuhsfAFAgsw");
+
+    mock_server.verify().await;
+}
+
+#[async_test]
+async fn download_file() {
+    let (_, mock_server, driver_handle) = run_test_driver(false).await;
+
+    negotiate_capabilities(&driver_handle, json!(["org.matrix.msc4039.download_file"])).await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/media/(r0|v3)/download/example.org/AQwafuaFswefuhsfAFAgsw"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"Hello, world!".to_vec()))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    send_request(
+        &driver_handle,
+        "download-file",
+        "org.matrix.msc4039.download_file",
+        json!({ "content_uri": "mxc://example.org/AQwafuaFswefuhsfAFAgsw" }),
+    )
+    .await;
+
+    // Receive the response
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["api"], "fromWidget");
+    assert_eq!(msg["action"], "org.matrix.msc4039.download_file");
+    // "Hello, world!" in base64.
+    assert_eq!(msg["response"]["file"], "SGVsbG8sIHdvcmxkIQ");
+
+    mock_server.verify().await;
+}
+
+#[async_test]
+async fn watch_turn_servers() {
+    let (_, mock_server, driver_handle) = run_test_driver(false).await;
+
+    negotiate_capabilities(&driver_handle, json!(["town.robin.msc3846.turn_servers"])).await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/.*/voip/turnServer"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "username": "1443779631:@user:example.com",
+            "password": "JlKfBy1QwLrO20385QyAtEyIv0=",
+            "uris": ["turn:turn.example.com:3478?transport=udp"],
+            "ttl": 86400,
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    send_request(&driver_handle, "watch-turn-servers", "watch_turn_servers", json!({})).await;
+
+    // Receive the response
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["api"], "fromWidget");
+    assert_eq!(msg["action"], "watch_turn_servers");
+    assert_eq!(msg["response"], json!({}));
+
+    // Receive the TURN servers
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["api"], "toWidget");
+    assert_eq!(msg["action"], "update_turn_servers");
+    assert_eq!(
+        msg["data"],
+        json!({
+            "uris": ["turn:turn.example.com:3478?transport=udp"],
+            "username": "1443779631:@user:example.com",
+            "password": "JlKfBy1QwLrO20385QyAtEyIv0=",
+        })
+    );
+
+    mock_server.verify().await;
+}
+
+#[cfg(feature = "e2e-encryption")]
+#[async_test]
+async fn send_encrypted_to_device_message() {
+    let (client, mock_server, driver_handle) = run_test_driver(false).await;
+
+    negotiate_capabilities(
+        &driver_handle,
+        json!(["org.matrix.msc3819.send.to_device:io.element.call.encryption_keys"]),
+    )
+    .await;
+
+    let (device_keys, one_time_key) = new_bob_device(device_id!("BOBDEVICE")).await;
+    let (blacklisted_device_keys, blacklisted_one_time_key) =
+        new_bob_device(device_id!("BLACKLISTED")).await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/keys/query"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device_keys": {
+                BOB.as_str(): {
+                    "BOBDEVICE": device_keys,
+                    "BLACKLISTED": blacklisted_device_keys,
+                },
+            },
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/keys/claim"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "one_time_keys": {
+                BOB.as_str(): {
+                    "BOBDEVICE": one_time_key,
+                    "BLACKLISTED": blacklisted_one_time_key,
+                },
+            },
+            "failures": {},
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/sendToDevice/m.room.encrypted/.*"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    // Send a message to all the devices of Bob.
+    send_request(
+        &driver_handle,
+        "send-to-all-devices",
+        "send_to_device",
+        json!({
+            "type": "io.element.call.encryption_keys",
+            "encrypted": true,
+            "messages": {
+                BOB.as_str(): {
+                    "*": { "keys": [] },
+                },
+            },
+        }),
+    )
+    .await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["api"], "fromWidget");
+    assert_eq!(msg["action"], "send_to_device");
+    assert!(msg["response"].get("error").is_none());
+
+    // Blacklisted devices are skipped, even when they are targeted explicitly.
+    client
+        .encryption()
+        .get_device(&BOB, device_id!("BLACKLISTED"))
+        .await
+        .unwrap()
+        .unwrap()
+        .set_local_trust(LocalTrust::BlackListed)
+        .await
+        .unwrap();
+
+    send_request(
+        &driver_handle,
+        "send-to-explicit-devices",
+        "send_to_device",
+        json!({
+            "type": "io.element.call.encryption_keys",
+            "encrypted": true,
+            "messages": {
+                BOB.as_str(): {
+                    "BOBDEVICE": { "keys": [] },
+                    "BLACKLISTED": { "keys": [] },
+                },
+            },
+        }),
+    )
+    .await;
+
+    let msg = recv_message(&driver_handle).await;
+    assert_eq!(msg["action"], "send_to_device");
+    assert!(msg["response"].get("error").is_none());
+
+    mock_server.verify().await;
+
+    let bodies: Vec<JsonValue> = mock_server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|request| request.url.path().contains("/sendToDevice/"))
+        .map(|request| request.body_json().unwrap())
+        .collect();
+    assert_eq!(bodies.len(), 2);
+
+    // The messages are encrypted with Olm for each device.
+    let messages = bodies[0]["messages"][BOB.as_str()].as_object().unwrap();
+    assert_eq!(messages.keys().collect::<Vec<_>>(), ["BLACKLISTED", "BOBDEVICE"]);
+    assert_eq!(messages["BOBDEVICE"]["algorithm"], "m.olm.v1.curve25519-aes-sha2");
+    assert!(messages["BOBDEVICE"]["ciphertext"].is_object());
+
+    let messages = bodies[1]["messages"][BOB.as_str()].as_object().unwrap();
+    assert_eq!(messages.keys().collect::<Vec<_>>(), ["BOBDEVICE"]);
+}
+
+/// Create a new device of Bob, and return its device keys and one of its
+/// signed one-time keys, as they would be uploaded by his client.
+#[cfg(feature = "e2e-encryption")]
+async fn new_bob_device(device_id: &DeviceId) -> (JsonValue, JsonValue) {
+    let machine = OlmMachine::new(&BOB, device_id).await;
+    let requests = machine.outgoing_requests().await.unwrap();
+    let upload = requests
+        .iter()
+        .find_map(|request| match request.request() {
+            OutgoingRequests::KeysUpload(upload) => Some(upload),
+            _ => None,
+        })
+        .unwrap();
+
+    let (key_id, one_time_key) = upload.one_time_keys.iter().next().unwrap();
+    (json!(upload.device_keys), json!({ key_id.as_str(): one_time_key }))
+}
+
 async fn negotiate_capabilities(driver_handle: &WidgetDriverHandle, caps: JsonValue) {
     {
         // Receive toWidget capabilities request